@@ -0,0 +1,105 @@
+//! Embeds data files in test suites as fixtures with [`fixture!`], which validates them
+//! when the test suite is compiled, so that mistakes in seeds fail the build rather
+//! than the tests that load them.
+//!
+//! This is a crate of its own rather than a feature of `hldr`, since it validates data
+//! files with `hldr` itself.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, Ident, LitStr, Token, Visibility};
+
+/// The function a fixture is loaded with and the path of its data file, eg.
+/// `pub people, "tests/seeds/people.hldr"`
+struct Fixture {
+    visibility: Visibility,
+    name: Ident,
+    path: LitStr,
+}
+
+impl Parse for Fixture {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let visibility = input.parse()?;
+        let name = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let path = input.parse()?;
+        input.parse::<Option<Token![,]>>()?;
+
+        Ok(Self { visibility, name, path })
+    }
+}
+
+/// Declares a function that loads the data file at the path, relative to the crate's
+/// `Cargo.toml`, into a transaction with the default options, just as
+/// `hldr::place_in_transaction` does, leaving the caller to commit or roll it back.
+///
+/// The data file is embedded with `include_str!`, so it is compiled again whenever it
+/// changes, and it is lexed, parsed, and analyzed when compiling, so that any error in it
+/// is a compile error. As with `hldr::embed::load_str`, it cannot include or import other
+/// files.
+///
+/// ```ignore
+/// hldr_macros::fixture!(people, "tests/seeds/people.hldr");
+///
+/// #[test]
+/// fn test_people() {
+///     let mut client = hldr::postgres::Client::connect("...", hldr::postgres::NoTls).unwrap();
+///     let mut transaction = client.transaction().unwrap();
+///
+///     people(&mut transaction).unwrap();
+/// }
+/// ```
+#[proc_macro]
+pub fn fixture(input: TokenStream) -> TokenStream {
+    let Fixture { visibility, name, path } = parse_macro_input!(input as Fixture);
+    let manifest_dir = env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    let full_path = manifest_dir.join(path.value());
+
+    if let Err(message) = validate(&full_path) {
+        return syn::Error::new(path.span(), message).to_compile_error().into();
+    }
+
+    let full_path = full_path.to_string_lossy();
+
+    quote! {
+        #visibility fn #name(
+            transaction: &mut ::hldr::postgres::Transaction<'_>,
+        ) -> ::std::result::Result<::hldr::PlaceReport, ::hldr::HldrError> {
+            ::hldr::place_in_transaction(transaction, include_str!(#full_path), &::hldr::Options::default())
+        }
+    }
+    .into()
+}
+
+/// Validates the data file just as it is before it is loaded, describing the first error
+/// if it is not valid.
+fn validate(path: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("could not read `{}`: {}", path.display(), e))?;
+    let invalid = |e: hldr::HldrError| format!("invalid data file `{}`: {}", path.display(), e);
+    let tree = hldr::parse(&source).map_err(invalid)?;
+
+    hldr::validate(tree, &hldr::Options::default()).map_err(invalid)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use std::path::Path;
+
+    #[test]
+    fn test_validate() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/seeds");
+
+        assert_eq!(validate(&dir.join("people.hldr")), Ok(()));
+
+        let error = validate(&dir.join("missing.hldr")).unwrap_err();
+        assert!(error.starts_with("could not read"), "{}", error);
+
+        let error = validate(&dir.join("invalid.hldr")).unwrap_err();
+        assert!(error.contains("`nobody` not found"), "{}", error);
+    }
+}
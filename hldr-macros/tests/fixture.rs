@@ -0,0 +1,15 @@
+use hldr::postgres::Transaction;
+use hldr::{HldrError, PlaceReport};
+
+hldr_macros::fixture!(people, "tests/seeds/people.hldr");
+
+mod seeds {
+    hldr_macros::fixture!(pub people, "tests/seeds/people.hldr",);
+}
+
+#[test]
+fn test_fixture() {
+    // Loading needs a database, so the fixtures are only checked to be declared
+    let _: fn(&mut Transaction<'_>) -> Result<PlaceReport, HldrError> = people;
+    let _: fn(&mut Transaction<'_>) -> Result<PlaceReport, HldrError> = seeds::people;
+}
@@ -0,0 +1,85 @@
+//! Rejects aliases that are used for more than one schema or table, or that are the
+//! name of another, since records in both would be declared in the same scope and
+//! references to either would resolve to whichever was declared first.
+
+use super::error::{AnalyzeError, AnalyzeErrorKind};
+use super::namespaced;
+use crate::parser::nodes::{StructuralIdentity, Table};
+use std::collections::HashMap;
+
+/// The schemas and tables declared in each scope so far, by their qualified names
+#[derive(Debug, Default)]
+pub(super) struct Aliases {
+    schemas: HashMap<String, StructuralIdentity>,
+    tables: HashMap<String, (Option<String>, StructuralIdentity)>,
+}
+
+impl Aliases {
+    /// Declares the table and its schema, if any, failing if either's scope was already
+    /// declared by a schema or table with a different name.
+    pub(super) fn declare(&mut self, schema: Option<&StructuralIdentity>, table: &Table) -> Result<(), AnalyzeError> {
+        let namespace = table.namespace.as_deref();
+        let error = |kind| AnalyzeError::new(kind, table.position);
+
+        if let Some(schema) = schema {
+            let scope = namespaced(namespace, scope(schema).to_owned());
+            let declared = self.schemas.entry(scope).or_insert_with(|| schema.clone());
+
+            if declared.name != schema.name {
+                return Err(error(conflict(declared, schema, &declared.name, &schema.name)));
+            }
+        }
+
+        let schema_scope = schema.map(|s| scope(s).to_owned());
+        let table_scope = match &schema_scope {
+            Some(schema_scope) => format!("{}.{}", schema_scope, scope(&table.identity)),
+            None => scope(&table.identity).to_owned(),
+        };
+        let schema_name = schema.map(|s| s.name.clone());
+        let (declared_schema, declared) = self
+            .tables
+            .entry(namespaced(namespace, table_scope))
+            .or_insert_with(|| (schema_name.clone(), table.identity.clone()));
+
+        if declared.name != table.identity.name {
+            let qualified = |schema: &Option<String>, name: &str| match schema {
+                Some(schema) => format!("{}.{}", schema, name),
+                None => name.to_owned(),
+            };
+            let declared_name = qualified(declared_schema, &declared.name);
+            let name = qualified(&schema_name, &table.identity.name);
+
+            return Err(error(conflict(declared, &table.identity, &declared_name, &name)));
+        }
+
+        Ok(())
+    }
+}
+
+/// The scope that the records of a schema or table are declared in, which is its alias
+/// or else its name
+fn scope(identity: &StructuralIdentity) -> &str {
+    identity.alias.as_deref().unwrap_or(&identity.name)
+}
+
+/// The error for two schemas or tables declared in the same scope, given with their
+/// qualified names, where the scope is the alias of at least one of them.
+fn conflict(declared: &StructuralIdentity, identity: &StructuralIdentity, declared_name: &str, name: &str) -> AnalyzeErrorKind {
+    match (&declared.alias, &identity.alias) {
+        (Some(alias), Some(_)) => AnalyzeErrorKind::DuplicateAlias {
+            alias: alias.clone(),
+            names: vec![declared_name.to_owned(), name.to_owned()],
+        },
+        (Some(alias), None) => AnalyzeErrorKind::AliasConflict {
+            alias: alias.clone(),
+            aliased: declared_name.to_owned(),
+            name: name.to_owned(),
+        },
+        (None, Some(alias)) => AnalyzeErrorKind::AliasConflict {
+            alias: alias.clone(),
+            aliased: name.to_owned(),
+            name: declared_name.to_owned(),
+        },
+        (None, None) => unreachable!("schemas and tables with the same scope and no alias have the same name"),
+    }
+}
@@ -0,0 +1,142 @@
+use crate::parser::nodes::{ParseTree, Record, StructuralIdentity, StructuralNode, Table};
+use crate::Position;
+use std::collections::HashMap;
+
+/// An anonymous record whose attributes are identical to those of an earlier anonymous
+/// record in the same table, which is almost always a copy-paste error.
+#[derive(Debug, PartialEq)]
+pub struct DuplicateRecord {
+    /// The name of the table, qualified with its schema's name, if any
+    pub table: String,
+    pub position: Option<Position>,
+    /// Where the earlier record with the same attributes was declared
+    pub original: Option<Position>,
+}
+
+/// The earliest anonymous record with each set of attributes, by table and attributes
+type Seen = HashMap<(String, String), Option<Position>>;
+
+/// Finds anonymous records that have the same attributes as an earlier anonymous record
+/// in the same table, including any other declarations of the table, regardless of the
+/// order their attributes were declared in. Records without any attributes are ignored,
+/// as they are usually meant to insert several rows of defaults.
+///
+/// This is meant to be run on a parsed tree before it is analyzed, so that the copies
+/// of repeated records and the defaults applied to records are not compared.
+pub fn duplicate_records(tree: &ParseTree) -> Vec<DuplicateRecord> {
+    let mut seen = Seen::new();
+    let mut duplicates = Vec::new();
+
+    for node in &tree.nodes {
+        match node {
+            StructuralNode::Schema(schema) => {
+                for table in &schema.nodes {
+                    find_duplicates(Some(&schema.identity), table, &mut seen, &mut duplicates);
+                }
+            }
+            StructuralNode::Table(table) => find_duplicates(None, table, &mut seen, &mut duplicates),
+        }
+    }
+
+    duplicates
+}
+
+fn find_duplicates(
+    schema: Option<&StructuralIdentity>,
+    table: &Table,
+    seen: &mut Seen,
+    duplicates: &mut Vec<DuplicateRecord>,
+) {
+    let table_name = match schema {
+        Some(schema) => format!("{}.{}", schema.name, table.identity.name),
+        None => table.identity.name.clone(),
+    };
+
+    for record in &table.nodes {
+        if record.name.is_some() || record.nodes.is_empty() {
+            continue;
+        }
+
+        match seen.get(&(table_name.clone(), attributes_key(record))) {
+            Some(original) => duplicates.push(DuplicateRecord {
+                table: table_name.clone(),
+                position: record.position,
+                original: *original,
+            }),
+            None => {
+                seen.insert((table_name.clone(), attributes_key(record)), record.position);
+            }
+        }
+    }
+}
+
+/// Returns a key that is the same for records with the same attributes in any order,
+/// extending the same record, if any. Values contain no positions, so their debug
+/// representations are only the same when the values themselves are.
+fn attributes_key(record: &Record) -> String {
+    let mut attributes: Vec<String> = record
+        .nodes
+        .iter()
+        .map(|a| format!("{:?} {:?}", a.name, a.value))
+        .collect();
+
+    attributes.sort();
+    format!("{:?}\n{:?}\n{}\n{}", record.extends, record.update_where, record.delete, attributes.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+    use pretty_assertions::assert_eq;
+
+    fn duplicates(input: &str) -> Vec<DuplicateRecord> {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        duplicate_records(&parser::parse(tokens.into_iter()).unwrap())
+    }
+
+    fn duplicate(table: &str, line: usize, original: usize) -> DuplicateRecord {
+        DuplicateRecord {
+            table: table.to_owned(),
+            position: Some(Position { line, column: 3 }),
+            original: Some(Position { line: original, column: 3 }),
+        }
+    }
+
+    #[test]
+    fn test_duplicate_records() {
+        let input = "table t1 (
+  (col1 1, col2 'a')
+  (col2 'a', col1 1)
+  (col1 1, col2 'b')
+  rec1 (col1 1, col2 'a')
+  ()
+  ()
+  _ * 2 (col1 2)
+)
+schema s1 (
+  table t1 (
+    (col1 1, col2 'a')
+  )
+)
+table t2 (
+  (col1 1, col2 'a')
+)
+table t1 (
+  (col1 2)
+  (col1 @rec1.col1)
+  (col1 @rec1.col1)
+  _ extends rec1 (col1 2)
+)
+";
+
+        assert_eq!(
+            duplicates(input),
+            vec![
+                duplicate("t1", 3, 2),
+                duplicate("t1", 19, 8),
+                duplicate("t1", 21, 20),
+            ],
+        );
+    }
+}
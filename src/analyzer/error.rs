@@ -1,31 +1,122 @@
+use crate::parser::nodes::{Attribute, Span};
+use crate::Position;
 use std::error::Error;
 use std::fmt;
-// use crate::Position;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum AnalyzeErrorKind {
+    /// An alias of a schema or table that is the name of another, with the qualified
+    /// names of the aliased one and the other
+    AliasConflict { alias: String, aliased: String, name: String },
+    BaseRecordNotFound { scope: String, record: String },
     ColumnNotFound { column: String },
+    /// A column-level reference to a column set to `default`, whose value is only
+    /// known once the record is inserted
+    DefaultColumnReferenced { scope: String, column: String },
+    /// A column that a delete identifies rows by but sets to `default`, which has no
+    /// value to identify rows by
+    DeleteColumnInvalid { scope: String, column: String },
+    /// A reference to a record whose row is deleted by a delete that identifies it by
+    /// a reference to it as well
+    DeletedRecordReferenced { record: String },
+    /// An alias used for different schemas or tables, with their qualified names
+    DuplicateAlias { alias: String, names: Vec<String> },
     DuplicateColumn { scope: String, column: String },
     DuplicateRecord { scope: String, record: String },
+    DuplicateVariable { name: String },
+    /// Records that extend each other, each extending the next and the last extending the first
+    ExtendsCycle { records: Vec<String> },
+    IndexNotRepeated { scope: String, column: String },
+    /// A reference to an anonymous record by its label, eg. `_#3`, from another table
+    LabelOutsideTable { record: String },
+    /// A reference to a record that the tags being loaded exclude
+    RecordExcluded { record: String },
     RecordNotFound { record: String },
+    /// Records that reference each other, each referencing the next and the last
+    /// referencing the first, so that none of them can be loaded first
+    ReferenceCycle { records: Vec<String> },
+    /// A search path that differs from the one declared in another data file
+    SearchPathConflict { schemas: Vec<String>, declared: Vec<String> },
+    /// A column that a record updates rows by but does not give a value for, or sets to
+    /// `default`, which has no value to identify rows by
+    UpdateColumnInvalid { scope: String, column: String },
+    /// A record that identifies the rows it updates by every column it gives values for,
+    /// leaving none to update them with
+    UpdateColumnsMissing { scope: String },
+    VariableNotFound { name: String },
 }
 
 impl fmt::Display for AnalyzeErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            AnalyzeErrorKind::AliasConflict { alias, aliased, name } => {
+                write!(f, "alias `{}` of `{}` conflicts with the name of `{}`", alias, aliased, name)
+            }
+            AnalyzeErrorKind::BaseRecordNotFound { scope, record } => {
+                write!(f, "base record `{}` not found in scope `{}`", record, scope)
+            }
             AnalyzeErrorKind::ColumnNotFound { column } => {
                 write!(f, "referenced column `{}` not found", column)
             }
+            AnalyzeErrorKind::DefaultColumnReferenced { scope, column } => {
+                write!(f, "referenced column `{}` in scope `{}` is set to `default`", column, scope)
+            }
+            AnalyzeErrorKind::DeleteColumnInvalid { scope, column } => {
+                write!(f, "deleted rows cannot be identified by column `{}` in scope `{}` without a value", column, scope)
+            }
+            AnalyzeErrorKind::DeletedRecordReferenced { record } => {
+                write!(f, "record `{}` cannot be referenced, as its row is deleted", record)
+            }
+            AnalyzeErrorKind::DuplicateAlias { alias, names } => {
+                let names: Vec<_> = names.iter().map(|n| format!("`{}`", n)).collect();
+                write!(f, "alias `{}` is used for both {}", alias, names.join(" and "))
+            }
             AnalyzeErrorKind::DuplicateColumn { scope, column } => {
-                // TODO: Need position
                 write!(f, "duplicate column `{}` in scope `{}`", column, scope)
             }
             AnalyzeErrorKind::DuplicateRecord { scope, record } => {
                 write!(f, "duplicate record `{}` in scope `{}`", record, scope)
             }
+            AnalyzeErrorKind::DuplicateVariable { name } => {
+                write!(f, "duplicate variable `${}`", name)
+            }
+            AnalyzeErrorKind::ExtendsCycle { records } => {
+                let cycle: Vec<_> = records.iter().chain(records.first()).map(|r| format!("`{}`", r)).collect();
+                write!(f, "records extend each other in a cycle: {}", cycle.join(" -> "))
+            }
+            AnalyzeErrorKind::IndexNotRepeated { scope, column } => {
+                write!(f, "`index()` used outside of a repeated record for column `{}` in scope `{}`", column, scope)
+            }
+            AnalyzeErrorKind::LabelOutsideTable { record } => {
+                write!(f, "anonymous record `{}` can only be referenced by records of its own table", record)
+            }
+            AnalyzeErrorKind::RecordExcluded { record } => {
+                write!(f, "referenced record `{}` is excluded by tags", record)
+            }
             AnalyzeErrorKind::RecordNotFound { record } => {
                 write!(f, "record `{}` not found", record)
             }
+            AnalyzeErrorKind::ReferenceCycle { records } => {
+                let cycle: Vec<_> = records.iter().chain(records.first()).map(|r| format!("`{}`", r)).collect();
+                write!(f, "records reference each other in a cycle: {}", cycle.join(" -> "))
+            }
+            AnalyzeErrorKind::SearchPathConflict { schemas, declared } => {
+                write!(
+                    f,
+                    "search path `{}` conflicts with search path `{}` declared in another data file",
+                    schemas.join(", "),
+                    declared.join(", "),
+                )
+            }
+            AnalyzeErrorKind::UpdateColumnInvalid { scope, column } => {
+                write!(f, "updated rows cannot be identified by column `{}` in scope `{}` without a value", column, scope)
+            }
+            AnalyzeErrorKind::UpdateColumnsMissing { scope } => {
+                write!(f, "record in scope `{}` updates rows without any columns to update", scope)
+            }
+            AnalyzeErrorKind::VariableNotFound { name } => {
+                write!(f, "variable `${}` not found", name)
+            }
         }
     }
 }
@@ -33,12 +124,40 @@ impl fmt::Display for AnalyzeErrorKind {
 #[derive(Debug, PartialEq)]
 pub struct AnalyzeError {
     pub kind: AnalyzeErrorKind,
-    // pub position: Position,
+    /// Where the offending record or attribute was declared, if it was parsed
+    pub position: Option<Position>,
+    /// Where the offending attribute ends, if the error is in one that was parsed, so that
+    /// along with the position it spans the attribute
+    pub end: Option<Position>,
+}
+
+impl AnalyzeError {
+    pub(crate) fn new(kind: AnalyzeErrorKind, position: Option<Position>) -> Self {
+        Self { kind, position, end: None }
+    }
+
+    /// An error in the attribute, which is where and as far as the error spans.
+    pub(crate) fn in_attribute(kind: AnalyzeErrorKind, attribute: &Attribute) -> Self {
+        Self {
+            kind,
+            position: attribute.position,
+            end: attribute.span.map(|s| s.end),
+        }
+    }
+
+    /// Sets how far the error spans, for errors found while the attribute is borrowed.
+    pub(crate) fn spanning(mut self, span: Option<Span>) -> Self {
+        self.end = span.map(|s| s.end);
+        self
+    }
 }
 
 impl fmt::Display for AnalyzeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.kind)
+        match self.position {
+            Some(p) => write!(f, "{} on line {}", self.kind, p.line),
+            None => write!(f, "{}", self.kind),
+        }
     }
 }
 
@@ -0,0 +1,82 @@
+//! Names the anonymous records that other records of the same table reference by their
+//! position, eg. `@_#3.id` for the third anonymous record of the table, so that records
+//! only referenced by their neighbours need no names of their own.
+
+use super::error::{AnalyzeError, AnalyzeErrorKind};
+use super::{namespaced, table_scope, tables};
+use crate::parser::nodes::{ParseTree, Reference, ReferencedColumn, StructuralIdentity, Table};
+use std::collections::{HashMap, HashSet};
+
+/// The labels of the anonymous records referenced in each table scope, qualified with
+/// its namespace
+pub(super) type Labels = HashMap<String, HashSet<String>>;
+
+pub(super) fn is_label(name: &str) -> bool {
+    name.starts_with("_#")
+}
+
+/// Finds the labels referenced by the records of each table, including their defaults,
+/// failing if any are referenced from outside of their own tables.
+pub(super) fn referenced(tree: &ParseTree) -> Result<Labels, AnalyzeError> {
+    let mut labels = Labels::new();
+
+    for (_, schema, table) in tables(tree) {
+        let scope = namespaced(table.namespace.as_deref(), table_scope(schema.map(|s| &s.identity), &table.identity));
+        let attributes = table.defaults.iter().chain(table.nodes.iter().flat_map(|r| &r.nodes));
+
+        for attr in attributes {
+            for refval in attr.value.references() {
+                // Until references are resolved, `@table._#3` reads as a column of `table`
+                let (label, column, local) = match refval {
+                    Reference::ColumnLevel(c) => (&c.column, None, true),
+                    Reference::RecordLevel(r) => (&r.record, Some(&r.column), r.namespace.is_none()),
+                    Reference::TableLevel(t) => (&t.record, Some(&t.column), false),
+                    Reference::SchemaLevel(s) => (&s.record, Some(&s.column), false),
+                };
+                let labelled_column = matches!(column, Some(ReferencedColumn::Explicit(c)) if is_label(c));
+
+                if (!local && is_label(label)) || labelled_column {
+                    return Err(AnalyzeError::in_attribute(
+                        AnalyzeErrorKind::LabelOutsideTable {
+                            record: refval.to_string().trim_start_matches('@').to_owned(),
+                        },
+                        attr,
+                    ));
+                }
+                if !is_label(label) {
+                    continue;
+                }
+
+                labels.entry(scope.clone()).or_default().insert(label.clone());
+            }
+        }
+    }
+
+    Ok(labels)
+}
+
+/// Names each anonymous record of the table that is referenced by its label, counting
+/// the anonymous records of every declaration of the table in order, once repeated
+/// records have been expanded into their copies.
+pub(super) fn apply(
+    schema: Option<&StructuralIdentity>,
+    table: &mut Table,
+    labels: &Labels,
+    counts: &mut HashMap<String, usize>,
+) {
+    let scope = namespaced(table.namespace.as_deref(), table_scope(schema, &table.identity));
+    let referenced = match labels.get(&scope) {
+        Some(referenced) => referenced,
+        None => return,
+    };
+    let count = counts.entry(scope).or_default();
+
+    for record in table.nodes.iter_mut().filter(|r| r.name.is_none()) {
+        *count += 1;
+        let label = format!("_#{}", count);
+
+        if referenced.contains(&label) {
+            record.name = Some(label);
+        }
+    }
+}
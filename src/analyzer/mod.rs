@@ -1,101 +1,619 @@
-/*
-TODO
-- Verify that aliases are not reused across *different* entities
-- Update analyzer so that this can't happen:
-
-    - Alias same as another table name
-        table t1 ()
-        table table1 as t1 ()
-
-    - Different tables have same alias
-        table table1 as t ()
-        table table2 as t ()
-
-- What about situations like..
-
-    table t1 (
-        rec1 ()
-    )
-    table t2 (
-        (col t1.rec1.col)
-        (col "t1".rec1."col")
-    )
-*/
+mod aliases;
+pub mod dedupe;
 pub mod error;
+mod labels;
+mod order;
+pub mod sample;
+pub mod tags;
+mod variables;
 
 use crate::parser::nodes::*;
+use crate::parser::StreamedTable;
+use crate::Position;
 use error::*;
-use std::collections::HashSet;
+use tags::TagFilter;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 
 pub type AnalyzeResult = Result<ValidatedParseTree, AnalyzeError>;
 
+#[derive(Clone, Debug)]
 pub struct ValidatedParseTree(ParseTree);
 
 impl ValidatedParseTree {
     pub fn into_inner(self) -> ParseTree {
         self.0
     }
+
+    pub fn inner(&self) -> &ParseTree {
+        &self.0
+    }
+
+    /// Wraps a tree that has already been validated, eg. one read back from the cache.
+    pub(crate) fn validated(tree: ParseTree) -> Self {
+        Self(tree)
+    }
+}
+
+/// The records of a table streamed from the parser once they have been analyzed.
+pub struct ValidatedTable(StreamedTable);
+
+impl ValidatedTable {
+    pub fn into_inner(self) -> StreamedTable {
+        self.0
+    }
 }
 
-type RefSet = HashSet<String>;
+/// The keys of the records declared in each namespace, with those declared
+/// outside of any namespace under `None`
+type RefSet = HashMap<Option<String>, HashSet<String>>;
 
 pub fn analyze(parse_tree: ParseTree) -> AnalyzeResult {
+    analyze_tagged(parse_tree, &TagFilter::default())
+}
+
+/// Analyzes the tree just as [`analyze`] does, but then removes the records that the
+/// filter does not select, failing if any of the records that remain reference them.
+pub fn analyze_tagged(mut parse_tree: ParseTree, filter: &TagFilter) -> AnalyzeResult {
     let mut refset = RefSet::default();
 
-    for node in &parse_tree.nodes {
+    check_search_paths(&parse_tree.search_paths)?;
+    let values = variables::declare(&parse_tree.variables)?;
+    let labels = labels::referenced(&parse_tree)?;
+    let mut label_counts = HashMap::new();
+    let mut aliases = aliases::Aliases::default();
+
+    for node in &mut parse_tree.nodes {
         match node {
             StructuralNode::Schema(schema) => {
-                for table in &schema.nodes {
-                    analyze_table(Some(schema), table, &mut refset)?;
+                for table in &mut schema.nodes {
+                    aliases.declare(Some(&schema.identity), table)?;
+                    variables::substitute(table, &values)?;
+                    apply_extends(Some(&schema.identity), table)?;
+                    apply_defaults(table);
+                    expand_quantities(table);
+                    labels::apply(Some(&schema.identity), table, &labels, &mut label_counts);
+                    analyze_table(Some(&schema.identity), table, &mut refset)?;
                 }
             }
             StructuralNode::Table(table) => {
+                aliases.declare(None, table)?;
+                variables::substitute(table, &values)?;
+                apply_extends(None, table)?;
+                apply_defaults(table);
+                expand_quantities(table);
+                labels::apply(None, table, &labels, &mut label_counts);
                 analyze_table(None, table, &mut refset)?;
             }
         }
     }
 
+    // References are only validated once every record has been declared, so that
+    // records can reference others declared after them, which are then loaded first
+    for node in &mut parse_tree.nodes {
+        match node {
+            StructuralNode::Schema(schema) => {
+                for table in &mut schema.nodes {
+                    resolve_references(Some(&schema.identity), table, &refset)?;
+                }
+            }
+            StructuralNode::Table(table) => resolve_references(None, table, &refset)?,
+        }
+    }
+
+    if !filter.is_empty() {
+        let mut excluded = RefSet::default();
+
+        for node in &mut parse_tree.nodes {
+            match node {
+                StructuralNode::Schema(schema) => {
+                    for table in &mut schema.nodes {
+                        tags::exclude(Some(&schema.identity), table, filter, &mut excluded);
+                    }
+                }
+                StructuralNode::Table(table) => tags::exclude(None, table, filter, &mut excluded),
+            }
+        }
+
+        for (_, schema, table) in tables(&parse_tree) {
+            tags::check_references(schema.map(|s| &s.identity), table, &excluded)?;
+        }
+    }
+
+    order::order_records(&mut parse_tree)?;
+
     Ok(ValidatedParseTree(parse_tree))
 }
 
+/// Fails if data files declare different search paths, as there is only one search
+/// path for the whole transaction.
+fn check_search_paths(search_paths: &[SearchPath]) -> Result<(), AnalyzeError> {
+    let mut search_paths = search_paths.iter();
+    let first = match search_paths.next() {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+
+    match search_paths.find(|search_path| search_path.schemas != first.schemas) {
+        Some(search_path) => Err(AnalyzeError::new(
+            AnalyzeErrorKind::SearchPathConflict {
+                schemas: search_path.schemas.clone(),
+                declared: first.schemas.clone(),
+            },
+            search_path.position,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Analyzes tables as they are streamed from the parser, a chunk of records at a time,
+/// keeping only the keys of the records declared so far rather than the whole tree.
+///
+/// Since streamed records are loaded as soon as they are analyzed, they can only
+/// reference and extend records declared before them.
+#[derive(Debug, Default)]
+pub struct StreamAnalyzer {
+    refset: RefSet,
+    /// The attributes of the named records declared so far in the table declaration
+    /// being streamed, which later records in the declaration can extend
+    bases: HashMap<String, Vec<Attribute>>,
+    /// The filter that selects which records are loaded, and the keys of the records
+    /// it has excluded so far
+    filter: TagFilter,
+    excluded: RefSet,
+    aliases: aliases::Aliases,
+}
+
+impl StreamAnalyzer {
+    /// Analyzes streamed tables, but only loads the records that the filter selects.
+    pub fn tagged(filter: TagFilter) -> Self {
+        Self {
+            filter,
+            ..Self::default()
+        }
+    }
+
+    pub fn analyze(&mut self, mut streamed: StreamedTable) -> Result<ValidatedTable, AnalyzeError> {
+        if !streamed.continued {
+            self.bases.clear();
+        }
+
+        let schema = streamed.schema.as_ref();
+        let table = &mut streamed.table;
+
+        self.aliases.declare(schema, table)?;
+
+        // Variables cannot be declared in streamed data files, so none can be used
+        variables::substitute(table, &variables::Values::new())?;
+        self.apply_extends(schema, table)?;
+        apply_defaults(table);
+        expand_quantities(table);
+
+        // Each record's references are resolved before its own key is added, so that
+        // it can only reference the records declared before it
+        let mut analyzed = Vec::with_capacity(table.nodes.len());
+
+        for record in mem::take(&mut table.nodes) {
+            table.nodes.push(record);
+            resolve_references(schema, table, &self.refset)?;
+            analyze_table(schema, table, &mut self.refset)?;
+            analyzed.append(&mut table.nodes);
+        }
+
+        table.nodes = analyzed;
+
+        if !self.filter.is_empty() {
+            tags::exclude(schema, table, &self.filter, &mut self.excluded);
+            tags::check_references(schema, table, &self.excluded)?;
+        }
+
+        Ok(ValidatedTable(streamed))
+    }
+
+    /// Merges the attributes of the record that each record extends into the record, just
+    /// as [`apply_extends`] does, but only from records declared before it.
+    fn apply_extends(&mut self, schema: Option<&StructuralIdentity>, table: &mut Table) -> Result<(), AnalyzeError> {
+        let scope = namespaced(table.namespace.as_deref(), table_scope(schema, &table.identity));
+
+        for record in &mut table.nodes {
+            if let Some(base) = record.extends.take() {
+                let attributes = self.bases.get(&base).ok_or_else(|| {
+                    AnalyzeError::new(
+                        AnalyzeErrorKind::BaseRecordNotFound {
+                            scope: scope.clone(),
+                            record: base,
+                        },
+                        record.position,
+                    )
+                })?;
+
+                record.nodes = merge_attributes(attributes, mem::take(&mut record.nodes));
+            }
+
+            if let Some(name) = &record.name {
+                if self.bases.insert(name.clone(), record.nodes.clone()).is_some() {
+                    return Err(AnalyzeError::new(
+                        AnalyzeErrorKind::DuplicateRecord {
+                            scope,
+                            record: name.clone(),
+                        },
+                        record.position,
+                    ));
+                }
+            }
+        }
+
+        table.nodes.retain(|r| !r.is_abstract);
+
+        Ok(())
+    }
+}
+
+/// Where a table was declared in the tree, as the index of its structural node
+/// and, for tables in a schema, the index of the table within the schema.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TableIndex {
+    node: usize,
+    table: Option<usize>,
+}
+
+/// Returns every table in the tree in the order they were declared, along with
+/// where they were declared and their schema, if any.
+fn tables(tree: &ParseTree) -> Vec<(TableIndex, Option<&Schema>, &Table)> {
+    let mut tables = Vec::new();
+
+    for (node, structural_node) in tree.nodes.iter().enumerate() {
+        match structural_node {
+            StructuralNode::Schema(schema) => {
+                for (table, t) in schema.nodes.iter().enumerate() {
+                    tables.push((TableIndex { node, table: Some(table) }, Some(&**schema), t));
+                }
+            }
+            StructuralNode::Table(table) => {
+                tables.push((TableIndex { node, table: None }, None, &**table));
+            }
+        }
+    }
+
+    tables
+}
+
+/// Returns the scope that the records of a table are declared in, which is its alias
+/// or name qualified with its schema's, eg. `hr.emp`.
+pub(crate) fn table_scope(schema: Option<&StructuralIdentity>, table: &StructuralIdentity) -> String {
+    let scope = table
+        .alias
+        .as_ref()
+        .unwrap_or(&table.name);
+
+    match schema {
+        Some(schema) => format!(
+            "{}.{}",
+            schema.alias.as_ref().unwrap_or(&schema.name),
+            scope,
+        ),
+        None => scope.to_owned(),
+    }
+}
+
+/// Returns the key of the record that the reference points to within its namespace,
+/// if it points to a record rather than another column of its own record.
+pub(crate) fn record_key(table_scope: &str, refval: &Reference) -> Option<String> {
+    match refval {
+        Reference::SchemaLevel(s) => Some(format!("{}.{}.{}", s.schema, s.table, s.record)),
+        Reference::TableLevel(t) => Some(format!("{}.{}", t.table, t.record)),
+        Reference::RecordLevel(r) => Some(format!("{}.{}", table_scope, r.record)),
+        Reference::ColumnLevel(_) => None,
+    }
+}
+
+/// Returns the reference as one to the primary key of the record named by the whole
+/// reference, which is how references are read when there is no record or column to
+/// read their last identifier as, eg. `@kevin` as `kevin`'s primary key rather than the
+/// `kevin` column of the same record.
+pub(crate) fn primary_key_reference(refval: &Reference) -> Option<Reference> {
+    match refval {
+        Reference::ColumnLevel(c) => Some(Reference::RecordLevel(RecordLevelReference {
+            namespace: None,
+            record: c.column.clone(),
+            column: ReferencedColumn::PrimaryKey,
+        })),
+        Reference::RecordLevel(RecordLevelReference {
+            namespace,
+            record,
+            column: ReferencedColumn::Explicit(column),
+        }) => Some(Reference::TableLevel(TableLevelReference {
+            namespace: namespace.clone(),
+            table: record.clone(),
+            record: column.clone(),
+            column: ReferencedColumn::PrimaryKey,
+        })),
+        Reference::TableLevel(TableLevelReference {
+            namespace,
+            table,
+            record,
+            column: ReferencedColumn::Explicit(column),
+        }) => Some(Reference::SchemaLevel(SchemaLevelReference {
+            namespace: namespace.clone(),
+            schema: table.clone(),
+            table: record.clone(),
+            record: column.clone(),
+            column: ReferencedColumn::PrimaryKey,
+        })),
+        _ => None,
+    }
+}
+
+/// Finds the columns of each named record that other records reference, by the key
+/// of the record qualified with its namespace, if any, as the loader keys their rows.
+/// Records whose primary keys are referenced are included without any columns for
+/// them, since primary key columns are only known to the database.
+///
+/// This is meant to be run on a tree after it is analyzed, once references have been
+/// qualified with the namespaces of the records they reference.
+pub(crate) fn referenced_columns(tree: &ParseTree) -> HashMap<String, HashSet<String>> {
+    let mut referenced: HashMap<String, HashSet<String>> = HashMap::new();
+
+    for (_, schema, table) in tables(tree) {
+        let table_scope = table_scope(schema.map(|s| &s.identity), &table.identity);
+
+        for attribute in table.nodes.iter().flat_map(|r| &r.nodes) {
+            for refval in attribute.value.references() {
+                let key = match record_key(&table_scope, refval) {
+                    Some(key) => namespaced(refval.namespace(), key),
+                    None => continue,
+                };
+                let column = match refval {
+                    Reference::SchemaLevel(s) => &s.column,
+                    Reference::TableLevel(t) => &t.column,
+                    Reference::RecordLevel(r) => &r.column,
+                    Reference::ColumnLevel(_) => unreachable!(),
+                };
+                let columns = referenced.entry(key).or_default();
+
+                match column {
+                    ReferencedColumn::Explicit(column) => {
+                        columns.insert(column.clone());
+                    }
+                    ReferencedColumn::Implicit => {
+                        columns.insert(attribute.name.clone());
+                    }
+                    ReferencedColumn::PrimaryKey => {}
+                }
+            }
+        }
+    }
+
+    referenced
+}
+
+/// Qualifies the scope or key with the namespace, if any, eg. `billing::customer`.
+fn namespaced(namespace: Option<&str>, key: String) -> String {
+    match namespace {
+        Some(namespace) => format!("{}::{}", namespace, key),
+        None => key,
+    }
+}
+
+/// Merges the attributes of the record that each record extends into the record, before
+/// defaults are applied so that the base record's attributes take precedence over them,
+/// and then removes abstract records, which are only declared to be extended.
+fn apply_extends(schema: Option<&StructuralIdentity>, table: &mut Table) -> Result<(), AnalyzeError> {
+    if table.nodes.iter().all(|r| r.extends.is_none() && !r.is_abstract) {
+        return Ok(());
+    }
+
+    let scope = namespaced(table.namespace.as_deref(), table_scope(schema, &table.identity));
+    let mut names = HashMap::new();
+
+    for (i, record) in table.nodes.iter().enumerate() {
+        if let Some(name) = &record.name {
+            if names.insert(name.as_str(), i).is_some() {
+                return Err(AnalyzeError::new(
+                    AnalyzeErrorKind::DuplicateRecord {
+                        scope,
+                        record: name.clone(),
+                    },
+                    record.position,
+                ));
+            }
+        }
+    }
+
+    let mut merged = vec![None; table.nodes.len()];
+
+    for i in 0..table.nodes.len() {
+        merge_base(i, &table.nodes, &names, &scope, &mut merged, &mut Vec::new())?;
+    }
+
+    for (record, attributes) in table.nodes.iter_mut().zip(merged) {
+        record.nodes = attributes.expect("every record should be merged");
+        record.extends = None;
+    }
+
+    table.nodes.retain(|r| !r.is_abstract);
+
+    Ok(())
+}
+
+/// Merges the attributes of the record's base record, and in turn those of its base
+/// record, into the record's own, given the indexes of the records extending it.
+fn merge_base(
+    i: usize,
+    records: &[Record],
+    names: &HashMap<&str, usize>,
+    scope: &str,
+    merged: &mut Vec<Option<Vec<Attribute>>>,
+    extending: &mut Vec<usize>,
+) -> Result<(), AnalyzeError> {
+    if merged[i].is_some() {
+        return Ok(());
+    }
+
+    let record = &records[i];
+
+    if extending.contains(&i) {
+        let start = extending.iter().position(|&r| r == i).unwrap();
+        let records = extending[start..]
+            .iter()
+            .map(|&r| records[r].name.clone().unwrap_or_else(|| "_".to_owned()))
+            .collect();
+
+        return Err(AnalyzeError::new(AnalyzeErrorKind::ExtendsCycle { records }, record.position));
+    }
+
+    let base = match &record.extends {
+        Some(base) => base,
+        None => {
+            merged[i] = Some(record.nodes.clone());
+            return Ok(());
+        }
+    };
+    let base = match names.get(base.as_str()) {
+        Some(&base) => base,
+        None => {
+            return Err(AnalyzeError::new(
+                AnalyzeErrorKind::BaseRecordNotFound {
+                    scope: scope.to_owned(),
+                    record: base.clone(),
+                },
+                record.position,
+            ));
+        }
+    };
+
+    extending.push(i);
+    merge_base(base, records, names, scope, merged, extending)?;
+    extending.pop();
+
+    let base_attributes = merged[base].as_deref().unwrap_or_default();
+    merged[i] = Some(merge_attributes(base_attributes, record.nodes.clone()));
+
+    Ok(())
+}
+
+/// Merges the table's `$defaults` into each of its records so that the records are
+/// validated and loaded with their final attributes, except for records that update
+/// existing rows, which only update the columns they declare.
+fn apply_defaults(table: &mut Table) {
+    let defaults = mem::take(&mut table.defaults);
+
+    if defaults.is_empty() {
+        return;
+    }
+
+    for record in table.nodes.iter_mut().filter(|r| r.update_where.is_none()) {
+        record.nodes = merge_attributes(&defaults, mem::take(&mut record.nodes));
+    }
+}
+
+/// Merges attributes that are overridden by others, where the attributes are declared
+/// first, with overridden values declared in place of them, followed by the rest of
+/// the overrides.
+fn merge_attributes(attributes: &[Attribute], mut overrides: Vec<Attribute>) -> Vec<Attribute> {
+    let mut merged = Vec::new();
+
+    for attribute in attributes {
+        match overrides.iter().position(|a| a.name == attribute.name) {
+            Some(i) => merged.push(overrides.remove(i)),
+            None => merged.push(attribute.clone()),
+        }
+    }
+
+    merged.append(&mut overrides);
+    merged
+}
+
+/// Expands anonymous records declared with a quantity into that many records, where
+/// each `index()` and each `${n}` in their text values is replaced with the number
+/// of the copy, from 1.
+fn expand_quantities(table: &mut Table) {
+    if table.nodes.iter().all(|r| r.quantity.is_none()) {
+        return;
+    }
+
+    for record in mem::take(&mut table.nodes) {
+        let quantity = match record.quantity {
+            Some(quantity) => quantity,
+            None => {
+                table.nodes.push(record);
+                continue;
+            }
+        };
+
+        for n in 1..=quantity {
+            let mut copy = Record::new(None);
+            copy.position = record.position;
+            copy.span = record.span;
+
+            for attribute in &record.nodes {
+                let value = expand_value(&attribute.value, n);
+                copy.nodes.push(Attribute { value, ..attribute.clone() });
+            }
+
+            table.nodes.push(copy);
+        }
+    }
+}
+
+fn expand_value(value: &Value, n: usize) -> Value {
+    match value {
+        Value::Expression(expression) => Value::Expression(Box::new(Expression {
+            left: expand_value(&expression.left, n),
+            right: expand_value(&expression.right, n),
+            ..**expression
+        })),
+        Value::Index => Value::Number(n.to_string()),
+        Value::InterpolatedText(segments) => Value::InterpolatedText(
+            segments
+                .iter()
+                .map(|segment| match segment {
+                    TextSegment::Literal(literal) => TextSegment::Literal(literal.replace("${n}", &n.to_string())),
+                    TextSegment::Value(value) => TextSegment::Value(expand_value(value, n)),
+                })
+                .collect(),
+        ),
+        Value::InterpolatedSqlFragment(segments) => Value::InterpolatedSqlFragment(
+            segments
+                .iter()
+                .map(|segment| match segment {
+                    TextSegment::Value(value) => TextSegment::Value(expand_value(value, n)),
+                    literal => literal.clone(),
+                })
+                .collect(),
+        ),
+        Value::Text(t) => Value::Text(t.replace("${n}", &n.to_string())),
+        Value::Uuid5 { namespace, name } => Value::Uuid5 {
+            namespace: *namespace,
+            name: name.replace("${n}", &n.to_string()),
+        },
+        value => value.clone(),
+    }
+}
+
 fn analyze_table(
-    schema: Option<&Schema>,
+    schema: Option<&StructuralIdentity>,
     table: &Table,
     refset: &mut RefSet,
 ) -> Result<(), AnalyzeError> {
-    // TODO: This is mostly copy-pasta
-    let table_scope = {
-        let scope = table
-            .identity
-            .alias
-            .as_ref()
-            .unwrap_or(&table.identity.name);
-        match schema {
-            Some(schema) => format!(
-                "{}.{}",
-                schema
-                    .identity
-                    .alias
-                    .as_ref()
-                    .unwrap_or(&schema.identity.name),
-                scope,
-            ),
-            None => scope.to_owned(),
-        }
-    };
+    let table_scope = table_scope(schema, &table.identity);
+    let namespaced_scope = namespaced(table.namespace.as_deref(), table_scope.clone());
+    let keys = refset.entry(table.namespace.clone()).or_default();
+
     for record in &table.nodes {
-        analyze_record(record, refset, &table_scope)?;
+        analyze_record(record, &namespaced_scope)?;
 
         if let Some(name) = &record.name {
             let key = format!("{}.{}", table_scope, name);
 
-            if !refset.insert(key) {
-                return Err(AnalyzeError {
-                    kind: AnalyzeErrorKind::DuplicateRecord {
-                        scope: table_scope,
+            if !keys.insert(key) {
+                return Err(AnalyzeError::new(
+                    AnalyzeErrorKind::DuplicateRecord {
+                        scope: namespaced_scope,
                         record: name.clone(),
                     },
-                });
+                    record.position,
+                ));
             }
         }
     }
@@ -105,52 +623,1216 @@ fn analyze_table(
 
 fn analyze_record(
     record: &Record,
-    refset: &RefSet,
     parent_scope: &str,
 ) -> Result<(), AnalyzeError> {
     let mut attrnames = HashSet::new();
+    let mut defaults = HashSet::new();
 
     for attr in &record.nodes {
         if !attrnames.insert(&attr.name) {
-            return Err(AnalyzeError {
-                kind: AnalyzeErrorKind::DuplicateColumn {
+            return Err(AnalyzeError::in_attribute(
+                AnalyzeErrorKind::DuplicateColumn {
                     scope: parent_scope.to_owned(),
                     column: attr.name.clone(),
                 },
-            });
+                attr,
+            ));
+        }
+
+        // Any remaining `index()` values were not expanded from a repeated record
+        if attr.value.values().contains(&&Value::Index) {
+            return Err(AnalyzeError::in_attribute(
+                AnalyzeErrorKind::IndexNotRepeated {
+                    scope: parent_scope.to_owned(),
+                    column: attr.name.clone(),
+                },
+                attr,
+            ));
         }
 
-        if let Value::Reference(refval) = &attr.value {
-            // Column-level references only need validation that the column being referenced
-            // is explicitly declared in the record already, since they cannot come from the
-            // database.
+        // Columns set to `default` have no value until the record is inserted, so other
+        // columns of the record cannot repeat it
+        for refval in attr.value.references() {
             if let Reference::ColumnLevel(c) = refval {
-                if !attrnames.contains(&c.column) {
-                    return Err(AnalyzeError {
-                        kind: AnalyzeErrorKind::ColumnNotFound {
+                if defaults.contains(&c.column) {
+                    return Err(AnalyzeError::in_attribute(
+                        AnalyzeErrorKind::DefaultColumnReferenced {
+                            scope: parent_scope.to_owned(),
                             column: c.column.clone(),
                         },
-                    });
+                        attr,
+                    ));
                 }
-                continue;
             }
+        }
 
-            let expected_key = match refval {
-                Reference::SchemaLevel(s) => format!("{}.{}.{}", s.schema, s.table, s.record),
-                Reference::TableLevel(t) => format!("{}.{}", t.table, t.record),
-                Reference::RecordLevel(r) => format!("{}.{}", parent_scope, r.record),
-                Reference::ColumnLevel(_) => unreachable!(),
-            };
+        if attr.value == Value::Default {
+            defaults.insert(&attr.name);
+        }
+    }
+
+    if record.delete {
+        if let Some(attr) = record.nodes.iter().find(|a| a.value == Value::Default) {
+            return Err(AnalyzeError::in_attribute(
+                AnalyzeErrorKind::DeleteColumnInvalid {
+                    scope: parent_scope.to_owned(),
+                    column: attr.name.clone(),
+                },
+                attr,
+            ));
+        }
+    }
 
-            if !refset.contains(&expected_key) {
-                return Err(AnalyzeError {
-                    kind: AnalyzeErrorKind::RecordNotFound {
-                        record: expected_key,
+    if let Some(columns) = &record.update_where {
+        let invalid = columns.iter().find(|c| !attrnames.contains(c) || defaults.contains(c));
+
+        if let Some(column) = invalid {
+            return Err(AnalyzeError::new(
+                AnalyzeErrorKind::UpdateColumnInvalid {
+                    scope: parent_scope.to_owned(),
+                    column: column.clone(),
+                },
+                record.position,
+            ));
+        }
+
+        if columns.len() >= record.nodes.len() {
+            return Err(AnalyzeError::new(
+                AnalyzeErrorKind::UpdateColumnsMissing {
+                    scope: parent_scope.to_owned(),
+                },
+                record.position,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Validates that every record referenced by the table's records was declared, and
+/// qualifies each reference with the namespace of the record it references.
+///
+/// Column-level references only need validation that the column being referenced is
+/// explicitly declared in the record already, since they cannot come from the database.
+/// References that name neither a declared column nor a record are instead resolved to
+/// the primary key of the record named by the whole reference, if there is one.
+///
+/// References qualified with a namespace are only to records in that namespace, while
+/// others are to records in the table's own namespace or, if there is no such record,
+/// to records declared outside of any namespace. Record-level references to another
+/// namespace are qualified with the table as well, so that after analysis they are
+/// always to records in the table's own namespace.
+fn resolve_references(
+    schema: Option<&StructuralIdentity>,
+    table: &mut Table,
+    refset: &RefSet,
+) -> Result<(), AnalyzeError> {
+    let table_scope = table_scope(schema, &table.identity);
+    let schema_scope = schema.map(|s| s.alias.as_ref().unwrap_or(&s.name).clone());
+    let table_alias = table.identity.alias.as_ref().unwrap_or(&table.identity.name).clone();
+    let own_namespace = table.namespace.clone();
+
+    // The namespace that the record referenced by the reference was declared in, if any
+    let find_namespace = |refval: &Reference| {
+        let key = record_key(&table_scope, refval)?;
+        let namespaces = match refval.namespace() {
+            Some(namespace) => vec![Some(namespace.to_owned())],
+            None => vec![own_namespace.clone(), None],
+        };
+
+        namespaces
+            .into_iter()
+            .find(|namespace| refset.get(namespace).is_some_and(|keys| keys.contains(&key)))
+    };
+
+    for record in &mut table.nodes {
+        let mut attrnames = HashSet::new();
+
+        for attr in &mut record.nodes {
+            attrnames.insert(attr.name.clone());
+
+            for refval in attr.value.references_mut() {
+                if let Reference::ColumnLevel(c) = refval {
+                    if attrnames.contains(&c.column) {
+                        continue;
+                    }
+                }
+
+                let namespace = match find_namespace(refval) {
+                    Some(namespace) => namespace,
+                    None => match primary_key_reference(refval).and_then(|r| Some((find_namespace(&r)?, r))) {
+                        Some((namespace, primary_key)) => {
+                            *refval = primary_key;
+                            namespace
+                        }
+                        None => {
+                            let error = unresolved(refval, &table_scope, own_namespace.as_deref(), attr.position);
+                            return Err(error.spanning(attr.span));
+                        }
                     },
-                });
+                };
+
+                if let Reference::RecordLevel(r) = refval {
+                    if namespace != own_namespace {
+                        let (record, column) = (mem::take(&mut r.record), r.column.clone());
+
+                        *refval = match &schema_scope {
+                            Some(schema) => Reference::SchemaLevel(SchemaLevelReference {
+                                namespace: None,
+                                schema: schema.clone(),
+                                table: table_alias.clone(),
+                                record,
+                                column,
+                            }),
+                            None => Reference::TableLevel(TableLevelReference {
+                                namespace: None,
+                                table: table_alias.clone(),
+                                record,
+                                column,
+                            }),
+                        };
+                    }
+                }
+
+                if let Some(qualified) = refval.namespace_mut() {
+                    *qualified = namespace;
+                }
             }
         }
     }
 
     Ok(())
 }
+
+/// Returns the error for a reference to a column or record that was never declared.
+fn unresolved(refval: &Reference, table_scope: &str, own_namespace: Option<&str>, position: Option<Position>) -> AnalyzeError {
+    let kind = match (refval, record_key(table_scope, refval)) {
+        (_, Some(key)) => AnalyzeErrorKind::RecordNotFound {
+            record: namespaced(refval.namespace().or(own_namespace), key),
+        },
+        // Labels are never columns, so they can only be to anonymous records
+        (Reference::ColumnLevel(c), None) if labels::is_label(&c.column) => AnalyzeErrorKind::RecordNotFound {
+            record: namespaced(own_namespace, format!("{}.{}", table_scope, c.column)),
+        },
+        (Reference::ColumnLevel(c), None) => AnalyzeErrorKind::ColumnNotFound {
+            column: c.column.clone(),
+        },
+        (_, None) => unreachable!(),
+    };
+
+    AnalyzeError::new(kind, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+    use pretty_assertions::assert_eq;
+
+    fn analyzed(input: &str) -> AnalyzeResult {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        analyze(parser::parse(tokens.into_iter()).unwrap())
+    }
+
+    fn parsed(input: &str) -> ParseTree {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        parser::parse(tokens.into_iter()).unwrap()
+    }
+
+    fn attribute(name: &str, value: Value) -> Attribute {
+        Attribute::new(name.to_owned(), value)
+    }
+
+    #[test]
+    fn test_apply_defaults() {
+        let tree = analyzed(
+            r#"
+            table t1 (
+                $defaults (col1 1
+                    col2 @col1)
+                (col3 'three')
+                (col1 2
+                    col3 @col2)
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+        let col1_ref = Value::Reference(Reference::ColumnLevel(ColumnLevelReference {
+            column: "col1".to_owned(),
+        }));
+        let col2_ref = Value::Reference(Reference::ColumnLevel(ColumnLevelReference {
+            column: "col2".to_owned(),
+        }));
+
+        assert_eq!(table.defaults, Vec::new());
+        assert_eq!(
+            table.nodes,
+            vec![
+                Record {
+                    name: None,
+                    quantity: None,
+                    extends: None,
+                    is_abstract: false,
+                    tags: Vec::new(),
+                    update_where: None,
+                    delete: false,
+                    nodes: vec![
+                        attribute("col1", Value::Number("1".to_owned())),
+                        attribute("col2", col1_ref.clone()),
+                        attribute("col3", Value::Text("'three'".to_owned())),
+                    ],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                },
+                Record {
+                    name: None,
+                    quantity: None,
+                    extends: None,
+                    is_abstract: false,
+                    tags: Vec::new(),
+                    update_where: None,
+                    delete: false,
+                    nodes: vec![
+                        attribute("col1", Value::Number("2".to_owned())),
+                        attribute("col2", col1_ref),
+                        attribute("col3", col2_ref),
+                    ],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_apply_extends() {
+        let tree = analyzed(
+            r#"
+            table t1 (
+                $defaults (col1 0, col4 'default')
+                admin2 extends admin (col3 'a2@x.io')
+                abstract base (col1 1, col2 'base')
+                abstract admin extends base (col2 'admin', col3 'admin@x.io')
+                _ extends admin2 (col1 2)
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+        let records: Vec<_> = table
+            .nodes
+            .iter()
+            .map(|r| {
+                let attributes: Vec<_> = r.nodes.iter().map(|a| format!("{} {:?}", a.name, a.value)).collect();
+                (r.name.as_deref(), r.extends.as_deref(), attributes)
+            })
+            .collect();
+
+        assert_eq!(
+            records,
+            vec![
+                (
+                    Some("admin2"),
+                    None,
+                    vec![
+                        r#"col1 Number("1")"#.to_owned(),
+                        r#"col4 Text("'default'")"#.to_owned(),
+                        r#"col2 Text("'admin'")"#.to_owned(),
+                        r#"col3 Text("'a2@x.io'")"#.to_owned(),
+                    ],
+                ),
+                (
+                    None,
+                    None,
+                    vec![
+                        r#"col1 Number("2")"#.to_owned(),
+                        r#"col4 Text("'default'")"#.to_owned(),
+                        r#"col2 Text("'admin'")"#.to_owned(),
+                        r#"col3 Text("'a2@x.io'")"#.to_owned(),
+                    ],
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_invalid_extends() {
+        for (input, message) in [
+            (
+                "table t1 (\n  a extends b ()\n  b extends c ()\n  c extends a ()\n)",
+                "records extend each other in a cycle: `a` -> `b` -> `c` -> `a` on line 2",
+            ),
+            ("table t1 (\n  _ extends _ ()\n)", "expected identifier, found symbol `_` on line 2"),
+            ("table t1 (\n  a extends a ()\n)", "records extend each other in a cycle: `a` -> `a` on line 2"),
+            ("table t1 (\n  a extends b ()\n)", "base record `b` not found in scope `t1` on line 2"),
+            (
+                "table t1 (\n  abstract a ()\n  a ()\n)",
+                "duplicate record `a` in scope `t1` on line 3",
+            ),
+            (
+                "table t1 (\n  abstract a ()\n)\ntable t2 (\n  (col1 @t1.a.col1)\n)",
+                "record `t1.a` not found on line 5",
+            ),
+        ] {
+            let tokens = lexer::tokenize(input.chars()).unwrap();
+            let error = parser::parse(tokens.into_iter()).map_err(|e| e.to_string()).and_then(|tree| {
+                analyze(tree).map(|_| ()).map_err(|e| e.to_string())
+            });
+
+            assert_eq!(error, Err(message.to_owned()));
+        }
+    }
+
+    #[test]
+    fn test_update_records() {
+        let tree = analyzed(
+            "table t1 (\n  $defaults (col1 'default')\n  update a where (col2 2) (col3 3)\n  b (col2 @a.col3)\n)",
+        )
+        .unwrap()
+        .into_inner();
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+        let columns: Vec<Vec<_>> = table.nodes.iter().map(|r| r.nodes.iter().map(|a| a.name.as_str()).collect()).collect();
+
+        assert_eq!(columns, vec![vec!["col2", "col3"], vec!["col1", "col2"]]);
+
+        for (input, message) in [
+            (
+                "table t1 (\n  update a where (col1 default) (col2 2)\n)",
+                "updated rows cannot be identified by column `col1` in scope `t1` without a value on line 2",
+            ),
+            ("table t1 (\n  update a where (col1 1) (col1 2)\n)", "duplicate column `col1` in scope `t1` on line 2"),
+        ] {
+            assert_eq!(analyzed(input).map(|_| ()).map_err(|e| e.to_string()), Err(message.to_owned()));
+        }
+
+        let mut tree = parsed("table t1 (\n  update a where (col1 1) (col2 2)\n)");
+        if let StructuralNode::Table(table) = &mut tree.nodes[0] {
+            table.nodes[0].update_where = Some(vec!["col1".to_owned(), "col2".to_owned()]);
+        }
+        assert_eq!(
+            analyze(tree).map(|_| ()).map_err(|e| e.to_string()),
+            Err("record in scope `t1` updates rows without any columns to update on line 2".to_owned()),
+        );
+    }
+
+    #[test]
+    fn test_delete_records() {
+        let tree = analyzed(
+            r#"
+            table pet ( (owner_id @person.kevin.id) )
+            delete from pet where (owner_id @person.kevin.id)
+            table person ( kevin (name 'Kevin') )
+            delete from person where (name 'Alice')
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        // Deletes are loaded after every record declared before them, however those
+        // records are reordered
+        assert_eq!(
+            tree,
+            parsed(
+                r#"
+                table person ( kevin (name 'Kevin') )
+                table pet ( (owner_id @person.kevin.id) )
+                delete from pet where (owner_id @person.kevin.id)
+                delete from person where (name 'Alice')
+            "#
+            ),
+        );
+
+        for (input, message) in [
+            (
+                "delete from t1 where (col1 1, col2 default)",
+                "deleted rows cannot be identified by column `col2` in scope `t1` without a value on line 1",
+            ),
+            (
+                "table person ( kevin () )\ntable pet ( (owner_id @person.kevin.id) )\ndelete from person where (id @person.kevin.id)",
+                "record `person.kevin` cannot be referenced, as its row is deleted on line 2",
+            ),
+        ] {
+            assert_eq!(analyzed(input).map(|_| ()).map_err(|e| e.to_string()), Err(message.to_owned()));
+        }
+    }
+
+    #[test]
+    fn test_expand_quantities() {
+        let tree = analyzed(
+            r#"
+            table t1 (
+                $defaults (col1 'default ${n}')
+                _ * 3 (col2 'copy ${n} of 3', col3 true, col4 index())
+                record1 (col2 'not ${n}')
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+        let copy = |n: usize| Record {
+            name: None,
+            quantity: None,
+            extends: None,
+            is_abstract: false,
+            tags: Vec::new(),
+            update_where: None,
+            delete: false,
+            nodes: vec![
+                attribute("col1", Value::Text(format!("'default {}'", n))),
+                attribute("col2", Value::Text(format!("'copy {} of 3'", n))),
+                attribute("col3", Value::Bool(true)),
+                attribute("col4", Value::Number(n.to_string())),
+            ],
+            position: None,
+            span: None,
+            trivia: Trivia::default(),
+        };
+
+        assert_eq!(
+            table.nodes,
+            vec![
+                copy(1),
+                copy(2),
+                copy(3),
+                Record {
+                    name: Some("record1".to_owned()),
+                    quantity: None,
+                    extends: None,
+                    is_abstract: false,
+                    tags: Vec::new(),
+                    update_where: None,
+                    delete: false,
+                    nodes: vec![
+                        attribute("col1", Value::Text("'default ${n}'".to_owned())),
+                        attribute("col2", Value::Text("'not ${n}'".to_owned())),
+                    ],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_index_outside_repeated_record() {
+        let err = analyzed("table t1 (record1 (col1 index()))").err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::IndexNotRepeated {
+                scope: "t1".to_owned(),
+                column: "col1".to_owned(),
+            },
+        );
+        assert_eq!(err.position, Some(crate::Position { line: 1, column: 20 }));
+    }
+
+    #[test]
+    fn test_tags() {
+        let input = r#"
+            @tag(billing)
+            table invoice (
+                @tag(smoke)
+                inv1 (person_id @person.kevin)
+                inv2 (person_id @person.kevin)
+            )
+            table person (
+                kevin ()
+                @tag(slow)
+                alice ()
+            )
+        "#;
+        let analyzed_with = |only: &[&str], skip: &[&str]| {
+            let tokens = lexer::tokenize(input.chars()).unwrap();
+            let filter = TagFilter {
+                only: only.iter().map(|tag| tag.to_string()).collect(),
+                skip: skip.iter().map(|tag| tag.to_string()).collect(),
+            };
+
+            analyze_tagged(parser::parse(tokens.into_iter()).unwrap(), &filter)
+        };
+        let records = |tree: ValidatedParseTree| -> Vec<String> {
+            tables(tree.inner())
+                .into_iter()
+                .flat_map(|(_, _, table)| {
+                    let name = &table.identity.name;
+                    table.nodes.iter().map(move |r| format!("{}.{}", name, r.name.as_ref().unwrap()))
+                })
+                .collect()
+        };
+
+        assert_eq!(records(analyzed_with(&[], &[]).unwrap()), ["person.kevin", "person.alice", "invoice.inv1", "invoice.inv2"]);
+        assert_eq!(records(analyzed_with(&[], &["slow"]).unwrap()), ["person.kevin", "invoice.inv1", "invoice.inv2"]);
+        assert_eq!(records(analyzed_with(&[], &["billing"]).unwrap()), ["person.kevin", "person.alice"]);
+
+        // Records can only reference records that are loaded along with them
+        let err = analyzed_with(&["smoke"], &[]).err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::RecordExcluded {
+                record: "person.kevin".to_owned(),
+            },
+        );
+        assert_eq!(err.position, Some(crate::Position { line: 5, column: 23 }));
+    }
+
+    #[test]
+    fn test_sample() {
+        let input = r#"
+            table person (
+                _ * 8 (name 'p${n}')
+                p0 ()
+                p1 (manager_id @p0)
+                p2 ()
+                p3 ()
+                p4 ()
+                p5 ()
+                p6 ()
+                p7 ()
+            )
+            table invoice (
+                expect 3 rows
+                (person_id @person.p1)
+                (person_id @person.p7)
+                (person_id @person.p4)
+            )
+        "#;
+        let sampled = |limit_per_table, fraction| {
+            let tokens = lexer::tokenize(input.chars()).unwrap();
+            let tree = analyze(parser::parse(tokens.into_iter()).unwrap()).unwrap();
+            let sample = sample::Sample { limit_per_table, fraction };
+
+            sample::sample(tree, &sample).into_inner()
+        };
+        let records = |tree: &ParseTree| -> Vec<String> {
+            tables(tree)
+                .into_iter()
+                .flat_map(|(_, _, table)| {
+                    let name = &table.identity.name;
+                    table.nodes.iter().map(move |r| format!("{}.{}", name, r.name.as_deref().unwrap_or("_")))
+                })
+                .collect()
+        };
+
+        assert_eq!(records(&sampled(None, None)).len(), 19);
+
+        // Referenced records are loaded however many records are sampled, and so are
+        // the records they reference in turn
+        let tree = sampled(Some(1), None);
+        assert_eq!(records(&tree), ["person._", "person.p0", "person.p1", "invoice._"]);
+
+        let tree = sampled(None, Some(0.25));
+        assert_eq!(records(&tree), ["person._", "person._", "person.p0", "person.p1", "person.p4", "invoice._"]);
+
+        let tree = sampled(Some(1), Some(0.5));
+        assert_eq!(records(&tree), ["person._", "person.p0", "person.p1", "invoice._"]);
+
+        // Tables that lose records no longer expect the rows they declared
+        let expected_rows = |tree: &ParseTree| tables(tree).into_iter().map(|(_, _, t)| t.expected_rows).collect::<Vec<_>>();
+        assert_eq!(expected_rows(&sampled(None, None)), [None, Some(3)]);
+        assert_eq!(expected_rows(&sampled(Some(1), None)), [None, None]);
+    }
+
+    #[test]
+    fn test_default_column_referenced() {
+        let err = analyzed("table t1 (\n  record1 (col1 default, col2 @col1 || '!')\n)").err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::DefaultColumnReferenced {
+                scope: "t1".to_owned(),
+                column: "col1".to_owned(),
+            },
+        );
+        assert_eq!(err.position, Some(crate::Position { line: 2, column: 26 }));
+    }
+
+    #[test]
+    fn test_variables() {
+        let tree = analyzed(
+            "let domain = 'test.dev'\nlet age = 40\ntable t1 (\n  $defaults (host $domain)\n  (email 'kevin@{$domain}', age $age + 1)\n)",
+        )
+        .unwrap()
+        .into_inner();
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+
+        assert_eq!(
+            table.nodes[0].nodes.iter().map(|a| a.value.clone()).collect::<Vec<_>>(),
+            vec![
+                Value::Text("'test.dev'".to_owned()),
+                Value::InterpolatedText(vec![
+                    TextSegment::Literal("kevin@".to_owned()),
+                    TextSegment::Value(Value::Text("'test.dev'".to_owned())),
+                ]),
+                Value::Number("40".to_owned()).combine(Operator::Add, Value::Number("1".to_owned())),
+            ],
+        );
+
+        let err = analyzed("table t1 (\n  (email $domain)\n)").err().unwrap();
+
+        assert_eq!(err.kind, AnalyzeErrorKind::VariableNotFound { name: "domain".to_owned() });
+        assert_eq!(err.position, Some(crate::Position { line: 2, column: 4 }));
+
+        let err = analyzed("let a = 1\nlet a = 2\ntable t1 ()").err().unwrap();
+
+        assert_eq!(err.kind, AnalyzeErrorKind::DuplicateVariable { name: "a".to_owned() });
+        assert_eq!(err.position, Some(crate::Position { line: 2, column: 1 }));
+    }
+
+    #[test]
+    fn test_duplicate_default() {
+        let err = analyzed("table t1 ($defaults (col1 true, col1 false) ())").err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::DuplicateColumn {
+                scope: "t1".to_owned(),
+                column: "col1".to_owned(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_order_records() {
+        let tree = analyzed(
+            r#"
+            table person (
+                kevin (pet_id @s.pet.milo.id)
+                alice ()
+            )
+            schema s (
+                table pet truncate (
+                    milo (owner_id @person.alice.id)
+                    rex ()
+                )
+                table toy ()
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        assert_eq!(
+            tree,
+            parsed(
+                r#"
+                schema s (
+                    table toy ()
+                )
+                table person (
+                    alice ()
+                )
+                schema s (
+                    table pet truncate (
+                        milo (owner_id @person.alice.id)
+                        rex ()
+                    )
+                )
+                table person (
+                    kevin (pet_id @s.pet.milo.id)
+                )
+            "#
+            ),
+        );
+    }
+
+    #[test]
+    fn test_forward_reference() {
+        let tree = analyzed(
+            r#"
+            table t1 (
+                record1 (col1 @record2.)
+                (col2 @record1.col2)
+                record2 (col1 'one')
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        // Records are reordered within the table's declaration rather than split out
+        assert_eq!(
+            tree,
+            parsed(
+                r#"
+                table t1 (
+                    record2 (col1 'one')
+                    record1 (col1 @record2.)
+                    (col2 @record1.col2)
+                )
+            "#
+            ),
+        );
+
+        let err = analyzed("table t1 (\n  record1 (col1 @record3.)\n  record2 ()\n)").err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::RecordNotFound {
+                record: "t1.record3".to_owned(),
+            },
+        );
+        assert_eq!(err.position, Some(crate::Position { line: 2, column: 12 }));
+    }
+
+    #[test]
+    fn test_expression_references() {
+        let tree = analyzed(
+            r#"
+            table t1 (
+                _ * 2 (col1 @record1.col1 + index() * 10)
+                record1 (col1 1)
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        // References within expressions are loaded first and indexes are expanded
+        assert_eq!(
+            tree,
+            parsed(
+                r#"
+                table t1 (
+                    record1 (col1 1)
+                    (col1 @record1.col1 + 1 * 10)
+                    (col1 @record1.col1 + 2 * 10)
+                )
+            "#
+            ),
+        );
+
+        let err = analyzed("table t1 (\n  (col1 1 + @record2.col1)\n)").err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::RecordNotFound {
+                record: "t1.record2".to_owned(),
+            },
+        );
+
+        let err = analyzed("table t1 (\n  (col1 'x', col2 @col1 || @col3)\n)").err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::ColumnNotFound {
+                column: "col3".to_owned(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_referenced_columns() {
+        let tree = analyzed(
+            r#"
+            namespace billing
+            table person (
+                kevin (name 'Kevin', age 40)
+                bob (name @kevin., boss_id @kevin)
+                (name @bob.name, tags '{@kevin.age}')
+            )
+            schema s (
+                table pet (
+                    rex (owner_id @person.kevin.id)
+                )
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        let columns = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<_>>();
+
+        assert_eq!(
+            referenced_columns(&tree),
+            HashMap::from([
+                ("billing::person.kevin".to_owned(), columns(&["name", "age", "id"])),
+                ("billing::person.bob".to_owned(), columns(&["name"])),
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_primary_key_references() {
+        let tree = analyzed(
+            r#"
+            table person (
+                kevin (name 'Kevin')
+                (boss_id @kevin, name @kevin.name)
+                (kevin 'Kevin', name @kevin)
+            )
+            schema s (
+                table pet (
+                    rex (owner_id @person.kevin)
+                )
+                table vet (
+                    (pet_id @s.pet.rex, owner_id @s.pet.rex.owner_id)
+                )
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        let references: Vec<&Reference> = tree
+            .nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Schema(schema) => schema.nodes.iter().collect(),
+                StructuralNode::Table(table) => vec![table.as_ref()],
+            })
+            .flat_map(|table| table.nodes.iter().flat_map(|r| &r.nodes))
+            .flat_map(|attribute| attribute.value.references())
+            .collect();
+
+        assert_eq!(
+            references,
+            vec![
+                &Reference::RecordLevel(RecordLevelReference {
+                    namespace: None,
+                    record: "kevin".to_owned(),
+                    column: ReferencedColumn::PrimaryKey,
+                }),
+                &Reference::RecordLevel(RecordLevelReference {
+                    namespace: None,
+                    record: "kevin".to_owned(),
+                    column: ReferencedColumn::Explicit("name".to_owned()),
+                }),
+                // Columns of the same record are still referenced before records are
+                &Reference::ColumnLevel(ColumnLevelReference {
+                    column: "kevin".to_owned(),
+                }),
+                &Reference::TableLevel(TableLevelReference {
+                    namespace: None,
+                    table: "person".to_owned(),
+                    record: "kevin".to_owned(),
+                    column: ReferencedColumn::PrimaryKey,
+                }),
+                &Reference::SchemaLevel(SchemaLevelReference {
+                    namespace: None,
+                    schema: "s".to_owned(),
+                    table: "pet".to_owned(),
+                    record: "rex".to_owned(),
+                    column: ReferencedColumn::PrimaryKey,
+                }),
+                &Reference::SchemaLevel(SchemaLevelReference {
+                    namespace: None,
+                    schema: "s".to_owned(),
+                    table: "pet".to_owned(),
+                    record: "rex".to_owned(),
+                    column: ReferencedColumn::Explicit("owner_id".to_owned()),
+                }),
+            ],
+        );
+
+        // References that name neither a column nor a record are still to columns
+        let err = analyzed("table t1 (\n  rec1 (col1 @rec2)\n)").err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::ColumnNotFound {
+                column: "rec2".to_owned(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_namespaces() {
+        // Namespaces are declared per file, so each input is parsed separately
+        let analyzed_files = |inputs: &[&str]| {
+            let mut tree = ParseTree::default();
+
+            for input in inputs {
+                tree.nodes.extend(parsed(input).nodes);
+            }
+
+            analyze(tree)
+        };
+        let global = "table roles (\n  admin (name 'Admin')\n)\ntable customer (\n  customer1 (name 'Global')\n)";
+        let billing = r#"
+            namespace billing
+            table customer (
+                customer1 (name 'Billing')
+            )
+            table invoice (
+                (customer_id @customer.customer1.id
+                    role_id @roles.admin.id)
+            )
+        "#;
+        let sales = "namespace sales\ntable customer (\n  customer1 (name 'Sales')\n  customer2 (billing_id @billing::customer1.id)\n)";
+
+        let tree = analyzed_files(&[sales, billing, global]).unwrap().into_inner();
+        let references: Vec<String> = tables(&tree)
+            .into_iter()
+            .flat_map(|(_, _, table)| table.nodes.iter().flat_map(|r| &r.nodes))
+            .flat_map(|attribute| attribute.value.references().map(|r| r.to_string()).collect::<Vec<_>>())
+            .collect();
+
+        // References are qualified with the namespace of the record they reference, which is
+        // the table's own before any outside of a namespace, and record-level references to
+        // other namespaces are qualified with the table as well
+        assert_eq!(
+            references,
+            vec!["@billing::customer.customer1.id", "@billing::customer.customer1.id", "@roles.admin.id"],
+        );
+
+        for (inputs, message) in [
+            (vec![billing, "namespace billing\ntable customer (\n  customer1 ()\n)"], "duplicate record `customer1` in scope `billing::customer` on line 3"),
+            (vec![billing, global, "table invoice (\n  (customer_id @billing::customer.customer9.id)\n)"], "record `billing::customer.customer9` not found on line 2"),
+            (vec![sales, global], "record `billing::customer.customer1` not found on line 4"),
+            (vec![sales, billing, global, "table invoice (\n  (customer_id @customer.customer2.id)\n)"], "record `customer.customer2` not found on line 2"),
+        ] {
+            assert_eq!(analyzed_files(&inputs).err().unwrap().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_aliases() {
+        // The same table can be declared with and without its alias, and with several
+        assert!(analyzed("table table1 as x ()\ntable table1 ()\ntable table1 as t1 ()\ntable table1 as x ()").is_ok());
+        assert!(analyzed("schema s1 as s (\n  table t1 ()\n)\nschema s2 (\n  table t1 as s ()\n)").is_ok());
+        assert!(analyzed("namespace a\ntable table1 as x ()\n").is_ok());
+
+        for (input, message) in [
+            ("table t1 ()\ntable table1 as t1 ()", "alias `t1` of `table1` conflicts with the name of `t1` on line 2"),
+            ("table table1 as t1 ()\ntable t1 ()", "alias `t1` of `table1` conflicts with the name of `t1` on line 2"),
+            ("table table1 as x ()\ntable table2 as x ()", "alias `x` is used for both `table1` and `table2` on line 2"),
+            (
+                "schema s1 as s (\n  table t1 ()\n)\nschema s2 as s (\n  table t1 ()\n)",
+                "alias `s` is used for both `s1` and `s2` on line 5",
+            ),
+            (
+                "schema s1 (\n  table table1 as x ()\n  table table2 as x ()\n)",
+                "alias `x` is used for both `s1.table1` and `s1.table2` on line 3",
+            ),
+        ] {
+            assert_eq!(analyzed(input).err().unwrap().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_quoted_identifiers() {
+        let tree = analyzed(
+            r#"
+            table person (
+                kevin (name 'Kevin')
+            )
+            table "person" (
+                (boss_id @"person".kevin."id")
+                ("first name" 'Bob', boss_id @person.kevin)
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+        let references: Vec<String> = tables(&tree)
+            .into_iter()
+            .flat_map(|(_, _, table)| table.nodes.iter().flat_map(|r| &r.nodes))
+            .flat_map(|attribute| attribute.value.references().map(|r| r.to_string()).collect::<Vec<_>>())
+            .collect();
+
+        // Names are the same whether or not they are quoted, and only quoted where needed
+        assert_eq!(references, vec!["@person.kevin.id", "@person.kevin"]);
+        assert_eq!(tables(&tree)[1].2.nodes[1].nodes[0].name, "first name");
+
+        assert_eq!(
+            analyzed("table person (\n  kevin ()\n)\ntable \"person\" (\n  kevin ()\n)").err().unwrap().to_string(),
+            "duplicate record `kevin` in scope `person` on line 5",
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let tree = analyzed(
+            r#"
+            table person (
+                (name 'Kevin')
+                _ * 2 (name 'Copy ${n}')
+                (name 'Bob', boss_id @_#1, friend @_#3.name)
+            )
+            table person (
+                (boss_id @_#4)
+            )
+        "#,
+        )
+        .unwrap()
+        .into_inner();
+
+        // Only the anonymous records that are referenced are named with their labels
+        let names: Vec<Option<&str>> = tables(&tree)
+            .into_iter()
+            .flat_map(|(_, _, table)| &table.nodes)
+            .map(|record| record.name.as_deref())
+            .collect();
+        assert_eq!(names, vec![Some("_#1"), None, Some("_#3"), Some("_#4"), None]);
+
+        for (input, message) in [
+            ("table person (\n  (name 'Kevin')\n  (boss_id @_#3)\n)", "record `person._#3` not found on line 3"),
+            (
+                "table person (\n  (name 'Kevin')\n)\ntable pet (\n  (owner_id @person._#1)\n)",
+                "anonymous record `person._#1` can only be referenced by records of its own table on line 5",
+            ),
+            (
+                "table person (\n  (name 'Kevin')\n)\ntable pet (\n  (owner_id @person._#1.id)\n)",
+                "anonymous record `person._#1.id` can only be referenced by records of its own table on line 5",
+            ),
+        ] {
+            assert_eq!(analyzed(input).err().unwrap().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_reference_cycle() {
+        let err = analyzed(
+            r#"
+            table t1 (
+                (col1 @t2.b.col1)
+                a (col1 @t2.b.col1)
+            )
+            table t2 (
+                b (col1 @t1.a.col1)
+            )
+        "#,
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::ReferenceCycle {
+                records: vec!["t1.a".to_owned(), "t2.b".to_owned()],
+            },
+        );
+        assert_eq!(err.position, Some(crate::Position { line: 4, column: 20 }));
+        assert_eq!(
+            err.to_string(),
+            "records reference each other in a cycle: `t1.a` -> `t2.b` -> `t1.a` on line 4",
+        );
+
+        let err = analyzed("table t1 (\n  a (col1 @a.col2)\n)").err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::ReferenceCycle {
+                records: vec!["t1.a".to_owned()],
+            },
+        );
+    }
+
+    #[test]
+    fn test_search_path_conflict() {
+        let analyzed_files = |inputs: &[&str]| {
+            let mut tree = ParseTree::default();
+
+            for input in inputs {
+                tree.search_paths.extend(parsed(input).search_paths);
+            }
+
+            analyze(tree)
+        };
+
+        assert!(analyzed_files(&["set search_path app, public", "", "set search_path app, public"]).is_ok());
+
+        let err = analyzed_files(&["set search_path app, public", "\nset search_path app"]).err().unwrap();
+
+        assert_eq!(
+            err.kind,
+            AnalyzeErrorKind::SearchPathConflict {
+                schemas: vec!["app".to_owned()],
+                declared: vec!["app".to_owned(), "public".to_owned()],
+            },
+        );
+        assert_eq!(
+            err.to_string(),
+            "search path `app` conflicts with search path `app, public` declared in another data file on line 2",
+        );
+    }
+
+    #[test]
+    fn test_stream_analyzer() {
+        let streamed = |input: &str| -> Result<Vec<Table>, String> {
+            let tokens = lexer::tokenize(input.chars()).unwrap();
+            let mut analyzer = StreamAnalyzer::default();
+
+            parser::stream(tokens.into_iter().map(Ok::<_, parser::error::ParseError>), 1)
+                .map(|streamed| {
+                    let streamed = streamed.map_err(|e| e.to_string())?;
+                    let analyzed = analyzer.analyze(streamed).map_err(|e| e.to_string())?;
+
+                    Ok(analyzed.into_inner().table)
+                })
+                .collect()
+        };
+
+        let tables = streamed(
+            "table t1 (
+  $defaults (col1 0)
+  abstract base (col2 'two')
+  rec1 extends base (col3 @rec2.col1)
+  rec2 extends base (col1 1)
+  rec3 extends rec2 (col3 @rec2.col1)
+  _ * 2 (col3 @rec3.col3)
+)
+table t2 (
+  (col1 @t1.rec2)
+)
+",
+        );
+
+        // References to records declared later cannot be resolved, as the records are
+        // loaded as soon as they are analyzed
+        assert_eq!(tables.err(), Some("record `t1.rec2` not found on line 4".to_owned()));
+
+        let tables = streamed(
+            "table t1 (
+  $defaults (col1 0)
+  abstract base (col2 'two')
+  rec2 extends base (col1 1)
+  rec3 extends rec2 (col3 @rec2.col1)
+  _ * 2 (col3 @rec3.col3)
+)
+table t2 (
+  (col1 @t1.rec2)
+)
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            tables.iter().map(|t| t.nodes.len()).collect::<Vec<_>>(),
+            // The abstract record is streamed but removed, and the copies are streamed together
+            vec![0, 1, 1, 2, 1],
+        );
+
+        // Records extend those from earlier chunks, before defaults are applied
+        let extended = &tables[2].nodes[0];
+        assert_eq!(extended.nodes.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), vec!["col1", "col2", "col3"]);
+        assert_eq!(extended.nodes[0].value, Value::Number("1".to_owned()));
+    }
+}
@@ -0,0 +1,372 @@
+use super::error::{AnalyzeError, AnalyzeErrorKind};
+use super::{namespaced, record_key, table_scope, tables, TableIndex};
+use crate::parser::nodes::*;
+use crate::Position;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::mem;
+use std::ops::Range;
+
+/// A record that another record references, along with where the attribute
+/// referencing it was declared.
+struct Dependency {
+    record: usize,
+    position: Option<Position>,
+    span: Option<Span>,
+}
+
+/// The key of a record that another record references, along with where the attribute
+/// referencing it starts and ends.
+type Referenced = (String, Option<Position>, Option<Span>);
+
+/// Reorders the tree's records so that every record is loaded after the records it
+/// references, keeping them in the order they were declared wherever references allow
+/// and splitting up as few table declarations as possible. Records that are moved end
+/// up in their own declaration of their table, eg.
+///
+/// ```text
+/// table person (
+///   kevin (pet_id @pet.milo.id)
+/// )
+/// table pet (
+///   milo (name 'Milo')
+/// )
+/// ```
+///
+/// is loaded as if `pet` had been declared first. References are expected to have
+/// already been validated, and it is an error if records reference each other in a
+/// cycle, as none of them could be loaded first.
+///
+/// Deletes declared with `delete from ... where (...)` are loaded after every record
+/// declared before them, so that rows are only deleted once whatever references them
+/// has been, and it is an error to reference a record whose row a delete identifies by
+/// a reference to it, eg. `delete from person where (id @person.kevin.id)`.
+pub(super) fn order_records(tree: &mut ParseTree) -> Result<(), AnalyzeError> {
+    let mut declarations = Vec::new();
+    let mut keys = HashMap::new();
+    let mut references = Vec::new();
+    let mut deletes = Vec::new();
+    let mut deleted = HashSet::new();
+
+    for (index, schema, table) in tables(tree) {
+        let table_scope = table_scope(schema.map(|s| &s.identity), &table.identity);
+
+        // References have been qualified with the namespace of the records they
+        // reference, so records are keyed by their namespace as well
+        for record in &table.nodes {
+            if let Some(name) = &record.name {
+                let key = namespaced(table.namespace.as_deref(), format!("{}.{}", table_scope, name));
+                keys.insert(key, declarations.len());
+            }
+
+            let record_references: Vec<_> = record
+                .nodes
+                .iter()
+                .flat_map(|attribute| {
+                    attribute
+                        .value
+                        .references()
+                        .filter_map(|refval| {
+                            let key = record_key(&table_scope, refval)?;
+
+                            // Deletes that identify rows by references to records of
+                            // the same table delete those records' rows
+                            if record.delete && key.rsplit_once('.').is_some_and(|(scope, _)| scope == table_scope) {
+                                deleted.insert(namespaced(refval.namespace(), key.clone()));
+                            }
+
+                            Some((namespaced(refval.namespace(), key), attribute.position, attribute.span))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+
+            if record.delete {
+                deletes.push(declarations.len());
+            }
+
+            declarations.push(index);
+            references.push(record_references);
+        }
+    }
+
+    check_deleted(&references, &deletes, &deleted)?;
+
+    let mut dependencies: Vec<Vec<Dependency>> = references
+        .into_iter()
+        .map(|record_references| {
+            record_references
+                .into_iter()
+                .map(|(key, position, span)| Dependency {
+                    record: keys[&key],
+                    position,
+                    span,
+                })
+                .collect()
+        })
+        .collect();
+
+    // Each delete depends on the last delete before it and the records since, and so
+    // on every record declared before it
+    let mut previous = 0;
+
+    for &delete in &deletes {
+        let earlier = (previous..delete).map(|record| Dependency {
+            record,
+            position: None,
+            span: None,
+        });
+
+        dependencies[delete].extend(earlier);
+        previous = delete;
+    }
+
+    let order = match sort(&dependencies, &declarations) {
+        Ok(order) => order,
+        Err(unloaded) => {
+            let names: HashMap<usize, String> = keys.into_iter().map(|(key, i)| (i, key)).collect();
+            return Err(cycle_error(&dependencies, &unloaded, &names));
+        }
+    };
+
+    if order.iter().enumerate().all(|(i, &record)| i == record) {
+        return Ok(());
+    }
+
+    rebuild(tree, &declarations, &order);
+
+    Ok(())
+}
+
+/// Fails if any record other than a delete references a record whose row is deleted.
+fn check_deleted(
+    references: &[Vec<Referenced>],
+    deletes: &[usize],
+    deleted: &HashSet<String>,
+) -> Result<(), AnalyzeError> {
+    if deleted.is_empty() {
+        return Ok(());
+    }
+
+    let referenced = references
+        .iter()
+        .enumerate()
+        .filter(|(record, _)| deletes.binary_search(record).is_err())
+        .flat_map(|(_, record_references)| record_references)
+        .find(|(key, _, _)| deleted.contains(key));
+
+    match referenced {
+        Some((key, position, span)) => Err(AnalyzeError::new(
+            AnalyzeErrorKind::DeletedRecordReferenced { record: key.clone() },
+            *position,
+        )
+        .spanning(*span)),
+        None => Ok(()),
+    }
+}
+
+/// Sorts the records topologically, loading the earliest declared record whose
+/// dependencies have all been loaded, preferring those in the same table declaration
+/// as the last record loaded, or returns the number of dependencies that could not be
+/// loaded for each record if any records reference each other in a cycle.
+fn sort(dependencies: &[Vec<Dependency>], declarations: &[TableIndex]) -> Result<Vec<usize>, Vec<usize>> {
+    let declaration_ranges = declaration_ranges(declarations);
+    let mut unloaded: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+    let mut dependents = vec![Vec::new(); dependencies.len()];
+
+    for (record, record_dependencies) in dependencies.iter().enumerate() {
+        for dependency in record_dependencies {
+            dependents[dependency.record].push(record);
+        }
+    }
+
+    let mut ready: BTreeSet<_> = (0..dependencies.len()).filter(|&record| unloaded[record] == 0).collect();
+    let mut order: Vec<usize> = Vec::with_capacity(dependencies.len());
+
+    loop {
+        let same_declaration = order
+            .last()
+            .and_then(|&last| ready.range(declaration_ranges[last].clone()).next());
+
+        let record = match same_declaration.or_else(|| ready.first()) {
+            Some(&record) => record,
+            None => break,
+        };
+
+        ready.remove(&record);
+        order.push(record);
+
+        for &dependent in &dependents[record] {
+            unloaded[dependent] -= 1;
+
+            if unloaded[dependent] == 0 {
+                ready.insert(dependent);
+            }
+        }
+    }
+
+    if order.len() == dependencies.len() {
+        Ok(order)
+    } else {
+        Err(unloaded)
+    }
+}
+
+/// Returns the range of records in each record's table declaration, which are contiguous.
+fn declaration_ranges(declarations: &[TableIndex]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::with_capacity(declarations.len());
+    let mut start = 0;
+
+    for (record, declaration) in declarations.iter().enumerate() {
+        if declarations.get(record + 1) != Some(declaration) {
+            ranges.extend((start..=record).map(|_| start..record + 1));
+            start = record + 1;
+        }
+    }
+
+    ranges
+}
+
+/// Finds a cycle among the records that could not be loaded, each of which
+/// references at least one other that could not be loaded, by following their
+/// references from the earliest declared until a record is reached again.
+fn cycle_error(dependencies: &[Vec<Dependency>], unloaded: &[usize], names: &HashMap<usize, String>) -> AnalyzeError {
+    let next = |record: usize| {
+        dependencies[record]
+            .iter()
+            .find(|d| unloaded[d.record] > 0)
+            .expect("unloaded record should reference another unloaded record")
+    };
+
+    let mut path = vec![unloaded.iter().position(|&n| n > 0).expect("a record should be unloaded")];
+
+    let start = loop {
+        let record = next(*path.last().unwrap()).record;
+
+        match path.iter().position(|&r| r == record) {
+            Some(start) => break start,
+            None => path.push(record),
+        }
+    };
+
+    // The cycle starts with whichever of its records was declared first
+    let mut cycle = path.split_off(start);
+    let first = cycle.iter().enumerate().min_by_key(|(_, &r)| r).map(|(i, _)| i).unwrap();
+    cycle.rotate_left(first);
+
+    let dependency = next(cycle[0]);
+
+    AnalyzeError::new(
+        AnalyzeErrorKind::ReferenceCycle {
+            records: cycle.iter().map(|r| names[r].clone()).collect(),
+        },
+        dependency.position,
+    )
+    .spanning(dependency.span)
+}
+
+/// Moves the records into the given order, grouping consecutive records from the
+/// same table declaration into a copy of that declaration. Declarations without
+/// any records are kept, before all others, as they may still be truncated.
+fn rebuild(tree: &mut ParseTree, declarations: &[TableIndex], order: &[usize]) {
+    let mut source = mem::take(&mut tree.nodes);
+    let mut records = Vec::with_capacity(declarations.len());
+    let mut empty = Vec::new();
+
+    for (node, structural_node) in source.iter_mut().enumerate() {
+        match structural_node {
+            StructuralNode::Schema(schema) => {
+                for (table, t) in schema.nodes.iter_mut().enumerate() {
+                    if t.nodes.is_empty() {
+                        empty.push(TableIndex { node, table: Some(table) });
+                    }
+                    records.extend(t.nodes.drain(..).map(Some));
+                }
+            }
+            StructuralNode::Table(table) => {
+                if table.nodes.is_empty() {
+                    empty.push(TableIndex { node, table: None });
+                }
+                records.extend(table.nodes.drain(..).map(Some));
+            }
+        }
+    }
+
+    let mut builder = Builder {
+        source: &source,
+        nodes: Vec::new(),
+        last: None,
+    };
+
+    for index in empty {
+        builder.push(index, None);
+    }
+
+    for &record in order {
+        builder.push(declarations[record], records[record].take());
+    }
+
+    tree.nodes = builder.nodes;
+}
+
+struct Builder<'a> {
+    /// The original nodes, whose tables have had their records taken
+    source: &'a [StructuralNode],
+    nodes: Vec<StructuralNode>,
+    /// Where the table that records are currently being added to was declared
+    last: Option<TableIndex>,
+}
+
+impl Builder<'_> {
+    fn push(&mut self, index: TableIndex, record: Option<Record>) {
+        if self.last != Some(index) {
+            self.push_declaration(index);
+        }
+
+        let table = match self.nodes.last_mut() {
+            Some(StructuralNode::Schema(schema)) => schema.nodes.last_mut(),
+            Some(StructuralNode::Table(table)) => Some(&mut **table),
+            None => None,
+        };
+
+        table.expect("table should have been declared").nodes.extend(record);
+    }
+
+    /// Adds a copy of the table declaration, within a copy of its schema unless the
+    /// previous table was declared in the same schema.
+    fn push_declaration(&mut self, index: TableIndex) {
+        let same_schema = self.last.is_some_and(|last| last.node == index.node);
+        self.last = Some(index);
+
+        match (&self.source[index.node], index.table) {
+            (StructuralNode::Schema(schema), Some(table)) => {
+                let table = copy_declaration(&schema.nodes[table]);
+
+                match self.nodes.last_mut() {
+                    Some(StructuralNode::Schema(copy)) if same_schema => copy.nodes.push(table),
+                    _ => {
+                        let identity = &schema.identity;
+                        let mut copy = Schema::new(identity.name.clone(), identity.alias.clone());
+
+                        copy.nodes.push(table);
+                        self.nodes.push(StructuralNode::Schema(Box::new(copy)));
+                    }
+                }
+            }
+            (StructuralNode::Table(table), None) => {
+                self.nodes.push(StructuralNode::Table(Box::new(copy_declaration(table))));
+            }
+            _ => unreachable!("table index should match its node"),
+        }
+    }
+}
+
+/// Copies the table declaration without its records, whose defaults have already
+/// been applied to them.
+fn copy_declaration(table: &Table) -> Table {
+    let identity = &table.identity;
+    let mut copy = Table::new(identity.name.clone(), identity.alias.clone(), table.conflict_target.clone());
+
+    copy.truncate = table.truncate;
+    copy.namespace = table.namespace.clone();
+    copy
+}
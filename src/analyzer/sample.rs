@@ -0,0 +1,112 @@
+use super::{namespaced, record_key, table_scope, tables, ValidatedParseTree};
+use crate::parser::nodes::{StructuralNode, Table};
+use std::collections::HashMap;
+
+/// Selects only some of each table's records, so that schema changes can be smoke
+/// tested quickly against large data files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sample {
+    /// The number of records to load from each table at most
+    pub limit_per_table: Option<usize>,
+    /// The fraction of each table's records to load, evenly spread across them
+    pub fraction: Option<f64>,
+}
+
+impl Sample {
+    pub fn is_empty(&self) -> bool {
+        self.limit_per_table.is_none() && self.fraction.is_none()
+    }
+
+    /// Whether each of the table's records is selected, which is always the same for
+    /// the same records, keeping each record while fewer than the fraction of the records
+    /// so far have been kept, starting with the first, up to the limit.
+    fn selects(&self, table: &Table) -> Vec<bool> {
+        let mut count = 0;
+
+        (0..table.nodes.len())
+            .map(|index| {
+                let sampled = self.fraction.is_none_or(|fraction| (count as f64) < (index + 1) as f64 * fraction);
+                let selected = sampled && self.limit_per_table.is_none_or(|limit| count < limit);
+
+                count += selected as usize;
+                selected
+            })
+            .collect()
+    }
+}
+
+/// Removes the records that the sample does not select from the analyzed tree, except
+/// named records that the records which remain reference, directly or through others,
+/// so that the references still resolve. Tables that lose records no longer expect any
+/// number of rows, as their expectations are of the whole table.
+pub fn sample(tree: ValidatedParseTree, sample: &Sample) -> ValidatedParseTree {
+    let mut tree = tree.into_inner();
+
+    if sample.is_empty() {
+        return ValidatedParseTree(tree);
+    }
+
+    let declared = tables(&tree);
+    let mut selected: Vec<Vec<bool>> = declared.iter().map(|(_, _, table)| sample.selects(table)).collect();
+
+    // The table and index of each named record, by key qualified with its namespace
+    let mut records = HashMap::new();
+
+    for (t, (_, schema, table)) in declared.iter().enumerate() {
+        let table_scope = table_scope(schema.map(|s| &s.identity), &table.identity);
+
+        for (r, record) in table.nodes.iter().enumerate() {
+            if let Some(name) = &record.name {
+                let key = namespaced(table.namespace.as_deref(), format!("{}.{}", table_scope, name));
+                records.insert(key, (t, r));
+            }
+        }
+    }
+
+    let mut pending: Vec<(usize, usize)> = selected
+        .iter()
+        .enumerate()
+        .flat_map(|(t, records)| records.iter().enumerate().filter(|(_, s)| **s).map(move |(r, _)| (t, r)))
+        .collect();
+
+    while let Some((t, r)) = pending.pop() {
+        let (_, schema, table) = declared[t];
+        let table_scope = table_scope(schema.map(|s| &s.identity), &table.identity);
+
+        for refval in table.nodes[r].nodes.iter().flat_map(|a| a.value.references()) {
+            let key = match record_key(&table_scope, refval) {
+                Some(key) => namespaced(refval.namespace(), key),
+                None => continue,
+            };
+
+            if let Some(&(t, r)) = records.get(&key) {
+                if !selected[t][r] {
+                    selected[t][r] = true;
+                    pending.push((t, r));
+                }
+            }
+        }
+    }
+
+    // Tables are visited in the same order as they were declared above
+    let mut selected = selected.into_iter();
+
+    for node in &mut tree.nodes {
+        let tables: Vec<&mut Table> = match node {
+            StructuralNode::Schema(schema) => schema.nodes.iter_mut().collect(),
+            StructuralNode::Table(table) => vec![&mut **table],
+        };
+
+        for table in tables {
+            let selected = selected.next().expect("table not declared");
+
+            if selected.contains(&false) {
+                let mut selected = selected.into_iter();
+                table.nodes.retain(|_| selected.next().unwrap());
+                table.expected_rows = None;
+            }
+        }
+    }
+
+    ValidatedParseTree(tree)
+}
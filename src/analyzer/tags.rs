@@ -0,0 +1,76 @@
+use super::error::{AnalyzeError, AnalyzeErrorKind};
+use super::{namespaced, record_key, table_scope, RefSet};
+use crate::parser::nodes::{Record, StructuralIdentity, Table};
+use std::mem;
+
+/// Selects records by the tags declared with `@tag(...)` above them or their tables,
+/// so that one data file can serve several test suites.
+#[derive(Clone, Debug, Default)]
+pub struct TagFilter {
+    /// The tags that records must have at least one of to be loaded, unless empty
+    pub only: Vec<String>,
+    /// The tags that exclude any records that have them
+    pub skip: Vec<String>,
+}
+
+impl TagFilter {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.skip.is_empty()
+    }
+
+    /// Whether the record is selected, given the tags of its own and of its table.
+    pub fn selects(&self, table: &Table, record: &Record) -> bool {
+        let tags: Vec<&String> = table.tags.iter().chain(&record.tags).collect();
+
+        (self.only.is_empty() || tags.iter().any(|tag| self.only.contains(tag)))
+            && !tags.iter().any(|tag| self.skip.contains(tag))
+    }
+}
+
+/// Removes the table's records that the filter does not select, adding the keys of any
+/// named ones to the excluded records so that references to them can be rejected.
+pub(super) fn exclude(schema: Option<&StructuralIdentity>, table: &mut Table, filter: &TagFilter, excluded: &mut RefSet) {
+    let table_scope = table_scope(schema, &table.identity);
+
+    for record in mem::take(&mut table.nodes) {
+        if filter.selects(table, &record) {
+            table.nodes.push(record);
+        } else if let Some(name) = &record.name {
+            excluded
+                .entry(table.namespace.clone())
+                .or_default()
+                .insert(format!("{}.{}", table_scope, name));
+        }
+    }
+}
+
+/// Fails if any of the table's records reference an excluded record, which is only
+/// known once references have been resolved and qualified with their namespaces.
+pub(super) fn check_references(
+    schema: Option<&StructuralIdentity>,
+    table: &Table,
+    excluded: &RefSet,
+) -> Result<(), AnalyzeError> {
+    let table_scope = table_scope(schema, &table.identity);
+
+    for attr in table.nodes.iter().flat_map(|r| &r.nodes) {
+        for refval in attr.value.references() {
+            let key = match record_key(&table_scope, refval) {
+                Some(key) => key,
+                None => continue,
+            };
+            let namespace = refval.namespace().map(str::to_owned);
+
+            if excluded.get(&namespace).is_some_and(|keys| keys.contains(&key)) {
+                return Err(AnalyzeError::in_attribute(
+                    AnalyzeErrorKind::RecordExcluded {
+                        record: namespaced(namespace.as_deref(), key),
+                    },
+                    attr,
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use super::error::{AnalyzeError, AnalyzeErrorKind};
+use crate::parser::nodes::{Table, Value, Variable};
+use std::collections::HashMap;
+
+/// The values of the variables declared with `let name = ...`, by name
+pub(super) type Values<'a> = HashMap<&'a str, &'a Value>;
+
+/// Collects the values of the variables, failing if any are declared more than once,
+/// since the data files loaded together share their variables.
+pub(super) fn declare(variables: &[Variable]) -> Result<Values<'_>, AnalyzeError> {
+    let mut values = Values::with_capacity(variables.len());
+
+    for variable in variables {
+        if values.insert(&variable.name, &variable.value).is_some() {
+            return Err(AnalyzeError::new(
+                AnalyzeErrorKind::DuplicateVariable {
+                    name: variable.name.clone(),
+                },
+                variable.position,
+            ));
+        }
+    }
+
+    Ok(values)
+}
+
+/// Replaces each variable used in the table's defaults and records with its value,
+/// including within expressions and interpolated text, eg. `'{$domain}'`.
+pub(super) fn substitute(table: &mut Table, values: &Values) -> Result<(), AnalyzeError> {
+    let attributes = table.defaults.iter_mut().chain(table.nodes.iter_mut().flat_map(|r| &mut r.nodes));
+
+    for attr in attributes {
+        for value in attr.value.values_mut() {
+            if let Value::Variable(name) = value {
+                match values.get(name.as_str()) {
+                    Some(&variable) => *value = variable.clone(),
+                    None => {
+                        return Err(AnalyzeError::new(
+                            AnalyzeErrorKind::VariableNotFound { name: name.clone() },
+                            attr.position,
+                        )
+                        .spanning(attr.span))
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+//! The parse tree of data files, for tooling that builds, inspects, or rewrites data
+//! without reaching into the parser or analyzer.
+//!
+//! Everything here is covered by the crate's semver guarantees, whereas the modules it
+//! is re-exported from may change between minor versions.
+
+pub use crate::analyzer::ValidatedParseTree;
+pub use crate::parser::builder::{
+    boolean, default, expression, fragment, null, number, reference, text, variable, ParseTreeBuilder,
+};
+pub use crate::parser::nodes::{
+    Attribute, ColumnLevelReference, Expression, Hook, HookTiming, Import, Include, Operator, ParseTree,
+    Record, RecordLevelReference, Reference, ReferencedColumn, Schema, SchemaLevelReference, SearchPath,
+    Setting, StructuralIdentity, StructuralNode, Table, TableLevelReference, TextSegment, TimeOffset,
+    Trivia, TriviaItem, Value, Variable,
+};
+pub use crate::position::Position;
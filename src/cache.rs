@@ -0,0 +1,193 @@
+//! Caches the analyzed trees of data files on disk with the `cache` option, so that
+//! repeatedly loading large data files that have not changed skips lexing, parsing,
+//! and analyzing them.
+
+use crate::analyzer::ValidatedParseTree;
+use crate::diagnostics::SourceMap;
+use crate::parser::nodes::ParseTree;
+use crate::Options;
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::{fs, io, process};
+
+/// The directory that entries are kept in, relative to the working directory
+pub(crate) const CACHE_DIR: &str = ".hldr-cache";
+
+/// The version of the format of entries, which must be increased whenever it or the
+/// parse tree changes, so that entries in an older format are never read
+const FORMAT_VERSION: u32 = 3;
+
+/// The analyzed tree of the data files along with the files it was parsed from, so that
+/// it is only used while none of them have changed.
+#[derive(Deserialize, Serialize)]
+struct Entry<Tree> {
+    format_version: u32,
+    hldr_version: String,
+    /// The data files and the files they include, in the order of the source map that
+    /// errors are located with
+    sources: Vec<CachedFile>,
+    imported_files: Vec<CachedFile>,
+    tree: Tree,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedFile {
+    path: PathBuf,
+    hash: u64,
+}
+
+impl CachedFile {
+    fn new(path: &Path, contents: &[u8]) -> Self {
+        Self {
+            path: path.to_owned(),
+            hash: hash(contents),
+        }
+    }
+
+    fn is_current(&self, contents: &[u8]) -> bool {
+        self.hash == hash(contents)
+    }
+}
+
+fn hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns the path of the entry for the data files given in the options and the other
+/// options that change how they are analyzed, which stays the same whatever the files
+/// contain, so that each new entry replaces the one before it.
+fn entry_path(dir: &Path, options: &Options) -> io::Result<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+
+    FORMAT_VERSION.hash(&mut hasher);
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    options.data_file_paths()?.hash(&mut hasher);
+    options.profiles.hash(&mut hasher);
+    options.only_tags.hash(&mut hasher);
+    options.skip_tags.hash(&mut hasher);
+    options.limit_per_table.hash(&mut hasher);
+    options.sample.map(f64::to_bits).hash(&mut hasher);
+
+    Ok(dir.join(format!("{:016x}.json", hasher.finish())))
+}
+
+/// Reads the analyzed tree of the data files given in the options from the cache, along
+/// with the source map to locate errors with, unless it was never cached or any of the
+/// files it was parsed from have changed since.
+pub(crate) fn read(dir: &Path, options: &Options) -> Option<(ValidatedParseTree, SourceMap)> {
+    let contents = fs::read_to_string(entry_path(dir, options).ok()?).ok()?;
+    let entry: Entry<ParseTree> = serde_json::from_str(&contents).ok()?;
+
+    if entry.format_version != FORMAT_VERSION || entry.hldr_version != env!("CARGO_PKG_VERSION") {
+        return None;
+    }
+
+    let mut sources = SourceMap::default();
+
+    for file in entry.sources {
+        let source = fs::read_to_string(&file.path).ok()?;
+
+        if !file.is_current(source.as_bytes()) {
+            return None;
+        }
+
+        // The positions in the tree were already offset before it was cached
+        sources.add(file.path, source, &mut ParseTree::default());
+    }
+
+    for file in &entry.imported_files {
+        if !file.is_current(&fs::read(&file.path).ok()?) {
+            return None;
+        }
+    }
+
+    Some((ValidatedParseTree::validated(entry.tree), sources))
+}
+
+/// Writes the analyzed tree of the data files given in the options to the cache, along
+/// with the files it was parsed from, replacing any entry for the same data files.
+pub(crate) fn write(
+    dir: &Path,
+    options: &Options,
+    tree: &ValidatedParseTree,
+    sources: &SourceMap,
+    imported_files: &[PathBuf],
+) -> io::Result<()> {
+    let entry = Entry {
+        format_version: FORMAT_VERSION,
+        hldr_version: env!("CARGO_PKG_VERSION").to_owned(),
+        sources: sources
+            .files()
+            .map(|(path, source)| CachedFile::new(path, source.as_bytes()))
+            .collect(),
+        imported_files: imported_files
+            .iter()
+            .map(|path| Ok(CachedFile::new(path, &fs::read(path)?)))
+            .collect::<io::Result<_>>()?,
+        tree: tree.inner(),
+    };
+    let path = entry_path(dir, options)?;
+
+    // Entries are written in full before replacing the previous one, so that other runs
+    // never read an entry that is only partly written
+    let partial = path.with_extension(format!("{}.tmp", process::id()));
+
+    fs::create_dir_all(dir)?;
+    fs::write(&partial, serde_json::to_string(&entry)?)?;
+    fs::rename(&partial, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write};
+    use crate::{analyze, parse_data_files, Options};
+    use std::fs;
+
+    #[test]
+    fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("hldr-cache-{}", std::process::id()));
+        let cache_dir = dir.join("cache");
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("a.hldr"), "table person (\n  kevin (name 'Kevin')\n)\ninclude 'b.hldr'\n").unwrap();
+        fs::write(dir.join("b.hldr"), "table pet (\n  $import 'data/pets.csv'\n  (owner_id @person.kevin)\n)\n").unwrap();
+        fs::write(dir.join("data/pets.csv"), "name\nRex\n").unwrap();
+
+        let options = Options {
+            data_files: vec![dir.join("a.hldr")],
+            ..Options::default()
+        };
+        let (tree, includes) = parse_data_files(&options).unwrap();
+        let tree = analyze(tree, &includes.sources, &options).unwrap();
+
+        assert!(read(&cache_dir, &options).is_none());
+        write(&cache_dir, &options, &tree, &includes.sources, &includes.imported_files).unwrap();
+
+        let (cached, sources) = read(&cache_dir, &options).unwrap();
+        assert_eq!(cached.inner(), tree.inner());
+        assert_eq!(
+            sources.files().collect::<Vec<_>>(),
+            includes.sources.files().collect::<Vec<_>>(),
+        );
+
+        // Entries are separate for different options and are not used once any file changes
+        let tagged = Options {
+            only_tags: vec!["demo".to_owned()],
+            ..options.clone()
+        };
+        assert!(read(&cache_dir, &tagged).is_none());
+
+        fs::write(dir.join("data/pets.csv"), "name\nTom\n").unwrap();
+        assert!(read(&cache_dir, &options).is_none());
+
+        fs::write(dir.join("data/pets.csv"), "name\nRex\n").unwrap();
+        assert!(read(&cache_dir, &options).is_some());
+
+        fs::write(dir.join("b.hldr"), "table pet ()\n").unwrap();
+        assert!(read(&cache_dir, &options).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
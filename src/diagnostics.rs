@@ -0,0 +1,552 @@
+//! Reports errors in data files along with the offending line of the file, eg.
+//!
+//! ```text
+//! error: duplicate record `kevin` in scope `person` on line 4
+//!  --> place.hldr:4:5
+//!   |
+//! 4 |     kevin (name 'Kevin')
+//!   |     ^
+//! ```
+//!
+//! Errors in attributes underline the whole attribute, as far as it goes on that line.
+
+use crate::analyzer::error::AnalyzeError;
+use crate::error::HldrError;
+use crate::loader::error::{LoadError, LoadErrorKind};
+use crate::parser::nodes::{ParseTree, Span, StructuralNode, Table};
+use crate::Position;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The place in a data file that an error refers to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    pub position: Position,
+    /// The text of the line at the position, without its line ending
+    pub line: String,
+    /// Where the underlined part of the line ends, if the error spans a node, which is
+    /// at the end of the line if the node continues past it
+    pub end: Option<Position>,
+}
+
+impl SourceLocation {
+    pub fn new(path: &Path, source: &str, position: Position) -> Self {
+        Self {
+            path: path.to_owned(),
+            position,
+            line: source.lines().nth(position.line - 1).unwrap_or_default().to_owned(),
+            end: None,
+        }
+    }
+
+    /// The location spanning to where the node ends, if known, without any whitespace
+    /// that the node ends with.
+    pub fn spanning(self, end: Option<Position>) -> Self {
+        let Position { line, column } = self.position;
+        let end = end.filter(|end| end.line >= line).map(|end| {
+            let end_column = if end.line == line { end.column } else { usize::MAX };
+            let spanned: String = self.line.chars().take(end_column - 1).skip(column - 1).collect();
+
+            Position {
+                line,
+                column: column + spanned.trim_end().chars().count().max(1),
+            }
+        });
+
+        Self { end, ..self }
+    }
+
+    /// The location just past the end of the source, for errors like unexpected
+    /// ends of file that have no position of their own.
+    pub fn end_of(path: &Path, source: &str) -> Self {
+        let line = source.lines().count().max(1);
+        let column = source.lines().last().map_or(0, |l| l.chars().count()) + 1;
+
+        Self::new(path, source, Position { line, column })
+    }
+}
+
+/// Where each line of a data file starts, so that the lines of errors in a file that is
+/// streamed can be read back from it when they are located rather than holding the whole
+/// file in memory.
+#[derive(Debug)]
+pub(crate) struct LineIndex {
+    path: PathBuf,
+    /// The byte offset of the start of each line, including an empty last line after
+    /// a trailing line ending
+    starts: Vec<u64>,
+    len: u64,
+}
+
+impl LineIndex {
+    /// Reads through the file at the path to find where its lines start.
+    pub(crate) fn read(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut starts = vec![0];
+        let mut len = 0;
+
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+
+            let read = buf.len();
+            let newlines = buf.iter().enumerate().filter(|(_, &b)| b == b'\n');
+            starts.extend(newlines.map(|(i, _)| len + i as u64 + 1));
+
+            len += read as u64;
+            reader.consume(read);
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            starts,
+            len,
+        })
+    }
+
+    /// Returns the location of the position in the file, reading its line from the file.
+    pub(crate) fn location(&self, position: Position) -> SourceLocation {
+        SourceLocation {
+            path: self.path.clone(),
+            position,
+            line: self.line(position.line),
+            end: None,
+        }
+    }
+
+    /// The location just past the end of the file, just as [`SourceLocation::end_of`]
+    /// returns for the whole source.
+    pub(crate) fn end_of(&self) -> SourceLocation {
+        let line = self.starts.iter().filter(|&&start| start < self.len).count().max(1);
+        let column = self.line(line).chars().count() + 1;
+
+        self.location(Position { line, column })
+    }
+
+    /// Reads the line from the file without its line ending, or returns an empty line
+    /// if it cannot be read.
+    fn line(&self, line: usize) -> String {
+        let Some(&start) = line.checked_sub(1).and_then(|i| self.starts.get(i)) else {
+            return String::new();
+        };
+        let mut text = String::new();
+        let read = File::open(&self.path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(start))?;
+            BufReader::new(file).read_line(&mut text)
+        });
+
+        if read.is_err() {
+            return String::new();
+        }
+
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        text.strip_suffix('\r').unwrap_or(text).to_owned()
+    }
+}
+
+/// Renders the error message followed by the location, if any, with the line
+/// of the file and carets underlining the column or the span from it.
+pub fn render(message: &str, location: Option<&SourceLocation>) -> String {
+    format!("error: {}", annotate(message, location))
+}
+
+/// Renders the message followed by the location, if any, just as [`render`] does
+/// but without labelling it as an error, eg. for warnings.
+pub fn annotate(message: &str, location: Option<&SourceLocation>) -> String {
+    let location = match location {
+        Some(location) => location,
+        None => return message.to_owned(),
+    };
+    let Position { line, column } = location.position;
+    let gutter = " ".repeat(line.to_string().len());
+
+    // Anything before the caret other than tabs is replaced with spaces, so that
+    // the caret lines up with the column no matter how the line is indented
+    let indent: String = location
+        .line
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let width = location.end.map_or(1, |end| end.column.saturating_sub(column).max(1));
+
+    format!(
+        "{}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+        message,
+        gutter,
+        location.path.display(),
+        line,
+        column,
+        gutter,
+        line,
+        location.line,
+        gutter,
+        indent,
+        "^".repeat(width),
+    )
+}
+
+/// The data files that are parsed separately but analyzed as a single tree,
+/// which each occupy their own range of lines in the tree, similar to how
+/// rustc maps positions across multiple files, so that the positions in
+/// analyzer errors can be traced back to the files.
+#[derive(Debug, Default)]
+pub(crate) struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    path: PathBuf,
+    source: String,
+    /// The number of lines in all of the files before this one
+    line_offset: usize,
+}
+
+impl SourceMap {
+    /// Adds the file, offsetting the positions in its tree by the lines in all
+    /// of the files added before it.
+    pub(crate) fn add(&mut self, path: PathBuf, source: String, tree: &mut ParseTree) {
+        let line_offset = self
+            .files
+            .last()
+            .map_or(0, |f| f.line_offset + f.source.lines().count().max(1));
+
+        for search_path in &mut tree.search_paths {
+            if let Some(position) = &mut search_path.position {
+                position.line += line_offset;
+            }
+        }
+
+        for variable in &mut tree.variables {
+            if let Some(position) = &mut variable.position {
+                position.line += line_offset;
+            }
+        }
+
+        for hook in &mut tree.hooks {
+            if let Some(position) = &mut hook.position {
+                position.line += line_offset;
+            }
+        }
+
+        for node in &mut tree.nodes {
+            match node {
+                StructuralNode::Schema(schema) => {
+                    if let Some(position) = &mut schema.position {
+                        position.line += line_offset;
+                    }
+
+                    offset_span(&mut schema.span, line_offset);
+
+                    for table in &mut schema.nodes {
+                        offset_table(table, line_offset);
+                    }
+                }
+                StructuralNode::Table(table) => offset_table(table, line_offset),
+            }
+        }
+
+        self.files.push(SourceFile {
+            path,
+            source,
+            line_offset,
+        });
+    }
+
+    /// The path and contents of each file, in the order they were added.
+    pub(crate) fn files(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.files.iter().map(|f| (f.path.as_path(), f.source.as_str()))
+    }
+
+    /// Adds the location to the error if it is an analyzer or loader error with a position.
+    pub(crate) fn locate_error(&self, error: HldrError) -> HldrError {
+        locate_error(error, |position, end| self.locate(position, end))
+    }
+
+    /// Returns the location of the error's position in the file it came from, if known,
+    /// changing the position and where the error ends to be relative to that file.
+    fn locate(&self, position: &mut Option<Position>, mut end: Option<&mut Position>) -> Option<SourceLocation> {
+        let line_offset = (*position)?.line;
+        let location = self.location((*position)?)?;
+        let line_offset = line_offset - location.position.line;
+
+        if let Some(end) = &mut end {
+            end.line -= line_offset;
+        }
+
+        *position = Some(location.position);
+        Some(location.spanning(end.map(|end| *end)))
+    }
+
+    /// Returns the location of the position in the tree in the file it came from.
+    pub(crate) fn location(&self, position: Position) -> Option<SourceLocation> {
+        let file = self.files.iter().rev().find(|f| f.line_offset < position.line)?;
+        let position = Position {
+            line: position.line - file.line_offset,
+            ..position
+        };
+
+        Some(SourceLocation::new(&file.path, &file.source, position))
+    }
+}
+
+/// Adds the location to the error if it is an analyzer or loader error with a position,
+/// found with the function given, which may change the position and where the error ends
+/// to be relative to the file it came from.
+pub(crate) fn locate_error(
+    mut error: HldrError,
+    locate: impl Fn(&mut Option<Position>, Option<&mut Position>) -> Option<SourceLocation>,
+) -> HldrError {
+    let location = if let Some(e) = error.error.downcast_mut::<AnalyzeError>() {
+        locate(&mut e.position, e.end.as_mut())
+    } else if let Some(e) = error.error.downcast_mut::<LoadError>() {
+        // Each of the collected errors is located on its own instead
+        if let LoadErrorKind::FailedInserts(failed) = &mut e.kind {
+            for failed in failed {
+                failed.location = locate(&mut failed.error.position, failed.error.end.as_deref_mut());
+            }
+        }
+
+        locate(&mut e.position, e.end.as_deref_mut())
+    } else {
+        None
+    };
+
+    match location {
+        Some(location) => error.at(Some(location)),
+        None => error,
+    }
+}
+
+fn offset_table(table: &mut Table, line_offset: usize) {
+    if let Some(position) = &mut table.position {
+        position.line += line_offset;
+    }
+
+    offset_span(&mut table.span, line_offset);
+
+    for record in &mut table.nodes {
+        if let Some(position) = &mut record.position {
+            position.line += line_offset;
+        }
+
+        offset_span(&mut record.span, line_offset);
+    }
+
+    for attribute in table.nodes.iter_mut().flat_map(|r| &mut r.nodes).chain(&mut table.defaults) {
+        if let Some(position) = &mut attribute.position {
+            position.line += line_offset;
+        }
+
+        offset_span(&mut attribute.span, line_offset);
+        offset_span(&mut attribute.value_span, line_offset);
+    }
+}
+
+fn offset_span(span: &mut Option<Span>, line_offset: usize) {
+    if let Some(span) = span {
+        span.start.line += line_offset;
+        span.end.line += line_offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::error::AnalyzeErrorKind;
+    use crate::{analyzer, lexer, parser};
+    use pretty_assertions::assert_eq;
+
+    fn parsed(input: &str) -> ParseTree {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        parser::parse(tokens.into_iter()).unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let location = SourceLocation::new(
+            Path::new("place.hldr"),
+            "table t1 (\n\t  rec1 (col1 1)\n)\n",
+            Position { line: 2, column: 4 },
+        );
+
+        assert_eq!(
+            render("something went wrong", Some(&location)),
+            "error: something went wrong
+ --> place.hldr:2:4
+  |
+2 | \t  rec1 (col1 1)
+  | \t  ^",
+        );
+        assert_eq!(render("something went wrong", None), "error: something went wrong");
+    }
+
+    #[test]
+    fn test_render_span() {
+        let source = "table t1 (\n  rec1 (col1 @rec2.col1  )\n  rec3 (col1 '\n')\n)\n";
+        let location = |line, column, end| {
+            SourceLocation::new(Path::new("place.hldr"), source, Position { line, column }).spanning(Some(end))
+        };
+
+        assert_eq!(
+            render("record not found", Some(&location(2, 9, Position { line: 2, column: 26 }))),
+            "error: record not found
+ --> place.hldr:2:9
+  |
+2 |   rec1 (col1 @rec2.col1  )
+  |         ^^^^^^^^^^^^^^^",
+        );
+        assert_eq!(
+            render("something went wrong", Some(&location(3, 9, Position { line: 4, column: 2 }))),
+            "error: something went wrong
+ --> place.hldr:3:9
+  |
+3 |   rec3 (col1 '
+  |         ^^^^^^",
+        );
+    }
+
+    #[test]
+    fn test_end_of() {
+        let location = SourceLocation::end_of(Path::new("place.hldr"), "table t1 (\n  rec1 (");
+
+        assert_eq!(location.position, Position { line: 2, column: 9 });
+        assert_eq!(location.line, "  rec1 (");
+    }
+
+    #[test]
+    fn test_line_index() {
+        let path = std::env::temp_dir().join(format!("hldr-line-index-{}.hldr", std::process::id()));
+        std::fs::write(&path, "table t1 (\r\n  rec1 (col1 'é')\n)\n").unwrap();
+
+        let lines = LineIndex::read(&path).unwrap();
+        let location = lines.location(Position { line: 2, column: 3 });
+        assert_eq!(location.line, "  rec1 (col1 'é')");
+        assert_eq!(lines.location(Position { line: 1, column: 1 }).line, "table t1 (");
+        assert_eq!(lines.end_of(), SourceLocation::end_of(&path, "table t1 (\r\n  rec1 (col1 'é')\n)\n"));
+
+        std::fs::write(&path, "").unwrap();
+        let lines = LineIndex::read(&path).unwrap();
+        assert_eq!(lines.end_of(), SourceLocation::end_of(&path, ""));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_locate() {
+        let mut sources = SourceMap::default();
+        let mut tree = ParseTree::default();
+        let files = [
+            ("a.hldr", "table t1 (\n  rec1 (col1 1\n  )\n)\n"),
+            ("b.hldr", "\ntable t1 (\n  rec2 (col1 2)\n  rec1 (col1 @rec2.col1)\n)\n"),
+        ];
+
+        for (path, source) in files {
+            let mut file_tree = parsed(source);
+            sources.add(PathBuf::from(path), source.to_owned(), &mut file_tree);
+            tree.nodes.extend(file_tree.nodes);
+        }
+
+        let mut error = analyzer::analyze(tree).err().unwrap();
+
+        assert_eq!(
+            error.kind,
+            AnalyzeErrorKind::DuplicateRecord {
+                scope: "t1".to_owned(),
+                record: "rec1".to_owned(),
+            },
+        );
+        assert_eq!(
+            sources.locate(&mut error.position, error.end.as_mut()),
+            Some(SourceLocation {
+                path: PathBuf::from("b.hldr"),
+                position: Position { line: 4, column: 3 },
+                line: "  rec1 (col1 @rec2.col1)".to_owned(),
+                end: None,
+            }),
+        );
+        assert_eq!(error.position, Some(Position { line: 4, column: 3 }));
+    }
+
+    #[test]
+    fn test_locate_span() {
+        let mut sources = SourceMap::default();
+        let mut tree = ParseTree::default();
+        let files = [
+            ("a.hldr", "table t1 (\n  rec1 (col1 1)\n)\n"),
+            ("b.hldr", "\ntable t1 (\n  rec2 (col1 @rec3.col1)\n)\n"),
+        ];
+
+        for (path, source) in files {
+            let mut file_tree = parsed(source);
+            sources.add(PathBuf::from(path), source.to_owned(), &mut file_tree);
+            tree.nodes.extend(file_tree.nodes);
+        }
+
+        let error = analyzer::analyze(tree).err().unwrap();
+        let error = sources.locate_error(error.into());
+        let analyze_error = error.error.downcast_ref::<AnalyzeError>().unwrap();
+
+        assert_eq!(
+            (analyze_error.position, analyze_error.end),
+            (Some(Position { line: 3, column: 9 }), Some(Position { line: 3, column: 24 })),
+        );
+        assert_eq!(
+            error.location,
+            Some(SourceLocation {
+                path: PathBuf::from("b.hldr"),
+                position: Position { line: 3, column: 9 },
+                line: "  rec2 (col1 @rec3.col1)".to_owned(),
+                end: Some(Position { line: 3, column: 24 }),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_locate_load_error() {
+        let mut sources = SourceMap::default();
+        let files = [
+            ("a.hldr", "table t1 (
+  rec1 (col1 1)
+)
+"),
+            ("b.hldr", "schema s1 (
+  table t2 (
+    rec2 (col1 2)
+  )
+)
+"),
+        ];
+        let mut schema_position = None;
+
+        for (path, source) in files {
+            let mut file_tree = parsed(source);
+            sources.add(PathBuf::from(path), source.to_owned(), &mut file_tree);
+
+            if let Some(StructuralNode::Schema(schema)) = file_tree.nodes.first() {
+                schema_position = schema.position;
+            }
+        }
+
+        assert_eq!(schema_position, Some(Position { line: 4, column: 1 }));
+
+        let error = LoadError::no_primary_key("s1.t2").at(Some(Position { line: 6, column: 5 }));
+        let error = sources.locate_error(error.into());
+
+        assert_eq!(
+            error.location,
+            Some(SourceLocation {
+                path: PathBuf::from("b.hldr"),
+                position: Position { line: 3, column: 5 },
+                line: "    rec2 (col1 2)".to_owned(),
+                end: None,
+            }),
+        );
+    }
+}
@@ -0,0 +1,716 @@
+//! Generates a data file from the rows of tables in an existing database, where rows
+//! referenced through foreign keys are declared as named records and referenced by
+//! name rather than by their raw keys.
+
+use crate::error::HldrError;
+use crate::identifier::{self, is_unquoted, quote};
+use crate::lexer;
+use crate::lexer::tokens::{TokenKind, FUNCTIONS};
+use postgres::{Client, IsolationLevel, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which tables are dumped from the database, and which of their rows.
+#[derive(Clone, Debug, Default)]
+pub struct DumpOptions {
+    pub database_conn: String,
+    /// Schemas whose tables are all dumped, which is only `public` if neither
+    /// schemas nor tables are given
+    pub schemas: Vec<String>,
+    /// Tables to dump, which can be qualified with their schema
+    pub tables: Vec<String>,
+    /// The most rows dumped from each table
+    pub limit: Option<u64>,
+    /// Conditions that the dumped rows of tables must meet
+    pub filters: Vec<Filter>,
+}
+
+/// A condition that the dumped rows of a table must meet, given as `table:condition`,
+/// eg. `person:age > 30`, where the table can be qualified with its schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    pub table: String,
+    pub condition: String,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((table, condition)) if !table.trim().is_empty() && !condition.trim().is_empty() => Ok(Self {
+                table: table.trim().to_owned(),
+                condition: condition.trim().to_owned(),
+            }),
+            _ => Err(format!("expected `table:condition` for filter, found `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DumpErrorKind {
+    /// A table given to dump does not exist
+    UnknownTable(String),
+    /// A filter is for a table that is not being dumped
+    UnknownFilterTable(String),
+}
+
+#[derive(Debug)]
+pub struct DumpError {
+    pub kind: DumpErrorKind,
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DumpErrorKind::*;
+
+        match &self.kind {
+            UnknownTable(table) => write!(f, "table `{}` does not exist", table),
+            UnknownFilterTable(table) => write!(f, "filter is for table `{}`, which is not being dumped", table),
+        }
+    }
+}
+
+impl Error for DumpError {}
+
+/// A table being dumped, along with its rows as text
+#[derive(Debug)]
+struct DumpedTable {
+    oid: u32,
+    schema: String,
+    name: String,
+    columns: Vec<DumpedColumn>,
+    rows: Vec<Vec<Option<String>>>,
+}
+
+#[derive(Debug)]
+struct DumpedColumn {
+    name: String,
+    type_name: String,
+    /// The category of the column's type, eg. `N` for numeric types
+    category: String,
+    /// Whether the database generates the column's values, eg. for serial primary
+    /// keys and generated columns, so it is left out of records
+    generated: bool,
+    /// Whether the column has a default, so null values cannot be left out of records
+    has_default: bool,
+    primary_key: bool,
+    /// The table and column that the column references with a foreign key
+    foreign_key: Option<(u32, String)>,
+}
+
+/// A dumped row, by the index of its table and its own index
+type RowIndex = (usize, usize);
+
+/// A dumped value, by the index of its table, row, and column
+type ValueIndex = (usize, usize, usize);
+
+/// The dumped row referenced by each foreign key value that references one
+type Links = HashMap<ValueIndex, RowIndex>;
+
+/// Reads the tables selected in the options within a read-only transaction, so that
+/// every table is read from the same snapshot, and renders them as a data file.
+pub(crate) fn generate(client: &mut Client, options: &DumpOptions) -> Result<String, HldrError> {
+    let mut transaction = client
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()?;
+    let mut tables = select_tables(&mut transaction, options)?;
+
+    for filter in &options.filters {
+        if !tables.iter().any(|t| applies_to(filter, t)) {
+            return Err(DumpError { kind: DumpErrorKind::UnknownFilterTable(filter.table.clone()) }.into());
+        }
+    }
+
+    for table in &mut tables {
+        table.columns = select_columns(&mut transaction, table.oid)?;
+        table.rows = select_rows(&mut transaction, table, options)?;
+        log::info!("Dumped {} row{} from {}", table.rows.len(), if table.rows.len() == 1 { "" } else { "s" }, qualified_name(table));
+    }
+
+    Ok(render(&tables, &link(&tables)))
+}
+
+fn select_tables(transaction: &mut Transaction, options: &DumpOptions) -> Result<Vec<DumpedTable>, HldrError> {
+    let mut tables = Vec::new();
+
+    for name in &options.tables {
+        let row = transaction.query_opt(
+            "SELECT c.oid, n.nspname::text, c.relname::text
+            FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+            WHERE c.oid = to_regclass($1)",
+            &[name],
+        )?;
+
+        match row {
+            Some(row) => tables.push(DumpedTable::new(row.get(0), row.get(1), row.get(2))),
+            None => return Err(DumpError { kind: DumpErrorKind::UnknownTable(name.clone()) }.into()),
+        }
+    }
+
+    let schemas = match (options.schemas.is_empty(), options.tables.is_empty()) {
+        (true, true) => vec!["public".to_owned()],
+        _ => options.schemas.clone(),
+    };
+    let rows = transaction.query(
+        "SELECT c.oid, n.nspname::text, c.relname::text
+        FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+        WHERE n.nspname = ANY($1) AND c.relkind IN ('r', 'p') AND NOT c.relispartition
+        ORDER BY n.nspname, c.relname",
+        &[&schemas],
+    )?;
+
+    for row in rows {
+        let oid: u32 = row.get(0);
+
+        if !tables.iter().any(|t| t.oid == oid) {
+            tables.push(DumpedTable::new(oid, row.get(1), row.get(2)));
+        }
+    }
+
+    Ok(tables)
+}
+
+/// Looks up the table's columns, where a primary key of a single column is treated as
+/// generated if it has a default or is an identity, since its values are generated
+/// again when the data file is loaded, and only foreign keys of a single column are
+/// looked up, since only those become references.
+fn select_columns(transaction: &mut Transaction, oid: u32) -> Result<Vec<DumpedColumn>, HldrError> {
+    let rows = transaction.query(
+        "SELECT DISTINCT ON (a.attnum)
+            a.attname::text,
+            format_type(a.atttypid, a.atttypmod),
+            t.typcategory::text,
+            a.attgenerated <> '' OR a.attidentity = 'a' OR COALESCE(
+                pk.conkey = ARRAY[a.attnum] AND (a.atthasdef OR a.attidentity <> ''),
+                false
+            ),
+            a.atthasdef OR a.attidentity <> '',
+            COALESCE(a.attnum = ANY(pk.conkey), false),
+            fk.confrelid,
+            fa.attname::text
+        FROM pg_attribute a
+        JOIN pg_type t ON t.oid = a.atttypid
+        LEFT JOIN pg_constraint pk ON pk.conrelid = a.attrelid AND pk.contype = 'p'
+        LEFT JOIN pg_constraint fk ON fk.conrelid = a.attrelid AND fk.contype = 'f' AND fk.conkey = ARRAY[a.attnum]
+        LEFT JOIN pg_attribute fa ON fa.attrelid = fk.confrelid AND fa.attnum = fk.confkey[1]
+        WHERE a.attrelid = $1 AND a.attnum > 0 AND NOT a.attisdropped
+        ORDER BY a.attnum",
+        &[&oid],
+    )?;
+
+    Ok(rows
+        .iter()
+        .map(|row| DumpedColumn {
+            name: row.get(0),
+            type_name: row.get(1),
+            category: row.get(2),
+            generated: row.get(3),
+            has_default: row.get(4),
+            primary_key: row.get(5),
+            foreign_key: row.get::<_, Option<u32>>(6).zip(row.get(7)),
+        })
+        .collect())
+}
+
+/// Selects the table's rows as text, ordered by primary key so that dumps of the
+/// same rows are the same.
+fn select_rows(transaction: &mut Transaction, table: &DumpedTable, options: &DumpOptions) -> Result<Vec<Vec<Option<String>>>, HldrError> {
+    let columns: Vec<String> = table.columns.iter().map(|c| format!("{}::text", quote(&c.name))).collect();
+    let mut query = format!("SELECT {} FROM {}", columns.join(", "), qualified_name(table));
+    let conditions: Vec<String> = options
+        .filters
+        .iter()
+        .filter(|f| applies_to(f, table))
+        .map(|f| format!("({})", f.condition))
+        .collect();
+    let primary_key: Vec<String> = table.columns.iter().filter(|c| c.primary_key).map(|c| quote(&c.name)).collect();
+
+    if !conditions.is_empty() {
+        query.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    if !primary_key.is_empty() {
+        query.push_str(&format!(" ORDER BY {}", primary_key.join(", ")));
+    }
+    if let Some(limit) = options.limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+
+    log::debug!("{}", query);
+
+    let rows = transaction.query(query.as_str(), &[])?;
+
+    Ok(rows
+        .iter()
+        .map(|row| (0..table.columns.len()).map(|i| row.get(i)).collect())
+        .collect())
+}
+
+impl DumpedTable {
+    fn new(oid: u32, schema: String, name: String) -> Self {
+        Self {
+            oid,
+            schema,
+            name,
+            columns: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Tables in `public` are declared at the top level of the data file, and
+    /// others within their schema
+    fn is_public(&self) -> bool {
+        self.schema == "public"
+    }
+}
+
+fn applies_to(filter: &Filter, table: &DumpedTable) -> bool {
+    filter.table == table.name || filter.table == format!("{}.{}", table.schema, table.name)
+}
+
+fn qualified_name(table: &DumpedTable) -> String {
+    format!("{}.{}", quote(&table.schema), quote(&table.name))
+}
+
+/// Links the foreign key values that reference dumped rows to those rows, other
+/// than any that would make rows reference each other in a cycle.
+fn link(tables: &[DumpedTable]) -> Links {
+    let referenced: HashSet<(u32, &str)> = tables
+        .iter()
+        .flat_map(|t| &t.columns)
+        .filter_map(|c| c.foreign_key.as_ref())
+        .map(|(oid, column)| (*oid, column.as_str()))
+        .collect();
+
+    // The rows of each table by the values of their referenced columns
+    let mut keys: HashMap<(u32, &str, &str), RowIndex> = HashMap::new();
+
+    for (t, table) in tables.iter().enumerate() {
+        for (c, column) in table.columns.iter().enumerate() {
+            if !referenced.contains(&(table.oid, column.name.as_str())) {
+                continue;
+            }
+            for (r, row) in table.rows.iter().enumerate() {
+                if let Some(value) = &row[c] {
+                    keys.entry((table.oid, column.name.as_str(), value.as_str())).or_insert((t, r));
+                }
+            }
+        }
+    }
+
+    let mut links = Links::new();
+
+    for (t, table) in tables.iter().enumerate() {
+        for (c, column) in table.columns.iter().enumerate() {
+            let (oid, referenced_column) = match &column.foreign_key {
+                Some(foreign_key) => foreign_key,
+                None => continue,
+            };
+            for (r, row) in table.rows.iter().enumerate() {
+                let target = row[c].as_ref().and_then(|v| keys.get(&(*oid, referenced_column.as_str(), v.as_str())));
+
+                if let Some(target) = target {
+                    links.insert((t, r, c), *target);
+                }
+            }
+        }
+    }
+
+    break_cycles(&mut links);
+    links
+}
+
+/// Removes the links that would make rows reference each other in a cycle, which
+/// cannot be loaded, so that those values are dumped as they are.
+fn break_cycles(links: &mut Links) {
+    let mut keys: Vec<ValueIndex> = links.keys().copied().collect();
+    keys.sort_unstable();
+
+    let mut edges: HashMap<RowIndex, Vec<ValueIndex>> = HashMap::new();
+    for key in &keys {
+        edges.entry((key.0, key.1)).or_default().push(*key);
+    }
+
+    let mut visited = HashSet::new();
+    let mut in_path = HashSet::new();
+
+    for start in keys.iter().map(|k| (k.0, k.1)) {
+        if !visited.insert(start) {
+            continue;
+        }
+        in_path.insert(start);
+
+        // Each row in the path, with the index of the next of its links to follow
+        let mut path = vec![(start, 0)];
+
+        while let Some((row, next)) = path.last_mut() {
+            let row = *row;
+            let key = edges.get(&row).and_then(|e| e.get(*next)).copied();
+            *next += 1;
+
+            match key {
+                Some(key) => {
+                    let target = links[&key];
+
+                    if in_path.contains(&target) {
+                        links.remove(&key);
+                    } else if visited.insert(target) {
+                        in_path.insert(target);
+                        path.push((target, 0));
+                    }
+                }
+                None => {
+                    in_path.remove(&row);
+                    path.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Names the rows that are referenced after their table and position, eg. `person_1`
+/// for the first row of `person`, including the schema of tables outside of `public`.
+fn record_names(tables: &[DumpedTable], links: &Links) -> HashMap<RowIndex, String> {
+    let mut referenced: Vec<RowIndex> = links.values().copied().collect();
+    referenced.sort_unstable();
+    referenced.dedup();
+
+    let mut names = HashMap::new();
+    let mut used = HashSet::new();
+
+    for (t, r) in referenced {
+        let table = &tables[t];
+        let prefix = match table.is_public() {
+            true => table.name.clone(),
+            false => format!("{}_{}", table.schema, table.name),
+        };
+        let prefix: String = prefix.chars().map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' }).collect();
+        let mut name = format!("{}_{}", prefix, r + 1);
+
+        if !is_unquoted(&name) {
+            name = format!("r_{}", name);
+        }
+        while !used.insert(name.clone()) {
+            name.push('_');
+        }
+        names.insert((t, r), name);
+    }
+
+    names
+}
+
+fn render(tables: &[DumpedTable], links: &Links) -> String {
+    let names = record_names(tables, links);
+    let mut output = String::new();
+    let mut schemas: Vec<&str> = Vec::new();
+
+    for table in tables.iter().filter(|t| !t.rows.is_empty()) {
+        if !table.is_public() && !schemas.contains(&table.schema.as_str()) {
+            schemas.push(&table.schema);
+        }
+    }
+
+    let render_tables = |output: &mut String, schema: Option<&str>, indent: &str| {
+        for (t, table) in tables.iter().enumerate() {
+            if table.rows.is_empty() || (!table.is_public() && Some(table.schema.as_str()) != schema) || (table.is_public() && schema.is_some()) {
+                continue;
+            }
+
+            output.push_str(&format!("{}table {} (\n", indent, identifier::render(&table.name)));
+
+            for r in 0..table.rows.len() {
+                let attributes: Vec<String> = (0..table.columns.len())
+                    .filter_map(|c| render_attribute(tables, links, &names, (t, r, c)))
+                    .collect();
+                let name = match names.get(&(t, r)) {
+                    Some(name) => format!("{} ", name),
+                    None => String::new(),
+                };
+
+                match attributes.is_empty() {
+                    true => output.push_str(&format!("{}  {}()\n", indent, name)),
+                    false => output.push_str(&format!("{}  {}( {} )\n", indent, name, attributes.join(", "))),
+                }
+            }
+
+            output.push_str(&format!("{})\n", indent));
+        }
+    };
+
+    render_tables(&mut output, None, "");
+
+    for schema in schemas {
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output.push_str(&format!("schema {} (\n", identifier::render(schema)));
+        render_tables(&mut output, Some(schema), "  ");
+        output.push_str(")\n");
+    }
+
+    output
+}
+
+/// Renders the column's value in the row, if it is not left out.
+fn render_attribute(tables: &[DumpedTable], links: &Links, names: &HashMap<RowIndex, String>, (t, r, c): ValueIndex) -> Option<String> {
+    let table = &tables[t];
+    let column = &table.columns[c];
+    let name = identifier::render(&column.name);
+
+    if column.generated {
+        return None;
+    }
+
+    if let Some(&(target_t, target_r)) = links.get(&(t, r, c)) {
+        let target = &tables[target_t];
+        let record = &names[&(target_t, target_r)];
+        let referenced_column = column.foreign_key.as_ref().map(|(_, c)| c.as_str()).unwrap_or_default();
+        let schema = match target.is_public() {
+            true => String::new(),
+            false => format!("{}.", identifier::render(&target.schema)),
+        };
+
+        // Columns named after the table and column they reference can use shorthand
+        return Some(match column.name == format!("{}_{}", target.name, referenced_column) && is_unquoted(&column.name) {
+            true => format!("{}@ {}{}", column.name, schema, record),
+            false => format!("{} @{}{}.{}.{}", name, schema, identifier::render(&target.name), record, identifier::render(referenced_column)),
+        });
+    }
+
+    match &table.rows[r][c] {
+        Some(value) => Some(format!("{} {}", name, literal(column, value))),
+        // Leaving out nulls would insert the column's default instead
+        None if column.has_default => Some(format!("{} null", name)),
+        None => None,
+    }
+}
+
+/// Renders the value as a literal of the column's type, which is a string unless
+/// there is a more specific literal for the type.
+fn literal(column: &DumpedColumn, value: &str) -> String {
+    match (column.category.as_str(), column.type_name.as_str()) {
+        ("B", _) => matches!(value, "true" | "t").to_string(),
+        ("N", _) if is_number(value) => value.to_owned(),
+        (_, "bytea") if value.starts_with("\\x") => format!("x'{}'", &value[2..]),
+        (_, "json" | "jsonb") if value.starts_with(['{', '[']) => format!("json{}", value),
+        _ => text(value),
+    }
+}
+
+/// Renders the value as a string, concatenating it from several strings if needed
+/// so that nothing in it is treated as an embedded value or as starting a text block,
+/// and escaping `${` so that it is not replaced with an environment variable.
+fn text(value: &str) -> String {
+    let mut parts = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in value.char_indices() {
+        let rest = &value[i + c.len_utf8()..];
+        let splits = match c {
+            '{' => embeds_value(rest),
+            '\'' => i == 0 && rest.starts_with(['\n', '\r']),
+            _ => false,
+        };
+
+        if splits {
+            parts.push(&value[start..i + c.len_utf8()]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+
+    parts
+        .iter()
+        .map(|part| format!("'{}'", part.replace('\'', "''").replace("${", "$${")))
+        .collect::<Vec<_>>()
+        .join(" || ")
+}
+
+/// Whether text after an opening brace would embed a reference, variable, or function call.
+fn embeds_value(rest: &str) -> bool {
+    if rest.starts_with(['@', '$']) {
+        return true;
+    }
+
+    let name: String = rest.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+    rest[name.len()..].starts_with('(') && FUNCTIONS.contains(&name.as_str())
+}
+
+/// Whether the text lexes as exactly the same number.
+fn is_number(value: &str) -> bool {
+    match lexer::tokenize(value.chars()).as_deref() {
+        Ok([token]) => matches!(&token.kind, TokenKind::Number(n) if n == value),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn column(name: &str, type_name: &str, category: &str) -> DumpedColumn {
+        DumpedColumn {
+            name: name.to_owned(),
+            type_name: type_name.to_owned(),
+            category: category.to_owned(),
+            generated: false,
+            has_default: false,
+            primary_key: false,
+            foreign_key: None,
+        }
+    }
+
+    fn table(oid: u32, schema: &str, name: &str, columns: Vec<DumpedColumn>, rows: Vec<Vec<Option<&str>>>) -> DumpedTable {
+        DumpedTable {
+            oid,
+            schema: schema.to_owned(),
+            name: name.to_owned(),
+            columns,
+            rows: rows
+                .into_iter()
+                .map(|row| row.into_iter().map(|v| v.map(str::to_owned)).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_filter_from_str() {
+        assert_eq!(
+            "s1.person: age > 30 AND name::text <> ''".parse(),
+            Ok(Filter {
+                table: "s1.person".to_owned(),
+                condition: "age > 30 AND name::text <> ''".to_owned(),
+            }),
+        );
+        assert!("person".parse::<Filter>().is_err());
+        assert!("person: ".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn test_literals() {
+        for (type_name, category, value, expected) in [
+            ("boolean", "B", "true", "true"),
+            ("boolean", "B", "false", "false"),
+            ("integer", "N", "-39", "-39"),
+            ("double precision", "N", "1.5e-07", "1.5e-07"),
+            ("double precision", "N", "NaN", "'NaN'"),
+            ("bytea", "U", "\\x00ff", "x'00ff'"),
+            ("jsonb", "U", r#"{"a": [1, 2]}"#, r#"json{"a": [1, 2]}"#),
+            ("jsonb", "U", r#""text""#, r#"'"text"'"#),
+            ("text", "S", "it's", "'it''s'"),
+            ("text", "S", "{1, 2} {uuid} {@x} {now()}", "'{1, 2} {uuid} {' || '@x} {' || 'now()}'"),
+            ("text", "S", "{$x} ${HOME} $${HOME}", "'{' || '$x} $${HOME} $$${HOME}'"),
+            ("text", "S", "'\nquoted", "'''' || '\nquoted'"),
+            ("integer[]", "A", "{1,2}", "'{1,2}'"),
+        ] {
+            assert_eq!(literal(&column("c", type_name, category), value), expected, "{}", value);
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_text_round_trip() {
+        use crate::loader::{backend, sqlite::Sqlite};
+        use crate::{analyzer, parser, Options};
+
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        connection.execute_batch("CREATE TABLE note (id INTEGER PRIMARY KEY, v TEXT)").unwrap();
+
+        let values = [
+            "{1, 2} {uuid} {@x} {$x} {now()}",
+            "${HLDR_DUMP_UNSET} $${HLDR_DUMP_UNSET} $$${n}",
+            "'\nquoted",
+        ];
+        let input = format!(
+            "table note (\n{}\n)",
+            values.iter().map(|v| format!("  ( v {} )", text(v))).collect::<Vec<_>>().join("\n"),
+        );
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let tree = analyzer::analyze(parser::parse(tokens.into_iter()).unwrap()).unwrap();
+        backend::load(&mut Sqlite::new(&connection), tree, &Options::default()).unwrap();
+
+        let mut statement = connection.prepare("SELECT v FROM note ORDER BY id").unwrap();
+        let loaded: Vec<String> = statement.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(loaded, values);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut id = column("id", "integer", "N");
+        id.generated = true;
+        id.primary_key = true;
+
+        let mut nickname = column("nickname", "text", "S");
+        nickname.has_default = true;
+
+        let mut person_id = column("person_id", "integer", "N");
+        person_id.foreign_key = Some((1, "id".to_owned()));
+
+        let mut owner = column("owner", "integer", "N");
+        owner.foreign_key = Some((1, "id".to_owned()));
+
+        let mut favorite_pet_id = column("favorite_pet_id", "integer", "N");
+        favorite_pet_id.foreign_key = Some((2, "id".to_owned()));
+
+        let mut manager_id = column("manager_id", "integer", "N");
+        manager_id.foreign_key = Some((3, "id".to_owned()));
+
+        let tables = vec![
+            table(
+                1,
+                "public",
+                "person",
+                vec![id, column("name", "text", "S"), nickname, favorite_pet_id],
+                vec![
+                    vec![Some("1"), Some("Kevin"), None, Some("10")],
+                    vec![Some("2"), Some("Stacey"), None, None],
+                ],
+            ),
+            table(
+                2,
+                "public",
+                "pet",
+                vec![column("id", "integer", "N"), person_id, owner],
+                vec![vec![Some("10"), Some("2"), Some("1")], vec![Some("11"), Some("99"), None]],
+            ),
+            table(
+                3,
+                "hr",
+                "employee",
+                vec![column("id", "integer", "N"), column("active", "boolean", "B"), manager_id],
+                vec![vec![Some("1"), Some("t"), Some("1")], vec![Some("2"), Some("false"), Some("1")]],
+            ),
+            table(4, "public", "empty", vec![column("id", "integer", "N")], vec![]),
+        ];
+
+        assert_eq!(
+            render(&tables, &link(&tables)),
+            [
+                "table person (",
+                "  ( name 'Kevin', nickname null, favorite_pet_id @pet.pet_1.id )",
+                "  person_2 ( name 'Stacey', nickname null )",
+                ")",
+                "table pet (",
+                // The reference back to `person_1` would be a cycle
+                "  pet_1 ( id 10, person_id@ person_2, owner 1 )",
+                "  ( id 11, person_id 99 )",
+                ")",
+                "",
+                "schema hr (",
+                "  table employee (",
+                // As would the reference to itself
+                "    hr_employee_1 ( id 1, active true, manager_id 1 )",
+                "    ( id 2, active false, manager_id @hr.employee.hr_employee_1.id )",
+                "  )",
+                ")",
+                "",
+            ]
+            .join("\n"),
+        );
+    }
+}
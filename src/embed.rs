@@ -0,0 +1,57 @@
+//! Loads data files as a step of migration frameworks, eg. refinery or sqlx, so that
+//! seeds can live next to the migrations, embedded with `include_str!`, and be loaded
+//! in the same pipeline.
+
+use crate::error::HldrError;
+use crate::{place_in_transaction, Options};
+use postgres::{Client, Transaction};
+
+/// A connection that seeds can be loaded with.
+pub trait SeedConnection {
+    /// Loads the data file with the default options.
+    fn load_str(&mut self, source: &str) -> Result<(), HldrError>;
+}
+
+/// Loads the data file within a transaction of its own, which is committed once loaded.
+impl SeedConnection for Client {
+    fn load_str(&mut self, source: &str) -> Result<(), HldrError> {
+        let mut transaction = self.transaction()?;
+
+        place_in_transaction(&mut transaction, source, &Options::default())?;
+        Ok(transaction.commit()?)
+    }
+}
+
+/// Loads the data file within the transaction, eg. that of a migration, leaving the
+/// caller to commit it.
+impl SeedConnection for Transaction<'_> {
+    fn load_str(&mut self, source: &str) -> Result<(), HldrError> {
+        place_in_transaction(self, source, &Options::default())?;
+        Ok(())
+    }
+}
+
+/// Loads the contents of a data file, which cannot include or import other files,
+/// with the default options.
+pub fn load_str(connection: &mut impl SeedConnection, source: &str) -> Result<(), HldrError> {
+    connection.load_str(source)
+}
+
+/// Loads data files through `sqlx`, with the `sqlx` feature.
+#[cfg(feature = "sqlx")]
+pub mod sqlx {
+    use crate::error::HldrError;
+    use crate::{analyzer, loader, Options};
+
+    /// Loads the contents of a data file with the default options, just as
+    /// [`load_str`](super::load_str) does, within a transaction of its own or a
+    /// savepoint of the one the connection is in, eg. that of a migration.
+    ///
+    /// The records are loaded by executing the statements that `--output` would write,
+    /// so the rows of referenced records are kept in temporary tables while loading.
+    pub async fn load_str(connection: &mut ::sqlx::PgConnection, source: &str) -> Result<(), HldrError> {
+        let parse_tree = analyzer::analyze(crate::parse(source)?)?;
+
+        Ok(loader::sqlx::load(connection, parse_tree, &Options::default()).await?)
+    }
+}
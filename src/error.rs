@@ -3,11 +3,25 @@ use std::error::Error;
 use std::fmt;
 use std::io;
 
-use crate::{analyzer, lexer, loader, parser};
+use crate::diagnostics::{self, SourceLocation};
+use crate::{analyzer, dump, fixtures, import, include, lexer, loader, parser};
+
+pub use crate::analyzer::error::{AnalyzeError, AnalyzeErrorKind};
+pub use crate::dump::{DumpError, DumpErrorKind};
+pub use crate::fixtures::{FixtureError, FixtureErrorKind};
+pub use crate::import::{ImportError, ImportErrorKind};
+pub use crate::include::{IncludeError, IncludeErrorKind};
+pub use crate::lexer::error::{LexError, LexErrorKind};
+pub use crate::loader::error::{ClientError, ClientErrorKind, FailedInsert, LoadError, LoadErrorKind};
+pub use crate::parser::error::{ParseError, ParseErrorKind};
 
 #[derive(Debug)]
 pub enum HldrErrorKind {
     IoError,
+    DumpError,
+    FixtureError,
+    ImportError,
+    IncludeError,
     LexError,
     ParseError,
     ValidateError,
@@ -20,6 +34,26 @@ pub enum HldrErrorKind {
 pub struct HldrError {
     pub kind: HldrErrorKind,
     pub error: Box<dyn Error>,
+    /// Where the error occurred in a data file, if it came from one
+    pub location: Option<SourceLocation>,
+}
+
+impl HldrError {
+    pub(crate) fn at(mut self, location: Option<SourceLocation>) -> Self {
+        self.location = location;
+        self
+    }
+
+    /// The error of the stage that failed, eg. a [`ParseError`] if the kind is
+    /// [`HldrErrorKind::ParseError`], if it is of the given type.
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        self.error.downcast_ref()
+    }
+
+    /// Renders the error with the offending line of the data file, if known.
+    pub fn render(&self) -> String {
+        diagnostics::render(&self.error.to_string(), self.location.as_ref())
+    }
 }
 
 impl From<io::Error> for HldrError {
@@ -27,6 +61,7 @@ impl From<io::Error> for HldrError {
         HldrError {
             kind: HldrErrorKind::IoError,
             error: Box::new(error),
+            location: None,
         }
     }
 }
@@ -36,6 +71,47 @@ impl From<postgres::error::Error> for HldrError {
         HldrError {
             kind: HldrErrorKind::GeneralDatabaseError,
             error: Box::new(error),
+            location: None,
+        }
+    }
+}
+
+impl From<dump::DumpError> for HldrError {
+    fn from(error: dump::DumpError) -> Self {
+        HldrError {
+            kind: HldrErrorKind::DumpError,
+            error: Box::new(error),
+            location: None,
+        }
+    }
+}
+
+impl From<fixtures::FixtureError> for HldrError {
+    fn from(error: fixtures::FixtureError) -> Self {
+        HldrError {
+            kind: HldrErrorKind::FixtureError,
+            error: Box::new(error),
+            location: None,
+        }
+    }
+}
+
+impl From<import::ImportError> for HldrError {
+    fn from(error: import::ImportError) -> Self {
+        HldrError {
+            kind: HldrErrorKind::ImportError,
+            error: Box::new(error),
+            location: None,
+        }
+    }
+}
+
+impl From<include::IncludeError> for HldrError {
+    fn from(error: include::IncludeError) -> Self {
+        HldrError {
+            kind: HldrErrorKind::IncludeError,
+            error: Box::new(error),
+            location: None,
         }
     }
 }
@@ -45,6 +121,7 @@ impl From<lexer::error::LexError> for HldrError {
         HldrError {
             kind: HldrErrorKind::LexError,
             error: Box::new(error),
+            location: None,
         }
     }
 }
@@ -54,6 +131,7 @@ impl From<parser::error::ParseError> for HldrError {
         HldrError {
             kind: HldrErrorKind::ParseError,
             error: Box::new(error),
+            location: None,
         }
     }
 }
@@ -63,6 +141,7 @@ impl From<analyzer::error::AnalyzeError> for HldrError {
         HldrError {
             kind: HldrErrorKind::ValidateError,
             error: Box::new(error),
+            location: None,
         }
     }
 }
@@ -72,6 +151,7 @@ impl From<loader::error::ClientError> for HldrError {
         HldrError {
             kind: HldrErrorKind::ClientError,
             error: Box::new(error),
+            location: None,
         }
     }
 }
@@ -81,6 +161,7 @@ impl From<loader::error::LoadError> for HldrError {
         HldrError {
             kind: HldrErrorKind::LoadError,
             error: Box::new(error),
+            location: None,
         }
     }
 }
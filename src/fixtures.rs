@@ -0,0 +1,335 @@
+//! Converts seed data declared in JSON or YAML into a parse tree, so that fixtures that
+//! already exist in those formats can be loaded along with data files without being
+//! rewritten.
+//!
+//! A fixture is a list of tables, each with a list of records whose keys are columns,
+//! along with the record's name under `$name` if it is named:
+//!
+//! ```yaml
+//! tables:
+//!   - table: person
+//!     records:
+//!       - $name: kevin
+//!         name: Kevin
+//!   - schema: hr
+//!     table: review
+//!     records:
+//!       - person_id: { $ref: person.kevin.id }
+//!         notes: { strengths: [ "patience" ] }
+//! ```
+
+use crate::diagnostics::SourceLocation;
+use crate::error::HldrError;
+use crate::parser::builder;
+use crate::parser::StreamedTable;
+use crate::parser::nodes::{Attribute, ParseTree, Record, Schema, StructuralNode, Table, Value};
+use crate::Position;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value as FixtureValue};
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// The format of a fixture, given by the extension of its file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Yaml,
+}
+
+impl Format {
+    /// The format of the file at the path, unless it is a data file.
+    pub fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FixtureErrorKind {
+    /// The fixture is not valid JSON or YAML or does not have the shape of a fixture
+    Invalid(String),
+    /// A value of a column cannot be converted, eg. a reference with too many parts
+    InvalidValue { table: String, column: String, reason: String },
+}
+
+#[derive(Debug)]
+pub struct FixtureError {
+    pub kind: FixtureErrorKind,
+}
+
+impl FixtureError {
+    fn invalid(message: String) -> Self {
+        Self {
+            kind: FixtureErrorKind::Invalid(message),
+        }
+    }
+
+    fn invalid_value(table: &str, column: &str, reason: String) -> Self {
+        Self {
+            kind: FixtureErrorKind::InvalidValue {
+                table: table.to_owned(),
+                column: column.to_owned(),
+                reason,
+            },
+        }
+    }
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            FixtureErrorKind::Invalid(message) => write!(f, "invalid fixture: {}", message),
+            FixtureErrorKind::InvalidValue { table, column, reason } => {
+                write!(f, "invalid value for column `{}` of table `{}`: {}", column, table, reason)
+            }
+        }
+    }
+}
+
+impl Error for FixtureError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fixture {
+    #[serde(default)]
+    tables: Vec<FixtureTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FixtureTable {
+    schema: Option<String>,
+    table: String,
+    #[serde(default)]
+    records: Vec<Mapping>,
+}
+
+/// Parses the fixture at the path into a tree, in which each table of a schema is
+/// declared in a schema of its own.
+pub(crate) fn parse(path: &Path, input: &str, format: Format) -> Result<ParseTree, HldrError> {
+    // Mappings are deserialized as YAML's, even from JSON, since they keep their order
+    let fixture: Fixture = match format {
+        Format::Json => serde_json::from_str(input).map_err(|e| {
+            let position = Position {
+                line: e.line(),
+                column: e.column(),
+            };
+            located(FixtureError::invalid(e.to_string()), path, input, Some(position))
+        })?,
+        Format::Yaml => serde_yaml::from_str(input).map_err(|e| {
+            let position = e.location().map(|l| Position {
+                line: l.line(),
+                column: l.column(),
+            });
+            located(FixtureError::invalid(e.to_string()), path, input, position)
+        })?,
+    };
+
+    let mut tree = ParseTree::default();
+
+    for fixture_table in fixture.tables {
+        let mut table = Table::new(fixture_table.table, None, None);
+
+        for fixture_record in fixture_table.records {
+            table.nodes.push(record(&table.identity.name, fixture_record)?);
+        }
+
+        tree.nodes.push(match fixture_table.schema {
+            Some(name) => {
+                let mut schema = Schema::new(name, None);
+                schema.nodes.push(table);
+                StructuralNode::Schema(Box::new(schema))
+            }
+            None => StructuralNode::Table(Box::new(table)),
+        });
+    }
+
+    Ok(tree)
+}
+
+/// The tables of the tree parsed from a fixture, as they would be streamed from a data
+/// file, where every table is declared whole.
+pub(crate) fn stream(tree: ParseTree) -> impl Iterator<Item = StreamedTable> {
+    tree.nodes.into_iter().flat_map(|node| match node {
+        StructuralNode::Schema(schema) => {
+            let identity = schema.identity;
+            schema
+                .nodes
+                .into_iter()
+                .map(|table| StreamedTable {
+                    schema: Some(identity.clone()),
+                    table,
+                    continued: false,
+                })
+                .collect()
+        }
+        StructuralNode::Table(table) => vec![StreamedTable {
+            schema: None,
+            table: *table,
+            continued: false,
+        }],
+    })
+}
+
+fn located(error: FixtureError, path: &Path, input: &str, position: Option<Position>) -> HldrError {
+    // Errors in the shape of the fixture rather than its syntax have no line
+    let location = position
+        .filter(|p| p.line > 0)
+        .map(|p| SourceLocation::new(path, input, p));
+
+    HldrError::from(error).at(location)
+}
+
+fn record(table: &str, mapping: Mapping) -> Result<Record, FixtureError> {
+    let mut record = Record::new(None);
+
+    for (key, value) in mapping {
+        let column = match key {
+            FixtureValue::String(column) => column,
+            key => return Err(FixtureError::invalid(format!("expected column name, found `{:?}`", key))),
+        };
+
+        if column == "$name" {
+            record.name = match value {
+                FixtureValue::String(name) => Some(name),
+                _ => return Err(FixtureError::invalid_value(table, &column, "expected record name".to_owned())),
+            };
+            continue;
+        }
+
+        let value = self::value(value).map_err(|reason| FixtureError::invalid_value(table, &column, reason))?;
+        record.nodes.push(Attribute::new(column, value));
+    }
+
+    Ok(record)
+}
+
+/// Converts the value of a column, where a mapping with a single key of `$ref`, `$sql`,
+/// or `$env` is a reference, SQL fragment, or environment variable, and any other
+/// mapping or sequence is loaded as JSON.
+fn value(value: FixtureValue) -> Result<Value, String> {
+    match value {
+        FixtureValue::Null => Ok(Value::Null),
+        FixtureValue::Bool(b) => Ok(builder::boolean(b)),
+        FixtureValue::Number(n) => Ok(builder::number(n)),
+        FixtureValue::String(s) => Ok(builder::text(&s)),
+        FixtureValue::Mapping(mapping) if mapping.len() == 1 => {
+            let (key, inner) = mapping.iter().next().expect("mapping should have one entry");
+
+            match (key.as_str(), inner) {
+                (Some("$ref"), FixtureValue::String(path)) => reference(path),
+                (Some("$sql"), FixtureValue::String(sql)) => Ok(Value::SqlFragment(sql.clone())),
+                (Some("$env"), FixtureValue::String(name)) => Ok(Value::EnvVar(name.clone())),
+                (Some(key @ ("$ref" | "$sql" | "$env")), _) => Err(format!("expected string for `{}`", key)),
+                _ => json(&FixtureValue::Mapping(mapping)),
+            }
+        }
+        value @ (FixtureValue::Mapping(_) | FixtureValue::Sequence(_)) => json(&value),
+        FixtureValue::Tagged(tagged) => Err(format!("unexpected tag `{}`", tagged.tag)),
+    }
+}
+
+/// A reference written as it would be in a data file, but without the `@`.
+fn reference(path: &str) -> Result<Value, String> {
+    let (namespace, unqualified) = match path.split_once("::") {
+        Some((namespace, unqualified)) => (Some(namespace), unqualified),
+        None => (None, path),
+    };
+    let parts: Vec<&str> = unqualified.split('.').collect();
+
+    // Only the column can be left out, and only after the record
+    let valid = match parts.split_last() {
+        Some((_, [])) => namespace.is_none() && !unqualified.is_empty(),
+        Some((_, qualifiers)) => parts.len() <= 4 && qualifiers.iter().all(|part| !part.is_empty()),
+        None => false,
+    };
+
+    if !valid || namespace.is_some_and(str::is_empty) {
+        return Err(format!("invalid reference `{}`", path));
+    }
+
+    Ok(builder::reference(path))
+}
+
+fn json(value: &FixtureValue) -> Result<Value, String> {
+    serde_json::to_string(value)
+        .map(Value::Json)
+        .map_err(|e| format!("cannot convert to JSON: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Format};
+    use crate::parser::builder::{boolean, null, number, reference, text, ParseTreeBuilder};
+    use crate::parser::nodes::Value;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse() {
+        let json = r#"{
+          "tables": [
+            { "table": "person", "records": [ { "$name": "kevin", "name": "Kevin's", "age": 30, "active": true } ] },
+            { "schema": "hr", "table": "review", "records": [
+              { "person_id": { "$ref": "person.kevin.id" }, "notes": { "strengths": ["patience"] }, "due": null },
+              { "created_at": { "$sql": "now()" }, "token": { "$env": "TOKEN" } }
+            ] }
+          ]
+        }"#;
+        let yaml = "
+tables:
+  - table: person
+    records:
+      - $name: kevin
+        name: Kevin's
+        age: 30
+        active: true
+  - schema: hr
+    table: review
+    records:
+      - person_id: { $ref: person.kevin.id }
+        notes: { strengths: [ patience ] }
+        due: null
+      - created_at: { $sql: now() }
+        token: { $env: TOKEN }
+";
+        let expected = ParseTreeBuilder::new()
+            .table("person")
+            .record("kevin")
+            .attr("name", text("Kevin's"))
+            .attr("age", number(30))
+            .attr("active", boolean(true))
+            .schema("hr")
+            .table("review")
+            .anonymous_record()
+            .attr("person_id", reference("person.kevin.id"))
+            .attr("notes", Value::Json(r#"{"strengths":["patience"]}"#.to_owned()))
+            .attr("due", null())
+            .anonymous_record()
+            .attr("created_at", Value::SqlFragment("now()".to_owned()))
+            .attr("token", Value::EnvVar("TOKEN".to_owned()))
+            .build();
+
+        assert_eq!(parse(Path::new("a.json"), json, Format::Json).unwrap(), expected);
+        assert_eq!(parse(Path::new("a.yaml"), yaml, Format::Yaml).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |input: &str| parse(Path::new("a.json"), input, Format::Json).unwrap_err();
+
+        let e = error("{\n  \"tables\": [ { \"name\": \"person\" } ]\n}");
+        assert_eq!(e.to_string(), "invalid fixture: unknown field `name`, expected one of `schema`, `table`, `records` at line 2 column 22");
+        assert_eq!(e.location.unwrap().position.line, 2);
+
+        let e = error(r#"{ "tables": [ { "table": "person", "records": [ { "boss_id": { "$ref": "a.b.c.d.e" } } ] } ] }"#);
+        assert_eq!(e.to_string(), "invalid value for column `boss_id` of table `person`: invalid reference `a.b.c.d.e`");
+        assert!(e.location.is_none());
+
+        let e = error(r#"{ "tables": [ { "table": "person", "records": [ { "boss_id": { "$ref": 1 } } ] } ] }"#);
+        assert_eq!(e.to_string(), "invalid value for column `boss_id` of table `person`: expected string for `$ref`");
+    }
+}
@@ -0,0 +1,76 @@
+//! Schema, table, and column names as they are stored in the parse tree, which is
+//! without the quotes they may have been written with, so that `"person"` and `person`
+//! are the same name wherever they are compared, and quoted again when rendered.
+//!
+//! Just as in Postgres, unquoted names are folded to lowercase while quoted names are
+//! kept as they are, so `Person` is `person` but `"Person"` is not.
+
+use crate::lexer;
+use crate::lexer::tokens::TokenKind;
+
+/// A schema, table, column, or role's name as it was written.
+#[derive(Clone, Debug)]
+pub(crate) struct Identifier {
+    pub(crate) quoted: bool,
+    pub(crate) value: String,
+}
+
+impl Identifier {
+    /// The name the identifier refers to, which is lowercased unless it is quoted.
+    pub(crate) fn name(self) -> String {
+        match self.quoted {
+            true => self.value,
+            false => self.value.to_ascii_lowercase(),
+        }
+    }
+}
+
+/// Quotes the name as a Postgres identifier, escaping any quotes within it.
+pub(crate) fn quote(name: &str) -> String {
+    format!(r#""{}""#, name.replace('"', r#""""#))
+}
+
+/// The number of characters the name takes up when written quoted.
+pub(crate) fn quoted_width(name: &str) -> usize {
+    name.chars().count() + name.matches('"').count() + 2
+}
+
+/// Whether the name can be written unquoted, as it lexes as exactly the same identifier
+/// rather than eg. a keyword, and is not folded to another name.
+pub(crate) fn is_unquoted(name: &str) -> bool {
+    match lexer::tokenize(name.chars()).as_deref() {
+        Ok([token]) => {
+            matches!(&token.kind, TokenKind::Identifier(i) if i == name) && !name.chars().any(|c| c.is_ascii_uppercase())
+        }
+        _ => false,
+    }
+}
+
+/// Renders the name as it would be written in a data file, quoting it if needed.
+pub(crate) fn render(name: &str) -> String {
+    match is_unquoted(name) {
+        true => name.to_owned(),
+        false => quote(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identifiers() {
+        assert_eq!(render("name"), "name");
+        assert_eq!(render("Name"), r#""Name""#);
+        assert_eq!(render("table"), r#""table""#);
+        assert_eq!(render("t"), r#""t""#);
+        assert_eq!(render("first name"), r#""first name""#);
+        assert_eq!(render(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(quoted_width(r#"say "hi""#), 12);
+
+        let identifier = |quoted, value: &str| Identifier { quoted, value: value.to_owned() }.name();
+        assert_eq!(identifier(false, "MyTable"), "mytable");
+        assert_eq!(identifier(true, "MyTable"), "MyTable");
+        assert_eq!(identifier(false, "Änderung"), "Änderung");
+    }
+}
@@ -0,0 +1,225 @@
+//! Expands the CSV files imported into tables with `$import 'path'` into anonymous
+//! records in place of each import, so that exported datasets can be loaded
+//! alongside records declared by hand.
+
+use crate::diagnostics::SourceLocation;
+use crate::error::HldrError;
+use crate::parser::nodes::{Attribute, Import, ParseTree, Record, StructuralNode, Value};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ImportErrorKind {
+    /// The imported file could not be found or read
+    Unreadable(String, io::Error),
+    /// The imported file is not valid CSV, eg. a row has more fields than the header
+    Invalid(String, csv::Error),
+    /// The header of the imported file has an empty name, with the number of its column
+    UnnamedColumn(String, usize),
+    /// The data was not read from a file, so the imported path has nothing to be relative to
+    NotInFile(String),
+}
+
+#[derive(Debug)]
+pub struct ImportError {
+    pub kind: ImportErrorKind,
+}
+
+impl ImportError {
+    fn unreadable(path: String, error: io::Error) -> Self {
+        Self {
+            kind: ImportErrorKind::Unreadable(path, error),
+        }
+    }
+
+    fn invalid(path: String, error: csv::Error) -> Self {
+        Self {
+            kind: ImportErrorKind::Invalid(path, error),
+        }
+    }
+
+    fn unnamed_column(path: String, column: usize) -> Self {
+        Self {
+            kind: ImportErrorKind::UnnamedColumn(path, column),
+        }
+    }
+
+    pub(crate) fn not_in_file(path: String) -> Self {
+        Self {
+            kind: ImportErrorKind::NotInFile(path),
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ImportErrorKind::*;
+
+        match &self.kind {
+            Unreadable(path, error) => write!(f, "could not read imported file `{}`: {}", path, error),
+            Invalid(path, error) => write!(f, "invalid CSV in imported file `{}`: {}", path, error),
+            UnnamedColumn(path, column) => write!(f, "expected name for column {} in header of imported file `{}`", column, path),
+            NotInFile(path) => write!(f, "cannot import `{}` outside of a data file", path),
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ImportErrorKind::Unreadable(_, error) => Some(error),
+            ImportErrorKind::Invalid(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces the imports of every table in the tree, which was parsed from the input
+/// of the data file at the path, with the records read from the imported files,
+/// returning the paths of those files.
+pub(crate) fn expand_imports(tree: &mut ParseTree, path: &Path, input: &str) -> Result<Vec<PathBuf>, HldrError> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut imported_files = Vec::new();
+    let tables = tree.nodes.iter_mut().flat_map(|node| match node {
+        StructuralNode::Schema(schema) => schema.nodes.iter_mut().collect(),
+        StructuralNode::Table(table) => vec![table.as_mut()],
+    });
+
+    for table in tables {
+        let imports = mem::take(&mut table.imports);
+        let mut records = mem::take(&mut table.nodes).into_iter();
+        let mut index = 0;
+
+        for import in imports {
+            table.nodes.extend(records.by_ref().take(import.index - index));
+            index = import.index;
+
+            let file = dir.join(&import.path);
+            let imported = read_records(&file, &import).map_err(|e| {
+                let location = SourceLocation::new(path, input, import.position);
+                HldrError::from(e).at(Some(location))
+            })?;
+            table.nodes.extend(imported);
+            imported_files.push(file);
+        }
+
+        table.nodes.extend(records);
+    }
+
+    Ok(imported_files)
+}
+
+/// Returns the path of the first import in the tree, if any.
+pub(crate) fn first_import(tree: &ParseTree) -> Option<&str> {
+    tree.nodes
+        .iter()
+        .flat_map(|node| match node {
+            StructuralNode::Schema(schema) => schema.nodes.iter().collect(),
+            StructuralNode::Table(table) => vec![table.as_ref()],
+        })
+        .flat_map(|table| table.imports.first())
+        .map(|import| import.path.as_str())
+        .next()
+}
+
+/// Reads an anonymous record from each row of the CSV file, whose header names the
+/// columns. Empty fields are null, and every other field is a string.
+fn read_records(file: &Path, import: &Import) -> Result<Vec<Record>, ImportError> {
+    let contents = fs::read(file).map_err(|e| ImportError::unreadable(import.path.clone(), e))?;
+    let mut reader = csv::Reader::from_reader(contents.as_slice());
+    let columns = reader
+        .headers()
+        .map_err(|e| ImportError::invalid(import.path.clone(), e))?
+        .clone();
+
+    if let Some(column) = columns.iter().position(str::is_empty) {
+        return Err(ImportError::unnamed_column(import.path.clone(), column + 1));
+    }
+
+    reader
+        .records()
+        .map(|row| {
+            let row = row.map_err(|e| ImportError::invalid(import.path.clone(), e))?;
+            let mut record = Record::new(None);
+
+            record.position = Some(import.position);
+            record.nodes = columns
+                .iter()
+                .zip(row.iter())
+                .map(|(column, field)| Attribute::new(column.to_owned(), field_value(field)))
+                .collect();
+
+            Ok(record)
+        })
+        .collect()
+}
+
+fn field_value(field: &str) -> Value {
+    match field {
+        "" => Value::Null,
+        _ => Value::Text(format!("'{}'", field.replace('\'', "''"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+    use pretty_assertions::assert_eq;
+
+    fn records(tree: &ParseTree) -> Vec<Vec<(String, Value)>> {
+        tree.nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Table(table) => table.nodes.iter(),
+                node => panic!("expected table, found {:?}", node),
+            })
+            .map(|record| record.nodes.iter().map(|a| (a.name.clone(), a.value.clone())).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_imports() {
+        let dir = std::env::temp_dir().join(format!("hldr-import-{}", std::process::id()));
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data/people.csv"), "name,age\nKevin,39\n\"O'Brien, Pat\",\n").unwrap();
+        fs::write(dir.join("data/ragged.csv"), "name,age\nKevin,39,blue\n").unwrap();
+        fs::write(dir.join("data/unnamed.csv"), "name,\nKevin,39\n").unwrap();
+
+        let data_file = dir.join("a.hldr");
+        let input = "table person (\n  kevin ( name 'Kevin' )\n  $import 'data/people.csv'\n  ( name 'Stacey' )\n)";
+        let mut tree = parser::parse(lexer::tokenize(input.chars()).unwrap().into_iter()).unwrap();
+        let imported_files = expand_imports(&mut tree, &data_file, input).unwrap();
+        assert_eq!(imported_files, vec![dir.join("data/people.csv")]);
+
+        let text = |s: &str| Value::Text(s.to_owned());
+        assert_eq!(
+            records(&tree),
+            vec![
+                vec![("name".to_owned(), text("'Kevin'"))],
+                vec![("name".to_owned(), text("'Kevin'")), ("age".to_owned(), text("'39'"))],
+                vec![("name".to_owned(), text("'O''Brien, Pat'")), ("age".to_owned(), Value::Null)],
+                vec![("name".to_owned(), text("'Stacey'"))],
+            ],
+        );
+
+        for (path, message) in [
+            ("nope.csv", "could not read imported file `nope.csv`: "),
+            ("data/ragged.csv", "invalid CSV in imported file `data/ragged.csv`: "),
+            ("data/unnamed.csv", "expected name for column 2 in header of imported file `data/unnamed.csv`"),
+        ] {
+            let input = format!("table person (\n  $import '{}'\n)", path);
+            let mut tree = parser::parse(lexer::tokenize(input.chars()).unwrap().into_iter()).unwrap();
+            let error = expand_imports(&mut tree, &data_file, &input).unwrap_err();
+
+            assert!(error.to_string().starts_with(message), "{}", error);
+            assert_eq!(error.location.unwrap().position, crate::Position { line: 2, column: 3 });
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+//! Expands the files included in data files with `include 'path'` in place of
+//! each include, so that shared data like countries or roles can be declared
+//! once and reused by many data files.
+
+use crate::diagnostics::{SourceLocation, SourceMap};
+use crate::error::HldrError;
+use crate::loader::report::Timings;
+use crate::parser::nodes::ParseTree;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum IncludeErrorKind {
+    /// The file includes itself, directly or through the files it includes
+    Cycle(String),
+    /// The included file could not be found or read
+    Unreadable(String, io::Error),
+    /// The data was not read from a file, so the included path has nothing to be relative to
+    NotInFile(String),
+}
+
+#[derive(Debug)]
+pub struct IncludeError {
+    pub kind: IncludeErrorKind,
+}
+
+impl IncludeError {
+    fn cycle(path: String) -> Self {
+        Self {
+            kind: IncludeErrorKind::Cycle(path),
+        }
+    }
+
+    fn unreadable(path: String, error: io::Error) -> Self {
+        Self {
+            kind: IncludeErrorKind::Unreadable(path, error),
+        }
+    }
+
+    pub(crate) fn not_in_file(path: String) -> Self {
+        Self {
+            kind: IncludeErrorKind::NotInFile(path),
+        }
+    }
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use IncludeErrorKind::*;
+
+        match &self.kind {
+            Cycle(path) => write!(f, "included file `{}` includes itself", path),
+            Unreadable(path, error) => write!(f, "could not read included file `{}`: {}", path, error),
+            NotInFile(path) => write!(f, "cannot include `{}` outside of a data file", path),
+        }
+    }
+}
+
+impl Error for IncludeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            IncludeErrorKind::Unreadable(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Parses data files along with the files they include, keeping track of the
+/// files being included to detect cycles and of those already parsed, since
+/// each file is only loaded once no matter how many files include it.
+#[derive(Debug, Default)]
+pub(crate) struct Includes {
+    pub(crate) sources: SourceMap,
+    /// The CSV files imported by the parsed files, which errors are never located in
+    pub(crate) imported_files: Vec<PathBuf>,
+    /// How long the parsed files took to lex and parse
+    pub(crate) timings: Timings,
+    /// The contents of files being edited, by canonical path, which are parsed
+    /// in place of what is on disk
+    documents: HashMap<PathBuf, String>,
+    including: Vec<PathBuf>,
+    parsed: HashSet<PathBuf>,
+}
+
+impl Includes {
+    pub(crate) fn with_documents(documents: HashMap<PathBuf, String>) -> Self {
+        Self {
+            documents,
+            ..Self::default()
+        }
+    }
+
+    /// Reads and parses the data file, with the nodes of any files it includes in
+    /// place of each include, or returns an empty tree if it was already parsed.
+    pub(crate) fn parse_file(&mut self, path: &Path) -> Result<ParseTree, HldrError> {
+        // Files being edited may not have been saved yet
+        let canonical = match path.canonicalize() {
+            Err(_) if self.documents.contains_key(path) => path.to_owned(),
+            result => result?,
+        };
+        let input = match self.documents.get(&canonical) {
+            Some(document) => document.clone(),
+            None => fs::read_to_string(path)?,
+        };
+
+        if !self.parsed.insert(canonical.clone()) {
+            return Ok(ParseTree::default());
+        }
+
+        let (mut tree, imported_files) = crate::parse_data_file(path, &input, &mut self.timings)?;
+        self.imported_files.extend(imported_files);
+        let includes = std::mem::take(&mut tree.includes);
+
+        // The included files are added to the source map after this one, but they
+        // still occupy their own ranges of lines, so where their nodes end up in the
+        // tree does not matter for locating errors
+        self.sources.add(path.to_owned(), input.clone(), &mut tree);
+        self.including.push(canonical);
+
+        let mut nodes = tree.nodes.into_iter();
+        let mut expanded = Vec::new();
+        let mut search_paths = tree.search_paths;
+        let mut variables = tree.variables;
+        let mut hooks = tree.hooks;
+        let mut index = 0;
+
+        for include in includes {
+            expanded.extend(nodes.by_ref().take(include.index - index));
+            index = include.index;
+
+            let location = Some(SourceLocation::new(path, &input, include.position));
+            let included = path.parent().unwrap_or(Path::new("")).join(&include.path);
+            let canonical = included
+                .canonicalize()
+                .map_err(|e| HldrError::from(IncludeError::unreadable(include.path.clone(), e)).at(location.clone()))?;
+
+            if self.including.contains(&canonical) {
+                return Err(HldrError::from(IncludeError::cycle(include.path)).at(location));
+            }
+
+            let included = self.parse_file(&included)?;
+            expanded.extend(included.nodes);
+            search_paths.extend(included.search_paths);
+            variables.extend(included.variables);
+            hooks.extend(included.hooks);
+        }
+
+        expanded.extend(nodes);
+        self.including.pop();
+
+        Ok(ParseTree {
+            nodes: expanded,
+            includes: Vec::new(),
+            search_paths,
+            variables,
+            hooks,
+            trivia: tree.trivia,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::nodes::StructuralNode;
+
+    fn table_names(tree: &ParseTree) -> Vec<&str> {
+        tree.nodes
+            .iter()
+            .map(|node| match node {
+                StructuralNode::Table(table) => table.identity.name.as_str(),
+                StructuralNode::Schema(schema) => schema.identity.name.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let dir = std::env::temp_dir().join(format!("hldr-include-{}", std::process::id()));
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::write(dir.join("a.hldr"), "table t1 ()\ninclude 'shared/b.hldr'\ntable t4 ()\ninclude 'shared/c.hldr'\n").unwrap();
+        fs::write(dir.join("shared/b.hldr"), "table t2 ()\ninclude 'c.hldr'\n").unwrap();
+        fs::write(dir.join("shared/c.hldr"), "table t3 ()\n").unwrap();
+        fs::write(dir.join("cycle.hldr"), "table t1 ()\ninclude 'shared/../cycle.hldr'\n").unwrap();
+        fs::write(dir.join("missing.hldr"), "\n  include 'nope.hldr'\n").unwrap();
+
+        // Files are expanded in place, relative to the including file, and only once
+        let mut includes = Includes::default();
+        let tree = includes.parse_file(&dir.join("a.hldr")).unwrap();
+        assert_eq!(table_names(&tree), vec!["t1", "t2", "t3", "t4"]);
+        assert_eq!(includes.parse_file(&dir.join("shared/b.hldr")).unwrap(), ParseTree::default());
+
+        let error = Includes::default().parse_file(&dir.join("cycle.hldr")).unwrap_err();
+        assert_eq!(error.to_string(), "included file `shared/../cycle.hldr` includes itself");
+        assert_eq!(error.location.unwrap().position, crate::Position { line: 2, column: 1 });
+
+        let error = Includes::default().parse_file(&dir.join("missing.hldr")).unwrap_err();
+        assert!(error.to_string().starts_with("could not read included file `nope.hldr`: "));
+        assert_eq!(error.location.unwrap().position, crate::Position { line: 2, column: 3 });
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -4,11 +4,15 @@ use std::fmt;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum LexErrorKind {
+    InvalidEscape(String),
+    InvalidHexLiteral(String),
+    InvalidJson(String),
     InvalidNumericLiteral(String),
     UnclosedQuotedIdentifier,
     UnclosedString,
     UnexpectedEOF,
     UnexpectedCharacter(char),
+    UnknownKeyword(String),
 }
 
 impl fmt::Display for LexErrorKind {
@@ -16,6 +20,15 @@ impl fmt::Display for LexErrorKind {
         use LexErrorKind::*;
 
         match self {
+            InvalidEscape(reason) => {
+                write!(f, "invalid escape string, {}", reason)
+            }
+            InvalidHexLiteral(h) => {
+                write!(f, "binary string x'{}' has an odd number of hex digits", h)
+            }
+            InvalidJson(reason) => {
+                write!(f, "invalid JSON literal, {}", reason)
+            }
             InvalidNumericLiteral(n) => {
                 write!(f, "invalid numeric literal `{}`", n)
             }
@@ -31,6 +44,9 @@ impl fmt::Display for LexErrorKind {
             UnexpectedCharacter(c) => {
                 write!(f, "unexpected character `{}`", c)
             }
+            UnknownKeyword(k) => {
+                write!(f, "unknown keyword `{}`", k)
+            }
         }
     }
 }
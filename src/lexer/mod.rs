@@ -1,26 +1,76 @@
 pub mod error;
 mod prelude;
+mod spans;
 mod states;
 pub mod tokens;
 
 use error::LexError;
 use prelude::{Context, State};
+pub use spans::{spans, Span, TokenClass};
 use states::Start;
 use tokens::Token;
+use crate::Position;
+use std::vec;
 
 pub fn tokenize(input: impl Iterator<Item = char>) -> Result<Vec<Token>, LexError> {
-    let mut ctx = Context::default();
-    let mut state: Box<dyn State> = Box::new(Start);
+    tokenize_from(input, Position::default())
+}
+
+/// Tokenizes input that starts at the given position rather than the start of a file,
+/// eg. a value embedded in a string literal.
+fn tokenize_from(input: impl Iterator<Item = char>, position: Position) -> Result<Vec<Token>, LexError> {
+    let mut tokens = tokens(input);
+    tokens.ctx.current_position = position;
+    tokens.collect()
+}
 
-    for c in input {
-        state = state.receive(&mut ctx, Some(c))?;
-        ctx.advance_position(c);
+/// Returns an iterator over the tokens of the input, which lexes the input as the
+/// tokens are taken rather than all at once, so that large data files can be parsed
+/// without holding all of their tokens in memory. Nothing is returned after an error.
+pub fn tokens<I: Iterator<Item = char>>(input: I) -> Tokens<I> {
+    Tokens {
+        input,
+        ctx: Context::default(),
+        state: Some(Box::new(Start)),
+        lexed: Vec::new().into_iter(),
     }
+}
+
+pub struct Tokens<I> {
+    input: I,
+    ctx: Context,
+    /// The state to receive the next character, or `None` once the end of the input
+    /// or an error has been received
+    state: Option<Box<dyn State>>,
+    /// The tokens lexed from the last character received that have not been taken yet
+    lexed: vec::IntoIter<Token>,
+}
+
+impl<I: Iterator<Item = char>> Iterator for Tokens<I> {
+    type Item = Result<Token, LexError>;
 
-    state.receive(&mut ctx, None)?;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.lexed.next() {
+                return Some(Ok(token));
+            }
 
-    let tokens = ctx.into_tokens();
-    Ok(tokens)
+            let state = self.state.take()?;
+            let c = self.input.next();
+
+            match state.receive(&mut self.ctx, c) {
+                Ok(state) => {
+                    if let Some(c) = c {
+                        self.ctx.advance_position(c);
+                        self.state = Some(state);
+                    }
+                }
+                Err(error) => return Some(Err(error)),
+            }
+
+            self.lexed = self.ctx.take_tokens().into_iter();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -28,7 +78,7 @@ mod tests {
     use pretty_assertions::assert_eq;
     use super::tokenize;
     use crate::lexer::error::{LexError, LexErrorKind};
-    use crate::lexer::tokens::{Keyword, Symbol, Token, TokenKind};
+    use crate::lexer::tokens::{Keyword, Symbol, TextPart, Token, TokenKind};
     use crate::Position;
 
     fn tokens(input: &str) -> Vec<Token> {
@@ -112,6 +162,10 @@ mod tests {
                     kind: TokenKind::LineSep,
                     position: Position { line: 1, column: 1 }
                 },
+                Token {
+                    kind: TokenKind::Comment(" this is -- a comment".to_owned()),
+                    position: Position { line: 2, column: 1 }
+                },
                 Token {
                     kind: TokenKind::LineSep,
                     position: Position {
@@ -125,7 +179,7 @@ mod tests {
 
     #[test]
     fn test_keywords() {
-        let input = "as schema table";
+        let input = "as schema table $columns $defaults";
         assert_eq!(
             tokens(input),
             vec![
@@ -141,6 +195,14 @@ mod tests {
                     kind: TokenKind::Keyword(Keyword::Table),
                     position: Position { line: 1, column: 11 },
                 },
+                Token {
+                    kind: TokenKind::Keyword(Keyword::Columns),
+                    position: Position { line: 1, column: 17 },
+                },
+                Token {
+                    kind: TokenKind::Keyword(Keyword::Defaults),
+                    position: Position { line: 1, column: 26 },
+                },
             ]
         );
     }
@@ -174,6 +236,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_null() {
+        assert_eq!(
+            tokens("null"),
+            vec![Token {
+                kind: TokenKind::Null,
+                position: Position { line: 1, column: 1 },
+            }]
+        );
+    }
+
     #[test]
     fn test_identifiers() {
         for ident in [
@@ -205,18 +278,18 @@ mod tests {
             tokens(input),
             vec![
                 Token {
-                    kind: TokenKind::QuotedIdentifier("\"this is an identifier\"".to_string()),
+                    kind: TokenKind::QuotedIdentifier("this is an identifier".to_string()),
                     position: Position { line: 1, column: 1 },
                 },
                 Token {
-                    kind: TokenKind::QuotedIdentifier("\"and so\n        is this\"".to_string()),
+                    kind: TokenKind::QuotedIdentifier("and so\n        is this".to_string()),
                     position: Position {
                         line: 1,
                         column: 25
                     },
                 },
                 Token {
-                    kind: TokenKind::QuotedIdentifier("\"and this\"".to_string()),
+                    kind: TokenKind::QuotedIdentifier("and this".to_string()),
                     position: Position { line: 2, column: 18 },
                 },
             ]
@@ -242,11 +315,62 @@ mod tests {
             "12_34",
             "1_2.3_4",
             "1_2.3_4_5",
+            "1e6",
+            "1E+6",
+            "-2.5e-3",
+            "1.e3",
+            ".5e1_0",
         ] {
+            // Underscores are removed so that any version of Postgres accepts the numbers
             assert_eq!(
                 tokens(num),
                 vec![Token {
-                    kind: TokenKind::Number(num.to_owned()),
+                    kind: TokenKind::Number(num.replace('_', "")),
+                    position: Position { line: 1, column: 1 },
+                }],
+                "{}",
+                num,
+            );
+        }
+
+        // Numbers can be followed directly by commas, eg. in a record's values
+        assert_eq!(
+            tokens("1,-2.5e3,"),
+            vec![
+                Token {
+                    kind: TokenKind::Number("1".to_owned()),
+                    position: Position { line: 1, column: 1 },
+                },
+                Token {
+                    kind: TokenKind::Symbol(Symbol::Comma),
+                    position: Position { line: 1, column: 2 },
+                },
+                Token {
+                    kind: TokenKind::Number("-2.5e3".to_owned()),
+                    position: Position { line: 1, column: 3 },
+                },
+                Token {
+                    kind: TokenKind::Symbol(Symbol::Comma),
+                    position: Position { line: 1, column: 9 },
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_radix_numbers() {
+        for (num, decimal) in [
+            ("0x1F", "31"),
+            ("0XfF", "255"),
+            ("-0x_1f", "-31"),
+            ("0o17", "15"),
+            ("0b1010_1010", "170"),
+            ("0x0", "0"),
+        ] {
+            assert_eq!(
+                tokens(num),
+                vec![Token {
+                    kind: TokenKind::Number(decimal.to_owned()),
                     position: Position { line: 1, column: 1 },
                 }],
                 "{}",
@@ -273,6 +397,22 @@ mod tests {
                 input,
             );
         }
+        for (input, c, column) in [("1e5x", 'x', 4), ("1e+-5", '-', 4), ("1_e5", 'e', 3), ("0x1G", 'G', 4), ("0b12", '2', 4), ("0x__1", '_', 4), ("12x3", 'x', 3)] {
+            assert_eq!(
+                tokenize(input.chars()),
+                Err(bad_char(c, Position { line: 1, column })),
+                "{}",
+                input,
+            );
+        }
+        for input in ["1e ", "1e+ ", "1e5_ ", "0x ", "0x1_ ", "0x1_0000_0000_0000_0000_0000_0000_0000_0000 "] {
+            assert_eq!(
+                tokenize(input.chars()),
+                Err(bad_number(input.trim_end().to_string(), Position { line: 1, column: 1 })),
+                "{}",
+                input,
+            );
+        }
         for input in ["123_ ", "12.34_ "] {
             assert_eq!(
                 tokenize(input.chars()),
@@ -315,6 +455,185 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_hex() {
+        assert_eq!(
+            tokens("x'DEADbeef' X'' x 'ab'"),
+            vec![
+                Token {
+                    kind: TokenKind::Hex("deadbeef".to_string()),
+                    position: Position { line: 1, column: 1 },
+                },
+                Token {
+                    kind: TokenKind::Hex(String::new()),
+                    position: Position { line: 1, column: 13 },
+                },
+                Token {
+                    kind: TokenKind::Identifier("x".to_string()),
+                    position: Position { line: 1, column: 17 },
+                },
+                Token {
+                    kind: TokenKind::Text("'ab'".to_string()),
+                    position: Position { line: 1, column: 19 },
+                },
+            ]
+        );
+        assert_eq!(tokenize("x'abg'".chars()), Err(bad_char('g', Position { line: 1, column: 5 })));
+        assert_eq!(
+            tokenize("x'abc'".chars()),
+            Err(LexError {
+                kind: LexErrorKind::InvalidHexLiteral("abc".to_string()),
+                position: Position { line: 1, column: 1 },
+            }),
+        );
+        assert_eq!(
+            tokenize("x'ab".chars()),
+            Err(LexError {
+                kind: LexErrorKind::UnclosedString,
+                position: Position { line: 1, column: 5 },
+            }),
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            tokens("json{ \"a\": [1, {\"b\": \"}]\\\"\"}] } json[]\njson"),
+            vec![
+                Token {
+                    kind: TokenKind::Json("{ \"a\": [1, {\"b\": \"}]\\\"\"}] }".to_string()),
+                    position: Position { line: 1, column: 1 },
+                },
+                Token {
+                    kind: TokenKind::Json("[]".to_string()),
+                    position: Position { line: 1, column: 33 },
+                },
+                Token {
+                    kind: TokenKind::LineSep,
+                    position: Position { line: 1, column: 39 },
+                },
+                Token {
+                    kind: TokenKind::Identifier("json".to_string()),
+                    position: Position { line: 2, column: 1 },
+                },
+            ]
+        );
+        assert_eq!(
+            tokenize("  json{ \"a\": 1,\n  \"b\" 2 }".chars()),
+            Err(LexError {
+                kind: LexErrorKind::InvalidJson("expected `:`".to_string()),
+                position: Position { line: 2, column: 7 },
+            }),
+        );
+        assert_eq!(
+            tokenize("json{ 'a': 1 }".chars()),
+            Err(LexError {
+                kind: LexErrorKind::InvalidJson("key must be a string".to_string()),
+                position: Position { line: 1, column: 7 },
+            }),
+        );
+    }
+
+    #[test]
+    fn test_text_blocks() {
+        let input = "x '''\n  <p>\n    It's ''quoted''\n\n  </p>\n  ''' y '''\r\nnot indented\r\n  ''' '''\n'''\n'''it''''s'''";
+        assert_eq!(
+            tokens(input),
+            vec![
+                Token {
+                    kind: TokenKind::Identifier("x".to_string()),
+                    position: Position { line: 1, column: 1 },
+                },
+                Token {
+                    kind: TokenKind::Text("'<p>\n  It''s ''''quoted''''\n\n</p>'".to_string()),
+                    position: Position { line: 1, column: 3 },
+                },
+                Token {
+                    kind: TokenKind::Identifier("y".to_string()),
+                    position: Position { line: 6, column: 7 },
+                },
+                Token {
+                    kind: TokenKind::Text("'not indented'".to_string()),
+                    position: Position { line: 6, column: 9 },
+                },
+                Token {
+                    kind: TokenKind::Text("''".to_string()),
+                    position: Position { line: 10, column: 7 },
+                },
+                Token {
+                    kind: TokenKind::LineSep,
+                    position: Position { line: 11, column: 4 },
+                },
+                // Without a newline, the quotes are escaped as usual
+                Token {
+                    kind: TokenKind::Text("'''it''''s'''".to_string()),
+                    position: Position { line: 12, column: 1 },
+                },
+            ]
+        );
+        assert_eq!(
+            tokenize("'''\nunclosed''".chars()),
+            Err(LexError {
+                kind: LexErrorKind::UnclosedString,
+                position: Position { line: 2, column: 11 },
+            }),
+        );
+    }
+
+    #[test]
+    fn test_escape_strings() {
+        let input = r"e'a\tb\nc' E'it\'s ''{x}''' e'\x41\101é\U0001F600\303\251\q\\' e 'x'";
+        assert_eq!(
+            tokens(input),
+            vec![
+                Token {
+                    kind: TokenKind::Text("'a\tb\nc'".to_string()),
+                    position: Position { line: 1, column: 1 },
+                },
+                Token {
+                    kind: TokenKind::Text("'it''s ''{x}'''".to_string()),
+                    position: Position { line: 1, column: 12 },
+                },
+                Token {
+                    kind: TokenKind::Text("'AAé😀éq\\'".to_string()),
+                    position: Position { line: 1, column: 29 },
+                },
+                Token {
+                    kind: TokenKind::Identifier("e".to_string()),
+                    position: Position { line: 1, column: 64 },
+                },
+                Token {
+                    kind: TokenKind::Text("'x'".to_string()),
+                    position: Position { line: 1, column: 66 },
+                },
+            ]
+        );
+
+        for (input, reason) in [
+            (r"e'\u12'", "invalid escape sequence `\\u12`"),
+            (r"e'\x'", "invalid escape sequence `\\x`"),
+            (r"e'\0'", "invalid escape sequence `\\0`"),
+            (r"e'\777'", "invalid escape sequence `\\777`"),
+            (r"e'\uD800'", "invalid escape sequence `\\uD800`"),
+            (r"e'\303'", "escaped bytes are not valid UTF-8"),
+        ] {
+            assert_eq!(
+                tokenize(input.chars()),
+                Err(LexError {
+                    kind: LexErrorKind::InvalidEscape(reason.to_string()),
+                    position: Position { line: 1, column: 1 },
+                }),
+            );
+        }
+        assert_eq!(
+            tokenize(r"e'unclosed\'".chars()),
+            Err(LexError {
+                kind: LexErrorKind::UnclosedString,
+                position: Position { line: 1, column: 13 },
+            }),
+        );
+    }
+
     #[test]
     fn test_underscores() {
         let input = "_ _ _one two_";
@@ -363,7 +682,7 @@ mod tests {
                     position: Position { line: 1, column: 7 },
                 },
                 Token {
-                    kind: TokenKind::QuotedIdentifier("\"two\"".to_string()),
+                    kind: TokenKind::QuotedIdentifier("two".to_string()),
                     position: Position { line: 1, column: 8 },
                 },
                 Token {
@@ -388,7 +707,7 @@ mod tests {
                     },
                 },
                 Token {
-                    kind: TokenKind::QuotedIdentifier("\"four\"".to_string()),
+                    kind: TokenKind::QuotedIdentifier("four".to_string()),
                     position: Position {
                         line: 1,
                         column: 22
@@ -397,4 +716,168 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_record_quantity() {
+        let input = "_ * 50 (";
+        assert_eq!(
+            tokens(input),
+            vec![
+                Token {
+                    kind: TokenKind::Symbol(Symbol::Underscore),
+                    position: Position { line: 1, column: 1 },
+                },
+                Token {
+                    kind: TokenKind::Symbol(Symbol::Asterisk),
+                    position: Position { line: 1, column: 3 },
+                },
+                Token {
+                    kind: TokenKind::Number("50".to_string()),
+                    position: Position { line: 1, column: 5 },
+                },
+                Token {
+                    kind: TokenKind::Symbol(Symbol::ParenLeft),
+                    position: Position { line: 1, column: 8 },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let input = "1 + 2.5*3/4 -@x || 'y' -5";
+        let kinds: Vec<TokenKind> = tokens(input).into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Number("1".to_string()),
+                TokenKind::Symbol(Symbol::Plus),
+                TokenKind::Number("2.5".to_string()),
+                TokenKind::Symbol(Symbol::Asterisk),
+                TokenKind::Number("3".to_string()),
+                TokenKind::Symbol(Symbol::Slash),
+                TokenKind::Number("4".to_string()),
+                TokenKind::Symbol(Symbol::Dash),
+                TokenKind::Symbol(Symbol::AtSign),
+                TokenKind::Identifier("x".to_string()),
+                TokenKind::Symbol(Symbol::DoublePipe),
+                TokenKind::Text("'y'".to_string()),
+                TokenKind::Number("-5".to_string()),
+            ]
+        );
+
+        assert_eq!(tokens("||")[0].position, Position { line: 1, column: 1 });
+        assert_eq!(tokenize("1 | 2".chars()), Err(bad_char(' ', Position { line: 1, column: 4 })));
+    }
+
+    #[test]
+    fn test_namespaced_reference() {
+        let kinds: Vec<TokenKind> = tokens("@billing::customer1.id").into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Symbol(Symbol::AtSign),
+                TokenKind::Identifier("billing".to_string()),
+                TokenKind::Symbol(Symbol::DoubleColon),
+                TokenKind::Identifier("customer1".to_string()),
+                TokenKind::Symbol(Symbol::Period),
+                TokenKind::Identifier("id".to_string()),
+            ]
+        );
+
+        assert_eq!(tokenize("@billing:customer1".chars()), Err(bad_char('c', Position { line: 1, column: 10 })));
+    }
+
+    #[test]
+    fn test_interpolated_text() {
+        let token = |kind, line, column| Token { kind, position: Position { line, column } };
+
+        assert_eq!(
+            tokens("'a{@b.c}d' '{x} {index()}' '{env('X')}'"),
+            vec![
+                token(
+                    TokenKind::InterpolatedText(vec![
+                        TextPart::Literal("a".to_string()),
+                        TextPart::Embedded(vec![
+                            token(TokenKind::Symbol(Symbol::AtSign), 1, 4),
+                            token(TokenKind::Identifier("b".to_string()), 1, 5),
+                            token(TokenKind::Symbol(Symbol::Period), 1, 6),
+                            token(TokenKind::Identifier("c".to_string()), 1, 7),
+                        ]),
+                        TextPart::Literal("d".to_string()),
+                    ]),
+                    1,
+                    1,
+                ),
+                token(
+                    TokenKind::InterpolatedText(vec![
+                        TextPart::Literal("{x} ".to_string()),
+                        TextPart::Embedded(vec![
+                            token(TokenKind::Identifier("index".to_string()), 1, 18),
+                            token(TokenKind::Symbol(Symbol::ParenLeft), 1, 23),
+                            token(TokenKind::Symbol(Symbol::ParenRight), 1, 24),
+                        ]),
+                    ]),
+                    1,
+                    12,
+                ),
+                token(
+                    TokenKind::InterpolatedText(vec![TextPart::Embedded(vec![
+                        token(TokenKind::Identifier("env".to_string()), 1, 30),
+                        token(TokenKind::Symbol(Symbol::ParenLeft), 1, 33),
+                        token(TokenKind::Text("'X'".to_string()), 1, 34),
+                        token(TokenKind::Symbol(Symbol::ParenRight), 1, 37),
+                    ])]),
+                    1,
+                    28,
+                ),
+            ]
+        );
+
+        // Braces are otherwise part of the text
+        assert_eq!(
+            tokens("'{\"a\": 1} {{name}} {other()}'"),
+            vec![token(TokenKind::Text("'{\"a\": 1} {{name}} {other()}'".to_string()), 1, 1)],
+        );
+    }
+
+    #[test]
+    fn test_interpolated_sql_fragments() {
+        let token = |kind, line, column| Token { kind, position: Position { line, column } };
+
+        assert_eq!(
+            tokens("`max(n) from t where a = {@\"b}\".c}` `'{x}'`"),
+            vec![
+                token(
+                    TokenKind::InterpolatedSqlFragment(vec![
+                        TextPart::Literal("max(n) from t where a = ".to_string()),
+                        TextPart::Embedded(vec![
+                            token(TokenKind::Symbol(Symbol::AtSign), 1, 27),
+                            token(TokenKind::QuotedIdentifier("b}".to_string()), 1, 28),
+                            token(TokenKind::Symbol(Symbol::Period), 1, 32),
+                            token(TokenKind::Identifier("c".to_string()), 1, 33),
+                        ]),
+                    ]),
+                    1,
+                    1,
+                ),
+                // Braces are otherwise part of the SQL
+                token(TokenKind::SqlFragment("'{x}'".to_string()), 1, 37),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_streamed_tokens() {
+        let input = "table person (\n  kevin (name 'Kevin', age 39)\n)\n";
+        let streamed: Result<Vec<Token>, LexError> = super::tokens(input.chars()).collect();
+        assert_eq!(streamed, tokenize(input.chars()));
+
+        // Tokens lexed before an error are still returned, but nothing is after it
+        let mut streamed = super::tokens("a b 1x c".chars());
+        assert_eq!(streamed.next().unwrap().unwrap().kind, TokenKind::Identifier("a".to_owned()));
+        assert_eq!(streamed.next().unwrap().unwrap().kind, TokenKind::Identifier("b".to_owned()));
+        assert_eq!(streamed.next(), Some(Err(bad_char('x', Position { line: 1, column: 6 }))));
+        assert_eq!(streamed.next(), None);
+    }
 }
@@ -26,9 +26,15 @@ impl Context {
         self.current_position.advance(matches!(c, '\r' | '\n'));
     }
 
+    #[cfg(test)]
     pub fn into_tokens(self) -> Vec<Token> {
         self.tokens
     }
+
+    /// Takes the tokens added so far, leaving none behind.
+    pub fn take_tokens(&mut self) -> Vec<Token> {
+        std::mem::take(&mut self.tokens)
+    }
 }
 
 #[derive(Debug, Default, PartialEq)]
@@ -40,7 +46,7 @@ pub(super) struct Stack {
 impl Stack {
     pub fn new(start_position: Position, c: Option<char>) -> Self {
         Self {
-            content: c.map(|c| String::from(c)).unwrap_or_default(),
+            content: c.map(String::from).unwrap_or_default(),
             start_position,
         }
     }
@@ -53,13 +59,17 @@ impl Stack {
         self.content.push(c);
     }
 
+    pub fn as_str(&self) -> &str {
+        &self.content
+    }
+
     pub fn top(&self) -> Option<char> {
-        self.content.chars().rev().next()
+        self.content.chars().next_back()
     }
 }
 
 /// A state in the lexer's state machine.
-pub trait State : any::Any + fmt::Debug {
+pub trait State : any::Any + fmt::Debug + Send {
     /// Receives a character (or `None` when EOF) and returns the next state.
     fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult;
 }
@@ -0,0 +1,345 @@
+use super::error::LexError;
+use super::tokens::{Symbol, TextPart, Token, TokenKind, FUNCTIONS};
+use super::tokenize;
+use crate::Position;
+
+/// Where a token is in the input, as byte offsets from the start of the input.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The kinds of tokens that editors highlight differently.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TokenClass {
+    Comment,
+    /// Booleans and null
+    Constant,
+    /// The name of a value function being called, eg. `now` in `now()`
+    Function,
+    /// Identifiers, whether quoted or not
+    Identifier,
+    Keyword,
+    Number,
+    /// SQL fragments between backticks
+    Sql,
+    /// Strings along with binary string and JSON literals, without any values
+    /// embedded in them, which are classified on their own
+    String,
+    Symbol,
+}
+
+/// Classifies every token in the input along with where it is, in order, for
+/// highlighting the input in an editor.
+pub fn spans(input: &str) -> Result<Vec<(Span, TokenClass)>, LexError> {
+    let offsets = LineOffsets::new(input);
+    let mut spans = Vec::new();
+
+    classify(&tokenize(input.chars())?, input.len(), input, &offsets, &mut spans);
+
+    Ok(spans)
+}
+
+/// The byte offset of the start of each line, where a carriage return and a
+/// line feed each end a line, just as when lexing.
+struct LineOffsets(Vec<usize>);
+
+impl LineOffsets {
+    fn new(input: &str) -> Self {
+        let ends = input.char_indices().filter(|(_, c)| matches!(c, '\r' | '\n')).map(|(i, _)| i + 1);
+        Self(std::iter::once(0).chain(ends).collect())
+    }
+
+    fn offset(&self, input: &str, position: Position) -> usize {
+        let start = self.0[position.line - 1];
+        input[start..].char_indices().nth(position.column - 1).map_or(input.len(), |(i, _)| start + i)
+    }
+}
+
+/// Classifies the tokens, each of which ends where the next one starts, or else at
+/// the given end, without any whitespace in between.
+fn classify(tokens: &[Token], end: usize, input: &str, offsets: &LineOffsets, spans: &mut Vec<(Span, TokenClass)>) {
+    let starts: Vec<usize> = tokens.iter().map(|t| offsets.offset(input, t.position)).collect();
+
+    for (i, t) in tokens.iter().enumerate() {
+        let start = starts[i];
+        let bound = starts.get(i + 1).copied().unwrap_or(end);
+        let span = Span {
+            start,
+            end: start + input[start..bound].trim_end().len(),
+        };
+        let class = match &t.kind {
+            TokenKind::LineSep => continue,
+            TokenKind::Comment(_) => TokenClass::Comment,
+            TokenKind::Bool(_) | TokenKind::Null => TokenClass::Constant,
+            TokenKind::Identifier(name)
+                if FUNCTIONS.contains(&name.as_str())
+                    && tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::Symbol(Symbol::ParenLeft)) =>
+            {
+                TokenClass::Function
+            }
+            // Only special before a SQL fragment, like a keyword
+            TokenKind::Identifier(name)
+                if name == "volatile"
+                    && tokens.get(i + 1).is_some_and(|t| {
+                        matches!(t.kind, TokenKind::SqlFragment(_) | TokenKind::InterpolatedSqlFragment(_))
+                    }) =>
+            {
+                TokenClass::Keyword
+            }
+            // Only special when declaring a variable, eg. `let password = 'hunter2'`
+            TokenKind::Identifier(name)
+                if name == "let" && tokens.get(i + 2).is_some_and(|t| t.kind == TokenKind::Symbol(Symbol::Equals)) =>
+            {
+                TokenClass::Keyword
+            }
+            // Only special before the name of a record, eg. `abstract base` or `rec1 extends base`
+            TokenKind::Identifier(name)
+                if (name == "abstract" || name == "extends")
+                    && tokens.get(i + 1).is_some_and(|t| {
+                        matches!(t.kind, TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_))
+                    }) =>
+            {
+                TokenClass::Keyword
+            }
+            // Only special at the root of a file, eg. `set search_path app` or `include 'roles.hldr'`
+            TokenKind::Identifier(name)
+                if name == "set"
+                    && tokens.get(i + 1).is_some_and(|t| t.kind == TokenKind::Identifier("search_path".to_owned())) =>
+            {
+                TokenClass::Keyword
+            }
+            TokenKind::Identifier(name)
+                if name == "include" && tokens.get(i + 1).is_some_and(|t| matches!(t.kind, TokenKind::Text(_))) =>
+            {
+                TokenClass::Keyword
+            }
+            TokenKind::Identifier(name)
+                if name == "namespace"
+                    && i.checked_sub(1).is_none_or(|i| tokens[i].kind == TokenKind::LineSep)
+                    && tokens.get(i + 1).is_some_and(|t| matches!(t.kind, TokenKind::Identifier(_))) =>
+            {
+                TokenClass::Keyword
+            }
+            // Only special after the name or alias of a table, eg. `table t1 truncate conflict (id)`
+            TokenKind::Identifier(name)
+                if (name == "conflict" || name == "truncate")
+                    && i.checked_sub(1).is_some_and(|i| {
+                        matches!(tokens[i].kind, TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_))
+                    })
+                    && tokens.get(i + 1).is_some_and(|t| {
+                        t.kind == TokenKind::Symbol(Symbol::ParenLeft) || t.kind == TokenKind::Identifier("conflict".to_owned())
+                    }) =>
+            {
+                TokenClass::Keyword
+            }
+            TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_) | TokenKind::Variable(_) => {
+                TokenClass::Identifier
+            }
+            TokenKind::Keyword(_) => TokenClass::Keyword,
+            TokenKind::Number(_) => TokenClass::Number,
+            TokenKind::SqlFragment(_) => TokenClass::Sql,
+            TokenKind::Hex(_) | TokenKind::Json(_) | TokenKind::Text(_) => TokenClass::String,
+            TokenKind::Symbol(_) => TokenClass::Symbol,
+            TokenKind::InterpolatedText(parts) => {
+                classify_text(parts, TokenClass::String, span, input, offsets, spans);
+                continue;
+            }
+            TokenKind::InterpolatedSqlFragment(parts) => {
+                classify_text(parts, TokenClass::Sql, span, input, offsets, spans);
+                continue;
+            }
+        };
+
+        spans.push((span, class));
+    }
+}
+
+/// Classifies the parts of a string or SQL fragment around any values embedded in it,
+/// with the braces around each value as part of the string or fragment.
+fn classify_text(
+    parts: &[TextPart],
+    class: TokenClass,
+    span: Span,
+    input: &str,
+    offsets: &LineOffsets,
+    spans: &mut Vec<(Span, TokenClass)>,
+) {
+    let mut start = span.start;
+
+    for part in parts {
+        let tokens = match part {
+            TextPart::Embedded(tokens) => tokens,
+            TextPart::Literal(_) => continue,
+        };
+        let value_start = offsets.offset(input, tokens[0].position);
+        let last_start = offsets.offset(input, tokens[tokens.len() - 1].position);
+
+        // Embedded values are references or function calls, so the closing brace is
+        // the first one after the start of the last token
+        let value_end = input[last_start..span.end].find('}').map_or(span.end, |i| last_start + i);
+
+        spans.push((Span { start, end: value_start }, class));
+        classify(tokens, value_end, input, offsets, spans);
+        start = value_end;
+    }
+
+    spans.push((Span { start, end: span.end }, class));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn classes(input: &str) -> Vec<(&str, TokenClass)> {
+        spans(input)
+            .unwrap()
+            .into_iter()
+            .map(|(span, class)| (&input[span.start..span.end], class))
+            .collect()
+    }
+
+    #[test]
+    fn test_spans() {
+        use TokenClass::*;
+
+        let input = "-- people 💝\r\ntable person as p (\n  kevin ( id uuid(), ok t, n null, age -3.5, at volatile `now()` ) -- boss\n  \"Bob\" ( bin x'ff', doc json{ \"a\": 1 }, now 1 )\n)";
+
+        assert_eq!(
+            classes(input),
+            vec![
+                ("-- people 💝", Comment),
+                ("table", Keyword),
+                ("person", Identifier),
+                ("as", Keyword),
+                ("p", Identifier),
+                ("(", Symbol),
+                ("kevin", Identifier),
+                ("(", Symbol),
+                ("id", Identifier),
+                ("uuid", Function),
+                ("(", Symbol),
+                (")", Symbol),
+                (",", Symbol),
+                ("ok", Identifier),
+                ("t", Constant),
+                (",", Symbol),
+                ("n", Identifier),
+                ("null", Constant),
+                (",", Symbol),
+                ("age", Identifier),
+                ("-3.5", Number),
+                (",", Symbol),
+                ("at", Identifier),
+                ("volatile", Keyword),
+                ("`now()`", Sql),
+                (")", Symbol),
+                ("-- boss", Comment),
+                ("\"Bob\"", Identifier),
+                ("(", Symbol),
+                ("bin", Identifier),
+                ("x'ff'", String),
+                (",", Symbol),
+                ("doc", Identifier),
+                ("json{ \"a\": 1 }", String),
+                (",", Symbol),
+                ("now", Identifier),
+                ("1", Number),
+                (")", Symbol),
+                (")", Symbol),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_interpolated_text_spans() {
+        use TokenClass::*;
+
+        let input = "( email 'it''s {@p.name}-{uuid5('a', 'b')}@x.dev' )";
+
+        assert_eq!(
+            classes(input),
+            vec![
+                ("(", Symbol),
+                ("email", Identifier),
+                ("'it''s {", String),
+                ("@", Symbol),
+                ("p", Identifier),
+                (".", Symbol),
+                ("name", Identifier),
+                ("}-{", String),
+                ("uuid5", Function),
+                ("(", Symbol),
+                ("'a'", String),
+                (",", Symbol),
+                ("'b'", String),
+                (")", Symbol),
+                ("}@x.dev'", String),
+                (")", Symbol),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_variable_spans() {
+        use TokenClass::*;
+
+        let input = "let pw = 'hunter2'\n( let $pw )";
+
+        assert_eq!(
+            classes(input),
+            vec![
+                ("let", Keyword),
+                ("pw", Identifier),
+                ("=", Symbol),
+                ("'hunter2'", String),
+                ("(", Symbol),
+                ("let", Identifier),
+                ("$pw", Identifier),
+                (")", Symbol),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_contextual_keyword_spans() {
+        use TokenClass::*;
+
+        let input = "namespace billing\ninclude 'roles.hldr'\nset search_path app\ntable conflict truncate conflict (id) ()\nabstract base ()\nrec1 extends base ( abstract t )";
+
+        assert_eq!(
+            classes(input),
+            vec![
+                ("namespace", Keyword),
+                ("billing", Identifier),
+                ("include", Keyword),
+                ("'roles.hldr'", String),
+                ("set", Keyword),
+                ("search_path", Identifier),
+                ("app", Identifier),
+                ("table", Keyword),
+                ("conflict", Identifier),
+                ("truncate", Keyword),
+                ("conflict", Keyword),
+                ("(", Symbol),
+                ("id", Identifier),
+                (")", Symbol),
+                ("(", Symbol),
+                (")", Symbol),
+                ("abstract", Keyword),
+                ("base", Identifier),
+                ("(", Symbol),
+                (")", Symbol),
+                ("rec1", Identifier),
+                ("extends", Keyword),
+                ("base", Identifier),
+                ("(", Symbol),
+                ("abstract", Identifier),
+                ("t", Constant),
+                (")", Symbol),
+            ],
+        );
+    }
+}
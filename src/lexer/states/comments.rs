@@ -2,23 +2,39 @@ use crate::lexer::tokens::{Token, TokenKind};
 use crate::lexer::prelude::*;
 use super::start::Start;
 
-/// State after receiving double-dashes.
+/// State after receiving double-dashes, along with the text of the comment so far.
 #[derive(Debug, PartialEq)]
-pub struct InComment;
+pub struct InComment(pub Stack);
 
 impl State for InComment {
     fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let mut stack = self.0;
+
         match c {
             Some(c) if is_newline(c) => {
+                add_comment(ctx, stack);
                 let kind = TokenKind::LineSep;
                 ctx.add_token(Token { kind, position: ctx.current_position });
                 to(Start)
             }
-            _ => to(InComment),
+            Some(c) => {
+                stack.push(c);
+                to(InComment(stack))
+            }
+            None => {
+                add_comment(ctx, stack);
+                to(Start)
+            }
         }
     }
 }
 
+fn add_comment(ctx: &mut Context, stack: Stack) {
+    let position = stack.start_position;
+    let kind = TokenKind::Comment(stack.consume());
+    ctx.add_token(Token { kind, position });
+}
+
 #[cfg(test)]
 mod comments_tests {
     use std::any::TypeId;
@@ -28,16 +44,26 @@ mod comments_tests {
     use crate::lexer::tokens::{Token, TokenKind};
     use super::*;
 
+    fn comment(text: &str) -> Box<InComment> {
+        let mut stack = Stack::new(Position { line: 1, column: 1 }, None);
+        text.chars().for_each(|c| stack.push(c));
+        Box::new(InComment(stack))
+    }
+
     #[test]
     fn test_newlines() {
         for (line, column) in [(1, 1), (2, 3), (5, 8)] {
             for c in ['\r', '\n'] {
                 let mut ctx = Context::default();
                 ctx.current_position = Position { line, column };
-                let state = Box::new(InComment).receive(&mut ctx, Some(c)).unwrap();
+                let state = comment(" note").receive(&mut ctx, Some(c)).unwrap();
 
                 assert!((*state).type_id() == TypeId::of::<Start>());
                 assert_eq!(ctx.into_tokens(), vec![
+                    Token {
+                        kind: TokenKind::Comment(" note".to_owned()),
+                        position: Position { line: 1, column: 1 },
+                    },
                     Token {
                         kind: TokenKind::LineSep,
                         position: Position { line, column },
@@ -49,9 +75,9 @@ mod comments_tests {
 
     #[test]
     fn test_others() {
-        for c in ['a', '1', ' ', '\t', '\0'] {
+        for c in ['a', '1', ' ', '\t', '\0', '-'] {
             let mut ctx = Context::default();
-            let state = Box::new(InComment).receive(&mut ctx, Some(c)).unwrap();
+            let state = comment(" note").receive(&mut ctx, Some(c)).unwrap();
 
             assert!((*state).type_id() == TypeId::of::<InComment>());
             assert_eq!(Context::default(), ctx);
@@ -61,10 +87,14 @@ mod comments_tests {
     #[test]
     fn test_none() {
         let mut ctx = Context::default();
-        let state = Box::new(InComment).receive(&mut ctx, None).unwrap();
-
-        assert!((*state).type_id() == TypeId::of::<InComment>());
-        assert_eq!(Context::default(), ctx);
+        let state = comment(" note").receive(&mut ctx, None).unwrap();
 
+        assert!((*state).type_id() == TypeId::of::<Start>());
+        assert_eq!(ctx.into_tokens(), vec![
+            Token {
+                kind: TokenKind::Comment(" note".to_owned()),
+                position: Position { line: 1, column: 1 },
+            },
+        ]);
     }
 }
@@ -1,7 +1,9 @@
 use crate::lexer::error::{LexError, LexErrorKind};
 use crate::lexer::tokens::{Keyword, Symbol, Token, TokenKind};
 use crate::lexer::prelude::*;
+use crate::Position;
 use super::start::Start;
+use super::text::InEscapeText;
 
 /// State after receiving a valid identifier character.
 #[derive(Debug)]
@@ -16,6 +18,29 @@ impl State for InIdentifier {
                 stack.push(c);
                 to(InIdentifier(stack))
             }
+            // The label of an anonymous record, eg. `_#3`, is read as an identifier
+            Some('#') if stack.as_str() == "_" => {
+                stack.push('#');
+                to(InIdentifier(stack))
+            }
+            // A quote directly after `x` starts a binary string, as in Postgres
+            Some('\'') if matches!(stack.as_str(), "x" | "X") => {
+                to(InHex(stack.start_position, String::new()))
+            }
+            // A quote directly after `e` starts an escape string, as in Postgres
+            Some('\'') if matches!(stack.as_str(), "e" | "E") => {
+                to(InEscapeText { position: stack.start_position, text: String::new(), escaped: false })
+            }
+            Some(c @ ('{' | '[')) if stack.as_str() == "json" => {
+                let json = InJson {
+                    position: stack.start_position,
+                    text: String::from(c),
+                    depth: 1,
+                    in_string: false,
+                    escaped: false,
+                };
+                to(json)
+            }
             _ => {
                 let position = stack.start_position;
                 let kind = identifier_to_token_kind(stack.consume());
@@ -26,6 +51,144 @@ impl State for InIdentifier {
     }
 }
 
+/// State inside a JSON literal after `json`, until the object or array is closed.
+/// Brackets and braces are only counted outside of JSON strings, and the JSON is
+/// only validated once closed.
+#[derive(Debug)]
+pub(super) struct InJson {
+    /// Where the literal started, including the `json`
+    position: Position,
+    text: String,
+    /// How many objects and arrays are open
+    depth: usize,
+    in_string: bool,
+    /// Whether the previous character in a string was an unescaped backslash
+    escaped: bool,
+}
+
+impl State for InJson {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let InJson { position, mut text, mut depth, mut in_string, mut escaped } = *self;
+
+        let c = match c {
+            Some(c) => c,
+            None => return Err(LexError {
+                kind: LexErrorKind::UnexpectedEOF,
+                position: ctx.current_position,
+            }),
+        };
+        text.push(c);
+
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else {
+            match c {
+                '"' => in_string = true,
+                '{' | '[' => depth += 1,
+                '}' | ']' => depth -= 1,
+                _ => {}
+            }
+        }
+
+        if depth > 0 {
+            return to(InJson { position, text, depth, in_string, escaped });
+        }
+
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(&text) {
+            // Errors are positioned within the JSON, which starts after the `json`
+            let column = if e.line() == 1 { position.column + 3 + e.column() } else { e.column() };
+
+            return Err(LexError {
+                kind: LexErrorKind::InvalidJson(json_error_reason(&e)),
+                position: Position { line: position.line + e.line() - 1, column },
+            });
+        }
+
+        ctx.add_token(Token { kind: TokenKind::Json(text), position });
+        to(Start)
+    }
+}
+
+/// Describes the JSON error without the line and column that serde appends to it.
+fn json_error_reason(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_owned(),
+        None => message,
+    }
+}
+
+/// State inside a binary string literal, along with where it started and
+/// the hex digits received so far.
+#[derive(Debug)]
+pub(super) struct InHex(pub Position, pub String);
+
+impl State for InHex {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let InHex(position, mut digits) = *self;
+
+        match c {
+            Some(c) if c.is_ascii_hexdigit() => {
+                digits.push(c.to_ascii_lowercase());
+                to(InHex(position, digits))
+            }
+            Some('\'') if digits.len() % 2 == 0 => {
+                ctx.add_token(Token { kind: TokenKind::Hex(digits), position });
+                to(Start)
+            }
+            Some('\'') => Err(LexError {
+                kind: LexErrorKind::InvalidHexLiteral(digits),
+                position,
+            }),
+            Some(c) => Err(LexError {
+                kind: LexErrorKind::UnexpectedCharacter(c),
+                position: ctx.current_position,
+            }),
+            None => Err(LexError {
+                kind: LexErrorKind::UnclosedString,
+                position: ctx.current_position,
+            }),
+        }
+    }
+}
+
+/// State after receiving a `$`, which begins either a keyword such as `$defaults` or
+/// a variable such as `$password`, since Postgres identifiers cannot begin with one.
+#[derive(Debug)]
+pub(super) struct InDollarKeyword(pub Stack);
+
+impl State for InDollarKeyword {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let mut stack = self.0;
+
+        match c {
+            Some(c) if is_identifier_char(c) => {
+                stack.push(c);
+                to(InDollarKeyword(stack))
+            }
+            _ => {
+                let position = stack.start_position;
+                let kind = match stack.consume().as_ref() {
+                    "$columns" => TokenKind::Keyword(Keyword::Columns),
+                    "$defaults" => TokenKind::Keyword(Keyword::Defaults),
+                    "$import" => TokenKind::Keyword(Keyword::Import),
+                    s if s[1..].starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                        TokenKind::Variable(s[1..].to_owned())
+                    }
+                    s => return Err(LexError {
+                        kind: LexErrorKind::UnknownKeyword(s.to_owned()),
+                        position,
+                    }),
+                };
+                ctx.add_token(Token { kind, position });
+                defer_to(Start, ctx, c)
+            }
+        }
+    }
+}
+
 /// State after receiving a valid identifier character.
 #[derive(Debug)]
 pub(super) struct InQuotedIdentifier(pub Stack);
@@ -59,9 +222,9 @@ pub(super) struct AfterQuotedIdentifier(pub Stack);
 impl State for AfterQuotedIdentifier {
     fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
         let mut stack = self.0;
-        stack.push('"');
 
         match c {
+            // An escaped quote is kept as a single quote, unlike the surrounding ones
             Some('"') => {
                 stack.push('"');
                 to(InQuotedIdentifier(stack))
@@ -83,6 +246,7 @@ fn identifier_to_token_kind(s: String) -> TokenKind {
         "_" => TokenKind::Symbol(Symbol::Underscore),
         "true" | "t" => TokenKind::Bool(true),
         "false" | "f" => TokenKind::Bool(false),
+        "null" => TokenKind::Null,
         "as" => TokenKind::Keyword(Keyword::As),
         "schema" => TokenKind::Keyword(Keyword::Schema),
         "table" => TokenKind::Keyword(Keyword::Table),
@@ -113,7 +277,7 @@ mod identifiers_tests {
         #[test]
         fn test_receive_whitespace() {
             let mut ctx = Context::default();
-            let mut stack = Stack::new(Position { line: 2, column: 3}, 'x');
+            let mut stack = Stack::new(Position { line: 2, column: 3}, Some('x'));
             stack.push('y');
             stack.push('z');
 
@@ -140,7 +304,7 @@ mod identifiers_tests {
                 let mut ctx = Context::default();
                 ctx.current_position = Position { line: 1, column: 3};
 
-                let mut stack = Stack::new(Position { line: 1, column: 1}, 'a');
+                let mut stack = Stack::new(Position { line: 1, column: 1}, Some('a'));
                 stack.push('b');
                 stack.push('c');
 
@@ -163,7 +327,7 @@ mod identifiers_tests {
         #[test]
         fn test_receive_none() {
             let mut ctx = Context::default();
-            let mut stack = Stack::new(Position { line: 2, column: 3}, 'x');
+            let mut stack = Stack::new(Position { line: 2, column: 3}, Some('x'));
             stack.push('y');
             stack.push('z');
 
@@ -179,6 +343,76 @@ mod identifiers_tests {
         }
     }
 
+    mod in_dollar_keyword_tests {
+        use pretty_assertions::assert_eq;
+        use super::*;
+
+        #[test]
+        fn test_receive_defaults() {
+            let mut ctx = Context::default();
+            let mut stack = Stack::new(Position { line: 2, column: 3}, Some('$'));
+            for c in "defaults".chars() {
+                stack.push(c);
+            }
+
+            let state = Box::new(InDollarKeyword(stack)).receive(&mut ctx, Some('(')).unwrap();
+
+            assert!((*state).type_id() == TypeId::of::<Start>());
+            assert_eq!(ctx.into_tokens(), vec![
+                Token {
+                    kind: TokenKind::Keyword(Keyword::Defaults),
+                    position: Position { line: 2, column: 3 },
+                },
+                Token {
+                    kind: TokenKind::Symbol(Symbol::ParenLeft),
+                    position: Position { line: 1, column: 1 },
+                },
+            ]);
+        }
+
+        #[test]
+        fn test_receive_variable() {
+            for (input, name) in [("$default", "default"), ("$DEFAULTS", "DEFAULTS"), ("$_pw2", "_pw2")] {
+                let mut ctx = Context::default();
+                let mut chars = input.chars();
+                let mut stack = Stack::new(Position { line: 2, column: 3}, chars.next());
+                for c in chars {
+                    stack.push(c);
+                }
+
+                let state = Box::new(InDollarKeyword(stack)).receive(&mut ctx, None).unwrap();
+
+                assert!((*state).type_id() == TypeId::of::<Start>());
+                assert_eq!(ctx.into_tokens(), vec![
+                    Token {
+                        kind: TokenKind::Variable(name.to_owned()),
+                        position: Position { line: 2, column: 3 },
+                    },
+                ]);
+            }
+        }
+
+        #[test]
+        fn test_receive_unknown() {
+            for input in ["$", "$1", "$2fa"] {
+                let mut ctx = Context::default();
+                let mut chars = input.chars();
+                let mut stack = Stack::new(Position { line: 2, column: 3}, chars.next());
+                for c in chars {
+                    stack.push(c);
+                }
+
+                assert_eq!(
+                    Box::new(InDollarKeyword(stack)).receive(&mut ctx, None).unwrap_err(),
+                    LexError {
+                        kind: LexErrorKind::UnknownKeyword(input.to_owned()),
+                        position: Position { line: 2, column: 3 },
+                    },
+                );
+            }
+        }
+    }
+
     mod in_quoted_identifier_tests {
         use pretty_assertions::assert_eq;
         use super::*;
@@ -206,7 +440,7 @@ mod identifiers_tests {
         #[test]
         fn test_receive_whitespace() {
             let mut ctx = Context::default();
-            let mut stack = Stack::new(Position { line: 2, column: 3}, 'x');
+            let mut stack = Stack::new(Position { line: 2, column: 3}, Some('x'));
             stack.push('y');
             stack.push('z');
 
@@ -220,7 +454,7 @@ mod identifiers_tests {
         fn test_receive_terminators() {
             for c in ['\r', '\n', ',', '(', ')'] {
                 let mut ctx = Context::default();
-                let mut stack = Stack::new(Position { line: 1, column: 1}, 'a');
+                let mut stack = Stack::new(Position { line: 1, column: 1}, Some('a'));
                 stack.push('b');
                 stack.push('c');
 
@@ -268,7 +502,7 @@ mod identifiers_tests {
         #[test]
         fn test_receive_whitespace() {
             let mut ctx = Context::default();
-            let mut stack = Stack::new(Position { line: 2, column: 3}, 'x');
+            let mut stack = Stack::new(Position { line: 2, column: 3}, Some('x'));
             stack.push('y');
             stack.push('z');
 
@@ -277,8 +511,7 @@ mod identifiers_tests {
             assert!((*state).type_id() == TypeId::of::<Start>());
             assert_eq!(ctx.into_tokens(), vec![
                 Token {
-                    // FIXME: Remove doublequotes from quoted identifiers
-                    kind: TokenKind::QuotedIdentifier("xyz\"".to_owned()),
+                    kind: TokenKind::QuotedIdentifier("xyz".to_owned()),
                     position: Position { line: 2, column: 3 },
                 }
             ]);
@@ -296,7 +529,7 @@ mod identifiers_tests {
                 let mut ctx = Context::default();
                 ctx.current_position = Position { line: 1, column: 3};
 
-                let mut stack = Stack::new(Position { line: 1, column: 1}, 'a');
+                let mut stack = Stack::new(Position { line: 1, column: 1}, Some('a'));
                 stack.push('b');
                 stack.push('c');
 
@@ -374,9 +607,17 @@ mod identifiers_tests {
             }
         }
 
+        #[test]
+        fn test_null() {
+            assert_eq!(
+                identifier_to_token_kind("null".to_owned()),
+                TokenKind::Null,
+            );
+        }
+
         #[test]
         fn test_anything_else() {
-            for ident in ["__", "True", "FALSE", "_something", "12345", "!@#$"] {
+            for ident in ["__", "True", "FALSE", "NULL", "_something", "12345", "!@#$", "abstract", "conflict", "extends", "include", "namespace", "set", "truncate"] {
                 assert_eq!(
                     identifier_to_token_kind(ident.to_owned()),
                     TokenKind::Identifier(ident.to_owned()),
@@ -30,6 +30,11 @@ impl State for InFloat {
                 stack.push(c);
                 to(InFloat(stack))
             }
+            // Postgres allows exponents after decimal points, eg. `1.e3`, but not `.e3`
+            Some(c @ ('e' | 'E')) if stack.top() != Some('_') && has_digits(&stack) => {
+                stack.push(c);
+                to(InExponent(stack))
+            }
             None | Some(_) if can_terminate(c) => match stack.top() {
                 Some('_') => Err(LexError {
                     position: stack.start_position,
@@ -37,7 +42,7 @@ impl State for InFloat {
                 }),
                 _ => {
                     let position = stack.start_position;
-                    let kind = TokenKind::Number(stack.consume());
+                    let kind = TokenKind::Number(without_underscores(stack.consume()));
                     ctx.add_token(Token { kind, position });
                     defer_to(Start, ctx, c)
                 }
@@ -77,6 +82,14 @@ impl State for InInteger {
                 stack.push(c);
                 to(InFloat(stack))
             }
+            Some(c @ ('e' | 'E')) if stack.top() != Some('_') => {
+                stack.push(c);
+                to(InExponent(stack))
+            }
+            Some(c @ ('b' | 'B' | 'o' | 'O' | 'x' | 'X')) if matches!(stack.as_str(), "0" | "-0") => {
+                stack.push(c);
+                to(InRadixInteger(stack, radix(c)))
+            }
             None | Some(_) if can_terminate(c) => match stack.top() {
                 Some('_') => Err(LexError {
                     position: stack.start_position,
@@ -84,7 +97,7 @@ impl State for InInteger {
                 }),
                 _ => {
                     let position = stack.start_position;
-                    let kind = TokenKind::Number(stack.consume());
+                    let kind = TokenKind::Number(without_underscores(stack.consume()));
                     ctx.add_token(Token { kind, position });
                     defer_to(Start, ctx, c)
                 }
@@ -98,9 +111,137 @@ impl State for InInteger {
     }
 }
 
+/// State after receiving the `e` of an exponent, eg. `1.5e-3`, which may be followed
+/// by a sign and must be followed by at least one digit.
+#[derive(Debug)]
+pub(super) struct InExponent(pub Stack);
+
+impl State for InExponent {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        use LexErrorKind::{InvalidNumericLiteral, UnexpectedCharacter};
+
+        let mut stack = self.0;
+
+        match c {
+            Some(c @ ('+' | '-')) if matches!(stack.top(), Some('e' | 'E')) => {
+                stack.push(c);
+                to(InExponent(stack))
+            }
+            Some(c @ '0'..='9') => {
+                stack.push(c);
+                to(InExponent(stack))
+            }
+            // Underscores can only be between digits of the exponent
+            Some('_') if matches!(stack.top(), Some('0'..='9')) => {
+                stack.push('_');
+                to(InExponent(stack))
+            }
+            None | Some(_) if can_terminate(c) => match stack.top() {
+                Some('0'..='9') => {
+                    let position = stack.start_position;
+                    let kind = TokenKind::Number(without_underscores(stack.consume()));
+                    ctx.add_token(Token { kind, position });
+                    defer_to(Start, ctx, c)
+                }
+                _ => Err(LexError {
+                    position: stack.start_position,
+                    kind: InvalidNumericLiteral(stack.consume()),
+                }),
+            },
+            Some(c) => Err(LexError {
+                kind: UnexpectedCharacter(c),
+                position: ctx.current_position,
+            }),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// State after receiving the prefix of a binary, octal, or hexadecimal integer, eg. `0x1F`,
+/// along with its radix. Since only newer versions of Postgres accept these, they are
+/// converted to decimal integers.
+#[derive(Debug)]
+pub(super) struct InRadixInteger(pub Stack, pub u32);
+
+impl State for InRadixInteger {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        use LexErrorKind::{InvalidNumericLiteral, UnexpectedCharacter};
+
+        let InRadixInteger(mut stack, radix) = *self;
+
+        match c {
+            Some(c) if c.is_digit(radix) => {
+                stack.push(c);
+                to(InRadixInteger(stack, radix))
+            }
+            // Underscores cannot be consecutive, though one can follow the prefix
+            Some('_') if stack.top() != Some('_') => {
+                stack.push('_');
+                to(InRadixInteger(stack, radix))
+            }
+            None | Some(_) if can_terminate(c) => {
+                let position = stack.start_position;
+                let literal = stack.consume();
+
+                match radix_to_decimal(&literal, radix) {
+                    Some(n) => {
+                        ctx.add_token(Token { kind: TokenKind::Number(n), position });
+                        defer_to(Start, ctx, c)
+                    }
+                    None => Err(LexError {
+                        position,
+                        kind: InvalidNumericLiteral(literal),
+                    }),
+                }
+            }
+            Some(c) => Err(LexError {
+                kind: UnexpectedCharacter(c),
+                position: ctx.current_position,
+            }),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn radix(prefix: char) -> u32 {
+    match prefix.to_ascii_lowercase() {
+        'b' => 2,
+        'o' => 8,
+        _ => 16,
+    }
+}
+
+/// Converts an integer literal with a radix prefix, eg. `-0x1F`, to a decimal integer,
+/// unless it has no digits, ends with an underscore, or is too large.
+fn radix_to_decimal(literal: &str, radix: u32) -> Option<String> {
+    let (sign, unsigned) = match literal.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", literal),
+    };
+    let digits = &unsigned[2..];
+
+    if digits.ends_with('_') {
+        return None;
+    }
+
+    let n = u128::from_str_radix(&digits.replace('_', ""), radix).ok()?;
+    Some(format!("{}{}", sign, n))
+}
+
+/// Removes the underscores that a number is formatted with, eg. `1_000`, since only newer
+/// versions of Postgres accept them, just as integers with radix prefixes are converted.
+fn without_underscores(literal: String) -> String {
+    literal.replace('_', "")
+}
+
+/// Whether the number received so far has at least one digit.
+fn has_digits(stack: &Stack) -> bool {
+    stack.as_str().chars().any(|c| c.is_ascii_digit())
+}
+
 fn can_terminate(c: Option<char>) -> bool {
     c.is_none()
-        || matches!(c, Some(')'))
+        || matches!(c, Some(')' | '*' | '+' | ',' | '/' | '|'))
         || matches!(c, Some(c) if is_whitespace(c) || is_newline(c))
 }
 
@@ -116,7 +257,7 @@ mod numbers_tests {
         #[test]
         fn test_digit_after_digit() {
             let mut ctx = Context::default();
-            let stack = Stack::new(Position::default(), '6');
+            let stack = Stack::new(Position::default(), Some('6'));
 
             let state = Box::new(InInteger(stack)).receive(&mut ctx, Some('7')).unwrap();
 
@@ -127,7 +268,7 @@ mod numbers_tests {
         #[test]
         fn test_underscore_after_digit() {
             let mut ctx = Context::default();
-            let stack = Stack::new(Position::default(), '9');
+            let stack = Stack::new(Position::default(), Some('9'));
 
             let state = Box::new(InInteger(stack)).receive(&mut ctx, Some('_')).unwrap();
 
@@ -138,7 +279,7 @@ mod numbers_tests {
         #[test]
         fn test_period_after_digit() {
             let mut ctx = Context::default();
-            let stack = Stack::new(Position::default(), '9');
+            let stack = Stack::new(Position::default(), Some('9'));
 
             let state = Box::new(InInteger(stack)).receive(&mut ctx, Some('.')).unwrap();
 
@@ -149,7 +290,7 @@ mod numbers_tests {
         #[test]
         fn test_underscore_after_underscore() {
             let mut ctx = Context::new(Position { line: 9, column: 10 }, None);
-            let stack = Stack::new(Position::default(), '_');
+            let stack = Stack::new(Position::default(), Some('_'));
 
             let err = Box::new(InInteger(stack)).receive(&mut ctx, Some('_')).err().unwrap();
 
@@ -166,7 +307,7 @@ mod numbers_tests {
         #[test]
         fn test_period_after_underscore() {
             let mut ctx = Context::new(Position { line: 9, column: 10 }, None);
-            let stack = Stack::new(Position::default(), '_');
+            let stack = Stack::new(Position::default(), Some('_'));
 
             let err = Box::new(InInteger(stack)).receive(&mut ctx, Some('.')).err().unwrap();
 
@@ -182,6 +323,91 @@ mod numbers_tests {
     }
 
     mod in_float_tests {
+        use super::*;
+
+        #[test]
+        fn test_exponent_after_digit() {
+            let mut ctx = Context::default();
+            let stack = Stack::new(Position::default(), Some('.'));
+            let state = Box::new(InFloat(stack)).receive(&mut ctx, Some('5')).unwrap();
+
+            let state = state.receive(&mut ctx, Some('e')).unwrap();
+
+            assert!((*state).type_id() == TypeId::of::<InExponent>());
+            assert_eq!(Context::default(), ctx);
+        }
+
+        #[test]
+        fn test_exponent_without_digits() {
+            let mut ctx = Context::new(Position { line: 9, column: 10 }, None);
+            let stack = Stack::new(Position::default(), Some('.'));
+
+            let err = Box::new(InFloat(stack)).receive(&mut ctx, Some('e')).err().unwrap();
+
+            assert_eq!(
+                LexError {
+                    kind: LexErrorKind::UnexpectedCharacter('e'),
+                    position: Position { line: 9, column: 10 },
+                },
+                err,
+            );
+        }
+    }
+
+    mod in_exponent_tests {
+        use super::*;
 
+        #[test]
+        fn test_sign_after_e() {
+            let mut ctx = Context::default();
+            let mut stack = Stack::new(Position::default(), Some('1'));
+            stack.push('e');
+
+            let state = Box::new(InExponent(stack)).receive(&mut ctx, Some('-')).unwrap();
+
+            assert!((*state).type_id() == TypeId::of::<InExponent>());
+            assert_eq!(Context::default(), ctx);
+        }
+
+        #[test]
+        fn test_sign_after_digit() {
+            let mut ctx = Context::new(Position { line: 9, column: 10 }, None);
+            let mut stack = Stack::new(Position::default(), Some('1'));
+            stack.push('e');
+            stack.push('5');
+
+            let err = Box::new(InExponent(stack)).receive(&mut ctx, Some('-')).err().unwrap();
+
+            assert_eq!(
+                LexError {
+                    kind: LexErrorKind::UnexpectedCharacter('-'),
+                    position: Position { line: 9, column: 10 },
+                },
+                err,
+            );
+        }
+    }
+
+    mod in_radix_integer_tests {
+        use super::*;
+
+        #[test]
+        fn test_prefix_after_zero() {
+            let mut ctx = Context::default();
+            let stack = Stack::new(Position::default(), Some('0'));
+
+            let state = Box::new(InInteger(stack)).receive(&mut ctx, Some('x')).unwrap();
+
+            assert!((*state).type_id() == TypeId::of::<InRadixInteger>());
+            assert_eq!(Context::default(), ctx);
+        }
+
+        #[test]
+        fn test_radix_to_decimal() {
+            assert_eq!(radix_to_decimal("0xff", 16), Some("255".to_owned()));
+            assert_eq!(radix_to_decimal("-0b_10", 2), Some("-2".to_owned()));
+            assert_eq!(radix_to_decimal("0o", 8), None);
+            assert_eq!(radix_to_decimal("0o7_", 8), None);
+        }
     }
 }
@@ -1,25 +1,30 @@
 use crate::lexer::error::{LexError, LexErrorKind};
-use crate::lexer::tokens::{Token, TokenKind};
+use crate::lexer::tokens::{TextPart, Token, TokenKind};
 use crate::lexer::prelude::*;
+use crate::lexer::tokenize_from;
+use crate::Position;
 use super::start::Start;
+use std::mem;
 
-/// State after receiving a backtick.
+/// State after receiving a backtick, along with the parts of the SQL select statement
+/// before any references embedded in it.
 #[derive(Debug)]
-pub(super) struct InSqlSelect(pub Stack);
+pub(super) struct InSqlSelect(pub Stack, pub Vec<TextPart>);
 
 impl State for InSqlSelect {
     fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
         use LexErrorKind::UnclosedString;
 
-        let mut stack = self.0;
+        let InSqlSelect(mut stack, parts) = *self;
 
         match c {
             Some('`') => {
-                to(AfterSqlSelect(stack))
+                to(AfterSqlSelect(stack, parts))
             }
+            Some('{') => to(AfterSqlBrace(stack, parts)),
             Some(c) => {
                 stack.push(c);
-                to(InSqlSelect(stack))
+                to(InSqlSelect(stack, parts))
             }
             None => Err(LexError {
                 kind: UnclosedString,
@@ -33,11 +38,11 @@ impl State for InSqlSelect {
 /// character received is another backtick, which indicates the previous
 /// backtick was being escaped and is part of the SQL select statement.
 #[derive(Debug)]
-pub(super) struct AfterSqlSelect(pub Stack);
+pub(super) struct AfterSqlSelect(pub Stack, pub Vec<TextPart>);
 
 impl State for AfterSqlSelect {
     fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
-        let mut stack = self.0;
+        let AfterSqlSelect(mut stack, mut parts) = *self;
 
         match c {
             Some('`') => {
@@ -46,14 +51,88 @@ impl State for AfterSqlSelect {
                 // since text strings and quoted identifiers have to remain properly escaped
                 // when passing to the database
                 stack.push('`');
-                to(InSqlSelect(stack))
+                to(InSqlSelect(stack, parts))
             }
             _ => {
                 let position = stack.start_position;
-                let kind = TokenKind::SqlFragment(stack.consume());
+                let kind = if parts.is_empty() {
+                    TokenKind::SqlFragment(stack.consume())
+                } else {
+                    split_literal(&mut stack, &mut parts);
+                    TokenKind::InterpolatedSqlFragment(parts)
+                };
                 ctx.add_token(Token { kind, position });
                 defer_to(Start, ctx, c)
             }
         }
     }
 }
+
+/// State after receiving an opening brace in a SQL select statement, which embeds a
+/// reference if followed by `@`, eg. `{@project1.id}`, and is otherwise part of the SQL.
+#[derive(Debug)]
+pub(super) struct AfterSqlBrace(pub Stack, pub Vec<TextPart>);
+
+impl State for AfterSqlBrace {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let AfterSqlBrace(mut stack, mut parts) = *self;
+
+        match c {
+            Some('@') => {
+                split_literal(&mut stack, &mut parts);
+                let position = ctx.current_position;
+                to(InSqlValue { stack, parts, value: String::from('@'), position, quoted: false })
+            }
+            _ => {
+                stack.push('{');
+                defer_to(InSqlSelect(stack, parts), ctx, c)
+            }
+        }
+    }
+}
+
+/// State inside a reference embedded in a SQL select statement, until the closing brace.
+#[derive(Debug)]
+pub(super) struct InSqlValue {
+    stack: Stack,
+    parts: Vec<TextPart>,
+    value: String,
+    /// Where the value started
+    position: Position,
+    /// Whether the value is inside a quoted identifier
+    quoted: bool,
+}
+
+impl State for InSqlValue {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        use LexErrorKind::UnclosedString;
+
+        let InSqlValue { stack, mut parts, mut value, position, quoted } = *self;
+
+        match c {
+            Some('}') if !quoted => {
+                let tokens = tokenize_from(value.chars(), position)?;
+                parts.push(TextPart::Embedded(tokens));
+                to(InSqlSelect(stack, parts))
+            }
+            Some(c) => {
+                value.push(c);
+                let quoted = if c == '"' { !quoted } else { quoted };
+                to(InSqlValue { stack, parts, value, position, quoted })
+            }
+            None => Err(LexError {
+                kind: UnclosedString,
+                position: ctx.current_position,
+            }),
+        }
+    }
+}
+
+/// Moves the SQL received so far into its own part.
+fn split_literal(stack: &mut Stack, parts: &mut Vec<TextPart>) {
+    let sql = mem::replace(stack, Stack::new(stack.start_position, None)).consume();
+
+    if !sql.is_empty() {
+        parts.push(TextPart::Literal(sql));
+    }
+}
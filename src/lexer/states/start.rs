@@ -1,10 +1,10 @@
 use crate::lexer::error::{LexError, LexErrorKind};
 use crate::lexer::tokens::{Symbol, Token, TokenKind};
 use crate::lexer::prelude::*;
-use super::identifiers::{InIdentifier, InQuotedIdentifier};
+use super::identifiers::{InDollarKeyword, InIdentifier, InQuotedIdentifier};
 use super::numbers::InInteger;
 use super::sql::InSqlSelect;
-use super::symbols::{AfterPeriod, AfterSingleDash};
+use super::symbols::{AfterColon, AfterPeriod, AfterPipe, AfterSingleDash};
 use super::text::InText;
 
 
@@ -38,6 +38,26 @@ impl State for Start {
                 ctx.add_token(Token { kind, position: ctx.current_position });
                 to(Start)
             }
+            '*' => {
+                let kind = TokenKind::Symbol(Symbol::Asterisk);
+                ctx.add_token(Token { kind, position: ctx.current_position });
+                to(Start)
+            }
+            '+' => {
+                let kind = TokenKind::Symbol(Symbol::Plus);
+                ctx.add_token(Token { kind, position: ctx.current_position });
+                to(Start)
+            }
+            '/' => {
+                let kind = TokenKind::Symbol(Symbol::Slash);
+                ctx.add_token(Token { kind, position: ctx.current_position });
+                to(Start)
+            }
+            '=' => {
+                let kind = TokenKind::Symbol(Symbol::Equals);
+                ctx.add_token(Token { kind, position: ctx.current_position });
+                to(Start)
+            }
             '@' => {
                 let kind = TokenKind::Symbol(Symbol::AtSign);
                 ctx.add_token(Token { kind, position: ctx.current_position });
@@ -56,17 +76,29 @@ impl State for Start {
                 let stack = Stack::new(ctx.current_position, Some(c));
                 to(AfterSingleDash(stack))
             }
+            ':' => {
+                let stack = Stack::new(ctx.current_position, Some(c));
+                to(AfterColon(stack))
+            }
+            '|' => {
+                let stack = Stack::new(ctx.current_position, Some(c));
+                to(AfterPipe(stack))
+            }
             '\'' => {
                 let stack = Stack::new(ctx.current_position, Some(c));
-                to(InText(stack))
+                to(InText(stack, Vec::new()))
             }
             '"' => {
-                let stack = Stack::new(ctx.current_position, Some(c));
+                let stack = Stack::new(ctx.current_position, None);
                 to(InQuotedIdentifier(stack))
             }
+            '$' => {
+                let stack = Stack::new(ctx.current_position, Some(c));
+                to(InDollarKeyword(stack))
+            }
             '`' => {
                 let stack = Stack::new(ctx.current_position, None);
-                to(InSqlSelect(stack))
+                to(InSqlSelect(stack, Vec::new()))
             }
             '0'..='9' => {
                 let stack = Stack::new(ctx.current_position, Some(c));
@@ -27,19 +27,72 @@ impl State for AfterPeriod {
     }
 }
 
-/// State after receiving a single dash
+/// State after receiving a colon, which must be followed by another to form the
+/// separator between a namespace and the rest of a reference.
 #[derive(Debug)]
-pub(super) struct AfterSingleDash(pub Stack);
+pub(super) struct AfterColon(pub Stack);
 
-impl State for AfterSingleDash {
+impl State for AfterColon {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        use LexErrorKind::{UnexpectedCharacter, UnexpectedEOF};
+
+        match c {
+            Some(':') => {
+                let kind = TokenKind::Symbol(Symbol::DoubleColon);
+                ctx.add_token(Token { kind, position: self.0.start_position });
+                to(Start)
+            }
+            Some(c) => Err(LexError {
+                kind: UnexpectedCharacter(c),
+                position: ctx.current_position,
+            }),
+            None => Err(LexError {
+                kind: UnexpectedEOF,
+                position: ctx.current_position,
+            }),
+        }
+    }
+}
+
+/// State after receiving a pipe, which must be followed by another to form the
+/// concatenation operator.
+#[derive(Debug)]
+pub(super) struct AfterPipe(pub Stack);
+
+impl State for AfterPipe {
     fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
         use LexErrorKind::{UnexpectedCharacter, UnexpectedEOF};
 
+        match c {
+            Some('|') => {
+                let kind = TokenKind::Symbol(Symbol::DoublePipe);
+                ctx.add_token(Token { kind, position: self.0.start_position });
+                to(Start)
+            }
+            Some(c) => Err(LexError {
+                kind: UnexpectedCharacter(c),
+                position: ctx.current_position,
+            }),
+            None => Err(LexError {
+                kind: UnexpectedEOF,
+                position: ctx.current_position,
+            }),
+        }
+    }
+}
+
+/// State after receiving a single dash, which starts a comment or a negative number
+/// or is otherwise the subtraction operator.
+#[derive(Debug)]
+pub(super) struct AfterSingleDash(pub Stack);
+
+impl State for AfterSingleDash {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
         let mut stack = self.0;
 
         match c {
             Some('-') => {
-                to(InComment)
+                to(InComment(Stack::new(stack.start_position, None)))
             }
             Some(c @ '0'..='9') => {
                 stack.push(c);
@@ -49,14 +102,11 @@ impl State for AfterSingleDash {
                 stack.push(c);
                 to(InFloat(stack))
             }
-            Some(c) => Err(LexError {
-                kind: UnexpectedCharacter(c),
-                position: ctx.current_position,
-            }),
-            None => Err(LexError {
-                kind: UnexpectedEOF,
-                position: ctx.current_position,
-            }),
+            _ => {
+                let kind = TokenKind::Symbol(Symbol::Dash);
+                ctx.add_token(Token { kind, position: stack.start_position });
+                defer_to(Start, ctx, c)
+            }
         }
     }
 }
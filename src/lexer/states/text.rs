@@ -1,25 +1,44 @@
 use crate::lexer::error::{LexError, LexErrorKind};
-use crate::lexer::tokens::{Token, TokenKind};
+use crate::lexer::tokens::{TextPart, Token, TokenKind, FUNCTIONS};
 use crate::lexer::prelude::*;
+use crate::lexer::tokenize_from;
+use crate::Position;
 use super::start::Start;
+use std::iter::Peekable;
+use std::mem;
+use std::str::Chars;
 
-/// State after receiving a single quote and inside a string literal.
+/// State after receiving a single quote and inside a string literal, along with
+/// the parts of the string before any values embedded in it.
 #[derive(Debug)]
-pub(super) struct InText(pub Stack);
+pub(super) struct InText(pub Stack, pub Vec<TextPart>);
 
 impl State for InText {
     fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
         use LexErrorKind::UnclosedString;
 
-        let mut stack = self.0;
+        let InText(mut stack, parts) = *self;
 
         match c {
             Some('\'') => {
-                to(AfterText(stack))
+                to(AfterText(stack, parts))
+            }
+            // Three quotes and a newline start a block of text rather than
+            // text that starts with an escaped quote
+            Some(c @ ('\n' | '\r')) if parts.is_empty() && stack.as_str() == "'''" => {
+                let text = if c == '\r' { String::new() } else { String::from(c) };
+                to(InTextBlock { position: stack.start_position, text })
+            }
+            Some('{') => {
+                // Any embedded value starts just after the brace, on the same line
+                let mut position = ctx.current_position;
+                position.column += 1;
+
+                to(AfterTextBrace { stack, parts, name: String::new(), position })
             }
             Some(c) => {
                 stack.push(c);
-                to(InText(stack))
+                to(InText(stack, parts))
             }
             None => Err(LexError {
                 kind: UnclosedString,
@@ -33,24 +52,303 @@ impl State for InText {
 /// character received is another single quote, which indicates the previous
 /// quote was being escaped and is part of the text string.
 #[derive(Debug)]
-pub(super) struct AfterText(pub Stack);
+pub(super) struct AfterText(pub Stack, pub Vec<TextPart>);
 
 impl State for AfterText {
     fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
-        let mut stack = self.0;
+        let AfterText(mut stack, mut parts) = *self;
         stack.push('\'');
 
         match c {
             Some('\'') => {
                 stack.push('\'');
-                to(InText(stack))
+                to(InText(stack, parts))
             }
             _ => {
                 let position = stack.start_position;
-                let kind = TokenKind::Text(stack.consume());
+                let kind = if parts.is_empty() {
+                    TokenKind::Text(stack.consume())
+                } else {
+                    let text = stack.consume();
+                    push_literal(&mut parts, &text[1..text.len() - 1]);
+                    TokenKind::InterpolatedText(parts)
+                };
                 ctx.add_token(Token { kind, position });
                 defer_to(Start, ctx, c)
             }
         }
     }
 }
+
+/// State inside an escape string after `e'`, in which a backslash starts an escape
+/// sequence just as in Postgres, eg. `e'tab\tseparated'`, along with where it started,
+/// the text received so far, and whether the previous character was a backslash.
+/// Nothing is embedded in escape strings.
+#[derive(Debug)]
+pub(super) struct InEscapeText {
+    pub position: Position,
+    pub text: String,
+    pub escaped: bool,
+}
+
+impl State for InEscapeText {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let InEscapeText { position, mut text, escaped } = *self;
+
+        match c {
+            Some('\'') if !escaped => to(AfterEscapeText { position, text }),
+            Some(c) => {
+                text.push(c);
+                to(InEscapeText { position, text, escaped: !escaped && c == '\\' })
+            }
+            None => Err(LexError {
+                kind: LexErrorKind::UnclosedString,
+                position: ctx.current_position,
+            }),
+        }
+    }
+}
+
+/// State after receiving what might be the closing quote of an escape string, unless
+/// it is followed by another quote, just as in other text.
+#[derive(Debug)]
+pub(super) struct AfterEscapeText {
+    position: Position,
+    text: String,
+}
+
+impl State for AfterEscapeText {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let AfterEscapeText { position, mut text } = *self;
+
+        if c == Some('\'') {
+            text.push_str("\\'");
+            return to(InEscapeText { position, text, escaped: false });
+        }
+
+        let text = unescape(&text).map_err(|reason| LexError {
+            kind: LexErrorKind::InvalidEscape(reason),
+            position,
+        })?;
+
+        // The text is stored just as if it had been written without escapes
+        let kind = TokenKind::Text(format!("'{}'", text.replace('\'', "''")));
+        ctx.add_token(Token { kind, position });
+        defer_to(Start, ctx, c)
+    }
+}
+
+/// Replaces the escape sequences that Postgres allows in escape strings with what they
+/// stand for, where octal and hex escapes are bytes that must together be valid UTF-8,
+/// failing with the reason if any are invalid.
+fn unescape(text: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let escaped = match c {
+            '\\' => chars.next().unwrap_or('\\'),
+            c => {
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                continue;
+            }
+        };
+        let (radix, digits) = match escaped {
+            'x' => (16, digits(&mut chars, 16, 2)),
+            'u' => (16, digits(&mut chars, 16, 4)),
+            'U' => (16, digits(&mut chars, 16, 8)),
+            '0'..='7' => (8, format!("{}{}", escaped, digits(&mut chars, 8, 2))),
+            c => {
+                let c = match c {
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    c => c,
+                };
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                continue;
+            }
+        };
+        let sequence = match escaped {
+            '0'..='7' => format!("\\{}", digits),
+            _ => format!("\\{}{}", escaped, digits),
+        };
+        let invalid = || format!("invalid escape sequence `{}`", sequence);
+        let code = u32::from_str_radix(&digits, radix).map_err(|_| invalid())?;
+
+        match escaped {
+            'u' | 'U' => {
+                let c = char::from_u32(code)
+                    .filter(|_| digits.len() == if escaped == 'u' { 4 } else { 8 })
+                    .filter(|c| *c != '\0')
+                    .ok_or_else(invalid)?;
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            _ => match u8::try_from(code) {
+                Ok(byte) if byte != 0 => bytes.push(byte),
+                _ => return Err(invalid()),
+            },
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| "escaped bytes are not valid UTF-8".to_owned())
+}
+
+/// Takes up to the given number of digits in the radix.
+fn digits(chars: &mut Peekable<Chars>, radix: u32, max: usize) -> String {
+    let mut digits = String::new();
+
+    while digits.len() < max {
+        match chars.next_if(|c| c.is_digit(radix)) {
+            Some(digit) => digits.push(digit),
+            None => break,
+        }
+    }
+
+    digits
+}
+
+/// State after receiving an opening brace in a string literal, which embeds a value if
+/// followed by a reference, a variable, or a function call, eg. `{@person1.id}`, `{$domain}`,
+/// or `{index()}`, and
+/// is otherwise part of the text, along with the name of the function if receiving one.
+#[derive(Debug)]
+pub(super) struct AfterTextBrace {
+    stack: Stack,
+    parts: Vec<TextPart>,
+    name: String,
+    /// Where the embedded value starts, if there is one
+    position: Position,
+}
+
+impl State for AfterTextBrace {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let AfterTextBrace { mut stack, mut parts, mut name, position } = *self;
+
+        match c {
+            Some(c @ ('@' | '$')) if name.is_empty() => {
+                split_literal(&mut stack, &mut parts);
+                to(InTextValue { stack, parts, value: String::from(c), position, quoted: false })
+            }
+            Some(c @ '(') if FUNCTIONS.contains(&name.as_str()) => {
+                split_literal(&mut stack, &mut parts);
+                name.push(c);
+                to(InTextValue { stack, parts, value: name, position, quoted: false })
+            }
+            Some(c) if is_identifier_char(c) => {
+                name.push(c);
+                to(AfterTextBrace { stack, parts, name, position })
+            }
+            _ => {
+                stack.push('{');
+                name.chars().for_each(|c| stack.push(c));
+                defer_to(InText(stack, parts), ctx, c)
+            }
+        }
+    }
+}
+
+/// State inside a value embedded in a string literal, until the closing brace.
+#[derive(Debug)]
+pub(super) struct InTextValue {
+    stack: Stack,
+    parts: Vec<TextPart>,
+    value: String,
+    /// Where the value started
+    position: Position,
+    /// Whether the value is inside a string literal of its own
+    quoted: bool,
+}
+
+impl State for InTextValue {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        use LexErrorKind::UnclosedString;
+
+        let InTextValue { stack, mut parts, mut value, position, quoted } = *self;
+
+        match c {
+            Some('}') if !quoted => {
+                let tokens = tokenize_from(value.chars(), position)?;
+                parts.push(TextPart::Embedded(tokens));
+                to(InText(stack, parts))
+            }
+            Some(c) => {
+                value.push(c);
+                let quoted = if c == '\'' { !quoted } else { quoted };
+                to(InTextValue { stack, parts, value, position, quoted })
+            }
+            None => Err(LexError {
+                kind: UnclosedString,
+                position: ctx.current_position,
+            }),
+        }
+    }
+}
+
+/// State inside a block of text between `'''` on its own line and `'''`, in which
+/// quotes do not need to be escaped and nothing is embedded, along with where the
+/// block started and the text received so far, starting from the opening newline.
+#[derive(Debug)]
+pub(super) struct InTextBlock {
+    position: Position,
+    text: String,
+}
+
+impl State for InTextBlock {
+    fn receive(self: Box<Self>, ctx: &mut Context, c: Option<char>) -> ReceiveResult {
+        let InTextBlock { position, mut text } = *self;
+
+        match c {
+            Some(c) => {
+                text.push(c);
+
+                if let Some(text) = text.strip_suffix("'''") {
+                    let text = unindent_block(text);
+                    let kind = TokenKind::Text(format!("'{}'", text.replace('\'', "''")));
+                    ctx.add_token(Token { kind, position });
+                    return to(Start);
+                }
+
+                to(InTextBlock { position, text })
+            }
+            None => Err(LexError {
+                kind: LexErrorKind::UnclosedString,
+                position: ctx.current_position,
+            }),
+        }
+    }
+}
+
+/// Returns the lines of a block of text between the line it opened on and the
+/// line it closed on, without the indentation before the closing quotes. Lines
+/// that are indented less have all of their indentation removed instead.
+fn unindent_block(text: &str) -> String {
+    let (text, indent) = match text.rfind('\n') {
+        Some(i) if text[i + 1..].chars().all(char::is_whitespace) => (&text[..i], text[i + 1..].len()),
+        _ => (text, 0),
+    };
+
+    text.split('\n')
+        .skip(1)
+        .map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let indented = line.chars().take(indent).take_while(|c| c.is_whitespace()).count();
+            &line[indented..]
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Moves the text received so far, after the opening quote, into its own part.
+fn split_literal(stack: &mut Stack, parts: &mut Vec<TextPart>) {
+    let text = mem::replace(stack, Stack::new(stack.start_position, Some('\''))).consume();
+    push_literal(parts, &text[1..]);
+}
+
+fn push_literal(parts: &mut Vec<TextPart>, text: &str) {
+    if !text.is_empty() {
+        parts.push(TextPart::Literal(text.replace("''", "'")));
+    }
+}
@@ -4,6 +4,9 @@ use std::fmt;
 #[derive(Clone, Debug, PartialEq)]
 pub enum Keyword {
     As,
+    Columns,
+    Defaults,
+    Import,
     Schema,
     Table,
 }
@@ -14,6 +17,9 @@ impl fmt::Display for Keyword {
 
         match self {
             As => write!(f, "as"),
+            Columns => write!(f, "$columns"),
+            Defaults => write!(f, "$defaults"),
+            Import => write!(f, "$import"),
             Schema => write!(f, "schema"),
             Table => write!(f, "table"),
         }
@@ -22,11 +28,18 @@ impl fmt::Display for Keyword {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Symbol {
+    Asterisk,
     AtSign,
     Comma,
+    Dash,
+    DoubleColon,
+    DoublePipe,
+    Equals,
     ParenLeft,
     ParenRight,
     Period,
+    Plus,
+    Slash,
     Underscore,
 }
 
@@ -35,27 +48,61 @@ impl fmt::Display for Symbol {
         use Symbol::*;
 
         match self {
+            Asterisk => write!(f, "*"),
             AtSign => write!(f, "@"),
             Comma => write!(f, ","),
+            Dash => write!(f, "-"),
+            DoubleColon => write!(f, "::"),
+            DoublePipe => write!(f, "||"),
+            Equals => write!(f, "="),
             ParenLeft => write!(f, "("),
             ParenRight => write!(f, ")"),
             Period => write!(f, "."),
+            Plus => write!(f, "+"),
+            Slash => write!(f, "/"),
             Underscore => write!(f, "_"),
         }
     }
 }
 
+/// The value functions that can be called in place of literal values,
+/// which take only strings as arguments
+pub const FUNCTIONS: [&str; 7] = ["ago", "bytes", "env", "index", "now", "uuid", "uuid5"];
+
+/// A part of a string literal or SQL fragment with values embedded in it, eg.
+/// `'user-{@person1.id}'`
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextPart {
+    /// Literal text, without any escaped quotes or backticks
+    Literal(String),
+    /// The tokens of a value between braces, without the braces
+    Embedded(Vec<Token>),
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum TokenKind {
     Bool(bool),
+    /// The text of a `--` comment, without the dashes, which the parser keeps as trivia
+    Comment(String),
+    /// The hex digits of a binary string literal, eg. `x'deadbeef'`
+    Hex(String),
     Identifier(String),
+    /// The text of a JSON literal, eg. `json{ "theme": "dark" }` without the `json`
+    Json(String),
+    /// A SQL fragment with at least one embedded reference
+    InterpolatedSqlFragment(Vec<TextPart>),
+    /// A string literal with at least one embedded value
+    InterpolatedText(Vec<TextPart>),
     Keyword(Keyword),
     LineSep,
+    Null,
     Number(String),
     QuotedIdentifier(String),
     SqlFragment(String),
     Symbol(Symbol),
     Text(String),
+    /// The name of a variable declared with `let`, without the `$`
+    Variable(String),
 }
 
 impl fmt::Display for TokenKind {
@@ -64,14 +111,21 @@ impl fmt::Display for TokenKind {
 
         match self {
             Bool(b) => write!(f, "boolean `{}`", b),
+            Comment(_) => write!(f, "comment"),
+            Hex(h) => write!(f, "binary string x'{}'", h),
             Identifier(i) => write!(f, "identifier `{}`", i),
+            InterpolatedSqlFragment(_) => write!(f, "interpolated SQL fragment"),
+            InterpolatedText(_) => write!(f, "interpolated string"),
+            Json(_) => write!(f, "JSON literal"),
             Keyword(k) => write!(f, "keyword `{}`", k),
             LineSep => write!(f, "newline"),
+            Null => write!(f, "null"),
             Number(n) => write!(f, "number `{}`", n),
             QuotedIdentifier(i) => write!(f, "quoted identifier `\"{}\"`", i),
             SqlFragment(s) => write!(f, "SQL fragment `{}`", s),
             Symbol(s) => write!(f, "symbol `{}`", s),
             Text(s) => write!(f, "string '{}'", s),
+            Variable(v) => write!(f, "variable `${}`", v),
         }
     }
 }
@@ -91,6 +145,9 @@ mod tests {
         use Keyword::*;
 
         assert_eq!(format!("{}", As), "as");
+        assert_eq!(format!("{}", Columns), "$columns");
+        assert_eq!(format!("{}", Defaults), "$defaults");
+        assert_eq!(format!("{}", Import), "$import");
         assert_eq!(format!("{}", Schema), "schema");
         assert_eq!(format!("{}", Table), "table");
     }
@@ -99,11 +156,18 @@ mod tests {
     fn test_display_symbol() {
         use Symbol::*;
 
+        assert_eq!(format!("{}", Asterisk), "*");
         assert_eq!(format!("{}", AtSign), "@");
         assert_eq!(format!("{}", Comma), ",");
+        assert_eq!(format!("{}", Dash), "-");
+        assert_eq!(format!("{}", DoubleColon), "::");
+        assert_eq!(format!("{}", DoublePipe), "||");
+        assert_eq!(format!("{}", Equals), "=");
         assert_eq!(format!("{}", ParenLeft), "(");
         assert_eq!(format!("{}", ParenRight), ")");
         assert_eq!(format!("{}", Period), ".");
+        assert_eq!(format!("{}", Plus), "+");
+        assert_eq!(format!("{}", Slash), "/");
         assert_eq!(format!("{}", Underscore), "_");
     }
 
@@ -114,12 +178,15 @@ mod tests {
         use TokenKind::*;
 
         assert_eq!(format!("{}", Bool(true)), "boolean `true`");
+        assert_eq!(format!("{}", Comment(" foo".to_string())), "comment");
         assert_eq!(format!("{}", Identifier("foo".to_string())), "identifier `foo`");
         assert_eq!(format!("{}", Keyword(As)), "keyword `as`");
         assert_eq!(format!("{}", LineSep), "newline");
+        assert_eq!(format!("{}", Null), "null");
         assert_eq!(format!("{}", Number("42".to_string())), "number `42`");
         assert_eq!(format!("{}", QuotedIdentifier("foo".to_string())), "quoted identifier `\"foo\"`");
         assert_eq!(format!("{}", Symbol(Comma)), "symbol `,`");
         assert_eq!(format!("{}", Text("foo".to_string())), "string 'foo'");
+        assert_eq!(format!("{}", Variable("foo".to_string())), "variable `$foo`");
     }
 }
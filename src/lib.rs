@@ -1,27 +1,344 @@
 pub mod analyzer;
+pub mod ast;
+mod cache;
+pub mod diagnostics;
+pub mod dump;
+pub mod embed;
 pub mod error;
+pub mod fixtures;
+mod identifier;
+pub mod import;
+pub mod include;
 pub mod lexer;
+pub mod lint;
 pub mod loader;
+mod lsp;
 pub mod parser;
+pub mod placer;
 mod position;
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::cell::Cell;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Instant;
 
-use error::HldrError;
+use diagnostics::{LineIndex, SourceLocation, SourceMap};
+pub use dump::{DumpOptions, Filter};
+pub use error::HldrError;
+use loader::conninfo::BackendKind;
+#[cfg(feature = "sqlite")]
+pub use rusqlite;
+pub use placer::Placer;
+pub use postgres;
+#[cfg(feature = "r2d2")]
+pub use r2d2;
+#[cfg(feature = "r2d2")]
+pub use r2d2_postgres;
+pub use loader::report::{PlaceReport, RecordRow, TableReport, Timings};
+use parser::nodes::{ParseTree, StructuralNode, Value};
+use postgres::{Client, Transaction};
 pub use position::Position;
 
 #[derive(Clone, Default, Debug, Deserialize)]
 pub struct Options {
+    #[serde(default)]
+    pub bulk: bool,
+
+    /// Whether to keep the analyzed trees of the data files in `.hldr-cache`, so that
+    /// later loads of the same data files skip lexing, parsing, and analyzing them
+    /// while none of them have changed
+    #[serde(default)]
+    pub cache: bool,
+
+    /// Whether to select each SQL fragment only once per load, reusing its value wherever
+    /// the same SQL is declared again, unless it is declared `volatile`
+    #[serde(default)]
+    pub cache_fragments: bool,
+
+    /// Whether to insert each record within a savepoint, so that if any fail to insert,
+    /// they are rolled back and loading continues, before failing with every error once
+    /// everything else is loaded
+    #[serde(default)]
+    pub collect_errors: bool,
+
     #[serde(default)]
     pub commit: bool,
 
-    #[serde(default = "default_data_file")]
-    pub data_file: PathBuf,
+    /// Whether to print a summary of what will be loaded and ask for confirmation on
+    /// stdin before committing
+    #[serde(default)]
+    pub confirm: bool,
+
+    /// Whether to create every schema declared in the data files before loading anything,
+    /// unless it already exists
+    #[serde(default)]
+    pub create_schemas: bool,
+
+    /// Whether to create every table declared in the data files before loading anything,
+    /// unless it already exists, with the columns that records give values for and types
+    /// inferred from those values, for prototyping before the real schema exists
+    #[serde(default)]
+    pub create_tables: bool,
+
+    #[serde(default, alias = "data_file", deserialize_with = "one_or_many")]
+    pub data_files: Vec<PathBuf>,
 
     #[serde(default)]
     pub database_conn: String,
+
+    #[serde(default)]
+    pub dedupe: bool,
+
+    #[serde(default)]
+    pub defer_constraints: bool,
+
+    /// How to disable triggers while loading, eg. audit triggers that would otherwise
+    /// fire for every seeded record, which are enabled again once everything is loaded
+    #[serde(default)]
+    pub disable_triggers: Option<DisableTriggers>,
+
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// The time to load the data files at, eg. `2024-01-01T00:00:00Z`, which `now()` and
+    /// `ago(...)` are relative to instead of the time of the transaction, so that repeated
+    /// loads give exactly the same data
+    #[serde(default)]
+    pub fixed_now: Option<String>,
+
+    #[serde(default)]
+    pub isolation_level: Option<IsolationLevel>,
+
+    /// Whether to load each table within a savepoint, so that tables that fail to load
+    /// are reported and skipped while everything else is still loaded
+    #[serde(default)]
+    pub keep_going: bool,
+
+    /// The severity of each lint rule, given in a `[lint]` table
+    #[serde(default)]
+    pub lint: lint::LintOptions,
+
+    /// The number of records to load from each table at most, along with any named
+    /// records that they reference
+    #[serde(default)]
+    pub limit_per_table: Option<usize>,
+
+    #[serde(default)]
+    pub lock_timeout: Option<String>,
+
+    /// The file to append every statement executed while loading to, with the time it was
+    /// executed, eg. to audit what was done to shared databases
+    #[serde(default)]
+    pub log_sql: Option<PathBuf>,
+
+    #[serde(default)]
+    pub on_conflict: OnConflict,
+
+    /// The tags that records must be tagged with at least one of, themselves or through
+    /// their table, to be loaded, unless empty
+    #[serde(default)]
+    pub only_tags: Vec<String>,
+
+    /// The SQL file to write the statements that would load the data files to, wrapped
+    /// in a transaction, rather than executing them
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+
+    /// The profiles whose sections, declared with `profile ... (...)`, are loaded along
+    /// with everything declared outside of any profile's section
+    #[serde(default)]
+    pub profiles: Vec<String>,
+
+    /// Whether to refresh the materialized views that depend on the tables that records are
+    /// loaded into, directly or through other views, once everything is loaded
+    #[serde(default)]
+    pub refresh_matviews: bool,
+
+    /// Whether to reset the sequences of columns that records give values for, eg. their
+    /// primary keys, to the columns' greatest values once everything is loaded, so that
+    /// rows inserted later are not given values that already exist
+    #[serde(default)]
+    pub reset_sequences: bool,
+
+    /// The role to load the data files as, switched to with `SET LOCAL ROLE` for the
+    /// transaction, unless tables or their schemas declare their own with `as role ...`
+    #[serde(default)]
+    pub role: Option<String>,
+
+    /// The schemas that unqualified tables are resolved against, set as the transaction's
+    /// `search_path` rather than relying on the connection's default, unless the data
+    /// files declare their own with `set search_path ...`
+    #[serde(default)]
+    pub search_path: Option<Vec<String>>,
+
+    /// The fraction of each table's records to load, between 0 and 1, which are always
+    /// the same records spread evenly across the table, along with any named records that
+    /// they reference
+    #[serde(default)]
+    pub sample: Option<f64>,
+
+    /// The keys that records are skipped by, rather than inserted, if a row with the same
+    /// values for their columns already exists, which is selected instead, eg. for shared
+    /// databases that do not declare unique constraints
+    #[serde(default)]
+    pub skip_existing: Vec<SkipExisting>,
+
+    /// The tags that exclude any records tagged with them, themselves or through their table
+    #[serde(default)]
+    pub skip_tags: Vec<String>,
+
+    #[serde(default)]
+    pub statement_timeout: Option<String>,
+
+    /// Whether to load the records of each data file as they are parsed rather than once
+    /// every data file has been, so that very large data files are never held in memory
+    /// as a whole, at the cost of records only being able to reference earlier ones
+    #[serde(default)]
+    pub stream: bool,
+
+    /// Whether numbers that Postgres would round to fit their columns, eg. `1.005` for
+    /// a `numeric(5,2)` column, fail the load rather than only being warned about, which
+    /// verifies the schema as `verify_schema` does
+    #[serde(default)]
+    pub strict_numbers: bool,
+
+    #[serde(default)]
+    pub synchronous_commit: Option<String>,
+
+    #[serde(default)]
+    pub truncate: bool,
+
+    #[serde(default)]
+    pub verify_schema: bool,
+}
+
+/// How inserted records that conflict with existing rows are handled for
+/// tables that do not declare their own conflict target.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Fail the load, as plain inserts do
+    #[default]
+    Error,
+    /// Skip the record, which can still be referenced via the existing row
+    Ignore,
+    /// Update the existing row, using the table's primary key as the conflict target
+    Update,
+}
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "ignore" => Ok(Self::Ignore),
+            "update" => Ok(Self::Update),
+            _ => Err(format!("invalid conflict mode `{}`", s)),
+        }
+    }
+}
+
+/// The columns of a key that records are skipped by with the `skip_existing` option,
+/// written as eg. `email` for every table whose records give values for them, or as
+/// `person=first_name,last_name` for a single table, which can be schema-qualified.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub struct SkipExisting {
+    pub table: Option<String>,
+    pub columns: Vec<String>,
+}
+
+impl FromStr for SkipExisting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (table, columns) = match s.split_once('=') {
+            Some((table, columns)) => (Some(table.trim().to_owned()), columns),
+            None => (None, s),
+        };
+        let columns: Vec<String> = columns.split(',').map(|c| c.trim().to_owned()).collect();
+
+        if table.as_deref() == Some("") || columns.iter().any(String::is_empty) {
+            return Err(format!("invalid key `{}`", s));
+        }
+
+        Ok(Self { table, columns })
+    }
+}
+
+impl TryFrom<String> for SkipExisting {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The isolation level of the transaction that data files are loaded in, which
+/// is otherwise the database's default.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl FromStr for IsolationLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read-committed" => Ok(Self::ReadCommitted),
+            "repeatable-read" => Ok(Self::RepeatableRead),
+            "serializable" => Ok(Self::Serializable),
+            _ => Err(format!("invalid isolation level `{}`", s)),
+        }
+    }
+}
+
+/// How triggers are disabled while loading with the `disable_triggers` option.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DisableTriggers {
+    /// Disable the triggers of each table that is loaded into other than those that
+    /// enforce constraints, which only the table's owner can do
+    User,
+    /// Disable every trigger of each table that is loaded into, including those that
+    /// enforce foreign keys, which only superusers can do
+    All,
+    /// Disable every trigger in the database other than those enabled for replicas by
+    /// setting `session_replication_role`, which only superusers can do
+    Replica,
+}
+
+impl FromStr for DisableTriggers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Self::User),
+            "all" => Ok(Self::All),
+            "replica" => Ok(Self::Replica),
+            _ => Err(format!("invalid trigger mode `{}`", s)),
+        }
+    }
+}
+
+impl From<IsolationLevel> for postgres::IsolationLevel {
+    fn from(level: IsolationLevel) -> Self {
+        match level {
+            IsolationLevel::ReadCommitted => Self::ReadCommitted,
+            IsolationLevel::RepeatableRead => Self::RepeatableRead,
+            IsolationLevel::Serializable => Self::Serializable,
+        }
+    }
 }
 
 impl Options {
@@ -38,32 +355,960 @@ impl Options {
 
         Ok(Some(toml::from_str(&contents).map_err(|e| e.to_string())?))
     }
+
+    /// Returns the data files to load in order, defaulting to `place.hldr` if none
+    /// were specified and expanding any directories into the `.hldr` files they
+    /// directly contain, sorted by name.
+    pub fn data_file_paths(&self) -> io::Result<Vec<PathBuf>> {
+        if self.data_files.is_empty() {
+            return Ok(vec![PathBuf::from(DEFAULT_DATA_FILE)]);
+        }
+
+        let mut paths = Vec::new();
+
+        for path in &self.data_files {
+            if !path.is_dir() {
+                paths.push(path.clone());
+                continue;
+            }
+
+            let mut entries = fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            entries.retain(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "hldr"));
+            entries.sort();
+            paths.extend(entries);
+        }
+
+        Ok(paths)
+    }
 }
 
-fn default_data_file() -> PathBuf {
-    PathBuf::from("place.hldr")
+const DEFAULT_DATA_FILE: &str = "place.hldr";
+
+/// Allows the options file to specify either a single data file or a list of them
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(path) => vec![path],
+        OneOrMany::Many(paths) => paths,
+    })
 }
 
-pub fn place(options: &Options) -> Result<(), HldrError> {
-    let input = fs::read_to_string(&options.data_file)?;
-    let tokens = lexer::tokenize(input.chars())?;
-    let parse_tree = parser::parse(tokens.into_iter())?;
-    let parse_tree = analyzer::analyze(parse_tree)?;
-    let mut client = loader::new_client(&options.database_conn)?;
-    let mut transaction = client.transaction()?;
+/// Loads the data files given in the options, returning what was loaded,
+/// or renders their statements if performing a dry run.
+pub fn place(options: &Options) -> Result<PlaceReport, HldrError> {
+    match backend_kind(options) {
+        BackendKind::Postgres => {}
+        #[cfg(feature = "mysql")]
+        BackendKind::MySql => return place_with::<loader::mysql::MySql>(options),
+        #[cfg(feature = "sqlite")]
+        BackendKind::Sqlite => return place_with::<loader::sqlite::Sqlite>(options),
+        #[allow(unreachable_patterns)]
+        kind => return Err(missing_feature(kind)),
+    }
+
+    loader::block_on(place_on::<Client>(options))
+}
+
+/// Loads the data files given in the options into Postgres with either client, so that
+/// [`place`] and [`place_async`] handle the options and locate errors in the same way.
+async fn place_on<C: loader::TransactionClient>(options: &Options) -> Result<PlaceReport, HldrError> {
+    if options.dry_run {
+        let (parse_tree, ..) = analyze_data_files(options)?;
+        loader::dry_run::render(&mut io::stdout().lock(), parse_tree, options)?;
+        return Ok(PlaceReport::default());
+    }
+
+    if let Some(path) = &options.output {
+        let (parse_tree, sources, _) = analyze_data_files(options)?;
+        let tables = loader::output::primary_key_tables(&parse_tree, options);
+        let mut primary_keys = HashMap::new();
+
+        if !tables.is_empty() {
+            let mut client = C::connect(&options.database_conn).await?;
+            // Unqualified tables are looked up with the search path they are loaded with
+            let mut transaction = client.begin(options).await?;
+
+            if let Some(statement) = loader::search_path_statement(parse_tree.inner()) {
+                loader::Connection::simple_query(&mut transaction, &statement).await?;
+            }
+
+            primary_keys = loader::output::primary_keys(&mut transaction, &tables).await?;
+        }
+
+        write_output(path, parse_tree, options, primary_keys).map_err(|e| sources.locate_error(e))?;
+        return Ok(PlaceReport::default());
+    }
+
+    if options.stream {
+        let mut client = C::connect(&options.database_conn).await?;
+        let mut transaction = client.begin(options).await?;
+        let report = stream_data_files(&mut transaction, options).await?;
+
+        return finish_transaction::<C>(transaction, report, options).await;
+    }
+
+    let (parse_tree, sources, timings) = analyze_data_files(options)?;
+
+    if !confirmed(&parse_tree, options)? {
+        return Ok(PlaceReport::default());
+    }
+
+    let mut client = C::connect(&options.database_conn).await?;
+    let mut report = load_in_transaction(&mut client, parse_tree, options)
+        .await
+        .map_err(|e| sources.locate_error(e))?;
 
-    loader::load(&mut transaction, parse_tree)?;
+    report.timings = timings;
+    Ok(report)
+}
+
+/// Writes the statements that would load the tree to the SQL file, given the primary
+/// keys of the tables that need them.
+fn write_output(
+    path: &Path,
+    parse_tree: analyzer::ValidatedParseTree,
+    options: &Options,
+    primary_keys: HashMap<String, Vec<String>>,
+) -> Result<(), HldrError> {
+    let mut executor = loader::output::SqlFileExecutor::create(path)?;
+
+    loader::output::write(&mut executor, parse_tree, options, primary_keys)?;
+    executor.finish()?;
+    log::info!("Wrote statements to {}", path.display());
+
+    Ok(())
+}
+
+/// Returns the database that the connection string, or else the environment, is for.
+fn backend_kind(options: &Options) -> BackendKind {
+    loader::conninfo::backend_kind(&loader::conninfo::resolve(&options.database_conn))
+}
+
+/// Fails for databases that need a feature that hldr was built without.
+fn missing_feature(kind: BackendKind) -> HldrError {
+    let error = format!(
+        "loading into {} requires hldr to be built with the `{}` feature",
+        kind.name(),
+        kind.feature().unwrap_or_default(),
+    );
+
+    loader::error::ClientError::config_error(error).into()
+}
+
+/// Loads the data files into a database other than Postgres, which inserts each
+/// record in turn rather than using any of the features of Postgres that the options
+/// rely on.
+#[cfg(any(feature = "mysql", feature = "sqlite"))]
+fn place_with<B: loader::backend::Backend>(options: &Options) -> Result<PlaceReport, HldrError> {
+    loader::backend::check_options::<B>(options)?;
+
+    let (parse_tree, sources, timings) = analyze_data_files(options)?;
+
+    if !confirmed(&parse_tree, options)? {
+        return Ok(PlaceReport::default());
+    }
+
+    let mut backend = B::connect(&options.database_conn)?;
+    backend.begin(options)?;
+
+    let mut report =
+        loader::backend::load(&mut backend, parse_tree, options).map_err(|e| sources.locate_error(e.into()))?;
+    report.timings = timings;
 
     if options.commit {
-        println!("Committing changes");
-        transaction.commit()?;
+        log::info!("Committing changes");
+        report.committed = true;
+        backend.execute("COMMIT")?;
     } else {
-        println!("Rolling back changes, pass `--commit` to apply")
+        log::info!("Rolling back changes, pass `--commit` to apply");
+        backend.execute("ROLLBACK")?;
+    }
+
+    Ok(report)
+}
+
+/// Prints the plan for loading the tree and asks whether to go ahead, if committing
+/// with the `confirm` option, before connecting to the database.
+fn confirmed(tree: &analyzer::ValidatedParseTree, options: &Options) -> io::Result<bool> {
+    if !(options.commit && options.confirm) {
+        return Ok(true);
+    }
+
+    let mut stderr = io::stderr().lock();
+    write!(stderr, "{}Commit these changes? [y/N] ", loader::plan::plan(tree, options))?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    let confirmed = matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+
+    if !confirmed {
+        log::info!("Nothing was loaded");
+    }
+
+    Ok(confirmed)
+}
+
+/// Generates a data file from the rows of the tables selected in the options,
+/// where rows referenced through foreign keys are declared as named records and
+/// referenced by name rather than by their raw keys.
+pub fn dump(options: &DumpOptions) -> Result<String, HldrError> {
+    let mut client = loader::new_client(&options.database_conn)?;
+
+    dump::generate(&mut client, options)
+}
+
+/// Serves the Language Server Protocol over stdin and stdout until the client exits,
+/// reporting errors in data files as they are edited, going from references to the
+/// records they reference, and completing names from the database.
+pub fn lsp(options: &Options) -> Result<(), HldrError> {
+    lsp::serve(io::stdin().lock(), io::stdout().lock(), options)
+}
+
+/// Loads the data files given in the options with a `tokio-postgres` client,
+/// just as [`place`] does with a blocking one.
+#[cfg(feature = "async")]
+pub async fn place_async(options: &Options) -> Result<PlaceReport, HldrError> {
+    if backend_kind(options) != BackendKind::Postgres {
+        let error = "only Postgres can be loaded into asynchronously";
+        return Err(loader::error::ClientError::config_error(error).into());
+    }
+
+    place_on::<tokio_postgres::Client>(options).await
+}
+
+/// Checks the data files given in the options against the lint rules, returning
+/// what breaks them, or the first error if they are not valid.
+pub fn lint(options: &Options) -> Result<Vec<lint::Lint>, HldrError> {
+    let (parse_tree, includes) = parse_data_files(options)?;
+    let sources = includes.sources;
+    let lints = lint::lint(&parse_tree, &options.lint, &sources);
+
+    analyzer::analyze(parse_tree).map_err(|e| sources.locate_error(e.into()))?;
+    Ok(lints)
+}
+
+/// Checks that the data files given in the options are valid, returning the
+/// first error found, without connecting to the database.
+pub fn check(options: &Options) -> Result<(), HldrError> {
+    if !options.stream {
+        analyze_data_files(options)?;
+        return Ok(());
+    }
+
+    // Records can only reference earlier records when streaming, so they are checked
+    // just as they would be streamed
+    let mut analyzer = analyzer::StreamAnalyzer::tagged(tag_filter(options));
+
+    for path in options.data_file_paths()? {
+        let lines = LineIndex::read(&path)?;
+        let mut tables = stream_data_file(&path, &lines, options);
+
+        while analyze_next(&mut tables, &mut analyzer, &lines)?.is_some() {}
     }
 
     Ok(())
 }
 
+/// Parses and analyzes the data files given in the options, returning the tree along
+/// with the source map to locate errors with and how long each stage took, or reads
+/// them from the cache with the `cache` option if none of the files have changed since
+/// they were cached.
+fn analyze_data_files(options: &Options) -> Result<(analyzer::ValidatedParseTree, SourceMap, Timings), HldrError> {
+    let cache_dir = Path::new(cache::CACHE_DIR);
+    // Duplicate records are only warned about while analyzing
+    let cached = options.cache && !options.dedupe;
+
+    if cached {
+        if let Some((parse_tree, sources)) = cache::read(cache_dir, options) {
+            log::info!("Using the analyzed data files cached in {}", cache::CACHE_DIR);
+            return Ok((parse_tree, sources, Timings::default()));
+        }
+    }
+
+    let (parse_tree, mut includes) = parse_data_files(options)?;
+    let started = Instant::now();
+    let parse_tree = analyze(parse_tree, &includes.sources, options)?;
+    includes.timings.analyze = started.elapsed();
+
+    if cached {
+        if let Err(e) = cache::write(cache_dir, options, &parse_tree, &includes.sources, &includes.imported_files) {
+            log::warn!("Not caching the analyzed data files: {}", e);
+        }
+    }
+
+    Ok((parse_tree, includes.sources, includes.timings))
+}
+
+/// Analyzes the tree parsed from the data files, first removing the tables of any
+/// profiles that are not loaded and warning about any duplicate anonymous records
+/// with the `dedupe` option, and then removing any records that are not sampled.
+fn analyze(mut parse_tree: ParseTree, sources: &SourceMap, options: &Options) -> Result<analyzer::ValidatedParseTree, HldrError> {
+    select_profiles(&mut parse_tree, options);
+
+    if options.dedupe {
+        for duplicate in analyzer::dedupe::duplicate_records(&parse_tree) {
+            let message = lint::duplicate_record_message(&duplicate, sources);
+            let location = duplicate.position.and_then(|p| sources.location(p));
+
+            log::warn!("{}", diagnostics::annotate(&message, location.as_ref()));
+        }
+    }
+
+    let tree = analyzer::analyze_tagged(parse_tree, &tag_filter(options)).map_err(|e| sources.locate_error(e.into()))?;
+
+    Ok(analyzer::sample::sample(tree, &sample(options)))
+}
+
+/// Removes the tables declared in sections of profiles that are not given in the options,
+/// so that they are neither analyzed nor loaded.
+fn select_profiles(tree: &mut ParseTree, options: &Options) {
+    tree.nodes.retain_mut(|node| match node {
+        StructuralNode::Schema(schema) => {
+            schema.nodes.retain(|table| in_profile(table, options));
+            true
+        }
+        StructuralNode::Table(table) => in_profile(table, options),
+    });
+}
+
+/// Whether the table is loaded, which it is unless it was declared in the section of a
+/// profile that is not given in the options.
+fn in_profile(table: &parser::nodes::Table, options: &Options) -> bool {
+    match &table.profile {
+        Some(profile) => options.profiles.contains(profile),
+        None => true,
+    }
+}
+
+/// Validates a parse tree as it would be before loading it, after removing the tables of
+/// any profiles and the records of any tags or samples that the options do not select.
+pub fn validate(parse_tree: ParseTree, options: &Options) -> Result<analyzer::ValidatedParseTree, HldrError> {
+    analyze(parse_tree, &SourceMap::default(), options)
+}
+
+/// Returns the filter that selects records by the tags given in the options.
+fn tag_filter(options: &Options) -> analyzer::tags::TagFilter {
+    analyzer::tags::TagFilter {
+        only: options.only_tags.clone(),
+        skip: options.skip_tags.clone(),
+    }
+}
+
+/// Returns the sample of each table's records given in the options.
+fn sample(options: &Options) -> analyzer::sample::Sample {
+    analyzer::sample::Sample {
+        limit_per_table: options.limit_per_table,
+        fraction: options.sample,
+    }
+}
+
+/// Parses the data files given in the options into a single tree, returning it along
+/// with the files that were included or imported while parsing them.
+fn parse_data_files(options: &Options) -> Result<(ParseTree, include::Includes), HldrError> {
+    // Each file is lexed and parsed on its own but the resulting trees are
+    // merged before analysis, so records can be referenced across files as
+    // if they had all been declared in a single file
+    let mut parse_tree = ParseTree::default();
+    let mut includes = include::Includes::default();
+
+    for data_file in options.data_file_paths()? {
+        let file_tree = includes.parse_file(&data_file)?;
+        parse_tree.nodes.extend(file_tree.nodes);
+        parse_tree.search_paths.extend(file_tree.search_paths);
+        parse_tree.variables.extend(file_tree.variables);
+        parse_tree.hooks.extend(file_tree.hooks);
+    }
+
+    Ok((parse_tree, includes))
+}
+
+/// The number of records of a table that are streamed together when streaming data
+/// files, which are loaded in as few statements as possible just as whole tables are
+const STREAMED_RECORDS: usize = 1000;
+
+/// Streams the tables of each data file given in the options through the analyzer to
+/// the loader as they are parsed, rather than parsing and analyzing every data file
+/// before loading anything, so that neither their tokens nor their trees are ever
+/// held in memory as a whole.
+async fn stream_data_files<C: loader::Connection>(connection: &mut C, options: &Options) -> Result<PlaceReport, HldrError> {
+    let mut connection = loader::SqlLog::new(connection, options)?;
+    let mut loader = loader::StreamLoader::new(&mut connection, options)?;
+    let mut analyzer = analyzer::StreamAnalyzer::tagged(tag_filter(options));
+
+    for path in options.data_file_paths()? {
+        let lines = LineIndex::read(&path)?;
+        let mut tables = stream_data_file(&path, &lines, options);
+
+        loop {
+            let table = match analyze_next(&mut tables, &mut analyzer, &lines)? {
+                Some(table) => table,
+                None => break,
+            };
+
+            loader.load(table).await.map_err(|e| locate_streamed_error(&lines, e.into()))?;
+        }
+    }
+
+    let report = loader.finish().await?;
+
+    connection.finish()?;
+    Ok(report)
+}
+
+/// Streams the tables of the data file as they are parsed, lexing a data file from the
+/// file as it is read, with the location of any lexer error.
+fn stream_data_file<'a>(
+    path: &'a Path,
+    lines: &'a LineIndex,
+    options: &'a Options,
+) -> Box<dyn Iterator<Item = Result<parser::StreamedTable, HldrError>> + 'a> {
+    let streamed: Box<dyn Iterator<Item = _>> = match fixtures::Format::of(path) {
+        // Fixtures are parsed whole, since their formats cannot be read incrementally
+        Some(format) => {
+            let tree = fs::read_to_string(path)
+                .map_err(HldrError::from)
+                .and_then(|input| fixtures::parse(path, &input, format));
+
+            match tree {
+                Ok(tree) => Box::new(fixtures::stream(tree).map(Ok)),
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            }
+        }
+        None => match FileChars::open(path) {
+            Ok(chars) => Box::new(parser::stream(file_tokens(chars, lines), STREAMED_RECORDS)),
+            Err(e) => Box::new(std::iter::once(Err(e.into()))),
+        },
+    };
+
+    Box::new(streamed.filter(move |streamed| match streamed {
+        Ok(streamed) => in_profile(&streamed.table, options),
+        Err(_) => true,
+    }))
+}
+
+/// Lexes the characters read from the data file, with the location of any lexer error.
+fn file_tokens(chars: FileChars, lines: &LineIndex) -> impl Iterator<Item = Result<lexer::tokens::Token, HldrError>> + '_ {
+    let read_error = chars.error.clone();
+    let mut tokens = lexer::tokens(chars);
+
+    // The lexer only sees the end of the file when it cannot be read any further, so
+    // the error reading it is returned instead of whatever was lexed from that
+    std::iter::from_fn(move || {
+        let token = tokens.next();
+
+        match read_error.take() {
+            Some(e) => Some(Err(e.into())),
+            None => token.map(|token| {
+                token.map_err(|e| {
+                    let location = lines.location(e.position);
+                    HldrError::from(e).at(Some(location))
+                })
+            }),
+        }
+    })
+}
+
+/// The characters of a file, read a line at a time as they are taken rather than all at
+/// once. Nothing is returned after an error reading the file, which is kept instead.
+struct FileChars {
+    reader: BufReader<fs::File>,
+    line: String,
+    /// How much of the line has been taken
+    taken: usize,
+    error: Rc<Cell<Option<io::Error>>>,
+}
+
+impl FileChars {
+    fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(fs::File::open(path)?),
+            line: String::new(),
+            taken: 0,
+            error: Rc::default(),
+        })
+    }
+}
+
+impl Iterator for FileChars {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.taken == self.line.len() {
+            self.line.clear();
+            self.taken = 0;
+
+            if let Err(e) = self.reader.read_line(&mut self.line) {
+                self.line.clear();
+                self.error.set(Some(e));
+            }
+        }
+
+        let c = self.line[self.taken..].chars().next()?;
+        self.taken += c.len_utf8();
+
+        Some(c)
+    }
+}
+
+/// Analyzes the next table streamed from the data file, if any, with the location of
+/// any error. Errors are never held across loading tables, as they cannot be sent
+/// between threads.
+fn analyze_next(
+    tables: &mut impl Iterator<Item = Result<parser::StreamedTable, HldrError>>,
+    analyzer: &mut analyzer::StreamAnalyzer,
+    lines: &LineIndex,
+) -> Result<Option<analyzer::ValidatedTable>, HldrError> {
+    // Lexer errors are already located, while parse errors are only converted
+    let streamed = match tables.next() {
+        Some(Ok(streamed)) => streamed,
+        Some(Err(e)) => {
+            let parse_error = e.error.downcast_ref::<parser::error::ParseError>();
+            let location = parse_error.map(|parse_error| match parse_error.position() {
+                Some(position) => lines.location(position),
+                None => lines.end_of(),
+            });
+
+            return Err(match location {
+                Some(location) => e.at(Some(location)),
+                None => e,
+            });
+        }
+        None => return Ok(None),
+    };
+
+    analyzer
+        .analyze(streamed)
+        .map(Some)
+        .map_err(|e| locate_streamed_error(lines, e.into()))
+}
+
+/// Adds the location in the streamed data file to the error if it is an analyzer or loader
+/// error with a position, which is already relative to the file.
+fn locate_streamed_error(lines: &LineIndex, error: HldrError) -> HldrError {
+    diagnostics::locate_error(error, |position, end| {
+        position.map(|position| lines.location(position).spanning(end.copied()))
+    })
+}
+
+/// Parses the data file at the path into its parse tree, without validating it, eg. to
+/// print it with `hldr parse`. Unlike [`parse`], imports are expanded into records and
+/// paths are resolved relative to the file, while includes are kept as they are.
+pub fn parse_file(path: &Path) -> Result<ParseTree, HldrError> {
+    let input = fs::read_to_string(path)?;
+    let (tree, _) = parse_data_file(path, &input, &mut Timings::default())?;
+
+    Ok(tree)
+}
+
+/// Parses a data file just as [`parse`] does, or a JSON or YAML fixture, but with the
+/// location of any error, along with the paths of the CSV files that it imports, adding
+/// how long it took to lex and parse to the timings.
+fn parse_data_file(path: &Path, input: &str, timings: &mut Timings) -> Result<(ParseTree, Vec<PathBuf>), HldrError> {
+    let started = Instant::now();
+
+    if let Some(format) = fixtures::Format::of(path) {
+        let tree = fixtures::parse(path, input, format)?;
+        timings.parse += started.elapsed();
+
+        return Ok((tree, Vec::new()));
+    }
+
+    let tokens = lexer::tokenize(input.chars()).map_err(|e| locate_lex_error(path, input, e))?;
+    timings.lex += started.elapsed();
+
+    let started = Instant::now();
+    let mut tree = parser::parse(tokens.into_iter()).map_err(|e| {
+        let location = parse_error_location(path, input, &e);
+        HldrError::from(e).at(Some(location))
+    })?;
+    timings.parse += started.elapsed();
+
+    resolve_file_paths(&mut tree, path.parent().unwrap_or(Path::new("")));
+    let imported_files = import::expand_imports(&mut tree, path, input)?;
+
+    Ok((tree, imported_files))
+}
+
+fn locate_lex_error(path: &Path, input: &str, error: lexer::error::LexError) -> HldrError {
+    let location = SourceLocation::new(path, input, error.position);
+    HldrError::from(error).at(Some(location))
+}
+
+/// Returns where the parse error occurred in the data file, which is the end of the
+/// file for unexpected ends of file.
+fn parse_error_location(path: &Path, input: &str, error: &parser::error::ParseError) -> SourceLocation {
+    match error.position() {
+        Some(position) => SourceLocation::new(path, input, position),
+        None => SourceLocation::end_of(path, input),
+    }
+}
+
+/// Makes the paths of files declared with `bytes('path')` relative to the directory
+/// of the data file rather than the working directory.
+fn resolve_file_paths(tree: &mut ParseTree, dir: &Path) {
+    let tables = tree.nodes.iter_mut().flat_map(|node| match node {
+        StructuralNode::Schema(schema) => schema.nodes.iter_mut().collect(),
+        StructuralNode::Table(table) => vec![table.as_mut()],
+    });
+
+    for table in tables {
+        let attributes = table.nodes.iter_mut().flat_map(|r| r.nodes.iter_mut()).chain(table.defaults.iter_mut());
+
+        for value in attributes.flat_map(|a| a.value.values_mut()) {
+            if let Value::File(file) = value {
+                *file = dir.join(&*file);
+            }
+        }
+    }
+}
+
+/// Analyzes and loads a parse tree, eg. one built with a
+/// [`ParseTreeBuilder`](parser::builder::ParseTreeBuilder) instead of parsed
+/// from data files, in a new transaction on the given client, returning what
+/// was loaded.
+///
+/// The transaction is committed only if the `commit` option is set, and
+/// the data file, connection, and dry run options are ignored.
+pub fn load_tree(client: &mut Client, parse_tree: ParseTree, options: &Options) -> Result<PlaceReport, HldrError> {
+    load_validated_tree(client, analyzer::analyze(parse_tree)?, options)
+}
+
+fn load_validated_tree(
+    client: &mut Client,
+    parse_tree: analyzer::ValidatedParseTree,
+    options: &Options,
+) -> Result<PlaceReport, HldrError> {
+    loader::block_on(load_in_transaction(client, parse_tree, options))
+}
+
+/// Loads the tree in a new transaction on the client, which is committed only if the
+/// `commit` option is set.
+async fn load_in_transaction<C: loader::TransactionClient>(
+    client: &mut C,
+    parse_tree: analyzer::ValidatedParseTree,
+    options: &Options,
+) -> Result<PlaceReport, HldrError> {
+    let mut transaction = client.begin(options).await?;
+    let report = loader::load_with(&mut transaction, parse_tree, options).await?;
+
+    finish_transaction::<C>(transaction, report, options).await
+}
+
+/// Commits the transaction that the report was loaded in with the `commit` option,
+/// or else rolls it back.
+async fn finish_transaction<C: loader::TransactionClient>(
+    transaction: C::Transaction<'_>,
+    mut report: PlaceReport,
+    options: &Options,
+) -> Result<PlaceReport, HldrError> {
+    if options.commit {
+        log::info!("Committing changes");
+        report.committed = true;
+        loader::log_statement(options, "COMMIT")?;
+        C::commit(transaction).await?;
+    } else {
+        log::info!("Rolling back changes, pass `--commit` to apply");
+        loader::log_statement(options, "ROLLBACK")?;
+    }
+
+    Ok(report)
+}
+
+/// The data to load with [`place_in_transaction`], either the contents of a data
+/// file or a parse tree that has already been parsed or built.
+pub enum Source<'a> {
+    Text(&'a str),
+    Tree(ParseTree),
+}
+
+impl<'a> From<&'a str> for Source<'a> {
+    fn from(text: &'a str) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<ParseTree> for Source<'_> {
+    fn from(tree: ParseTree) -> Self {
+        Self::Tree(tree)
+    }
+}
+
+impl Source<'_> {
+    fn into_tree(self) -> Result<ParseTree, HldrError> {
+        match self {
+            Self::Text(text) => parse(text),
+            Self::Tree(tree) => Ok(tree),
+        }
+    }
+}
+
+/// Loads the data within an existing transaction, returning what was loaded, so
+/// that the caller controls the connection and whether the transaction is
+/// committed or rolled back.
+///
+/// Only the options that affect how records are loaded are used, so the commit,
+/// data file, connection, dry run, and isolation level and other transaction
+/// setting options are all ignored.
+pub fn place_in_transaction<'a>(
+    transaction: &mut Transaction,
+    source: impl Into<Source<'a>>,
+    options: &Options,
+) -> Result<PlaceReport, HldrError> {
+    let parse_tree = analyzer::analyze(source.into().into_tree()?)?;
+
+    Ok(loader::load(transaction, parse_tree, options)?)
+}
+
+/// Loads the data within an existing `tokio-postgres` transaction, just as
+/// [`place_in_transaction`] does with a blocking one.
+#[cfg(feature = "async")]
+pub async fn place_in_transaction_async<'a>(
+    transaction: &mut tokio_postgres::Transaction<'_>,
+    source: impl Into<Source<'a>>,
+    options: &Options,
+) -> Result<PlaceReport, HldrError> {
+    let parse_tree = analyzer::analyze(source.into().into_tree()?)?;
+
+    Ok(loader::load_async(transaction, parse_tree, options).await?)
+}
+
+/// Loads the data into a SQLite connection, eg. an in-memory database for the fixtures
+/// of unit tests, returning what was loaded. Records are loaded within any transaction
+/// that the caller has begun, eg. by passing a `rusqlite::Transaction`.
+///
+/// The options that rely on Postgres are rejected rather than ignored, while the commit,
+/// data file, and connection options are ignored, as with [`place_in_transaction`].
+/// Tables in schemas are loaded into the attached databases with the same names, or
+/// else into the main database, with a warning.
+#[cfg(feature = "sqlite")]
+pub fn place_in_sqlite<'a>(
+    connection: &rusqlite::Connection,
+    source: impl Into<Source<'a>>,
+    options: &Options,
+) -> Result<PlaceReport, HldrError> {
+    loader::backend::check_options::<loader::sqlite::Sqlite>(options)?;
+
+    let parse_tree = analyzer::analyze(source.into().into_tree()?)?;
+    let mut sqlite = loader::sqlite::Sqlite::new(connection);
+
+    Ok(loader::backend::load(&mut sqlite, parse_tree, options)?)
+}
+
+/// Parses the contents of a data file into its parse tree, without validating it, eg. for
+/// tooling that inspects or rewrites data files. Includes and imports are rejected, since
+/// their paths are only resolved relative to the data files given in the options.
+pub fn parse(input: &str) -> Result<ParseTree, HldrError> {
+    let tokens = lexer::tokenize(input.chars())?;
+    let tree = parser::parse(tokens.into_iter())?;
+
+    if let Some(include) = tree.includes.first() {
+        return Err(include::IncludeError::not_in_file(include.path.clone()).into());
+    }
+    if let Some(path) = import::first_import(&tree) {
+        return Err(import::ImportError::not_in_file(path.to_owned()).into());
+    }
+
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod options_tests {
+    use super::{DisableTriggers, IsolationLevel, Options, PathBuf};
+
+    #[test]
+    fn test_single_data_file() {
+        let options: Options = toml::from_str(r#"data_file = "a.hldr""#).unwrap();
+        assert_eq!(options.data_files, vec![PathBuf::from("a.hldr")]);
+    }
+
+    #[test]
+    fn test_multiple_data_files() {
+        let options: Options = toml::from_str(r#"data_files = ["a.hldr", "b/"]"#).unwrap();
+        assert_eq!(options.data_files, vec![PathBuf::from("a.hldr"), PathBuf::from("b/")]);
+    }
+
+    #[test]
+    fn test_transaction_settings() {
+        let options: Options = toml::from_str(
+            r#"
+            defer_constraints = true
+            disable_triggers = "replica"
+            isolation_level = "repeatable-read"
+            lock_timeout = "5s"
+            role = "seeder"
+            search_path = ["app", "public"]
+            synchronous_commit = "off"
+            "#,
+        )
+        .unwrap();
+
+        assert!(options.defer_constraints);
+        assert_eq!(options.disable_triggers, Some(DisableTriggers::Replica));
+        assert_eq!(options.isolation_level, Some(IsolationLevel::RepeatableRead));
+        assert_eq!(options.lock_timeout.as_deref(), Some("5s"));
+        assert_eq!(options.role.as_deref(), Some("seeder"));
+        assert_eq!(options.search_path, Some(vec!["app".to_owned(), "public".to_owned()]));
+        assert_eq!(options.statement_timeout, None);
+        assert_eq!(options.synchronous_commit.as_deref(), Some("off"));
+    }
+
+    #[test]
+    fn test_selection() {
+        let options: Options = toml::from_str(
+            r#"
+            only_tags = ["smoke", "billing"]
+            profiles = ["demo"]
+            skip_tags = ["slow"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(options.only_tags, vec!["smoke".to_owned(), "billing".to_owned()]);
+        assert_eq!(options.profiles, vec!["demo".to_owned()]);
+        assert_eq!(options.skip_tags, vec!["slow".to_owned()]);
+    }
+
+    #[test]
+    fn test_default_data_file() {
+        let options: Options = toml::from_str("").unwrap();
+        assert_eq!(options.data_file_paths().unwrap(), vec![PathBuf::from("place.hldr")]);
+    }
+}
+
+#[cfg(test)]
+mod check_tests {
+    use super::{check, Options, SourceLocation};
+    use std::fs;
+
+    #[test]
+    fn test_check() {
+        let dir = std::env::temp_dir().join(format!("hldr-check-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.hldr"), "table t1 (\n  rec1 ()\n)\n").unwrap();
+        fs::write(dir.join("b.hldr"), "table t1 (\n  (col1 @rec1.id)\n)\n").unwrap();
+        fs::write(dir.join("c.hldr"), "\ntable t1 (\n  rec1 ()\n)\n").unwrap();
+
+        let options = |files: &[&str]| Options {
+            data_files: files.iter().map(|file| dir.join(file)).collect(),
+            ..Options::default()
+        };
+        let error = check(&Options {
+            data_files: vec![dir.clone()],
+            ..Options::default()
+        })
+        .unwrap_err();
+
+        // The second file can only reference the record from the first when they are loaded
+        // together, but in either order
+        assert!(check(&options(&["a.hldr", "b.hldr"])).is_ok());
+        assert!(check(&options(&["b.hldr", "a.hldr"])).is_ok());
+        assert_eq!(check(&options(&["b.hldr"])).unwrap_err().to_string(), "record `t1.rec1` not found on line 2");
+        assert_eq!(error.to_string(), "duplicate record `rec1` in scope `t1` on line 3");
+        assert_eq!(error.location.unwrap().path, dir.join("c.hldr"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_profiles() {
+        let dir = std::env::temp_dir().join(format!("hldr-check-profiles-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.hldr"), "table t1 (\n  rec1 ()\n)\nprofile demo (\n  table t2 (\n    (col1 @rec2.id)\n  )\n)\n").unwrap();
+
+        let options = |profiles: &[&str], stream| Options {
+            data_files: vec![dir.join("a.hldr")],
+            profiles: profiles.iter().map(|profile| profile.to_string()).collect(),
+            stream,
+            ..Options::default()
+        };
+
+        // Sections of profiles that are not loaded are not analyzed either
+        assert!(check(&options(&[], false)).is_ok());
+        assert!(check(&options(&[], true)).is_ok());
+        assert_eq!(check(&options(&["demo"], false)).unwrap_err().to_string(), "record `t2.rec2` not found on line 6");
+        assert_eq!(check(&options(&["demo"], true)).unwrap_err().to_string(), "record `t2.rec2` not found on line 6");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_streamed() {
+        let dir = std::env::temp_dir().join(format!("hldr-check-streamed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.hldr"), "table t1 (\n  rec1 ()\n)\n").unwrap();
+        fs::write(dir.join("b.hldr"), "table t1 (\n  (col1 @rec1.id)\n)\n").unwrap();
+
+        let options = |files: &[&str]| Options {
+            data_files: files.iter().map(|file| dir.join(file)).collect(),
+            stream: true,
+            ..Options::default()
+        };
+
+        // Records can only reference records streamed before them
+        assert!(check(&options(&["a.hldr", "b.hldr"])).is_ok());
+
+        let error = check(&options(&["b.hldr", "a.hldr"])).unwrap_err();
+        assert_eq!(error.to_string(), "record `t1.rec1` not found on line 2");
+        let location = error.location.unwrap();
+        assert_eq!(location.path, dir.join("b.hldr"));
+        assert_eq!(location.line, "  (col1 @rec1.id)");
+
+        // Errors in files that are read as they are lexed are located just the same
+        fs::write(dir.join("c.hldr"), "table t1 (\n  (col1 'é') (col1 #)\n)\n").unwrap();
+        let location = check(&options(&["c.hldr"])).unwrap_err().location.unwrap();
+        assert_eq!(location.position, crate::Position { line: 2, column: 20 });
+        assert_eq!(location.line, "  (col1 'é') (col1 #)");
+
+        fs::write(dir.join("d.hldr"), "table t1 (\n  (col1 1)\n").unwrap();
+        let location = check(&options(&["d.hldr"])).unwrap_err().location.unwrap();
+        assert_eq!(location, SourceLocation::end_of(&dir.join("d.hldr"), "table t1 (\n  (col1 1)\n"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod pipeline_tests {
+    use super::ast::{text, ParseTreeBuilder, StructuralNode};
+    use super::error::{HldrErrorKind, ParseError};
+    use super::{parse, validate, Options};
+
+    #[test]
+    fn test_parse_and_validate() {
+        let tree = parse("table person (\n  bob (name 'Bob')\n)\n").unwrap();
+        let built = ParseTreeBuilder::new().table("person").record("bob").attr("name", text("Bob")).build();
+        assert_eq!(tree, built);
+
+        let validated = validate(tree, &Options::default()).unwrap();
+        let StructuralNode::Table(table) = &validated.inner().nodes[0] else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.nodes[0].nodes[0].value, text("Bob"));
+
+        let error = validate(parse("table person (\n  (name @alice.name)\n)\n").unwrap(), &Options::default()).unwrap_err();
+        assert!(matches!(error.kind, HldrErrorKind::ValidateError));
+        assert_eq!(error.to_string(), "record `person.alice` not found on line 2");
+
+        let error = parse("table person (").unwrap_err();
+        assert!(matches!(error.kind, HldrErrorKind::ParseError));
+        assert!(error.downcast_ref::<ParseError>().is_some());
+    }
+}
+
 #[cfg(test)]
 mod root_tests {
     /*
@@ -0,0 +1,328 @@
+//! Checks data files for declarations that are valid but are likely mistakes or make the
+//! files harder to maintain, with rules whose severity can be configured in the options
+//! file, eg.
+//!
+//! ```toml
+//! [lint]
+//! unused-record = "off"
+//! magic-foreign-key = "error"
+//! ```
+
+use crate::analyzer::{dedupe, primary_key_reference, record_key, table_scope};
+use crate::diagnostics::{self, SourceLocation, SourceMap};
+use crate::parser::nodes::{Attribute, ParseTree, Reference, StructuralIdentity, StructuralNode, Table, Value};
+use crate::Position;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// Anonymous records with the same attributes as an earlier one in the same table
+    DuplicateRecord,
+    /// Tables that are declared more than once
+    DuplicateTable,
+    /// Numbers given for columns that look like foreign keys, eg. `person_id 3`, rather
+    /// than references to the records they point to
+    MagicForeignKey,
+    /// References from tables in a schema to records in tables outside of any schema,
+    /// which read as if they were to tables in the same schema
+    UnqualifiedReference,
+    /// Named records that are never referenced
+    UnusedRecord,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Rule::DuplicateRecord => "duplicate-record",
+            Rule::DuplicateTable => "duplicate-table",
+            Rule::MagicForeignKey => "magic-foreign-key",
+            Rule::UnqualifiedReference => "unqualified-reference",
+            Rule::UnusedRecord => "unused-record",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Off,
+    #[default]
+    Warn,
+    Error,
+}
+
+/// The severity of each rule, which is `warn` for any rule that is not given.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LintOptions {
+    pub duplicate_record: Severity,
+    pub duplicate_table: Severity,
+    pub magic_foreign_key: Severity,
+    pub unqualified_reference: Severity,
+    pub unused_record: Severity,
+}
+
+impl LintOptions {
+    pub fn severity(&self, rule: Rule) -> Severity {
+        match rule {
+            Rule::DuplicateRecord => self.duplicate_record,
+            Rule::DuplicateTable => self.duplicate_table,
+            Rule::MagicForeignKey => self.magic_foreign_key,
+            Rule::UnqualifiedReference => self.unqualified_reference,
+            Rule::UnusedRecord => self.unused_record,
+        }
+    }
+}
+
+/// A declaration in a data file that breaks one of the rules.
+#[derive(Debug, PartialEq)]
+pub struct Lint {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<SourceLocation>,
+}
+
+impl Lint {
+    /// Renders the lint with its rule, followed by the line of the data file it is on.
+    pub fn render(&self) -> String {
+        let label = match self.severity {
+            Severity::Error => "error",
+            _ => "warning",
+        };
+        let message = format!("{} [{}]", self.message, self.rule);
+
+        format!("{}: {}", label, diagnostics::annotate(&message, self.location.as_ref()))
+    }
+}
+
+/// Checks the tree parsed from data files against every rule that is not turned off,
+/// returning what breaks them in the order they were declared.
+pub(crate) fn lint(tree: &ParseTree, options: &LintOptions, sources: &SourceMap) -> Vec<Lint> {
+    let mut found: Vec<(Rule, String, Option<Position>)> = Vec::new();
+
+    for duplicate in dedupe::duplicate_records(tree) {
+        found.push((Rule::DuplicateRecord, duplicate_record_message(&duplicate, sources), duplicate.position));
+    }
+
+    let tables = tables(tree);
+    let mut declared: HashMap<(Option<&str>, &str), Option<Position>> = HashMap::new();
+    let mut referenced = HashSet::new();
+
+    for (schema, table) in &tables {
+        let name = (schema.map(|s| s.name.as_str()), table.identity.name.as_str());
+
+        // Deletes are declared as tables of their own
+        let is_delete = table.nodes.iter().any(|r| r.delete);
+
+        match declared.get(&name) {
+            _ if is_delete => {}
+            Some(original) => found.push((
+                Rule::DuplicateTable,
+                format!("table `{}` is already declared {}", qualified_name(*schema, table), at(*original, sources)),
+                table.position,
+            )),
+            None => {
+                declared.insert(name, table.position);
+            }
+        }
+
+        let table_scope = table_scope(*schema, &table.identity);
+
+        // Records that are extended are used as much as those that are referenced
+        for base in table.nodes.iter().filter_map(|r| r.extends.as_ref()) {
+            referenced.insert(format!("{}.{}", table_scope, base));
+        }
+
+        for attribute in table.nodes.iter().flat_map(|r| &r.nodes).chain(&table.defaults) {
+            if let Some(lint) = magic_foreign_key(attribute) {
+                found.push(lint);
+            }
+
+            for reference in attribute.value.references() {
+                // References that could be to a record's primary key are counted as such,
+                // as any record they name is most likely what is meant
+                referenced.extend(record_key(&table_scope, reference));
+                referenced.extend(primary_key_reference(reference).and_then(|r| record_key(&table_scope, &r)));
+
+                if let (Some(schema), Reference::TableLevel(r)) = (schema, reference) {
+                    let message = format!(
+                        "reference to record `{}.{}` in schema `{}` is to a table outside of any schema",
+                        r.table, r.record, schema.name,
+                    );
+                    found.push((Rule::UnqualifiedReference, message, attribute.position));
+                }
+            }
+        }
+    }
+
+    for (schema, table) in &tables {
+        let table_scope = table_scope(*schema, &table.identity);
+
+        for record in &table.nodes {
+            if let Some(name) = &record.name {
+                let key = format!("{}.{}", table_scope, name);
+
+                if !referenced.contains(&key) {
+                    found.push((Rule::UnusedRecord, format!("record `{}` is never referenced", key), record.position));
+                }
+            }
+        }
+    }
+
+    found.sort_by_key(|(_, _, position)| position.map(|p| (p.line, p.column)));
+    found
+        .into_iter()
+        .filter_map(|(rule, message, position)| {
+            let severity = options.severity(rule);
+
+            (severity != Severity::Off).then(|| Lint {
+                rule,
+                severity,
+                message,
+                location: position.and_then(|p| sources.location(p)),
+            })
+        })
+        .collect()
+}
+
+/// Describes the duplicate record and where the record it duplicates was declared.
+pub(crate) fn duplicate_record_message(duplicate: &dedupe::DuplicateRecord, sources: &SourceMap) -> String {
+    format!(
+        "anonymous record in table `{}` is identical to the one {}",
+        duplicate.table,
+        at(duplicate.original, sources),
+    )
+}
+
+/// Describes where the position is in the data files, eg. `at place.hldr:4:3`.
+fn at(position: Option<Position>, sources: &SourceMap) -> String {
+    match position.and_then(|p| sources.location(p)) {
+        Some(location) => format!(
+            "at {}:{}:{}",
+            location.path.display(),
+            location.position.line,
+            location.position.column,
+        ),
+        None => "earlier".to_owned(),
+    }
+}
+
+fn tables(tree: &ParseTree) -> Vec<(Option<&StructuralIdentity>, &Table)> {
+    tree.nodes
+        .iter()
+        .flat_map(|node| match node {
+            StructuralNode::Schema(schema) => schema.nodes.iter().map(|t| (Some(&schema.identity), t)).collect(),
+            StructuralNode::Table(table) => vec![(None, table.as_ref())],
+        })
+        .collect()
+}
+
+fn qualified_name(schema: Option<&StructuralIdentity>, table: &Table) -> String {
+    match schema {
+        Some(schema) => format!("{}.{}", schema.name, table.identity.name),
+        None => table.identity.name.clone(),
+    }
+}
+
+/// Finds a number given for a column whose name ends in `_id`, which is likely a
+/// foreign key whose value should come from a reference to the record it points to.
+fn magic_foreign_key(attribute: &Attribute) -> Option<(Rule, String, Option<Position>)> {
+    match &attribute.value {
+        Value::Number(n) if attribute.name.ends_with("_id") => Some((
+            Rule::MagicForeignKey,
+            format!("column `{}` is given the number {} rather than a reference to a record", attribute.name, n),
+            attribute.position,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    fn lints(input: &str, options: &LintOptions) -> Vec<Lint> {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let mut tree = parser::parse(tokens.into_iter()).unwrap();
+        let mut sources = SourceMap::default();
+        sources.add(PathBuf::from("place.hldr"), input.to_owned(), &mut tree);
+
+        lint(&tree, options, &sources)
+    }
+
+    fn linted(input: &str, options: &LintOptions) -> Vec<(Rule, String, usize)> {
+        lints(input, options)
+            .into_iter()
+            .map(|lint| (lint.rule, lint.message, lint.location.unwrap().position.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let input = "table person (
+  kevin (name 'Kevin')
+  abstract base (age 30)
+  bob extends base (name 'Bob')
+  (name 'Bob')
+  (name 'Bob')
+)
+schema zoo (
+  table pet (
+    (owner_id @person.bob.id, keeper_id 4, age 3)
+  )
+)
+table person (
+  (name @bob.name)
+)
+delete from person where (name 'Bob')
+";
+
+        assert_eq!(
+            linted(input, &LintOptions::default()),
+            vec![
+                (Rule::UnusedRecord, "record `person.kevin` is never referenced".to_owned(), 2),
+                (
+                    Rule::DuplicateRecord,
+                    "anonymous record in table `person` is identical to the one at place.hldr:5:3".to_owned(),
+                    6,
+                ),
+                (
+                    Rule::UnqualifiedReference,
+                    "reference to record `person.bob` in schema `zoo` is to a table outside of any schema".to_owned(),
+                    10,
+                ),
+                (
+                    Rule::MagicForeignKey,
+                    "column `keeper_id` is given the number 4 rather than a reference to a record".to_owned(),
+                    10,
+                ),
+                (Rule::DuplicateTable, "table `person` is already declared at place.hldr:1:1".to_owned(), 13),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_severities() {
+        let input = "table person (\n  kevin (name 'Kevin', team_id 1)\n)\n";
+        let options: LintOptions = toml::from_str("unused-record = \"off\"\nmagic-foreign-key = \"error\"").unwrap();
+        let lints = lints(input, &options);
+
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].severity, Severity::Error);
+        assert_eq!(
+            lints[0].render(),
+            "error: column `team_id` is given the number 1 rather than a reference to a record [magic-foreign-key]
+ --> place.hldr:2:24
+  |
+2 |   kevin (name 'Kevin', team_id 1)
+  |                        ^",
+        );
+        assert!(toml::from_str::<LintOptions>("unused = \"off\"").is_err());
+    }
+}
@@ -0,0 +1,734 @@
+//! Loads data files into databases other than Postgres, one record at a time.
+//!
+//! The [`Loader`](super::Loader) relies on Postgres for much of what it does, eg.
+//! returning the rows of inserted records, casting text parameters to the types of
+//! their columns, and `COPY`, so other databases instead implement [`Backend`], which
+//! emulates whatever they do not support.
+
+use super::error::{ClientError, LoadError};
+use super::params::unquote_text;
+use super::report::{PlaceReport, RecordRow, SkippedTable, TableReport};
+use super::{
+    count_statement, env, expression, hook_statements, log_inserted, namespaced, record_key, referenced_column, table_scope, ExpectedCounts,
+    LoadResult,
+};
+use crate::analyzer::ValidatedParseTree;
+use crate::parser::nodes::{Attribute, HookTiming, Record, Reference, StructuralIdentity, StructuralNode, Table, TextSegment, TimeOffset, Value};
+use crate::{OnConflict, Options};
+use std::collections::HashMap;
+use std::{fs, mem, time::Instant};
+use uuid::Uuid;
+
+/// A database that data files can be loaded into, within a transaction that is begun
+/// once connected and then committed or rolled back with [`Backend::execute`].
+pub(crate) trait Backend: Sized {
+    /// The name of the database, eg. to say what it does not support
+    const NAME: &'static str;
+
+    fn connect(connstr: &str) -> Result<Self, ClientError>;
+
+    /// Quotes an identifier, eg. a table or column name
+    fn quote(identifier: &str) -> String;
+
+    /// Renders the placeholder for the parameter at the index, eg. `$1` or `?`
+    fn placeholder(index: usize) -> String;
+
+    /// Renders the SQL for the time of the transaction with the offset, if any
+    fn now(offset: Option<&TimeOffset>) -> LoadResult<String>;
+
+    /// Begins the transaction, with the isolation level given in the options, if any
+    fn begin(&mut self, options: &Options) -> LoadResult<()>;
+
+    /// Executes a statement without any parameters, eg. to commit the transaction
+    fn execute(&mut self, statement: &str) -> LoadResult<()>;
+
+    /// Selects the single value returned by the query, eg. of a SQL fragment, as text
+    fn select(&mut self, query: &str, params: &[Param]) -> LoadResult<Option<String>>;
+
+    /// Looks up the names of the table's primary key columns, which are empty if it has none
+    fn primary_key(&mut self, table: &TableName) -> LoadResult<Vec<String>>;
+
+    /// Inserts a row with the values of the columns, returning the whole row with each
+    /// column's value as text if `returning` is set, which databases without `RETURNING`
+    /// have to select again once it is inserted.
+    fn insert(
+        &mut self,
+        table: &TableName,
+        columns: &[&str],
+        values: &[Param],
+        returning: bool,
+    ) -> LoadResult<Option<RecordRow>>;
+}
+
+/// A table by its unquoted names, where the schema is `None` for top-level tables.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct TableName {
+    pub schema: Option<String>,
+    pub table: String,
+}
+
+impl TableName {
+    fn new(schema: Option<&StructuralIdentity>, table: &Table) -> Self {
+        Self {
+            schema: schema.map(|s| s.name.clone()),
+            table: table.identity.name.clone(),
+        }
+    }
+
+    pub(crate) fn qualified<B: Backend>(&self) -> String {
+        match &self.schema {
+            Some(schema) => format!("{}.{}", B::quote(schema), B::quote(&self.table)),
+            None => B::quote(&self.table),
+        }
+    }
+}
+
+/// A value bound to a statement, which is sent as text unless the database has to
+/// know its type to convert it to the type of its column.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Param {
+    Null,
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Text(String),
+}
+
+impl Param {
+    fn text(&self) -> Option<String> {
+        match self {
+            Param::Null => None,
+            Param::Bool(b) => Some(b.to_string()),
+            Param::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            Param::Text(text) => Some(text.clone()),
+        }
+    }
+}
+
+impl From<Option<String>> for Param {
+    fn from(value: Option<String>) -> Self {
+        value.map_or(Param::Null, Param::Text)
+    }
+}
+
+/// Fails for options that rely on features of Postgres, which are checked before
+/// connecting rather than ignored.
+pub(crate) fn check_options<B: Backend>(options: &Options) -> LoadResult<()> {
+    let unsupported = [
+        ("bulk", options.bulk),
+        ("create_schemas", options.create_schemas),
+        ("create_tables", options.create_tables),
+        ("defer_constraints", options.defer_constraints),
+        ("disable_triggers", options.disable_triggers.is_some()),
+        ("dry_run", options.dry_run),
+        ("fixed_now", options.fixed_now.is_some()),
+        ("lock_timeout", options.lock_timeout.is_some()),
+        ("log_sql", options.log_sql.is_some()),
+        ("on_conflict", options.on_conflict != OnConflict::Error),
+        ("output", options.output.is_some()),
+        ("refresh_matviews", options.refresh_matviews),
+        ("reset_sequences", options.reset_sequences),
+        ("role", options.role.is_some()),
+        ("search_path", options.search_path.is_some()),
+        ("skip_existing", !options.skip_existing.is_empty()),
+        ("statement_timeout", options.statement_timeout.is_some()),
+        ("stream", options.stream),
+        ("strict_numbers", options.strict_numbers),
+        ("synchronous_commit", options.synchronous_commit.is_some()),
+        ("truncate", options.truncate),
+        ("verify_schema", options.verify_schema),
+    ];
+
+    match unsupported.into_iter().find(|(_, set)| *set) {
+        Some((option, _)) => Err(LoadError::unsupported(B::NAME, format!("the `{}` option", option))),
+        None => Ok(()),
+    }
+}
+
+/// Loads the tree within the backend's transaction, inserting each record in turn.
+pub(crate) fn load<B: Backend>(backend: &mut B, tree: ValidatedParseTree, options: &Options) -> LoadResult<PlaceReport> {
+    if !tree.inner().search_paths.is_empty() {
+        return Err(LoadError::unsupported(B::NAME, "`set search_path`"));
+    }
+
+    let tables = tree.inner().nodes.iter().flat_map(|node| match node {
+        StructuralNode::Schema(schema) => schema.nodes.iter().collect(),
+        StructuralNode::Table(table) => vec![&**table],
+    });
+
+    for table in tables {
+        if table.role.is_some() {
+            return Err(LoadError::unsupported(B::NAME, "roles declared with `as role`"));
+        }
+
+        if !table.settings.is_empty() {
+            return Err(LoadError::unsupported(B::NAME, "settings declared with `with (...)`"));
+        }
+    }
+
+    let started = Instant::now();
+    let after = hook_statements(tree.inner(), HookTiming::After);
+
+    for statement in hook_statements(tree.inner(), HookTiming::Before) {
+        backend.execute(&statement)?;
+    }
+
+    let mut loader = RowLoader {
+        backend,
+        collect_errors: options.collect_errors,
+        keep_going: options.keep_going,
+        refmap: HashMap::new(),
+        fragments: options.cache_fragments.then(HashMap::new),
+        times: HashMap::new(),
+        expected_counts: ExpectedCounts::default(),
+        failed_inserts: Vec::new(),
+        report: PlaceReport::default(),
+    };
+
+    for node in tree.into_inner().nodes {
+        match node {
+            StructuralNode::Schema(schema) => {
+                for table in &schema.nodes {
+                    loader.load_or_skip_table(Some(&schema.identity), table)?;
+                }
+            }
+            StructuralNode::Table(table) => loader.load_or_skip_table(None, &table)?,
+        }
+    }
+
+    if !loader.failed_inserts.is_empty() {
+        return Err(LoadError::failed_inserts(loader.failed_inserts));
+    }
+
+    for statement in after {
+        loader.backend.execute(&statement)?;
+    }
+
+    loader.verify_counts()?;
+    loader.report.duration = started.elapsed();
+
+    Ok(loader.report)
+}
+
+/// The row of a named record along with the table it was inserted into, to look up
+/// its primary key for references to the record itself.
+struct InsertedRow {
+    table: TableName,
+    row: RecordRow,
+}
+
+struct RowLoader<'a, B: Backend> {
+    backend: &'a mut B,
+    collect_errors: bool,
+    keep_going: bool,
+    refmap: HashMap<String, InsertedRow>,
+    /// The values of the SQL fragments already selected in the transaction, by their SQL,
+    /// if caching them with the `cache_fragments` option
+    fragments: Option<HashMap<String, Option<String>>>,
+    /// The times already selected for each offset from the time of the transaction
+    times: HashMap<Option<TimeOffset>, Option<String>>,
+    expected_counts: ExpectedCounts,
+    /// The errors of the inserts that failed and were rolled back with the `collect_errors` option
+    failed_inserts: Vec<LoadError>,
+    report: PlaceReport,
+}
+
+impl<B: Backend> RowLoader<'_, B> {
+    /// Loads the table, or with the `keep_going` option, loads it within a savepoint so
+    /// that if it fails, the error is reported and the table is skipped, as Postgres does.
+    fn load_or_skip_table(&mut self, schema: Option<&StructuralIdentity>, table: &Table) -> LoadResult<()> {
+        if !self.keep_going {
+            return self.load_table(schema, table).map_err(|e| e.at(table.position));
+        }
+
+        self.backend.execute("SAVEPOINT hldr_table")?;
+
+        let error = match self.load_table(schema, table).map_err(|e| e.at(table.position)) {
+            Ok(()) => return self.backend.execute("RELEASE SAVEPOINT hldr_table"),
+            Err(error) => error,
+        };
+
+        self.backend.execute("ROLLBACK TO SAVEPOINT hldr_table")?;
+        log::error!("Skipping table {}: {}", TableName::new(schema, table).qualified::<B>(), error);
+
+        let table_scope = table_scope(schema, table);
+
+        for name in table.nodes.iter().filter_map(|r| r.name.as_ref()) {
+            self.refmap.remove(&format!("{}.{}", table_scope, name));
+        }
+
+        self.report.skipped.push(SkippedTable {
+            schema: schema.map(|s| s.name.clone()),
+            table: table.identity.name.clone(),
+            error: error.to_string(),
+        });
+
+        Ok(())
+    }
+
+    fn load_table(&mut self, schema: Option<&StructuralIdentity>, table: &Table) -> LoadResult<()> {
+        if table.truncate {
+            return Err(LoadError::unsupported(B::NAME, "truncating tables"));
+        }
+
+        if table.conflict_target.is_some() {
+            return Err(LoadError::unsupported(B::NAME, "conflict targets"));
+        }
+
+        if table.nodes.iter().any(|record| record.update_where.is_some()) {
+            return Err(LoadError::unsupported(B::NAME, "records declared with `update`"));
+        }
+
+        if table.nodes.iter().any(|record| record.delete) {
+            return Err(LoadError::unsupported(B::NAME, "deletes declared with `delete from`"));
+        }
+
+        let started = Instant::now();
+        let table_name = TableName::new(schema, table);
+        let table_scope = table_scope(schema, table);
+        let mut report = TableReport::new(table_name.schema.clone(), table_name.table.clone());
+
+        for record in &table.nodes {
+            let row = match self.insert_or_collect(&table_name, &table_scope, record)? {
+                Some(row) => row,
+                None => continue,
+            };
+
+            report.rows_inserted += 1;
+
+            if let (Some(name), Some(row)) = (&record.name, row) {
+                report.records.insert(name.clone(), row.clone());
+                self.refmap.insert(
+                    format!("{}.{}", table_scope, name),
+                    InsertedRow { table: table_name.clone(), row },
+                );
+            }
+        }
+
+        // Records are reported with their namespace, as with Postgres
+        if let Some(namespace) = &table.namespace {
+            report.records = mem::take(&mut report.records)
+                .into_iter()
+                .map(|(name, row)| (namespaced(Some(namespace), name), row))
+                .collect();
+        }
+
+        log_inserted(report.rows_inserted, &table_name.qualified::<B>());
+        report.duration = started.elapsed();
+        self.report.merge(report);
+        self.expected_counts.add(&table_name.qualified::<B>(), table);
+
+        Ok(())
+    }
+
+    /// Counts the rows of each table declared with `expect N rows`, as with Postgres.
+    fn verify_counts(&mut self) -> LoadResult<()> {
+        for (qualified_table_name, expected) in &self.expected_counts.0 {
+            let count = self.backend.select(&count_statement(qualified_table_name), &[])?;
+            let found = count.and_then(|count| count.parse().ok()).unwrap_or_default();
+
+            if found != *expected {
+                return Err(LoadError::row_count_mismatch(qualified_table_name, *expected, found));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Inserts the record, or with the `collect_errors` option, inserts it within a savepoint
+    /// so that if it fails, it is rolled back and the error is collected while loading
+    /// continues, returning the row of the record if it was inserted, as Postgres does.
+    fn insert_or_collect(
+        &mut self,
+        table_name: &TableName,
+        table_scope: &str,
+        record: &Record,
+    ) -> LoadResult<Option<Option<RecordRow>>> {
+        if !self.collect_errors {
+            return self.insert(table_name, table_scope, record).map(Some);
+        }
+
+        self.backend.execute("SAVEPOINT hldr_insert")?;
+
+        let error = match self.insert(table_name, table_scope, record) {
+            Ok(row) => return self.backend.execute("RELEASE SAVEPOINT hldr_insert").map(|_| Some(row)),
+            Err(error) => error,
+        };
+
+        self.backend.execute("ROLLBACK TO SAVEPOINT hldr_insert")?;
+        log::debug!("Collected error: {}", error);
+        self.failed_inserts.push(error);
+
+        Ok(None)
+    }
+
+    fn insert(&mut self, table_name: &TableName, table_scope: &str, record: &Record) -> LoadResult<Option<RecordRow>> {
+        // Columns set to `default` are left out, as not every backend accepts `DEFAULT`
+        // as a value
+        let columns: Vec<&str> = record
+            .nodes
+            .iter()
+            .filter(|a| a.value != Value::Default)
+            .map(|a| a.name.as_str())
+            .collect();
+        let values = self.bind_values(table_scope, record).map_err(|e| e.at(record.position))?;
+
+        self.backend
+            .insert(table_name, &columns, &values, record.name.is_some())
+            .map_err(|e| e.at(record.position))
+    }
+
+    fn bind_values(&mut self, table_scope: &str, record: &Record) -> LoadResult<Vec<Param>> {
+        // Column references only refer to earlier columns within the same record
+        let mut columns: HashMap<&str, Param> = HashMap::new();
+        let mut values = Vec::new();
+
+        for attribute in record.nodes.iter().filter(|a| a.value != Value::Default) {
+            let value = self
+                .bind(table_scope, attribute, &attribute.value, &columns)
+                .map_err(|e| e.in_attribute(attribute))?;
+
+            columns.insert(&attribute.name, value.clone());
+            values.push(value);
+        }
+
+        Ok(values)
+    }
+
+    fn bind(
+        &mut self,
+        table_scope: &str,
+        attribute: &Attribute,
+        value: &Value,
+        columns: &HashMap<&str, Param>,
+    ) -> LoadResult<Param> {
+        Ok(match value {
+            Value::Bool(b) => Param::Bool(*b),
+            Value::Default => unreachable!("defaults should have been left out of the insert"),
+            Value::EnvVar(name) => Param::Text(env::var(name)?),
+            Value::Expression(expression) => {
+                let left = self.bind(table_scope, attribute, &expression.left, columns)?.text();
+                let right = self.bind(table_scope, attribute, &expression.right, columns)?.text();
+                let evaluated = format!(
+                    "{} {} {}",
+                    left.as_deref().unwrap_or("NULL"),
+                    expression.operator,
+                    right.as_deref().unwrap_or("NULL"),
+                );
+
+                expression::evaluate(left, expression.operator, right)
+                    .map_err(|reason| LoadError::invalid_expression(&attribute.name, &evaluated, reason))?
+                    .into()
+            }
+            Value::File(path) => Param::Bytes(fs::read(path).map_err(|e| LoadError::file(path, e))?),
+            Value::Hex(digits) => Param::Bytes(hex_bytes(digits)),
+            Value::Index => unreachable!("index() should have been expanded by the analyzer"),
+            Value::Variable(_) => unreachable!("variables should have been substituted by the analyzer"),
+            Value::InterpolatedSqlFragment(segments) => {
+                let mut fragment = String::new();
+                let mut params = Vec::new();
+
+                for segment in segments {
+                    match segment {
+                        TextSegment::Literal(sql) => fragment.push_str(sql),
+                        TextSegment::Value(value) => {
+                            params.push(self.bind(table_scope, attribute, value, columns)?);
+                            fragment.push_str(&B::placeholder(params.len() - 1));
+                        }
+                    }
+                }
+
+                self.select(&fragment, &params)?.into()
+            }
+            // Embedded values that are null are left out of the text
+            Value::InterpolatedText(segments) => {
+                let mut text = String::new();
+
+                for segment in segments {
+                    match segment {
+                        TextSegment::Literal(literal) => text.push_str(&env::interpolate(literal)?),
+                        TextSegment::Value(value) => {
+                            let value = self.bind(table_scope, attribute, value, columns)?;
+                            text.push_str(&value.text().unwrap_or_default());
+                        }
+                    }
+                }
+
+                Param::Text(text)
+            }
+            Value::Json(json) => Param::Text(json.clone()),
+            Value::Now(offset) => {
+                if !self.times.contains_key(offset) {
+                    let time = self.select(&B::now(offset.as_ref())?, &[])?;
+                    self.times.insert(offset.clone(), time);
+                }
+
+                self.times[offset].clone().into()
+            }
+            Value::Null => Param::Null,
+            Value::Number(n) => Param::Text(n.clone()),
+            Value::RandomUuid => Param::Text(Uuid::new_v4().to_string()),
+            Value::Reference(Reference::ColumnLevel(colref)) => {
+                columns.get(colref.column.as_str()).expect("missing column").clone()
+            }
+            Value::Reference(refval) => self.follow_ref(table_scope, attribute, refval)?,
+            Value::SqlFragment(fragment) => match self.fragments.as_ref().and_then(|f| f.get(fragment)) {
+                Some(value) => value.clone().into(),
+                None => {
+                    let value = self.select(fragment, &[])?;
+
+                    if let Some(fragments) = &mut self.fragments {
+                        fragments.insert(fragment.clone(), value.clone());
+                    }
+
+                    value.into()
+                }
+            },
+            Value::Text(t) => Param::Text(env::interpolate(&unquote_text(t))?),
+            Value::Uuid5 { namespace, name } => Param::Text(Uuid::new_v5(namespace, name.as_bytes()).to_string()),
+            Value::VolatileSqlFragment(fragment) => self.select(fragment, &[])?.into(),
+        })
+    }
+
+    fn select(&mut self, fragment: &str, params: &[Param]) -> LoadResult<Option<String>> {
+        let query = format!("SELECT {}", fragment);
+        log::debug!("{}", query);
+
+        self.backend.select(&query, params)
+    }
+
+    fn follow_ref(&mut self, table_scope: &str, attribute: &Attribute, refval: &Reference) -> LoadResult<Param> {
+        let key = record_key(table_scope, refval).unwrap();
+        let column = match referenced_column(attribute, refval) {
+            Some(column) => column,
+            None => self.primary_key(&key)?,
+        };
+        let referenced = self
+            .refmap
+            .get(&key)
+            .ok_or_else(|| LoadError::unresolved_reference(&key, &column, "it failed to load"))?;
+
+        match referenced.row.get(&column) {
+            Some(value) => Ok(value.clone().into()),
+            None => Err(LoadError::unresolved_reference(&key, &column, "the column does not exist")),
+        }
+    }
+
+    /// Looks up the name of the primary key column of the referenced record's table,
+    /// for references to the record itself.
+    fn primary_key(&mut self, key: &str) -> LoadResult<String> {
+        let table = match self.refmap.get(key) {
+            Some(referenced) => referenced.table.clone(),
+            None => return Err(LoadError::unresolved_primary_key(key, "it failed to load")),
+        };
+
+        match &self.backend.primary_key(&table)?[..] {
+            [column] => Ok(column.clone()),
+            [] => Err(LoadError::unresolved_primary_key(key, "its table has no primary key")),
+            _ => Err(LoadError::unresolved_primary_key(key, "its table's primary key has more than one column")),
+        }
+    }
+}
+
+/// Splits the interval of a time offset, eg. `1 day 2 hours`, into the quantity and
+/// unit of each part, with the unit in the singular and in uppercase, for databases
+/// without Postgres intervals. Returns `None` unless every part is a whole number of
+/// one of the units.
+pub(crate) fn interval_parts(interval: &str, units: &[&str]) -> Option<Vec<(i64, String)>> {
+    let words: Vec<&str> = interval.split_whitespace().collect();
+
+    if words.is_empty() || !words.len().is_multiple_of(2) {
+        return None;
+    }
+
+    words
+        .chunks(2)
+        .map(|pair| {
+            let unit = pair[1].to_ascii_uppercase();
+            let unit = unit.strip_suffix('S').unwrap_or(&unit).to_owned();
+
+            match (pair[0].parse(), units.contains(&unit.as_str())) {
+                (Ok(quantity), true) => Some((quantity, unit)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Converts the hex digits of a binary string literal, which the lexer only
+/// accepts in pairs, into bytes.
+fn hex_bytes(digits: &str) -> Vec<u8> {
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).expect("invalid hex digits"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyzer, lexer, parser};
+    use pretty_assertions::assert_eq;
+
+    /// Records what it is asked to do, generating sequential IDs for each table's rows
+    #[derive(Default)]
+    struct Recorder {
+        statements: Vec<String>,
+        ids: HashMap<TableName, u64>,
+    }
+
+    impl Backend for Recorder {
+        const NAME: &'static str = "Recorder";
+
+        fn connect(_connstr: &str) -> Result<Self, ClientError> {
+            Ok(Self::default())
+        }
+
+        fn quote(identifier: &str) -> String {
+            format!("[{}]", identifier)
+        }
+
+        fn placeholder(index: usize) -> String {
+            format!(":{}", index + 1)
+        }
+
+        fn now(offset: Option<&TimeOffset>) -> LoadResult<String> {
+            Ok(offset.map_or("now()".to_owned(), |offset| format!("now() {}", offset)))
+        }
+
+        fn begin(&mut self, _options: &Options) -> LoadResult<()> {
+            self.execute("BEGIN")
+        }
+
+        fn execute(&mut self, statement: &str) -> LoadResult<()> {
+            self.statements.push(statement.to_owned());
+            Ok(())
+        }
+
+        fn select(&mut self, query: &str, params: &[Param]) -> LoadResult<Option<String>> {
+            self.statements.push(format!("{} {:?}", query, params));
+            Ok(Some(format!("selected {}", self.statements.len())))
+        }
+
+        fn primary_key(&mut self, _table: &TableName) -> LoadResult<Vec<String>> {
+            Ok(vec!["id".to_owned()])
+        }
+
+        fn insert(
+            &mut self,
+            table: &TableName,
+            columns: &[&str],
+            values: &[Param],
+            returning: bool,
+        ) -> LoadResult<Option<RecordRow>> {
+            if table.table == "broken" {
+                return Err(LoadError::unsupported(Self::NAME, "broken tables"));
+            }
+
+            let id = self.ids.entry(table.clone()).or_default();
+            *id += 1;
+            self.statements
+                .push(format!("INSERT {} {:?} {:?}", table.qualified::<Self>(), columns, values));
+
+            Ok(returning.then(|| {
+                let mut row: RecordRow = columns.iter().map(|c| c.to_string()).zip(values.iter().map(Param::text)).collect();
+                row.insert("id".to_owned(), Some(id.to_string()));
+                row
+            }))
+        }
+    }
+
+    fn loaded(input: &str, options: &Options) -> (Recorder, LoadResult<PlaceReport>) {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let tree = analyzer::analyze(parser::parse(tokens.into_iter()).unwrap()).unwrap();
+        let mut recorder = Recorder::connect("").unwrap();
+        let report = load(&mut recorder, tree, options);
+
+        (recorder, report)
+    }
+
+    #[test]
+    fn test_load() {
+        let input = "
+            table person (
+              kevin (name 'Kevin', nick @name, active true)
+              (name 'Bob', manager_id @kevin, team `default_team()`, joined now())
+            )
+            schema zoo (
+              table pet (
+                (owner_id @person.kevin.id, owner_name @person.kevin.name, photo x'dead00', team `default_team()`)
+                (tag `tag({@person.kevin.nick})`, total 1 + 2)
+              )
+            )
+        ";
+        let options = Options { cache_fragments: true, ..Options::default() };
+        let (recorder, report) = loaded(input, &options);
+        let report = report.unwrap();
+
+        assert_eq!(
+            recorder.statements,
+            vec![
+                r#"INSERT [person] ["name", "nick", "active"] [Text("Kevin"), Text("Kevin"), Bool(true)]"#,
+                "SELECT default_team() []",
+                "SELECT now() []",
+                r#"INSERT [person] ["name", "manager_id", "team", "joined"] [Text("Bob"), Text("1"), Text("selected 2"), Text("selected 3")]"#,
+                r#"INSERT [zoo].[pet] ["owner_id", "owner_name", "photo", "team"] [Text("1"), Text("Kevin"), Bytes([222, 173, 0]), Text("selected 2")]"#,
+                r#"SELECT tag(:1) [Text("Kevin")]"#,
+                r#"INSERT [zoo].[pet] ["tag", "total"] [Text("selected 6"), Text("3")]"#,
+            ],
+        );
+        assert_eq!(report.table(None, "person").unwrap().rows_inserted, 2);
+        assert_eq!(report.table(Some("zoo"), "pet").unwrap().rows_inserted, 2);
+        assert_eq!(report.record(None, "person", "kevin").unwrap()["id"].as_deref(), Some("1"));
+
+        let (recorder, _) = loaded(input, &Options::default());
+        let selected = recorder.statements.iter().filter(|s| s.starts_with("SELECT default_team()"));
+        assert_eq!(selected.count(), 2, "fragments are only cached with the cache_fragments option");
+    }
+
+    #[test]
+    fn test_keep_going() {
+        let input = "
+            table broken (
+              rec1 (col1 1)
+            )
+            table person (
+              (name 'Kevin')
+            )
+        ";
+        let options = Options { keep_going: true, ..Options::default() };
+        let (recorder, report) = loaded(input, &options);
+        let report = report.unwrap();
+
+        assert_eq!(
+            recorder.statements,
+            vec![
+                "SAVEPOINT hldr_table",
+                "ROLLBACK TO SAVEPOINT hldr_table",
+                "SAVEPOINT hldr_table",
+                r#"INSERT [person] ["name"] [Text("Kevin")]"#,
+                "RELEASE SAVEPOINT hldr_table",
+            ],
+        );
+        assert_eq!(report.skipped[0].error, "Recorder does not support broken tables");
+
+        let (_, report) = loaded("table person truncate ()", &Options::default());
+        assert_eq!(report.unwrap_err().to_string(), "Recorder does not support truncating tables");
+
+        let (_, report) = loaded("table person with (app.tenant_id '42') ()", &Options::default());
+        assert_eq!(
+            report.unwrap_err().to_string(),
+            "Recorder does not support settings declared with `with (...)`",
+        );
+    }
+
+    #[test]
+    fn test_check_options() {
+        assert!(check_options::<Recorder>(&Options { keep_going: true, ..Options::default() }).is_ok());
+
+        let options = Options { on_conflict: OnConflict::Ignore, ..Options::default() };
+        assert_eq!(
+            check_options::<Recorder>(&options).unwrap_err().to_string(),
+            "Recorder does not support the `on_conflict` option",
+        );
+    }
+}
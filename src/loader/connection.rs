@@ -0,0 +1,168 @@
+use super::error::{ClientError, LoadError};
+use super::LoadResult;
+use crate::Options;
+use postgres::types::ToSql;
+use postgres::{Client, Error, Row, SimpleQueryMessage, Transaction};
+use std::future::Future;
+use std::io::Write;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+/// The database operations that the loader performs within a transaction.
+///
+/// The loader is written asynchronously so that it can be used with either the
+/// blocking `postgres` transaction or, with the `async` feature, the `tokio-postgres`
+/// one, as the blocking implementation simply never yields.
+pub(crate) trait Connection {
+    /// Executes the statement and returns the number of rows it affected.
+    async fn execute(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error>;
+
+    async fn query(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error>;
+
+    async fn query_opt(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>, Error>;
+
+    async fn simple_query(&mut self, query: &str) -> Result<Vec<SimpleQueryMessage>, Error>;
+
+    /// Copies the rows, already in the text format, with the `COPY ... FROM STDIN`
+    /// query and returns the number of rows copied.
+    async fn copy_in(&mut self, query: &str, rows: String) -> Result<u64, LoadError>;
+}
+
+impl Connection for Transaction<'_> {
+    async fn execute(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        Transaction::execute(self, query, params)
+    }
+
+    async fn query(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        Transaction::query(self, query, params)
+    }
+
+    async fn query_opt(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>, Error> {
+        Transaction::query_opt(self, query, params)
+    }
+
+    async fn simple_query(&mut self, query: &str) -> Result<Vec<SimpleQueryMessage>, Error> {
+        Transaction::simple_query(self, query)
+    }
+
+    async fn copy_in(&mut self, query: &str, rows: String) -> Result<u64, LoadError> {
+        let mut writer = Transaction::copy_in(self, query).map_err(LoadError::new)?;
+
+        writer.write_all(rows.as_bytes()).map_err(LoadError::copy)?;
+        writer.finish().map_err(LoadError::new)
+    }
+}
+
+#[cfg(feature = "async")]
+impl Connection for tokio_postgres::Transaction<'_> {
+    async fn execute(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        tokio_postgres::Transaction::execute(self, query, params).await
+    }
+
+    async fn query(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        tokio_postgres::Transaction::query(self, query, params).await
+    }
+
+    async fn query_opt(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>, Error> {
+        tokio_postgres::Transaction::query_opt(self, query, params).await
+    }
+
+    async fn simple_query(&mut self, query: &str) -> Result<Vec<SimpleQueryMessage>, Error> {
+        tokio_postgres::Transaction::simple_query(self, query).await
+    }
+
+    async fn copy_in(&mut self, query: &str, rows: String) -> Result<u64, LoadError> {
+        use futures_util::SinkExt;
+
+        let sink = tokio_postgres::Transaction::copy_in(self, query)
+            .await
+            .map_err(LoadError::new)?;
+        let mut sink = pin!(sink);
+
+        sink.send(bytes::Bytes::from(rows)).await.map_err(LoadError::new)?;
+        sink.finish().await.map_err(LoadError::new)
+    }
+}
+
+/// A client that data files are loaded with in a transaction of their own, either the
+/// blocking `postgres` client or, with the `async` feature, the `tokio-postgres` one.
+pub(crate) trait TransactionClient: Sized {
+    type Transaction<'a>: Connection
+    where
+        Self: 'a;
+
+    async fn connect(connstr: &str) -> Result<Self, ClientError>;
+
+    /// Begins a transaction with the isolation level and settings given in the options.
+    async fn begin<'a>(&'a mut self, options: &Options) -> LoadResult<Self::Transaction<'a>>;
+
+    async fn commit<'a>(transaction: Self::Transaction<'a>) -> Result<(), Error>;
+}
+
+impl TransactionClient for Client {
+    type Transaction<'a> = Transaction<'a>;
+
+    async fn connect(connstr: &str) -> Result<Self, ClientError> {
+        super::new_client(connstr)
+    }
+
+    async fn begin<'a>(&'a mut self, options: &Options) -> LoadResult<Transaction<'a>> {
+        super::begin(self, options)
+    }
+
+    async fn commit<'a>(transaction: Self::Transaction<'a>) -> Result<(), Error> {
+        transaction.commit()
+    }
+}
+
+#[cfg(feature = "async")]
+impl TransactionClient for tokio_postgres::Client {
+    type Transaction<'a> = tokio_postgres::Transaction<'a>;
+
+    async fn connect(connstr: &str) -> Result<Self, ClientError> {
+        super::new_async_client(connstr).await
+    }
+
+    async fn begin<'a>(&'a mut self, options: &Options) -> LoadResult<tokio_postgres::Transaction<'a>> {
+        super::begin_async(self, options).await
+    }
+
+    async fn commit<'a>(transaction: Self::Transaction<'a>) -> Result<(), Error> {
+        transaction.commit().await
+    }
+}
+
+/// Runs a future that never yields, as is the case for the loader with a blocking
+/// connection, to completion.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+
+    match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("blocking connections never yield"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::block_on;
+
+    #[test]
+    fn test_block_on() {
+        assert_eq!(block_on(async { 1 }), 1);
+    }
+
+    /// Async callers commonly spawn loads onto multi-threaded runtimes, which
+    /// requires the future to be `Send`, so this only needs to compile
+    #[cfg(feature = "async")]
+    #[allow(dead_code)]
+    fn load_async_is_send(
+        transaction: &mut tokio_postgres::Transaction<'_>,
+        tree: crate::analyzer::ValidatedParseTree,
+        options: &crate::Options,
+    ) {
+        fn send<T: Send>(_: T) {}
+
+        send(super::super::load_async(transaction, tree, options));
+    }
+}
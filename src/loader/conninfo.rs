@@ -0,0 +1,160 @@
+use std::env;
+
+/// The standard libpq environment variables and the connection parameters they set
+const PG_VARS: [(&str, &str); 5] = [
+    ("PGHOST", "host"),
+    ("PGPORT", "port"),
+    ("PGUSER", "user"),
+    ("PGPASSWORD", "password"),
+    ("PGDATABASE", "dbname"),
+];
+
+/// Resolves the connection string to use, which is the given one unless it is empty,
+/// in which case `DATABASE_URL` is used if set, or else a key/value connection string
+/// is built from any of the `PGHOST`, `PGPORT`, `PGUSER`, `PGPASSWORD`, and `PGDATABASE`
+/// variables that are set, as psql would.
+///
+/// Command-line options take precedence over the options file before this, so the
+/// environment is only used if neither specify a connection.
+pub fn resolve(connstr: &str) -> String {
+    resolve_with(connstr, |name| env::var(name).ok())
+}
+
+fn resolve_with(connstr: &str, var: impl Fn(&str) -> Option<String>) -> String {
+    if !connstr.is_empty() {
+        return connstr.to_owned();
+    }
+
+    let var = |name: &str| var(name).filter(|value| !value.is_empty());
+
+    if let Some(url) = var("DATABASE_URL") {
+        return url;
+    }
+
+    PG_VARS
+        .iter()
+        .filter_map(|(name, param)| var(name).map(|value| format!("{}={}", param, quote(&value))))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The databases that data files can be loaded into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackendKind {
+    Postgres,
+    /// MySQL or MariaDB, which needs the `mysql` feature
+    MySql,
+    /// SQLite, which needs the `sqlite` feature
+    Sqlite,
+}
+
+impl BackendKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BackendKind::Postgres => "Postgres",
+            BackendKind::MySql => "MySQL",
+            BackendKind::Sqlite => "SQLite",
+        }
+    }
+
+    /// Returns the name of the feature that hldr has to be built with to load into
+    /// the database, if any.
+    pub fn feature(self) -> Option<&'static str> {
+        match self {
+            BackendKind::Postgres => None,
+            BackendKind::MySql => Some("mysql"),
+            BackendKind::Sqlite => Some("sqlite"),
+        }
+    }
+}
+
+/// Returns the database that a resolved connection string is for, by the scheme of its
+/// URI. Anything that is not a `mysql://`, `mariadb://`, or `sqlite:` URI is assumed to
+/// be for Postgres, including key/value connection strings.
+pub fn backend_kind(connstr: &str) -> BackendKind {
+    let scheme = connstr
+        .split_once(':')
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+
+    match scheme.as_deref() {
+        Some("mysql" | "mariadb") => BackendKind::MySql,
+        Some("sqlite") => BackendKind::Sqlite,
+        _ => BackendKind::Postgres,
+    }
+}
+
+/// Returns the path of the database file that a `sqlite:` connection string is for,
+/// eg. `sqlite://fixtures.db` or `sqlite::memory:`, which is `:memory:` for a new
+/// in-memory database.
+pub fn sqlite_path(connstr: &str) -> &str {
+    let path = connstr.split_once(':').map_or(connstr, |(_, path)| path);
+    path.strip_prefix("//").unwrap_or(path)
+}
+
+/// Quotes a key/value connection string value, escaping any quotes and backslashes
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backend_kind, resolve_with, sqlite_path, BackendKind};
+    use std::collections::HashMap;
+
+    fn resolved(connstr: &str, vars: &[(&str, &str)]) -> String {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        resolve_with(connstr, |name| vars.get(name).map(|v| v.to_string()))
+    }
+
+    #[test]
+    fn test_explicit_connstr() {
+        let vars = [("DATABASE_URL", "postgres://env"), ("PGHOST", "envhost")];
+        assert_eq!(resolved("host=explicit", &vars), "host=explicit");
+    }
+
+    #[test]
+    fn test_database_url() {
+        let vars = [("DATABASE_URL", "postgres://env"), ("PGHOST", "envhost")];
+        assert_eq!(resolved("", &vars), "postgres://env");
+    }
+
+    #[test]
+    fn test_pg_vars() {
+        let vars = [
+            ("DATABASE_URL", ""),
+            ("PGDATABASE", "db"),
+            ("PGHOST", "/tmp"),
+            ("PGPASSWORD", r"it's a \ secret"),
+            ("PGPORT", "5433"),
+            ("PGUSER", ""),
+        ];
+        assert_eq!(
+            resolved("", &vars),
+            r"host='/tmp' port='5433' password='it\'s a \\ secret' dbname='db'",
+        );
+    }
+
+    #[test]
+    fn test_no_vars() {
+        assert_eq!(resolved("", &[]), "");
+    }
+
+    #[test]
+    fn test_backend_kind() {
+        assert_eq!(backend_kind("postgresql://user@host/db"), BackendKind::Postgres);
+        assert_eq!(backend_kind("host=/tmp dbname='mysql://'"), BackendKind::Postgres);
+        assert_eq!(backend_kind(""), BackendKind::Postgres);
+        assert_eq!(backend_kind("mysql://user@host/db"), BackendKind::MySql);
+        assert_eq!(backend_kind("MariaDB://user@host/db"), BackendKind::MySql);
+        assert_eq!(backend_kind("sqlite::memory:"), BackendKind::Sqlite);
+        assert_eq!(backend_kind("host=/tmp password=a:b"), BackendKind::Postgres);
+    }
+
+    #[test]
+    fn test_sqlite_path() {
+        assert_eq!(sqlite_path("sqlite::memory:"), ":memory:");
+        assert_eq!(sqlite_path("sqlite://:memory:"), ":memory:");
+        assert_eq!(sqlite_path("sqlite://fixtures.db"), "fixtures.db");
+        assert_eq!(sqlite_path("sqlite:///tmp/fixtures.db"), "/tmp/fixtures.db");
+    }
+}
@@ -0,0 +1,224 @@
+//! Infers minimal table definitions from the values that records give each column,
+//! so that data files can be loaded for prototyping before the real schema exists.
+
+use super::{qualified_table_name, quote, record_key, table_scope};
+use crate::parser::nodes::{Operator, ParseTree, Reference, ReferencedColumn, StructuralIdentity, StructuralNode, Table, Value};
+use crate::Options;
+use std::collections::HashMap;
+
+/// The primary key column that tables are created with unless their records give it values
+const PRIMARY_KEY: &str = "id";
+
+/// A table as inferred from the records of every declaration of it
+#[derive(Default)]
+struct InferredTable {
+    /// The columns in the order records first give them values, with their types once known
+    columns: Vec<(String, Option<&'static str>)>,
+    conflict_target: Option<Vec<String>>,
+}
+
+impl InferredTable {
+    fn column_type(&self, column: &str) -> Option<&'static str> {
+        self.columns.iter().find(|(name, _)| name == column).and_then(|(_, t)| *t)
+    }
+
+    /// Adds the type of a value given to the column, which becomes `text` if values of
+    /// different types are given, returning whether the column's type changed.
+    fn add(&mut self, column: &str, value_type: Option<&'static str>) -> bool {
+        let index = match self.columns.iter().position(|(name, _)| name == column) {
+            Some(index) => index,
+            None => {
+                self.columns.push((column.to_owned(), None));
+                self.columns.len() - 1
+            }
+        };
+        let merged = match (self.columns[index].1, value_type) {
+            (current, None) => current,
+            (None, new) => new,
+            (Some(current), Some(new)) if current == new => Some(current),
+            (Some(_), Some(_)) => Some("text"),
+        };
+
+        let changed = self.columns[index].1 != merged;
+        self.columns[index].1 = merged;
+        changed
+    }
+
+    fn primary_key_type(&self) -> &'static str {
+        self.column_type(PRIMARY_KEY).unwrap_or("bigint")
+    }
+
+    /// The type of a column that references can name, including the identity primary
+    /// key that the table is created with unless records give it values.
+    fn referenced_type(&self, column: &str) -> Option<&'static str> {
+        match column {
+            PRIMARY_KEY => Some(self.primary_key_type()),
+            _ => self.column_type(column),
+        }
+    }
+
+    /// Returns the table's definition, with an identity primary key unless records give
+    /// it values, and with a unique constraint on its conflict target, if any, so that
+    /// records can be upserted. Columns that are never given values of a known type,
+    /// eg. only SQL fragments, are `text`.
+    fn definition(&self, qualified_table_name: &str) -> String {
+        let mut definitions = Vec::new();
+
+        if self.columns.iter().all(|(name, _)| name != PRIMARY_KEY) {
+            definitions.push(format!("{} bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY", quote(PRIMARY_KEY)));
+        }
+
+        for (name, column_type) in &self.columns {
+            let primary_key = if name == PRIMARY_KEY { " PRIMARY KEY" } else { "" };
+            definitions.push(format!("{} {}{}", quote(name), column_type.unwrap_or("text"), primary_key));
+        }
+
+        if let Some(target) = &self.conflict_target {
+            let columns: Vec<String> = target.iter().map(|c| quote(c)).collect();
+            definitions.push(format!("UNIQUE ({})", columns.join(", ")));
+        }
+
+        format!("CREATE TABLE IF NOT EXISTS {} ({})", qualified_table_name, definitions.join(", "))
+    }
+}
+
+/// Returns the statements that create every table declared in the data files with the
+/// `create_tables` option, unless they already exist, with the columns that their
+/// records give values for and types inferred from those values.
+pub(super) fn create_table_statements(tree: &ParseTree, options: &Options) -> Vec<String> {
+    if !options.create_tables {
+        return Vec::new();
+    }
+
+    let mut declarations: Vec<(Option<&StructuralIdentity>, &Table)> = Vec::new();
+
+    for node in &tree.nodes {
+        match node {
+            StructuralNode::Schema(schema) => declarations.extend(schema.nodes.iter().map(|t| (Some(&schema.identity), t))),
+            StructuralNode::Table(table) => declarations.push((None, table)),
+        }
+    }
+
+    // Tables in the order they were first declared, and the table of each named record
+    let mut names: Vec<String> = Vec::new();
+    let mut tables: HashMap<String, InferredTable> = HashMap::new();
+    let mut records: HashMap<String, String> = HashMap::new();
+
+    for &(schema, table) in &declarations {
+        let qualified_table_name = qualified_table_name(schema, table);
+        let table_scope = table_scope(schema, table);
+
+        for name in table.nodes.iter().filter_map(|r| r.name.as_ref()) {
+            records.insert(format!("{}.{}", table_scope, name), qualified_table_name.clone());
+        }
+
+        let inferred = tables.entry(qualified_table_name.clone()).or_insert_with(|| {
+            names.push(qualified_table_name.clone());
+            InferredTable::default()
+        });
+
+        if inferred.conflict_target.is_none() {
+            inferred.conflict_target = table.conflict_target.clone();
+        }
+    }
+
+    // References take the types of the columns they reference, which are only known once
+    // those columns have been given values, so types are inferred until none change
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for &(schema, table) in &declarations {
+            let qualified_table_name = qualified_table_name(schema, table);
+            let table_scope = table_scope(schema, table);
+
+            for attribute in table.nodes.iter().flat_map(|r| &r.nodes) {
+                let value_type = value_type(&attribute.value, &|refval| {
+                    let column = match refval {
+                        Reference::ColumnLevel(colref) => {
+                            return tables[&qualified_table_name].column_type(&colref.column);
+                        }
+                        Reference::SchemaLevel(s) => &s.column,
+                        Reference::TableLevel(t) => &t.column,
+                        Reference::RecordLevel(r) => &r.column,
+                    };
+                    let referenced = &tables[records.get(&record_key(&table_scope, refval)?)?];
+
+                    match column {
+                        ReferencedColumn::Explicit(column) => referenced.referenced_type(column),
+                        ReferencedColumn::Implicit => referenced.referenced_type(&attribute.name),
+                        ReferencedColumn::PrimaryKey => Some(referenced.primary_key_type()),
+                    }
+                });
+
+                let inferred = tables.get_mut(&qualified_table_name).expect("table not inferred");
+                changed |= inferred.add(&attribute.name, value_type);
+            }
+        }
+    }
+
+    names.iter().map(|name| tables[name].definition(name)).collect()
+}
+
+/// The type of column that the value is inferred to need, if known, given how to look
+/// up the types of the columns that references are to.
+fn value_type(value: &Value, reference_type: &dyn Fn(&Reference) -> Option<&'static str>) -> Option<&'static str> {
+    match value {
+        Value::Bool(_) => Some("boolean"),
+        Value::Expression(expression) if expression.operator == Operator::Concat => Some("text"),
+        Value::Expression(_) | Value::Number(_) => Some("numeric"),
+        Value::File(_) | Value::Hex(_) => Some("bytea"),
+        Value::InterpolatedText(_) | Value::Text(_) => Some("text"),
+        Value::Json(_) => Some("jsonb"),
+        Value::Now(_) => Some("timestamptz"),
+        Value::RandomUuid | Value::Uuid5 { .. } => Some("uuid"),
+        Value::Reference(refval) => reference_type(refval),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::create_table_statements;
+    use crate::{analyzer, lexer, parser, Options};
+
+    #[test]
+    fn test_create_table_statements() {
+        let input = r#"
+            schema app (
+                table person as p conflict (email) (
+                    kevin (email 'kevin@example.com', active true, joined now())
+                    alice (email 'alice@example.com', active 'yes', manager_id @kevin)
+                    (name 'Anon' || '!', age 40, avatar x'00', settings json{})
+                )
+            )
+            table pet (
+                (id 1, name 'Rex', owner_id @app.p.kevin, owner_email @app.p.alice.email, checked_at `now()`)
+                (id 2.5, name 'Tom', nickname @name, sitter_id @app.p.alice.id)
+            )
+        "#;
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let tree = analyzer::analyze(parser::parse(tokens.into_iter()).unwrap()).unwrap().into_inner();
+        let options = Options {
+            create_tables: true,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            create_table_statements(&tree, &options),
+            [
+                concat!(
+                    r#"CREATE TABLE IF NOT EXISTS "app"."person" ("id" bigint GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY, "#,
+                    r#""email" text, "active" text, "joined" timestamptz, "manager_id" bigint, "name" text, "age" numeric, "#,
+                    r#""avatar" bytea, "settings" jsonb, UNIQUE ("email"))"#,
+                ),
+                concat!(
+                    r#"CREATE TABLE IF NOT EXISTS "pet" ("id" numeric PRIMARY KEY, "name" text, "owner_id" bigint, "#,
+                    r#""owner_email" text, "checked_at" text, "nickname" text, "sitter_id" bigint)"#,
+                ),
+            ],
+        );
+        assert!(create_table_statements(&tree, &Options::default()).is_empty());
+    }
+}
@@ -0,0 +1,748 @@
+use super::ddl::create_table_statements;
+use super::{
+    change_settings,
+    conflict_clause,
+    create_schema_statements,
+    hook_statements,
+    now_sql,
+    qualified_table_name,
+    quote,
+    search_path_statement,
+    set_role,
+    table_scope,
+    truncate_statement,
+    ConflictAction,
+    DisabledTriggers,
+    ExpectedCounts,
+    ExplicitColumns,
+    SeededTables,
+};
+use crate::analyzer::ValidatedParseTree;
+use crate::parser::nodes::{
+    Attribute,
+    HookTiming,
+    Reference,
+    Setting,
+    StructuralIdentity,
+    StructuralNode,
+    Table,
+    TextSegment,
+    Value,
+};
+use crate::{OnConflict, Options};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use uuid::Uuid;
+
+/// Writes the insert statements that the loader would execute for the tree
+/// without requiring a database connection, one per record rather than in the
+/// multi-row batches the loader uses.
+///
+/// Literal values are written inline and SQL fragments as scalar subqueries,
+/// but references to other records are written as they were declared, since
+/// the values they point to (eg. generated primary keys) only exist once the
+/// records have actually been inserted. Likewise, primary keys that would be
+/// discovered for upserts are written as `<primary key>`, and environment
+/// variables and random UUIDs are written as declared rather than their values.
+pub fn render(out: &mut impl Write, tree: ValidatedParseTree, options: &Options) -> io::Result<()> {
+    let tree = tree.into_inner();
+
+    if let Some(statement) = search_path_statement(&tree) {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    for statement in create_schema_statements(&tree, options) {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    for statement in create_table_statements(&tree, options) {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    for statement in hook_statements(&tree, HookTiming::Before) {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    if let Some(statement) = truncate_statement(&tree, options) {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    let mut scope = Scope::default();
+    let mut explicit_columns = ExplicitColumns::default();
+    let mut expected_counts = ExpectedCounts::default();
+    let mut seeded_tables = SeededTables::default();
+    let mut triggers = DisabledTriggers::new(options);
+    let tables = tree.nodes.iter().flat_map(|node| match node {
+        StructuralNode::Schema(schema) => schema.nodes.iter().map(|table| (Some(&schema.identity), table)).collect(),
+        StructuralNode::Table(table) => vec![(None, &**table)],
+    });
+
+    for (schema, table) in tables {
+        let qualified_table_name = qualified_table_name(schema, table);
+
+        // Triggers are disabled as the base role, as when loading
+        if let Some(statement) = triggers.disable(&qualified_table_name) {
+            let base = Scope { role: None, settings: scope.settings.clone() };
+            render_scope(out, &mut scope, base, options)?;
+            writeln!(out, "{};\n", statement)?;
+        }
+
+        render_scope(out, &mut scope, Scope::of(table), options)?;
+        render_table(out, schema, table, options)?;
+        explicit_columns.add(&qualified_table_name, table);
+        expected_counts.add(&qualified_table_name, table);
+        seeded_tables.add(&qualified_table_name, table);
+    }
+
+    render_scope(out, &mut scope, Scope::default(), options)?;
+
+    for statement in triggers.enable() {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    if options.reset_sequences {
+        for statement in explicit_columns.statements() {
+            writeln!(out, "{};\n", statement)?;
+        }
+    }
+
+    if options.refresh_matviews {
+        if let Some(statement) = seeded_tables.statement() {
+            writeln!(out, "{};\n", statement)?;
+        }
+    }
+
+    for statement in hook_statements(&tree, HookTiming::After) {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    for statement in expected_counts.statements() {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    Ok(())
+}
+
+/// The role and settings that a table's records are inserted with.
+#[derive(Default)]
+struct Scope {
+    role: Option<String>,
+    settings: Vec<Setting>,
+}
+
+impl Scope {
+    fn of(table: &Table) -> Self {
+        Self {
+            role: table.role.clone(),
+            settings: table.settings.clone(),
+        }
+    }
+}
+
+/// Writes the statements that switch from the role and settings of the last table to
+/// the next's, where tables without roles of their own are loaded as the role given
+/// with the `role` option, if any.
+fn render_scope(out: &mut impl Write, current: &mut Scope, next: Scope, options: &Options) -> io::Result<()> {
+    if current.role != next.role {
+        writeln!(out, "{};\n", set_role(next.role.as_deref().or(options.role.as_deref())))?;
+    }
+
+    for statement in change_settings(&current.settings, &next.settings) {
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    *current = next;
+
+    Ok(())
+}
+
+fn render_table(
+    out: &mut impl Write,
+    schema: Option<&StructuralIdentity>,
+    table: &Table,
+    options: &Options,
+) -> io::Result<()> {
+    let qualified_table_name = qualified_table_name(schema, table);
+    let table_scope = table_scope(schema, table);
+    let target = table
+        .conflict_target
+        .as_ref()
+        .map(|columns| columns.iter().map(|c| quote(c)).collect());
+
+    let on_conflict = match (target, options.on_conflict) {
+        (target, OnConflict::Ignore) => ConflictAction::Ignore { target, keys: Vec::new() },
+        (Some(target), _) => ConflictAction::Update(target),
+        (None, OnConflict::Error) => ConflictAction::Error,
+        (None, OnConflict::Update) => ConflictAction::Update(vec!["<primary key>".to_owned()]),
+    };
+
+    for record in &table.nodes {
+        if let Some(name) = &record.name {
+            writeln!(out, "-- {}.{}", table_scope, name)?;
+        }
+
+        let fixed_now = options.fixed_now.as_deref();
+        let statement = match &record.update_where {
+            _ if record.delete => render_delete(&qualified_table_name, &record.nodes, fixed_now),
+            Some(conditions) => render_update(&qualified_table_name, &record.nodes, conditions, fixed_now),
+            None => render_insert(&qualified_table_name, &record.nodes, &on_conflict, fixed_now),
+        };
+        writeln!(out, "{};\n", statement)?;
+    }
+
+    Ok(())
+}
+
+pub(super) fn render_insert(
+    qualified_table_name: &str,
+    attributes: &[Attribute],
+    on_conflict: &ConflictAction,
+    fixed_now: Option<&str>,
+) -> String {
+    let columns: Vec<String> = attributes.iter().map(|a| quote(&a.name)).collect();
+    let on_conflict = match conflict_clause(on_conflict, &columns) {
+        clause if clause.is_empty() => clause,
+        clause => format!(" {}", clause),
+    };
+
+    if attributes.is_empty() {
+        return format!("INSERT INTO {} DEFAULT VALUES{}", qualified_table_name, on_conflict);
+    }
+
+    let mut rendered: HashMap<&str, String> = HashMap::new();
+    let mut values = Vec::new();
+
+    for attribute in attributes {
+        let value = render_value(&attribute.value, &rendered, fixed_now);
+
+        values.push(value.clone());
+        rendered.insert(&attribute.name, parenthesized(&attribute.value, value));
+    }
+
+    format!(
+        "INSERT INTO {} ({}) VALUES ({}){}",
+        qualified_table_name,
+        columns.join(", "),
+        values.join(", "),
+        on_conflict,
+    )
+}
+
+/// Renders the update of the rows identified by the columns given as conditions, which
+/// sets the record's other columns.
+pub(super) fn render_update(
+    qualified_table_name: &str,
+    attributes: &[Attribute],
+    conditions: &[String],
+    fixed_now: Option<&str>,
+) -> String {
+    let mut rendered: HashMap<&str, String> = HashMap::new();
+    let mut assignments = Vec::new();
+    let mut predicates = Vec::new();
+
+    for attribute in attributes {
+        let value = render_value(&attribute.value, &rendered, fixed_now);
+
+        match conditions.contains(&attribute.name) {
+            true => predicates.push(render_predicate(attribute, &value)),
+            false => assignments.push(format!("{} = {}", quote(&attribute.name), value)),
+        }
+
+        rendered.insert(&attribute.name, parenthesized(&attribute.value, value));
+    }
+
+    format!(
+        "UPDATE {} SET {} WHERE {}",
+        qualified_table_name,
+        assignments.join(", "),
+        predicates.join(" AND "),
+    )
+}
+
+/// Renders the delete of the rows identified by all of the columns.
+pub(super) fn render_delete(qualified_table_name: &str, attributes: &[Attribute], fixed_now: Option<&str>) -> String {
+    let mut rendered: HashMap<&str, String> = HashMap::new();
+    let mut predicates = Vec::new();
+
+    for attribute in attributes {
+        let value = render_value(&attribute.value, &rendered, fixed_now);
+
+        predicates.push(render_predicate(attribute, &value));
+        rendered.insert(&attribute.name, parenthesized(&attribute.value, value));
+    }
+
+    format!("DELETE FROM {} WHERE {}", qualified_table_name, predicates.join(" AND "))
+}
+
+/// Renders the condition just as the loader does, comparing null values with `IS NULL`,
+/// although references are always compared with `=`, as whether their values are null
+/// is only known when loading.
+fn render_predicate(attribute: &Attribute, value: &str) -> String {
+    match attribute.value {
+        Value::Null => format!("{} IS NULL", quote(&attribute.name)),
+        _ => format!("{} = {}", quote(&attribute.name), value),
+    }
+}
+
+/// Renders the value, given the values already rendered for the record's columns and
+/// the time `now()` is fixed to, if any.
+fn render_value(value: &Value, rendered: &HashMap<&str, String>, fixed_now: Option<&str>) -> String {
+    match value {
+        Value::Bool(b) => b.to_string(),
+        Value::Default => "DEFAULT".to_owned(),
+        Value::EnvVar(name) => format!("env('{}')", name.replace('\'', "''")),
+        Value::Expression(expression) => format!(
+            "{} {} {}",
+            parenthesized(&expression.left, render_value(&expression.left, rendered, fixed_now)),
+            expression.operator,
+            parenthesized(&expression.right, render_value(&expression.right, rendered, fixed_now)),
+        ),
+        Value::File(path) => format!("bytes('{}')", path.display().to_string().replace('\'', "''")),
+        Value::Hex(digits) => format!("'\\x{}'", digits),
+        Value::Json(json) => format!("'{}'::jsonb", json.replace('\'', "''")),
+        Value::Index => unreachable!("index() should have been expanded by the analyzer"),
+        Value::Variable(_) => unreachable!("variables should have been substituted by the analyzer"),
+        // Rendered with `concat` rather than `||`, which leaves out null values just as the
+        // loader does instead of making the whole text null
+        Value::InterpolatedText(segments) => format!(
+            "concat({})",
+            segments
+                .iter()
+                .map(|segment| match segment {
+                    TextSegment::Literal(literal) => format!("'{}'", literal.replace('\'', "''")),
+                    TextSegment::Value(value) => render_value(value, rendered, fixed_now),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        Value::Now(offset) => now_sql(fixed_now, offset.as_ref()),
+        Value::Null => "NULL".to_owned(),
+        Value::Number(n) => n.clone(),
+        Value::RandomUuid => "uuid()".to_owned(),
+        // Column-level references simply repeat the value being referenced,
+        // which the analyzer guarantees was declared earlier in the record
+        Value::Reference(Reference::ColumnLevel(colref)) => rendered
+            .get(colref.column.as_str())
+            .expect("missing column")
+            .clone(),
+        Value::Reference(r) => r.to_string(),
+        Value::SqlFragment(s) | Value::VolatileSqlFragment(s) => format!("(SELECT {})", s),
+        Value::InterpolatedSqlFragment(segments) => {
+            let sql: String = segments
+                .iter()
+                .map(|segment| match segment {
+                    TextSegment::Literal(sql) => sql.clone(),
+                    TextSegment::Value(value) => parenthesized(value, render_value(value, rendered, fixed_now)),
+                })
+                .collect();
+
+            format!("(SELECT {})", sql)
+        }
+        // Text literals retain their surrounding (and any escaped) quotes
+        Value::Text(t) => t.clone(),
+        Value::Uuid5 { namespace, name } => format!("'{}'", Uuid::new_v5(namespace, name.as_bytes())),
+    }
+}
+
+/// Parenthesizes the rendered value if it is an expression or an offset time, so that it
+/// is evaluated as a whole within another expression.
+fn parenthesized(value: &Value, rendered: String) -> String {
+    match value {
+        Value::Expression(_) | Value::Now(Some(_)) => format!("({})", rendered),
+        _ => rendered,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use crate::{analyzer, lexer, parser, DisableTriggers, OnConflict, Options};
+    use pretty_assertions::assert_eq;
+
+    fn rendered(input: &str, options: &Options) -> String {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let tree = parser::parse(tokens.into_iter()).unwrap();
+        let tree = analyzer::analyze(tree).unwrap();
+        let mut out = Vec::new();
+
+        render(&mut out, tree, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let input = r#"
+            schema s1 as s (
+                table t1 (
+                    rec1 (
+                        col1 123
+                        col2 'isn''t'
+                        col3 `now()`
+                        col4 @col3
+                        col5 null
+                    )
+                    _ ()
+                )
+            )
+            table t2 (
+                (col1 true, col2 @s.t1.rec1.id, col3 @s.t1.rec1.)
+            )
+        "#;
+
+        assert_eq!(
+            rendered(input, &Options::default()),
+            r#"-- s.t1.rec1
+INSERT INTO "s1"."t1" ("col1", "col2", "col3", "col4", "col5") VALUES (123, 'isn''t', (SELECT now()), (SELECT now()), NULL);
+
+INSERT INTO "s1"."t1" DEFAULT VALUES;
+
+INSERT INTO "t2" ("col1", "col2", "col3") VALUES (true, @s.t1.rec1.id, @s.t1.rec1.);
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_functions() {
+        let input = r#"
+            table t1 (
+                (col1 uuid(), col2 uuid5('dns', 'example.com'), col3 env('HOME'))
+                _ * 2 (col1 uuid5('url', 'https://example.com/${n}'))
+                (col1 now(), col2 ago('7 days'), col3 now() + interval '1 hour', col4 'at ' || now() - interval 'it''s')
+                (col1 x'00ff', col2 bytes('logo.png'))
+                (col1 json{ "name": "it's", "tags": [] })
+            )
+        "#;
+
+        assert_eq!(
+            rendered(input, &Options::default()),
+            r#"INSERT INTO "t1" ("col1", "col2", "col3") VALUES (uuid(), 'cfbff0d1-9375-5685-968c-48ce8b15ae17', env('HOME'));
+
+INSERT INTO "t1" ("col1") VALUES ('0adedf02-e466-573b-a3fe-6a7f799d7d3d');
+
+INSERT INTO "t1" ("col1") VALUES ('a4cbbdd4-b7e0-589f-965c-38ae16a97c4c');
+
+INSERT INTO "t1" ("col1", "col2", "col3", "col4") VALUES (now(), now() - interval '7 days', now() + interval '1 hour', 'at ' || (now() - interval 'it''s'));
+
+INSERT INTO "t1" ("col1", "col2") VALUES ('\x00ff', bytes('logo.png'));
+
+INSERT INTO "t1" ("col1") VALUES ('{ "name": "it''s", "tags": [] }'::jsonb);
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_fixed_now() {
+        let input = r#"
+            table t1 (
+                (col1 now(), col2 ago('7 days'), col3 'at ' || now())
+            )
+        "#;
+        let options = Options {
+            fixed_now: Some("2024-01-01T00:00:00Z".to_owned()),
+            ..Options::default()
+        };
+
+        assert_eq!(
+            rendered(input, &options),
+            r#"INSERT INTO "t1" ("col1", "col2", "col3") VALUES ('2024-01-01T00:00:00Z'::timestamptz, '2024-01-01T00:00:00Z'::timestamptz - interval '7 days', 'at ' || '2024-01-01T00:00:00Z'::timestamptz);
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_defaults() {
+        let input = r#"
+            table t1 (
+                $columns (col1, col2)
+                ('one', default)
+                (default, default)
+            )
+        "#;
+
+        assert_eq!(
+            rendered(input, &Options::default()),
+            r#"INSERT INTO "t1" ("col1", "col2") VALUES ('one', DEFAULT);
+
+INSERT INTO "t1" ("col1", "col2") VALUES (DEFAULT, DEFAULT);
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_expressions() {
+        let input = r#"
+            table t1 (
+                rec1 (col1 1)
+                (
+                    col1 @rec1.col1 + 2 * 3
+                    col2 'item-' || @col1
+                )
+            )
+        "#;
+
+        assert_eq!(
+            rendered(input, &Options::default()),
+            r#"-- t1.rec1
+INSERT INTO "t1" ("col1") VALUES (1);
+
+INSERT INTO "t1" ("col1", "col2") VALUES (@rec1.col1 + (2 * 3), 'item-' || (@rec1.col1 + (2 * 3)));
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_interpolated_text() {
+        let input = r#"
+            table t1 (
+                rec1 (col1 'it''s')
+                _ * 2 (col1 'copy {index()} of {@rec1.col1 || '!'}', col2 'a {@col1}')
+            )
+        "#;
+
+        assert_eq!(
+            rendered(input, &Options::default()),
+            r#"-- t1.rec1
+INSERT INTO "t1" ("col1") VALUES ('it''s');
+
+INSERT INTO "t1" ("col1", "col2") VALUES (concat('copy ', 1, ' of ', @rec1.col1 || '!'), concat('a ', concat('copy ', 1, ' of ', @rec1.col1 || '!')));
+
+INSERT INTO "t1" ("col1", "col2") VALUES (concat('copy ', 2, ' of ', @rec1.col1 || '!'), concat('a ', concat('copy ', 2, ' of ', @rec1.col1 || '!')));
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_interpolated_sql_fragments() {
+        let input = r#"
+            table t1 (
+                rec1 (col1 1)
+                (col1 `max(col1) + {@rec1.col1 + 1} from t1 where col2 = '{}'`)
+            )
+        "#;
+
+        assert_eq!(
+            rendered(input, &Options::default()),
+            r#"-- t1.rec1
+INSERT INTO "t1" ("col1") VALUES (1);
+
+INSERT INTO "t1" ("col1") VALUES ((SELECT max(col1) + (@rec1.col1 + 1) from t1 where col2 = '{}'));
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_upserts() {
+        let input = r#"
+            table t1 conflict (email) (
+                (email 'a@b.c', name 'A')
+                (email 'd@e.f')
+            )
+            table t2 (
+                (name 'B')
+                ()
+            )
+        "#;
+        let options = Options {
+            on_conflict: OnConflict::Update,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            rendered(input, &options),
+            r#"INSERT INTO "t1" ("email", "name") VALUES ('a@b.c', 'A') ON CONFLICT ("email") DO UPDATE SET "name" = EXCLUDED."name";
+
+INSERT INTO "t1" ("email") VALUES ('d@e.f') ON CONFLICT ("email") DO UPDATE SET "email" = EXCLUDED."email";
+
+INSERT INTO "t2" ("name") VALUES ('B') ON CONFLICT (<primary key>) DO UPDATE SET "name" = EXCLUDED."name";
+
+INSERT INTO "t2" DEFAULT VALUES ON CONFLICT (<primary key>) DO UPDATE SET <primary key> = EXCLUDED.<primary key>;
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_updates() {
+        let input = r#"
+            table person (
+                update admin where (email 'a@b.c') (is_active true, name 'A')
+                update _ where (email null, name 'B') (is_active false)
+            )
+        "#;
+
+        assert_eq!(
+            rendered(input, &Options::default()),
+            r#"-- person.admin
+UPDATE "person" SET "is_active" = true, "name" = 'A' WHERE "email" = 'a@b.c';
+
+UPDATE "person" SET "is_active" = false WHERE "email" IS NULL AND "name" = 'B';
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_deletes() {
+        let input = r#"
+            table person ( kevin (name 'Kevin') )
+            delete from pet where (owner_id @person.kevin.id)
+            schema s1 (
+                delete from person where (email 'a@b.c', team null)
+            )
+        "#;
+
+        assert_eq!(
+            rendered(input, &Options::default()),
+            r#"-- person.kevin
+INSERT INTO "person" ("name") VALUES ('Kevin');
+
+DELETE FROM "pet" WHERE "owner_id" = @person.kevin.id;
+
+DELETE FROM "s1"."person" WHERE "email" = 'a@b.c' AND "team" IS NULL;
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_ignored_conflicts() {
+        let input = r#"
+            table t1 conflict (email) (
+                (email 'a@b.c', name 'A')
+            )
+            table t2 (
+                ()
+            )
+        "#;
+        let options = Options {
+            on_conflict: OnConflict::Ignore,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            rendered(input, &options),
+            r#"INSERT INTO "t1" ("email", "name") VALUES ('a@b.c', 'A') ON CONFLICT ("email") DO NOTHING;
+
+INSERT INTO "t2" DEFAULT VALUES ON CONFLICT DO NOTHING;
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_roles_and_settings() {
+        let input = r#"
+            schema s1 as role owner with (app.tenant_id '42') (
+                table t1 (
+                    ()
+                )
+                table t2 with (app.tenant_id '7', app.user_id 3) (
+                    ()
+                )
+            )
+            table t3 (
+                ()
+            )
+        "#;
+        let options = Options {
+            role: Some("seeder".to_owned()),
+            ..Options::default()
+        };
+
+        assert_eq!(
+            rendered(input, &options),
+            r#"SET LOCAL ROLE "owner";
+
+SET LOCAL app.tenant_id = '42';
+
+INSERT INTO "s1"."t1" DEFAULT VALUES;
+
+SET LOCAL app.tenant_id = '7';
+
+SET LOCAL app.user_id = '3';
+
+INSERT INTO "s1"."t2" DEFAULT VALUES;
+
+SET LOCAL ROLE "seeder";
+
+SET LOCAL app.tenant_id TO DEFAULT;
+
+SET LOCAL app.user_id TO DEFAULT;
+
+INSERT INTO "t3" DEFAULT VALUES;
+
+"#,
+        );
+    }
+
+    #[test]
+    fn test_render_disabled_triggers() {
+        let input = r#"
+            table t1 as role app (
+                ()
+            )
+            table t2 as role app (
+                ()
+            )
+            table t1 (
+                ()
+            )
+        "#;
+        let options = Options {
+            disable_triggers: Some(DisableTriggers::User),
+            ..Options::default()
+        };
+
+        assert_eq!(
+            rendered(input, &options),
+            r#"ALTER TABLE "t1" DISABLE TRIGGER USER;
+
+SET LOCAL ROLE "app";
+
+INSERT INTO "t1" DEFAULT VALUES;
+
+SET LOCAL ROLE NONE;
+
+ALTER TABLE "t2" DISABLE TRIGGER USER;
+
+SET LOCAL ROLE "app";
+
+INSERT INTO "t2" DEFAULT VALUES;
+
+SET LOCAL ROLE NONE;
+
+INSERT INTO "t1" DEFAULT VALUES;
+
+ALTER TABLE "t1" ENABLE TRIGGER USER;
+
+ALTER TABLE "t2" ENABLE TRIGGER USER;
+
+"#,
+        );
+
+        let options = Options {
+            disable_triggers: Some(DisableTriggers::Replica),
+            ..Options::default()
+        };
+
+        assert_eq!(
+            rendered("table t1 (\n  ()\n)\ntable t2 (\n  ()\n)", &options),
+            r#"SET LOCAL session_replication_role = 'replica';
+
+INSERT INTO "t1" DEFAULT VALUES;
+
+INSERT INTO "t2" DEFAULT VALUES;
+
+SET LOCAL session_replication_role TO DEFAULT;
+
+"#,
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use super::error::LoadError;
+use std::env;
+
+/// Looks up the value of the environment variable for an `env('NAME')` value.
+pub(super) fn var(name: &str) -> Result<String, LoadError> {
+    env::var(name).map_err(|e| LoadError::env_var(name, e))
+}
+
+/// Replaces each `${NAME}` in the text with the value of the environment variable,
+/// where `$${` is written as a literal `${`. Placeholders that are not valid variable
+/// names are left as-is, as is `${n}`, which is reserved for repeated records.
+pub(super) fn interpolate(text: &str) -> Result<String, LoadError> {
+    let mut interpolated = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        let (before, after) = (&rest[..start], &rest[start + 2..]);
+
+        if let Some(before) = before.strip_suffix('$') {
+            interpolated.push_str(before);
+            interpolated.push_str("${");
+            rest = after;
+            continue;
+        }
+
+        interpolated.push_str(before);
+
+        match after.find('}') {
+            Some(end) if is_var_name(&after[..end]) => {
+                interpolated.push_str(&var(&after[..end])?);
+                rest = &after[end + 1..];
+            }
+            _ => {
+                interpolated.push_str("${");
+                rest = after;
+            }
+        }
+    }
+
+    interpolated.push_str(rest);
+
+    Ok(interpolated)
+}
+
+fn is_var_name(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    s != "n"
+        && chars
+            .next()
+            .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::interpolate;
+    use std::env;
+
+    #[test]
+    fn test_interpolate() {
+        env::set_var("HLDR_TEST_INTERPOLATE", "secret");
+
+        for (text, expected) in [
+            ("", ""),
+            ("no variables", "no variables"),
+            ("${HLDR_TEST_INTERPOLATE}", "secret"),
+            (
+                "a ${HLDR_TEST_INTERPOLATE} and ${HLDR_TEST_INTERPOLATE}!",
+                "a secret and secret!",
+            ),
+            (
+                "escaped $${HLDR_TEST_INTERPOLATE}",
+                "escaped ${HLDR_TEST_INTERPOLATE}",
+            ),
+            ("copy ${n}", "copy ${n}"),
+            (
+                "not ${a name} or ${} or ${1X} or ${unclosed",
+                "not ${a name} or ${} or ${1X} or ${unclosed",
+            ),
+            ("$5 and {braces}", "$5 and {braces}"),
+        ] {
+            assert_eq!(interpolate(text).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_interpolate_unset() {
+        let err = interpolate("${HLDR_TEST_DEFINITELY_UNSET}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "environment variable `HLDR_TEST_DEFINITELY_UNSET` is not set"
+        );
+    }
+}
@@ -1,38 +1,45 @@
-use std::{error::Error, fmt};
+use std::{env::VarError, error::Error, fmt, io, path::{Path, PathBuf}};
 
 use postgres::error::Error as PostgresError;
 
+use crate::diagnostics::{self, SourceLocation};
+use crate::parser::nodes::Attribute;
+use crate::Position;
+
 #[derive(Debug)]
 pub enum ClientErrorKind {
     Config,
     Connection,
 }
 
+/// An error from whichever database driver the connection string is for
+type DriverError = Box<dyn Error + Send + Sync>;
+
 #[derive(Debug)]
 pub struct ClientError {
-    error: PostgresError,
+    error: DriverError,
     kind: ClientErrorKind,
 }
 
 impl ClientError {
-    pub fn config_error(error: PostgresError) -> Self {
+    pub fn config_error(error: impl Into<DriverError>) -> Self {
         Self {
             kind: ClientErrorKind::Config,
-            error,
+            error: error.into(),
         }
     }
 
-    pub fn connection_error(error: PostgresError) -> Self {
+    pub fn connection_error(error: impl Into<DriverError>) -> Self {
         Self {
             kind: ClientErrorKind::Connection,
-            error,
+            error: error.into(),
         }
     }
 }
 
 impl Error for ClientError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.error)
+        Some(&*self.error)
     }
 }
 
@@ -48,22 +55,463 @@ impl fmt::Display for ClientError {
 }
 
 #[derive(Debug)]
-pub struct LoadError(PostgresError);
+pub enum LoadErrorKind {
+    /// Errors from databases other than Postgres
+    Backend(DriverError),
+    Copy(io::Error),
+    Database(PostgresError),
+    EnvVar { name: String, error: VarError },
+    /// Inserts that failed and were rolled back with the `collect_errors` option, which
+    /// fail the whole load once every table has been loaded
+    FailedInserts(Vec<FailedInsert>),
+    File { path: PathBuf, error: io::Error },
+    /// A SQL fragment that selected other than a single column
+    FragmentColumns { fragment: String, found: usize },
+    /// A SQL fragment that selected other than a single row
+    FragmentRows { fragment: String, found: usize },
+    /// An error from the database while inserting into the table, along with the statement
+    /// that failed, if it was the insert itself that did, and the name of the record being
+    /// inserted if it is named and was inserted on its own
+    Insert { table: String, record: Option<String>, statement: Option<String>, error: PostgresError },
+    InvalidExpression { column: String, expression: String, reason: String },
+    NoPrimaryKey { table: String },
+    /// The statements could not be written to the output file rather than executed
+    Output { path: PathBuf, error: io::Error },
+    /// A table that does not have the number of rows declared with `expect N rows` once loaded
+    RowCountMismatch { table: String, expected: u64, found: u64 },
+    /// Every way in which the data files do not match the database, found by verifying the schema
+    SchemaMismatch(Vec<String>),
+    /// The statements could not be logged to the file given with `log_sql`
+    SqlLog { path: PathBuf, error: io::Error },
+    UnresolvedConflict { table: String },
+    /// A record declared with `update ... where (...)` that matched no rows, or several
+    /// rows while being named, along with how many it matched
+    UnmatchedUpdate { table: String, record: Option<String>, found: u64 },
+    UnresolvedPrimaryKey { record: String, reason: &'static str },
+    UnresolvedReference { record: String, column: String, reason: &'static str },
+    /// The row inserted for a record could not be selected, for databases that cannot return it
+    UnresolvedRow { table: String, reason: &'static str },
+    /// Options that need every data file to be parsed before anything is loaded
+    UnstreamableOption(&'static str),
+    /// Features of data files or options that the database being loaded into does not support
+    Unsupported { backend: &'static str, feature: String },
+}
+
+/// An insert that failed with the `collect_errors` option, along with where its record
+/// was declared in the data files once known.
+#[derive(Debug)]
+pub struct FailedInsert {
+    pub error: LoadError,
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Debug)]
+pub struct LoadError {
+    pub kind: LoadErrorKind,
+    /// Where in the data files the error occurred, if it came from loading a particular
+    /// table, record, or attribute
+    pub position: Option<Position>,
+    /// Where the attribute that the error occurred in ends, if it came from loading a
+    /// particular attribute, so that along with the position it spans the attribute,
+    /// which is boxed to keep errors small
+    pub end: Option<Box<Position>>,
+}
 
 impl LoadError {
+    /// Adds where in the data files the error occurred, unless it already has a position,
+    /// so that the position of the most specific node is kept as the error is passed up.
+    pub fn at(mut self, position: Option<Position>) -> Self {
+        if self.position.is_none() {
+            self.position = position;
+        }
+
+        self
+    }
+
+    /// Adds where the attribute that the error occurred in starts and ends, along with its
+    /// position, unless the error already has a position.
+    pub fn in_attribute(mut self, attribute: &Attribute) -> Self {
+        if self.position.is_none() {
+            self.position = attribute.position;
+            self.end = attribute.span.map(|s| Box::new(s.end));
+        }
+
+        self
+    }
+
     pub fn new(e: PostgresError) -> Self {
-        Self(e)
+        Self {
+            kind: LoadErrorKind::Database(e),
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn backend(e: impl Into<DriverError>) -> Self {
+        Self {
+            kind: LoadErrorKind::Backend(e.into()),
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn copy(e: io::Error) -> Self {
+        Self {
+            kind: LoadErrorKind::Copy(e),
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn env_var(name: &str, error: VarError) -> Self {
+        Self {
+            kind: LoadErrorKind::EnvVar {
+                name: name.to_owned(),
+                error,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn file(path: &Path, error: io::Error) -> Self {
+        Self {
+            kind: LoadErrorKind::File {
+                path: path.to_owned(),
+                error,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn failed_inserts(errors: Vec<LoadError>) -> Self {
+        let failed = errors
+            .into_iter()
+            .map(|error| FailedInsert { error, location: None })
+            .collect();
+
+        Self {
+            kind: LoadErrorKind::FailedInserts(failed),
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn fragment_columns(fragment: &str, found: usize) -> Self {
+        Self {
+            kind: LoadErrorKind::FragmentColumns {
+                fragment: fragment.to_owned(),
+                found,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn fragment_rows(fragment: &str, found: usize) -> Self {
+        Self {
+            kind: LoadErrorKind::FragmentRows {
+                fragment: fragment.to_owned(),
+                found,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    /// Turns errors from the database into errors inserting into the table, leaving any
+    /// other errors as they are.
+    pub fn inserting(self, table: &str, statement: Option<&str>) -> Self {
+        let kind = match self.kind {
+            LoadErrorKind::Database(error) if error.as_db_error().is_some() => LoadErrorKind::Insert {
+                table: table.to_owned(),
+                record: None,
+                statement: statement.map(str::to_owned),
+                error,
+            },
+            kind => kind,
+        };
+
+        Self { kind, ..self }
+    }
+
+    pub fn invalid_expression(column: &str, expression: &str, reason: String) -> Self {
+        Self {
+            kind: LoadErrorKind::InvalidExpression {
+                column: column.to_owned(),
+                expression: expression.to_owned(),
+                reason,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn no_primary_key(table: &str) -> Self {
+        Self {
+            kind: LoadErrorKind::NoPrimaryKey {
+                table: table.to_owned(),
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn output(path: &Path, error: io::Error) -> Self {
+        Self {
+            kind: LoadErrorKind::Output {
+                path: path.to_owned(),
+                error,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn sql_log(path: &Path, error: io::Error) -> Self {
+        Self {
+            kind: LoadErrorKind::SqlLog {
+                path: path.to_owned(),
+                error,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn row_count_mismatch(table: &str, expected: u64, found: u64) -> Self {
+        Self {
+            kind: LoadErrorKind::RowCountMismatch {
+                table: table.to_owned(),
+                expected,
+                found,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn schema_mismatch(mismatches: Vec<String>) -> Self {
+        Self {
+            kind: LoadErrorKind::SchemaMismatch(mismatches),
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn unresolved_conflict(table: &str) -> Self {
+        Self {
+            kind: LoadErrorKind::UnresolvedConflict {
+                table: table.to_owned(),
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn unmatched_update(table: &str, record: Option<&str>, found: u64) -> Self {
+        Self {
+            kind: LoadErrorKind::UnmatchedUpdate {
+                table: table.to_owned(),
+                record: record.map(str::to_owned),
+                found,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn unresolved_primary_key(record: &str, reason: &'static str) -> Self {
+        Self {
+            kind: LoadErrorKind::UnresolvedPrimaryKey {
+                record: record.to_owned(),
+                reason,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn unresolved_reference(record: &str, column: &str, reason: &'static str) -> Self {
+        Self {
+            kind: LoadErrorKind::UnresolvedReference {
+                record: record.to_owned(),
+                column: column.to_owned(),
+                reason,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn unresolved_row(table: &str, reason: &'static str) -> Self {
+        Self {
+            kind: LoadErrorKind::UnresolvedRow {
+                table: table.to_owned(),
+                reason,
+            },
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn unstreamable_option(option: &'static str) -> Self {
+        Self {
+            kind: LoadErrorKind::UnstreamableOption(option),
+            position: None,
+            end: None,
+        }
+    }
+
+    pub fn unsupported(backend: &'static str, feature: impl Into<String>) -> Self {
+        Self {
+            kind: LoadErrorKind::Unsupported {
+                backend,
+                feature: feature.into(),
+            },
+            position: None,
+            end: None,
+        }
     }
 }
 
 impl Error for LoadError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.0)
+        match &self.kind {
+            LoadErrorKind::Backend(e) => Some(&**e),
+            LoadErrorKind::Copy(e) => Some(e),
+            LoadErrorKind::Database(e) => Some(e),
+            LoadErrorKind::File { error, .. } => Some(error),
+            LoadErrorKind::Insert { error, .. } => Some(error),
+            LoadErrorKind::Output { error, .. } => Some(error),
+            LoadErrorKind::SqlLog { error, .. } => Some(error),
+            _ => None,
+        }
     }
 }
 
 impl fmt::Display for LoadError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.0.fmt(f)
+        match &self.kind {
+            LoadErrorKind::Backend(e) => e.fmt(f),
+            LoadErrorKind::Copy(e) => e.fmt(f),
+            LoadErrorKind::Database(e) => e.fmt(f),
+            LoadErrorKind::EnvVar { name, error } => match error {
+                VarError::NotPresent => write!(f, "environment variable `{}` is not set", name),
+                VarError::NotUnicode(_) => write!(f, "environment variable `{}` is not valid unicode", name),
+            },
+            LoadErrorKind::File { path, error } => write!(f, "could not read file `{}`: {}", path.display(), error),
+            LoadErrorKind::FailedInserts(failed) => {
+                let plural = if failed.len() == 1 { "" } else { "s" };
+                write!(f, "{} insert{} failed, so nothing was loaded", failed.len(), plural)?;
+
+                for FailedInsert { error, location } in failed {
+                    let mut message = error.to_string();
+
+                    // Statements are written over several lines, which are joined into one
+                    if let LoadErrorKind::Insert { statement: Some(statement), .. } = &error.kind {
+                        let lines: Vec<&str> = statement.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+                        message.push_str(&format!("\n  statement: {}", lines.join(" ")));
+                    }
+
+                    write!(f, "\n\n{}", diagnostics::render(&message, location.as_ref()))?;
+                }
+
+                Ok(())
+            }
+            LoadErrorKind::FragmentColumns { fragment, found } => {
+                write!(f, "expected a single column from SQL fragment `{}`, found {}", fragment, found)
+            }
+            LoadErrorKind::FragmentRows { fragment, found } => {
+                write!(f, "expected a single row from SQL fragment `{}`, found {}", fragment, found)
+            }
+            LoadErrorKind::Insert { table, record, error, .. } => {
+                match record {
+                    Some(record) => write!(f, "could not insert record `{}` into table {}", record, table)?,
+                    None => write!(f, "could not insert into table {}", table)?,
+                }
+
+                let db_error = match error.as_db_error() {
+                    Some(db_error) => db_error,
+                    None => return write!(f, ": {}", error),
+                };
+
+                write!(f, ": {}", db_error.message())?;
+
+                // The fields that the server gives for some errors, eg. the column of a
+                // not-null violation or the constraint of a foreign key violation
+                let fields = [
+                    ("detail", db_error.detail()),
+                    ("hint", db_error.hint()),
+                    ("column", db_error.column()),
+                    ("type", db_error.datatype()),
+                    ("constraint", db_error.constraint()),
+                ];
+
+                for (label, value) in fields {
+                    if let Some(value) = value {
+                        write!(f, "\n  {}: {}", label, value)?;
+                    }
+                }
+
+                Ok(())
+            }
+            LoadErrorKind::InvalidExpression { column, expression, reason } => write!(
+                f,
+                "could not evaluate `{}` for column `{}`, as {}",
+                expression, column, reason,
+            ),
+            LoadErrorKind::NoPrimaryKey { table } => {
+                write!(f, "table {} has no primary key to use as a conflict target", table)
+            }
+            LoadErrorKind::Output { path, error } => {
+                write!(f, "could not write statements to `{}`: {}", path.display(), error)
+            }
+            LoadErrorKind::RowCountMismatch { table, expected, found } => {
+                write!(f, "expected {} rows in table {} once loaded, found {}", expected, table, found)
+            }
+            LoadErrorKind::SchemaMismatch(mismatches) => {
+                write!(f, "data files do not match the database schema:")?;
+
+                for mismatch in mismatches {
+                    write!(f, "\n  - {}", mismatch)?;
+                }
+
+                Ok(())
+            }
+            LoadErrorKind::SqlLog { path, error } => {
+                write!(f, "could not log statements to `{}`: {}", path.display(), error)
+            }
+            LoadErrorKind::UnresolvedConflict { table } => write!(
+                f,
+                "could not find the existing row in table {} that a skipped record conflicts with",
+                table,
+            ),
+            LoadErrorKind::UnmatchedUpdate { table, record, found } => match (record, found) {
+                (Some(record), 0) => write!(f, "no rows in table {} match record `{}` to update", table, record),
+                (None, _) => write!(f, "no rows in table {} match the record to update", table),
+                (Some(record), found) => write!(
+                    f,
+                    "{} rows in table {} match record `{}`, which must match a single row to be named",
+                    found, table, record,
+                ),
+            },
+            LoadErrorKind::UnresolvedPrimaryKey { record, reason } => {
+                write!(f, "could not reference the primary key of record `{}`, as {}", record, reason)
+            }
+            LoadErrorKind::UnresolvedReference { record, column, reason } => write!(
+                f,
+                "could not select referenced column `{}` of record `{}`, as {}",
+                column, record, reason,
+            ),
+            LoadErrorKind::UnresolvedRow { table, reason } => {
+                write!(f, "could not select the row inserted into table {}, as {}", table, reason)
+            }
+            LoadErrorKind::UnstreamableOption(option) => {
+                write!(f, "the `{}` option cannot be used when streaming data files", option)
+            }
+            LoadErrorKind::Unsupported { backend, feature } => write!(f, "{} does not support {}", backend, feature),
+        }
     }
 }
@@ -0,0 +1,218 @@
+use crate::parser::nodes::Operator;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// The minimum number of decimal places in the result of dividing numbers that are
+/// not both integers, as with `numeric` division in Postgres.
+const DIVISION_SCALE: u32 = 16;
+
+/// Applies the operator to the values of its operands, which are null if either is,
+/// as in SQL. Arithmetic is exact rather than in floating point, so that eg.
+/// `10.00 * 1.1` is `11.000`, and division of integers is integer division.
+///
+/// Returns the reason the expression could not be evaluated if either operand of
+/// an arithmetic operator is not a number or the result is out of range.
+pub(super) fn evaluate(left: Option<String>, operator: Operator, right: Option<String>) -> Result<Option<String>, String> {
+    let (left, right) = match (left, right) {
+        (Some(left), Some(right)) => (left, right),
+        _ => return Ok(None),
+    };
+
+    if operator == Operator::Concat {
+        return Ok(Some(left + &right));
+    }
+
+    let number = |value: &str| {
+        value
+            .parse::<Decimal>()
+            .map_err(|_| format!("`{}` is not a number", value))
+    };
+    let (left, right) = (number(&left)?, number(&right)?);
+
+    let result = match operator {
+        Operator::Add => left.add(right),
+        Operator::Subtract => left.add(right.negate()),
+        Operator::Multiply => left.multiply(right),
+        Operator::Divide if right.digits == 0 => return Err("it divides by zero".to_owned()),
+        Operator::Divide => left.divide(right),
+        Operator::Concat => unreachable!(),
+    };
+
+    match result {
+        Some(result) => Ok(Some(result.to_string())),
+        None => Err("the result is out of range".to_owned()),
+    }
+}
+
+/// An exact decimal number with the given number of digits after the decimal point,
+/// eg. `1.50` has the digits `150` and a scale of two.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Decimal {
+    digits: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    fn add(self, other: Self) -> Option<Self> {
+        let scale = self.scale.max(other.scale);
+        let digits = self.rescale(scale)?.checked_add(other.rescale(scale)?)?;
+
+        Some(Self { digits, scale })
+    }
+
+    fn negate(self) -> Self {
+        Self {
+            digits: -self.digits,
+            ..self
+        }
+    }
+
+    fn multiply(self, other: Self) -> Option<Self> {
+        Some(Self {
+            digits: self.digits.checked_mul(other.digits)?,
+            scale: self.scale.checked_add(other.scale)?,
+        })
+    }
+
+    /// Divides by a non-zero number, truncating integers and otherwise rounding half
+    /// away from zero, without trailing zeros beyond the larger of the two scales.
+    fn divide(self, other: Self) -> Option<Self> {
+        if self.scale == 0 && other.scale == 0 {
+            return Some(Self {
+                digits: self.digits.checked_div(other.digits)?,
+                scale: 0,
+            });
+        }
+
+        let scale = DIVISION_SCALE.max(self.scale).max(other.scale);
+        // The extra digit is used for rounding
+        let dividend = self.digits.checked_mul(10i128.checked_pow(scale + other.scale - self.scale + 1)?)?;
+        let quotient = dividend / other.digits;
+        let rounded = match quotient.cmp(&0) {
+            Ordering::Less => (quotient - 5) / 10,
+            _ => (quotient + 5) / 10,
+        };
+        let mut result = Self { digits: rounded, scale };
+
+        while result.scale > self.scale.max(other.scale) && result.digits % 10 == 0 {
+            result.digits /= 10;
+            result.scale -= 1;
+        }
+
+        Some(result)
+    }
+
+    /// Returns the digits at a scale no smaller than the current one
+    fn rescale(self, scale: u32) -> Option<i128> {
+        self.digits.checked_mul(10i128.checked_pow(scale - self.scale)?)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ();
+
+    /// Parses numbers as written in data files, which may contain underscores and
+    /// exponents, or as returned by Postgres.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().replace('_', "");
+        let (s, exponent) = match s.split_once(['e', 'E']) {
+            Some((s, exponent)) => (s, exponent.parse::<i32>().map_err(|_| ())?),
+            None => (s.as_str(), 0),
+        };
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+        if integer.is_empty() && fraction.is_empty() || !(integer.chars().chain(fraction.chars())).all(|c| c.is_ascii_digit()) {
+            return Err(());
+        }
+
+        let mut digits: i128 = format!("{}{}", integer, fraction).parse().map_err(|_| ())?;
+
+        if negative {
+            digits = -digits;
+        }
+
+        // The exponent moves the decimal point, adding zeros to the digits if it moves
+        // past them, eg. `1.5e3` is `1500` and `1.5e-3` is `0.0015`
+        let scale = fraction.len() as i32 - exponent;
+
+        if scale < 0 {
+            digits = 10_i128
+                .checked_pow(scale.unsigned_abs())
+                .and_then(|factor| digits.checked_mul(factor))
+                .ok_or(())?;
+        }
+
+        Ok(Self {
+            digits,
+            scale: scale.max(0) as u32,
+        })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.digits < 0 { "-" } else { "" };
+        let digits = format!("{:0>width$}", self.digits.unsigned_abs(), width = self.scale as usize + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+
+        match fraction {
+            "" => write!(f, "{}{}", sign, integer),
+            _ => write!(f, "{}{}.{}", sign, integer, fraction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluated(left: &str, operator: Operator, right: &str) -> Result<Option<String>, String> {
+        evaluate(Some(left.to_owned()), operator, Some(right.to_owned()))
+    }
+
+    #[test]
+    fn test_evaluate() {
+        use Operator::*;
+
+        for (left, operator, right, expected) in [
+            ("1", Add, "2", "3"),
+            ("1.5", Add, "-2.25", "-0.75"),
+            ("1_000", Subtract, ".5", "999.5"),
+            ("10.00", Multiply, "1.1", "11.000"),
+            ("7", Divide, "2", "3"),
+            ("-7", Divide, "2", "-3"),
+            ("7.0", Divide, "2", "3.5"),
+            ("1", Divide, "3.0", "0.3333333333333333"),
+            ("2", Divide, "-3.0", "-0.6666666666666667"),
+            ("1e5", Add, "1", "100001"),
+            ("1.5E-3", Multiply, "2", "0.0030"),
+            ("-2.5e+1", Subtract, "1_0e-1", "-26.0"),
+            ("item-", Concat, "1", "item-1"),
+        ] {
+            assert_eq!(
+                evaluated(left, operator, right),
+                Ok(Some(expected.to_owned())),
+                "{} {} {}",
+                left,
+                operator,
+                right,
+            );
+        }
+
+        assert_eq!(evaluate(None, Add, Some("1".to_owned())), Ok(None));
+        assert_eq!(evaluate(Some("a".to_owned()), Concat, None), Ok(None));
+        assert_eq!(evaluated("a", Add, "1"), Err("`a` is not a number".to_owned()));
+        assert_eq!(evaluated("1e", Add, "1"), Err("`1e` is not a number".to_owned()));
+        assert_eq!(evaluated("1e99", Add, "1"), Err("`1e99` is not a number".to_owned()));
+        assert_eq!(evaluated("1", Divide, "0.0"), Err("it divides by zero".to_owned()));
+        assert_eq!(
+            evaluated(&i128::MAX.to_string(), Add, "1"),
+            Err("the result is out of range".to_owned()),
+        );
+    }
+}
@@ -1,22 +1,59 @@
+#[cfg(any(feature = "mysql", feature = "sqlite"))]
+pub(crate) mod backend;
+mod connection;
+pub mod conninfo;
+mod ddl;
+pub mod dry_run;
+mod env;
 pub mod error;
+mod expression;
+#[cfg(feature = "mysql")]
+pub(crate) mod mysql;
+pub mod output;
+mod params;
+pub mod plan;
+pub mod report;
+mod schema;
+mod sql_log;
+#[cfg(feature = "sqlite")]
+pub(crate) mod sqlite;
+#[cfg(feature = "sqlx")]
+pub(crate) mod sqlx;
 
-use crate::analyzer::ValidatedParseTree;
+use crate::{DisableTriggers, IsolationLevel, OnConflict, Options, SkipExisting};
+use crate::identifier::quote;
+use crate::analyzer::{referenced_columns, ValidatedParseTree, ValidatedTable};
 use crate::parser::nodes::{
     Attribute,
+    HookTiming,
+    Operator,
+    ParseTree,
+    Record,
     Reference,
     ReferencedColumn,
+    Setting,
     StructuralIdentity,
     StructuralNode,
     Table,
+    TextSegment,
+    TimeOffset,
     Value,
 };
-use error::{ClientError, LoadError};
-use postgres::{config::Config, Client, NoTls, SimpleQueryMessage, SimpleQueryRow, Transaction};
-use std::{collections::HashMap, str::FromStr, time::Duration};
+pub(crate) use connection::{block_on, Connection, TransactionClient};
+pub(crate) use sql_log::{log_statement, SqlLog};
+use error::{ClientError, LoadError, LoadErrorKind};
+use params::{bytea_text, unquote_text, TextParam};
+use report::{record_row, PlaceReport, RecordRow, SkippedTable, TableReport};
+use postgres::types::ToSql;
+use postgres::{config::Config, Client, NoTls, Row, SimpleQueryMessage, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::{fs, mem, str::FromStr, time::{Duration, Instant}};
+use uuid::Uuid;
 
 // TODO: move this
 pub fn new_client(connstr: &str) -> Result<Client, ClientError> {
-    let mut config = Config::from_str(connstr).map_err(ClientError::config_error)?;
+    let connstr = conninfo::resolve(connstr);
+    let mut config = Config::from_str(&connstr).map_err(ClientError::config_error)?;
 
     config.application_name("hldr");
 
@@ -27,321 +64,3045 @@ pub fn new_client(connstr: &str) -> Result<Client, ClientError> {
     config.connect(NoTls).map_err(ClientError::connection_error)
 }
 
+/// Connects a `tokio-postgres` client, whose connection is spawned onto the current runtime.
+#[cfg(feature = "async")]
+pub async fn new_async_client(connstr: &str) -> Result<tokio_postgres::Client, ClientError> {
+    let connstr = conninfo::resolve(connstr);
+    let mut config = tokio_postgres::Config::from_str(&connstr).map_err(ClientError::config_error)?;
+
+    config.application_name("hldr");
+
+    if config.get_connect_timeout().is_none() {
+        config.connect_timeout(Duration::new(30, 0));
+    }
+
+    let (client, connection) = config
+        .connect(tokio_postgres::NoTls)
+        .await
+        .map_err(ClientError::connection_error)?;
+
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::error!("Connection error: {}", e);
+        }
+    });
+
+    Ok(client)
+}
+
+/// Begins a transaction with the isolation level and settings given in the options.
+pub fn begin<'a>(client: &'a mut Client, options: &Options) -> LoadResult<Transaction<'a>> {
+    let mut builder = client.build_transaction();
+
+    if let Some(level) = options.isolation_level {
+        builder = builder.isolation_level(level.into());
+    }
+
+    let mut transaction = builder.start().map_err(LoadError::new)?;
+    let mut connection = SqlLog::new(&mut transaction, options)?;
+
+    connection.log(&begin_statement(options));
+    block_on(apply_settings(&mut connection, options))?;
+    connection.finish()?;
+
+    Ok(transaction)
+}
+
+/// Begins a `tokio-postgres` transaction, just as [`begin`] does a blocking one.
+#[cfg(feature = "async")]
+pub async fn begin_async<'a>(
+    client: &'a mut tokio_postgres::Client,
+    options: &Options,
+) -> LoadResult<tokio_postgres::Transaction<'a>> {
+    let mut builder = client.build_transaction();
+
+    if let Some(level) = options.isolation_level {
+        builder = builder.isolation_level(level.into());
+    }
+
+    let mut transaction = builder.start().await.map_err(LoadError::new)?;
+    let mut connection = SqlLog::new(&mut transaction, options)?;
+
+    connection.log(&begin_statement(options));
+    apply_settings(&mut connection, options).await?;
+    connection.finish()?;
+
+    Ok(transaction)
+}
+
+/// Returns the statement that begins the transaction, as it is logged with the `log_sql`
+/// option, since the transaction is begun by the driver rather than the loader.
+fn begin_statement(options: &Options) -> String {
+    match options.isolation_level {
+        Some(IsolationLevel::ReadCommitted) => "BEGIN ISOLATION LEVEL READ COMMITTED".to_owned(),
+        Some(IsolationLevel::RepeatableRead) => "BEGIN ISOLATION LEVEL REPEATABLE READ".to_owned(),
+        Some(IsolationLevel::Serializable) => "BEGIN ISOLATION LEVEL SERIALIZABLE".to_owned(),
+        None => "BEGIN".to_owned(),
+    }
+}
+
+/// Sets the timeouts and other settings given in the options for the rest of the
+/// transaction, which are only set when hldr begins the transaction itself, as
+/// transactions given to it are left as the caller configured them.
+async fn apply_settings<C: Connection>(connection: &mut C, options: &Options) -> LoadResult<()> {
+    for statement in settings(options) {
+        log::debug!("{}", statement);
+
+        connection.simple_query(&statement).await.map_err(LoadError::new)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the statements that apply the options' settings to the transaction.
+fn settings(options: &Options) -> Vec<String> {
+    let mut statements = Vec::new();
+
+    // Deferrable constraints, eg. foreign keys between tables that reference each
+    // other, are only checked on commit so records can be loaded in any order
+    if options.defer_constraints {
+        statements.push("SET CONSTRAINTS ALL DEFERRED".to_owned());
+    }
+
+    if let Some(schemas) = &options.search_path {
+        statements.push(set_search_path(schemas));
+    }
+
+    if let Some(role) = &options.role {
+        statements.push(set_role(Some(role)));
+    }
+
+    let settings = [
+        ("lock_timeout", &options.lock_timeout),
+        ("statement_timeout", &options.statement_timeout),
+        ("synchronous_commit", &options.synchronous_commit),
+    ];
+
+    for (name, value) in settings {
+        if let Some(value) = value {
+            statements.push(set_local(name, value));
+        }
+    }
+
+    statements
+}
+
+fn set_local(name: &str, value: &str) -> String {
+    format!("SET LOCAL {} = '{}'", name, value.replace('\'', "''"))
+}
+
+/// Returns the SQL for the time of the transaction with the offset, if any, which is
+/// the time given with the `fixed_now` option rather than `now()` if it is set.
+pub(crate) fn now_sql(fixed_now: Option<&str>, offset: Option<&TimeOffset>) -> String {
+    let now = match fixed_now {
+        Some(time) => format!("'{}'::timestamptz", time.replace('\'', "''")),
+        None => "now()".to_owned(),
+    };
+
+    match offset {
+        Some(offset) => format!("{} {}", now, offset),
+        None => now,
+    }
+}
+
+/// Returns the statement that sets the search path, whose schemas are quoted rather
+/// than given as a single string, which would be taken as the name of one schema.
+fn set_search_path(schemas: &[String]) -> String {
+    let schemas: Vec<String> = schemas.iter().map(|s| quote(s)).collect();
+    format!("SET LOCAL search_path = {}", schemas.join(", "))
+}
+
+/// Returns the statement that switches to the role, or without one, back to the
+/// session's own role.
+fn set_role(role: Option<&str>) -> String {
+    match role {
+        Some(role) => format!("SET LOCAL ROLE {}", quote(role)),
+        None => "SET LOCAL ROLE NONE".to_owned(),
+    }
+}
+
+/// Returns the statements that change the settings from those of the last table loaded
+/// to the next's, resetting any that the next table does not declare to their defaults.
+fn change_settings(from: &[Setting], to: &[Setting]) -> Vec<String> {
+    let mut statements = Vec::new();
+
+    for setting in from {
+        if !to.iter().any(|s| s.name == setting.name) {
+            statements.push(format!("SET LOCAL {} TO DEFAULT", setting.name));
+        }
+    }
+
+    for setting in to {
+        if !from.contains(setting) {
+            statements.push(set_local(&setting.name, &setting.value));
+        }
+    }
+
+    statements
+}
+
+/// Returns the statement that sets the search path declared in the data files with
+/// `set search_path ...`, if any, which is set when loading the tree rather than along
+/// with the transaction's other settings, as it is part of the data itself.
+pub(crate) fn search_path_statement(tree: &ParseTree) -> Option<String> {
+    tree.search_paths.first().map(|search_path| set_search_path(&search_path.schemas))
+}
+
+// TODO: A lot of this is copy-pasta from analyzer
+//
+// *something something* visitor pattern
+fn qualified_table_name(schema: Option<&StructuralIdentity>, table: &Table) -> String {
+    match schema {
+        Some(schema) => format!("{}.{}", quote(&schema.name), quote(&table.identity.name)),
+        None => quote(&table.identity.name),
+    }
+}
+
+/// Returns the scope that the table's records are keyed by in the ref map, which
+/// is qualified with the table's namespace, if any, eg. `billing::customer`.
+fn table_scope(schema: Option<&StructuralIdentity>, table: &Table) -> String {
+    let scope = table
+        .identity
+        .alias
+        .as_ref()
+        .unwrap_or(&table.identity.name);
+    let scope = match schema {
+        Some(schema) => format!(
+            "{}.{}",
+            schema.alias.as_ref().unwrap_or(&schema.name),
+            scope,
+        ),
+        None => scope.to_owned(),
+    };
+
+    namespaced(table.namespace.as_deref(), scope)
+}
+
+fn namespaced(namespace: Option<&str>, key: String) -> String {
+    match namespace {
+        Some(namespace) => format!("{}::{}", namespace, key),
+        None => key,
+    }
+}
+
 type LoadResult<T> = Result<T, LoadError>;
-type RefMap = HashMap<String, SimpleQueryRow>;
 
-struct Loader<'a, 'b>
-where
-    'b: 'a,
-{
+/// The column that upserts return to tell whether each row was inserted rather than
+/// updated, which is left out of the rows that are reported and referenced.
+const INSERTED_COLUMN: &str = "hldr_inserted";
+
+type RefMap = HashMap<String, ReferencedRow>;
+
+/// The row of a named record that other records can reference, along with the
+/// table it was loaded into to select any referenced columns that were not returned
+struct ReferencedRow {
+    qualified_table_name: String,
+    row: RecordRow,
+    /// The name of the table's primary key column, once looked up for references
+    /// to the record itself
+    primary_key: Option<String>,
+}
+
+/// The maximum number of parameters that can be bound to a single statement
+const MAX_PARAMS: usize = u16::MAX as usize;
+
+/// Selects the names of the primary key columns of the table named by the parameter,
+/// in the order they are declared
+const PRIMARY_KEY_QUERY: &str = "
+    SELECT a.attname::text
+    FROM pg_index i
+    JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey)
+    WHERE i.indrelid = $1::text::regclass AND i.indisprimary
+    ORDER BY array_position(i.indkey::int2[], a.attnum)
+";
+
+/// Selects the sequence of each of the columns given by the table and column names in
+/// the parameters that has one, eg. `serial` and identity columns, in the order given
+const SERIAL_SEQUENCES_QUERY: &str = "
+    SELECT c.table_name, c.column_name, s.sequence
+    FROM unnest($1::text[], $2::text[]) WITH ORDINALITY AS c(table_name, column_name, position)
+    CROSS JOIN pg_get_serial_sequence(c.table_name, c.column_name) AS s(sequence)
+    WHERE s.sequence IS NOT NULL
+    ORDER BY c.position
+";
+
+/// How the insert statements for a table handle conflicts with existing rows,
+/// where all column names are already quoted.
+enum ConflictAction {
+    Error,
+    /// Skip conflicting records, whose existing rows are then selected by the
+    /// first unique key whose columns are all present in the record
+    Ignore {
+        target: Option<Vec<String>>,
+        keys: Vec<Vec<String>>,
+    },
+    Update(Vec<String>),
+}
+
+struct Loader<'a, C: Connection> {
+    bulk: bool,
+    collect_errors: bool,
+    keep_going: bool,
+    on_conflict: OnConflict,
+    /// The keys that records are skipped by if their rows already exist
+    skip_existing: Vec<SkipExisting>,
+    /// The columns that other records reference of each record that is referenced, by key,
+    /// or `None` if which records are referenced is not known, eg. when streaming, in which
+    /// case every named record is assumed to be referenced in full
+    referenced: Option<HashMap<String, HashSet<String>>>,
     refmap: RefMap,
-    transaction: &'a mut Transaction<'b>,
+    /// The keys of the named records in tables skipped with the `keep_going` option
+    skipped_records: HashSet<String>,
+    report: PlaceReport,
+    /// The role the transaction was in before switching to a table's role for the first
+    /// time, which tables without roles of their own are loaded as
+    base_role: Option<String>,
+    /// The role of the table last loaded, if it declared one
+    role: Option<String>,
+    /// The settings of the table last loaded
+    settings: Vec<Setting>,
+    /// The columns given values, if their sequences are reset once everything is loaded
+    explicit_columns: Option<ExplicitColumns>,
+    expected_counts: ExpectedCounts,
+    /// The errors of the inserts that failed and were rolled back with the `collect_errors` option
+    failed_inserts: Vec<LoadError>,
+    /// The tables records were loaded into, if the views depending on them are refreshed
+    seeded_tables: Option<SeededTables>,
+    triggers: DisabledTriggers,
+    /// The names of each table's columns, in order
+    table_columns: HashMap<String, Vec<String>>,
+    /// The type of each table's columns by column name, which bound values are cast to
+    column_types: HashMap<String, HashMap<String, String>>,
+    /// The values of the SQL fragments already selected in the transaction, by their SQL,
+    /// if caching them with the `cache_fragments` option
+    fragments: Option<HashMap<String, Option<String>>>,
+    /// The time `now()` is instead, if fixed with the `fixed_now` option
+    fixed_now: Option<String>,
+    /// The number of rows inserted from the table declaration being streamed, if streaming,
+    /// which is logged once the whole declaration is loaded rather than for each chunk
+    streamed_rows: Option<u64>,
+    transaction: &'a mut C,
 }
 
-impl<'a, 'b> Loader<'a, 'b> {
-    fn new(transaction: &'a mut Transaction<'b>) -> Self {
+impl<'a, C: Connection> Loader<'a, C> {
+    fn new(transaction: &'a mut C, options: &Options) -> Self {
         Self {
+            bulk: options.bulk,
+            collect_errors: options.collect_errors,
+            keep_going: options.keep_going,
+            on_conflict: options.on_conflict,
+            skip_existing: options.skip_existing.clone(),
+            referenced: None,
             refmap: HashMap::new(),
+            skipped_records: HashSet::new(),
+            report: PlaceReport::default(),
+            base_role: None,
+            role: None,
+            settings: Vec::new(),
+            explicit_columns: options.reset_sequences.then(ExplicitColumns::default),
+            expected_counts: ExpectedCounts::default(),
+            failed_inserts: Vec::new(),
+            seeded_tables: options.refresh_matviews.then(SeededTables::default),
+            triggers: DisabledTriggers::new(options),
+            table_columns: HashMap::new(),
+            column_types: HashMap::new(),
+            fragments: options.cache_fragments.then(HashMap::new),
+            fixed_now: options.fixed_now.clone(),
+            streamed_rows: None,
             transaction,
         }
     }
 
-    fn load_table(&mut self, schema: Option<&StructuralIdentity>, table: &Table) -> LoadResult<()> {
-        // TODO: A lot of this is copy-pasta from analyzer
-        //
-        // *something something* visitor pattern
-        let qualified_table_name = match schema {
-            Some(schema) => format!(r#""{}"."{}""#, schema.name, table.identity.name),
-            None => format!(r#""{}""#, table.identity.name),
-        };
-        let table_scope = {
-            let scope = table
-                .identity
-                .alias
-                .as_ref()
-                .unwrap_or(&table.identity.name);
-            match schema {
-                Some(schema) => format!(
-                    "{}.{}",
-                    schema.alias.as_ref().unwrap_or(&schema.name),
-                    scope,
-                ),
-                None => scope.to_owned(),
-            }
+    /// Whether the record's row must be returned when inserted, which is only
+    /// true for named records that other records reference when bulk loading
+    fn needs_returning(&self, table_scope: &str, record: &Record) -> bool {
+        if !self.bulk {
+            return true;
+        }
+
+        match (&record.name, &self.referenced) {
+            (Some(name), Some(referenced)) => referenced.contains_key(&format!("{}.{}", table_scope, name)),
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Loads the table, or with the `keep_going` option, loads it within a savepoint
+    /// so that if it fails, the error is reported and the table is skipped rather
+    /// than failing the whole load.
+    async fn load_or_skip_table(&mut self, schema: Option<&StructuralIdentity>, table: &Table) -> LoadResult<()> {
+        // Triggers are disabled as the role the transaction was in, which is more likely to
+        // own the table than the table's own role
+        if let Some(statement) = self.triggers.disable(&qualified_table_name(schema, table)) {
+            self.switch_role(None).await?;
+            log::debug!("{}", statement);
+            self.transaction.simple_query(&statement).await.map_err(LoadError::new)?;
+        }
+
+        // Switched outside of the savepoint so that the role and settings are still known
+        // if the table is rolled back
+        self.switch_role(table.role.as_deref()).await?;
+        self.switch_settings(&table.settings).await?;
+
+        if !self.keep_going {
+            return self.load_table(schema, table).await.map_err(|e| e.at(table.position));
+        }
+
+        self.savepoint("SAVEPOINT hldr_table").await?;
+
+        let error = match self.load_table(schema, table).await.map_err(|e| e.at(table.position)) {
+            Ok(()) => return self.savepoint("RELEASE SAVEPOINT hldr_table").await,
+            Err(error) => error,
         };
 
-        for record in &table.nodes {
-            let row = self.insert(&qualified_table_name, &table_scope, &record.nodes)?;
+        self.savepoint("ROLLBACK TO SAVEPOINT hldr_table").await?;
+        log::error!("Skipping table {}: {}", qualified_table_name(schema, table), error);
 
-            if let Some(name) = &record.name {
-                let key = format!("{}.{}", table_scope, name);
+        // Rows of the table's records were rolled back, so any later references to
+        // them fail rather than referencing rows that no longer exist
+        let table_scope = table_scope(schema, table);
 
-                if self.refmap.insert(key, row).is_some() {
-                    panic!("duplicate record in table {}: {}", table_scope, name);
-                }
-            }
+        for name in table.nodes.iter().filter_map(|r| r.name.as_ref()) {
+            let key = format!("{}.{}", table_scope, name);
+
+            self.refmap.remove(&key);
+            self.skipped_records.insert(key);
         }
 
+        self.report.skipped.push(SkippedTable {
+            schema: schema.map(|s| s.name.clone()),
+            table: table.identity.name.clone(),
+            error: error.to_string(),
+        });
+
         Ok(())
     }
 
-    fn insert(
-        &mut self,
-        qualified_table_name: &str,
-        table_scope: &str,
-        attributes: &[Attribute],
-    ) -> Result<SimpleQueryRow, LoadError> {
-        let statement = InsertStatement::build(self.transaction)
-            .attributes(attributes)
-            .current_scope(table_scope)
-            .qualified_table_name(qualified_table_name)
-            .refmap(&self.refmap)
-            .finish()?;
+    /// Switches to the role, or without one, back to the role the transaction was in,
+    /// unless the last table was loaded as the same role.
+    async fn switch_role(&mut self, role: Option<&str>) -> LoadResult<()> {
+        if self.role.as_deref() == role {
+            return Ok(());
+        }
 
-        let resp = self
-            .transaction
-            .simple_query(statement.as_ref())
-            .map_err(LoadError::new)?
-            .remove(0);
+        if self.base_role.is_none() {
+            let rows = self
+                .transaction
+                .query("SELECT current_user::text", &[])
+                .await
+                .map_err(LoadError::new)?;
 
-        match resp {
-            SimpleQueryMessage::Row(row) => Ok(row),
-            _ => unreachable!(),
+            self.base_role = Some(rows[0].get(0));
         }
+
+        let statement = set_role(role.or(self.base_role.as_deref()));
+        log::debug!("{}", statement);
+        self.transaction.simple_query(&statement).await.map_err(LoadError::new)?;
+        self.role = role.map(str::to_owned);
+
+        Ok(())
     }
-}
 
-struct FragmentRunner<'a, 'b>
-where
-    'b: 'a,
-{
-    transaction: &'a mut Transaction<'b>,
-}
+    /// Changes the settings from those of the last table to the next's.
+    async fn switch_settings(&mut self, settings: &[Setting]) -> LoadResult<()> {
+        for statement in change_settings(&self.settings, settings) {
+            log::debug!("{}", statement);
+            self.transaction.simple_query(&statement).await.map_err(LoadError::new)?;
+        }
 
-impl<'a, 'b> FragmentRunner<'a, 'b> {
-    fn select(&mut self, fragment: &str) -> Result<String, LoadError> {
-        let query = format!("SELECT {}", fragment);
+        self.settings = settings.to_vec();
 
-        let mut rows = self
-            .transaction
-            .simple_query(&query)
-            .map_err(LoadError::new)?;
+        Ok(())
+    }
 
-        if !matches!(rows[..], [SimpleQueryMessage::Row(_), SimpleQueryMessage::CommandComplete(1)]) {
-            panic!("expected single row from SQL fragment `{}`", fragment);
+    /// Enables the triggers that were disabled, if any.
+    async fn enable_triggers(&mut self) -> LoadResult<()> {
+        for statement in self.triggers.enable() {
+            log::debug!("{}", statement);
+            self.transaction.simple_query(&statement).await.map_err(LoadError::new)?;
         }
 
-        let row = match rows.remove(0) {
-            SimpleQueryMessage::Row(row) => row,
-            _ => unreachable!(),
+        Ok(())
+    }
+
+    /// Resets the sequences of the columns that records gave values for, if any, after
+    /// looking up which of the columns have sequences.
+    async fn reset_sequences(&mut self) -> LoadResult<()> {
+        let columns = match &self.explicit_columns {
+            Some(explicit_columns) if !explicit_columns.columns.is_empty() => &explicit_columns.columns,
+            _ => return Ok(()),
         };
+        let (tables, columns): (Vec<&str>, Vec<&str>) =
+            columns.iter().map(|(table, column)| (table.as_str(), column.as_str())).unzip();
 
-        if row.len() != 1 {
-            panic!("expected one column in SQL fragment result `{}`", fragment);
-        }
-
-        let value = row.get(0).expect("unreachable");
-
-        // TODO: Using simple query protocol to select value is messy because
-        // it requires stringifying selected values, which also means manually
-        // trying to escape single quotes by replacing with two single quotes.
-        //
-        // Using extended query protocol would not; however, it would also complicate
-        // selecting and storing values (since they would no longer just be strings
-        // for all the things) and also requires rewriting the insert logic to rely
-        // on having table column types queried before insert to build the right
-        // type conversions in the literal SQL string.
-        //
-        // That would still most likely be for the best, though.
-        //
-        // Alternatively, maybe SQL fragments could be converted to CTEs in the
-        // insert statement and the inserted values could be selected from the CTE
-        // and completely avoid the round-tripping in either protocol, but this
-        // would require a rewrite of the insert statement builder.
-        let value = format!("'{}'", value.replace("'", "''"));
-        println!("VALUE: {}", value);
+        let sequences = self
+            .transaction
+            .query(SERIAL_SEQUENCES_QUERY, &[&tables, &columns])
+            .await
+            .map_err(LoadError::new)?;
 
-        Ok(value)
-    }
-}
+        for row in sequences {
+            let statement = set_sequence(row.get(0), row.get(1), row.get(2));
 
-struct InsertStatementBuilder<
-    'attribute,
-    'current_scope,
-    'fragment1,
-    'fragment2,
-    'qualified_table_name,
-    'refmap,
->
-where
-    'fragment2: 'fragment1
-{
-    attributes: &'attribute [Attribute],
-    attribute_indexes: HashMap<&'attribute str, usize>,
-    current_scope: &'current_scope str,
-    fragment_runner: FragmentRunner<'fragment1, 'fragment2>,
-    qualified_table_name: &'qualified_table_name str,
-    refmap: Option<&'refmap RefMap>,
-}
+            log::debug!("{}", statement);
+            self.transaction.simple_query(&statement).await.map_err(LoadError::new)?;
+        }
 
-impl<'a, 'c, 'f1, 'f2, 'q, 'r> InsertStatementBuilder<'a, 'c, 'f1, 'f2, 'q, 'r> {
-    fn attributes(mut self, attributes: &'a [Attribute]) -> Self {
-        self.attributes = attributes;
-        self.attribute_indexes = HashMap::new();
-        self
+        Ok(())
     }
 
-    fn current_scope(mut self, current_scope: &'c str) -> Self {
-        self.current_scope = current_scope;
-        self
+    /// Refreshes the materialized views that depend on the tables records were loaded
+    /// into, if any.
+    async fn refresh_matviews(&mut self) -> LoadResult<()> {
+        if let Some(statement) = self.seeded_tables.as_ref().and_then(SeededTables::statement) {
+            log::debug!("{}", statement);
+            self.transaction.simple_query(&statement).await.map_err(LoadError::new)?;
+        }
+
+        Ok(())
     }
 
-    fn qualified_table_name(mut self, qualified_table_name: &'q str) -> Self {
-        self.qualified_table_name = qualified_table_name;
-        self
+    /// Counts the rows of each table declared with `expect N rows`, failing if any
+    /// table does not have the expected number.
+    async fn verify_counts(&mut self) -> LoadResult<()> {
+        for (qualified_table_name, expected) in &self.expected_counts.0 {
+            let statement = count_statement(qualified_table_name);
+            log::debug!("{}", statement);
+
+            let rows = self.transaction.query(&statement, &[]).await.map_err(LoadError::new)?;
+            let found = rows[0].get::<_, i64>(0) as u64;
+
+            if found != *expected {
+                return Err(LoadError::row_count_mismatch(qualified_table_name, *expected, found));
+            }
+        }
+
+        Ok(())
     }
 
-    fn refmap(mut self, refmap: &'r RefMap) -> Self {
-        self.refmap = Some(refmap);
-        self
+    async fn savepoint(&mut self, statement: &str) -> LoadResult<()> {
+        log::debug!("{}", statement);
+        self.transaction.simple_query(statement).await.map_err(LoadError::new)?;
+
+        Ok(())
     }
 
-    fn finish(mut self) -> Result<InsertStatement, LoadError> {
-        // TODO: Use bind params and clean this up in general
-        let mut columns = String::new();
-        let mut values = String::new();
+    async fn load_table(&mut self, schema: Option<&StructuralIdentity>, table: &Table) -> LoadResult<()> {
+        let started = Instant::now();
+        let qualified_table_name = qualified_table_name(schema, table);
+        let table_scope = table_scope(schema, table);
+        let on_conflict = match (&table.conflict_target, self.on_conflict) {
+            (Some(columns), OnConflict::Ignore) => {
+                let target: Vec<_> = columns.iter().map(|c| quote(c)).collect();
 
-        for (i, attribute) in self.attributes.iter().enumerate() {
-            columns.push('"');
-            columns.push_str(&attribute.name);
-            columns.push('"');
+                ConflictAction::Ignore {
+                    keys: vec![target.clone()],
+                    target: Some(target),
+                }
+            }
+            (Some(columns), _) => ConflictAction::Update(columns.iter().map(|c| quote(c)).collect()),
+            (None, OnConflict::Error) => ConflictAction::Error,
+            (None, OnConflict::Ignore) => ConflictAction::Ignore {
+                target: None,
+                keys: self.unique_keys(&qualified_table_name).await?,
+            },
+            (None, OnConflict::Update) => ConflictAction::Update(self.primary_key(&qualified_table_name).await?),
+        };
+        let skip_keys = skip_keys(&self.skip_existing, schema, table);
+        let skippable = |record: &Record| skip_keys.iter().any(|key| gives_values(record, key));
+
+        let mut report = TableReport::new(schema.map(|s| s.name.clone()), table.identity.name.clone());
+        let mut batch: Vec<&Record> = Vec::new();
 
-            self.write_value(attribute, &mut values)?;
+        if let Some(explicit_columns) = &mut self.explicit_columns {
+            explicit_columns.add(&qualified_table_name, table);
+        }
 
-            // Only add this after to prevent cyclic references
-            self.attribute_indexes.insert(&attribute.name, i);
+        for record in &table.nodes {
+            if let Some(first) = batch.first() {
+                // Bulk loading copies batches of records whose rows are not needed,
+                // so they cannot be batched with records whose rows are
+                let same_returning =
+                    self.needs_returning(&table_scope, record) == self.needs_returning(&table_scope, first);
 
-            if i < self.attributes.len() - 1 {
-                columns.push_str(", ");
-                values.push_str(", ");
+                // Records are inserted on their own when collecting errors, so that each
+                // failure is of a single record, and when they may be skipped, so that
+                // their existing rows can be selected first
+                if !same_returning
+                    || self.collect_errors
+                    || skippable(record)
+                    || skippable(first)
+                    || !can_batch(&batch, record, &table_scope, &on_conflict, self.fragments.is_some())
+                {
+                    self.insert_or_collect(&qualified_table_name, &table_scope, &batch, &on_conflict, &skip_keys, &mut report)
+                        .await?;
+                    batch.clear();
+                }
             }
+            batch.push(record);
         }
 
-        let statement = format!(
-            r#"
-            INSERT INTO {} ({}) VALUES ({})
-            RETURNING *
-        "#,
-            self.qualified_table_name, columns, values,
-        );
-        println!("{}", statement);
+        if !batch.is_empty() {
+            self.insert_or_collect(&qualified_table_name, &table_scope, &batch, &on_conflict, &skip_keys, &mut report)
+                .await?;
+        }
 
-        Ok(InsertStatement(statement))
-    }
+        // Records are reported with their namespace, since records in other namespaces
+        // can have the same name in the same table
+        if let Some(namespace) = &table.namespace {
+            report.records = mem::take(&mut report.records)
+                .into_iter()
+                .map(|(name, row)| (namespaced(Some(namespace), name), row))
+                .collect();
+        }
 
-    fn write_value(&mut self, attribute: &Attribute, out: &mut String) -> Result<(), LoadError> {
-        match &attribute.value {
-            Value::Bool(b) => out.push_str(&b.to_string()),
-            Value::Number(n) => out.push_str(n),
-            Value::Reference(Reference::ColumnLevel(colref)) => {
-                // Column-reference could refer to a literal value, another
-                // column reference, or a reference to a different record
-                let index = self
-                    .attribute_indexes
-                    .get(&colref.column.as_ref())
-                    .expect("missing column");
+        // Declarations that only update or delete rows are not logged as inserting none
+        let only_deletes = table.nodes.iter().all(|record| record.delete);
 
-                let attribute = &self.attributes[*index];
+        match &mut self.streamed_rows {
+            Some(rows) => *rows += report.rows_inserted,
+            None if report.rows_inserted == 0 && (report.rows_updated > 0 || only_deletes) => {}
+            None => log_inserted(report.rows_inserted, &qualified_table_name),
+        }
 
-                // TODO: Probably best to avoid the recursion?
-                self.write_value(attribute, out)?;
-            }
-            Value::Reference(refval) => {
-                let val = self.follow_ref(attribute, refval)?;
-                out.push_str(&val);
-            }
-            Value::SqlFragment(s) => {
-                let value = self.fragment_runner.select(s)?;
-                out.push_str(&value);
-            }
-            Value::Text(t) => out.push_str(t),
+        if report.rows_updated > 0 {
+            log_updated(report.rows_updated, &qualified_table_name);
+        }
+
+        if only_deletes {
+            log_deleted(report.rows_deleted, &qualified_table_name);
+        }
+
+        report.duration = started.elapsed();
+        self.report.merge(report);
+        self.expected_counts.add(&qualified_table_name, table);
+
+        if let Some(seeded_tables) = &mut self.seeded_tables {
+            seeded_tables.add(&qualified_table_name, table);
         }
 
         Ok(())
     }
 
-    fn follow_ref(&self, attribute: &Attribute, refval: &Reference) -> Result<String, LoadError> {
-        use ReferencedColumn::*;
+    /// Inserts the batch, or with the `collect_errors` option, inserts it within a savepoint
+    /// so that if it fails, it is rolled back and the error is collected while loading
+    /// continues.
+    async fn insert_or_collect(
+        &mut self,
+        qualified_table_name: &str,
+        table_scope: &str,
+        batch: &[&Record],
+        on_conflict: &ConflictAction,
+        skip_keys: &[Vec<String>],
+        report: &mut TableReport,
+    ) -> LoadResult<()> {
+        if !self.collect_errors {
+            return self
+                .insert_batch(qualified_table_name, table_scope, batch, on_conflict, skip_keys, report)
+                .await
+                .map_err(|e| batch_error(e, qualified_table_name, batch));
+        }
+
+        self.savepoint("SAVEPOINT hldr_insert").await?;
 
-        let mut col = &attribute.name;
-        let key = match refval {
-            Reference::SchemaLevel(s) => {
-                if let Explicit(c) = &s.column {
-                    col = &c;
-                }
-                format!("{}.{}.{}", s.schema, s.table, s.record)
+        let error = match self
+            .insert_batch(qualified_table_name, table_scope, batch, on_conflict, skip_keys, report)
+            .await
+        {
+            Ok(()) => return self.savepoint("RELEASE SAVEPOINT hldr_insert").await,
+            Err(error) => batch_error(error, qualified_table_name, batch),
+        };
+
+        self.savepoint("ROLLBACK TO SAVEPOINT hldr_insert").await?;
+        log::debug!("Collected error: {}", error);
+        self.failed_inserts.push(error);
+
+        Ok(())
+    }
+
+    /// Fails with the errors of any inserts that failed with the `collect_errors` option,
+    /// once every table has been loaded.
+    fn check_failed_inserts(&mut self) -> LoadResult<()> {
+        match self.failed_inserts.is_empty() {
+            true => Ok(()),
+            false => Err(LoadError::failed_inserts(mem::take(&mut self.failed_inserts))),
+        }
+    }
+
+    async fn insert_batch(
+        &mut self,
+        qualified_table_name: &str,
+        table_scope: &str,
+        records: &[&Record],
+        on_conflict: &ConflictAction,
+        skip_keys: &[Vec<String>],
+        report: &mut TableReport,
+    ) -> LoadResult<()> {
+        if let [record] = records {
+            if record.delete {
+                report.rows_deleted += self.delete(qualified_table_name, table_scope, record).await?;
+                return Ok(());
             }
-            Reference::TableLevel(t) => {
-                if let Explicit(c) = &t.column {
-                    col = &c;
+
+            if let Some(columns) = &record.update_where {
+                let (rows, updated) = self.update(qualified_table_name, table_scope, record, columns).await?;
+                report.rows_updated += updated;
+
+                if let Some(row) = rows.first() {
+                    self.register(qualified_table_name, table_scope, record, row, report);
                 }
-                format!("{}.{}", t.table, t.record)
+
+                return Ok(());
             }
-            Reference::RecordLevel(r) => {
-                if let Explicit(c) = &r.column {
-                    col = &c;
-                }
-                format!("{}.{}", self.current_scope, r.record)
+
+            // Records that may be skipped are never batched, and are only inserted if no
+            // row with the same values for any of the keys exists
+            if let Some(row) = self.select_skipped(qualified_table_name, table_scope, record, skip_keys).await? {
+                log::debug!("Skipped record as its row already exists in {}", qualified_table_name);
+                self.register(qualified_table_name, table_scope, record, &row, report);
+                return Ok(());
             }
-            // Column-references are handled differently, as there is no record in
-            // the map to look up
-            Reference::ColumnLevel(_) => unreachable!(),
-        };
+        }
 
-        let row = self.refmap.expect("no refmap set").get(&key).unwrap();
-        let val = row.try_get(col.as_str());
+        // `COPY` has no way to leave a column to its default other than leaving it out
+        let can_copy = matches!(on_conflict, ConflictAction::Error)
+            && !records[0].nodes.is_empty()
+            && !records.iter().flat_map(|r| &r.nodes).any(|a| a.value == Value::Default)
+            && !self.needs_returning(table_scope, records[0]);
 
-        Ok(val
-            .unwrap_or_else(|_| panic!("no column '{}' in record {}", col, key))
-            .map_or_else(|| "null".to_owned(), |v| format!("'{}'", v)))
-    }
-}
+        if can_copy {
+            report.rows_inserted += self.copy(qualified_table_name, table_scope, records).await?;
+            return Ok(());
+        }
 
-struct InsertStatement(String);
+        let (rows, inserted, updated) = self
+            .insert(qualified_table_name, table_scope, records, on_conflict)
+            .await?;
+        report.rows_inserted += inserted;
+        report.rows_updated += updated;
 
-impl InsertStatement {
-    fn build<'f1, 'f2>(t: &'f1 mut Transaction<'f2>) -> InsertStatementBuilder<'static, 'static, 'f1, 'f2, 'static, 'static> {
-        InsertStatementBuilder {
-            attributes: &[],
-            attribute_indexes: HashMap::new(),
-            current_scope: "",
-            fragment_runner: FragmentRunner { transaction: t },
-            qualified_table_name: "",
-            refmap: None,
+        for (record, row) in records.iter().zip(rows) {
+            self.register(qualified_table_name, table_scope, record, &row, report);
         }
-    }
 
-    fn as_ref(&self) -> &str {
-        &self.0
+        Ok(())
     }
-}
 
-pub fn load(transaction: &mut Transaction, tree: ValidatedParseTree) -> LoadResult<()> {
-    let mut loader = Loader::new(transaction);
+    /// Reports the row of the record, if named, and keeps it for other records to reference.
+    fn register(&mut self, qualified_table_name: &str, table_scope: &str, record: &Record, row: &Row, report: &mut TableReport) {
+        if let Some(name) = &record.name {
+            let key = format!("{}.{}", table_scope, name);
 
-    for node in tree.into_inner().nodes {
-        match node {
-            StructuralNode::Schema(schema) => {
-                let identity = schema.identity;
-                for table in schema.nodes {
-                    loader.load_table(Some(&identity), &table)?;
-                }
-            }
-            StructuralNode::Table(table) => {
-                loader.load_table(None, &table)?;
+            let row = record_row(row);
+            let referenced = ReferencedRow {
+                qualified_table_name: qualified_table_name.to_owned(),
+                row: row.clone(),
+                primary_key: None,
+            };
+
+            report.records.insert(name.clone(), row);
+
+            if self.refmap.insert(key, referenced).is_some() {
+                panic!("duplicate record in table {}: {}", table_scope, name);
             }
         }
     }
 
-    Ok(())
+    /// Selects the existing row of a record with the `skip_existing` option, by the first of
+    /// the keys that the record gives values for, if any row exists.
+    async fn select_skipped(
+        &mut self,
+        qualified_table_name: &str,
+        table_scope: &str,
+        record: &Record,
+        keys: &[Vec<String>],
+    ) -> LoadResult<Option<Row>> {
+        if !keys.iter().any(|key| gives_values(record, key)) {
+            return Ok(None);
+        }
+
+        self.load_columns(qualified_table_name).await?;
+        self.select_referenced_columns(table_scope, &[record]).await?;
+
+        // Every value is selected beforehand so that each can be compared to existing rows
+        let records = [record];
+        let statement = InsertStatement::build(self.transaction, self.fragments.as_mut())
+            .fixed_now(self.fixed_now.as_deref())
+            .records(&records)
+            .current_scope(table_scope)
+            .qualified_table_name(qualified_table_name)
+            .refmap(&self.refmap)
+            .column_types(&self.column_types[qualified_table_name])
+            .select_fragments()
+            .finish()
+            .await?;
+
+        // Columns left to their defaults have no values to compare
+        match keys.iter().find(|key| key.iter().all(|c| statement.bound_param(0, c).is_some())) {
+            Some(key) => self.select_by_key(qualified_table_name, &statement, key).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Looks up the names and types of all columns in the table, once per table, so that
+    /// returned rows can explicitly select each column as text and bound values can be
+    /// cast to their columns' types. Only columns that can be selected are returned, as
+    /// returning any others would fail, so any that are referenced are selected later.
+    async fn load_columns(&mut self, qualified_table_name: &str) -> LoadResult<()> {
+        if self.table_columns.contains_key(qualified_table_name) {
+            return Ok(());
+        }
+
+        // Types are formatted without modifiers, since explicitly casting to eg.
+        // `varchar(3)` would silently truncate values rather than rejecting them
+        let rows = self
+            .transaction
+            .query(
+                "
+                SELECT a.attname::text, format_type(a.atttypid, NULL), has_column_privilege(a.attrelid, a.attnum, 'SELECT')
+                FROM pg_attribute a
+                WHERE a.attrelid = $1::text::regclass AND a.attnum > 0 AND NOT a.attisdropped
+                ORDER BY a.attnum
+                ",
+                &[&qualified_table_name],
+            )
+            .await
+            .map_err(LoadError::new)?;
+
+        let columns = rows.iter().filter(|row| row.get(2)).map(|row| row.get(0)).collect();
+        let types = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+
+        self.table_columns.insert(qualified_table_name.to_owned(), columns);
+        self.column_types.insert(qualified_table_name.to_owned(), types);
+
+        Ok(())
+    }
+
+    async fn primary_key(&mut self, qualified_table_name: &str) -> LoadResult<Vec<String>> {
+        primary_key(self.transaction, qualified_table_name).await
+    }
+
+    /// Looks up the quoted column names of each of the table's unique keys, starting
+    /// with the primary key, that can identify rows for records skipped due to conflicts.
+    /// Partial and expression indexes are excluded, as their columns alone cannot.
+    async fn unique_keys(&mut self, qualified_table_name: &str) -> LoadResult<Vec<Vec<String>>> {
+        let rows = self
+            .transaction
+            .query(
+                "
+                SELECT array_agg(a.attname::text ORDER BY array_position(i.indkey::int2[], a.attnum))
+                FROM pg_index i
+                JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey)
+                WHERE i.indrelid = $1::text::regclass
+                    AND i.indisunique
+                    AND i.indpred IS NULL
+                    AND NOT 0 = ANY(i.indkey::int2[])
+                GROUP BY i.indexrelid, i.indisprimary
+                ORDER BY i.indisprimary DESC, i.indexrelid
+                ",
+                &[&qualified_table_name],
+            )
+            .await
+            .map_err(LoadError::new)?;
+
+        Ok(rows
+            .iter()
+            .map(|row| row.get::<_, Vec<String>>(0).iter().map(|c| quote(c)).collect())
+            .collect())
+    }
+
+    /// Inserts the records with a single statement, returning the inserted rows in
+    /// the same order as the records along with how many rows were actually inserted,
+    /// which excludes the existing row selected for a skipped record, and how many
+    /// existing rows upserts updated instead.
+    async fn insert(
+        &mut self,
+        qualified_table_name: &str,
+        table_scope: &str,
+        records: &[&Record],
+        on_conflict: &ConflictAction,
+    ) -> Result<(Vec<Row>, u64, u64), LoadError> {
+        self.load_columns(qualified_table_name).await?;
+        self.select_referenced_columns(table_scope, records).await?;
+
+        let returning = self.returning(qualified_table_name, table_scope, records).await?;
+        let statement = InsertStatement::build(self.transaction, self.fragments.as_mut())
+            .fixed_now(self.fixed_now.as_deref())
+            .records(records)
+            .on_conflict(on_conflict)
+            .current_scope(table_scope)
+            .qualified_table_name(qualified_table_name)
+            .refmap(&self.refmap)
+            .returning(&returning)
+            .column_types(&self.column_types[qualified_table_name])
+            .finish()
+            .await?;
+
+        log::debug!("{}", statement.as_ref());
+
+        let upsert = matches!(on_conflict, ConflictAction::Update(_));
+
+        // Nothing is returned for records that are never referenced when bulk loading,
+        // unless upserting, since upserts return whether each row was inserted
+        if returning.is_empty() && !upsert {
+            let inserted = self
+                .transaction
+                .execute(statement.as_ref(), &statement.params())
+                .await
+                .map_err(|e| LoadError::new(e).inserting(qualified_table_name, Some(statement.as_ref())))?;
+
+            return Ok((Vec::new(), inserted, 0));
+        }
+
+        let rows = self
+            .transaction
+            .query(statement.as_ref(), &statement.params())
+            .await
+            .map_err(|e| LoadError::new(e).inserting(qualified_table_name, Some(statement.as_ref())))?;
+
+        let updated = match upsert {
+            true => rows.iter().filter(|row| !row.get::<_, bool>(INSERTED_COLUMN)).count() as u64,
+            false => 0,
+        };
+        let inserted = rows.len() as u64 - updated;
+
+        // Only whether each row was inserted is returned for records that are never referenced
+        if returning.is_empty() {
+            return Ok((Vec::new(), inserted, updated));
+        }
+
+        if rows.len() == records.len() {
+            return Ok((rows, inserted, updated));
+        }
+
+        // Records that are skipped due to conflicts are never batched, so the
+        // only row missing can be the existing row for a single record
+        match on_conflict {
+            ConflictAction::Ignore { keys, .. } if records.len() == 1 => {
+                Ok((vec![self.select_existing(qualified_table_name, &statement, keys).await?], inserted, 0))
+            }
+            _ => unreachable!("expected {} returned rows, found {}", records.len(), rows.len()),
+        }
+    }
+
+    /// Updates the rows that the record identifies by the columns declared with
+    /// `update ... where (...)` with the rest of its values, returning the updated rows if
+    /// they are needed, along with how many there were, which must be exactly one for
+    /// named records so that they can be referenced.
+    async fn update(
+        &mut self,
+        qualified_table_name: &str,
+        table_scope: &str,
+        record: &Record,
+        columns: &[String],
+    ) -> Result<(Vec<Row>, u64), LoadError> {
+        self.load_columns(qualified_table_name).await?;
+        self.select_referenced_columns(table_scope, &[record]).await?;
+
+        let records = [record];
+        let returning = self.returning(qualified_table_name, table_scope, &records).await?;
+        let statement = InsertStatement::build(self.transaction, self.fragments.as_mut())
+            .fixed_now(self.fixed_now.as_deref())
+            .records(&records)
+            .update_where(columns)
+            .current_scope(table_scope)
+            .qualified_table_name(qualified_table_name)
+            .refmap(&self.refmap)
+            .returning(&returning)
+            .column_types(&self.column_types[qualified_table_name])
+            .finish()
+            .await?;
+
+        log::debug!("{}", statement.as_ref());
+
+        let failed = |e| LoadError::new(e).inserting(qualified_table_name, Some(statement.as_ref()));
+        let (rows, updated) = match returning.is_empty() {
+            true => {
+                let updated = self.transaction.execute(statement.as_ref(), &statement.params()).await.map_err(failed)?;
+                (Vec::new(), updated)
+            }
+            false => {
+                let rows = self.transaction.query(statement.as_ref(), &statement.params()).await.map_err(failed)?;
+                let updated = rows.len() as u64;
+                (rows, updated)
+            }
+        };
+
+        match (updated, record.name.as_deref()) {
+            (0, name) => Err(LoadError::unmatched_update(qualified_table_name, name, 0)),
+            (1, _) | (_, None) => Ok((rows, updated)),
+            (found, name) => Err(LoadError::unmatched_update(qualified_table_name, name, found)),
+        }
+    }
+
+    /// Deletes the rows that the record declared with `delete from ... where (...)`
+    /// identifies by its values, returning how many there were, which may be none.
+    async fn delete(&mut self, qualified_table_name: &str, table_scope: &str, record: &Record) -> Result<u64, LoadError> {
+        self.load_columns(qualified_table_name).await?;
+        self.select_referenced_columns(table_scope, &[record]).await?;
+
+        let records = [record];
+        let statement = InsertStatement::build(self.transaction, self.fragments.as_mut())
+            .fixed_now(self.fixed_now.as_deref())
+            .records(&records)
+            .delete()
+            .current_scope(table_scope)
+            .qualified_table_name(qualified_table_name)
+            .refmap(&self.refmap)
+            .column_types(&self.column_types[qualified_table_name])
+            .finish()
+            .await?;
+
+        log::debug!("{}", statement.as_ref());
+
+        self.transaction
+            .execute(statement.as_ref(), &statement.params())
+            .await
+            .map_err(|e| LoadError::new(e).inserting(qualified_table_name, Some(statement.as_ref())))
+    }
+
+    /// Returns the columns to return when inserting the records, which are only the columns
+    /// that other records reference, along with the primary key to select any that cannot
+    /// be returned later, or none if the records are never referenced. Every column is
+    /// still returned for named records unless bulk loading, since they are reported, and
+    /// if which records are referenced is not known.
+    async fn returning(&mut self, qualified_table_name: &str, table_scope: &str, records: &[&Record]) -> LoadResult<Vec<String>> {
+        let referenced = match &self.referenced {
+            Some(referenced) if self.bulk || records.iter().all(|r| r.name.is_none()) => referenced,
+            _ => return Ok(self.table_columns[qualified_table_name].clone()),
+        };
+        let referenced: Vec<&HashSet<String>> = records
+            .iter()
+            .filter_map(|r| r.name.as_ref())
+            .filter_map(|name| referenced.get(&format!("{}.{}", table_scope, name)))
+            .collect();
+
+        if referenced.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut columns: HashSet<String> = referenced.into_iter().flatten().cloned().collect();
+
+        match self.primary_key(qualified_table_name).await {
+            // The primary key columns are quoted but returned rows are keyed by name
+            Ok(primary_key) => columns.extend(primary_key.iter().map(|c| c.trim_matches('"').to_owned())),
+            Err(LoadError { kind: LoadErrorKind::NoPrimaryKey { .. }, .. }) => {}
+            Err(e) => return Err(e),
+        }
+
+        Ok(self.table_columns[qualified_table_name]
+            .iter()
+            .filter(|c| columns.contains(*c))
+            .cloned()
+            .collect())
+    }
+
+    /// Loads the records with `COPY`, which is much faster than inserting them but
+    /// does not return their rows. Values are still evaluated as they would be for
+    /// an insert statement, but are then written in the text format instead of bound.
+    /// Returns the number of rows copied.
+    async fn copy(&mut self, qualified_table_name: &str, table_scope: &str, records: &[&Record]) -> LoadResult<u64> {
+        self.select_referenced_columns(table_scope, records).await?;
+
+        let statement = InsertStatement::build(self.transaction, self.fragments.as_mut())
+            .fixed_now(self.fixed_now.as_deref())
+            .records(records)
+            .current_scope(table_scope)
+            .qualified_table_name(qualified_table_name)
+            .refmap(&self.refmap)
+            .select_fragments()
+            .finish()
+            .await?;
+
+        let columns: Vec<&str> = statement.bound_rows[0]
+            .iter()
+            .map(|(column, _)| column.as_str())
+            .collect();
+
+        let query = format!("COPY {} ({}) FROM STDIN", qualified_table_name, columns.join(", "));
+        log::debug!("{}", query);
+
+        let mut rows = String::new();
+
+        for row in &statement.bound_rows {
+            let line = row
+                .iter()
+                .map(|(_, value)| match value {
+                    BoundValue::Param(param) => copy_text(statement.params[param.index].0.as_deref()),
+                    BoundValue::Fragment(_) => unreachable!("fragments should have been selected to be copied"),
+                    BoundValue::Null => copy_text(None),
+                    BoundValue::Default => unreachable!("defaults should have been inserted rather than copied"),
+                })
+                .collect::<Vec<_>>()
+                .join("\t");
+
+            rows.push_str(&line);
+            rows.push('\n');
+        }
+
+        self.transaction
+            .copy_in(&query, rows)
+            .await
+            .map_err(|e| e.inserting(qualified_table_name, Some(&query)))
+    }
+
+    /// Selects any columns that the records reference but that were not returned
+    /// when the referenced records were loaded, eg. due to column privileges, by
+    /// the primary key of the referenced record's row.
+    async fn select_referenced_columns(&mut self, table_scope: &str, records: &[&Record]) -> LoadResult<()> {
+        let mut references = Vec::new();
+
+        for attribute in records.iter().flat_map(|r| &r.nodes) {
+            for refval in attribute.value.references() {
+                if let Some(key) = record_key(table_scope, refval) {
+                    references.push((key, referenced_column(attribute, refval)));
+                }
+            }
+        }
+
+        for (key, column) in references {
+            let column = match column {
+                Some(column) => column,
+                None => self.select_primary_key(&key).await?,
+            };
+            let column = column.as_str();
+            let referenced = match self.refmap.get(&key) {
+                Some(referenced) => referenced,
+                None => return Err(LoadError::unresolved_reference(&key, column, self.unloaded_reason(&key))),
+            };
+
+            if referenced.row.contains_key(column) {
+                continue;
+            }
+
+            let qualified_table_name = referenced.qualified_table_name.clone();
+
+            if !self.column_types[&qualified_table_name].contains_key(column) {
+                return Err(LoadError::unresolved_reference(&key, column, "the column does not exist"));
+            }
+
+            let primary_key = self.primary_key(&qualified_table_name).await?;
+            let referenced = &self.refmap[&key];
+            let mut params = Vec::new();
+            let mut conditions = Vec::new();
+
+            for pk_column in &primary_key {
+                // The primary key columns are quoted but returned rows are keyed by name
+                let value = referenced.row.get(pk_column.trim_matches('"')).ok_or_else(|| {
+                    LoadError::unresolved_reference(&key, column, "its primary key was not returned")
+                })?;
+
+                params.push(TextParam(value.clone()));
+                conditions.push(format!("{} = ${}", pk_column, params.len()));
+            }
+
+            let query = format!(
+                "SELECT {} FROM {} WHERE {}",
+                returning_list(&[column.to_owned()]),
+                qualified_table_name,
+                conditions.join(" AND "),
+            );
+            let params: Vec<_> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+            log::debug!("{}", query);
+
+            let row = self
+                .transaction
+                .query_opt(&query, &params)
+                .await
+                .map_err(LoadError::new)?
+                .ok_or_else(|| LoadError::unresolved_reference(&key, column, "its row no longer exists"))?;
+
+            let value = row.get(0);
+            self.refmap.get_mut(&key).unwrap().row.insert(column.to_owned(), value);
+        }
+
+        Ok(())
+    }
+
+    /// Why the record has no row to reference, given that it was never loaded.
+    fn unloaded_reason(&self, key: &str) -> &'static str {
+        match self.skipped_records.contains(key) {
+            true => "its table was skipped",
+            false => "it failed to load",
+        }
+    }
+
+    /// Looks up the name of the primary key column of the referenced record's table,
+    /// for references to the record itself, which requires that the table has a
+    /// primary key of a single column.
+    async fn select_primary_key(&mut self, key: &str) -> LoadResult<String> {
+        let referenced = match self.refmap.get(key) {
+            Some(referenced) => referenced,
+            None => return Err(LoadError::unresolved_primary_key(key, self.unloaded_reason(key))),
+        };
+
+        if let Some(primary_key) = &referenced.primary_key {
+            return Ok(primary_key.clone());
+        }
+
+        let qualified_table_name = referenced.qualified_table_name.clone();
+        let primary_key = match self.primary_key(&qualified_table_name).await {
+            Ok(primary_key) => primary_key,
+            Err(LoadError { kind: LoadErrorKind::NoPrimaryKey { .. }, .. }) => {
+                return Err(LoadError::unresolved_primary_key(key, "its table has no primary key"));
+            }
+            Err(e) => return Err(e),
+        };
+
+        if primary_key.len() > 1 {
+            return Err(LoadError::unresolved_primary_key(key, "its table's primary key has more than one column"));
+        }
+
+        // The primary key columns are quoted but returned rows are keyed by name
+        let column = primary_key[0].trim_matches('"').to_owned();
+        self.refmap.get_mut(key).unwrap().primary_key = Some(column.clone());
+
+        Ok(column)
+    }
+
+    /// Selects the existing row that a skipped record conflicted with, using the values
+    /// bound for the columns of the first unique key the record provides all of, so that
+    /// the record can still be referenced.
+    async fn select_existing(
+        &mut self,
+        qualified_table_name: &str,
+        statement: &InsertStatement,
+        keys: &[Vec<String>],
+    ) -> Result<Row, LoadError> {
+        let unresolved = || LoadError::unresolved_conflict(qualified_table_name);
+
+        let key = keys
+            .iter()
+            .find(|key| key.iter().all(|c| statement.bound_param(0, c).is_some()))
+            .ok_or_else(unresolved)?;
+
+        self.select_by_key(qualified_table_name, statement, key)
+            .await?
+            .ok_or_else(unresolved)
+    }
+
+    /// Selects a row with the same values for the key's columns as the statement's first row,
+    /// if any, where every column of the key must be bound to a parameter.
+    async fn select_by_key(
+        &mut self,
+        qualified_table_name: &str,
+        statement: &InsertStatement,
+        key: &[String],
+    ) -> Result<Option<Row>, LoadError> {
+        let mut params = Vec::new();
+        let mut conditions = Vec::new();
+
+        for column in key {
+            let param = statement.bound_param(0, column).unwrap();
+
+            params.push(statement.params[param.index].clone());
+            conditions.push(format!(
+                "{} = {}",
+                column,
+                placeholder(
+                    BoundParam {
+                        index: params.len() - 1,
+                        cast: param.cast,
+                    },
+                    None,
+                ),
+            ));
+        }
+
+        // Keys given with the `skip_existing` option need not be unique
+        let query = format!(
+            "SELECT {} FROM {} WHERE {} LIMIT 1",
+            returning_list(&self.table_columns[qualified_table_name]),
+            qualified_table_name,
+            conditions.join(" AND "),
+        );
+
+        let params: Vec<_> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+        log::debug!("{}", query);
+
+        self.transaction
+            .query_opt(&query, &params)
+            .await
+            .map_err(LoadError::new)
+    }
+}
+
+struct FragmentRunner<'a, C: Connection> {
+    transaction: &'a mut C,
+    /// The values of the SQL fragments already selected in the transaction, by their SQL,
+    /// if caching them
+    cache: Option<&'a mut HashMap<String, Option<String>>>,
+    /// The times already selected for each offset from the time of the transaction
+    times: HashMap<Option<TimeOffset>, Option<String>>,
+    /// The time `now()` is instead, if fixed with the `fixed_now` option
+    fixed_now: Option<String>,
+}
+
+impl<C: Connection> FragmentRunner<'_, C> {
+    async fn select(&mut self, fragment: &str) -> Result<Option<String>, LoadError> {
+        let query = format!("SELECT {}", fragment);
+        log::debug!("{}", query);
+
+        let mut rows: Vec<_> = self
+            .transaction
+            .simple_query(&query)
+            .await
+            .map_err(LoadError::new)?
+            .into_iter()
+            .filter_map(|message| match message {
+                SimpleQueryMessage::Row(row) => Some(row),
+                _ => None,
+            })
+            .collect();
+
+        if rows.len() != 1 {
+            return Err(LoadError::fragment_rows(fragment, rows.len()));
+        }
+
+        let row = rows.remove(0);
+
+        if row.len() != 1 {
+            return Err(LoadError::fragment_columns(fragment, row.len()));
+        }
+
+        // Selected values are stringified and passed back to the database as text
+        // parameters, which is why fragments are otherwise selected in CTEs of the
+        // insert statement itself
+        Ok(row.get(0).map(str::to_owned))
+    }
+
+    /// Selects the SQL fragment unless it has already been selected in the transaction,
+    /// in which case its earlier value is reused, if caching fragments.
+    async fn select_cached(&mut self, fragment: &str) -> Result<Option<String>, LoadError> {
+        if let Some(value) = self.cache.as_ref().and_then(|cache| cache.get(fragment)) {
+            return Ok(value.clone());
+        }
+
+        let value = self.select(fragment).await?;
+
+        if let Some(cache) = &mut self.cache {
+            cache.insert(fragment.to_owned(), value.clone());
+        }
+
+        Ok(value)
+    }
+
+    /// Selects a SQL fragment with references embedded in it, whose values are bound
+    /// as parameters in place of the references rather than written into the SQL.
+    async fn select_with_params(&mut self, fragment: &str, params: &[TextParam]) -> Result<Option<String>, LoadError> {
+        // Selecting from the fragment lets it be cast to text whatever its type,
+        // like the values selected with the simple query protocol, and its columns
+        // are counted since any others would otherwise be ignored
+        let query = format!(
+            "SELECT fragment::text, (SELECT count(*) FROM json_object_keys(row_to_json(selected))) \
+             FROM (SELECT {}) AS selected(fragment)",
+            fragment,
+        );
+        let params: Vec<_> = params.iter().map(|p| p as &(dyn ToSql + Sync)).collect();
+        log::debug!("{}", query);
+
+        let rows = self
+            .transaction
+            .query(&query, &params)
+            .await
+            .map_err(LoadError::new)?;
+
+        if rows.len() != 1 {
+            return Err(LoadError::fragment_rows(fragment, rows.len()));
+        }
+
+        let columns: i64 = rows[0].get(1);
+        if columns != 1 {
+            return Err(LoadError::fragment_columns(fragment, columns as usize));
+        }
+
+        Ok(rows[0].get(0))
+    }
+
+    /// Selects the time of the transaction with the offset, if any, unless already
+    /// selected, since it is the same throughout the transaction.
+    async fn select_time(&mut self, offset: &Option<TimeOffset>) -> Result<(), LoadError> {
+        if !self.times.contains_key(offset) {
+            let fragment = now_sql(self.fixed_now.as_deref(), offset.as_ref());
+            let time = self.select(&fragment).await?;
+            self.times.insert(offset.clone(), time);
+        }
+
+        Ok(())
+    }
+}
+
+struct InsertStatementBuilder<
+    'record,
+    'on_conflict,
+    'current_scope,
+    'fragment,
+    'qualified_table_name,
+    'refmap,
+    'returning,
+    'column_types,
+    C: Connection,
+> {
+    attribute_params: HashMap<&'record str, BoundValue>,
+    /// The types of the table's columns, which values are cast to when known
+    column_types: Option<&'column_types HashMap<String, String>>,
+    current_scope: &'current_scope str,
+    fragment_runner: FragmentRunner<'fragment, C>,
+    /// The SQL fragments selected in CTEs of the statement, in order
+    fragments: Vec<String>,
+    on_conflict: &'on_conflict ConflictAction,
+    params: Vec<TextParam>,
+    qualified_table_name: &'qualified_table_name str,
+    records: &'record [&'record Record],
+    refmap: Option<&'refmap RefMap>,
+    returning: &'returning [String],
+    /// Whether every SQL fragment is selected before the statement rather than in it
+    select_fragments: bool,
+    /// The columns of the current record whose SQL fragments are selected before the statement
+    selected_columns: HashSet<&'record str>,
+    /// The columns that identify the rows to update, if the record updates rows rather
+    /// than being inserted
+    update_where: Option<&'record [String]>,
+    /// Whether the record deletes the rows that its columns identify rather than being
+    /// inserted
+    delete: bool,
+}
+
+/// The position of a bound parameter in the statement along with the type
+/// it should be cast to, if any.
+#[derive(Clone, Copy)]
+struct BoundParam {
+    index: usize,
+    cast: Option<&'static str>,
+}
+
+/// The value given for a column in the statement, which is either a bound parameter,
+/// the result of a SQL fragment selected in one of the statement's CTEs, or the
+/// column's default.
+#[derive(Clone, Copy)]
+enum BoundValue {
+    Default,
+    Param(BoundParam),
+    /// The index of the fragment's CTE
+    Fragment(usize),
+    /// A null value that a column is compared with in a `where` clause, which is not
+    /// bound, as the column is compared with `IS NULL` instead
+    Null,
+}
+
+impl<'a, 'oc, 'c, 'f, 'q, 'r, 'rt, 'ct, C: Connection> InsertStatementBuilder<'a, 'oc, 'c, 'f, 'q, 'r, 'rt, 'ct, C> {
+    fn column_types(mut self, column_types: &'ct HashMap<String, String>) -> Self {
+        self.column_types = Some(column_types);
+        self
+    }
+
+    fn current_scope(mut self, current_scope: &'c str) -> Self {
+        self.current_scope = current_scope;
+        self
+    }
+
+    fn fixed_now(mut self, fixed_now: Option<&str>) -> Self {
+        self.fragment_runner.fixed_now = fixed_now.map(str::to_owned);
+        self
+    }
+
+    fn on_conflict(mut self, on_conflict: &'oc ConflictAction) -> Self {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    fn qualified_table_name(mut self, qualified_table_name: &'q str) -> Self {
+        self.qualified_table_name = qualified_table_name;
+        self
+    }
+
+    /// Sets the records to insert, which must all declare the same columns
+    fn records(mut self, records: &'a [&'a Record]) -> Self {
+        self.records = records;
+        self
+    }
+
+    fn refmap(mut self, refmap: &'r RefMap) -> Self {
+        self.refmap = Some(refmap);
+        self
+    }
+
+    fn returning(mut self, returning: &'rt [String]) -> Self {
+        self.returning = returning;
+        self
+    }
+
+    /// Updates the rows that the record identifies by the columns, as records declared with
+    /// `update ... where (...)` do, rather than inserting the records
+    fn update_where(mut self, columns: &'a [String]) -> Self {
+        self.update_where = Some(columns);
+        self
+    }
+
+    /// Deletes the rows that the record identifies by all of its columns, as records
+    /// declared with `delete from ... where (...)` do, rather than inserting the records
+    fn delete(mut self) -> Self {
+        self.delete = true;
+        self
+    }
+
+    /// Selects every SQL fragment before the statement rather than in it, for when
+    /// every value has to be known, eg. to copy them rather than insert them
+    fn select_fragments(mut self) -> Self {
+        self.select_fragments = true;
+        self
+    }
+
+    async fn finish(mut self) -> Result<InsertStatement, LoadError> {
+        let columns: Vec<String> = match self.records.first() {
+            Some(record) => record.nodes.iter().map(|a| quote(&a.name)).collect(),
+            None => Vec::new(),
+        };
+        let mut rows = Vec::new();
+        let mut bound_rows = Vec::new();
+        let mut value_lists = Vec::new();
+        let records = self.records;
+
+        for record in records {
+            let mut values = Vec::new();
+            let mut bound_columns = Vec::new();
+
+            // Column references only refer to columns within the same record
+            self.attribute_params.clear();
+            self.selected_columns = match self.select_fragments {
+                true => record.nodes.iter().map(|a| a.name.as_str()).collect(),
+                false => selected_columns(record, self.on_conflict),
+            };
+
+            for attribute in &record.nodes {
+                let mut param = self.bind_value(attribute).await.map_err(|e| e.in_attribute(attribute))?;
+
+                if self.delete || self.update_where.is_some_and(|c| c.contains(&attribute.name)) {
+                    param = self.unbind_null(param);
+                }
+
+                let column_type = self.column_types.and_then(|types| types.get(&attribute.name));
+
+                values.push(bound_sql(param, column_type.map(String::as_str)));
+                bound_columns.push((quote(&attribute.name), param));
+
+                // Only add this after to prevent cyclic references
+                self.attribute_params.insert(&attribute.name, param);
+            }
+
+            rows.push(format!("({})", values.join(", ")));
+            bound_rows.push(bound_columns);
+            value_lists.push(values);
+        }
+
+        let returning = returning_clause(self.returning, self.on_conflict);
+        let on_conflict = conflict_clause(self.on_conflict, &columns);
+        let with = match self.fragments.is_empty() {
+            true => String::new(),
+            false => format!(
+                "WITH {}",
+                self.fragments
+                    .iter()
+                    .enumerate()
+                    .map(|(i, fragment)| format!("fragment{}(fragment) AS (SELECT {})", i + 1, fragment))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+        };
+
+        let statement = if let ([values], true) = (&value_lists[..], self.delete) {
+            let predicates: Vec<_> = bound_rows[0]
+                .iter()
+                .zip(values)
+                .map(|((column, bound), value)| predicate(column, *bound, value))
+                .collect();
+
+            format!(
+                r#"
+            {}
+            DELETE FROM {} WHERE {}
+        "#,
+                with,
+                self.qualified_table_name,
+                predicates.join(" AND "),
+            )
+        } else if let (Some(conditions), [record], [values]) = (self.update_where, records, &value_lists[..]) {
+            let mut assignments = Vec::new();
+            let mut predicates = Vec::new();
+
+            for ((attribute, (column, bound)), value) in record.nodes.iter().zip(&bound_rows[0]).zip(values) {
+                match conditions.contains(&attribute.name) {
+                    true => predicates.push(predicate(column, *bound, value)),
+                    false => assignments.push(format!("{} = {}", column, value)),
+                }
+            }
+
+            format!(
+                r#"
+            {}
+            UPDATE {} SET {} WHERE {}
+            {}
+        "#,
+                with,
+                self.qualified_table_name,
+                assignments.join(", "),
+                predicates.join(" AND "),
+                returning,
+            )
+        } else if columns.is_empty() {
+            format!(
+                r#"
+            INSERT INTO {} DEFAULT VALUES {}
+            {}
+        "#,
+                self.qualified_table_name, on_conflict, returning,
+            )
+        } else {
+            format!(
+                r#"
+            {}
+            INSERT INTO {} ({}) VALUES {} {}
+            {}
+        "#,
+                with,
+                self.qualified_table_name,
+                columns.join(", "),
+                rows.join(", "),
+                on_conflict,
+                returning,
+            )
+        };
+
+        Ok(InsertStatement {
+            sql: statement,
+            params: self.params,
+            bound_rows,
+        })
+    }
+
+    async fn bind_value(&mut self, attribute: &Attribute) -> Result<BoundValue, LoadError> {
+        // Times are selected beforehand so that they can be evaluated like any other value
+        for value in attribute.value.values() {
+            if let Value::Now(offset) = value {
+                self.fragment_runner.select_time(offset).await?;
+            }
+        }
+
+        // Fragments that are not cached are selected in CTEs of the statement unless their
+        // values are needed
+        let selected = self.selected_columns.contains(attribute.name.as_str());
+
+        Ok(match &attribute.value {
+            Value::Bool(b) => self.bind(Some(b.to_string()), Some("bool")),
+            Value::Default => BoundValue::Default,
+            Value::Expression(expression) => {
+                let cast = match expression.operator {
+                    Operator::Concat => None,
+                    _ => Some("numeric"),
+                };
+                let value = self.evaluate(attribute, &attribute.value)?;
+                self.bind(value, cast)
+            }
+            Value::Index => unreachable!("index() should have been expanded by the analyzer"),
+            Value::Variable(_) => unreachable!("variables should have been substituted by the analyzer"),
+            Value::Json(json) => self.bind(Some(json.clone()), Some("jsonb")),
+            Value::Now(_) => {
+                let value = self.evaluate(attribute, &attribute.value)?;
+                self.bind(value, Some("timestamptz"))
+            }
+            Value::Number(n) => self.bind(Some(n.clone()), Some("numeric")),
+            Value::Reference(Reference::ColumnLevel(colref)) => {
+                // Column-reference could refer to a literal value, another
+                // column reference, or a reference to a different record,
+                // all of which have already been bound, so the value is
+                // re-bound rather than re-evaluated
+                match *self.attribute_params.get(&colref.column.as_ref()).expect("missing column") {
+                    BoundValue::Param(param) => {
+                        let value = self.params[param.index].0.clone();
+                        self.bind(value, param.cast)
+                    }
+                    // The same CTE is selected from again, which is only evaluated once, and
+                    // unbound nulls are still null
+                    value => value,
+                }
+            }
+            Value::SqlFragment(s) if self.fragment_runner.cache.is_some() => {
+                let value = self.fragment_runner.select_cached(s).await?;
+                self.bind(value, None)
+            }
+            Value::SqlFragment(s) | Value::VolatileSqlFragment(s) if selected => {
+                let value = self.fragment_runner.select(s).await?;
+                self.bind(value, None)
+            }
+            Value::SqlFragment(s) | Value::VolatileSqlFragment(s) => self.add_fragment(s.clone()),
+            Value::InterpolatedSqlFragment(segments) => {
+                let mut fragment = String::new();
+                let mut params = Vec::new();
+
+                // Embedded values are bound as parameters of the statement itself when the
+                // fragment is selected in a CTE
+                for segment in segments {
+                    match segment {
+                        TextSegment::Literal(sql) => fragment.push_str(sql),
+                        TextSegment::Value(value) if selected => {
+                            params.push(TextParam(self.evaluate(attribute, value)?));
+                            fragment.push_str(&format!("${}", params.len()));
+                        }
+                        TextSegment::Value(value) => {
+                            let value = self.evaluate(attribute, value)?;
+                            let param = self.param(value, None);
+                            fragment.push_str(&placeholder(param, None));
+                        }
+                    }
+                }
+
+                match selected {
+                    true => {
+                        let value = self.fragment_runner.select_with_params(&fragment, &params).await?;
+                        self.bind(value, None)
+                    }
+                    false => self.add_fragment(fragment),
+                }
+            }
+            value => {
+                let value = self.evaluate(attribute, value)?;
+                self.bind(value, None)
+            }
+        })
+    }
+
+    /// Returns the text of a value that is known without querying the database,
+    /// which are the only values allowed in expressions.
+    fn evaluate(&self, attribute: &Attribute, value: &Value) -> Result<Option<String>, LoadError> {
+        Ok(match value {
+            Value::EnvVar(name) => Some(env::var(name)?),
+            Value::Expression(expression) => {
+                let left = self.evaluate(attribute, &expression.left)?;
+                let right = self.evaluate(attribute, &expression.right)?;
+                let evaluated = format!(
+                    "{} {} {}",
+                    left.as_deref().unwrap_or("NULL"),
+                    expression.operator,
+                    right.as_deref().unwrap_or("NULL"),
+                );
+
+                expression::evaluate(left, expression.operator, right)
+                    .map_err(|reason| LoadError::invalid_expression(&attribute.name, &evaluated, reason))?
+            }
+            Value::File(path) => Some(bytea_text(&fs::read(path).map_err(|e| LoadError::file(path, e))?)),
+            Value::Hex(digits) => Some(format!("\\x{}", digits)),
+            Value::Json(json) => Some(json.clone()),
+            // Embedded values that are null are left out of the text
+            Value::InterpolatedText(segments) => {
+                let mut text = String::new();
+
+                for segment in segments {
+                    match segment {
+                        TextSegment::Literal(literal) => text.push_str(&env::interpolate(literal)?),
+                        TextSegment::Value(value) => text.push_str(&self.evaluate(attribute, value)?.unwrap_or_default()),
+                    }
+                }
+
+                Some(text)
+            }
+            Value::Now(offset) => self.fragment_runner.times.get(offset).expect("time not selected").clone(),
+            Value::Null => None,
+            Value::Number(n) => Some(n.clone()),
+            Value::RandomUuid => Some(Uuid::new_v4().to_string()),
+            // Only within expressions, as the value already bound for the column is re-bound otherwise
+            Value::Reference(Reference::ColumnLevel(colref)) => {
+                match self.attribute_params.get(colref.column.as_str()).expect("missing column") {
+                    BoundValue::Param(param) => self.params[param.index].0.clone(),
+                    BoundValue::Null => None,
+                    BoundValue::Fragment(_) => unreachable!("fragments should have been selected to be evaluated"),
+                    BoundValue::Default => unreachable!("defaults should not have been referenced"),
+                }
+            }
+            Value::Reference(refval) => self.follow_ref(attribute, refval)?,
+            Value::Text(t) => Some(env::interpolate(&unquote_text(t))?),
+            Value::Uuid5 { namespace, name } => Some(Uuid::new_v5(namespace, name.as_bytes()).to_string()),
+            Value::Variable(_) => unreachable!("variables should have been substituted by the analyzer"),
+            Value::Bool(_)
+            | Value::Default
+            | Value::Index
+            | Value::SqlFragment(_)
+            | Value::InterpolatedSqlFragment(_)
+            | Value::VolatileSqlFragment(_) => {
+                unreachable!("{:?} should have been bound rather than evaluated", value)
+            }
+        })
+    }
+
+    /// Unbinds the value if it is a null parameter, which was the last one bound, so that
+    /// the column is compared with `IS NULL` rather than the parameter.
+    fn unbind_null(&mut self, value: BoundValue) -> BoundValue {
+        match value {
+            BoundValue::Param(param) if param.index + 1 == self.params.len() && self.params[param.index].0.is_none() => {
+                self.params.pop();
+                BoundValue::Null
+            }
+            value => value,
+        }
+    }
+
+    fn bind(&mut self, value: Option<String>, cast: Option<&'static str>) -> BoundValue {
+        BoundValue::Param(self.param(value, cast))
+    }
+
+    fn param(&mut self, value: Option<String>, cast: Option<&'static str>) -> BoundParam {
+        self.params.push(TextParam(value));
+
+        BoundParam {
+            index: self.params.len() - 1,
+            cast,
+        }
+    }
+
+    fn add_fragment(&mut self, fragment: String) -> BoundValue {
+        self.fragments.push(fragment);
+        BoundValue::Fragment(self.fragments.len() - 1)
+    }
+
+    fn follow_ref(&self, attribute: &Attribute, refval: &Reference) -> Result<Option<String>, LoadError> {
+        let key = record_key(self.current_scope, refval).unwrap();
+
+        let referenced = self.refmap.expect("no refmap set").get(&key).unwrap();
+        let col = referenced_column(attribute, refval)
+            .or(referenced.primary_key.clone())
+            .expect("primary key not selected");
+        let val = referenced.row.get(&col).cloned();
+
+        // Any columns that were not returned have already been selected by the loader
+        Ok(val.unwrap_or_else(|| panic!("no column '{}' in record {}", col, key)))
+    }
+}
+
+/// Returns the name of the column being referenced, which is the attribute's own
+/// name for implicit column references, or `None` for references to the primary key,
+/// which has to be looked up
+fn referenced_column(attribute: &Attribute, refval: &Reference) -> Option<String> {
+    use ReferencedColumn::*;
+
+    let col = match refval {
+        Reference::SchemaLevel(s) => &s.column,
+        Reference::TableLevel(t) => &t.column,
+        Reference::RecordLevel(r) => &r.column,
+        // Column-references are handled differently, as there is no record in
+        // the map to look up
+        Reference::ColumnLevel(_) => unreachable!(),
+    };
+
+    match col {
+        Explicit(c) => Some(c.clone()),
+        Implicit => Some(attribute.name.clone()),
+        PrimaryKey => None,
+    }
+}
+
+/// Returns the key of the record being referenced in the ref map, if any. The analyzer
+/// qualifies references with the namespace of the record being referenced, and record-level
+/// references are always to records in the same namespace as the current scope.
+fn record_key(current_scope: &str, refval: &Reference) -> Option<String> {
+    match refval {
+        Reference::SchemaLevel(s) => {
+            Some(namespaced(s.namespace.as_deref(), format!("{}.{}.{}", s.schema, s.table, s.record)))
+        }
+        Reference::TableLevel(t) => Some(namespaced(t.namespace.as_deref(), format!("{}.{}", t.table, t.record))),
+        Reference::RecordLevel(r) => Some(format!("{}.{}", current_scope, r.record)),
+        Reference::ColumnLevel(_) => None,
+    }
+}
+
+/// Whether the record can be inserted in the same statement as the batch of records
+/// before it, which requires that they declare the same columns and that the record
+/// does not reference any of them, since their rows are only returned afterwards.
+/// Records with SQL fragments that are not cached, ie. volatile fragments, those with
+/// references, and any without the `cache_fragments` option, can only start a batch, so
+/// that they are still evaluated after all previous records have been inserted, while
+/// cached fragments have the same value whenever they are selected.
+///
+/// Conflicting records are never batched, since skipped records leave no returned
+/// row to match up and updates would fail for rows conflicting within the batch.
+fn can_batch(
+    batch: &[&Record],
+    record: &Record,
+    table_scope: &str,
+    on_conflict: &ConflictAction,
+    cache_fragments: bool,
+) -> bool {
+    if !matches!(on_conflict, ConflictAction::Error) || record.nodes.is_empty() {
+        return false;
+    }
+
+    // Records that update or delete rows do so with statements of their own
+    if record.update_where.is_some() || batch[0].update_where.is_some() || record.delete || batch[0].delete {
+        return false;
+    }
+
+    let same_columns = batch[0].nodes.len() == record.nodes.len()
+        && batch[0].nodes.iter().zip(&record.nodes).all(|(a, b)| a.name == b.name);
+
+    if !same_columns || (batch.len() + 1) * record.nodes.len() > MAX_PARAMS {
+        return false;
+    }
+
+    let references_batch = record.nodes.iter().any(|attribute| {
+        match attribute.value {
+            Value::VolatileSqlFragment(_) | Value::InterpolatedSqlFragment(_) => return true,
+            Value::SqlFragment(_) if !cache_fragments => return true,
+            _ => {}
+        }
+
+        attribute.value.references().filter_map(|refval| record_key(table_scope, refval)).any(|key| {
+            batch
+                .iter()
+                .filter_map(|r| r.name.as_ref())
+                .any(|name| key == format!("{}.{}", table_scope, name))
+        })
+    });
+
+    !references_batch
+}
+
+/// Returns the quoted columns of each key that the table's records are skipped by with the
+/// `skip_existing` option, starting with those given for the table itself.
+fn skip_keys(skip_existing: &[SkipExisting], schema: Option<&StructuralIdentity>, table: &Table) -> Vec<Vec<String>> {
+    let name = &table.identity.name;
+    let qualified_name = schema.map(|s| format!("{}.{}", s.name, name));
+    let (own, shared): (Vec<_>, Vec<_>) = skip_existing
+        .iter()
+        .filter(|key| match &key.table {
+            Some(table) => table == name || Some(table) == qualified_name.as_ref(),
+            None => true,
+        })
+        .partition(|key| key.table.is_some());
+
+    own.into_iter()
+        .chain(shared)
+        .map(|key| key.columns.iter().map(|c| quote(c)).collect())
+        .collect()
+}
+
+/// Whether the record gives values for every quoted column of the key.
+fn gives_values(record: &Record, key: &[String]) -> bool {
+    key.iter().all(|column| record.nodes.iter().any(|a| quote(&a.name) == *column))
+}
+
+/// Renders the condition that a column of the rows that a record updates or deletes
+/// has the value, comparing it with `IS NULL` if the value is null, including values
+/// only known to be null once evaluated, eg. references to null columns, so that the
+/// condition can still use the column's indexes.
+fn predicate(column: &str, bound: BoundValue, value: &str) -> String {
+    match bound {
+        BoundValue::Null => format!("{} IS NULL", column),
+        _ => format!("{} = {}", column, value),
+    }
+}
+
+/// Renders the `ON CONFLICT` clause, if any, for an insert of the already-quoted columns
+fn conflict_clause(on_conflict: &ConflictAction, columns: &[String]) -> String {
+    match on_conflict {
+        ConflictAction::Error => String::new(),
+        ConflictAction::Ignore { target: None, .. } => "ON CONFLICT DO NOTHING".to_owned(),
+        ConflictAction::Ignore { target: Some(target), .. } => {
+            format!("ON CONFLICT ({}) DO NOTHING", target.join(", "))
+        }
+        ConflictAction::Update(target) => upsert_clause(target, columns),
+    }
+}
+
+/// Renders an `ON CONFLICT` clause from already-quoted column names that updates
+/// every inserted column outside of the conflict target with its new value
+fn upsert_clause(conflict_target: &[String], columns: &[String]) -> String {
+    let mut updates: Vec<&String> = columns
+        .iter()
+        .filter(|c| !conflict_target.contains(c))
+        .collect();
+
+    // Something has to be updated for the conflicting row to be returned,
+    // so fall back to a no-op update of the conflict target itself
+    if updates.is_empty() {
+        updates = conflict_target.iter().collect();
+    }
+
+    format!(
+        "ON CONFLICT ({}) DO UPDATE SET {}",
+        conflict_target.join(", "),
+        updates
+            .into_iter()
+            .map(|c| format!("{0} = EXCLUDED.{0}", c))
+            .collect::<Vec<_>>()
+            .join(", "),
+    )
+}
+
+/// Renders a value for the `COPY` text format, escaping characters that would
+/// otherwise be interpreted as delimiters
+fn copy_text(value: Option<&str>) -> String {
+    match value {
+        Some(value) => value
+            .replace('\\', "\\\\")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+            .replace('\t', "\\t"),
+        None => "\\N".to_owned(),
+    }
+}
+
+/// Renders the list of all columns to return from a statement, as text
+/// Renders the `RETURNING` clause, if any, for the columns, along with whether each row
+/// was inserted for upserts, as only the rows they insert rather than update have no `xmax`.
+fn returning_clause(columns: &[String], on_conflict: &ConflictAction) -> String {
+    let mut returned = match columns.is_empty() {
+        true => Vec::new(),
+        false => vec![returning_list(columns)],
+    };
+
+    if matches!(on_conflict, ConflictAction::Update(_)) {
+        returned.push(format!(r#"xmax = 0 AS "{}""#, INSERTED_COLUMN));
+    }
+
+    match returned.is_empty() {
+        true => String::new(),
+        false => format!("RETURNING {}", returned.join(", ")),
+    }
+}
+
+fn returning_list(columns: &[String]) -> String {
+    columns
+        .iter()
+        .map(|c| format!(r#""{0}"::text AS "{0}""#, c.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the columns of the record whose SQL fragments have to be selected before the
+/// statement rather than in it, since their values are needed to evaluate other values
+/// in the record or to select the existing row if the record is skipped.
+fn selected_columns<'a>(record: &'a Record, on_conflict: &ConflictAction) -> HashSet<&'a str> {
+    let mut selected: HashSet<&str> = match on_conflict {
+        ConflictAction::Ignore { keys, .. } => record
+            .nodes
+            .iter()
+            .map(|a| a.name.as_str())
+            .filter(|name| keys.iter().flatten().any(|column| *column == quote(name)))
+            .collect(),
+        _ => HashSet::new(),
+    };
+
+    // Column references are only to earlier columns, so a selected column that
+    // re-binds another column's value is always found before the other column
+    for attribute in record.nodes.iter().rev() {
+        match &attribute.value {
+            Value::Reference(Reference::ColumnLevel(colref)) => {
+                if selected.contains(attribute.name.as_str()) {
+                    selected.insert(&colref.column);
+                }
+            }
+            value => selected.extend(value.references().filter_map(|refval| match refval {
+                Reference::ColumnLevel(colref) => Some(colref.column.as_str()),
+                _ => None,
+            })),
+        }
+    }
+
+    selected
+}
+
+/// Renders a bound value, either as a placeholder or by selecting from its fragment's
+/// CTE, cast to the column's type if known, or as `DEFAULT`.
+fn bound_sql(value: BoundValue, column_type: Option<&str>) -> String {
+    match (value, column_type) {
+        (BoundValue::Default, _) => "DEFAULT".to_owned(),
+        (BoundValue::Null, _) => "NULL".to_owned(),
+        (BoundValue::Param(param), _) => placeholder(param, column_type),
+        (BoundValue::Fragment(index), Some(cast)) => format!("(SELECT fragment FROM fragment{})::{}", index + 1, cast),
+        (BoundValue::Fragment(index), None) => format!("(SELECT fragment FROM fragment{})", index + 1),
+    }
+}
+
+/// Renders the positional placeholder for a bound parameter, eg. `$1::numeric`, cast to
+/// the column's type if known or else the type of the value, if any
+fn placeholder(param: BoundParam, column_type: Option<&str>) -> String {
+    match column_type.or(param.cast) {
+        Some(cast) => format!("${}::{}", param.index + 1, cast),
+        None => format!("${}", param.index + 1),
+    }
+}
+
+struct InsertStatement {
+    sql: String,
+    params: Vec<TextParam>,
+    /// The quoted name of each inserted column and the value bound to it, per row
+    bound_rows: Vec<Vec<(String, BoundValue)>>,
+}
+
+impl InsertStatement {
+    fn build<'f, C: Connection>(
+        t: &'f mut C,
+        fragments: Option<&'f mut HashMap<String, Option<String>>>,
+    ) -> InsertStatementBuilder<'static, 'static, 'static, 'f, 'static, 'static, 'static, 'static, C> {
+        InsertStatementBuilder {
+            attribute_params: HashMap::new(),
+            column_types: None,
+            current_scope: "",
+            fragment_runner: FragmentRunner {
+                transaction: t,
+                cache: fragments,
+                times: HashMap::new(),
+                fixed_now: None,
+            },
+            fragments: Vec::new(),
+            on_conflict: &ConflictAction::Error,
+            params: Vec::new(),
+            qualified_table_name: "",
+            records: &[],
+            refmap: None,
+            returning: &[],
+            select_fragments: false,
+            selected_columns: HashSet::new(),
+            update_where: None,
+            delete: false,
+        }
+    }
+
+    fn as_ref(&self) -> &str {
+        &self.sql
+    }
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params.iter().map(|p| p as &(dyn ToSql + Sync)).collect()
+    }
+
+    /// Returns the parameter bound to the column, unless its value is selected in a CTE
+    fn bound_param(&self, row: usize, quoted_column: &str) -> Option<BoundParam> {
+        self.bound_rows[row]
+            .iter()
+            .find(|(column, _)| column == quoted_column)
+            .and_then(|(_, value)| match value {
+                BoundValue::Param(param) => Some(*param),
+                BoundValue::Default | BoundValue::Fragment(_) | BoundValue::Null => None,
+            })
+    }
+}
+
+/// Returns the SQL of the tree's hooks that are executed at the time, in the order
+/// they were declared.
+fn hook_statements(tree: &ParseTree, timing: HookTiming) -> Vec<String> {
+    tree.hooks
+        .iter()
+        .filter(|hook| hook.timing == timing)
+        .map(|hook| hook.sql.clone())
+        .collect()
+}
+
+/// Returns the statements that create every schema declared in the data files with the
+/// `create_schemas` option, in the order they were declared, for databases that do not
+/// have them yet.
+fn create_schema_statements(tree: &ParseTree, options: &Options) -> Vec<String> {
+    if !options.create_schemas {
+        return Vec::new();
+    }
+
+    let mut schemas: Vec<&str> = Vec::new();
+
+    for node in &tree.nodes {
+        if let StructuralNode::Schema(schema) = node {
+            if !schemas.contains(&schema.identity.name.as_str()) {
+                schemas.push(&schema.identity.name);
+            }
+        }
+    }
+
+    schemas.into_iter().map(create_schema_statement).collect()
+}
+
+fn create_schema_statement(schema: &str) -> String {
+    format!("CREATE SCHEMA IF NOT EXISTS {}", quote(schema))
+}
+
+/// Returns the statement that truncates every table declared with `truncate`, or
+/// every table in the tree with the `truncate` option, if there are any. Identities
+/// are restarted so that generated keys are the same on every load, and tables are
+/// truncated in one statement with `CASCADE` so that their order does not matter.
+fn truncate_statement(tree: &ParseTree, options: &Options) -> Option<String> {
+    let tables = truncated_tables(tree, options);
+
+    if tables.is_empty() {
+        return None;
+    }
+
+    Some(format!("TRUNCATE {} RESTART IDENTITY CASCADE", tables.join(", ")))
+}
+
+/// The triggers disabled while loading with the `disable_triggers` option, which are
+/// enabled again once everything is loaded.
+#[derive(Default)]
+struct DisabledTriggers {
+    mode: Option<DisableTriggers>,
+    /// The statements that enable the triggers disabled so far, in the order they were disabled
+    enable: Vec<String>,
+}
+
+impl DisabledTriggers {
+    fn new(options: &Options) -> Self {
+        Self {
+            mode: options.disable_triggers,
+            enable: Vec::new(),
+        }
+    }
+
+    /// Returns the statement that disables the triggers that would fire when loading into
+    /// the table, unless they are disabled already.
+    fn disable(&mut self, qualified_table_name: &str) -> Option<String> {
+        let alter = |action: &str, triggers: &str| format!("ALTER TABLE {} {} TRIGGER {}", qualified_table_name, action, triggers);
+
+        let (disable, enable) = match self.mode? {
+            DisableTriggers::User => (alter("DISABLE", "USER"), alter("ENABLE", "USER")),
+            DisableTriggers::All => (alter("DISABLE", "ALL"), alter("ENABLE", "ALL")),
+            DisableTriggers::Replica => (
+                set_local("session_replication_role", "replica"),
+                "SET LOCAL session_replication_role TO DEFAULT".to_owned(),
+            ),
+        };
+
+        if self.enable.contains(&enable) {
+            return None;
+        }
+
+        self.enable.push(enable);
+        Some(disable)
+    }
+
+    /// Returns the statements that enable the disabled triggers again.
+    fn enable(&mut self) -> Vec<String> {
+        mem::take(&mut self.enable)
+    }
+}
+
+/// The columns that records give values for, by qualified table name, whose sequences
+/// are reset once everything is loaded with the `reset_sequences` option.
+#[derive(Default)]
+struct ExplicitColumns {
+    columns: Vec<(String, String)>,
+    seen: HashSet<(String, String)>,
+}
+
+impl ExplicitColumns {
+    fn add(&mut self, qualified_table_name: &str, table: &Table) {
+        for attribute in table.nodes.iter().flat_map(|record| &record.nodes) {
+            let column = (qualified_table_name.to_owned(), attribute.name.clone());
+
+            if self.seen.insert(column.clone()) {
+                self.columns.push(column);
+            }
+        }
+    }
+
+    /// Returns the statements that reset the sequences of the columns, in the order that
+    /// values were first given for them.
+    fn statements(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|(qualified_table_name, column)| reset_sequence(qualified_table_name, column))
+            .collect()
+    }
+}
+
+/// The number of rows that tables declared with `expect N rows` are expected to have, by
+/// qualified table name, which are counted once everything is loaded.
+#[derive(Default)]
+struct ExpectedCounts(Vec<(String, u64)>);
+
+impl ExpectedCounts {
+    fn add(&mut self, qualified_table_name: &str, table: &Table) {
+        if let Some(count) = table.expected_rows {
+            let expected = (qualified_table_name.to_owned(), count);
+
+            if !self.0.contains(&expected) {
+                self.0.push(expected);
+            }
+        }
+    }
+
+    /// Returns the statements that fail unless each table has the expected number of rows,
+    /// for when the statements are written rather than executed.
+    fn statements(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|(qualified_table_name, count)| expect_rows_statement(qualified_table_name, *count))
+            .collect()
+    }
+}
+
+/// The qualified names of the tables that records are loaded into, whose materialized
+/// views are refreshed once everything is loaded with the `refresh_matviews` option.
+#[derive(Default)]
+struct SeededTables(Vec<String>);
+
+impl SeededTables {
+    fn add(&mut self, qualified_table_name: &str, table: &Table) {
+        if !table.nodes.is_empty() && !self.0.iter().any(|name| name == qualified_table_name) {
+            self.0.push(qualified_table_name.to_owned());
+        }
+    }
+
+    /// Returns the statement that refreshes the materialized views depending on the
+    /// tables, unless no records were loaded.
+    fn statement(&self) -> Option<String> {
+        (!self.0.is_empty()).then(|| refresh_matviews_statement(&self.0))
+    }
+}
+
+fn count_statement(qualified_table_name: &str) -> String {
+    format!("SELECT count(*) FROM {}", qualified_table_name)
+}
+
+/// Returns the statement that raises an error unless the table has the number of rows.
+fn expect_rows_statement(qualified_table_name: &str, count: u64) -> String {
+    let message = format!("expected {} rows in table {} once loaded, found %", count, qualified_table_name);
+
+    format!(
+        "DO $$ DECLARE found bigint := ({}); BEGIN IF found <> {} THEN RAISE EXCEPTION '{}', found; END IF; END $$",
+        count_statement(qualified_table_name),
+        count,
+        message.replace('\'', "''"),
+    )
+}
+
+/// Returns the statement that refreshes the materialized views that depend on the tables,
+/// directly or through other views, with each view refreshed after any views it depends
+/// on. The statement looks up the views itself from the rewrite rules that define them,
+/// as with resetting sequences, so that it can also be written rather than executed.
+fn refresh_matviews_statement(qualified_table_names: &[String]) -> String {
+    let tables: Vec<String> = qualified_table_names
+        .iter()
+        .map(|name| format!("'{}'::regclass", name.replace('\'', "''")))
+        .collect();
+
+    format!(
+        "DO $$ DECLARE matview regclass; BEGIN FOR matview IN \
+         WITH RECURSIVE dependent (oid, depth) AS (\
+         SELECT r.ev_class, 1 FROM pg_depend d JOIN pg_rewrite r ON r.oid = d.objid \
+         WHERE d.classid = 'pg_rewrite'::regclass AND d.refobjid IN ({}) AND r.ev_class <> d.refobjid \
+         UNION \
+         SELECT r.ev_class, dependent.depth + 1 FROM dependent \
+         JOIN pg_depend d ON d.refobjid = dependent.oid JOIN pg_rewrite r ON r.oid = d.objid \
+         WHERE d.classid = 'pg_rewrite'::regclass AND r.ev_class <> d.refobjid) \
+         SELECT dependent.oid::regclass FROM dependent JOIN pg_class c ON c.oid = dependent.oid \
+         WHERE c.relkind = 'm' GROUP BY dependent.oid ORDER BY max(dependent.depth) \
+         LOOP EXECUTE format('REFRESH MATERIALIZED VIEW %s', matview); END LOOP; END $$",
+        tables.join(", "),
+    )
+}
+
+/// Returns the statement that sets the sequence that generates the column's values, eg.
+/// of a `serial` or identity column, to the column's greatest value, so that values
+/// generated later do not conflict with those given explicitly. The statement does
+/// nothing for columns without sequences, which it checks itself so that it can be
+/// written rather than executed, and the column is cast so that it type checks
+/// whatever its type.
+fn reset_sequence(qualified_table_name: &str, column: &str) -> String {
+    format!(
+        "SELECT setval(seq::regclass, (SELECT max({}::text::bigint) FROM {})) \
+         FROM pg_get_serial_sequence('{}', '{}') AS seq WHERE seq IS NOT NULL",
+        quote(column),
+        qualified_table_name,
+        qualified_table_name.replace('\'', "''"),
+        column.replace('\'', "''"),
+    )
+}
+
+/// Returns the statement that sets the sequence, once looked up, to the greatest value of
+/// the column it generates values for, just as [`reset_sequence`] does.
+fn set_sequence(qualified_table_name: &str, column: &str, sequence: &str) -> String {
+    format!(
+        "SELECT setval('{}', (SELECT max({}::text::bigint) FROM {}))",
+        sequence.replace('\'', "''"),
+        quote(column),
+        qualified_table_name,
+    )
+}
+
+/// Looks up the quoted names of the table's primary key columns to use as the
+/// conflict target for tables that do not explicitly declare one.
+async fn primary_key<C: Connection>(connection: &mut C, qualified_table_name: &str) -> LoadResult<Vec<String>> {
+    let rows = connection
+        .query(PRIMARY_KEY_QUERY, &[&qualified_table_name])
+        .await
+        .map_err(LoadError::new)?;
+
+    if rows.is_empty() {
+        return Err(LoadError::no_primary_key(qualified_table_name));
+    }
+
+    Ok(rows.iter().map(|row| quote(row.get(0))).collect())
+}
+
+/// Adds the table that the batch was inserted into to errors from the database, along
+/// with the name and position of the batch's record if it has only one, since which of
+/// several records inserted by the same statement caused an error is not known. Errors
+/// that the database reports for a column, eg. not-null violations, are instead at the
+/// record's attribute for the column, if it has one.
+fn batch_error(error: LoadError, qualified_table_name: &str, batch: &[&Record]) -> LoadError {
+    let record = match batch {
+        [record] => Some(record),
+        _ => None,
+    };
+    let mut error = error.inserting(qualified_table_name, None);
+    let mut column = None;
+
+    if let LoadErrorKind::Insert { record: name, error: db_error, .. } = &mut error.kind {
+        *name = record.and_then(|r| r.name.clone());
+        column = db_error.as_db_error().and_then(|e| e.column()).map(str::to_owned);
+    }
+
+    match record.and_then(|r| r.nodes.iter().find(|a| Some(&a.name) == column.as_ref())) {
+        Some(attribute) => error.in_attribute(attribute),
+        None => error.at(record.and_then(|r| r.position)),
+    }
+}
+
+fn log_inserted(rows: u64, qualified_table_name: &str) {
+    let plural = if rows == 1 { "" } else { "s" };
+    log::info!("Inserted {} row{} into {}", rows, plural, qualified_table_name);
+}
+
+fn log_deleted(rows: u64, qualified_table_name: &str) {
+    let plural = if rows == 1 { "" } else { "s" };
+    log::info!("Deleted {} row{} from {}", rows, plural, qualified_table_name);
+}
+
+fn log_updated(rows: u64, qualified_table_name: &str) {
+    let plural = if rows == 1 { "" } else { "s" };
+    log::info!("Updated {} row{} in {}", rows, plural, qualified_table_name);
+}
+
+/// Returns the qualified names of the tables to truncate, without duplicates.
+fn truncated_tables(tree: &ParseTree, options: &Options) -> Vec<String> {
+    let mut tables: Vec<String> = Vec::new();
+    let mut collect = |schema: Option<&StructuralIdentity>, table: &Table| {
+        let qualified_table_name = qualified_table_name(schema, table);
+
+        if (options.truncate || table.truncate) && !tables.contains(&qualified_table_name) {
+            tables.push(qualified_table_name);
+        }
+    };
+
+    for node in &tree.nodes {
+        match node {
+            StructuralNode::Schema(schema) => {
+                for table in &schema.nodes {
+                    collect(Some(&schema.identity), table);
+                }
+            }
+            StructuralNode::Table(table) => collect(None, table),
+        }
+    }
+
+    tables
+}
+
+pub fn load(transaction: &mut Transaction, tree: ValidatedParseTree, options: &Options) -> LoadResult<PlaceReport> {
+    block_on(load_with(transaction, tree, options))
+}
+
+/// Loads the tree within a `tokio-postgres` transaction, just as [`load`] does
+/// with a blocking one.
+#[cfg(feature = "async")]
+pub async fn load_async(
+    transaction: &mut tokio_postgres::Transaction<'_>,
+    tree: ValidatedParseTree,
+    options: &Options,
+) -> LoadResult<PlaceReport> {
+    load_with(transaction, tree, options).await
+}
+
+/// Loads the tree, logging each statement with the `log_sql` option.
+pub(crate) async fn load_with<C: Connection>(connection: &mut C, tree: ValidatedParseTree, options: &Options) -> LoadResult<PlaceReport> {
+    let mut connection = SqlLog::new(connection, options)?;
+    let report = load_tree(&mut connection, tree, options).await?;
+
+    connection.finish()?;
+    Ok(report)
+}
+
+async fn load_tree<C: Connection>(connection: &mut C, tree: ValidatedParseTree, options: &Options) -> LoadResult<PlaceReport> {
+    let tree = tree.into_inner();
+
+    if let Some(statement) = search_path_statement(&tree) {
+        log::debug!("{}", statement);
+        connection.simple_query(&statement).await.map_err(LoadError::new)?;
+    }
+
+    for statement in create_schema_statements(&tree, options) {
+        log::debug!("{}", statement);
+        connection.simple_query(&statement).await.map_err(LoadError::new)?;
+    }
+
+    for statement in ddl::create_table_statements(&tree, options) {
+        log::debug!("{}", statement);
+        connection.simple_query(&statement).await.map_err(LoadError::new)?;
+    }
+
+    if options.verify_schema || options.strict_numbers {
+        schema::verify(connection, &tree, options.strict_numbers).await?;
+    }
+
+    let started = Instant::now();
+
+    for statement in hook_statements(&tree, HookTiming::Before) {
+        log::debug!("{}", statement);
+        connection.simple_query(&statement).await.map_err(LoadError::new)?;
+    }
+
+    if let Some(statement) = truncate_statement(&tree, options) {
+        // Shown by default rather than only in verbose mode, as it deletes existing rows
+        log::info!("{}", statement);
+        connection.simple_query(&statement).await.map_err(LoadError::new)?;
+    }
+
+    let after = hook_statements(&tree, HookTiming::After);
+
+    let mut loader = Loader::new(connection, options);
+
+    loader.referenced = Some(referenced_columns(&tree));
+
+    for node in tree.nodes {
+        match node {
+            StructuralNode::Schema(schema) => {
+                let identity = schema.identity;
+                for table in schema.nodes {
+                    loader.load_or_skip_table(Some(&identity), &table).await?;
+                }
+            }
+            StructuralNode::Table(table) => {
+                loader.load_or_skip_table(None, &table).await?;
+            }
+        }
+    }
+
+    loader.check_failed_inserts()?;
+
+    // Anything executed in the transaction afterwards, eg. by a caller, is executed as
+    // the role and with the settings it had before loading rather than the last table's
+    loader.switch_role(None).await?;
+    loader.switch_settings(&[]).await?;
+    loader.enable_triggers().await?;
+    loader.reset_sequences().await?;
+    loader.refresh_matviews().await?;
+
+    for statement in after {
+        log::debug!("{}", statement);
+        loader.transaction.simple_query(&statement).await.map_err(LoadError::new)?;
+    }
+
+    loader.verify_counts().await?;
+    loader.report.duration = started.elapsed();
+
+    Ok(loader.report)
+}
+
+/// Loads tables as they are streamed through the analyzer rather than a whole tree at
+/// once, keeping the rows of named records to resolve later references to them.
+pub(crate) struct StreamLoader<'a, C: Connection> {
+    loader: Loader<'a, C>,
+    started: Instant,
+    /// The qualified name of the table whose declaration is being streamed
+    declaration: Option<String>,
+    /// The schemas created so far with the `create_schemas` option, if set, as they are
+    /// created when their first table is streamed
+    created_schemas: Option<HashSet<String>>,
+}
+
+impl<'a, C: Connection> StreamLoader<'a, C> {
+    /// Fails for options that need every data file to be parsed before anything is
+    /// loaded, as tables are loaded as soon as they are parsed when streaming.
+    pub(crate) fn new(connection: &'a mut C, options: &Options) -> LoadResult<Self> {
+        let unstreamable = [
+            ("cache", options.cache),
+            ("confirm", options.confirm),
+            ("create_tables", options.create_tables),
+            ("dedupe", options.dedupe),
+            ("limit_per_table", options.limit_per_table.is_some()),
+            ("sample", options.sample.is_some()),
+            ("strict_numbers", options.strict_numbers),
+            ("truncate", options.truncate),
+            ("verify_schema", options.verify_schema),
+        ];
+
+        if let Some((option, _)) = unstreamable.into_iter().find(|(_, set)| *set) {
+            return Err(LoadError::unstreamable_option(option));
+        }
+
+        let mut loader = Loader::new(connection, options);
+        loader.streamed_rows = Some(0);
+
+        Ok(Self {
+            loader,
+            started: Instant::now(),
+            declaration: None,
+            created_schemas: options.create_schemas.then(HashSet::new),
+        })
+    }
+
+    pub(crate) async fn load(&mut self, table: ValidatedTable) -> LoadResult<()> {
+        let streamed = table.into_inner();
+
+        if let (Some(created), Some(schema)) = (&mut self.created_schemas, &streamed.schema) {
+            if created.insert(schema.name.clone()) {
+                let statement = create_schema_statement(&schema.name);
+                log::debug!("{}", statement);
+                self.loader.transaction.simple_query(&statement).await.map_err(LoadError::new)?;
+            }
+        }
+
+        if !streamed.continued {
+            self.log_declaration();
+            self.declaration = Some(qualified_table_name(streamed.schema.as_ref(), &streamed.table));
+        }
+
+        self.loader.load_or_skip_table(streamed.schema.as_ref(), &streamed.table).await
+    }
+
+    pub(crate) async fn finish(mut self) -> LoadResult<PlaceReport> {
+        self.log_declaration();
+        self.loader.check_failed_inserts()?;
+        self.loader.switch_role(None).await?;
+        self.loader.switch_settings(&[]).await?;
+        self.loader.enable_triggers().await?;
+        self.loader.reset_sequences().await?;
+        self.loader.refresh_matviews().await?;
+        self.loader.verify_counts().await?;
+        self.loader.report.duration = self.started.elapsed();
+
+        Ok(self.loader.report)
+    }
+
+    /// Logs the rows inserted from the table declaration that was being streamed, if any.
+    fn log_declaration(&mut self) {
+        let rows = self.loader.streamed_rows.replace(0).unwrap_or_default();
+
+        if let Some(qualified_table_name) = self.declaration.take() {
+            log_inserted(rows, &qualified_table_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn table(input: &str) -> Table {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let tree = parser::parse(tokens.into_iter()).unwrap();
+
+        match tree.nodes.into_iter().next() {
+            Some(StructuralNode::Table(table)) => *table,
+            node => panic!("expected table, found {:?}", node),
+        }
+    }
+
+    /// A connection on which every statement succeeds without returning any rows,
+    /// which keeps the statements it was given.
+    #[derive(Default)]
+    struct EmptyConnection(Vec<String>);
+
+    impl Connection for EmptyConnection {
+        async fn execute(&mut self, query: &str, _params: &[&(dyn ToSql + Sync)]) -> Result<u64, postgres::Error> {
+            self.0.push(query.to_owned());
+            Ok(0)
+        }
+
+        async fn query(&mut self, query: &str, _params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, postgres::Error> {
+            self.0.push(query.to_owned());
+            Ok(Vec::new())
+        }
+
+        async fn query_opt(&mut self, query: &str, _params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>, postgres::Error> {
+            self.0.push(query.to_owned());
+            Ok(None)
+        }
+
+        async fn simple_query(&mut self, query: &str) -> Result<Vec<SimpleQueryMessage>, postgres::Error> {
+            self.0.push(query.to_owned());
+            Ok(Vec::new())
+        }
+
+        async fn copy_in(&mut self, query: &str, _rows: String) -> Result<u64, LoadError> {
+            self.0.push(query.to_owned());
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_can_batch() {
+        let table = table(
+            r#"
+            table t1 (
+                r1 (a 'one', b 'two')
+                (a 'three', b 'four')
+                (b 'five', a 'six')
+                (a 'seven')
+                ()
+                (a 'eight', b @r1.)
+                (a 'nine', b @t1.r1.b)
+                (a 'ten', b volatile `now()`)
+                (a 'eleven', b @a)
+                (a 'twelve', b `now()`)
+            )
+        "#,
+        );
+        let batch: Vec<_> = table.nodes.iter().take(2).collect();
+        let can_batch = |i: usize| can_batch(&batch, &table.nodes[i], "t1", &ConflictAction::Error, true);
+
+        assert!(can_batch(1));
+        assert!(!can_batch(2), "different column order");
+        assert!(!can_batch(3), "different columns");
+        assert!(!can_batch(4), "default values");
+        assert!(!can_batch(5), "record-level reference to batch");
+        assert!(!can_batch(6), "table-level reference to batch");
+        assert!(!can_batch(7), "volatile SQL fragment");
+        assert!(can_batch(8));
+        assert!(can_batch(9), "cached SQL fragment");
+        assert!(
+            !super::can_batch(&batch, &table.nodes[9], "t1", &ConflictAction::Error, false),
+            "SQL fragment without caching",
+        );
+
+        let update = ConflictAction::Update(vec![r#""a""#.to_owned()]);
+        assert!(!super::can_batch(&batch, &table.nodes[1], "t1", &update, true), "upsert");
+    }
+
+    #[test]
+    fn test_skip_keys() {
+        let table = table("table person (\n  (email 'kevin@example.com', name 'Kevin')\n  (name 'Alice')\n)");
+        let schema = StructuralIdentity::new("app".to_owned(), None);
+        let keys = |keys: &[&str], schema| {
+            let skip_existing: Vec<SkipExisting> = keys.iter().map(|k| k.parse().unwrap()).collect();
+            skip_keys(&skip_existing, schema, &table)
+        };
+        let quoted = |columns: &[&str]| columns.iter().map(|c| quote(c)).collect::<Vec<_>>();
+
+        assert_eq!(
+            keys(&["email", "person=first_name, last_name", "pet=name"], None),
+            vec![quoted(&["first_name", "last_name"]), quoted(&["email"])],
+        );
+        assert_eq!(keys(&["app.person=name", "other.person=email"], Some(&schema)), vec![quoted(&["name"])]);
+        assert_eq!(keys(&["app.person=name"], None), Vec::<Vec<String>>::new());
+
+        assert!(gives_values(&table.nodes[0], &quoted(&["email"])));
+        assert!(!gives_values(&table.nodes[1], &quoted(&["email"])));
+        assert!(!gives_values(&table.nodes[0], &quoted(&["email", "age"])));
+
+        assert!("=email".parse::<SkipExisting>().is_err());
+        assert!("person=email,".parse::<SkipExisting>().is_err());
+    }
+
+    #[test]
+    fn test_keep_going() {
+        let tokens = lexer::tokenize(
+            "table t1 (\n  r1 (a env('HLDR_TEST_KEEP_GOING'))\n)\ntable t2 (\n  (b @t1.r1.a)\n)".chars(),
+        )
+        .unwrap();
+        let tables: Vec<Table> = parser::parse(tokens.into_iter())
+            .unwrap()
+            .nodes
+            .into_iter()
+            .map(|node| match node {
+                StructuralNode::Table(table) => *table,
+                node => panic!("expected table, found {:?}", node),
+            })
+            .collect();
+        let load = |keep_going| {
+            let options = Options { keep_going, ..Options::default() };
+            let mut connection = EmptyConnection::default();
+            let mut loader = Loader::new(&mut connection, &options);
+            let result = tables.iter().try_for_each(|table| block_on(loader.load_or_skip_table(None, table)));
+            let skipped = mem::take(&mut loader.report.skipped);
+            let savepoints: Vec<_> = connection.0.into_iter().filter(|s| s.contains("SAVEPOINT")).collect();
+
+            (result, skipped, savepoints)
+        };
+        let skipped = |table: &str, error: &str| SkippedTable {
+            schema: None,
+            table: table.to_owned(),
+            error: error.to_owned(),
+        };
+
+        let (result, skipped_tables, savepoints) = load(true);
+        assert!(result.is_ok());
+        assert_eq!(
+            skipped_tables,
+            vec![
+                skipped("t1", "environment variable `HLDR_TEST_KEEP_GOING` is not set"),
+                skipped("t2", "could not select referenced column `a` of record `t1.r1`, as its table was skipped"),
+            ],
+        );
+        assert_eq!(
+            savepoints,
+            ["SAVEPOINT hldr_table", "ROLLBACK TO SAVEPOINT hldr_table"].repeat(2),
+        );
+
+        let (result, skipped_tables, savepoints) = load(false);
+        assert_eq!(result.unwrap_err().position, Some(crate::Position { line: 2, column: 7 }));
+        assert!(skipped_tables.is_empty());
+        assert!(savepoints.is_empty());
+    }
+
+    #[test]
+    fn test_returning() {
+        let t1 = table("table t1 (\n  r1 (a 1, b 2)\n  (a 3, b 4)\n)");
+        let records: Vec<_> = t1.nodes.iter().collect();
+        let anonymous = table("table t1 (\n  (a 3, b 4)\n)");
+        let options = Options::default();
+        let mut connection = EmptyConnection::default();
+        let mut loader = Loader::new(&mut connection, &options);
+        let columns = vec!["a".to_owned(), "b".to_owned()];
+        loader.table_columns.insert(r#""t1""#.to_owned(), columns.clone());
+        loader.column_types.insert(r#""t1""#.to_owned(), HashMap::new());
+        loader.referenced = Some(HashMap::new());
+
+        // Named records are reported, so they still return every column
+        let returning = block_on(loader.returning(r#""t1""#, "t1", &records[..1])).unwrap();
+        assert_eq!(returning, columns);
+
+        // While anonymous records are never referenced, so they return nothing
+        let returning = block_on(loader.returning(r#""t1""#, "t1", &records[1..])).unwrap();
+        assert!(returning.is_empty());
+
+        block_on(loader.load_or_skip_table(None, &anonymous)).unwrap();
+        let statement = connection.0.iter().find(|s| s.contains("INSERT")).unwrap();
+        assert_eq!(
+            statement.split_whitespace().collect::<Vec<_>>().join(" "),
+            r#"INSERT INTO "t1" ("a", "b") VALUES ($1::numeric, $2::numeric)"#,
+        );
+    }
+
+    #[test]
+    fn test_update_where() {
+        let t1 = table("table t1 (\n  update _ where (a null, b 2) (c 3)\n)");
+        let options = Options::default();
+        let mut connection = EmptyConnection::default();
+        let mut loader = Loader::new(&mut connection, &options);
+        let columns = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        loader.table_columns.insert(r#""t1""#.to_owned(), columns);
+        loader.column_types.insert(r#""t1""#.to_owned(), HashMap::new());
+        loader.referenced = Some(HashMap::new());
+
+        // No rows are ever returned, so the update matches none
+        let error = block_on(loader.load_or_skip_table(None, &t1)).unwrap_err();
+        assert!(matches!(error.kind, LoadErrorKind::UnmatchedUpdate { found: 0, .. }));
+
+        let statement = connection.0.iter().find(|s| s.contains("UPDATE")).unwrap();
+        assert_eq!(
+            statement.split_whitespace().collect::<Vec<_>>().join(" "),
+            r#"UPDATE "t1" SET "c" = $2::numeric WHERE "a" IS NULL AND "b" = $1::numeric"#,
+        );
+    }
+
+    #[test]
+    fn test_returning_clause() {
+        let upsert = ConflictAction::Update(vec![r#""a""#.to_owned()]);
+
+        assert_eq!(returning_clause(&[], &ConflictAction::Error), "");
+        assert_eq!(returning_clause(&["a".to_owned()], &ConflictAction::Error), r#"RETURNING "a"::text AS "a""#);
+        assert_eq!(returning_clause(&[], &upsert), r#"RETURNING xmax = 0 AS "hldr_inserted""#);
+        assert_eq!(
+            returning_clause(&["a".to_owned()], &upsert),
+            r#"RETURNING "a"::text AS "a", xmax = 0 AS "hldr_inserted""#,
+        );
+    }
+
+    #[test]
+    fn test_reset_sequences() {
+        let t1 = table("table t1 (\n  (a 1, b 2)\n)");
+        let options = Options { reset_sequences: true, ..Options::default() };
+        let mut connection = EmptyConnection::default();
+        let mut loader = Loader::new(&mut connection, &options);
+        loader.explicit_columns.as_mut().unwrap().add(r#""t1""#, &t1);
+
+        // No sequences are ever found, so none are set
+        block_on(loader.reset_sequences()).unwrap();
+        assert_eq!(connection.0, vec![SERIAL_SEQUENCES_QUERY.to_owned()]);
+
+        assert_eq!(
+            set_sequence(r#""t1""#, "a", "public.t1_a_seq"),
+            r#"SELECT setval('public.t1_a_seq', (SELECT max("a"::text::bigint) FROM "t1"))"#,
+        );
+    }
+
+    #[test]
+    fn test_batch_error() {
+        let table = table("table t1 (\n  r1 (a 1)\n  (a 2)\n)");
+        let records: Vec<_> = table.nodes.iter().collect();
+        let error = || LoadError::no_primary_key(r#""t1""#);
+
+        let position = batch_error(error(), r#""t1""#, &records[1..]).position;
+        assert_eq!(position, Some(crate::Position { line: 3, column: 3 }));
+
+        let position = batch_error(error(), r#""t1""#, &records).position;
+        assert_eq!(position, None, "records inserted together");
+
+        let error = error().at(Some(crate::Position { line: 9, column: 9 }));
+        let position = batch_error(error, r#""t1""#, &records[..1]).position;
+        assert_eq!(position, Some(crate::Position { line: 9, column: 9 }), "more specific position");
+    }
+
+    #[test]
+    fn test_fragment_without_rows() {
+        let mut connection = EmptyConnection::default();
+        let mut runner = FragmentRunner {
+            transaction: &mut connection,
+            cache: None,
+            times: HashMap::new(),
+            fixed_now: None,
+        };
+
+        assert_eq!(
+            block_on(runner.select("id FROM person")).unwrap_err().to_string(),
+            "expected a single row from SQL fragment `id FROM person`, found 0",
+        );
+    }
+
+    #[test]
+    fn test_copy_text() {
+        assert_eq!(copy_text(None), r"\N");
+        assert_eq!(copy_text(Some("")), "");
+        assert_eq!(copy_text(Some(r"\N")), r"\\N");
+        assert_eq!(copy_text(Some("tab\tline\nreturn\r")), r"tab\tline\nreturn\r");
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let param = |cast| BoundParam { index: 0, cast };
+
+        assert_eq!(placeholder(param(None), None), "$1");
+        assert_eq!(placeholder(param(Some("numeric")), None), "$1::numeric");
+        assert_eq!(placeholder(param(Some("numeric")), Some("integer[]")), "$1::integer[]");
+        assert_eq!(placeholder(param(None), Some("timestamp with time zone")), "$1::timestamp with time zone");
+    }
+
+    #[test]
+    fn test_bound_sql() {
+        let param = BoundValue::Param(BoundParam { index: 0, cast: None });
+
+        assert_eq!(bound_sql(param, Some("integer")), "$1::integer");
+        assert_eq!(bound_sql(BoundValue::Fragment(1), None), "(SELECT fragment FROM fragment2)");
+        assert_eq!(bound_sql(BoundValue::Fragment(0), Some("jsonb")), "(SELECT fragment FROM fragment1)::jsonb");
+    }
+
+    #[test]
+    fn test_selected_columns() {
+        let table = table(
+            r#"
+            table t1 (
+                (a `1`, b @a, c @b || 'x', d `2`, e @d, g `3`)
+                (a `1`, b '{@a}', c `{@a}`)
+            )
+        "#,
+        );
+        let selected = |i: usize, on_conflict| {
+            let mut columns: Vec<_> = selected_columns(&table.nodes[i], on_conflict).into_iter().collect();
+            columns.sort();
+            columns
+        };
+
+        assert_eq!(selected(0, &ConflictAction::Error), vec!["a", "b"]);
+        assert_eq!(selected(1, &ConflictAction::Error), vec!["a"]);
+
+        let ignore = ConflictAction::Ignore {
+            target: None,
+            keys: vec![vec![r#""g""#.to_owned()]],
+        };
+        assert_eq!(selected(0, &ignore), vec!["a", "b", "g"]);
+    }
+
+    #[test]
+    fn test_truncate_statement() {
+        let tree = |input: &str| {
+            let tokens = lexer::tokenize(input.chars()).unwrap();
+            parser::parse(tokens.into_iter()).unwrap()
+        };
+        let truncate = |truncate| Options {
+            truncate,
+            ..Options::default()
+        };
+
+        let declared = tree("table t1 ()
+schema s1 (table t2 truncate ())
+table t1 truncate ()");
+        let undeclared = tree("table t1 ()");
+
+        assert_eq!(
+            truncate_statement(&declared, &truncate(false)).as_deref(),
+            Some(r#"TRUNCATE "s1"."t2", "t1" RESTART IDENTITY CASCADE"#),
+        );
+        assert_eq!(
+            truncate_statement(&declared, &truncate(true)).as_deref(),
+            Some(r#"TRUNCATE "t1", "s1"."t2" RESTART IDENTITY CASCADE"#),
+        );
+        assert_eq!(truncate_statement(&undeclared, &truncate(false)), None);
+    }
+
+    #[test]
+    fn test_create_schema_statements() {
+        let tokens = lexer::tokenize("schema s1 (table t1 ())\ntable t2 ()\nschema \"App\" as a (table t3 ())\nschema s1 (table t4 ())".chars()).unwrap();
+        let tree = parser::parse(tokens.into_iter()).unwrap();
+        let options = Options {
+            create_schemas: true,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            create_schema_statements(&tree, &options),
+            [r#"CREATE SCHEMA IF NOT EXISTS "s1""#, r#"CREATE SCHEMA IF NOT EXISTS "App""#],
+        );
+        assert!(create_schema_statements(&tree, &Options::default()).is_empty());
+    }
+
+    #[test]
+    fn test_set_local() {
+        assert_eq!(set_local("lock_timeout", "5s"), "SET LOCAL lock_timeout = '5s'");
+        assert_eq!(set_local("search_path", "it's"), "SET LOCAL search_path = 'it''s'");
+    }
+
+    #[test]
+    fn test_search_path() {
+        let tokens = lexer::tokenize("set search_path app, \"Public\"\"s\"\ntable t1 ()".chars()).unwrap();
+        let tree = parser::parse(tokens.into_iter()).unwrap();
+        let options = Options {
+            search_path: Some(vec!["app".to_owned(), "public".to_owned()]),
+            ..Options::default()
+        };
+
+        assert_eq!(search_path_statement(&tree).as_deref(), Some(r#"SET LOCAL search_path = "app", "Public""s""#));
+        assert_eq!(search_path_statement(&ParseTree::default()), None);
+        assert_eq!(settings(&options), vec![r#"SET LOCAL search_path = "app", "public""#]);
+    }
+
+    #[test]
+    fn test_role() {
+        let options = Options { role: Some("app\"s".to_owned()), ..Options::default() };
+
+        assert_eq!(settings(&options), vec![r#"SET LOCAL ROLE "app""s""#]);
+        assert_eq!(set_role(None), "SET LOCAL ROLE NONE");
+    }
+
+    #[test]
+    fn test_change_settings() {
+        let setting = |name: &str, value: &str| Setting {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        };
+        let from = [setting("app.tenant_id", "42"), setting("app.user_id", "7")];
+        let to = [setting("app.tenant_id", "42"), setting("statement_timeout", "it's")];
+
+        assert_eq!(
+            change_settings(&from, &to),
+            vec!["SET LOCAL app.user_id TO DEFAULT", "SET LOCAL statement_timeout = 'it''s'"],
+        );
+        assert!(change_settings(&to, &to).is_empty());
+    }
 }
@@ -0,0 +1,315 @@
+//! Loads data files into MySQL or MariaDB, with the `mysql` feature.
+
+use super::backend::{interval_parts, Backend, Param, TableName};
+use super::conninfo;
+use super::error::{ClientError, LoadError};
+use super::report::RecordRow;
+use super::LoadResult;
+use crate::parser::nodes::TimeOffset;
+use crate::{IsolationLevel, Options};
+use mysql::consts::ColumnType;
+use mysql::prelude::Queryable;
+use mysql::{Conn, Opts, OptsBuilder, Params, Row, Value as MySqlValue};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The units of the intervals that times can be offset by, which are the singular
+/// forms of the units Postgres accepts that MySQL also has.
+const INTERVAL_UNITS: [&str; 9] = [
+    "MICROSECOND",
+    "SECOND",
+    "MINUTE",
+    "HOUR",
+    "DAY",
+    "WEEK",
+    "MONTH",
+    "QUARTER",
+    "YEAR",
+];
+
+pub(crate) struct MySql {
+    conn: Conn,
+    /// The names of each table's primary key columns, once looked up
+    primary_keys: HashMap<TableName, Vec<String>>,
+}
+
+impl Backend for MySql {
+    const NAME: &'static str = "MySQL";
+
+    fn connect(connstr: &str) -> Result<Self, ClientError> {
+        let connstr = conninfo::resolve(connstr);
+
+        // The driver only accepts `mysql://` URIs, which MariaDB's are otherwise the same as
+        let url = match connstr.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("mariadb") => format!("mysql://{}", rest),
+            _ => connstr,
+        };
+        let opts = Opts::from_url(&url).map_err(ClientError::config_error)?;
+        let timeout = opts.get_tcp_connect_timeout().unwrap_or(Duration::new(30, 0));
+        let opts = OptsBuilder::from_opts(opts).tcp_connect_timeout(Some(timeout));
+
+        Ok(Self {
+            conn: Conn::new(opts).map_err(ClientError::connection_error)?,
+            primary_keys: HashMap::new(),
+        })
+    }
+
+    fn quote(identifier: &str) -> String {
+        format!("`{}`", identifier.replace('`', "``"))
+    }
+
+    fn placeholder(_index: usize) -> String {
+        "?".to_owned()
+    }
+
+    /// MySQL has no function for the time the transaction began, so the time the
+    /// first record that needs it is inserted is used throughout the transaction.
+    fn now(offset: Option<&TimeOffset>) -> LoadResult<String> {
+        let (operator, interval) = match offset {
+            Some(TimeOffset::Add(interval)) => ('+', interval),
+            Some(TimeOffset::Subtract(interval)) => ('-', interval),
+            None => return Ok("NOW(6)".to_owned()),
+        };
+        let parts = interval_parts(interval, &INTERVAL_UNITS)
+            .ok_or_else(|| LoadError::unsupported(Self::NAME, format!("the interval `{}`", interval)))?;
+
+        Ok(parts.iter().fold("NOW(6)".to_owned(), |sql, (quantity, unit)| {
+            format!("{} {} INTERVAL {} {}", sql, operator, quantity, unit)
+        }))
+    }
+
+    fn begin(&mut self, options: &Options) -> LoadResult<()> {
+        // Setting the isolation level without `SESSION` only applies to the next transaction
+        if let Some(level) = options.isolation_level {
+            let level = match level {
+                IsolationLevel::ReadCommitted => "READ COMMITTED",
+                IsolationLevel::RepeatableRead => "REPEATABLE READ",
+                IsolationLevel::Serializable => "SERIALIZABLE",
+            };
+
+            self.execute(&format!("SET TRANSACTION ISOLATION LEVEL {}", level))?;
+        }
+
+        self.execute("START TRANSACTION")
+    }
+
+    fn execute(&mut self, statement: &str) -> LoadResult<()> {
+        log::debug!("{}", statement);
+        self.conn.query_drop(statement).map_err(LoadError::backend)
+    }
+
+    fn select(&mut self, query: &str, params: &[Param]) -> LoadResult<Option<String>> {
+        let mut rows: Vec<Row> = self
+            .conn
+            .exec(query, params_of(params))
+            .map_err(LoadError::backend)?;
+
+        if rows.len() != 1 {
+            return Err(LoadError::fragment_rows(query, rows.len()));
+        }
+
+        let mut columns = row_text(rows.remove(0));
+
+        if columns.len() != 1 {
+            return Err(LoadError::fragment_columns(query, columns.len()));
+        }
+
+        Ok(columns.remove(0).1)
+    }
+
+    fn primary_key(&mut self, table: &TableName) -> LoadResult<Vec<String>> {
+        if let Some(primary_key) = self.primary_keys.get(table) {
+            return Ok(primary_key.clone());
+        }
+
+        let primary_key: Vec<String> = self
+            .conn
+            .exec(
+                "
+                SELECT column_name
+                FROM information_schema.key_column_usage
+                WHERE table_schema = COALESCE(?, DATABASE()) AND table_name = ? AND constraint_name = 'PRIMARY'
+                ORDER BY ordinal_position
+                ",
+                (table.schema.clone(), table.table.clone()),
+            )
+            .map_err(LoadError::backend)?;
+
+        self.primary_keys.insert(table.clone(), primary_key.clone());
+
+        Ok(primary_key)
+    }
+
+    /// Inserts the row, which MySQL cannot return, so it is selected again by its
+    /// primary key, using the values given for the key's columns or else the value
+    /// generated for its auto-increment column.
+    fn insert(
+        &mut self,
+        table: &TableName,
+        columns: &[&str],
+        values: &[Param],
+        returning: bool,
+    ) -> LoadResult<Option<RecordRow>> {
+        let qualified_table_name = table.qualified::<Self>();
+        let statement = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            qualified_table_name,
+            columns.iter().map(|c| Self::quote(c)).collect::<Vec<_>>().join(", "),
+            vec!["?"; columns.len()].join(", "),
+        );
+        log::debug!("{}", statement);
+
+        self.conn
+            .exec_drop(&statement, params_of(values))
+            .map_err(LoadError::backend)?;
+
+        if !returning {
+            return Ok(None);
+        }
+
+        let last_insert_id = self.conn.last_insert_id();
+        let primary_key = self.primary_key(table)?;
+
+        if primary_key.is_empty() {
+            return Err(LoadError::unresolved_row(&qualified_table_name, "it has no primary key"));
+        }
+
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+
+        for pk_column in &primary_key {
+            // Column names are case-insensitive in MySQL
+            let given = columns.iter().position(|c| c.eq_ignore_ascii_case(pk_column));
+            let value = match given {
+                Some(index) => mysql_value(&values[index]),
+                None if primary_key.len() == 1 && last_insert_id != 0 => MySqlValue::UInt(last_insert_id),
+                None => {
+                    return Err(LoadError::unresolved_row(
+                        &qualified_table_name,
+                        "its primary key was neither given nor generated",
+                    ));
+                }
+            };
+
+            conditions.push(format!("{} = ?", Self::quote(pk_column)));
+            params.push(value);
+        }
+
+        let query = format!("SELECT * FROM {} WHERE {}", qualified_table_name, conditions.join(" AND "));
+        log::debug!("{}", query);
+
+        let row: Row = self
+            .conn
+            .exec_first(&query, Params::Positional(params))
+            .map_err(LoadError::backend)?
+            .ok_or_else(|| LoadError::unresolved_row(&qualified_table_name, "it no longer exists"))?;
+
+        Ok(Some(row_text(row).into_iter().collect()))
+    }
+}
+
+fn params_of(params: &[Param]) -> Params {
+    match params.is_empty() {
+        true => Params::Empty,
+        false => Params::Positional(params.iter().map(mysql_value).collect()),
+    }
+}
+
+/// Converts a bound value, where text is sent as is for MySQL to convert to the type
+/// of its column, as Postgres does with text parameters, but booleans are sent as the
+/// numbers they are stored as.
+fn mysql_value(param: &Param) -> MySqlValue {
+    match param {
+        Param::Null => MySqlValue::NULL,
+        Param::Bool(b) => MySqlValue::Int(*b as i64),
+        Param::Bytes(bytes) => MySqlValue::Bytes(bytes.clone()),
+        Param::Text(text) => MySqlValue::Bytes(text.clone().into_bytes()),
+    }
+}
+
+/// Returns each column of the row by name with its value as text.
+fn row_text(row: Row) -> Vec<(String, Option<String>)> {
+    let columns = row.columns();
+
+    columns
+        .iter()
+        .zip(row.unwrap())
+        .map(|(column, value)| (column.name_str().into_owned(), text(value, column.column_type())))
+        .collect()
+}
+
+/// Formats a value as MySQL would in its text protocol, so that values returned
+/// from prepared statements are the same as if they had been selected as text.
+fn text(value: MySqlValue, column_type: ColumnType) -> Option<String> {
+    Some(match value {
+        MySqlValue::NULL => return None,
+        MySqlValue::Bytes(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        MySqlValue::Int(n) => n.to_string(),
+        MySqlValue::UInt(n) => n.to_string(),
+        MySqlValue::Float(n) => n.to_string(),
+        MySqlValue::Double(n) => n.to_string(),
+        MySqlValue::Date(year, month, day, ..) if column_type == ColumnType::MYSQL_TYPE_DATE => {
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        }
+        MySqlValue::Date(year, month, day, hour, minute, second, micros) => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}{}",
+            year, month, day, hour, minute, second, fraction(micros),
+        ),
+        MySqlValue::Time(negative, days, hours, minutes, seconds, micros) => format!(
+            "{}{:02}:{:02}:{:02}{}",
+            if negative { "-" } else { "" },
+            days * 24 + hours as u32,
+            minutes,
+            seconds,
+            fraction(micros),
+        ),
+    })
+}
+
+fn fraction(micros: u32) -> String {
+    match micros {
+        0 => String::new(),
+        micros => format!(".{:06}", micros),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_now() {
+        let add = |interval: &str| MySql::now(Some(&TimeOffset::Add(interval.to_owned())));
+
+        assert_eq!(MySql::now(None).unwrap(), "NOW(6)");
+        assert_eq!(add("1 day").unwrap(), "NOW(6) + INTERVAL 1 DAY");
+        assert_eq!(
+            MySql::now(Some(&TimeOffset::Subtract("2 Hours 30 minutes".to_owned()))).unwrap(),
+            "NOW(6) - INTERVAL 2 HOUR - INTERVAL 30 MINUTE",
+        );
+        assert_eq!(add("1 fortnight").unwrap_err().to_string(), "MySQL does not support the interval `1 fortnight`");
+        assert!(add("1.5 days").is_err());
+        assert!(add("1 day 2").is_err());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(MySql::quote("person"), "`person`");
+        assert_eq!(MySql::quote("odd`name"), "`odd``name`");
+    }
+
+    #[test]
+    fn test_text() {
+        use ColumnType::*;
+
+        assert_eq!(text(MySqlValue::NULL, MYSQL_TYPE_NULL), None);
+        assert_eq!(text(MySqlValue::Bytes(b"Kevin".to_vec()), MYSQL_TYPE_VAR_STRING).as_deref(), Some("Kevin"));
+        assert_eq!(text(MySqlValue::Int(-3), MYSQL_TYPE_LONGLONG).as_deref(), Some("-3"));
+        assert_eq!(text(MySqlValue::Date(2024, 2, 9, 0, 0, 0, 0), MYSQL_TYPE_DATE).as_deref(), Some("2024-02-09"));
+        assert_eq!(
+            text(MySqlValue::Date(2024, 2, 9, 13, 5, 0, 250), MYSQL_TYPE_DATETIME).as_deref(),
+            Some("2024-02-09 13:05:00.000250"),
+        );
+        assert_eq!(text(MySqlValue::Time(true, 1, 2, 3, 4, 0), MYSQL_TYPE_TIME).as_deref(), Some("-26:03:04"));
+    }
+}
@@ -0,0 +1,866 @@
+//! Writes the statements that would load data files to a SQL file rather than
+//! executing them, eg. for a migration tool to apply.
+
+use super::ddl::create_table_statements;
+use super::dry_run::{render_delete, render_insert, render_update};
+use super::error::{LoadError, LoadErrorKind};
+use super::params::{bytea_text, unquote_text};
+use super::{
+    change_settings,
+    create_schema_statements,
+    env,
+    expression,
+    hook_statements,
+    primary_key,
+    qualified_table_name,
+    quote,
+    record_key,
+    referenced_column,
+    referenced_columns,
+    search_path_statement,
+    set_role,
+    settings,
+    table_scope,
+    truncate_statement,
+    ConflictAction,
+    Connection,
+    DisabledTriggers,
+    ExpectedCounts,
+    ExplicitColumns,
+    LoadResult,
+    SeededTables,
+};
+use crate::analyzer::ValidatedParseTree;
+use crate::parser::nodes::{
+    Attribute,
+    Expression,
+    HookTiming,
+    Operator,
+    Reference,
+    Setting,
+    StructuralIdentity,
+    StructuralNode,
+    Table,
+    TextSegment,
+    Value,
+};
+use crate::{IsolationLevel, OnConflict, Options};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// What is unsupported when writing statements rather than executing them.
+const WRITING: &str = "Writing statements to a file";
+
+/// Executes the statements generated for data files, which is kept apart from
+/// generating them so that they can be written somewhere instead of executed.
+pub trait Executor {
+    fn execute(&mut self, statement: &str) -> LoadResult<()>;
+}
+
+/// Writes each statement to a SQL file, eg. `seeds.sql`, rather than executing it.
+pub struct SqlFileExecutor {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl SqlFileExecutor {
+    pub fn create(path: &Path) -> LoadResult<Self> {
+        let file = File::create(path).map_err(|e| LoadError::output(path, e))?;
+
+        Ok(Self {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Flushes the statements written so far to the file.
+    pub fn finish(mut self) -> LoadResult<()> {
+        self.file.flush().map_err(|e| LoadError::output(&self.path, e))
+    }
+}
+
+/// Collects the statements, eg. to execute them with a driver other than `postgres`.
+impl Executor for Vec<String> {
+    fn execute(&mut self, statement: &str) -> LoadResult<()> {
+        self.push(statement.to_owned());
+        Ok(())
+    }
+}
+
+impl Executor for SqlFileExecutor {
+    fn execute(&mut self, statement: &str) -> LoadResult<()> {
+        writeln!(self.file, "{};\n", statement).map_err(|e| LoadError::output(&self.path, e))
+    }
+}
+
+/// Writes the statements that would load the tree to the executor, within a single
+/// transaction, one per record.
+///
+/// Unlike a dry run, the statements can be executed as they are. The rows of named
+/// records that other records reference are kept in temporary tables as they are
+/// inserted, which the references select from, while environment variables, files,
+/// random UUIDs, and expressions of them are written as their values. Primary keys
+/// are only known to the database, so those of the tables that [`primary_key_tables`]
+/// returns have to be looked up beforehand.
+pub fn write(
+    executor: &mut impl Executor,
+    tree: ValidatedParseTree,
+    options: &Options,
+    primary_keys: HashMap<String, Vec<String>>,
+) -> LoadResult<()> {
+    executor.execute(&begin_statement(options))?;
+    write_statements(executor, tree, options, primary_keys)?;
+    executor.execute("COMMIT")
+}
+
+/// Writes the statements that load the tree without beginning or committing the
+/// transaction they need to be executed within, eg. for a caller's own transaction.
+/// The temporary tables are dropped afterwards, so that the tree can be loaded again
+/// within the same transaction.
+pub(crate) fn write_statements(
+    executor: &mut impl Executor,
+    tree: ValidatedParseTree,
+    options: &Options,
+    primary_keys: HashMap<String, Vec<String>>,
+) -> LoadResult<()> {
+    // Whether a record is skipped depends on the rows that exist when it is loaded
+    if !options.skip_existing.is_empty() {
+        return Err(LoadError::unsupported(WRITING, "the `skip_existing` option"));
+    }
+
+    let tree = tree.into_inner();
+    let mut writer = Writer {
+        referenced: referenced_columns(&tree),
+        executor,
+        on_conflict: options.on_conflict,
+        fixed_now: options.fixed_now.clone(),
+        captured: HashMap::new(),
+        temp_tables: HashMap::new(),
+        primary_keys,
+        base_role: options.role.clone(),
+        role: None,
+        settings: Vec::new(),
+        explicit_columns: ExplicitColumns::default(),
+        expected_counts: ExpectedCounts::default(),
+        seeded_tables: SeededTables::default(),
+        triggers: DisabledTriggers::new(options),
+    };
+
+    for statement in settings(options) {
+        writer.executor.execute(&statement)?;
+    }
+
+    if let Some(statement) = search_path_statement(&tree) {
+        writer.executor.execute(&statement)?;
+    }
+
+    for statement in create_schema_statements(&tree, options) {
+        writer.executor.execute(&statement)?;
+    }
+
+    for statement in create_table_statements(&tree, options) {
+        writer.executor.execute(&statement)?;
+    }
+
+    for statement in hook_statements(&tree, HookTiming::Before) {
+        writer.executor.execute(&statement)?;
+    }
+
+    if let Some(statement) = truncate_statement(&tree, options) {
+        writer.executor.execute(&statement)?;
+    }
+
+    let after = hook_statements(&tree, HookTiming::After);
+
+    for node in tree.nodes {
+        match node {
+            StructuralNode::Schema(schema) => {
+                for table in &schema.nodes {
+                    writer.write_table(Some(&schema.identity), table).map_err(|e| e.at(table.position))?;
+                }
+            }
+            StructuralNode::Table(table) => {
+                writer.write_table(None, &table).map_err(|e| e.at(table.position))?;
+            }
+        }
+    }
+
+    writer.switch_role(None)?;
+    writer.switch_settings(&[])?;
+
+    for statement in writer.triggers.enable() {
+        writer.executor.execute(&statement)?;
+    }
+
+    if options.reset_sequences {
+        for statement in writer.explicit_columns.statements() {
+            writer.executor.execute(&statement)?;
+        }
+    }
+
+    if options.refresh_matviews {
+        if let Some(statement) = writer.seeded_tables.statement() {
+            writer.executor.execute(&statement)?;
+        }
+    }
+
+    for statement in after {
+        writer.executor.execute(&statement)?;
+    }
+
+    for statement in writer.expected_counts.statements() {
+        writer.executor.execute(&statement)?;
+    }
+
+    let mut temp_tables: Vec<_> = writer.temp_tables.into_values().collect();
+    temp_tables.sort();
+
+    if !temp_tables.is_empty() {
+        writer.executor.execute(&format!("DROP TABLE {}", temp_tables.join(", ")))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the qualified names of the tables whose primary keys are needed to write the
+/// tree's statements, which are those of records that other records reference themselves
+/// and those upserted into without declaring their own conflict targets.
+pub fn primary_key_tables(tree: &ValidatedParseTree, options: &Options) -> Vec<String> {
+    let mut record_tables = HashMap::new();
+    let mut tables = Vec::new();
+
+    for (schema, table) in tables_of(tree) {
+        let qualified_table_name = qualified_table_name(schema, table);
+        let table_scope = table_scope(schema, table);
+        let mut needed = Vec::new();
+
+        if options.on_conflict == OnConflict::Update && table.conflict_target.is_none() && !table.nodes.is_empty() {
+            needed.push(qualified_table_name.clone());
+        }
+
+        for record in &table.nodes {
+            for attribute in &record.nodes {
+                for refval in attribute.value.references() {
+                    if matches!(refval, Reference::ColumnLevel(_)) || referenced_column(attribute, refval).is_some() {
+                        continue;
+                    }
+
+                    let key = record_key(&table_scope, refval).unwrap();
+                    needed.extend(record_tables.get(&key).cloned());
+                }
+            }
+
+            if let Some(name) = &record.name {
+                record_tables.insert(format!("{}.{}", table_scope, name), qualified_table_name.clone());
+            }
+        }
+
+        for table in needed {
+            if !tables.contains(&table) {
+                tables.push(table);
+            }
+        }
+    }
+
+    tables
+}
+
+/// Looks up the quoted names of each table's primary key columns, which are empty for
+/// tables without primary keys.
+pub(crate) async fn primary_keys<C: Connection>(
+    connection: &mut C,
+    tables: &[String],
+) -> LoadResult<HashMap<String, Vec<String>>> {
+    let mut primary_keys = HashMap::new();
+
+    for table in tables {
+        let columns = match primary_key(connection, table).await {
+            Ok(columns) => columns,
+            Err(LoadError { kind: LoadErrorKind::NoPrimaryKey { .. }, .. }) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        primary_keys.insert(table.clone(), columns);
+    }
+
+    Ok(primary_keys)
+}
+
+/// Returns every table in the tree in the order they were declared, along with the
+/// identity of its schema, if any.
+fn tables_of(tree: &ValidatedParseTree) -> Vec<(Option<&StructuralIdentity>, &Table)> {
+    let mut tables = Vec::new();
+
+    for node in &tree.inner().nodes {
+        match node {
+            StructuralNode::Schema(schema) => tables.extend(schema.nodes.iter().map(|t| (Some(&schema.identity), t))),
+            StructuralNode::Table(table) => tables.push((None, &**table)),
+        }
+    }
+
+    tables
+}
+
+struct Writer<'a, E: Executor> {
+    executor: &'a mut E,
+    on_conflict: OnConflict,
+    /// The time `now()` is instead, if fixed with the `fixed_now` option
+    fixed_now: Option<String>,
+    /// The columns that other records reference of each record that is referenced, by key
+    referenced: HashMap<String, HashSet<String>>,
+    /// The qualified name of the table of each record whose row has been kept, by key
+    captured: HashMap<String, String>,
+    /// The temporary table that the rows of each table's referenced records are kept in,
+    /// by qualified table name, once created
+    temp_tables: HashMap<String, String>,
+    /// The quoted names of the primary key columns of each table that needs them
+    primary_keys: HashMap<String, Vec<String>>,
+    /// The role given with the `role` option, which tables without roles of their own
+    /// are loaded as
+    base_role: Option<String>,
+    /// The role of the table last written, if it declared one
+    role: Option<String>,
+    /// The settings of the table last written
+    settings: Vec<Setting>,
+    explicit_columns: ExplicitColumns,
+    expected_counts: ExpectedCounts,
+    seeded_tables: SeededTables,
+    triggers: DisabledTriggers,
+}
+
+impl<E: Executor> Writer<'_, E> {
+    fn write_table(&mut self, schema: Option<&StructuralIdentity>, table: &Table) -> LoadResult<()> {
+        let qualified_table_name = qualified_table_name(schema, table);
+        let table_scope = table_scope(schema, table);
+        let target = table
+            .conflict_target
+            .as_ref()
+            .map(|columns| columns.iter().map(|c| quote(c)).collect());
+
+        self.expected_counts.add(&qualified_table_name, table);
+
+        if table.nodes.is_empty() {
+            return Ok(());
+        }
+
+        // Triggers are disabled as the base role, as when loading
+        if let Some(statement) = self.triggers.disable(&qualified_table_name) {
+            self.switch_role(None)?;
+            self.executor.execute(&statement)?;
+        }
+
+        self.switch_role(table.role.as_deref())?;
+        self.switch_settings(&table.settings)?;
+        self.explicit_columns.add(&qualified_table_name, table);
+        self.seeded_tables.add(&qualified_table_name, table);
+
+        let on_conflict = match (target, self.on_conflict) {
+            (target, OnConflict::Ignore) => ConflictAction::Ignore { target, keys: Vec::new() },
+            (Some(target), _) => ConflictAction::Update(target),
+            (None, OnConflict::Error) => ConflictAction::Error,
+            (None, OnConflict::Update) => match self.primary_key(&qualified_table_name) {
+                primary_key if primary_key.is_empty() => return Err(LoadError::no_primary_key(&qualified_table_name)),
+                primary_key => ConflictAction::Update(primary_key),
+            },
+        };
+
+        for record in &table.nodes {
+            let attributes = record
+                .nodes
+                .iter()
+                .map(|attribute| {
+                    Ok(Attribute {
+                        value: self
+                            .lower(&table_scope, attribute, &attribute.value)
+                            .map_err(|e| e.in_attribute(attribute))?,
+                        ..attribute.clone()
+                    })
+                })
+                .collect::<LoadResult<Vec<_>>>()?;
+            let fixed_now = self.fixed_now.as_deref();
+            let insert = match &record.update_where {
+                _ if record.delete => render_delete(&qualified_table_name, &attributes, fixed_now),
+                Some(conditions) => render_update(&qualified_table_name, &attributes, conditions, fixed_now),
+                None => render_insert(&qualified_table_name, &attributes, &on_conflict, fixed_now),
+            };
+
+            let statement = match &record.name {
+                Some(name) if self.referenced.contains_key(&format!("{}.{}", table_scope, name)) => {
+                    let key = format!("{}.{}", table_scope, name);
+                    self.capture(&qualified_table_name, key, insert, &on_conflict)
+                        .map_err(|e| e.at(record.position))?
+                }
+                _ => insert,
+            };
+
+            self.executor.execute(&statement)?;
+        }
+
+        Ok(())
+    }
+
+    /// Switches to the role, or without one, back to the base role, unless the last
+    /// table was written as the same role.
+    fn switch_role(&mut self, role: Option<&str>) -> LoadResult<()> {
+        if self.role.as_deref() == role {
+            return Ok(());
+        }
+
+        self.executor.execute(&set_role(role.or(self.base_role.as_deref())))?;
+        self.role = role.map(str::to_owned);
+
+        Ok(())
+    }
+
+    /// Changes the settings from those of the last table written to the next's.
+    fn switch_settings(&mut self, settings: &[Setting]) -> LoadResult<()> {
+        for statement in change_settings(&self.settings, settings) {
+            self.executor.execute(&statement)?;
+        }
+
+        self.settings = settings.to_vec();
+
+        Ok(())
+    }
+
+    /// Wraps the record's insert so that its row is kept in the temporary table for the
+    /// table's referenced records, which is created first if need be.
+    fn capture(
+        &mut self,
+        qualified_table_name: &str,
+        key: String,
+        insert: String,
+        on_conflict: &ConflictAction,
+    ) -> LoadResult<String> {
+        // The existing row that a skipped record conflicts with can only be found by
+        // looking up the table's unique keys, which the loader does but this cannot
+        if matches!(on_conflict, ConflictAction::Ignore { .. }) {
+            let feature = format!("referencing records that are skipped if they conflict, such as `{}`", key);
+            return Err(LoadError::unsupported(WRITING, feature));
+        }
+
+        if !self.temp_tables.contains_key(qualified_table_name) {
+            let temp_table = format!("pg_temp.{}", quote(&format!("hldr:{}", qualified_table_name.replace('"', ""))));
+
+            self.executor.execute(&format!(
+                "CREATE TEMPORARY TABLE {} ON COMMIT DROP AS SELECT NULL::text AS hldr_record, * FROM {} WITH NO DATA",
+                temp_table, qualified_table_name,
+            ))?;
+            self.temp_tables.insert(qualified_table_name.to_owned(), temp_table);
+        }
+
+        let statement = format!(
+            "WITH inserted AS ({} RETURNING *) INSERT INTO {} SELECT {}, * FROM inserted",
+            insert,
+            self.temp_tables[qualified_table_name],
+            literal(&key),
+        );
+        self.captured.insert(key, qualified_table_name.to_owned());
+
+        Ok(statement)
+    }
+
+    /// Replaces the values that the loader evaluates itself with the values it would
+    /// evaluate them to, and references to other records with queries of their rows,
+    /// so that the value can be rendered just as it is for dry runs.
+    fn lower(&mut self, table_scope: &str, attribute: &Attribute, value: &Value) -> LoadResult<Value> {
+        Ok(match value {
+            Value::EnvVar(name) => Value::Text(literal(&env::var(name)?)),
+            Value::Expression(expression) => {
+                let left = self.lower(table_scope, attribute, &expression.left)?;
+                let right = self.lower(table_scope, attribute, &expression.right)?;
+
+                match (known(&left), known(&right)) {
+                    (Some(left), Some(right)) => evaluate(attribute, left, expression.operator, right)?,
+                    _ => Value::Expression(Box::new(Expression { left, operator: expression.operator, right })),
+                }
+            }
+            Value::File(path) => {
+                let bytes = fs::read(path).map_err(|e| LoadError::file(path, e))?;
+                Value::Hex(bytea_text(&bytes)[2..].to_owned())
+            }
+            Value::InterpolatedText(segments) => {
+                let mut lowered = Vec::new();
+
+                for segment in segments {
+                    lowered.push(match segment {
+                        TextSegment::Literal(literal) => TextSegment::Literal(env::interpolate(literal)?),
+                        TextSegment::Value(value) => TextSegment::Value(self.lower(table_scope, attribute, value)?),
+                    });
+                }
+
+                Value::InterpolatedText(lowered)
+            }
+            Value::InterpolatedSqlFragment(segments) => {
+                let mut lowered = Vec::new();
+
+                for segment in segments {
+                    lowered.push(match segment {
+                        TextSegment::Literal(sql) => TextSegment::Literal(sql.clone()),
+                        TextSegment::Value(value) => TextSegment::Value(self.lower(table_scope, attribute, value)?),
+                    });
+                }
+
+                Value::InterpolatedSqlFragment(lowered)
+            }
+            Value::RandomUuid => Value::Text(literal(&Uuid::new_v4().to_string())),
+            Value::Reference(Reference::ColumnLevel(_)) => value.clone(),
+            Value::Reference(refval) => Value::SqlFragment(self.select_reference(table_scope, attribute, refval)?),
+            Value::Text(text) => Value::Text(literal(&env::interpolate(&unquote_text(text))?)),
+            value => value.clone(),
+        })
+    }
+
+    /// Returns the query, without `SELECT`, of the referenced column of the kept row of
+    /// the record being referenced.
+    fn select_reference(&mut self, table_scope: &str, attribute: &Attribute, refval: &Reference) -> LoadResult<String> {
+        let key = record_key(table_scope, refval).unwrap();
+        let qualified_table_name = self
+            .captured
+            .get(&key)
+            .unwrap_or_else(|| panic!("no row kept for record {}", key))
+            .clone();
+
+        let column = match referenced_column(attribute, refval) {
+            Some(column) => quote(&column),
+            None => {
+                let primary_key = self.primary_key(&qualified_table_name);
+
+                if primary_key.is_empty() {
+                    return Err(LoadError::unresolved_primary_key(&key, "its table has no primary key"));
+                }
+
+                if primary_key.len() > 1 {
+                    return Err(LoadError::unresolved_primary_key(&key, "its table's primary key has more than one column"));
+                }
+
+                primary_key[0].clone()
+            }
+        };
+
+        Ok(format!(
+            "{} FROM {} WHERE hldr_record = {}",
+            column,
+            self.temp_tables[&qualified_table_name],
+            literal(&key),
+        ))
+    }
+
+    fn primary_key(&self, qualified_table_name: &str) -> Vec<String> {
+        self.primary_keys
+            .get(qualified_table_name)
+            .unwrap_or_else(|| panic!("primary key of table {} not looked up", qualified_table_name))
+            .clone()
+    }
+}
+
+fn begin_statement(options: &Options) -> String {
+    let level = match options.isolation_level {
+        Some(IsolationLevel::ReadCommitted) => "READ COMMITTED",
+        Some(IsolationLevel::RepeatableRead) => "REPEATABLE READ",
+        Some(IsolationLevel::Serializable) => "SERIALIZABLE",
+        None => return "BEGIN".to_owned(),
+    };
+
+    format!("BEGIN ISOLATION LEVEL {}", level)
+}
+
+/// Returns the text of a lowered value that is known without querying the database,
+/// as the loader evaluates it within expressions, or `None` if it is not known.
+fn known(value: &Value) -> Option<Option<String>> {
+    match value {
+        Value::Hex(digits) => Some(Some(format!("\\x{}", digits))),
+        Value::Json(json) => Some(Some(json.clone())),
+        Value::Null => Some(None),
+        Value::Number(n) => Some(Some(n.clone())),
+        Value::Text(text) => Some(Some(unquote_text(text))),
+        Value::Uuid5 { namespace, name } => Some(Some(Uuid::new_v5(namespace, name.as_bytes()).to_string())),
+        _ => None,
+    }
+}
+
+/// Evaluates the expression of known values as the loader would.
+fn evaluate(attribute: &Attribute, left: Option<String>, operator: Operator, right: Option<String>) -> LoadResult<Value> {
+    let evaluated = format!(
+        "{} {} {}",
+        left.as_deref().unwrap_or("NULL"),
+        operator,
+        right.as_deref().unwrap_or("NULL"),
+    );
+    let value = expression::evaluate(left, operator, right)
+        .map_err(|reason| LoadError::invalid_expression(&attribute.name, &evaluated, reason))?;
+
+    Ok(match value {
+        Some(text) if operator == Operator::Concat => Value::Text(literal(&text)),
+        Some(number) => Value::Number(number),
+        None => Value::Null,
+    })
+}
+
+/// Quotes the text as a SQL string literal.
+fn literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyzer, lexer, parser};
+    use pretty_assertions::assert_eq;
+
+    fn analyzed(input: &str) -> ValidatedParseTree {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        analyzer::analyze(parser::parse(tokens.into_iter()).unwrap()).unwrap()
+    }
+
+    fn written(input: &str, options: &Options) -> LoadResult<Vec<String>> {
+        let mut statements = Vec::new();
+        write(&mut statements, analyzed(input), options, HashMap::new())?;
+
+        Ok(statements)
+    }
+
+    #[test]
+    fn test_write() {
+        let input = "
+            schema app (
+              table person (
+                kevin (name 'Kevin', nick @name, age 30 + 2)
+                bob (name 'Bob')
+              )
+            )
+            table pet (
+              (name 'Rex', owner_name @app.person.kevin.name, tag 'pet-{@app.person.kevin.age}')
+              (name 'It''s', note `upper({@app.person.kevin.nick})`)
+            )
+        ";
+        let options = Options { isolation_level: Some(IsolationLevel::Serializable), ..Options::default() };
+
+        assert_eq!(
+            written(input, &options).unwrap(),
+            vec![
+                "BEGIN ISOLATION LEVEL SERIALIZABLE",
+                r#"CREATE TEMPORARY TABLE pg_temp."hldr:app.person" ON COMMIT DROP AS SELECT NULL::text AS hldr_record, * FROM "app"."person" WITH NO DATA"#,
+                r#"WITH inserted AS (INSERT INTO "app"."person" ("name", "nick", "age") VALUES ('Kevin', 'Kevin', 32) RETURNING *) INSERT INTO pg_temp."hldr:app.person" SELECT 'app.person.kevin', * FROM inserted"#,
+                r#"INSERT INTO "app"."person" ("name") VALUES ('Bob')"#,
+                r#"INSERT INTO "pet" ("name", "owner_name", "tag") VALUES ('Rex', (SELECT "name" FROM pg_temp."hldr:app.person" WHERE hldr_record = 'app.person.kevin'), concat('pet-', (SELECT "age" FROM pg_temp."hldr:app.person" WHERE hldr_record = 'app.person.kevin')))"#,
+                r#"INSERT INTO "pet" ("name", "note") VALUES ('It''s', (SELECT upper((SELECT "nick" FROM pg_temp."hldr:app.person" WHERE hldr_record = 'app.person.kevin'))))"#,
+                r#"DROP TABLE pg_temp."hldr:app.person""#,
+                "COMMIT",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_write_null_interpolation() {
+        let input = "
+            table person (
+              kevin (name 'Kevin', middle null)
+            )
+            table pet (
+              (tag 'x{@person.kevin.middle}y')
+            )
+        ";
+
+        assert_eq!(
+            written(input, &Options::default()).unwrap()[3],
+            r#"INSERT INTO "pet" ("tag") VALUES (concat('x', (SELECT "middle" FROM pg_temp."hldr:person" WHERE hldr_record = 'person.kevin'), 'y'))"#,
+        );
+    }
+
+    #[test]
+    fn test_write_conflicts() {
+        let input = "
+            table person conflict (email) (
+              kevin (email 'kevin@example.com')
+            )
+            table pet (
+              (owner_email @person.kevin.email)
+            )
+        ";
+
+        let statements = written(input, &Options::default()).unwrap();
+        assert_eq!(
+            statements[2],
+            r#"WITH inserted AS (INSERT INTO "person" ("email") VALUES ('kevin@example.com') ON CONFLICT ("email") DO UPDATE SET "email" = EXCLUDED."email" RETURNING *) INSERT INTO pg_temp."hldr:person" SELECT 'person.kevin', * FROM inserted"#,
+        );
+
+        let options = Options { on_conflict: OnConflict::Ignore, ..Options::default() };
+        assert_eq!(
+            written(input, &options).unwrap_err().to_string(),
+            "Writing statements to a file does not support referencing records that are skipped if they conflict, such as `person.kevin`",
+        );
+    }
+
+    #[test]
+    fn test_write_roles() {
+        let input = "
+            schema app as role owner (
+              table person (
+                (name 'Kevin')
+              )
+              table pet as role \"Pet Owner\" (
+                (name 'Rex')
+              )
+            )
+            table audit (
+              (note 'seeded')
+            )
+        ";
+        let options = Options { role: Some("seeder".to_owned()), ..Options::default() };
+
+        assert_eq!(
+            written(input, &options).unwrap(),
+            vec![
+                "BEGIN",
+                r#"SET LOCAL ROLE "seeder""#,
+                r#"SET LOCAL ROLE "owner""#,
+                r#"INSERT INTO "app"."person" ("name") VALUES ('Kevin')"#,
+                r#"SET LOCAL ROLE "Pet Owner""#,
+                r#"INSERT INTO "app"."pet" ("name") VALUES ('Rex')"#,
+                r#"SET LOCAL ROLE "seeder""#,
+                r#"INSERT INTO "audit" ("note") VALUES ('seeded')"#,
+                "COMMIT",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_write_reset_sequences() {
+        let input = "
+            table person (
+              (id 1, name 'Kevin')
+              (id 2, name 'Bob')
+            )
+            table pet (
+              (name 'Rex')
+            )
+        ";
+        let options = Options { reset_sequences: true, ..Options::default() };
+        let statements = written(input, &options).unwrap();
+
+        assert_eq!(
+            statements[statements.len() - 4..],
+            [
+                r#"SELECT setval(seq::regclass, (SELECT max("id"::text::bigint) FROM "person")) FROM pg_get_serial_sequence('"person"', 'id') AS seq WHERE seq IS NOT NULL"#,
+                r#"SELECT setval(seq::regclass, (SELECT max("name"::text::bigint) FROM "person")) FROM pg_get_serial_sequence('"person"', 'name') AS seq WHERE seq IS NOT NULL"#,
+                r#"SELECT setval(seq::regclass, (SELECT max("name"::text::bigint) FROM "pet")) FROM pg_get_serial_sequence('"pet"', 'name') AS seq WHERE seq IS NOT NULL"#,
+                "COMMIT",
+            ],
+        );
+        assert!(!written(input, &Options::default()).unwrap().iter().any(|s| s.contains("setval")));
+    }
+
+    #[test]
+    fn test_write_refresh_matviews() {
+        let input = "
+            table person (
+              (name 'Kevin')
+            )
+            table pet ()
+            schema zoo (
+              table animal ( (name 'Bob') )
+            )
+        ";
+        let options = Options { refresh_matviews: true, ..Options::default() };
+        let statements = written(input, &options).unwrap();
+        let statement = &statements[statements.len() - 2];
+
+        assert!(statement.starts_with("DO $$ DECLARE matview regclass;"));
+        assert!(statement.contains(r#"d.refobjid IN ('"person"'::regclass, '"zoo"."animal"'::regclass)"#));
+        assert!(!written(input, &Options::default()).unwrap().iter().any(|s| s.contains("matview")));
+        assert!(!written("table pet ()", &options).unwrap().iter().any(|s| s.contains("matview")));
+    }
+
+    #[test]
+    fn test_write_expected_rows() {
+        let input = "
+            table person (
+              expect 2 rows
+              (name 'Kevin')
+            )
+            table pet (expect 0 rows)
+        ";
+        let statements = written(input, &Options::default()).unwrap();
+
+        assert_eq!(
+            statements[statements.len() - 3..],
+            [
+                r#"DO $$ DECLARE found bigint := (SELECT count(*) FROM "person"); BEGIN IF found <> 2 THEN RAISE EXCEPTION 'expected 2 rows in table "person" once loaded, found %', found; END IF; END $$"#,
+                r#"DO $$ DECLARE found bigint := (SELECT count(*) FROM "pet"); BEGIN IF found <> 0 THEN RAISE EXCEPTION 'expected 0 rows in table "pet" once loaded, found %', found; END IF; END $$"#,
+                "COMMIT",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_write_hooks() {
+        let input = "
+            before `SET CONSTRAINTS ALL DEFERRED`
+            table person (
+              expect 1 row
+              (name 'Kevin')
+            )
+            after `ANALYZE person`
+        ";
+        let statements = written(input, &Options::default()).unwrap();
+
+        assert_eq!(statements[..2], ["BEGIN", "SET CONSTRAINTS ALL DEFERRED"]);
+        assert_eq!(
+            statements[statements.len() - 3..],
+            [
+                "ANALYZE person",
+                r#"DO $$ DECLARE found bigint := (SELECT count(*) FROM "person"); BEGIN IF found <> 1 THEN RAISE EXCEPTION 'expected 1 rows in table "person" once loaded, found %', found; END IF; END $$"#,
+                "COMMIT",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_write_primary_keys() {
+        let input = "
+            schema app (
+              table person (
+                kevin (name 'Kevin')
+              )
+            )
+            table pet (
+              (owner_id @app.person.kevin)
+            )
+        ";
+        let primary_keys = HashMap::from([(r#""app"."person""#.to_owned(), vec![r#""id""#.to_owned()])]);
+        let mut statements = Vec::new();
+
+        write(&mut statements, analyzed(input), &Options::default(), primary_keys).unwrap();
+        assert_eq!(
+            statements[3],
+            r#"INSERT INTO "pet" ("owner_id") VALUES ((SELECT "id" FROM pg_temp."hldr:app.person" WHERE hldr_record = 'app.person.kevin'))"#,
+        );
+
+        let mut statements = Vec::new();
+        let primary_keys = HashMap::from([(r#""app"."person""#.to_owned(), Vec::new())]);
+        assert_eq!(
+            write(&mut statements, analyzed(input), &Options::default(), primary_keys).unwrap_err().to_string(),
+            "could not reference the primary key of record `app.person.kevin`, as its table has no primary key",
+        );
+    }
+
+    #[test]
+    fn test_primary_key_tables() {
+        let options = Options::default();
+        let upsert = Options { on_conflict: OnConflict::Update, ..Options::default() };
+        let tables = |input: &str, options: &Options| primary_key_tables(&analyzed(input), options);
+
+        assert!(tables("table person ( kevin (name 'Kevin') )", &options).is_empty());
+        assert_eq!(tables("table person ( kevin (name 'Kevin') )", &upsert), vec![r#""person""#]);
+        assert!(tables("table person conflict (name) ( (name 'Kevin') )", &upsert).is_empty());
+        assert_eq!(
+            tables("schema app ( table person ( kevin (name 'Kevin') ) ) table pet ( (owner_id @app.person.kevin) )", &options),
+            vec![r#""app"."person""#],
+        );
+        assert!(tables("table person ( kevin (name 'Kevin') ) table pet ( (name @person.kevin.name) )", &options).is_empty());
+    }
+}
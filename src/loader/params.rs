@@ -0,0 +1,89 @@
+use bytes::BytesMut;
+use postgres::types::{to_sql_checked, Format, IsNull, ToSql, Type};
+use std::error::Error;
+use std::fmt;
+
+/// A bind parameter that is always sent to the database in text format, which
+/// lets Postgres parse it with the input function of whatever type it infers
+/// for the parameter, just as it would for an untyped quoted literal.
+#[derive(Clone)]
+pub(super) struct TextParam(pub Option<String>);
+
+/// Renders the value as the literal it is parsed like, eg. in the statements logged
+/// with the `log_sql` option.
+impl fmt::Debug for TextParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            Some(value) => write!(f, "'{}'", value.replace('\'', "''")),
+            None => write!(f, "NULL"),
+        }
+    }
+}
+
+impl ToSql for TextParam {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match &self.0 {
+            Some(value) => {
+                out.extend_from_slice(value.as_bytes());
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn encode_format(&self, _ty: &Type) -> Format {
+        Format::Text
+    }
+
+    to_sql_checked!();
+}
+
+/// Converts a text literal as stored by the lexer, ie. still wrapped in single
+/// quotes and with embedded quotes doubled, into its raw value.
+pub(super) fn unquote_text(text: &str) -> String {
+    let inner = text
+        .strip_prefix('\'')
+        .and_then(|t| t.strip_suffix('\''))
+        .unwrap_or(text);
+
+    inner.replace("''", "'")
+}
+
+/// Converts binary data into the hex format of `bytea` text, eg. `\xdeadbeef`.
+pub(super) fn bytea_text(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    let mut text = String::with_capacity(2 + bytes.len() * 2);
+    text.push_str("\\x");
+
+    for byte in bytes {
+        text.push(DIGITS[(byte >> 4) as usize] as char);
+        text.push(DIGITS[(byte & 0xf) as usize] as char);
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bytea_text, unquote_text};
+
+    #[test]
+    fn test_bytea_text() {
+        assert_eq!(bytea_text(&[]), "\\x");
+        assert_eq!(bytea_text(&[0xde, 0xad, 0x00, 0x0f]), "\\xdead000f");
+    }
+
+    #[test]
+    fn test_unquote_text() {
+        assert_eq!(unquote_text("''"), "");
+        assert_eq!(unquote_text("'hello'"), "hello");
+        assert_eq!(unquote_text("'isn''t'"), "isn't");
+        assert_eq!(unquote_text("'back\\slash'"), "back\\slash");
+        assert_eq!(unquote_text("'multi\nline'"), "multi\nline");
+    }
+}
@@ -0,0 +1,126 @@
+use super::{qualified_table_name, truncated_tables};
+use crate::analyzer::ValidatedParseTree;
+use crate::parser::nodes::{StructuralIdentity, StructuralNode, Table};
+use crate::Options;
+use std::fmt;
+
+/// What loading a tree would do, worked out from the tree alone before anything
+/// is executed, eg. to confirm the changes before committing them.
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    /// The qualified names of the tables truncated before loading anything
+    pub truncated: Vec<String>,
+    /// The tables that rows would be inserted into, in the order they are first loaded
+    pub tables: Vec<TablePlan>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TablePlan {
+    pub qualified_table_name: String,
+    /// The number of records declared for the table, which are all inserted unless
+    /// they conflict with existing rows and conflicts are ignored
+    pub rows: usize,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.truncated.is_empty() {
+            writeln!(f, "Truncate {}", self.truncated.join(", "))?;
+        }
+
+        for table in &self.tables {
+            let plural = if table.rows == 1 { "" } else { "s" };
+            writeln!(f, "Insert {} row{} into {}", table.rows, plural, table.qualified_table_name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Plans what loading the tree with the options would do.
+pub fn plan(tree: &ValidatedParseTree, options: &Options) -> Plan {
+    let tree = tree.inner();
+    let mut plan = Plan {
+        truncated: truncated_tables(tree, options),
+        tables: Vec::new(),
+    };
+    let mut add = |schema: Option<&StructuralIdentity>, table: &Table| {
+        let qualified_table_name = qualified_table_name(schema, table);
+
+        // Tables can be declared more than once, which are loaded separately but
+        // planned together
+        match plan.tables.iter_mut().find(|t| t.qualified_table_name == qualified_table_name) {
+            Some(existing) => existing.rows += table.nodes.len(),
+            None => plan.tables.push(TablePlan {
+                qualified_table_name,
+                rows: table.nodes.len(),
+            }),
+        }
+    };
+
+    for node in &tree.nodes {
+        match node {
+            StructuralNode::Schema(schema) => {
+                for table in &schema.nodes {
+                    add(Some(&schema.identity), table);
+                }
+            }
+            StructuralNode::Table(table) => add(None, table),
+        }
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyzer, lexer, parser};
+    use pretty_assertions::assert_eq;
+
+    fn planned(input: &str, options: &Options) -> Plan {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let tree = parser::parse(tokens.into_iter()).unwrap();
+
+        plan(&analyzer::analyze(tree).unwrap(), options)
+    }
+
+    #[test]
+    fn test_plan() {
+        let input = r#"
+            schema s1 (
+                table t1 truncate (
+                    rec1 ()
+                    _ * 3 ()
+                )
+            )
+            table t2 (
+                (col1 1)
+            )
+            table t3 ()
+            table t2 (
+                (col1 2)
+            )
+        "#;
+        let plan = planned(input, &Options::default());
+
+        assert_eq!(
+            plan,
+            Plan {
+                truncated: vec![r#""s1"."t1""#.to_owned()],
+                tables: vec![
+                    TablePlan { qualified_table_name: r#""s1"."t1""#.to_owned(), rows: 4 },
+                    TablePlan { qualified_table_name: r#""t2""#.to_owned(), rows: 2 },
+                    TablePlan { qualified_table_name: r#""t3""#.to_owned(), rows: 0 },
+                ],
+            },
+        );
+        assert_eq!(
+            plan.to_string(),
+            "Truncate \"s1\".\"t1\"\nInsert 4 rows into \"s1\".\"t1\"\nInsert 2 rows into \"t2\"\nInsert 0 rows into \"t3\"\n",
+        );
+
+        let options = Options { truncate: true, ..Options::default() };
+        assert_eq!(planned(input, &options).truncated, vec![r#""s1"."t1""#, r#""t2""#, r#""t3""#]);
+    }
+}
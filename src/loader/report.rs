@@ -0,0 +1,316 @@
+use postgres::Row;
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// The row of a record as returned when it was loaded, with each column's value as text.
+pub type RecordRow = HashMap<String, Option<String>>;
+
+/// What was loaded into each table, in the order the tables were first loaded.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct PlaceReport {
+    /// Whether the transaction was committed, which is only known when hldr manages
+    /// the transaction and so is always `false` when loading within an existing one
+    pub committed: bool,
+    /// How long loading took, from the first statement to the last, excluding
+    /// parsing the data files and committing the transaction
+    #[serde(rename = "duration_ms", serialize_with = "milliseconds")]
+    pub duration: Duration,
+    /// How long the data files took to lex, parse, and analyze before loading, which is
+    /// only known when hldr parses them itself
+    pub timings: Timings,
+    pub tables: Vec<TableReport>,
+    /// The tables that failed to load and were skipped with the `keep_going` option
+    pub skipped: Vec<SkippedTable>,
+}
+
+impl PlaceReport {
+    /// Returns the report for the table, where the schema is `None` for top-level tables.
+    pub fn table(&self, schema: Option<&str>, table: &str) -> Option<&TableReport> {
+        self.tables
+            .iter()
+            .find(|t| t.schema.as_deref() == schema && t.table == table)
+    }
+
+    /// Returns the row of the named record in the table, eg. to look up its generated ID.
+    pub fn record(&self, schema: Option<&str>, table: &str, record: &str) -> Option<&RecordRow> {
+        self.table(schema, table)?.records.get(record)
+    }
+
+    /// Serializes the report as pretty-printed JSON, with records and their
+    /// columns sorted by name.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report should serialize")
+    }
+
+    /// Summarizes how long each stage took and how long each table took to load, eg.
+    /// to tell whether slowness comes from hldr or the database.
+    pub fn timings_summary(&self) -> String {
+        let mut lines = vec![
+            format!("lex      {}", format_duration(self.timings.lex)),
+            format!("parse    {}", format_duration(self.timings.parse)),
+            format!("analyze  {}", format_duration(self.timings.analyze)),
+            format!("load     {}", format_duration(self.duration)),
+        ];
+        let names: Vec<String> = self
+            .tables
+            .iter()
+            .map(|t| match &t.schema {
+                Some(schema) => format!("{}.{}", schema, t.table),
+                None => t.table.clone(),
+            })
+            .collect();
+        let width = names.iter().map(String::len).max().unwrap_or_default();
+
+        for (name, table) in names.iter().zip(&self.tables) {
+            let rows = table.rows_inserted + table.rows_updated + table.rows_deleted;
+            let seconds = table.duration.as_secs_f64();
+            let rate = if seconds > 0.0 { rows as f64 / seconds } else { 0.0 };
+
+            lines.push(format!(
+                "  {:width$}  {}  {} rows  {:.0} rows/s",
+                name,
+                format_duration(table.duration),
+                rows,
+                rate,
+                width = width,
+            ));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Adds the report for a table, merging it with any earlier report for the same
+    /// table, as tables can be declared more than once.
+    pub(super) fn merge(&mut self, report: TableReport) {
+        let existing = self
+            .tables
+            .iter_mut()
+            .find(|t| t.schema == report.schema && t.table == report.table);
+
+        match existing {
+            Some(existing) => {
+                existing.rows_inserted += report.rows_inserted;
+                existing.rows_updated += report.rows_updated;
+                existing.rows_deleted += report.rows_deleted;
+                existing.duration += report.duration;
+                existing.records.extend(report.records);
+            }
+            None => self.tables.push(report),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TableReport {
+    pub schema: Option<String>,
+    pub table: String,
+    /// The number of rows inserted, which includes neither the existing rows updated by
+    /// upserts nor those of records skipped due to conflicts
+    pub rows_inserted: u64,
+    /// The number of existing rows updated by records declared with `update ... where (...)`
+    /// or by upserts that conflicted with them
+    pub rows_updated: u64,
+    /// The number of existing rows deleted by deletes declared with `delete from ... where (...)`
+    pub rows_deleted: u64,
+    /// How long the table's records took to load, including looking up its primary and
+    /// unique keys and selecting the values of its SQL fragments
+    #[serde(rename = "duration_ms", serialize_with = "milliseconds")]
+    pub duration: Duration,
+    /// The rows of the table's named records by record name, except for those that
+    /// are not referenced when bulk loading, since they are loaded without returning
+    /// rows, while those that are referenced only include the columns referenced and
+    /// the primary key
+    #[serde(serialize_with = "sorted_records")]
+    pub records: HashMap<String, RecordRow>,
+}
+
+impl TableReport {
+    pub(super) fn new(schema: Option<String>, table: String) -> Self {
+        Self {
+            schema,
+            table,
+            rows_inserted: 0,
+            rows_updated: 0,
+            rows_deleted: 0,
+            duration: Duration::ZERO,
+            records: HashMap::new(),
+        }
+    }
+}
+
+/// How long each stage before loading took, over every data file.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Timings {
+    #[serde(rename = "lex_ms", serialize_with = "milliseconds")]
+    pub lex: Duration,
+    #[serde(rename = "parse_ms", serialize_with = "milliseconds")]
+    pub parse: Duration,
+    #[serde(rename = "analyze_ms", serialize_with = "milliseconds")]
+    pub analyze: Duration,
+}
+
+/// A table that failed to load, none of whose records were loaded, but which was
+/// skipped rather than failing the whole load.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SkippedTable {
+    pub schema: Option<String>,
+    pub table: String,
+    pub error: String,
+}
+
+/// Formats the duration in milliseconds, eg. `12.5ms`.
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn milliseconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_millis())
+}
+
+fn sorted_records<S: Serializer>(records: &HashMap<String, RecordRow>, serializer: S) -> Result<S::Ok, S::Error> {
+    let sorted: BTreeMap<_, BTreeMap<_, _>> = records.iter().map(|(name, row)| (name, row.iter().collect())).collect();
+    sorted.serialize(serializer)
+}
+
+/// Converts a row returned by the loader, whose columns are all selected as text.
+pub(super) fn record_row(row: &Row) -> RecordRow {
+    row.columns()
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| column.name() != super::INSERTED_COLUMN)
+        .map(|(i, column)| (column.name().to_owned(), row.get(i)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PlaceReport, SkippedTable, TableReport, Timings};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn test_merge() {
+        let mut report = PlaceReport::default();
+        let table = |schema: Option<&str>, rows_inserted, record: &str| {
+            let mut table = TableReport::new(schema.map(str::to_owned), "table1".to_owned());
+            table.rows_inserted = rows_inserted;
+            table.records.insert(record.to_owned(), Default::default());
+            table
+        };
+
+        report.merge(table(None, 1, "record1"));
+        report.merge(table(Some("schema1"), 2, "record2"));
+        report.merge(table(None, 3, "record3"));
+
+        assert_eq!(report.tables.len(), 2);
+
+        let top_level = report.table(None, "table1").unwrap();
+        assert_eq!(top_level.rows_inserted, 4);
+        assert!(top_level.records.contains_key("record1"));
+        assert!(top_level.records.contains_key("record3"));
+
+        assert!(report.record(Some("schema1"), "table1", "record2").is_some());
+        assert!(report.record(Some("schema1"), "table1", "record1").is_none());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut table = TableReport::new(None, "person".to_owned());
+        table.rows_inserted = 2;
+        table.duration = Duration::from_millis(4);
+        table.records.insert(
+            "kevin".to_owned(),
+            [("name", Some("Kevin")), ("id", Some("1")), ("age", None)]
+                .into_iter()
+                .map(|(column, value)| (column.to_owned(), value.map(str::to_owned)))
+                .collect(),
+        );
+
+        let report = PlaceReport {
+            committed: true,
+            duration: Duration::from_micros(12_500),
+            timings: Timings {
+                lex: Duration::from_millis(1),
+                parse: Duration::from_millis(2),
+                analyze: Duration::from_millis(3),
+            },
+            tables: vec![table],
+            skipped: vec![SkippedTable {
+                schema: Some("zoo".to_owned()),
+                table: "pet".to_owned(),
+                error: "it failed".to_owned(),
+            }],
+        };
+
+        assert_eq!(
+            report.to_json(),
+            r#"{
+  "committed": true,
+  "duration_ms": 12,
+  "timings": {
+    "lex_ms": 1,
+    "parse_ms": 2,
+    "analyze_ms": 3
+  },
+  "tables": [
+    {
+      "schema": null,
+      "table": "person",
+      "rows_inserted": 2,
+      "rows_updated": 0,
+      "rows_deleted": 0,
+      "duration_ms": 4,
+      "records": {
+        "kevin": {
+          "age": null,
+          "id": "1",
+          "name": "Kevin"
+        }
+      }
+    }
+  ],
+  "skipped": [
+    {
+      "schema": "zoo",
+      "table": "pet",
+      "error": "it failed"
+    }
+  ]
+}"#,
+        );
+    }
+
+    #[test]
+    fn test_timings_summary() {
+        let table = |schema: Option<&str>, name: &str, rows_inserted, millis| {
+            let mut table = TableReport::new(schema.map(str::to_owned), name.to_owned());
+            table.rows_inserted = rows_inserted;
+            table.duration = Duration::from_millis(millis);
+            table
+        };
+        let report = PlaceReport {
+            duration: Duration::from_micros(30_250),
+            timings: Timings {
+                lex: Duration::from_micros(1_500),
+                parse: Duration::from_millis(2),
+                analyze: Duration::ZERO,
+            },
+            tables: vec![table(None, "person", 100, 20), table(Some("zoo"), "pet", 0, 0)],
+            ..PlaceReport::default()
+        };
+
+        assert_eq!(
+            report.timings_summary(),
+            [
+                "lex      1.5ms",
+                "parse    2.0ms",
+                "analyze  0.0ms",
+                "load     30.2ms",
+                "  person   20.0ms  100 rows  5000 rows/s",
+                "  zoo.pet  0.0ms  0 rows  0 rows/s",
+            ]
+            .join("\n"),
+        );
+    }
+}
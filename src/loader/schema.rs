@@ -0,0 +1,367 @@
+use super::connection::Connection;
+use super::error::LoadError;
+use super::{qualified_table_name, LoadResult};
+use crate::parser::nodes::{Operator, ParseTree, StructuralIdentity, StructuralNode, Table, Value};
+use std::collections::HashMap;
+
+/// A column of a table in the database, with its type as Postgres formats it
+/// and the type's category, eg. `N` for numeric types
+struct Column {
+    type_name: String,
+    category: String,
+}
+
+/// Verifies that every schema, table, and column in the tree exists in the database,
+/// and that literal values are plausibly compatible with their columns' types, so that
+/// all mismatches are reported together before anything is loaded rather than one at
+/// a time as statements fail.
+///
+/// Numbers that Postgres would round to fit their columns are only warned about unless
+/// `strict_numbers` is set.
+pub(super) async fn verify<C: Connection>(connection: &mut C, tree: &ParseTree, strict_numbers: bool) -> LoadResult<()> {
+    let mut verifier = Verifier {
+        connection,
+        strict_numbers,
+        tables: HashMap::new(),
+        mismatches: Vec::new(),
+    };
+
+    for node in &tree.nodes {
+        match node {
+            StructuralNode::Schema(schema) => {
+                if !verifier.schema_exists(&schema.identity.name).await? {
+                    verifier.mismatch(format!("schema `{}` does not exist", schema.identity.name));
+                    continue;
+                }
+
+                for table in &schema.nodes {
+                    verifier.verify_table(Some(&schema.identity), table).await?;
+                }
+            }
+            StructuralNode::Table(table) => verifier.verify_table(None, table).await?,
+        }
+    }
+
+    if verifier.mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(LoadError::schema_mismatch(verifier.mismatches))
+    }
+}
+
+struct Verifier<'a, C: Connection> {
+    connection: &'a mut C,
+    strict_numbers: bool,
+    /// The columns of each table by qualified table name, or `None` if the table does not exist
+    tables: HashMap<String, Option<HashMap<String, Column>>>,
+    mismatches: Vec<String>,
+}
+
+impl<C: Connection> Verifier<'_, C> {
+    /// Records a mismatch unless it has already been, as eg. a missing column is
+    /// likely declared in every record of the table
+    fn mismatch(&mut self, mismatch: String) {
+        if !self.mismatches.contains(&mismatch) {
+            self.mismatches.push(mismatch);
+        }
+    }
+
+    async fn verify_table(&mut self, schema: Option<&StructuralIdentity>, table: &Table) -> LoadResult<()> {
+        let qualified_table_name = qualified_table_name(schema, table);
+
+        if !self.tables.contains_key(&qualified_table_name) {
+            let columns = self.columns(&qualified_table_name).await?;
+            self.tables.insert(qualified_table_name.clone(), columns);
+        }
+
+        let columns = match &self.tables[&qualified_table_name] {
+            Some(columns) => columns,
+            None => {
+                self.mismatch(format!("table {} does not exist", qualified_table_name));
+                return Ok(());
+            }
+        };
+
+        let mut mismatches = Vec::new();
+        let conflict_target = table.conflict_target.iter().flatten();
+        let attributes = table.nodes.iter().flat_map(|r| &r.nodes);
+
+        for name in conflict_target.chain(attributes.clone().map(|a| &a.name)) {
+            if !columns.contains_key(name) {
+                mismatches.push(format!("column `{}` does not exist in table {}", name, qualified_table_name));
+            }
+        }
+
+        for attribute in attributes {
+            if let Some(column) = columns.get(&attribute.name) {
+                if let Some(kind) = incompatible_value(&attribute.value, column) {
+                    mismatches.push(format!(
+                        "column `{}` in table {} has type `{}` but is given {}",
+                        attribute.name, qualified_table_name, column.type_name, kind,
+                    ));
+                } else if let Value::Number(number) = &attribute.value {
+                    let mismatch = match inexact_number(number, &column.type_name) {
+                        Some(Inexact::Rounded) if !self.strict_numbers => {
+                            log::warn!(
+                                "column `{}` in table {} has type `{}`, so `{}` is rounded",
+                                attribute.name, qualified_table_name, column.type_name, number,
+                            );
+                            continue;
+                        }
+                        Some(inexact) => inexact.description(),
+                        None => continue,
+                    };
+
+                    mismatches.push(format!(
+                        "column `{}` in table {} has type `{}` but is given `{}`, {}",
+                        attribute.name, qualified_table_name, column.type_name, number, mismatch,
+                    ));
+                }
+            }
+        }
+
+        for mismatch in mismatches {
+            self.mismatch(mismatch);
+        }
+
+        Ok(())
+    }
+
+    async fn schema_exists(&mut self, schema: &str) -> LoadResult<bool> {
+        let row = self
+            .connection
+            .query_opt("SELECT 1 FROM pg_namespace WHERE nspname = $1", &[&schema])
+            .await
+            .map_err(LoadError::new)?;
+
+        Ok(row.is_some())
+    }
+
+    /// Looks up the table's columns, or `None` if the table does not exist, which uses
+    /// `to_regclass` rather than casting to `regclass` so that a missing table does not
+    /// abort the transaction.
+    async fn columns(&mut self, qualified_table_name: &str) -> LoadResult<Option<HashMap<String, Column>>> {
+        let exists = self
+            .connection
+            .query_opt("SELECT 1 WHERE to_regclass($1::text) IS NOT NULL", &[&qualified_table_name])
+            .await
+            .map_err(LoadError::new)?
+            .is_some();
+
+        if !exists {
+            return Ok(None);
+        }
+
+        let rows = self
+            .connection
+            .query(
+                "
+                SELECT a.attname::text, format_type(a.atttypid, a.atttypmod), t.typcategory::text
+                FROM pg_attribute a
+                JOIN pg_type t ON t.oid = a.atttypid
+                WHERE a.attrelid = to_regclass($1::text) AND a.attnum > 0 AND NOT a.attisdropped
+                ",
+                &[&qualified_table_name],
+            )
+            .await
+            .map_err(LoadError::new)?;
+
+        Ok(Some(
+            rows.iter()
+                .map(|row| {
+                    let column = Column {
+                        type_name: row.get(1),
+                        category: row.get(2),
+                    };
+                    (row.get(0), column)
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Describes the kind of value if it cannot be inserted into the column. Only values
+/// whose types are known before loading are checked, since text and everything else
+/// is bound untyped and left to Postgres to parse as the column's type, and any type
+/// can be assigned to string columns.
+fn incompatible_value(value: &Value, column: &Column) -> Option<&'static str> {
+    let (kind, compatible) = match value {
+        Value::Bool(_) => ("a boolean", column.category == "B"),
+        Value::File(_) | Value::Hex(_) => ("binary data", column.type_name == "bytea"),
+        Value::Expression(e) if e.operator != Operator::Concat => ("a number", column.category == "N"),
+        Value::Json(_) => ("JSON", matches!(column.type_name.as_str(), "json" | "jsonb")),
+        Value::Now(_) => ("a time", column.category == "D"),
+        Value::Number(_) => ("a number", column.category == "N"),
+        Value::RandomUuid | Value::Uuid5 { .. } => ("a UUID", column.type_name == "uuid"),
+        _ => return None,
+    };
+
+    if compatible || column.category == "S" {
+        None
+    } else {
+        Some(kind)
+    }
+}
+
+/// How a number literal does not fit exactly into a numeric column
+#[derive(Debug, PartialEq)]
+enum Inexact {
+    /// Postgres fails to insert the number, as it is beyond the type's range
+    OutOfRange,
+    /// Postgres fails to insert the number, as the type only holds whole numbers
+    Fractional,
+    /// Postgres rounds the number to the type's scale
+    Rounded,
+}
+
+impl Inexact {
+    fn description(&self) -> &'static str {
+        match self {
+            Self::OutOfRange => "which is out of range",
+            Self::Fractional => "which is not a whole number",
+            Self::Rounded => "which would be rounded",
+        }
+    }
+}
+
+/// A number literal's decimal digits before and after its point, without its sign,
+/// underscores, exponent, or insignificant zeros, eg. `-1.25e-1` is negative with
+/// no integer digits and `125` after its point
+struct Decimal {
+    negative: bool,
+    integer: String,
+    fraction: String,
+}
+
+impl Decimal {
+    fn parse(number: &str) -> Option<Self> {
+        let number = number.replace('_', "");
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, number.strip_prefix('+').unwrap_or(&number)),
+        };
+        let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i64>().ok()?),
+            None => (number, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+        let digits = format!("{}{}", integer, fraction);
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        // Limited so that huge exponents cannot allocate huge strings, which is still
+        // far beyond any type's range or scale
+        let point = integer.len() as i64 + exponent.clamp(-2000, 2000);
+        let (integer, fraction) = if point <= 0 {
+            (String::new(), "0".repeat(-point as usize) + &digits)
+        } else if point as usize >= digits.len() {
+            (digits.clone() + &"0".repeat(point as usize - digits.len()), String::new())
+        } else {
+            let (integer, fraction) = digits.split_at(point as usize);
+            (integer.to_owned(), fraction.to_owned())
+        };
+
+        Some(Self {
+            negative,
+            integer: integer.trim_start_matches('0').to_owned(),
+            fraction: fraction.trim_end_matches('0').to_owned(),
+        })
+    }
+}
+
+/// Describes how the number does not fit exactly into a column of the type, if it does
+/// not, which is only known for integer types and numeric types with a precision.
+fn inexact_number(number: &str, type_name: &str) -> Option<Inexact> {
+    let decimal = Decimal::parse(number)?;
+
+    let max: u128 = match type_name {
+        "smallint" => i16::MAX as u128,
+        "integer" => i32::MAX as u128,
+        "bigint" => i64::MAX as u128,
+        _ => {
+            let modifiers = type_name.strip_prefix("numeric(")?.strip_suffix(')')?;
+            let (precision, scale) = modifiers.split_once(',').unwrap_or((modifiers, "0"));
+            let precision: i64 = precision.trim().parse().ok()?;
+            let scale: i64 = scale.trim().parse().ok()?;
+
+            return if decimal.integer.len() as i64 > precision - scale {
+                Some(Inexact::OutOfRange)
+            } else if decimal.fraction.len() as i64 > scale {
+                Some(Inexact::Rounded)
+            } else {
+                None
+            };
+        }
+    };
+
+    let max = max + decimal.negative as u128;
+    let in_range = decimal.integer.is_empty() || decimal.integer.parse::<u128>().is_ok_and(|integer| integer <= max);
+
+    if !in_range {
+        Some(Inexact::OutOfRange)
+    } else if !decimal.fraction.is_empty() {
+        Some(Inexact::Fractional)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{incompatible_value, inexact_number, Column, Inexact};
+    use crate::parser::nodes::Value;
+
+    fn column(type_name: &str, category: &str) -> Column {
+        Column {
+            type_name: type_name.to_owned(),
+            category: category.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_incompatible_value() {
+        let number = Value::Number("1".to_owned());
+
+        assert_eq!(incompatible_value(&number, &column("integer", "N")), None);
+        assert_eq!(incompatible_value(&number, &column("text", "S")), None);
+        assert_eq!(incompatible_value(&number, &column("boolean", "B")), Some("a number"));
+        assert_eq!(incompatible_value(&Value::Bool(true), &column("boolean", "B")), None);
+        assert_eq!(incompatible_value(&Value::Bool(true), &column("integer", "N")), Some("a boolean"));
+        assert_eq!(incompatible_value(&Value::RandomUuid, &column("uuid", "U")), None);
+        assert_eq!(incompatible_value(&Value::RandomUuid, &column("character varying(36)", "S")), None);
+        assert_eq!(incompatible_value(&Value::RandomUuid, &column("integer", "N")), Some("a UUID"));
+        assert_eq!(incompatible_value(&Value::Hex("ff".to_owned()), &column("bytea", "U")), None);
+        assert_eq!(incompatible_value(&Value::Hex("ff".to_owned()), &column("integer", "N")), Some("binary data"));
+        assert_eq!(incompatible_value(&Value::Json("{}".to_owned()), &column("jsonb", "U")), None);
+        assert_eq!(incompatible_value(&Value::Json("{}".to_owned()), &column("integer", "N")), Some("JSON"));
+        assert_eq!(incompatible_value(&Value::Now(None), &column("timestamp with time zone", "D")), None);
+        assert_eq!(incompatible_value(&Value::Now(None), &column("integer", "N")), Some("a time"));
+        assert_eq!(incompatible_value(&Value::Text("'x'".to_owned()), &column("integer", "N")), None);
+        assert_eq!(incompatible_value(&Value::Null, &column("integer", "N")), None);
+    }
+
+    #[test]
+    fn test_inexact_number() {
+        assert_eq!(inexact_number("32767", "smallint"), None);
+        assert_eq!(inexact_number("32768", "smallint"), Some(Inexact::OutOfRange));
+        assert_eq!(inexact_number("-32768", "smallint"), None);
+        assert_eq!(inexact_number("-32769", "smallint"), Some(Inexact::OutOfRange));
+        assert_eq!(inexact_number("3_000_000_000", "integer"), Some(Inexact::OutOfRange));
+        assert_eq!(inexact_number("3e9", "bigint"), None);
+        assert_eq!(inexact_number("1e100", "bigint"), Some(Inexact::OutOfRange));
+        assert_eq!(inexact_number("13.37", "integer"), Some(Inexact::Fractional));
+        assert_eq!(inexact_number("13.00", "integer"), None);
+        assert_eq!(inexact_number("1.5e1", "integer"), None);
+        assert_eq!(inexact_number("1.55e1", "integer"), Some(Inexact::Fractional));
+        assert_eq!(inexact_number("0.0", "integer"), None);
+        assert_eq!(inexact_number("1.005", "numeric(5,2)"), Some(Inexact::Rounded));
+        assert_eq!(inexact_number("999.99", "numeric(5,2)"), None);
+        assert_eq!(inexact_number("1000", "numeric(5,2)"), Some(Inexact::OutOfRange));
+        assert_eq!(inexact_number("-0.001e2", "numeric(5,1)"), None);
+        assert_eq!(inexact_number("1.5", "numeric(3,0)"), Some(Inexact::Rounded));
+        assert_eq!(inexact_number("1.005", "numeric"), None);
+        assert_eq!(inexact_number("1.005", "real"), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Appends every statement executed while loading to the file given with the `log_sql`
+//! option, each with the time it was executed, so that what was done to shared
+//! databases can be audited afterwards.
+
+use super::connection::Connection;
+use super::error::LoadError;
+use super::LoadResult;
+use crate::Options;
+use postgres::types::ToSql;
+use postgres::{Error, Row, SimpleQueryMessage};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A connection that logs each statement before executing it, or simply executes it
+/// without the `log_sql` option.
+pub(crate) struct SqlLog<'a, C> {
+    connection: &'a mut C,
+    file: Option<(PathBuf, File)>,
+    /// The first error writing to the file, which is only returned by [`SqlLog::finish`]
+    /// as the connection's own methods can only fail with database errors
+    error: Option<io::Error>,
+}
+
+impl<'a, C: Connection> SqlLog<'a, C> {
+    pub(crate) fn new(connection: &'a mut C, options: &Options) -> LoadResult<Self> {
+        let file = match &options.log_sql {
+            Some(path) => Some((path.clone(), open(path)?)),
+            None => None,
+        };
+
+        Ok(Self {
+            connection,
+            file,
+            error: None,
+        })
+    }
+
+    /// Logs a statement that is executed other than through the connection, eg. by
+    /// beginning the transaction.
+    pub(crate) fn log(&mut self, statement: &str) {
+        self.write(statement, &[], None);
+    }
+
+    /// Fails if any statement could not be logged.
+    pub(crate) fn finish(self) -> LoadResult<()> {
+        match (self.file, self.error) {
+            (Some((path, _)), Some(error)) => Err(LoadError::sql_log(&path, error)),
+            _ => Ok(()),
+        }
+    }
+
+    fn write(&mut self, statement: &str, params: &[&(dyn ToSql + Sync)], rows: Option<&str>) {
+        if let Some((_, file)) = &mut self.file {
+            if self.error.is_none() {
+                self.error = file.write_all(entry(SystemTime::now(), statement, params, rows).as_bytes()).err();
+            }
+        }
+    }
+}
+
+impl<C: Connection> Connection for SqlLog<'_, C> {
+    async fn execute(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        self.write(query, params, None);
+        self.connection.execute(query, params).await
+    }
+
+    async fn query(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        self.write(query, params, None);
+        self.connection.query(query, params).await
+    }
+
+    async fn query_opt(&mut self, query: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Option<Row>, Error> {
+        self.write(query, params, None);
+        self.connection.query_opt(query, params).await
+    }
+
+    async fn simple_query(&mut self, query: &str) -> Result<Vec<SimpleQueryMessage>, Error> {
+        self.write(query, &[], None);
+        self.connection.simple_query(query).await
+    }
+
+    async fn copy_in(&mut self, query: &str, rows: String) -> Result<u64, LoadError> {
+        self.write(query, &[], Some(&rows));
+        self.connection.copy_in(query, rows).await
+    }
+}
+
+/// Logs a statement that is executed other than through a connection, eg. committing
+/// the transaction, with the `log_sql` option.
+pub(crate) fn log_statement(options: &Options, statement: &str) -> LoadResult<()> {
+    let Some(path) = &options.log_sql else {
+        return Ok(());
+    };
+
+    open(path)?
+        .write_all(entry(SystemTime::now(), statement, &[], None).as_bytes())
+        .map_err(|e| LoadError::sql_log(path, e))
+}
+
+fn open(path: &Path) -> LoadResult<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| LoadError::sql_log(path, e))
+}
+
+/// Renders the statement as it is logged, after a comment with the time it was executed
+/// and followed by comments with its parameters, if any, or by the rows it copies, in the
+/// same format as `psql` scripts.
+fn entry(time: SystemTime, statement: &str, params: &[&(dyn ToSql + Sync)], rows: Option<&str>) -> String {
+    let statement = statement.trim();
+    let terminator = if statement.ends_with(';') { "" } else { ";" };
+    let mut entry = format!("-- {}\n{}{}\n", timestamp(time), statement, terminator);
+
+    for (index, param) in params.iter().enumerate() {
+        entry.push_str(&format!("-- ${} = {:?}\n", index + 1, param));
+    }
+
+    if let Some(rows) = rows {
+        entry.push_str(rows);
+        entry.push_str("\\.\n");
+    }
+
+    entry.push('\n');
+    entry
+}
+
+/// Formats the time in UTC as eg. `2024-01-01T00:00:00.000Z`.
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Converts days since the epoch to a date in the proleptic Gregorian calendar, as
+    // described in http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entry, timestamp};
+    use crate::loader::params::TextParam;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_entry() {
+        let time = |seconds: u64, millis: u64| UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis);
+
+        assert_eq!(timestamp(time(0, 0)), "1970-01-01T00:00:00.000Z");
+        assert_eq!(timestamp(time(951_782_400, 5)), "2000-02-29T00:00:00.005Z");
+        assert_eq!(timestamp(time(1_735_689_599, 999)), "2024-12-31T23:59:59.999Z");
+
+        let name = TextParam(Some("O'Brien".to_owned()));
+        let age = TextParam(None);
+
+        assert_eq!(
+            entry(time(0, 0), "\n    INSERT INTO person (name, age) VALUES ($1, $2)\n", &[&name, &age], None),
+            "-- 1970-01-01T00:00:00.000Z\nINSERT INTO person (name, age) VALUES ($1, $2);\n-- $1 = 'O''Brien'\n-- $2 = NULL\n\n",
+        );
+        assert_eq!(
+            entry(time(0, 0), "SET LOCAL lock_timeout = '5s';\n", &[], None),
+            "-- 1970-01-01T00:00:00.000Z\nSET LOCAL lock_timeout = '5s';\n\n",
+        );
+        assert_eq!(
+            entry(time(0, 0), "COPY person (name) FROM STDIN", &[], Some("Kevin\nAlice\n")),
+            "-- 1970-01-01T00:00:00.000Z\nCOPY person (name) FROM STDIN;\nKevin\nAlice\n\\.\n\n",
+        );
+    }
+}
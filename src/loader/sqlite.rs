@@ -0,0 +1,458 @@
+//! Loads data files into SQLite, with the `sqlite` feature, eg. into in-memory
+//! databases for the fixtures of unit tests.
+
+use super::backend::{interval_parts, Backend, Param, TableName};
+use super::conninfo;
+use super::error::{ClientError, LoadError};
+use super::params::bytea_text;
+use super::report::RecordRow;
+use super::LoadResult;
+use crate::parser::nodes::TimeOffset;
+use crate::Options;
+use rusqlite::types::{Value as SqliteValue, ValueRef};
+use rusqlite::{params_from_iter, Connection};
+use std::collections::HashSet;
+
+/// The units of the intervals that times can be offset by, which are the singular
+/// forms of the units Postgres accepts that SQLite also has.
+const INTERVAL_UNITS: [&str; 6] = ["SECOND", "MINUTE", "HOUR", "DAY", "MONTH", "YEAR"];
+
+pub(crate) struct Sqlite<'a> {
+    connection: SqliteConnection<'a>,
+    /// The names of the attached databases, which schemas are qualified by, once looked up
+    databases: Option<HashSet<String>>,
+    /// The schemas that are not attached databases, which are only warned about once
+    ignored_schemas: HashSet<String>,
+}
+
+/// Either a connection that hldr opened itself, or one that the caller manages.
+enum SqliteConnection<'a> {
+    Opened(Connection),
+    Given(&'a Connection),
+}
+
+impl<'a> Sqlite<'a> {
+    /// Loads into a connection that the caller manages, eg. within a transaction.
+    pub(crate) fn new(connection: &'a Connection) -> Self {
+        Self::with_connection(SqliteConnection::Given(connection))
+    }
+
+    fn with_connection(connection: SqliteConnection<'a>) -> Self {
+        Self {
+            connection,
+            databases: None,
+            ignored_schemas: HashSet::new(),
+        }
+    }
+
+    fn connection(&self) -> &Connection {
+        match &self.connection {
+            SqliteConnection::Opened(connection) => connection,
+            SqliteConnection::Given(connection) => connection,
+        }
+    }
+
+    /// Returns the quoted name of the table, which is qualified by its schema if there
+    /// is an attached database with the same name. Other schemas are ignored with a
+    /// warning, so that data files written for Postgres can still be loaded into a
+    /// single SQLite database.
+    fn table_name(&mut self, table: &TableName) -> LoadResult<String> {
+        let schema = match self.schema(table)? {
+            Some(schema) => schema,
+            None => return Ok(Self::quote(&table.table)),
+        };
+
+        Ok(format!("{}.{}", Self::quote(&schema), Self::quote(&table.table)))
+    }
+
+    /// Returns the table's schema if it is an attached database.
+    fn schema(&mut self, table: &TableName) -> LoadResult<Option<String>> {
+        let schema = match &table.schema {
+            Some(schema) => schema,
+            None => return Ok(None),
+        };
+
+        if self.databases.is_none() {
+            let mut statement = self
+                .connection()
+                .prepare("SELECT name FROM pragma_database_list")
+                .map_err(LoadError::backend)?;
+            let databases = statement
+                .query_map([], |row| row.get(0))
+                .and_then(|rows| rows.collect())
+                .map_err(LoadError::backend)?;
+
+            drop(statement);
+            self.databases = Some(databases);
+        }
+
+        if self.databases.as_ref().unwrap().contains(schema) {
+            return Ok(Some(schema.clone()));
+        }
+
+        if self.ignored_schemas.insert(schema.clone()) {
+            log::warn!("Ignoring schema `{}`, as there is no attached database with that name", schema);
+        }
+
+        Ok(None)
+    }
+}
+
+impl Backend for Sqlite<'_> {
+    const NAME: &'static str = "SQLite";
+
+    fn connect(connstr: &str) -> Result<Self, ClientError> {
+        let connstr = conninfo::resolve(connstr);
+        let connection = Connection::open(conninfo::sqlite_path(&connstr)).map_err(ClientError::connection_error)?;
+
+        Ok(Self::with_connection(SqliteConnection::Opened(connection)))
+    }
+
+    fn quote(identifier: &str) -> String {
+        format!(r#""{}""#, identifier.replace('"', "\"\""))
+    }
+
+    fn placeholder(index: usize) -> String {
+        format!("?{}", index + 1)
+    }
+
+    /// SQLite has no function for the time the transaction began, so the time the
+    /// first record that needs it is inserted is used throughout the transaction.
+    fn now(offset: Option<&TimeOffset>) -> LoadResult<String> {
+        let (negate, interval) = match offset {
+            Some(TimeOffset::Add(interval)) => (false, interval),
+            Some(TimeOffset::Subtract(interval)) => (true, interval),
+            None => return Ok("strftime('%Y-%m-%d %H:%M:%f', 'now')".to_owned()),
+        };
+        let parts = interval_parts(interval, &INTERVAL_UNITS)
+            .ok_or_else(|| LoadError::unsupported(Self::NAME, format!("the interval `{}`", interval)))?;
+        let modifiers: Vec<String> = parts
+            .into_iter()
+            .map(|(quantity, unit)| {
+                let quantity = if negate { quantity.saturating_neg() } else { quantity };
+                format!("'{:+} {}'", quantity, unit)
+            })
+            .collect();
+
+        Ok(format!("strftime('%Y-%m-%d %H:%M:%f', 'now', {})", modifiers.join(", ")))
+    }
+
+    /// SQLite transactions are always serializable, so any isolation level is ignored.
+    fn begin(&mut self, _options: &Options) -> LoadResult<()> {
+        self.execute("BEGIN")
+    }
+
+    fn execute(&mut self, statement: &str) -> LoadResult<()> {
+        log::debug!("{}", statement);
+        self.connection().execute_batch(statement).map_err(LoadError::backend)
+    }
+
+    fn select(&mut self, query: &str, params: &[Param]) -> LoadResult<Option<String>> {
+        let mut statement = self.connection().prepare(query).map_err(LoadError::backend)?;
+        let columns = statement.column_count();
+        let mut values = statement
+            .query_map(params_from_iter(params.iter().map(sqlite_value)), |row| {
+                row.get_ref(0).map(text)
+            })
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+            .map_err(LoadError::backend)?;
+
+        if values.len() != 1 {
+            return Err(LoadError::fragment_rows(query, values.len()));
+        }
+
+        if columns != 1 {
+            return Err(LoadError::fragment_columns(query, columns));
+        }
+
+        Ok(values.remove(0))
+    }
+
+    fn primary_key(&mut self, table: &TableName) -> LoadResult<Vec<String>> {
+        let schema = self.schema(table)?;
+        let mut statement = self
+            .connection()
+            .prepare("SELECT name FROM pragma_table_info(?1, ?2) WHERE pk > 0 ORDER BY pk")
+            .map_err(LoadError::backend)?;
+
+        statement
+            .query_map((&table.table, schema), |row| row.get(0))
+            .and_then(|rows| rows.collect())
+            .map_err(LoadError::backend)
+    }
+
+    /// Inserts the row, which SQLite can return itself.
+    fn insert(
+        &mut self,
+        table: &TableName,
+        columns: &[&str],
+        values: &[Param],
+        returning: bool,
+    ) -> LoadResult<Option<RecordRow>> {
+        let table_name = self.table_name(table)?;
+        let statement = match columns.is_empty() {
+            true => format!("INSERT INTO {} DEFAULT VALUES", table_name),
+            false => format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table_name,
+                columns.iter().map(|c| Self::quote(c)).collect::<Vec<_>>().join(", "),
+                (0..columns.len()).map(Self::placeholder).collect::<Vec<_>>().join(", "),
+            ),
+        };
+        let params = params_from_iter(values.iter().map(sqlite_value));
+
+        if !returning {
+            log::debug!("{}", statement);
+            self.connection().execute(&statement, params).map_err(LoadError::backend)?;
+
+            return Ok(None);
+        }
+
+        let statement = format!("{} RETURNING *", statement);
+        log::debug!("{}", statement);
+
+        let mut statement = self.connection().prepare(&statement).map_err(LoadError::backend)?;
+        let names: Vec<String> = statement.column_names().into_iter().map(str::to_owned).collect();
+        let values = statement
+            .query_row(params, |row| (0..names.len()).map(|i| row.get_ref(i).map(text)).collect::<Result<Vec<_>, _>>())
+            .map_err(LoadError::backend)?;
+
+        Ok(Some(names.into_iter().zip(values).collect()))
+    }
+}
+
+/// Converts a bound value, where text is sent as is for SQLite to convert by the
+/// affinity of its column, but booleans are sent as the numbers they are stored as.
+fn sqlite_value(param: &Param) -> SqliteValue {
+    match param {
+        Param::Null => SqliteValue::Null,
+        Param::Bool(b) => SqliteValue::Integer(*b as i64),
+        Param::Bytes(bytes) => SqliteValue::Blob(bytes.clone()),
+        Param::Text(text) => SqliteValue::Text(text.clone()),
+    }
+}
+
+/// Formats a value as text, where blobs are formatted as Postgres formats `bytea`.
+fn text(value: ValueRef) -> Option<String> {
+    Some(match value {
+        ValueRef::Null => return None,
+        ValueRef::Integer(n) => n.to_string(),
+        ValueRef::Real(n) => n.to_string(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+        ValueRef::Blob(bytes) => bytea_text(bytes),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analyzer, lexer, parser};
+    use super::super::backend;
+    use super::super::error::LoadErrorKind;
+    use super::super::report::PlaceReport;
+
+    fn loaded(connection: &Connection, input: &str) -> LoadResult<PlaceReport> {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let tree = analyzer::analyze(parser::parse(tokens.into_iter()).unwrap()).unwrap();
+
+        backend::load(&mut Sqlite::new(connection), tree, &Options::default())
+    }
+
+    #[test]
+    fn test_load() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "
+                ATTACH DATABASE ':memory:' AS zoo;
+                CREATE TABLE person (id INTEGER PRIMARY KEY, name TEXT, nick TEXT, manager_id INTEGER, joined TEXT);
+                CREATE TABLE zoo.pet (id INTEGER PRIMARY KEY, owner_id INTEGER, photo BLOB, tag TEXT);
+                CREATE TABLE team (name TEXT);
+                ",
+            )
+            .unwrap();
+
+        let input = "
+            table person (
+              kevin (name 'Kevin', nick @name)
+              bob (name 'Bob', manager_id @kevin, joined now())
+            )
+            schema zoo (
+              table pet (
+                rover (owner_id @person.kevin.id, photo x'dead00', tag `upper({@person.kevin.nick})`)
+              )
+            )
+            schema public (
+              table team (
+                (name 'Ops')
+              )
+            )
+        ";
+        let report = loaded(&connection, input).unwrap();
+
+        let kevin = report.record(None, "person", "kevin").unwrap();
+        assert_eq!(kevin["id"].as_deref(), Some("1"));
+        assert_eq!(kevin["manager_id"], None);
+
+        let bob = report.record(None, "person", "bob").unwrap();
+        assert_eq!(bob["manager_id"].as_deref(), Some("1"));
+        assert_eq!(bob["joined"].as_ref().map(String::len), Some(23));
+
+        let rover = report.record(Some("zoo"), "pet", "rover").unwrap();
+        assert_eq!(rover["owner_id"].as_deref(), Some("1"));
+        assert_eq!(rover["photo"].as_deref(), Some("\\xdead00"));
+        assert_eq!(rover["tag"].as_deref(), Some("KEVIN"));
+
+        // The `public` schema is not attached, so the table in the main database is used
+        let teams: i64 = connection.query_row("SELECT count(*) FROM team", [], |row| row.get(0)).unwrap();
+        assert_eq!(teams, 1);
+        assert_eq!(report.table(Some("public"), "team").unwrap().rows_inserted, 1);
+    }
+
+    #[test]
+    fn test_expected_rows() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch("CREATE TABLE person (name TEXT); INSERT INTO person VALUES ('Alice');")
+            .unwrap();
+
+        let input = "table person (\n  expect 3 rows\n  (name 'Kevin')\n  (name 'Bob')\n)";
+        assert!(loaded(&connection, input).is_ok());
+
+        let input = "table person (\n  expect 3 rows\n  (name 'Carol')\n)";
+        assert_eq!(
+            loaded(&connection, input).unwrap_err().to_string(),
+            r#"expected 3 rows in table "person" once loaded, found 4"#,
+        );
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch("CREATE TABLE person (name TEXT NOT NULL, age INTEGER);")
+            .unwrap();
+        let position = |line, column| Some(crate::Position { line, column });
+
+        let input = "table person (\n  (name 'Kevin')\n  (age 3)\n)";
+        assert_eq!(loaded(&connection, input).unwrap_err().position, position(3, 3));
+
+        let input = "table person (\n  (name 'Kevin', age 1 / 0)\n)";
+        let error = loaded(&connection, input).unwrap_err();
+        assert_eq!(error.position, position(2, 18));
+        assert_eq!(error.end.map(|end| *end), position(2, 27), "to the end of the attribute");
+
+        let input = "\ntable person truncate (\n  (name 'Kevin')\n)";
+        assert_eq!(loaded(&connection, input).unwrap_err().position, position(2, 1));
+    }
+
+    #[test]
+    fn test_fragment_results() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch("CREATE TABLE person (name TEXT); INSERT INTO person VALUES ('Kevin'), ('Bob');")
+            .unwrap();
+        let error = |fragment| {
+            let input = format!("table person (\n  (name `{}`)\n)", fragment);
+            loaded(&connection, &input).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            error("name FROM person"),
+            "expected a single row from SQL fragment `SELECT name FROM person`, found 2",
+        );
+        assert_eq!(
+            error("name FROM person WHERE false"),
+            "expected a single row from SQL fragment `SELECT name FROM person WHERE false`, found 0",
+        );
+        assert_eq!(
+            error("'Kevin', 'Bob'"),
+            "expected a single column from SQL fragment `SELECT 'Kevin', 'Bob'`, found 2",
+        );
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch("CREATE TABLE person (name TEXT NOT NULL, age INTEGER); CREATE TABLE pet (owner TEXT);")
+            .unwrap();
+
+        let input = "table person (\n  kevin (name 'Kevin')\n  (age 3)\n  bob (age 4)\n)\ntable pet (\n  (owner @person.bob.name)\n  (owner @person.kevin.name)\n)";
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        let tree = analyzer::analyze(parser::parse(tokens.into_iter()).unwrap()).unwrap();
+        let options = Options { collect_errors: true, ..Options::default() };
+        let error = backend::load(&mut Sqlite::new(&connection), tree, &options).unwrap_err();
+
+        let failed = match &error.kind {
+            LoadErrorKind::FailedInserts(failed) => failed,
+            kind => panic!("expected failed inserts, found {:?}", kind),
+        };
+        let positions: Vec<_> = failed.iter().map(|f| f.error.position.map(|p| p.line)).collect();
+
+        assert_eq!(positions, [Some(3), Some(4), Some(7)]);
+        assert_eq!(
+            failed[2].error.to_string(),
+            "could not select referenced column `name` of record `person.bob`, as it failed to load",
+        );
+        assert!(error.to_string().starts_with("3 inserts failed, so nothing was loaded\n\nerror: "));
+
+        let count: i64 = connection.query_row("SELECT count(*) FROM pet", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_hooks() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch("CREATE TABLE person (name TEXT); CREATE TABLE total (people INTEGER);")
+            .unwrap();
+
+        let input = "
+            before `DELETE FROM total`
+            table person (
+              (name 'Kevin')
+              (name 'Bob')
+            )
+            after `INSERT INTO total SELECT count(*) FROM person`
+        ";
+        assert!(loaded(&connection, input).is_ok());
+
+        let people: i64 = connection.query_row("SELECT people FROM total", [], |row| row.get(0)).unwrap();
+        assert_eq!(people, 2);
+    }
+
+    #[test]
+    fn test_primary_key() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "
+                ATTACH DATABASE ':memory:' AS zoo;
+                CREATE TABLE zoo.enclosure (zone TEXT, number INTEGER, PRIMARY KEY (number, zone));
+                CREATE TABLE keeper (name TEXT);
+                ",
+            )
+            .unwrap();
+        let mut sqlite = Sqlite::new(&connection);
+        let table = |schema: Option<&str>, table: &str| TableName {
+            schema: schema.map(str::to_owned),
+            table: table.to_owned(),
+        };
+
+        assert_eq!(sqlite.primary_key(&table(Some("zoo"), "enclosure")).unwrap(), vec!["number", "zone"]);
+        assert!(sqlite.primary_key(&table(None, "keeper")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_now() {
+        let add = |interval: &str| Sqlite::now(Some(&TimeOffset::Add(interval.to_owned())));
+
+        assert_eq!(Sqlite::now(None).unwrap(), "strftime('%Y-%m-%d %H:%M:%f', 'now')");
+        assert_eq!(
+            Sqlite::now(Some(&TimeOffset::Subtract("2 hours 30 minutes".to_owned()))).unwrap(),
+            "strftime('%Y-%m-%d %H:%M:%f', 'now', '-2 HOUR', '-30 MINUTE')",
+        );
+        assert_eq!(add("1 week").unwrap_err().to_string(), "SQLite does not support the interval `1 week`");
+    }
+}
@@ -0,0 +1,40 @@
+//! Loads data files through `sqlx` Postgres connections, with the `sqlx` feature, by
+//! executing the same statements that are written to SQL files.
+
+use super::error::LoadError;
+use super::output::{primary_key_tables, write_statements};
+use super::{quote, LoadResult, PRIMARY_KEY_QUERY};
+use crate::analyzer::ValidatedParseTree;
+use crate::Options;
+use sqlx::{Connection, PgConnection};
+use std::collections::HashMap;
+
+/// Loads the tree within a transaction of its own, or a savepoint if the connection
+/// is already within a transaction, which is committed or released once loaded.
+pub(crate) async fn load(connection: &mut PgConnection, tree: ValidatedParseTree, options: &Options) -> LoadResult<()> {
+    let mut transaction = connection.begin().await.map_err(LoadError::backend)?;
+    let mut primary_keys = HashMap::new();
+
+    for table in primary_key_tables(&tree, options) {
+        let columns: Vec<String> = sqlx::query_scalar(PRIMARY_KEY_QUERY)
+            .bind(&table)
+            .fetch_all(&mut *transaction)
+            .await
+            .map_err(LoadError::backend)?;
+
+        primary_keys.insert(table, columns.iter().map(|c| quote(c)).collect());
+    }
+
+    let mut statements = Vec::new();
+    write_statements(&mut statements, tree, options, primary_keys)?;
+
+    for statement in &statements {
+        log::debug!("{}", statement);
+        sqlx::raw_sql(statement)
+            .execute(&mut *transaction)
+            .await
+            .map_err(LoadError::backend)?;
+    }
+
+    transaction.commit().await.map_err(LoadError::backend)
+}
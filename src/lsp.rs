@@ -0,0 +1,927 @@
+//! A language server for data files, started with `hldr lsp`, which speaks the
+//! Language Server Protocol over stdin and stdout so that editors can report errors
+//! as files are edited, go from references to the records they reference, and
+//! complete the names of schemas, tables, and columns in the database.
+
+use crate::analyzer;
+use crate::error::HldrError;
+use crate::identifier::{self, Identifier};
+use crate::include::Includes;
+use crate::lexer::{self, tokens::{Keyword, Symbol, TextPart, Token, TokenKind}};
+use crate::loader;
+use crate::parser::{self, nodes::{ParseTree, StructuralIdentity, StructuralNode}};
+use crate::{Options, Position};
+use serde_json::{json, Value};
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The columns of every table in the database, by schema and table
+type Catalog = BTreeMap<String, BTreeMap<String, Vec<String>>>;
+
+/// Where each named record is declared, by namespace and then by its key
+/// within the namespace, eg. `person.kevin`
+type Declarations = HashMap<Option<String>, HashMap<String, (PathBuf, Position)>>;
+
+/// Serves requests read from the input until the client exits, writing responses and
+/// diagnostics to the output.
+pub(crate) fn serve(mut input: impl BufRead, output: impl Write, options: &Options) -> Result<(), HldrError> {
+    let mut server = Server {
+        output,
+        options,
+        documents: HashMap::new(),
+        catalog: OnceCell::new(),
+    };
+
+    while let Some(message) = read_message(&mut input)? {
+        if !server.handle(message)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+struct Server<'a, W> {
+    output: W,
+    options: &'a Options,
+    /// The text of each open data file, by canonical path, which is used in place
+    /// of what is on disk
+    documents: HashMap<PathBuf, String>,
+    /// The database's schemas, tables, and columns, selected when first completing
+    /// names, unless the database could not be connected to
+    catalog: OnceCell<Option<Catalog>>,
+}
+
+impl<W: Write> Server<'_, W> {
+    /// Handles the request or notification, returning whether to keep serving.
+    fn handle(&mut self, message: Value) -> io::Result<bool> {
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+        let document = params["textDocument"]["uri"].as_str().map(uri_path);
+
+        match (message["method"].as_str(), id) {
+            (Some("initialize"), Some(id)) => self.respond(id, json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "completionProvider": {},
+                    "semanticTokensProvider": {
+                        "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": [] },
+                        "full": true,
+                    },
+                },
+                "serverInfo": { "name": "hldr", "version": env!("CARGO_PKG_VERSION") },
+            }))?,
+            (Some("shutdown"), Some(id)) => self.respond(id, Value::Null)?,
+            (Some("exit"), _) => return Ok(false),
+            (Some("textDocument/didOpen"), _) => {
+                if let (Some(path), Some(text)) = (document, params["textDocument"]["text"].as_str()) {
+                    self.documents.insert(path, normalize(text));
+                    self.publish_diagnostics()?;
+                }
+            }
+            (Some("textDocument/didChange"), _) => {
+                let text = params["contentChanges"].as_array().and_then(|c| c.last()).and_then(|c| c["text"].as_str());
+
+                if let (Some(path), Some(text)) = (document, text) {
+                    self.documents.insert(path, normalize(text));
+                    self.publish_diagnostics()?;
+                }
+            }
+            (Some("textDocument/didClose"), _) => {
+                if let Some(path) = document {
+                    self.documents.remove(&path);
+                    self.notify("textDocument/publishDiagnostics", json!({
+                        "uri": path_uri(&path),
+                        "diagnostics": [],
+                    }))?;
+                    self.publish_diagnostics()?;
+                }
+            }
+            (Some("textDocument/definition"), Some(id)) => {
+                let location = document.and_then(|path| self.definition(&path, &params["position"]));
+                self.respond(id, location.unwrap_or(Value::Null))?;
+            }
+            (Some("textDocument/completion"), Some(id)) => {
+                let items = document.map(|path| self.completion(&path, &params["position"])).unwrap_or_default();
+                self.respond(id, Value::Array(items))?;
+            }
+            (Some("textDocument/semanticTokens/full"), Some(id)) => {
+                let data = document.map(|path| self.semantic_tokens(&path)).unwrap_or_default();
+                self.respond(id, json!({ "data": data }))?;
+            }
+            (Some(method), Some(id)) => self.write(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": format!("unsupported method `{}`", method) },
+            }))?,
+            // Other notifications and responses to requests never sent are ignored
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    fn respond(&mut self, id: Value, result: Value) -> io::Result<()> {
+        self.write(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.write(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn write(&mut self, message: Value) -> io::Result<()> {
+        write_message(&mut self.output, &message)
+    }
+
+    /// Publishes the diagnostics of every open document, since a change to one can
+    /// cause or fix errors in the others, eg. by renaming a record they reference.
+    fn publish_diagnostics(&mut self) -> io::Result<()> {
+        let mut paths: Vec<_> = self.documents.keys().cloned().collect();
+        paths.sort();
+
+        for path in paths {
+            let diagnostics = self.diagnostics(&path);
+            self.notify("textDocument/publishDiagnostics", json!({
+                "uri": path_uri(&path),
+                "diagnostics": diagnostics,
+            }))?;
+        }
+
+        Ok(())
+    }
+
+    /// Analyzes the document along with the data files it is loaded with, returning
+    /// the first error, if any, just as checking the data files would.
+    fn diagnostics(&self, path: &Path) -> Vec<Value> {
+        let mut includes = Includes::with_documents(self.documents.clone());
+        let mut tree = ParseTree::default();
+        let result = self.data_files(path).iter().try_for_each(|data_file| {
+            tree.nodes.extend(includes.parse_file(data_file)?.nodes);
+            Ok(())
+        });
+        let error = match result.and_then(|_| {
+            analyzer::analyze(tree).map_err(|e| includes.sources.locate_error(e.into()))
+        }) {
+            Ok(_) => return Vec::new(),
+            Err(error) => error,
+        };
+
+        // Errors in other files are reported at the top of the document, as it is
+        // loaded with them and the file may not be open
+        let message = error.error.to_string();
+        let (position, end, message) = match &error.location {
+            Some(location) if canonical(&location.path) == path => (location.position, location.end, message),
+            Some(location) => (
+                Position::default(),
+                None,
+                format!("{} in {}:{}:{}", message, location.path.display(), location.position.line, location.position.column),
+            ),
+            None => (Position::default(), None, message),
+        };
+        let text = &self.documents[path];
+        let end = end.unwrap_or(Position {
+            column: position.column + 1,
+            ..position
+        });
+
+        vec![json!({
+            "range": { "start": lsp_position(text, position), "end": lsp_position(text, end) },
+            "severity": 1,
+            "source": "hldr",
+            "message": message,
+        })]
+    }
+
+    /// The data files that the document is loaded with, which are those in the options
+    /// if it is one of them, or otherwise only the document itself.
+    fn data_files(&self, path: &Path) -> Vec<PathBuf> {
+        match self.options.data_file_paths() {
+            Ok(data_files) if data_files.iter().any(|f| canonical(f) == path) => data_files,
+            _ => vec![path.to_owned()],
+        }
+    }
+
+    /// The text of the file, whether open or on disk.
+    fn text(&self, path: &Path) -> Option<String> {
+        match self.documents.get(path) {
+            Some(text) => Some(text.clone()),
+            None => fs::read_to_string(path).ok().map(|text| normalize(&text)),
+        }
+    }
+
+    /// Returns the location of the record declaration that the reference at the
+    /// position refers to, if any.
+    fn definition(&self, path: &Path, position: &Value) -> Option<Value> {
+        let text = self.documents.get(path)?;
+        let position = text_position(text, position)?;
+
+        // References cannot span lines, so only the text through the end of the line
+        // is needed, which is less likely to be mid-edit and fail to lex
+        let line_end = offset(text, Position { line: position.line + 1, column: 1 });
+        let tokens = lexer::tokenize(text[..line_end].chars()).ok()?;
+        let (namespaces, keys) = reference_at(&flatten(tokens), position)?;
+        let declarations = self.declarations(path);
+        let (file, position) = keys
+            .iter()
+            .find_map(|key| namespaces.iter().find_map(|namespace| declarations.get(namespace)?.get(key)))?;
+        let position = lsp_position(&self.text(file)?, *position);
+
+        Some(json!({
+            "uri": path_uri(file),
+            "range": { "start": position, "end": position },
+        }))
+    }
+
+    /// Finds every named record declared in the document, the data files it is loaded
+    /// with, and the files they include, skipping any that cannot be parsed.
+    fn declarations(&self, path: &Path) -> Declarations {
+        let mut declarations = Declarations::new();
+        let mut queue = self.data_files(path);
+        let mut parsed = HashSet::new();
+
+        queue.push(path.to_owned());
+
+        while let Some(file) = queue.pop() {
+            let file = canonical(&file);
+            let tree = match self.text(&file).filter(|_| parsed.insert(file.clone())) {
+                Some(text) => lexer::tokenize(text.chars()).ok().and_then(|t| parser::parse(t.into_iter()).ok()),
+                None => None,
+            };
+            let tree = match tree {
+                Some(tree) => tree,
+                None => continue,
+            };
+            let dir = file.parent().unwrap_or(Path::new(""));
+
+            queue.extend(tree.includes.iter().map(|include| dir.join(&include.path)));
+
+            let tables = tree.nodes.iter().flat_map(|node| match node {
+                StructuralNode::Schema(schema) => schema.nodes.iter().map(|t| (Some(&schema.identity), t)).collect(),
+                StructuralNode::Table(table) => vec![(None, table.as_ref())],
+            });
+
+            for (schema, table) in tables {
+                let scope = analyzer::table_scope(schema, &table.identity);
+                let keys = declarations.entry(table.namespace.clone()).or_default();
+
+                for record in &table.nodes {
+                    if let (Some(name), Some(position)) = (&record.name, record.position) {
+                        keys.entry(format!("{}.{}", scope, name)).or_insert((file.clone(), position));
+                    }
+                }
+            }
+        }
+
+        declarations
+    }
+
+    /// Returns the names of schemas, tables, or columns in the database that could be
+    /// declared at the position, depending on what precedes it.
+    fn completion(&self, path: &Path, position: &Value) -> Vec<Value> {
+        let (text, position) = match self.documents.get(path).and_then(|t| Some((t, text_position(t, position)?))) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+        let mut tokens = match lexer::tokenize(text[..offset(text, position)].chars()) {
+            Ok(tokens) => tokens,
+            Err(_) => return Vec::new(),
+        };
+
+        // The name being typed, if any, is what is being completed
+        if tokens.last().is_some_and(|t| identifier(t).is_some() && end(t) == position) {
+            tokens.pop();
+        }
+
+        let catalog = match self.catalog.get_or_init(|| select_catalog(&self.options.database_conn)) {
+            Some(catalog) => catalog,
+            None => return Vec::new(),
+        };
+        let scope = Scope::of(&tokens);
+        let schema = scope.schema.as_ref().map_or("public".to_owned(), |s| s.name.clone());
+
+        let (names, kind): (Vec<&String>, _) = match (tokens.last().map(|t| &t.kind), &scope.within) {
+            (Some(TokenKind::Keyword(Keyword::Schema)), _) => (catalog.keys().collect(), COMPLETE_MODULE),
+            (Some(TokenKind::Keyword(Keyword::Table)), _) => {
+                (catalog.get(&schema).into_iter().flat_map(|tables| tables.keys()).collect(), COMPLETE_CLASS)
+            }
+            (
+                Some(TokenKind::LineSep | TokenKind::Symbol(Symbol::ParenLeft | Symbol::Comma)),
+                Some(Frame::Record | Frame::Columns),
+            ) => {
+                let columns = scope
+                    .table
+                    .and_then(|table| catalog.get(&schema)?.get(&table.name));
+                (columns.into_iter().flatten().collect(), COMPLETE_FIELD)
+            }
+            _ => (Vec::new(), COMPLETE_FIELD),
+        };
+
+        names
+            .into_iter()
+            .map(|name| json!({ "label": identifier::render(name), "kind": kind }))
+            .collect()
+    }
+
+    /// Returns the class of each token in the document for highlighting, as the line and
+    /// character each starts at relative to the previous token, its length, and its type,
+    /// with tokens that span lines split into one per line.
+    fn semantic_tokens(&self, path: &Path) -> Vec<usize> {
+        let text = match self.documents.get(path) {
+            Some(text) => text,
+            None => return Vec::new(),
+        };
+        let spans = match lexer::spans(text) {
+            Ok(spans) => spans,
+            Err(_) => return Vec::new(),
+        };
+        let line_starts: Vec<usize> = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        let mut data = Vec::new();
+        let mut previous = (0, 0);
+
+        for (span, class) in spans {
+            let mut start = span.start;
+
+            for part in text[span.start..span.end].split('\n') {
+                let line = line_starts.partition_point(|&s| s <= start) - 1;
+                let character = text[line_starts[line]..start].encode_utf16().count();
+                let length = part.encode_utf16().count();
+
+                if length > 0 {
+                    let delta = if line == previous.0 { character - previous.1 } else { character };
+                    data.extend([line - previous.0, delta, length, class as usize, 0]);
+                    previous = (line, character);
+                }
+                start += part.len() + 1;
+            }
+        }
+
+        data
+    }
+}
+
+/// The types of semantic tokens in the protocol for each class of token, in order
+const TOKEN_TYPES: [&str; 9] = [
+    "comment",
+    "enumMember",
+    "function",
+    "variable",
+    "keyword",
+    "number",
+    "macro",
+    "string",
+    "operator",
+];
+
+/// The kinds of completion items in the protocol for schemas, tables, and columns
+const COMPLETE_MODULE: u8 = 9;
+const COMPLETE_CLASS: u8 = 7;
+const COMPLETE_FIELD: u8 = 5;
+
+/// Selects the database's schemas, tables, and columns, or returns nothing if the
+/// database cannot be connected to, in which case nothing is completed.
+fn select_catalog(database_conn: &str) -> Option<Catalog> {
+    let result = loader::new_client(database_conn).map_err(HldrError::from).and_then(|mut client| {
+        let rows = client.query(
+            "SELECT n.nspname, c.relname, a.attname
+            FROM pg_catalog.pg_attribute a
+            JOIN pg_catalog.pg_class c ON c.oid = a.attrelid
+            JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace
+            WHERE c.relkind IN ('r', 'p')
+              AND a.attnum > 0
+              AND NOT a.attisdropped
+              AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+              AND n.nspname NOT LIKE 'pg\\_toast%'
+            ORDER BY n.nspname, c.relname, a.attnum",
+            &[],
+        )?;
+        let mut catalog = Catalog::new();
+
+        for row in rows {
+            catalog
+                .entry(row.get(0))
+                .or_default()
+                .entry(row.get(1))
+                .or_default()
+                .push(row.get(2));
+        }
+
+        Ok(catalog)
+    });
+
+    match result {
+        Ok(catalog) => Some(catalog),
+        Err(e) => {
+            log::warn!("Not completing names from the database: {}", e.error);
+            None
+        }
+    }
+}
+
+/// What encloses the end of a sequence of tokens, as declared by the tokens, which
+/// does not require them to parse, eg. while a record is being typed.
+#[derive(Debug, Default, PartialEq)]
+struct Scope {
+    namespace: Option<String>,
+    schema: Option<StructuralIdentity>,
+    table: Option<StructuralIdentity>,
+    /// What the innermost parentheses contain, if within any
+    within: Option<Frame>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Frame {
+    Schema(StructuralIdentity),
+    Table(StructuralIdentity),
+    Record,
+    /// Columns or attributes of the table, eg. `$defaults (...)` or `conflict (...)`
+    Columns,
+    /// Anything else, eg. the arguments of a function call
+    Other,
+}
+
+impl Scope {
+    fn of(tokens: &[Token]) -> Self {
+        let mut namespace = None;
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut declared = None;
+
+        for (i, t) in tokens.iter().enumerate() {
+            let declaration = || {
+                let name = tokens.get(i + 1).and_then(name)?;
+                let alias = match tokens.get(i + 2).map(|t| &t.kind) {
+                    Some(TokenKind::Keyword(Keyword::As)) => tokens.get(i + 3).and_then(self::name),
+                    _ => None,
+                };
+                Some(StructuralIdentity::new(name, alias))
+            };
+            let previous = i.checked_sub(1).map(|i| &tokens[i].kind);
+
+            match &t.kind {
+                TokenKind::Identifier(ident) if ident == "namespace" && matches!(previous, None | Some(TokenKind::LineSep)) => {
+                    namespace = tokens.get(i + 1).and_then(identifier).map(str::to_owned)
+                }
+                TokenKind::Keyword(Keyword::Schema) => declared = declaration().map(Frame::Schema),
+                TokenKind::Keyword(Keyword::Table) => declared = declaration().map(Frame::Table),
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    let frame = match previous {
+                        Some(TokenKind::Keyword(Keyword::Columns | Keyword::Defaults)) => Frame::Columns,
+                        Some(TokenKind::Identifier(ident)) if ident == "conflict" && declared.is_some() => Frame::Columns,
+                        _ => match (declared.take(), frames.last()) {
+                            (Some(frame), _) => frame,
+                            (None, Some(Frame::Table(_))) => Frame::Record,
+                            // Several columns declared at once with a reference, rather than a function call
+                            (None, Some(Frame::Record)) if !matches!(previous, Some(TokenKind::Identifier(_))) => {
+                                Frame::Columns
+                            }
+                            (None, _) => Frame::Other,
+                        },
+                    };
+                    frames.push(frame);
+                }
+                TokenKind::Symbol(Symbol::ParenRight) => {
+                    frames.pop();
+                }
+                _ => {}
+            }
+        }
+
+        let identity = |f: &Frame, schema: bool| match f {
+            Frame::Schema(identity) if schema => Some(identity.clone()),
+            Frame::Table(identity) if !schema => Some(identity.clone()),
+            _ => None,
+        };
+
+        Self {
+            namespace,
+            schema: frames.iter().rev().find_map(|f| identity(f, true)),
+            table: frames.iter().rev().find_map(|f| identity(f, false)),
+            within: frames.pop(),
+        }
+    }
+}
+
+/// Returns the keys of the records that the reference at the position could be to, along
+/// with the namespaces to look for them in, in order, just as the analyzer resolves
+/// references, including to the primary key of the record named by the whole reference.
+fn reference_at(tokens: &[Token], position: Position) -> Option<(Vec<Option<String>>, Vec<String>)> {
+    for (i, t) in tokens.iter().enumerate() {
+        if t.kind != TokenKind::Symbol(Symbol::AtSign) {
+            continue;
+        }
+
+        // Shorthand references directly follow the name of the column, eg. `person_id@ kevin`
+        let shorthand = i
+            .checked_sub(1)
+            .map(|i| &tokens[i])
+            .filter(|name| identifier(name).is_some() && end(name) == t.position);
+
+        let mut j = i + 1;
+        let namespace = match (tokens.get(j).and_then(identifier), tokens.get(j + 1).map(|t| &t.kind)) {
+            (Some(namespace), Some(TokenKind::Symbol(Symbol::DoubleColon))) => {
+                j += 2;
+                Some(namespace.to_owned())
+            }
+            _ => None,
+        };
+        let mut identifiers = Vec::new();
+        let mut implicit = false;
+
+        while let Some(t) = tokens.get(j).filter(|t| identifier(t).is_some()) {
+            identifiers.push(t);
+            j += 1;
+            implicit = tokens.get(j).is_some_and(|t| t.kind == TokenKind::Symbol(Symbol::Period));
+            if implicit {
+                j += 1;
+            }
+        }
+
+        let (start, end) = (shorthand.unwrap_or(t).position, end(&tokens[j - 1]));
+        if start.line != position.line || position.column < start.column || position.column > end.column {
+            continue;
+        }
+
+        // The last identifier is the column, unless it is implicit or a shorthand reference,
+        // or else the whole reference is to the primary key of the record it names
+        let qualifiers = match (shorthand, implicit) {
+            (Some(name), _) => {
+                let name = self::name(name)?;
+                let (table, _) = name.rsplit_once('_')?;
+                vec![vec![table.to_owned(), identifier(identifiers.first()?)?.to_owned()]]
+            }
+            (None, true) => vec![qualified(&identifiers)],
+            (None, false) => vec![qualified(&identifiers[..identifiers.len().checked_sub(1)?]), qualified(&identifiers)],
+        };
+        let scope = Scope::of(&tokens[..i]);
+        let keys: Vec<String> = qualifiers
+            .iter()
+            .filter_map(|qualifiers| match qualifiers.len() {
+                1 => Some(format!(
+                    "{}.{}",
+                    analyzer::table_scope(scope.schema.as_ref(), scope.table.as_ref()?),
+                    qualifiers[0],
+                )),
+                2 | 3 => Some(qualifiers.join(".")),
+                _ => None,
+            })
+            .collect();
+
+        if keys.is_empty() {
+            return None;
+        }
+
+        let namespaces = match namespace {
+            Some(namespace) => vec![Some(namespace)],
+            None => vec![scope.namespace, None],
+        };
+
+        return Some((namespaces, keys));
+    }
+
+    None
+}
+
+/// Returns the tokens with the tokens of each value embedded in a string or SQL fragment
+/// in place of it.
+fn flatten(tokens: Vec<Token>) -> Vec<Token> {
+    tokens
+        .into_iter()
+        .flat_map(|t| match t.kind {
+            TokenKind::InterpolatedText(parts) | TokenKind::InterpolatedSqlFragment(parts) => parts
+                .into_iter()
+                .flat_map(|part| match part {
+                    TextPart::Embedded(tokens) => flatten(tokens),
+                    TextPart::Literal(_) => Vec::new(),
+                })
+                .collect(),
+            _ => vec![t],
+        })
+        .collect()
+}
+
+fn identifier(t: &Token) -> Option<&str> {
+    match &t.kind {
+        TokenKind::Identifier(name) | TokenKind::QuotedIdentifier(name) => Some(name),
+        _ => None,
+    }
+}
+
+/// The name of the schema, table, or column that the token refers to, which is folded
+/// to lowercase unless it is quoted, just as the parser does.
+fn name(t: &Token) -> Option<String> {
+    match &t.kind {
+        TokenKind::Identifier(value) => Some(Identifier { quoted: false, value: value.clone() }.name()),
+        TokenKind::QuotedIdentifier(value) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+/// The names of a record's schema and table, if any, followed by the record's own.
+fn qualified(identifiers: &[&Token]) -> Vec<String> {
+    match identifiers.split_last() {
+        Some((record, qualifiers)) => {
+            qualifiers.iter().filter_map(|t| name(t)).chain(identifier(record).map(str::to_owned)).collect()
+        }
+        None => Vec::new(),
+    }
+}
+
+/// The position just after the token, for tokens that references are made of.
+fn end(t: &Token) -> Position {
+    let width = match &t.kind {
+        TokenKind::Identifier(name) => name.chars().count(),
+        TokenKind::QuotedIdentifier(name) => identifier::quoted_width(name),
+        TokenKind::Symbol(symbol) => symbol.to_string().len(),
+        _ => 1,
+    };
+
+    Position { column: t.position.column + width, ..t.position }
+}
+
+/// Normalizes line endings to line feeds, since the lexer counts both a carriage return
+/// and a line feed as ending a line but the protocol counts them together as one.
+fn normalize(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// The byte offset of the position in the text, or of the end of its line if past it.
+fn offset(text: &str, position: Position) -> usize {
+    let mut start = 0;
+
+    for _ in 1..position.line {
+        match text[start..].find('\n') {
+            Some(end) => start += end + 1,
+            None => return text.len(),
+        }
+    }
+
+    let line = &text[start..text[start..].find('\n').map_or(text.len(), |end| start + end)];
+    start + line.char_indices().nth(position.column - 1).map_or(line.len(), |(i, _)| i)
+}
+
+/// Converts a position in the protocol, which counts lines and characters from zero
+/// and characters in UTF-16 code units, to a position in the text.
+fn text_position(text: &str, position: &Value) -> Option<Position> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let mut units = 0;
+    let column = text
+        .split('\n')
+        .nth(line)
+        .unwrap_or_default()
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= character
+        })
+        .count();
+
+    Some(Position { line: line + 1, column: column + 1 })
+}
+
+/// Converts a position in the text to a position in the protocol.
+fn lsp_position(text: &str, position: Position) -> Value {
+    let line = text.split('\n').nth(position.line.saturating_sub(1)).unwrap_or_default();
+    let character: usize = line.chars().take(position.column.saturating_sub(1)).map(char::len_utf16).sum();
+
+    json!({ "line": position.line.saturating_sub(1), "character": character })
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+/// Converts a `file://` URI to the canonical path of the file, decoding any escapes.
+fn uri_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = match (byte, tail) {
+            (b'%', [high, low, ..]) => std::str::from_utf8(&[*high, *low]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    canonical(Path::new(&*String::from_utf8_lossy(&bytes)))
+}
+
+/// Converts a path to a `file://` URI, escaping anything other than unreserved characters.
+fn path_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    uri
+}
+
+/// Reads the next message, which is JSON preceded by headers giving its length, or
+/// returns nothing at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "expected Content-Length header"))?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+
+    Ok(Some(serde_json::from_slice(&content)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn message(content: Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &content).unwrap();
+        bytes
+    }
+
+    fn messages(mut output: &[u8]) -> Vec<Value> {
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn reference(input: &str, line: usize, column: usize) -> Option<(Vec<Option<String>>, Vec<String>)> {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        reference_at(&flatten(tokens), Position { line, column })
+    }
+
+    #[test]
+    fn test_scope() {
+        let tokens = lexer::tokenize("namespace hr\nschema staff as s (\n  table person conflict (id) (\n    kevin ( age now(".chars()).unwrap();
+        let scope = Scope::of(&tokens);
+
+        assert_eq!(scope.namespace, Some("hr".to_owned()));
+        assert_eq!(scope.schema, Some(StructuralIdentity::new("staff".to_owned(), Some("s".to_owned()))));
+        assert_eq!(scope.table, Some(StructuralIdentity::new("person".to_owned(), None)));
+        assert_eq!(scope.within, Some(Frame::Other));
+
+        for (input, within) in [
+            ("table person (\n  kevin (", Some(Frame::Record)),
+            ("table person (\n  $defaults (", Some(Frame::Columns)),
+            ("table person (\n  kevin ( (", Some(Frame::Columns)),
+            ("table person (\n  kevin ( name 'Kevin' )", Some(Frame::Table(StructuralIdentity::new("person".to_owned(), None)))),
+            ("table person (\n)", None),
+        ] {
+            assert_eq!(Scope::of(&lexer::tokenize(input.chars()).unwrap()).within, within, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reference_at() {
+        let input = "namespace hr\ntable person as p (\n  ( boss_id @kevin.id, pet_id@ rex, name 'Pat {@hr::person.pat.}' )\n)\nschema s ( table u ( ( a @s.u.r.a ) ) )";
+        let keys = |keys: &[&str]| Some((vec![Some("hr".to_owned()), None], keys.iter().map(|k| k.to_string()).collect()));
+
+        assert_eq!(reference(input, 3, 13), keys(&["p.kevin", "kevin.id"]));
+        assert_eq!(reference(input, 3, 22), keys(&["p.kevin", "kevin.id"]));
+        assert_eq!(reference(input, 3, 23), None);
+        assert_eq!(reference(input, 3, 27), keys(&["pet.rex"]));
+        assert_eq!(reference(input, 3, 35), keys(&["pet.rex"]));
+        assert_eq!(reference(input, 3, 50), Some((vec![Some("hr".to_owned())], vec!["person.pat".to_owned()])));
+        assert_eq!(reference(input, 5, 30), keys(&["s.u.r"]));
+        assert_eq!(reference("table u ( ( b @a ) )", 1, 16), Some((vec![None, None], vec!["u.a".to_owned()])));
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "table 💝 (\n  ( a 'é' )\n)";
+
+        for (line, character, column) in [(0, 0, 1), (0, 8, 8), (1, 8, 9), (2, 5, 2)] {
+            let position = text_position(text, &json!({ "line": line, "character": character })).unwrap();
+            assert_eq!(position, Position { line: line + 1, column });
+        }
+
+        assert_eq!(lsp_position(text, Position { line: 1, column: 10 }), json!({ "line": 0, "character": 10 }));
+        assert_eq!(&text[offset(text, Position { line: 2, column: 8 })..], "é' )\n)");
+        assert_eq!(offset(text, Position { line: 2, column: 40 }), text.find("\n)").unwrap());
+        assert_eq!(offset(text, Position { line: 9, column: 1 }), text.len());
+        assert_eq!(normalize("a\r\nb\rc"), "a\nb\nc");
+        assert_eq!(uri_path(&path_uri(Path::new("/tmp/a b%.hldr"))), PathBuf::from("/tmp/a b%.hldr"));
+    }
+
+    #[test]
+    fn test_serve() {
+        let dir = canonical(&std::env::temp_dir()).join(format!("hldr-lsp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("person.hldr"), "table person (\n  kevin ( name 'Kevin' )\n)\n").unwrap();
+
+        let options = Options {
+            data_files: vec![dir.join("person.hldr"), dir.join("pet.hldr")],
+            ..Options::default()
+        };
+        let uri = path_uri(&dir.join("pet.hldr"));
+        let open = |text: &str| json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "text": text } },
+        });
+        let input = [
+            message(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} })),
+            message(open("table pet (\r\n  ( person_id @person.kev.id )\r\n)\r\n")),
+            message(open("table pet (\n  ( person_id @person.kevin.id )\n)\n")),
+            message(json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "textDocument/definition",
+                "params": { "textDocument": { "uri": uri }, "position": { "line": 1, "character": 24 } },
+            })),
+            message(json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "textDocument/semanticTokens/full",
+                "params": { "textDocument": { "uri": uri } },
+            })),
+            message(json!({ "jsonrpc": "2.0", "id": 4, "method": "textDocument/hover", "params": {} })),
+            message(json!({ "jsonrpc": "2.0", "method": "exit" })),
+            message(json!({ "jsonrpc": "2.0", "id": 5, "method": "shutdown" })),
+        ]
+        .concat();
+
+        let mut output = Vec::new();
+        serve(input.as_slice(), &mut output, &options).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let messages = messages(&output);
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[0]["result"]["capabilities"]["textDocumentSync"], json!(1));
+        assert_eq!(
+            messages[1]["params"],
+            json!({
+                "uri": uri,
+                "diagnostics": [{
+                    "range": { "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 28 } },
+                    "severity": 1,
+                    "source": "hldr",
+                    "message": "record `person.kev` not found on line 2",
+                }],
+            }),
+        );
+        assert_eq!(messages[2]["params"]["diagnostics"], json!([]));
+        assert_eq!(
+            messages[3]["result"],
+            json!({
+                "uri": path_uri(&dir.join("person.hldr")),
+                "range": { "start": { "line": 1, "character": 2 }, "end": { "line": 1, "character": 2 } },
+            }),
+        );
+        assert_eq!(
+            messages[4]["result"]["data"],
+            json!([
+                0, 0, 5, 4, 0, 0, 6, 3, 3, 0, 0, 4, 1, 8, 0,
+                1, 2, 1, 8, 0, 0, 2, 9, 3, 0, 0, 10, 1, 8, 0, 0, 1, 6, 3, 0,
+                0, 6, 1, 8, 0, 0, 1, 5, 3, 0, 0, 5, 1, 8, 0, 0, 1, 2, 3, 0, 0, 3, 1, 8, 0,
+                1, 0, 1, 8, 0,
+            ]),
+        );
+        assert_eq!(messages[5]["error"]["code"], json!(-32601));
+    }
+}
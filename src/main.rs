@@ -1,18 +1,274 @@
 use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
 
 use clap::{crate_version, Parser};
+use log::{Level, LevelFilter, Log, Metadata, Record};
 
 /// Placeholder: Easy PostgreSQL data seeding
 #[derive(Parser, Debug)]
 #[clap(version = crate_version!())]
 struct Command {
+    /// Load records that are never referenced with COPY instead of INSERT, which is much
+    /// faster for large data files
+    #[clap(long = "bulk")]
+    bulk: bool,
+
+    /// Keep the analyzed data files in `.hldr-cache`, so that later runs skip parsing and
+    /// analyzing them until any of them change
+    #[clap(long = "cache")]
+    cache: bool,
+
+    /// Select each SQL fragment only once per load, reusing its value wherever the same SQL
+    /// appears again, unless the fragment is declared `volatile`
+    #[clap(long = "cache-fragments")]
+    cache_fragments: bool,
+
+    /// Only check that the data files are valid, without connecting to the database, exiting
+    /// with an error if they are not
+    #[clap(long = "check")]
+    check: bool,
+
+    /// Insert each record within a savepoint, so that records that fail to insert are rolled
+    /// back while loading continues, then report every failure at once and roll back the
+    /// transaction, eg. to check data files against a new version of a schema
+    #[clap(long = "collect-errors")]
+    collect_errors: bool,
+
     /// Commit the transaction
     #[clap(long = "commit")]
     commit: Option<bool>,
 
-    /// Path to the .hldr data file to load [default: place.hldr if not specified in options file]
-    #[clap(short = 'f', long = "data-file", name = "DATA-FILE")]
-    file: Option<PathBuf>,
+    /// Print a summary of the rows to insert and tables to truncate and ask for confirmation
+    /// before committing
+    #[clap(long = "confirm", conflicts_with = "yes")]
+    confirm: bool,
+
+    /// Create every schema declared in the data files with `CREATE SCHEMA IF NOT EXISTS`
+    /// before loading anything, eg. for fresh databases in CI
+    #[clap(long = "create-schemas")]
+    create_schemas: bool,
+
+    /// Create every table declared in the data files with `CREATE TABLE IF NOT EXISTS` before
+    /// loading anything, with the columns that records give values for and types inferred from
+    /// those values, eg. for prototyping before the real schema exists
+    #[clap(long = "create-tables")]
+    create_tables: bool,
+
+    /// Warn about anonymous records with the same attributes as an earlier anonymous record in
+    /// the same table, which are usually copy-paste errors
+    #[clap(long = "dedupe")]
+    dedupe: bool,
+
+    /// Defer checking deferrable constraints, such as foreign keys between tables that reference
+    /// each other, until the transaction is committed
+    #[clap(long = "defer-constraints")]
+    defer_constraints: bool,
+
+    /// Disable triggers while loading, enabling them again once everything is loaded, where
+    /// `user` disables each table's triggers other than those that enforce constraints, `all`
+    /// disables all of them, and `replica` sets `session_replication_role`, both of which only
+    /// superusers can do
+    #[clap(
+        long = "disable-triggers",
+        name = "DISABLE-TRIGGERS",
+        value_name = "MODE",
+        possible_values = ["user", "all", "replica"]
+    )]
+    disable_triggers: Option<hldr::DisableTriggers>,
+
+    /// Print the statements that would be executed without connecting to the database
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+
+    /// Path to a .hldr data file or directory of them, or a JSON or YAML fixture, to load,
+    /// which can be repeated to load multiple in order [default: place.hldr if not specified
+    /// in options file]
+    #[clap(
+        short = 'f',
+        long = "data-file",
+        name = "DATA-FILE",
+        multiple_occurrences = true
+    )]
+    files: Vec<PathBuf>,
+
+    /// Isolation level of the transaction [default: the database's default]
+    #[clap(
+        long = "isolation-level",
+        name = "LEVEL",
+        possible_values = ["read-committed", "repeatable-read", "serializable"]
+    )]
+    isolation_level: Option<hldr::IsolationLevel>,
+
+    /// Load each table within a savepoint, so that if a table fails to load, the error is
+    /// reported and the table is skipped while the rest of the data files are still loaded
+    #[clap(long = "keep-going")]
+    keep_going: bool,
+
+    /// Load at most this many records from each table, along with any named records that
+    /// they reference, eg. to quickly smoke test schema changes against large data files
+    #[clap(long = "limit-per-table", name = "LIMIT-PER-TABLE", value_name = "N")]
+    limit_per_table: Option<usize>,
+
+    /// How long to wait to acquire a lock before failing, eg. `5s`, set as the transaction's
+    /// `lock_timeout`
+    #[clap(long = "lock-timeout", name = "LOCK-TIMEOUT", value_name = "TIME")]
+    lock_timeout: Option<String>,
+
+    /// Append every statement executed while loading, including SQL fragments and settings, to
+    /// the file with the time it was executed, eg. to audit what was done to shared databases
+    #[clap(long = "log-sql", name = "LOG-SQL", value_name = "FILE")]
+    log_sql: Option<PathBuf>,
+
+    /// The time that `now()` and `ago(...)` are relative to instead of the time of the
+    /// transaction, eg. `2024-01-01T00:00:00Z`, so that repeated loads give exactly the same data
+    #[clap(long = "now", name = "NOW", value_name = "TIME")]
+    fixed_now: Option<String>,
+
+    /// How to handle inserts that conflict with existing rows, where `ignore` skips records
+    /// and `update` upserts them using each table's primary key unless it declares its own
+    /// conflict target
+    #[clap(long = "on-conflict", name = "MODE", possible_values = ["error", "ignore", "update"])]
+    on_conflict: Option<hldr::OnConflict>,
+
+    /// Only load this fraction of each table's records, between 0 and 1, which are always the
+    /// same records spread evenly across the table, along with any named records that they
+    /// reference
+    #[clap(long = "sample", name = "SAMPLE", value_name = "FRACTION")]
+    sample: Option<f64>,
+
+    /// Only load records tagged with `@tag(...)` with this tag, themselves or through their
+    /// table, which can be repeated or given as a comma-separated list to load records with
+    /// any of them
+    #[clap(
+        long = "only-tags",
+        name = "ONLY-TAGS",
+        value_name = "TAG",
+        multiple_occurrences = true,
+        use_value_delimiter = true
+    )]
+    only_tags: Vec<String>,
+
+    /// Write the statements that would load the data files to a SQL file, wrapped in a
+    /// transaction, instead of executing them, only connecting to the database to look up
+    /// primary keys if records reference other records themselves or are upserted
+    #[clap(long = "output", name = "OUTPUT", value_name = "FILE", conflicts_with_all = &["dry-run", "stream"])]
+    output: Option<PathBuf>,
+
+    /// A profile whose section of the data files, declared with `profile ... (...)`, is loaded
+    /// along with everything outside of any profile's section, which can be repeated or given
+    /// as a comma-separated list
+    #[clap(
+        long = "profile",
+        name = "PROFILE",
+        value_name = "PROFILE",
+        multiple_occurrences = true,
+        use_value_delimiter = true
+    )]
+    profiles: Vec<String>,
+
+    /// Refresh the materialized views that depend on the tables records are loaded into,
+    /// directly or through other views, with `REFRESH MATERIALIZED VIEW` once everything
+    /// is loaded
+    #[clap(long = "refresh-matviews")]
+    refresh_matviews: bool,
+
+    /// Print a report of what was loaded to stdout once finished, including the rows of named
+    /// records, how long loading took, and whether the transaction was committed
+    #[clap(
+        long = "report",
+        name = "FORMAT",
+        possible_values = ["json"],
+        conflicts_with_all = &["check", "dry-run"]
+    )]
+    report: Option<ReportFormat>,
+
+    /// Reset the sequences of columns that records give values for, eg. `serial` or identity
+    /// primary keys, to the columns' greatest values once everything is loaded, so that rows
+    /// inserted later are not given values that already exist
+    #[clap(long = "reset-sequences")]
+    reset_sequences: bool,
+
+    /// The role to load the data files as, switched to for the transaction with `SET LOCAL
+    /// ROLE` [default: the connection's role, unless tables declare one with `as role`]
+    #[clap(long = "role", name = "ROLE", value_name = "ROLE")]
+    role: Option<String>,
+
+    /// A schema to resolve unqualified tables against, set as the transaction's `search_path`,
+    /// which can be repeated or given as a comma-separated list [default: the connection's
+    /// search path, unless the data files declare one with `set search_path`]
+    #[clap(
+        long = "search-path",
+        name = "SEARCH-PATH",
+        value_name = "SCHEMA",
+        multiple_occurrences = true,
+        use_value_delimiter = true
+    )]
+    search_path: Vec<String>,
+
+    /// Skip records that give values for the columns of this key if a row with the same values
+    /// already exists, which other records then reference instead, where `email` applies to
+    /// every table and `person=first_name,last_name` only to `person`, which can be repeated
+    #[clap(
+        long = "skip-existing",
+        name = "SKIP-EXISTING",
+        value_name = "KEY",
+        multiple_occurrences = true
+    )]
+    skip_existing: Vec<hldr::SkipExisting>,
+
+    /// Skip records tagged with `@tag(...)` with this tag, themselves or through their table,
+    /// which can be repeated or given as a comma-separated list
+    #[clap(
+        long = "skip-tags",
+        name = "SKIP-TAGS",
+        value_name = "TAG",
+        multiple_occurrences = true,
+        use_value_delimiter = true
+    )]
+    skip_tags: Vec<String>,
+
+    /// How long any statement can run before failing, eg. `30s`, set as the transaction's
+    /// `statement_timeout`
+    #[clap(long = "statement-timeout", name = "STATEMENT-TIMEOUT", value_name = "TIME")]
+    statement_timeout: Option<String>,
+
+    /// Load the records of each data file as they are parsed rather than once every data file
+    /// has been, so that very large data files are never held in memory as a whole, although
+    /// records can then only reference and extend records declared before them
+    #[clap(long = "stream", conflicts_with_all = &["cache", "confirm", "create-tables", "dedupe", "dry-run", "LIMIT-PER-TABLE", "SAMPLE", "strict-numbers", "truncate", "verify-schema"])]
+    stream: bool,
+
+    /// Fail rather than warn when numbers would be rounded to fit their columns, eg. `1.005`
+    /// for a `numeric(5,2)` column, verifying the schema as --verify-schema does
+    #[clap(long = "strict-numbers")]
+    strict_numbers: bool,
+
+    /// The transaction's `synchronous_commit` setting, eg. `off` to commit faster at the risk
+    /// of losing the transaction if the database crashes
+    #[clap(long = "synchronous-commit", name = "SYNCHRONOUS-COMMIT", value_name = "MODE")]
+    synchronous_commit: Option<String>,
+
+    /// Print how long lexing, parsing, and analyzing the data files and loading each table
+    /// took to stderr once finished, along with the rows loaded per second, eg. to tell
+    /// whether slowness comes from hldr or the database
+    #[clap(long = "timings", conflicts_with_all = &["check", "dry-run"])]
+    timings: bool,
+
+    /// Truncate every table in the data files before loading anything, restarting their
+    /// identities and cascading to tables that reference them, rather than only those declared
+    /// with `truncate`
+    #[clap(long = "truncate")]
+    truncate: bool,
+
+    /// Verify that every schema, table, and column exists and that values are plausibly
+    /// compatible with their columns' types before loading anything, reporting all mismatches
+    #[clap(long = "verify-schema")]
+    verify_schema: bool,
+
+    /// Commit without asking for confirmation, even if `confirm` is set in the options file
+    #[clap(short = 'y', long = "yes")]
+    yes: bool,
 
     /// Path to the optional .toml options file
     #[clap(
@@ -23,22 +279,154 @@ struct Command {
     )]
     opts_file: PathBuf,
 
-    /// Database connection string, either key/value pair or URI style
+    /// Print each statement as it is executed, in addition to the number of rows inserted
+    /// into each table
+    #[clap(short = 'v', long = "verbose", conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Print nothing but errors
+    #[clap(short = 'q', long = "quiet")]
+    quiet: bool,
+
+    /// Database connection string, either key/value pair or URI style [default: DATABASE_URL or
+    /// the PGHOST, PGPORT, PGUSER, PGPASSWORD, and PGDATABASE environment variables if not
+    /// specified in options file]
     #[clap(short = 'c', long = "database-conn", name = "CONN")]
     database_conn: Option<String>,
+
+    #[clap(subcommand)]
+    subcommand: Option<Subcommand>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Subcommand {
+    /// Print a data file generated from the rows of an existing database, where rows
+    /// referenced through foreign keys are declared as named records and referenced by name
+    Dump(DumpCommand),
+    /// Check data files for declarations that are likely mistakes, such as named records that
+    /// are never referenced, with the severity of each rule configured in the options file
+    Lint,
+    /// Run a language server over stdin and stdout, which reports errors in data files as
+    /// they are edited, goes from references to the records they reference, and completes
+    /// the names of schemas, tables, and columns in the database
+    Lsp,
+    /// Print the parse tree of a data file, which can be deserialized into the same tree,
+    /// eg. to inspect it or to generate trees to load from other tools
+    Parse(ParseCommand),
+}
+
+#[derive(clap::Args, Debug)]
+struct DumpCommand {
+    /// Dump every table in the schema, which can be repeated [default: public if no tables
+    /// are given]
+    #[clap(short = 's', long = "schema", name = "SCHEMA", multiple_occurrences = true)]
+    schemas: Vec<String>,
+
+    /// Dump the table, which can be qualified with its schema and repeated
+    #[clap(short = 't', long = "table", name = "TABLE", multiple_occurrences = true)]
+    tables: Vec<String>,
+
+    /// Dump at most this many rows from each table
+    #[clap(long = "limit", name = "ROWS")]
+    limit: Option<u64>,
+
+    /// Only dump the rows of a table that meet a condition, given as `table:condition`, eg.
+    /// `person:age > 30`, which can be repeated
+    #[clap(long = "where", name = "FILTER", multiple_occurrences = true)]
+    filters: Vec<hldr::Filter>,
+
+    /// Database connection string [default: as when loading data files]
+    #[clap(short = 'c', long = "database-conn", name = "CONN")]
+    database_conn: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct ParseCommand {
+    /// The format to print the parse tree in
+    #[clap(long = "format", name = "FORMAT", possible_values = ["json", "yaml"], default_value = "json")]
+    format: TreeFormat,
+
+    /// The data file to parse
+    #[clap(name = "FILE", parse(from_os_str))]
+    file: PathBuf,
+}
+
+#[derive(Debug)]
+enum TreeFormat {
+    Json,
+    Yaml,
+}
+
+impl FromStr for TreeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "yaml" => Ok(Self::Yaml),
+            _ => Err(format!("invalid parse tree format `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ReportFormat {
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            _ => Err(format!("invalid report format `{}`", s)),
+        }
+    }
+}
+
+/// Writes hldr's own log messages to stderr, leaving stdout for output such as
+/// dry runs, where anything other than informational messages is prefixed with
+/// its level.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level() && metadata.target().starts_with("hldr")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        match record.level() {
+            Level::Info => eprintln!("{}", record.args()),
+            level => eprintln!("{}: {}", level.as_str().to_lowercase(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
 }
 
 fn main() {
     let cmd = Command::parse();
+
+    log::set_logger(&Logger).unwrap();
+    log::set_max_level(match (cmd.verbose, cmd.quiet) {
+        (true, _) => LevelFilter::Debug,
+        (_, true) => LevelFilter::Error,
+        _ => LevelFilter::Info,
+    });
     let options = {
         let mut options = hldr::Options::new(&cmd.opts_file)
             .unwrap() // consume result
             .unwrap_or_default();
 
-        // The options file can specify the data file and connection string,
+        // The options file can specify the data files and connection string,
         // which should be overridden by command-line options
-        if let Some(f) = cmd.file {
-            options.data_file = f.clone();
+        if !cmd.files.is_empty() {
+            options.data_files = cmd.files;
         }
 
         if let Some(dc) = cmd.database_conn {
@@ -49,10 +437,196 @@ fn main() {
             options.commit = commit;
         }
 
+        if cmd.confirm {
+            options.confirm = true;
+        }
+
+        if cmd.create_schemas {
+            options.create_schemas = true;
+        }
+
+        if cmd.create_tables {
+            options.create_tables = true;
+        }
+
+        if cmd.yes {
+            options.confirm = false;
+        }
+
+        if cmd.dry_run {
+            options.dry_run = true;
+        }
+
+        if cmd.dedupe {
+            options.dedupe = true;
+        }
+
+        if cmd.defer_constraints {
+            options.defer_constraints = true;
+        }
+
+        if cmd.disable_triggers.is_some() {
+            options.disable_triggers = cmd.disable_triggers;
+        }
+
+        if cmd.bulk {
+            options.bulk = true;
+        }
+
+        if cmd.cache {
+            options.cache = true;
+        }
+
+        if cmd.cache_fragments {
+            options.cache_fragments = true;
+        }
+
+        if cmd.collect_errors {
+            options.collect_errors = true;
+        }
+
+        if cmd.keep_going {
+            options.keep_going = true;
+        }
+
+        if cmd.refresh_matviews {
+            options.refresh_matviews = true;
+        }
+
+        if cmd.reset_sequences {
+            options.reset_sequences = true;
+        }
+
+        if cmd.stream {
+            options.stream = true;
+        }
+
+        if cmd.strict_numbers {
+            options.strict_numbers = true;
+        }
+
+        if cmd.truncate {
+            options.truncate = true;
+        }
+
+        if cmd.verify_schema {
+            options.verify_schema = true;
+        }
+
+        if let Some(on_conflict) = cmd.on_conflict {
+            options.on_conflict = on_conflict;
+        }
+
+        if cmd.output.is_some() {
+            options.output = cmd.output;
+        }
+
+        if cmd.isolation_level.is_some() {
+            options.isolation_level = cmd.isolation_level;
+        }
+
+        if cmd.limit_per_table.is_some() {
+            options.limit_per_table = cmd.limit_per_table;
+        }
+
+        if cmd.sample.is_some() {
+            options.sample = cmd.sample;
+        }
+
+        if cmd.lock_timeout.is_some() {
+            options.lock_timeout = cmd.lock_timeout;
+        }
+
+        if cmd.log_sql.is_some() {
+            options.log_sql = cmd.log_sql;
+        }
+
+        if cmd.fixed_now.is_some() {
+            options.fixed_now = cmd.fixed_now;
+        }
+
+        if cmd.role.is_some() {
+            options.role = cmd.role;
+        }
+
+        if !cmd.search_path.is_empty() {
+            options.search_path = Some(cmd.search_path);
+        }
+
+        if !cmd.profiles.is_empty() {
+            options.profiles = cmd.profiles;
+        }
+
+        if !cmd.only_tags.is_empty() {
+            options.only_tags = cmd.only_tags;
+        }
+
+        if !cmd.skip_existing.is_empty() {
+            options.skip_existing = cmd.skip_existing;
+        }
+
+        if !cmd.skip_tags.is_empty() {
+            options.skip_tags = cmd.skip_tags;
+        }
+
+        if cmd.statement_timeout.is_some() {
+            options.statement_timeout = cmd.statement_timeout;
+        }
+
+        if cmd.synchronous_commit.is_some() {
+            options.synchronous_commit = cmd.synchronous_commit;
+        }
+
         options
     };
 
-    if let Err(e) = hldr::place(&options) {
-        eprintln!("Error: {}", e);
+    let result = if let Some(Subcommand::Dump(dump)) = cmd.subcommand {
+        let dump_options = hldr::DumpOptions {
+            database_conn: dump.database_conn.unwrap_or(options.database_conn),
+            schemas: dump.schemas,
+            tables: dump.tables,
+            limit: dump.limit,
+            filters: dump.filters,
+        };
+        hldr::dump(&dump_options).map(|data_file| print!("{}", data_file))
+    } else if let Some(Subcommand::Lint) = cmd.subcommand {
+        hldr::lint(&options).map(|lints| {
+            for (i, lint) in lints.iter().enumerate() {
+                if i > 0 {
+                    eprintln!();
+                }
+                eprintln!("{}", lint.render());
+            }
+
+            if lints.iter().any(|lint| lint.severity == hldr::lint::Severity::Error) {
+                process::exit(1);
+            }
+        })
+    } else if let Some(Subcommand::Parse(parse)) = cmd.subcommand {
+        hldr::parse_file(&parse.file).map(|tree| match parse.format {
+            TreeFormat::Json => println!("{}", tree.to_json()),
+            TreeFormat::Yaml => print!("{}", tree.to_yaml()),
+        })
+    } else if let Some(Subcommand::Lsp) = cmd.subcommand {
+        hldr::lsp(&options)
+    } else if cmd.check {
+        hldr::check(&options)
+    } else {
+        hldr::place(&options).map(|report| {
+            // Logged like the rest of the progress, so that `--quiet` silences it too
+            if cmd.timings {
+                log::info!("{}", report.timings_summary());
+            }
+
+            match cmd.report {
+                Some(ReportFormat::Json) => println!("{}", report.to_json()),
+                None => {}
+            }
+        })
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e.render());
+        process::exit(1);
     }
 }
@@ -0,0 +1,363 @@
+//! Builds parse trees in Rust rather than lexing and parsing them from text,
+//! eg. to declare seed data from a test harness and load it with [`crate::load_tree`].
+//!
+//! ```
+//! use hldr::parser::builder::{boolean, reference, text, ParseTreeBuilder};
+//!
+//! let tree = ParseTreeBuilder::new()
+//!     .schema("public")
+//!     .table("person")
+//!     .record("kevin")
+//!     .attr("name", text("Kevin"))
+//!     .attr("active", boolean(true))
+//!     .end_schema()
+//!     .table("pet")
+//!     .anonymous_record()
+//!     .attr("person_id", reference("public.person.kevin.id"))
+//!     .build();
+//!
+//! assert_eq!(tree.nodes.len(), 2);
+//! ```
+
+use super::nodes::*;
+use std::fmt::Display;
+
+/// Builds a [`ParseTree`] one node at a time, where each table is added to the
+/// most recent schema (until the schema is ended), each record to the most recent
+/// table, and each attribute to the most recent record.
+///
+/// Names are used as-is, just as quoted identifiers are in data files.
+#[derive(Debug, Default)]
+pub struct ParseTreeBuilder {
+    tree: ParseTree,
+    in_schema: bool,
+    namespace: Option<String>,
+}
+
+impl ParseTreeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts the following tables in the namespace, as with `namespace ...` at the top
+    /// of a data file.
+    pub fn namespace(mut self, name: &str) -> Self {
+        self.namespace = Some(name.to_owned());
+        self
+    }
+
+    /// Sets the search path that unqualified tables are resolved against, as with
+    /// `set search_path ...` at the top of a data file.
+    pub fn search_path(mut self, schemas: &[&str]) -> Self {
+        let schemas = schemas.iter().map(|s| s.to_string()).collect();
+        self.tree.search_paths = vec![SearchPath { schemas, position: None }];
+        self
+    }
+
+    /// Declares a variable that values can use with [`variable`], as with
+    /// `let name = ...` at the top of a data file.
+    pub fn variable(mut self, name: &str, value: Value) -> Self {
+        self.tree.variables.push(Variable { name: name.to_owned(), value, position: None });
+        self
+    }
+
+    /// Adds SQL that is executed as-is before anything is loaded, as with
+    /// ``before `...` `` in a data file.
+    pub fn before(mut self, sql: &str) -> Self {
+        self.tree.hooks.push(Hook { timing: HookTiming::Before, sql: sql.to_owned(), position: None });
+        self
+    }
+
+    /// Adds SQL that is executed as-is once everything is loaded, as with
+    /// ``after `...` `` in a data file.
+    pub fn after(mut self, sql: &str) -> Self {
+        self.tree.hooks.push(Hook { timing: HookTiming::After, sql: sql.to_owned(), position: None });
+        self
+    }
+
+    /// Starts a schema, which the following tables are added to.
+    pub fn schema(self, name: &str) -> Self {
+        self.push_schema(Schema::new(name.to_owned(), None))
+    }
+
+    /// Starts a schema with an alias for references.
+    pub fn schema_as(self, name: &str, alias: &str) -> Self {
+        self.push_schema(Schema::new(name.to_owned(), Some(alias.to_owned())))
+    }
+
+    /// Ends the current schema, so that the following tables are top-level tables.
+    pub fn end_schema(mut self) -> Self {
+        self.in_schema = false;
+        self
+    }
+
+    /// Starts a table, which the following records are added to.
+    pub fn table(self, name: &str) -> Self {
+        self.push_table(Table::new(name.to_owned(), None, None))
+    }
+
+    /// Starts a table with an alias for references.
+    pub fn table_as(self, name: &str, alias: &str) -> Self {
+        self.push_table(Table::new(name.to_owned(), Some(alias.to_owned()), None))
+    }
+
+    /// Declares the conflict target of the current table, as with `conflict (...)`.
+    ///
+    /// Panics if no table has been started.
+    pub fn conflict(mut self, columns: &[&str]) -> Self {
+        let columns = columns.iter().map(|c| c.to_string()).collect();
+        self.current_table().conflict_target = Some(columns);
+        self
+    }
+
+    /// Loads the current table's records as the role, as with `as role ...`.
+    ///
+    /// Panics if no table has been started.
+    pub fn role(mut self, role: &str) -> Self {
+        self.current_table().role = Some(role.to_owned());
+        self
+    }
+
+    /// Inserts the current table's records with the setting, as with `with (name 'value')`.
+    ///
+    /// Panics if no table has been started.
+    pub fn setting(mut self, name: &str, value: &str) -> Self {
+        let setting = Setting {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        };
+        self.current_table().settings.push(setting);
+        self
+    }
+
+    /// Truncates the current table before anything is loaded, as with `truncate`.
+    ///
+    /// Panics if no table has been started.
+    pub fn truncate(mut self) -> Self {
+        self.current_table().truncate = true;
+        self
+    }
+
+    /// Adds an attribute to the current table's `$defaults`.
+    ///
+    /// Panics if no table has been started.
+    pub fn default_attr(mut self, name: &str, value: Value) -> Self {
+        self.current_table().defaults.push(Attribute::new(name.to_owned(), value));
+        self
+    }
+
+    /// Starts a named record, which the following attributes are added to.
+    ///
+    /// Panics if no table has been started.
+    pub fn record(mut self, name: &str) -> Self {
+        self.current_table().nodes.push(Record::new(Some(name.to_owned())));
+        self
+    }
+
+    /// Starts an anonymous record, which the following attributes are added to.
+    ///
+    /// Panics if no table has been started.
+    pub fn anonymous_record(mut self) -> Self {
+        self.current_table().nodes.push(Record::new(None));
+        self
+    }
+
+    /// Starts an anonymous record that is repeated `quantity` times, as with `_ * N (...)`.
+    ///
+    /// Panics if no table has been started.
+    pub fn repeated_record(mut self, quantity: usize) -> Self {
+        let mut record = Record::new(None);
+        record.quantity = Some(quantity);
+        self.current_table().nodes.push(record);
+        self
+    }
+
+    /// Adds an attribute to the current record.
+    ///
+    /// Panics if no record has been started.
+    pub fn attr(mut self, name: &str, value: Value) -> Self {
+        self.current_table()
+            .nodes
+            .last_mut()
+            .expect("no record has been started")
+            .nodes
+            .push(Attribute::new(name.to_owned(), value));
+        self
+    }
+
+    pub fn build(self) -> ParseTree {
+        self.tree
+    }
+
+    fn push_schema(mut self, schema: Schema) -> Self {
+        self.tree.nodes.push(StructuralNode::Schema(Box::new(schema)));
+        self.in_schema = true;
+        self
+    }
+
+    fn push_table(mut self, mut table: Table) -> Self {
+        table.namespace = self.namespace.clone();
+
+        match self.tree.nodes.last_mut() {
+            Some(StructuralNode::Schema(schema)) if self.in_schema => schema.nodes.push(table),
+            _ => self.tree.nodes.push(StructuralNode::Table(Box::new(table))),
+        }
+        self
+    }
+
+    fn current_table(&mut self) -> &mut Table {
+        let table = match self.tree.nodes.last_mut() {
+            Some(StructuralNode::Schema(schema)) if self.in_schema => schema.nodes.last_mut(),
+            Some(StructuralNode::Table(table)) if !self.in_schema => Some(table.as_mut()),
+            _ => None,
+        };
+
+        table.expect("no table has been started")
+    }
+}
+
+pub fn boolean(b: bool) -> Value {
+    Value::Bool(b)
+}
+
+/// The column's default, which is inserted as `DEFAULT` rather than a value.
+pub fn default() -> Value {
+    Value::Default
+}
+
+/// A SQL fragment, written without the `SELECT` keyword just as it is between backticks.
+pub fn fragment(sql: &str) -> Value {
+    Value::SqlFragment(sql.to_owned())
+}
+
+/// An expression applying the operator to both values as-is, without regard to the
+/// precedence of any operators within them.
+pub fn expression(left: Value, operator: Operator, right: Value) -> Value {
+    Value::Expression(Box::new(Expression { left, operator, right }))
+}
+
+pub fn null() -> Value {
+    Value::Null
+}
+
+pub fn number(n: impl Display) -> Value {
+    Value::Number(n.to_string())
+}
+
+/// A reference written as it would be after the `@`, eg. `record.column`,
+/// `schema.table.record.` with an implicit column, or `billing::record.column`.
+///
+/// Panics if the reference does not have between one and four parts.
+pub fn reference(path: &str) -> Value {
+    let (namespace, path) = match path.split_once("::") {
+        Some((namespace, path)) => (Some(namespace.to_owned()), path),
+        None => (None, path),
+    };
+    let parts: Vec<&str> = path.split('.').collect();
+    let column = |c: &str| match c {
+        "" => ReferencedColumn::Implicit,
+        c => ReferencedColumn::Explicit(c.to_owned()),
+    };
+
+    Value::Reference(match parts[..] {
+        [c] if namespace.is_none() => Reference::ColumnLevel(ColumnLevelReference { column: c.to_owned() }),
+        [r, c] => Reference::RecordLevel(RecordLevelReference {
+            namespace,
+            record: r.to_owned(),
+            column: column(c),
+        }),
+        [t, r, c] => Reference::TableLevel(TableLevelReference {
+            namespace,
+            table: t.to_owned(),
+            record: r.to_owned(),
+            column: column(c),
+        }),
+        [s, t, r, c] => Reference::SchemaLevel(SchemaLevelReference {
+            namespace,
+            schema: s.to_owned(),
+            table: t.to_owned(),
+            record: r.to_owned(),
+            column: column(c),
+        }),
+        _ => panic!("invalid reference `{}`", path),
+    })
+}
+
+pub fn text(s: &str) -> Value {
+    // Text values retain their quotes as they would from the lexer
+    Value::Text(format!("'{}'", s.replace('\'', "''")))
+}
+
+/// The value of a variable declared with [`ParseTreeBuilder::variable`], written
+/// without the `$`.
+pub fn variable(name: &str) -> Value {
+    Value::Variable(name.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+    use pretty_assertions::assert_eq;
+
+    fn parsed(input: &str) -> ParseTree {
+        let tokens = lexer::tokenize(input.chars()).unwrap();
+        parser::parse(tokens.into_iter()).unwrap()
+    }
+
+    #[test]
+    fn test_build() {
+        let tree = ParseTreeBuilder::new()
+            .schema_as("schema1", "s")
+            .table("table1")
+            .record("record1")
+            .attr("col1", text("isn't"))
+            .attr("col2", number(1.5))
+            .attr("col3", reference("col1"))
+            .attr("col4", expression(reference("col2"), Operator::Multiply, number(2)))
+            .table_as("table2", "t2")
+            .role("admin")
+            .setting("app.tenant_id", "42")
+            .truncate()
+            .conflict(&["col1"])
+            .default_attr("col1", boolean(false))
+            .anonymous_record()
+            .attr("col2", reference("s.table1.record1."))
+            .end_schema()
+            .table("table3")
+            .repeated_record(2)
+            .attr("col1", null())
+            .attr("col2", fragment("now()"))
+            .attr("col3", reference("s.t2.record2.col1"))
+            .record("record2")
+            .build();
+
+        assert_eq!(
+            tree,
+            parsed(
+                r#"
+                schema schema1 as s (
+                    table table1 (
+                        record1 (col1 'isn''t', col2 1.5
+                        col3 @col1, col4 @col2 * 2)
+                    )
+                    table table2 as t2 as role admin with (app.tenant_id '42') truncate conflict (col1) (
+                        $defaults (col1 false)
+                        (col2 @s.table1.record1.)
+                    )
+                )
+                table table3 (
+                    _ * 2 (col1 null, col2 `now()`, col3 @s.t2.record2.col1)
+                    record2 ()
+                )
+            "#
+            ),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no table has been started")]
+    fn test_record_without_table() {
+        ParseTreeBuilder::new().schema("schema1").end_schema().record("record1");
+    }
+}
@@ -10,18 +10,58 @@ pub enum ParseErrorKind {
     // Would that work for eof at all? An EOF token should work..
     ExpectedAliasName(Token),
     ExpectedAliasOrScope(Token),
+    ExpectedArgument(Token),
+    ExpectedCloseArguments(Token),
     ExpectedCloseAttribute(Token),
+    ExpectedColumns(Token),
+    ExpectedEquals(Token),
+    ExpectedFrom(Token),
+    ExpectedHookSql(Token),
     ExpectedIdentifier(Token),
+    ExpectedImportPath(Token),
+    ExpectedIncludePath(Token),
+    ExpectedInterval(Token),
+    ExpectedOperand(Token),
+    ExpectedQuantity(Token),
+    ExpectedReference(Token),
+    ExpectedRole(Token),
+    ExpectedRowCount(Token),
+    ExpectedRows(Token),
     ExpectedScope(Token),
     ExpectedSchemaName(Token),
+    ExpectedSetting(Token),
+    ExpectedSettingValue(Token),
+    ExpectedSqlFragment(Token),
     ExpectedTableName(Token),
+    ExpectedTag(Token),
+    ExpectedTagged(Token),
     ExpectedValue(Token),
+    ExpectedVariableValue(Token),
+    ExpectedWhere(Token),
+    ExtraPositionalValue(Token),
     UnexpectedInSchema(Token),
     UnexpectedInTable(Token),
     UnexpectedInRecord(Token),
     UnexpectedToken(Token),
+    InvalidUuidNamespace(Token),
     // But this one breaks the Token pattern
     RecordNameQuoted(String, Position),
+    // As does this, with the namespace and the column referenced in it
+    NamespacedColumn(String, String, Position),
+    // As does this, with the name of the column used for a shorthand reference
+    InvalidShorthandColumn(String, Position),
+    // As does this, with the expected and found counts of values for the table's
+    // column set, and the closing parenthesis of the record
+    WrongValueCount(usize, usize, Token),
+    // As does this, with the function name, expected and found argument counts,
+    // and the closing parenthesis
+    WrongArgumentCount(String, usize, usize, Token),
+    // As does this, with the expected and found counts of columns referenced for
+    // several columns at once, and the closing parenthesis of the referenced columns
+    WrongColumnCount(usize, usize, Token),
+    // As does this, with what cannot be declared when streaming tables as they are
+    // parsed, eg. includes, and where it was declared
+    Unstreamable(&'static str, Position),
 }
 
 impl fmt::Display for ParseErrorKind {
@@ -35,6 +75,12 @@ impl fmt::Display for ParseErrorKind {
             ExpectedAliasOrScope(t) => {
                 write!(f, "expected alias or opening parenthesis, found {}", t.kind)
             }
+            ExpectedArgument(t) => {
+                write!(f, "expected string for function argument, found {}", t.kind)
+            }
+            ExpectedCloseArguments(t) => {
+                write!(f, "expected comma or closing parenthesis, found {}", t.kind)
+            }
             ExpectedCloseAttribute(t) => {
                 write!(
                     f,
@@ -42,21 +88,99 @@ impl fmt::Display for ParseErrorKind {
                     t.kind
                 )
             }
+            ExpectedColumns(t) => {
+                write!(f, "expected columns in parentheses to reference for each column, found {}", t.kind)
+            }
+            ExpectedEquals(t) => {
+                write!(f, "expected `=` after variable name, found {}", t.kind)
+            }
+            ExpectedFrom(t) => {
+                write!(f, "expected `from` after `delete`, found {}", t.kind)
+            }
+            ExpectedHookSql(t) => {
+                write!(f, "expected SQL fragment without embedded values for hook, found {}", t.kind)
+            }
             ExpectedIdentifier(t) => {
                 write!(f, "expected identifier, found {}", t.kind)
             }
+            ExpectedImportPath(t) => {
+                write!(f, "expected string for imported file path, found {}", t.kind)
+            }
+            ExpectedIncludePath(t) => {
+                write!(f, "expected string for included file path, found {}", t.kind)
+            }
+            ExpectedInterval(t) => {
+                write!(f, "expected string for interval, found {}", t.kind)
+            }
+            ExpectedOperand(t) => {
+                write!(f, "expected number, string, reference, or function for operand, found {}", t.kind)
+            }
+            ExpectedQuantity(t) => {
+                write!(f, "expected positive integer for record quantity, found {}", t.kind)
+            }
+            ExpectedReference(t) => {
+                write!(f, "expected reference for columns, found {}", t.kind)
+            }
+            ExpectedRole(t) => {
+                write!(f, "expected `role`, found {}", t.kind)
+            }
+            ExpectedRowCount(t) => {
+                write!(f, "expected non-negative integer for expected row count, found {}", t.kind)
+            }
+            ExpectedRows(t) => {
+                write!(f, "expected `rows` after expected row count, found {}", t.kind)
+            }
             ExpectedSchemaName(t) => {
                 write!(f, "expected identifier for schema name, found {}", t.kind)
             }
+            ExpectedSetting(t) => {
+                write!(f, "expected `search_path` for setting, found {}", t.kind)
+            }
+            ExpectedSettingValue(t) => {
+                write!(f, "expected string, number, or boolean for setting value, found {}", t.kind)
+            }
+            ExpectedSqlFragment(t) => {
+                write!(f, "expected SQL fragment after `volatile`, found {}", t.kind)
+            }
             ExpectedTableName(t) => {
                 write!(f, "expected identifier for table name, found {}", t.kind)
             }
+            ExpectedTag(t) => {
+                write!(f, "expected `tag`, found {}", t.kind)
+            }
+            ExpectedTagged(t) => {
+                write!(f, "expected table or record declaration after tags, found {}", t.kind)
+            }
             ExpectedScope(t) => {
                 write!(f, "expected opening parenthesis, found {}", t.kind)
             }
+            ExtraPositionalValue(t) => {
+                write!(f, "expected closing parenthesis after value for each column in column set, found {}", t.kind)
+            }
             ExpectedValue(t) => {
                 write!(f, "expected value, found {}", t.kind)
             }
+            ExpectedVariableValue(t) => {
+                write!(f, "expected string, number, boolean, or null for variable value, found {}", t.kind)
+            }
+            ExpectedWhere(t) => {
+                write!(f, "expected `where` after name of updated record or deleted table, found {}", t.kind)
+            }
+            InvalidUuidNamespace(t) => {
+                write!(f, "expected `dns`, `oid`, `url`, `x500`, or a UUID for namespace, found {}", t.kind)
+            }
+            InvalidShorthandColumn(column, _) => {
+                write!(f, "expected column named after a table and its column for shorthand reference, eg. `person_id`, found `{}`", column)
+            }
+            WrongColumnCount(expected, found, _) => {
+                write!(f, "expected {} referenced columns, found {}", expected, found)
+            }
+            WrongValueCount(expected, found, _) => {
+                write!(f, "expected {} values for column set, found {}", expected, found)
+            }
+            NamespacedColumn(namespace, column, _) => {
+                write!(f, "expected record in reference to namespace `{}`, found column `{}`", namespace, column)
+            }
             RecordNameQuoted(s, _) => {
                 write!(
                     f,
@@ -91,6 +215,13 @@ impl fmt::Display for ParseErrorKind {
             UnexpectedToken(t) => {
                 write!(f, "unexpected {}", t.kind)
             }
+            Unstreamable(declared, _) => {
+                write!(f, "cannot stream {}", declared)
+            }
+            WrongArgumentCount(name, expected, found, _) => {
+                let plural = if *expected == 1 { "" } else { "s" };
+                write!(f, "expected {} argument{} for `{}`, found {}", expected, plural, name, found)
+            }
         }
     }
 }
@@ -119,18 +250,144 @@ impl ParseError {
         }
     }
 
+    pub(crate) fn exp_arg(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedArgument(t),
+        }
+    }
+
+    pub(crate) fn exp_close_args(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedCloseArguments(t),
+        }
+    }
+
     pub(crate) fn exp_close_attr(t: Token) -> Self {
         Self {
             kind: ParseErrorKind::ExpectedCloseAttribute(t),
         }
     }
 
+    pub(crate) fn exp_columns(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedColumns(t),
+        }
+    }
+
+    pub(crate) fn exp_equals(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedEquals(t),
+        }
+    }
+
+    pub(crate) fn exp_from(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedFrom(t),
+        }
+    }
+
+    pub(crate) fn exp_hook_sql(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedHookSql(t),
+        }
+    }
+
     pub(crate) fn exp_ident(t: Token) -> Self {
         Self {
             kind: ParseErrorKind::ExpectedIdentifier(t),
         }
     }
 
+    pub(crate) fn exp_import(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedImportPath(t),
+        }
+    }
+
+    pub(crate) fn exp_include(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedIncludePath(t),
+        }
+    }
+
+    pub(crate) fn exp_fragment(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedSqlFragment(t),
+        }
+    }
+
+    pub(crate) fn exp_interval(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedInterval(t),
+        }
+    }
+
+    pub(crate) fn ns_column(namespace: String, column: String, position: Position) -> Self {
+        Self {
+            kind: ParseErrorKind::NamespacedColumn(namespace, column, position),
+        }
+    }
+
+    pub(crate) fn shorthand(column: String, position: Position) -> Self {
+        Self {
+            kind: ParseErrorKind::InvalidShorthandColumn(column, position),
+        }
+    }
+
+    pub(crate) fn extra_value(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExtraPositionalValue(t),
+        }
+    }
+
+    pub(crate) fn column_count(expected: usize, found: usize, t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::WrongColumnCount(expected, found, t),
+        }
+    }
+
+    pub(crate) fn value_count(expected: usize, found: usize, t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::WrongValueCount(expected, found, t),
+        }
+    }
+
+    pub(crate) fn exp_operand(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedOperand(t),
+        }
+    }
+
+    pub(crate) fn exp_quantity(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedQuantity(t),
+        }
+    }
+
+    pub(crate) fn exp_reference(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedReference(t),
+        }
+    }
+
+    pub(crate) fn exp_role(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedRole(t),
+        }
+    }
+
+    pub(crate) fn exp_row_count(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedRowCount(t),
+        }
+    }
+
+    pub(crate) fn exp_rows(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedRows(t),
+        }
+    }
+
     pub(crate) fn exp_scope(t: Token) -> Self {
         Self {
             kind: ParseErrorKind::ExpectedScope(t),
@@ -143,18 +400,60 @@ impl ParseError {
         }
     }
 
+    pub(crate) fn exp_setting(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedSetting(t),
+        }
+    }
+
+    pub(crate) fn exp_setting_value(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedSettingValue(t),
+        }
+    }
+
     pub(crate) fn exp_table(t: Token) -> Self {
         Self {
             kind: ParseErrorKind::ExpectedTableName(t),
         }
     }
 
+    pub(crate) fn exp_tag(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedTag(t),
+        }
+    }
+
+    pub(crate) fn exp_tagged(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedTagged(t),
+        }
+    }
+
     pub(crate) fn exp_value(t: Token) -> Self {
         Self {
             kind: ParseErrorKind::ExpectedValue(t),
         }
     }
 
+    pub(crate) fn exp_variable_value(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedVariableValue(t),
+        }
+    }
+
+    pub(crate) fn exp_where(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::ExpectedWhere(t),
+        }
+    }
+
+    pub(crate) fn fn_args(name: String, expected: usize, found: usize, t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::WrongArgumentCount(name, expected, found, t),
+        }
+    }
+
     pub(crate) fn in_record(t: Token) -> Self {
         Self {
             kind: ParseErrorKind::UnexpectedInRecord(t),
@@ -179,45 +478,82 @@ impl ParseError {
         }
     }
 
+    pub(crate) fn unstreamable(declared: &'static str, p: Position) -> Self {
+        Self {
+            kind: ParseErrorKind::Unstreamable(declared, p),
+        }
+    }
+
+    pub(crate) fn uuid_namespace(t: Token) -> Self {
+        Self {
+            kind: ParseErrorKind::InvalidUuidNamespace(t),
+        }
+    }
+
     pub(crate) fn token(t: Token) -> Self {
         Self {
             kind: ParseErrorKind::UnexpectedToken(t),
         }
     }
+
+    /// Returns where the error occurred, which is unknown only for unexpected ends of file.
+    pub fn position(&self) -> Option<Position> {
+        use ParseErrorKind::*;
+
+        match &self.kind {
+            ExpectedAliasName(t)
+            | ExpectedAliasOrScope(t)
+            | ExpectedArgument(t)
+            | ExpectedCloseArguments(t)
+            | ExpectedCloseAttribute(t)
+            | ExpectedColumns(t)
+            | ExpectedEquals(t)
+            | ExpectedFrom(t)
+            | ExpectedHookSql(t)
+            | ExpectedIdentifier(t)
+            | ExpectedImportPath(t)
+            | ExpectedIncludePath(t)
+            | ExpectedInterval(t)
+            | ExpectedOperand(t)
+            | ExpectedQuantity(t)
+            | ExpectedReference(t)
+            | ExpectedRole(t)
+            | ExpectedRowCount(t)
+            | ExpectedRows(t)
+            | ExpectedScope(t)
+            | ExpectedSchemaName(t)
+            | ExpectedSetting(t)
+            | ExpectedSettingValue(t)
+            | ExpectedSqlFragment(t)
+            | ExpectedTableName(t)
+            | ExpectedTag(t)
+            | ExpectedTagged(t)
+            | ExpectedValue(t)
+            | ExpectedVariableValue(t)
+            | ExpectedWhere(t)
+            | UnexpectedInSchema(t)
+            | UnexpectedInTable(t)
+            | UnexpectedInRecord(t)
+            | UnexpectedToken(t)
+            | InvalidUuidNamespace(t)
+            | ExtraPositionalValue(t)
+            | WrongValueCount(_, _, t)
+            | WrongColumnCount(_, _, t)
+            | WrongArgumentCount(_, _, _, t) => Some(t.position),
+            InvalidShorthandColumn(_, p) | NamespacedColumn(_, _, p) | RecordNameQuoted(_, p) | Unstreamable(_, p) => Some(*p),
+            UnexpectedEOF => None,
+        }
+    }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use ParseErrorKind::*;
-
-        // TODO: This is an indication that the parser error could use some
-        // general tidying up, given that the `RecordNameQuoted` kind is handled
-        // the same way as the others but needs to be destructured differently,
-        // and only the `UnexpectedEOF` kind is reported without a line number
-        match self.kind {
-            ExpectedAliasName(ref t)
-            | ExpectedAliasOrScope(ref t)
-            | ExpectedCloseAttribute(ref t)
-            | ExpectedIdentifier(ref t)
-            | ExpectedScope(ref t)
-            | ExpectedSchemaName(ref t)
-            | ExpectedTableName(ref t)
-            | ExpectedValue(ref t)
-            | UnexpectedInSchema(ref t)
-            | UnexpectedInTable(ref t)
-            | UnexpectedInRecord(ref t)
-            | UnexpectedToken(ref t) => {
-                // TODO: Token positions' columns are not always accurate, so they
-                // need to be tightened up before reporting in parser errors. Or maybe
-                // the column is less relevant for parser errors than it is for lexer?
-                write!(f, "{} on line {}", self.kind, t.position.line)
-            }
-            RecordNameQuoted(_, p) => {
-                write!(f, "{} on line {}", self.kind, p.line)
-            }
-            _ => {
-                write!(f, "{}", self.kind)
-            }
+        // TODO: Token positions' columns are not always accurate, so they
+        // need to be tightened up before reporting in parser errors. Or maybe
+        // the column is less relevant for parser errors than it is for lexer?
+        match self.position() {
+            Some(p) => write!(f, "{} on line {}", self.kind, p.line),
+            None => write!(f, "{}", self.kind),
         }
     }
 }
@@ -1,10 +1,13 @@
+pub mod builder;
 pub mod error;
 pub mod nodes;
 mod states;
 
-use super::lexer::tokens::Token;
+use super::lexer::tokens::{Token, TokenKind};
 
 use error::{ParseError, ParseErrorKind};
+use std::collections::VecDeque;
+use std::mem;
 
 pub fn parse(input: impl Iterator<Item = Token>) -> Result<nodes::ParseTree, ParseError> {
     let mut context = states::Context::default();
@@ -14,179 +17,2122 @@ pub fn parse(input: impl Iterator<Item = Token>) -> Result<nodes::ParseTree, Par
     let mut state: Box<dyn states::State> = Box::new(states::Root);
 
     for token in input {
+        // Comments are kept as trivia attached to nodes rather than parsed
+        let token = match token.kind {
+            TokenKind::Comment(text) => {
+                context.receive_comment(text);
+                continue;
+            }
+            _ => token,
+        };
+        let ends_line = token.kind == TokenKind::LineSep;
+
+        context.receive_token(&token);
         state = state.receive(&mut context, Some(token))?;
+
+        if ends_line {
+            context.end_line();
+        }
+    }
+
+    state.receive(&mut context, None)?;
+    context.end_file();
+
+    match context.stack.pop() {
+        Some(states::StackItem::TreeRoot(tree)) => Ok(*tree),
+        _ => Err(ParseError {
+            kind: ParseErrorKind::UnexpectedEOF,
+        }),
+    }
+}
+
+/// A table declared in the input, or the records of it declared since it was last
+/// streamed, along with the schema it was declared in, if any.
+#[derive(Debug, PartialEq)]
+pub struct StreamedTable {
+    pub schema: Option<nodes::StructuralIdentity>,
+    pub table: nodes::Table,
+    /// Whether the records continue a declaration of the table that was already streamed
+    pub continued: bool,
+}
+
+/// Returns an iterator over the tables declared in the input as they are parsed, which
+/// streams the records of each table in chunks of `chunk_size` records as they are
+/// closed rather than once the whole table is, so that large data files can be loaded
+/// without holding their whole tree in memory.
+///
+/// Since tables are streamed before the rest of the input is parsed, includes, imports,
+/// search paths, tables declared with `truncate`, and `$defaults` declared after records
+/// that were already streamed are all errors. The tokens are taken along with any error
+/// from lexing them, which is returned as is, and nothing is returned after an error.
+pub fn stream<E, I: Iterator<Item = Result<Token, E>>>(input: I, chunk_size: usize) -> Stream<I> {
+    let mut context = states::Context::default();
+    context
+        .stack
+        .push(states::StackItem::TreeRoot(Box::default()));
+
+    Stream {
+        input,
+        context,
+        state: Some(Box::new(states::Root)),
+        chunk_size,
+        parsed: VecDeque::new(),
+        streamed_defaults: None,
+    }
+}
+
+pub struct Stream<I> {
+    input: I,
+    context: states::Context,
+    /// The state to receive the next token, or `None` once the end of the input or
+    /// an error has been received
+    state: Option<Box<dyn states::State>>,
+    chunk_size: usize,
+    /// The tables parsed since the last token that have not been streamed yet
+    parsed: VecDeque<StreamedTable>,
+    /// The number of `$defaults` of the table being declared when its records were
+    /// first streamed, if they have been
+    streamed_defaults: Option<usize>,
+}
+
+impl<E: From<ParseError>, I: Iterator<Item = Result<Token, E>>> Iterator for Stream<I> {
+    type Item = Result<StreamedTable, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(table) = self.parsed.pop_front() {
+                return Some(Ok(table));
+            }
+
+            let mut state = self.state.take()?;
+
+            let received = match self.input.next().transpose() {
+                Err(error) => return Some(Err(error)),
+                Ok(Some(token)) => {
+                    // Comments are kept as trivia attached to nodes rather than parsed
+                    if let TokenKind::Comment(text) = token.kind {
+                        self.context.receive_comment(text);
+                        self.state = Some(state);
+                        continue;
+                    }
+                    let ends_line = token.kind == TokenKind::LineSep;
+
+                    self.context.receive_token(&token);
+                    let received = state.receive(&mut self.context, Some(token));
+
+                    if ends_line {
+                        self.context.end_line();
+                    }
+                    received.map(Some)
+                }
+                Ok(None) => state.receive(&mut self.context, None).and_then(|_| {
+                    self.context.end_file();
+
+                    match self.context.stack.as_slice() {
+                        [states::StackItem::TreeRoot(_)] => Ok(None),
+                        _ => Err(ParseError {
+                            kind: ParseErrorKind::UnexpectedEOF,
+                        }),
+                    }
+                }),
+            };
+
+            match received.and_then(|state| self.take_parsed().map(|_| state)) {
+                Ok(state) => self.state = state,
+                Err(error) => return Some(Err(error.into())),
+            }
+        }
+    }
+}
+
+impl<I> Stream<I> {
+    /// Takes the tables closed by the last token, and the records of the table being
+    /// declared once there are enough of them, to be streamed.
+    fn take_parsed(&mut self) -> Result<(), ParseError> {
+        let Self { context, chunk_size, parsed, streamed_defaults, .. } = self;
+        let mut schema = None;
+
+        for item in &mut context.stack {
+            match item {
+                states::StackItem::TreeRoot(tree) => {
+                    if let Some(include) = tree.includes.first() {
+                        return Err(ParseError::unstreamable("includes", include.position));
+                    }
+                    if let Some(hook) = tree.hooks.first() {
+                        let position = hook.position.unwrap_or_default();
+                        return Err(ParseError::unstreamable("hooks declared with `before` or `after`", position));
+                    }
+                    if let Some(variable) = tree.variables.first() {
+                        let position = variable.position.unwrap_or_default();
+                        return Err(ParseError::unstreamable("variables declared with `let`", position));
+                    }
+                    if let Some(search_path) = tree.search_paths.first() {
+                        let position = search_path.position.unwrap_or_default();
+                        return Err(ParseError::unstreamable("search paths declared with `set search_path`", position));
+                    }
+
+                    // Tables in schemas were already streamed as they were closed, so
+                    // closed schemas are empty
+                    for node in tree.nodes.drain(..) {
+                        if let nodes::StructuralNode::Table(table) = node {
+                            close_streamed_table(None, *table, streamed_defaults, parsed)?;
+                        }
+                    }
+                }
+                states::StackItem::Schema(s) => {
+                    for table in s.nodes.drain(..) {
+                        close_streamed_table(Some(s.identity.clone()), table, streamed_defaults, parsed)?;
+                    }
+                    schema = Some(&s.identity);
+                }
+                states::StackItem::Table(table) => {
+                    check_streamable(table, *streamed_defaults)?;
+
+                    if table.nodes.len() >= *chunk_size {
+                        // The records are streamed with everything declared for the table
+                        // other than its trivia, which is only kept for formatting
+                        let chunk = nodes::Table {
+                            identity: table.identity.clone(),
+                            conflict_target: table.conflict_target.clone(),
+                            truncate: table.truncate,
+                            expected_rows: table.expected_rows,
+                            role: table.role.clone(),
+                            settings: table.settings.clone(),
+                            tags: table.tags.clone(),
+                            profile: table.profile.clone(),
+                            namespace: table.namespace.clone(),
+                            defaults: table.defaults.clone(),
+                            imports: Vec::new(),
+                            nodes: mem::take(&mut table.nodes),
+                            position: table.position,
+                            // The table only ends once it is closed
+                            span: None,
+                            trivia: nodes::Trivia::default(),
+                        };
+
+                        parsed.push_back(StreamedTable {
+                            schema: schema.cloned(),
+                            table: chunk,
+                            continued: streamed_defaults.replace(table.defaults.len()).is_some(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Streams the rest of the records of a table once it is closed, unless it has none
+/// left after its records were already streamed.
+fn close_streamed_table(
+    schema: Option<nodes::StructuralIdentity>,
+    table: nodes::Table,
+    streamed_defaults: &mut Option<usize>,
+    parsed: &mut VecDeque<StreamedTable>,
+) -> Result<(), ParseError> {
+    check_streamable(&table, *streamed_defaults)?;
+
+    let continued = streamed_defaults.take().is_some();
+
+    if !(continued && table.nodes.is_empty()) {
+        parsed.push_back(StreamedTable { schema, table, continued });
+    }
+
+    Ok(())
+}
+
+fn check_streamable(table: &nodes::Table, streamed_defaults: Option<usize>) -> Result<(), ParseError> {
+    let position = table.position.unwrap_or_default();
+
+    if let Some(import) = table.imports.first() {
+        return Err(ParseError::unstreamable("imports", import.position));
+    }
+    if table.truncate {
+        return Err(ParseError::unstreamable("tables declared with `truncate`", position));
+    }
+    if streamed_defaults.is_some_and(|n| n != table.defaults.len()) {
+        return Err(ParseError::unstreamable("`$defaults` declared after records", position));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::error::ParseError;
+    use super::parse;
+    use crate::lexer::tokenize;
+    use crate::lexer::tokens::Token;
+    use crate::parser::nodes::*;
+    use uuid::Uuid;
+
+    fn tokens(input: &str) -> impl Iterator<Item = Token> {
+        tokenize(input.chars()).unwrap().into_iter()
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let input: Vec<Token> = Vec::new();
+        assert_eq!(parse(input.into_iter()), Ok(ParseTree::default()));
+    }
+
+    #[test]
+    fn test_empty_schema() {
+        /*
+        let position = Position::default();
+        let input = vec![
+            Tkn { position, kind: TKnd::Keyword(Kwd::Schema) },
+            Tkn { position, kind: TKnd::Identifier("my_schema".to_owned()) },
+            Tkn { position, kind: TKnd::Symbol(Sym::ParenLeft) },
+            Tkn { position, kind: TKnd::Symbol(Sym::ParenRight) },
+        ];
+         */
+
+        let input = tokens("schema my_schema ()");
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Schema(Box::new(Schema {
+                    identity: StructuralIdentity {
+                        alias: None,
+                        name: "my_schema".to_owned(),
+                    },
+                    role: None,
+                    settings: Vec::new(),
+                    nodes: Vec::new(),
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                })),],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_empty_schema_with_alias() {
+        /*
+        let position = Position::default();
+        let input = vec![
+            Tkn { position, kind: TKnd::Keyword(Kwd::Schema) },
+            Tkn { position, kind: TKnd::Identifier("my_other_schema".to_owned()) },
+            Tkn { position, kind: TKnd::Keyword(Kwd::As) },
+            Tkn { position, kind: TKnd::Identifier("some_alias".to_owned()) },
+            Tkn { position, kind: TKnd::Symbol(Sym::ParenLeft) },
+            Tkn { position, kind: TKnd::Symbol(Sym::ParenRight) },
+        ];
+        */
+
+        let input = tokens("schema my_other_schema as some_alias ()");
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Schema(Box::new(Schema {
+                    identity: StructuralIdentity {
+                        alias: Some("some_alias".to_owned()),
+                        name: "my_other_schema".to_owned(),
+                    },
+                    role: None,
+                    settings: Vec::new(),
+                    nodes: Vec::new(),
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                })),],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_empty_top_level_table() {
+        /*
+        let position = Position::default();
+        let input = vec![
+            Tkn {
+                kind: TKnd::Keyword(Kwd::Table),
+                position,
+            },
+            Tkn {
+                kind: TKnd::Identifier("my_table".to_owned()),
+                position,
+            },
+            Tkn {
+                kind: TKnd::Symbol(Sym::ParenLeft),
+                position,
+            },
+            Tkn {
+                kind: TKnd::Symbol(Sym::ParenRight),
+                position,
+            },
+        ];
+        */
+
+        let input = tokens("table my_table ()");
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Table(Box::new(Table {
+                    identity: StructuralIdentity {
+                        alias: None,
+                        name: "my_table".to_owned(),
+                    },
+                    conflict_target: None,
+                    truncate: false,
+                    expected_rows: None,
+                    role: None,
+                    settings: Vec::new(),
+                    tags: Vec::new(),
+                    profile: None,
+                    namespace: None,
+                    defaults: Vec::new(),
+                    imports: Vec::new(),
+                    nodes: Vec::new(),
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                })),],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_empty_top_level_table_with_alias() {
+        /*
+        let position = Position::default();
+        let input = vec![
+            Tkn {
+                kind: TKnd::Keyword(Kwd::Table),
+                position,
+            },
+            Tkn {
+                kind: TKnd::Identifier("my_other_table".to_owned()),
+                position,
+            },
+            Tkn {
+                kind: TKnd::Keyword(Kwd::As),
+                position,
+            },
+            Tkn {
+                kind: TKnd::Identifier("some_alias".to_owned()),
+                position,
+            },
+            Tkn {
+                kind: TKnd::Symbol(Sym::ParenLeft),
+                position,
+            },
+            Tkn {
+                kind: TKnd::Symbol(Sym::ParenRight),
+                position,
+            },
+        ];
+        */
+
+        let input = tokens("table my_other_table as another_alias ()");
+
+        assert_eq!(
+            parse(input.into_iter()),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Table(Box::new(Table {
+                    identity: StructuralIdentity {
+                        alias: Some("another_alias".to_owned()),
+                        name: "my_other_table".to_owned(),
+                    },
+                    conflict_target: None,
+                    truncate: false,
+                    expected_rows: None,
+                    role: None,
+                    settings: Vec::new(),
+                    tags: Vec::new(),
+                    profile: None,
+                    namespace: None,
+                    defaults: Vec::new(),
+                    imports: Vec::new(),
+                    nodes: Vec::new(),
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                })),],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_table_with_conflict_target() {
+        let input = tokens(
+            r#"
+            table t1 conflict (col1) ()
+            table t2 as tt conflict (
+                col1,
+                "col 2"
+            ) ()
+        "#,
+        );
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![
+                    StructuralNode::Table(Box::new(Table {
+                        identity: StructuralIdentity {
+                            alias: None,
+                            name: "t1".to_owned(),
+                        },
+                        conflict_target: Some(vec!["col1".to_owned()]),
+                        truncate: false,
+                        expected_rows: None,
+                        role: None,
+                        settings: Vec::new(),
+                        tags: Vec::new(),
+                        profile: None,
+                        namespace: None,
+                        defaults: Vec::new(),
+                        imports: Vec::new(),
+                        nodes: Vec::new(),
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
+                    })),
+                    StructuralNode::Table(Box::new(Table {
+                        identity: StructuralIdentity {
+                            alias: Some("tt".to_owned()),
+                            name: "t2".to_owned(),
+                        },
+                        conflict_target: Some(vec!["col1".to_owned(), "col 2".to_owned()]),
+                        truncate: false,
+                        expected_rows: None,
+                        role: None,
+                        settings: Vec::new(),
+                        tags: Vec::new(),
+                        profile: None,
+                        namespace: None,
+                        defaults: Vec::new(),
+                        imports: Vec::new(),
+                        nodes: Vec::new(),
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
+                    })),
+                ],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
     }
 
-    state.receive(&mut context, None)?;
+    #[test]
+    fn test_table_with_truncate() {
+        let input = tokens(
+            r#"
+            table t1 truncate ()
+            table t2 as tt truncate conflict (col1) ()
+            table t3 ()
+            table truncate as truncate truncate ()
+        "#,
+        );
+
+        let table = |name: &str, alias: Option<&str>, conflict_target: Option<Vec<String>>, truncate| {
+            StructuralNode::Table(Box::new(Table {
+                identity: StructuralIdentity {
+                    alias: alias.map(str::to_owned),
+                    name: name.to_owned(),
+                },
+                conflict_target,
+                truncate,
+                expected_rows: None,
+                role: None,
+                settings: Vec::new(),
+                tags: Vec::new(),
+                profile: None,
+                namespace: None,
+                defaults: Vec::new(),
+                imports: Vec::new(),
+                nodes: Vec::new(),
+                position: None,
+                span: None,
+                trivia: Trivia::default(),
+            }))
+        };
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![
+                    table("t1", None, None, true),
+                    table("t2", Some("tt"), Some(vec!["col1".to_owned()]), true),
+                    table("t3", None, None, false),
+                    table("truncate", Some("truncate"), None, true),
+                ],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_table_with_defaults() {
+        let input = tokens(
+            r#"
+            table t1 (
+                $defaults (col1 1
+                    col2 'two')
+                record1 (col1 3)
+                $defaults (col3 @col1)
+            )
+        "#,
+        );
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Table(Box::new(Table {
+                    identity: StructuralIdentity {
+                        alias: None,
+                        name: "t1".to_owned(),
+                    },
+                    conflict_target: None,
+                    truncate: false,
+                    expected_rows: None,
+                    role: None,
+                    settings: Vec::new(),
+                    tags: Vec::new(),
+                    profile: None,
+                    namespace: None,
+                    defaults: vec![
+                        Attribute {
+                            name: "col1".to_owned(),
+                            value: Value::Number("1".to_owned()),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
+                        },
+                        Attribute {
+                            name: "col2".to_owned(),
+                            value: Value::Text("'two'".to_owned()),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
+                        },
+                        Attribute {
+                            name: "col3".to_owned(),
+                            value: Value::Reference(Reference::ColumnLevel(ColumnLevelReference {
+                                column: "col1".to_owned(),
+                            })),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
+                        },
+                    ],
+                    imports: Vec::new(),
+                    nodes: vec![Record {
+                        name: Some("record1".to_owned()),
+                        quantity: None,
+                        extends: None,
+                        is_abstract: false,
+                        tags: Vec::new(),
+                        update_where: None,
+                        delete: false,
+                        nodes: vec![Attribute {
+                            name: "col1".to_owned(),
+                            value: Value::Number("3".to_owned()),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
+                        }],
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
+                    }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                }))],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_column_sets() {
+        let tree = parse(tokens(
+            "table t1 (\n  $columns (col1, col2)\n  $defaults (col3 true)\n  record1 ('one', 1)\n  ( 'two' || '!'\n    @record1.col2 + 1 )\n  _ * 2 (@col2, index())\n)\ntable t2 ( (col1 'one') )",
+        ))
+        .unwrap();
+        let records: Vec<Vec<(String, Value)>> = tree
+            .nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Table(table) => table.nodes.iter(),
+                node => panic!("expected table, found {:?}", node),
+            })
+            .map(|record| record.nodes.iter().map(|a| (a.name.clone(), a.value.clone())).collect())
+            .collect();
+
+        assert_eq!(
+            records,
+            vec![
+                vec![
+                    ("col1".to_owned(), Value::Text("'one'".to_owned())),
+                    ("col2".to_owned(), Value::Number("1".to_owned())),
+                ],
+                vec![
+                    (
+                        "col1".to_owned(),
+                        Value::Text("'two'".to_owned()).combine(Operator::Concat, Value::Text("'!'".to_owned())),
+                    ),
+                    (
+                        "col2".to_owned(),
+                        Value::Reference(Reference::RecordLevel(RecordLevelReference {
+                            namespace: None,
+                            record: "record1".to_owned(),
+                            column: ReferencedColumn::Explicit("col2".to_owned()),
+                        }))
+                        .combine(Operator::Add, Value::Number("1".to_owned())),
+                    ),
+                ],
+                vec![
+                    (
+                        "col1".to_owned(),
+                        Value::Reference(Reference::ColumnLevel(ColumnLevelReference {
+                            column: "col2".to_owned(),
+                        })),
+                    ),
+                    ("col2".to_owned(), Value::Index),
+                ],
+                vec![("col1".to_owned(), Value::Text("'one'".to_owned()))],
+            ],
+        );
+
+        for (input, message) in [
+            ("table t1 (\n  $columns (col1, col2)\n  ('one')\n)", "expected 2 values for column set, found 1 on line 3"),
+            ("table t1 (\n  $columns (col1)\n  ('one', 'two')\n)", "expected closing parenthesis after value for each column in column set, found string ''two'' on line 3"),
+            ("table t1 (\n  $columns (col1)\n  (col1 'one')\n)", "expected value, found identifier `col1` on line 3"),
+            ("table t1 ( $columns () )", "expected identifier, found symbol `)` on line 1"),
+            ("table t1 ( $columns (col1 col2) )", "expected comma, newline, or closing parenthesis, found identifier `col2` on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_profiles() {
+        let tree = parse(tokens(
+            "table t1 ()\nprofile demo (\n  table t2 ()\n  schema s1 (\n    table t3 ()\n  )\n)\ntable t4 ()",
+        ))
+        .unwrap();
+        let tables: Vec<(&str, Option<&str>)> = tree
+            .nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Schema(schema) => schema.nodes.iter().collect(),
+                StructuralNode::Table(table) => vec![table.as_ref()],
+            })
+            .map(|table| (table.identity.name.as_str(), table.profile.as_deref()))
+            .collect();
+
+        assert_eq!(tables, [("t1", None), ("t2", Some("demo")), ("t3", Some("demo")), ("t4", None)]);
+
+        for (input, message) in [
+            ("profile demo (\n  profile other ()\n)", "unexpected identifier `profile` on line 2"),
+            ("profile demo (\n  include 'other.hldr'\n)", "unexpected identifier `include` on line 2"),
+            ("profile 'demo' ()", "expected identifier, found string ''demo'' on line 1"),
+            ("profile demo (\n  table t1 ()\n", "unexpected end of file"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_tags() {
+        let tree = parse(tokens(
+            "@tag(billing)\ntable t1 (\n  @tag(smoke, slow)\n  @tag(smoke)\n  record1 ()\n  @tag(slow) _ * 2 ()\n  ()\n)\nschema s1 (\n  @tag(\n    smoke\n  ) table t2 ()\n)",
+        ))
+        .unwrap();
+        let tables: Vec<(Vec<String>, Vec<Vec<String>>)> = tree
+            .nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Schema(schema) => schema.nodes.iter().collect(),
+                StructuralNode::Table(table) => vec![table.as_ref()],
+            })
+            .map(|table| (table.tags.clone(), table.nodes.iter().map(|r| r.tags.clone()).collect()))
+            .collect();
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            tables,
+            vec![
+                (tags(&["billing"]), vec![tags(&["smoke", "slow"]), tags(&["slow"]), tags(&[])]),
+                (tags(&["smoke"]), vec![]),
+            ],
+        );
+
+        for (input, message) in [
+            ("@tags(smoke) table t1 ()", "expected `tag`, found identifier `tags` on line 1"),
+            ("@tag(smoke)\nschema s1 ()", "expected table or record declaration after tags, found keyword `schema` on line 2"),
+            ("table t1 (\n  @tag(smoke)\n  $columns (col1)\n)", "expected table or record declaration after tags, found keyword `$columns` on line 3"),
+            ("table t1 ( @tag() () )", "expected identifier, found symbol `)` on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_default_values() {
+        let tree = parse(tokens("table t1 (\n  $columns (col1, default)\n  (default, 1)\n)\ntable t2 ( (col1 'one', default default) )")).unwrap();
+        let records: Vec<Vec<(String, Value)>> = tree
+            .nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Table(table) => table.nodes.iter(),
+                node => panic!("expected table, found {:?}", node),
+            })
+            .map(|record| record.nodes.iter().map(|a| (a.name.clone(), a.value.clone())).collect())
+            .collect();
+
+        assert_eq!(
+            records,
+            vec![
+                vec![
+                    ("col1".to_owned(), Value::Default),
+                    ("default".to_owned(), Value::Number("1".to_owned())),
+                ],
+                vec![
+                    ("col1".to_owned(), Value::Text("'one'".to_owned())),
+                    ("default".to_owned(), Value::Default),
+                ],
+            ],
+        );
+
+        let err = parse(tokens("table t1 ( (col1 default + 1) )")).unwrap_err();
+        assert_eq!(err.to_string(), "expected comma, newline, or closing parenthesis, found symbol `+` on line 1");
+    }
+
+    #[test]
+    fn test_repeated_records() {
+        let input = tokens("table t1 ( _ * 1_000 (col1 true) )");
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Table(Box::new(Table {
+                    identity: StructuralIdentity {
+                        alias: None,
+                        name: "t1".to_owned(),
+                    },
+                    conflict_target: None,
+                    truncate: false,
+                    expected_rows: None,
+                    role: None,
+                    settings: Vec::new(),
+                    tags: Vec::new(),
+                    profile: None,
+                    namespace: None,
+                    defaults: Vec::new(),
+                    imports: Vec::new(),
+                    nodes: vec![Record {
+                        name: None,
+                        quantity: Some(1000),
+                        extends: None,
+                        is_abstract: false,
+                        tags: Vec::new(),
+                        update_where: None,
+                        delete: false,
+                        nodes: vec![Attribute {
+                            name: "col1".to_owned(),
+                            value: Value::Bool(true),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
+                        }],
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
+                    }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                }))],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_extends() {
+        let input = tokens("table t1 (\n  abstract base (col1 1)\n  rec1 extends base ()\n  _ extends rec1 (col2 2)\n  abstract (abstract true, extends 1)\n  extends extends abstract ()\n)");
+        let tree = parse(input).unwrap();
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+        let records: Vec<_> = table
+            .nodes
+            .iter()
+            .map(|r| (r.name.as_deref(), r.extends.as_deref(), r.is_abstract, r.nodes.len()))
+            .collect();
+
+        assert_eq!(
+            records,
+            vec![
+                (Some("base"), None, true, 1),
+                (Some("rec1"), Some("base"), false, 0),
+                (None, Some("rec1"), false, 1),
+                (Some("abstract"), None, false, 2),
+                (Some("extends"), Some("abstract"), false, 0),
+            ],
+        );
+
+        for (input, message) in [
+            ("table t1 ( abstract 'x' (col1 1) )", "expected opening parenthesis, found string ''x'' on line 1"),
+            ("table t1 ( rec1 extends (col1 1) )", "expected identifier, found symbol `(` on line 1"),
+            ("table t1 ( rec1 extends base base () )", "expected opening parenthesis, found identifier `base` on line 1"),
+        ] {
+            assert_eq!(parse(tokens(input)).unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let input = tokens(
+            "table t1 (\n  $columns (email, name)\n  update admin where (email 'a@x.io') ( is_active true )\n  update _ where (role 'guest', active true)\n    (locked false)\n  update ('x', 'y')\n)",
+        );
+        let tree = parse(input).unwrap();
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+        let records: Vec<_> = table
+            .nodes
+            .iter()
+            .map(|r| {
+                let columns: Vec<_> = r.nodes.iter().map(|a| a.name.as_str()).collect();
+                (r.name.as_deref(), r.update_where.clone(), columns)
+            })
+            .collect();
+
+        assert_eq!(
+            records,
+            vec![
+                (Some("admin"), Some(vec!["email".to_owned()]), vec!["email", "is_active"]),
+                (None, Some(vec!["role".to_owned(), "active".to_owned()]), vec!["role", "active", "locked"]),
+                (Some("update"), None, vec!["email", "name"]),
+            ],
+        );
+
+        for (input, message) in [
+            ("table t1 ( update admin (col1 1) )", "expected `where` after name of updated record or deleted table, found symbol `(` on line 1"),
+            ("table t1 ( update admin where () (col1 1) )", "expected identifier, found symbol `)` on line 1"),
+            ("table t1 ( update admin where (col1 1) () )", "expected identifier, found symbol `)` on line 1"),
+            ("table t1 ( update admin where (col1 1) )", "expected opening parenthesis, found symbol `)` on line 1"),
+        ] {
+            assert_eq!(parse(tokens(input)).unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_delete() {
+        let input = tokens(
+            "delete from t1 where (email 'a@x.io')\nschema s1 (\n  delete from t2 where (\n    role 'guest'\n    active true\n  )\n  table delete ( delete (col1 1) )\n)",
+        );
+        let tree = parse(input).unwrap();
+        let tables: Vec<_> = tree
+            .nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Schema(schema) => schema.nodes.iter().collect(),
+                StructuralNode::Table(table) => vec![table.as_ref()],
+            })
+            .map(|table| {
+                let records: Vec<_> = table
+                    .nodes
+                    .iter()
+                    .map(|r| (r.name.as_deref(), r.delete, r.nodes.iter().map(|a| a.name.as_str()).collect::<Vec<_>>()))
+                    .collect();
+                (table.identity.name.as_str(), records)
+            })
+            .collect();
+
+        assert_eq!(
+            tables,
+            vec![
+                ("t1", vec![(None, true, vec!["email"])]),
+                ("t2", vec![(None, true, vec!["role", "active"])]),
+                ("delete", vec![(Some("delete"), false, vec!["col1"])]),
+            ],
+        );
+
+        for (input, message) in [
+            ("delete t1 where (col1 1)", "expected `from` after `delete`, found identifier `t1` on line 1"),
+            ("delete from where (col1 1)", "expected `where` after name of updated record or deleted table, found symbol `(` on line 1"),
+            ("delete from t1 (col1 1)", "expected `where` after name of updated record or deleted table, found symbol `(` on line 1"),
+            ("delete from t1 where ()", "expected identifier, found symbol `)` on line 1"),
+            ("table t1 ( delete from t2 where (col1 1) )", "expected opening parenthesis, found identifier `from` on line 1"),
+        ] {
+            assert_eq!(parse(tokens(input)).unwrap_err().to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_invalid_record_quantities() {
+        for (input, found) in [
+            ("table t1 ( _ * 0 () )", "number `0`"),
+            ("table t1 ( _ * 1.5 () )", "number `1.5`"),
+            ("table t1 ( _ * n () )", "identifier `n`"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("expected positive integer for record quantity, found {} on line 1", found),
+            );
+        }
+    }
+
+    #[test]
+    fn test_env_values() {
+        let input = tokens("table t1 ( (col1 env('ADMIN_PASSWORD'), env 'x', col3 env('it''s') ) )");
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Table(Box::new(Table {
+                    identity: StructuralIdentity {
+                        alias: None,
+                        name: "t1".to_owned(),
+                    },
+                    conflict_target: None,
+                    truncate: false,
+                    expected_rows: None,
+                    role: None,
+                    settings: Vec::new(),
+                    tags: Vec::new(),
+                    profile: None,
+                    namespace: None,
+                    defaults: Vec::new(),
+                    imports: Vec::new(),
+                    nodes: vec![Record {
+                        name: None,
+                        quantity: None,
+                        extends: None,
+                        is_abstract: false,
+                        tags: Vec::new(),
+                        update_where: None,
+                        delete: false,
+                        nodes: vec![
+                            Attribute {
+                                name: "col1".to_owned(),
+                                value: Value::EnvVar("ADMIN_PASSWORD".to_owned()),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                            Attribute {
+                                name: "env".to_owned(),
+                                value: Value::Text("'x'".to_owned()),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                            Attribute {
+                                name: "col3".to_owned(),
+                                value: Value::EnvVar("it's".to_owned()),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                        ],
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
+                    }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                }))],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_uuid_values() {
+        let input = tokens(
+            "table t1 ( (col1 uuid(), col2 uuid5('dns', 'example.com'), col3 uuid5('6ba7b811-9dad-11d1-80b4-00c04fd430c8', 'it''s') ) )",
+        );
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Table(Box::new(Table {
+                    identity: StructuralIdentity {
+                        alias: None,
+                        name: "t1".to_owned(),
+                    },
+                    conflict_target: None,
+                    truncate: false,
+                    expected_rows: None,
+                    role: None,
+                    settings: Vec::new(),
+                    tags: Vec::new(),
+                    profile: None,
+                    namespace: None,
+                    defaults: Vec::new(),
+                    imports: Vec::new(),
+                    nodes: vec![Record {
+                        name: None,
+                        quantity: None,
+                        extends: None,
+                        is_abstract: false,
+                        tags: Vec::new(),
+                        update_where: None,
+                        delete: false,
+                        nodes: vec![
+                            Attribute {
+                                name: "col1".to_owned(),
+                                value: Value::RandomUuid,
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                            Attribute {
+                                name: "col2".to_owned(),
+                                value: Value::Uuid5 {
+                                    namespace: Uuid::NAMESPACE_DNS,
+                                    name: "example.com".to_owned(),
+                                },
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                            Attribute {
+                                name: "col3".to_owned(),
+                                value: Value::Uuid5 {
+                                    namespace: Uuid::NAMESPACE_URL,
+                                    name: "it's".to_owned(),
+                                },
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                        ],
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
+                    }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                }))],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_time_values() {
+        let tree = parse(tokens(
+            "table t1 ( (col1 now(), col2 ago('7 days'), col3 now() + interval '1 hour', col4 'at ' || now() - interval '1 day') )",
+        ))
+        .unwrap();
+        let values = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table.nodes[0].nodes.iter().map(|a| a.value.clone()).collect::<Vec<_>>(),
+            node => panic!("expected table; found {:?}", node),
+        };
+
+        assert_eq!(
+            values,
+            vec![
+                Value::Now(None),
+                Value::Now(Some(TimeOffset::Subtract("7 days".to_owned()))),
+                Value::Now(Some(TimeOffset::Add("1 hour".to_owned()))),
+                Value::Expression(Box::new(Expression {
+                    left: Value::Text("'at '".to_owned()),
+                    operator: Operator::Concat,
+                    right: Value::Now(Some(TimeOffset::Subtract("1 day".to_owned()))),
+                })),
+            ],
+        );
+
+        for (input, message) in [
+            (
+                "table t1 ( (col1 now() - interval 7) )",
+                "expected string for interval, found number `7` on line 1",
+            ),
+            (
+                "table t1 ( (col1 now() * interval '1 day') )",
+                "expected number, string, reference, or function for operand, found identifier `interval` on line 1",
+            ),
+            (
+                "table t1 ( (col1 ago('1 day') - interval '1 day') )",
+                "expected number, string, reference, or function for operand, found identifier `interval` on line 1",
+            ),
+            (
+                "table t1 ( (col1 ago()) )",
+                "expected 1 argument for `ago`, found 0 on line 1",
+            ),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_binary_values() {
+        let tree = parse(tokens("table t1 ( (col1 x'00FF', col2 bytes('images/logo.png')) )")).unwrap();
+        let values = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table.nodes[0].nodes.iter().map(|a| a.value.clone()).collect::<Vec<_>>(),
+            node => panic!("expected table; found {:?}", node),
+        };
+
+        assert_eq!(values, vec![Value::Hex("00ff".to_owned()), Value::File("images/logo.png".into())]);
+
+        for (input, message) in [
+            (
+                "table t1 ( (col1 x'00' || x'01') )",
+                "expected comma, newline, or closing parenthesis, found symbol `||` on line 1",
+            ),
+            (
+                "table t1 ( (col1 bytes('a.png') + 1) )",
+                "expected comma, newline, or closing parenthesis, found symbol `+` on line 1",
+            ),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_json_values() {
+        let tree = parse(tokens("table t1 ( (col1 json{ \"a\": [1] }, col2 json[]) )")).unwrap();
+        let values = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table.nodes[0].nodes.iter().map(|a| a.value.clone()).collect::<Vec<_>>(),
+            node => panic!("expected table; found {:?}", node),
+        };
+
+        assert_eq!(values, vec![Value::Json("{ \"a\": [1] }".to_owned()), Value::Json("[]".to_owned())]);
+
+        let err = parse(tokens("table t1 ( (col1 json{} || 'x') )")).unwrap_err();
+        assert_eq!(err.to_string(), "expected comma, newline, or closing parenthesis, found symbol `||` on line 1");
+    }
+
+    #[test]
+    fn test_sql_fragment_values() {
+        let tree = parse(tokens("table t1 ( (col1 `now()`, col2 volatile `random()`, col3 volatile `{@col1}`) )")).unwrap();
+        let values = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table.nodes[0].nodes.iter().map(|a| a.value.clone()).collect::<Vec<_>>(),
+            node => panic!("expected table; found {:?}", node),
+        };
+
+        assert_eq!(
+            values,
+            vec![
+                Value::SqlFragment("now()".to_owned()),
+                Value::VolatileSqlFragment("random()".to_owned()),
+                Value::InterpolatedSqlFragment(vec![TextSegment::Value(Value::Reference(Reference::ColumnLevel(
+                    ColumnLevelReference { column: "col1".to_owned() },
+                )))]),
+            ],
+        );
+
+        let err = parse(tokens("table t1 ( (col1 volatile 'x') )")).unwrap_err();
+        assert_eq!(err.to_string(), "expected SQL fragment after `volatile`, found string ''x'' on line 1");
+
+        let err = parse(tokens("table t1 ( (col1 volatile `random()` + 1) )")).unwrap_err();
+        assert_eq!(err.to_string(), "expected comma, newline, or closing parenthesis, found symbol `+` on line 1");
+    }
+
+    #[test]
+    fn test_invalid_function_values() {
+        for (input, message) in [
+            (
+                "table t1 ( (col1 env) )",
+                "expected opening parenthesis, found symbol `)` on line 1",
+            ),
+            (
+                "table t1 ( (col1 env(ADMIN)) )",
+                "expected string for function argument, found identifier `ADMIN` on line 1",
+            ),
+            (
+                "table t1 ( (col1 env('ADMIN' 'x')) )",
+                "expected comma or closing parenthesis, found string ''x'' on line 1",
+            ),
+            (
+                "table t1 ( (col1 uuid5('dns', )) )",
+                "expected string for function argument, found symbol `)` on line 1",
+            ),
+            (
+                "table t1 ( (col1 env()) )",
+                "expected 1 argument for `env`, found 0 on line 1",
+            ),
+            (
+                "table t1 ( (col1 uuid5('dns')) )",
+                "expected 2 arguments for `uuid5`, found 1 on line 1",
+            ),
+            (
+                "table t1 ( (col1 uuid5('host', 'example.com')) )",
+                "expected `dns`, `oid`, `url`, `x500`, or a UUID for namespace, found string ''host'' on line 1",
+            ),
+            (
+                "table t1 ( (col1 other()) )",
+                "expected value, found identifier `other` on line 1",
+            ),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_expression_values() {
+        let input = tokens(
+            "table t1 ( (
+                col1 @item1.position + 1
+                col2 @base.price * 1.1 - 2 / @col1
+                col3 'item-' || @col1 + 1 || 'x'
+                col4 3 -1
+            ) )",
+        );
+        let expression = |left, operator, right| Value::Expression(Box::new(Expression { left, operator, right }));
+        let reference = |record: &str, column: &str| {
+            Value::Reference(Reference::RecordLevel(RecordLevelReference {
+                namespace: None,
+                record: record.to_owned(),
+                column: ReferencedColumn::Explicit(column.to_owned()),
+            }))
+        };
+        let col1 = || Value::Reference(Reference::ColumnLevel(ColumnLevelReference { column: "col1".to_owned() }));
+        let number = |n: &str| Value::Number(n.to_owned());
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Table(Box::new(Table {
+                    identity: StructuralIdentity {
+                        alias: None,
+                        name: "t1".to_owned(),
+                    },
+                    conflict_target: None,
+                    truncate: false,
+                    expected_rows: None,
+                    role: None,
+                    settings: Vec::new(),
+                    tags: Vec::new(),
+                    profile: None,
+                    namespace: None,
+                    defaults: Vec::new(),
+                    imports: Vec::new(),
+                    nodes: vec![Record {
+                        name: None,
+                        quantity: None,
+                        extends: None,
+                        is_abstract: false,
+                        tags: Vec::new(),
+                        update_where: None,
+                        delete: false,
+                        nodes: vec![
+                            Attribute {
+                                name: "col1".to_owned(),
+                                value: expression(reference("item1", "position"), Operator::Add, number("1")),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                            Attribute {
+                                name: "col2".to_owned(),
+                                value: expression(
+                                    expression(reference("base", "price"), Operator::Multiply, number("1.1")),
+                                    Operator::Subtract,
+                                    expression(number("2"), Operator::Divide, col1()),
+                                ),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                            Attribute {
+                                name: "col3".to_owned(),
+                                value: expression(
+                                    expression(
+                                        Value::Text("'item-'".to_owned()),
+                                        Operator::Concat,
+                                        expression(col1(), Operator::Add, number("1")),
+                                    ),
+                                    Operator::Concat,
+                                    Value::Text("'x'".to_owned()),
+                                ),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                            Attribute {
+                                name: "col4".to_owned(),
+                                value: expression(number("3"), Operator::Subtract, number("1")),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                        ],
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
+                    }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                }))],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_invalid_expression_values() {
+        for (input, message) in [
+            (
+                "table t1 ( (col1 true + 1) )",
+                "expected comma, newline, or closing parenthesis, found symbol `+` on line 1",
+            ),
+            (
+                "table t1 ( (col1 `now()` || 'x') )",
+                "expected comma, newline, or closing parenthesis, found symbol `||` on line 1",
+            ),
+            (
+                "table t1 ( (col1 1 + ) )",
+                "expected number, string, reference, or function for operand, found symbol `)` on line 1",
+            ),
+            (
+                "table t1 ( (col1 @rec1.col1 * false) )",
+                "expected number, string, reference, or function for operand, found boolean `false` on line 1",
+            ),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_interpolated_text_values() {
+        let input = tokens("table t1 ( (col1 'user-{@person1.id}@test.dev', col2 '{index()}: {@col1 || '!'}') )");
+
+        assert_eq!(
+            parse(input),
+            Ok(ParseTree {
+                nodes: vec![StructuralNode::Table(Box::new(Table {
+                    identity: StructuralIdentity {
+                        alias: None,
+                        name: "t1".to_owned(),
+                    },
+                    conflict_target: None,
+                    truncate: false,
+                    expected_rows: None,
+                    role: None,
+                    settings: Vec::new(),
+                    tags: Vec::new(),
+                    profile: None,
+                    namespace: None,
+                    defaults: Vec::new(),
+                    imports: Vec::new(),
+                    nodes: vec![Record {
+                        name: None,
+                        quantity: None,
+                        extends: None,
+                        is_abstract: false,
+                        tags: Vec::new(),
+                        update_where: None,
+                        delete: false,
+                        nodes: vec![
+                            Attribute {
+                                name: "col1".to_owned(),
+                                value: Value::InterpolatedText(vec![
+                                    TextSegment::Literal("user-".to_owned()),
+                                    TextSegment::Value(Value::Reference(Reference::RecordLevel(RecordLevelReference {
+                                        namespace: None,
+                                        record: "person1".to_owned(),
+                                        column: ReferencedColumn::Explicit("id".to_owned()),
+                                    }))),
+                                    TextSegment::Literal("@test.dev".to_owned()),
+                                ]),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                            Attribute {
+                                name: "col2".to_owned(),
+                                value: Value::InterpolatedText(vec![
+                                    TextSegment::Value(Value::Index),
+                                    TextSegment::Literal(": ".to_owned()),
+                                    TextSegment::Value(Value::Expression(Box::new(Expression {
+                                        left: Value::Reference(Reference::ColumnLevel(ColumnLevelReference {
+                                            column: "col1".to_owned(),
+                                        })),
+                                        operator: Operator::Concat,
+                                        right: Value::Text("'!'".to_owned()),
+                                    }))),
+                                ]),
+                                position: None,
+                                span: None,
+                                value_span: None,
+                                trivia: Trivia::default(),
+                            },
+                        ],
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
+                    }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
+                }))],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_invalid_interpolated_text_values() {
+        for (input, message) in [
+            ("table t1 ( (col1 '{@rec1.col1, col2}') )", "unexpected symbol `,` on line 1"),
+            ("table t1 ( (col1 '{@rec1.col1)}') )", "unexpected symbol `)` on line 1"),
+            ("table t1 ( (col1 '{@rec1.col1 true}') )", "unexpected boolean `true` on line 1"),
+            ("table t1 ( (col1 '{index(}') )", "expected string for function argument, found newline on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_table_with_empty_conflict_target() {
+        let err = parse(tokens("table t1 conflict () ()")).unwrap_err();
+        assert_eq!(err.to_string(), "expected identifier, found symbol `)` on line 1");
+    }
+
+    #[test]
+    fn test_table_named_conflict() {
+        let tree = parse(tokens("table conflict as conflict conflict (conflict) ( conflict (conflict 1) )")).unwrap();
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+
+        assert_eq!(table.identity.name, "conflict");
+        assert_eq!(table.identity.alias.as_deref(), Some("conflict"));
+        assert_eq!(table.conflict_target, Some(vec!["conflict".to_owned()]));
+        assert_eq!(table.nodes[0].name.as_deref(), Some("conflict"));
+        assert_eq!(table.nodes[0].nodes[0].name, "conflict");
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let tree = parse(tokens("\nnamespace billing\nschema s1 (table t1 ())\ntable t2 (\n  (col1 @sales::record1.col1, col2 @sales::t1.record1.)\n)\ntable namespace ( namespace (namespace 1) )")).unwrap();
+        let tables: Vec<&Table> = tree
+            .nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Schema(schema) => schema.nodes.iter().collect(),
+                StructuralNode::Table(table) => vec![&**table],
+            })
+            .collect();
+
+        assert!(tables.iter().all(|t| t.namespace.as_deref() == Some("billing")));
+        assert_eq!(tables[2].identity.name, "namespace");
+        assert_eq!(tables[2].nodes[0].name.as_deref(), Some("namespace"));
+        assert_eq!(
+            tables[1].nodes[0].nodes.iter().map(|a| a.value.clone()).collect::<Vec<_>>(),
+            vec![
+                Value::Reference(Reference::RecordLevel(RecordLevelReference {
+                    namespace: Some("sales".to_owned()),
+                    record: "record1".to_owned(),
+                    column: ReferencedColumn::Explicit("col1".to_owned()),
+                })),
+                Value::Reference(Reference::TableLevel(TableLevelReference {
+                    namespace: Some("sales".to_owned()),
+                    table: "t1".to_owned(),
+                    record: "record1".to_owned(),
+                    column: ReferencedColumn::Implicit,
+                })),
+            ],
+        );
+
+        for (input, message) in [
+            ("table t1 ()\nnamespace billing", "unexpected identifier `namespace` on line 2"),
+            ("namespace billing\nnamespace sales", "unexpected identifier `namespace` on line 2"),
+            ("namespace \"billing\"", "expected identifier, found quoted identifier `\"billing\"` on line 1"),
+            ("table t1 ( (col1 @sales::col2) )", "expected record in reference to namespace `sales`, found column `col2` on line 1"),
+            ("table t1 ( (col1 @sales::t1::record1.) )", "unexpected symbol `::` on line 1"),
+            ("table t1 ( (col1 @\"sales\"::record1.) )", "unexpected symbol `::` on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_identifier_case() {
+        use crate::parser::builder::{reference, text, ParseTreeBuilder};
+
+        let tree = parse(tokens(
+            "schema HR as H (\n  table Person (\n    Kevin (\"Name\" 'Kevin', Boss_id @H.\"Person\".Kevin.ID)\n  )\n)\n\
+             table \"Review\" (\n  (person_id @Person.Kevin, (Col_A, col_b) @HR.person.Kevin.(Col_C, \"Col_D\"))\n)",
+        ))
+        .unwrap();
+
+        // Unquoted names and aliases are folded to lowercase, unlike record names
+        let expected = ParseTreeBuilder::new()
+            .schema_as("hr", "h")
+            .table("person")
+            .record("Kevin")
+            .attr("Name", text("Kevin"))
+            .attr("boss_id", reference("h.Person.Kevin.id"))
+            .end_schema()
+            .table("Review")
+            .anonymous_record()
+            .attr("person_id", reference("Person.kevin"))
+            .attr("col_a", reference("hr.person.Kevin.col_c"))
+            .attr("col_b", reference("hr.person.Kevin.Col_D"))
+            .build();
+
+        assert_eq!(tree, expected);
+    }
+
+    #[test]
+    fn test_roles() {
+        let tree = parse(tokens(
+            "schema s1 as role admin (\n  table t1 ()\n  table t2 as role \"App User\" ()\n)\nschema s2 as s as role admin ()\n\
+             table t3 as tt as role app truncate ()\ntable t4 as role ()\ntable t5 ()",
+        ))
+        .unwrap();
+
+        let mut roles = Vec::new();
+
+        for node in &tree.nodes {
+            match node {
+                StructuralNode::Schema(schema) => {
+                    roles.push((schema.identity.clone(), schema.role.clone()));
+                    roles.extend(schema.nodes.iter().map(|t| (t.identity.clone(), t.role.clone())));
+                }
+                StructuralNode::Table(table) => roles.push((table.identity.clone(), table.role.clone())),
+            }
+        }
+
+        let identity = |name: &str, alias: Option<&str>| StructuralIdentity::new(name.to_owned(), alias.map(str::to_owned));
+        let role = |role: &str| Some(role.to_owned());
+
+        // Tables are loaded as their schema's role unless they declare their own, and
+        // `role` on its own is an alias
+        assert_eq!(
+            roles,
+            vec![
+                (identity("s1", None), role("admin")),
+                (identity("t1", None), role("admin")),
+                (identity("t2", None), role("App User")),
+                (identity("s2", Some("s")), role("admin")),
+                (identity("t3", Some("tt")), role("app")),
+                (identity("t4", Some("role")), None),
+                (identity("t5", None), None),
+            ],
+        );
 
-    match context.stack.pop() {
-        Some(states::StackItem::TreeRoot(tree)) => Ok(*tree),
-        _ => Err(ParseError {
-            kind: ParseErrorKind::UnexpectedEOF,
-        }),
+        for (input, message) in [
+            ("table t1 as tt as admin ()", "expected `role`, found identifier `admin` on line 1"),
+            ("table t1 as tt as role ()", "expected identifier, found symbol `(` on line 1"),
+            ("table t1 as role admin as role app ()", "expected opening parenthesis, found keyword `as` on line 1"),
+            ("table t1 as role admin as tt ()", "expected opening parenthesis, found keyword `as` on line 1"),
+            ("schema s1 as s as role 'admin' ()", "expected identifier, found string ''admin'' on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::parse;
-    use crate::lexer::tokenize;
-    use crate::lexer::tokens::Token;
-    use crate::parser::nodes::*;
+    #[test]
+    fn test_settings() {
+        let tree = parse(tokens(
+            "schema s1 with (app.tenant_id '42', app.strict true) (\n  table t1 ()\n  table t2 as tt with (\n    app.tenant_id 7\n  ) ()\n)\n\
+             table t3 as role admin with (statement_timeout '5s') truncate ()\ntable t4 ()",
+        ))
+        .unwrap();
 
-    fn tokens(input: &str) -> impl Iterator<Item = Token> {
-        tokenize(input.chars()).unwrap().into_iter()
+        let mut settings = Vec::new();
+
+        for node in &tree.nodes {
+            match node {
+                StructuralNode::Schema(schema) => settings.extend(schema.nodes.iter().map(|t| t.settings.clone())),
+                StructuralNode::Table(table) => settings.push(table.settings.clone()),
+            }
+        }
+
+        let setting = |name: &str, value: &str| Setting {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        };
+
+        // Tables are loaded with their schema's settings along with their own, which
+        // take precedence
+        assert_eq!(
+            settings,
+            vec![
+                vec![setting("app.tenant_id", "42"), setting("app.strict", "true")],
+                vec![setting("app.strict", "true"), setting("app.tenant_id", "7")],
+                vec![setting("statement_timeout", "5s")],
+                vec![],
+            ],
+        );
+
+        for (input, message) in [
+            ("table t1 with app.tenant_id '42' ()", "expected opening parenthesis, found identifier `app` on line 1"),
+            ("table t1 with (app.tenant_id) ()", "expected string, number, or boolean for setting value, found symbol `)` on line 1"),
+            ("table t1 with (app. '42') ()", "expected identifier, found string ''42'' on line 1"),
+            ("table t1 with (app.tenant_id '42' app.user_id '7') ()", "expected comma, newline, or closing parenthesis, found identifier `app` on line 1"),
+            ("table t1 with (a 1) with (b 2) ()", "expected opening parenthesis, found identifier `with` on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
     }
 
     #[test]
-    fn test_empty_input() {
-        let input: Vec<Token> = Vec::new();
-        assert_eq!(parse(input.into_iter()), Ok(ParseTree::default()));
+    fn test_hooks() {
+        let tree = parse(tokens(
+            "before `SET CONSTRAINTS ALL DEFERRED`\nschema s1 (\n  after `REFRESH MATERIALIZED VIEW s1.totals`\n  table t1 ()\n)\nafter `ANALYZE`",
+        ))
+        .unwrap();
+        let hook = |timing, sql: &str, line| Hook {
+            timing,
+            sql: sql.to_owned(),
+            position: Some(crate::Position { line, column: 1 }),
+        };
+
+        assert_eq!(
+            tree.hooks,
+            vec![
+                hook(HookTiming::Before, "SET CONSTRAINTS ALL DEFERRED", 1),
+                Hook {
+                    position: Some(crate::Position { line: 3, column: 3 }),
+                    ..hook(HookTiming::After, "REFRESH MATERIALIZED VIEW s1.totals", 3)
+                },
+                hook(HookTiming::After, "ANALYZE", 6),
+            ],
+        );
+        assert_eq!(tree.nodes.len(), 1);
+
+        for (input, message) in [
+            ("after 'ANALYZE'", "expected SQL fragment without embedded values for hook, found string ''ANALYZE'' on line 1"),
+            ("after `ANALYZE {@t1.a.b}`", "expected SQL fragment without embedded values for hook, found interpolated SQL fragment on line 1"),
+            ("after `ANALYZE` table t1 ()", "unexpected keyword `table` on line 1"),
+            ("profile demo (\n  after `ANALYZE`\n)", "unexpected identifier `after` on line 2"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
     }
 
     #[test]
-    fn test_empty_schema() {
-        /*
-        let position = Position::default();
-        let input = vec![
-            Tkn { position, kind: TKnd::Keyword(Kwd::Schema) },
-            Tkn { position, kind: TKnd::Identifier("my_schema".to_owned()) },
-            Tkn { position, kind: TKnd::Symbol(Sym::ParenLeft) },
-            Tkn { position, kind: TKnd::Symbol(Sym::ParenRight) },
-        ];
-         */
+    fn test_search_path() {
+        let tree = parse(tokens("namespace billing\nset search_path app, \"Public\"\ntable t1 ()\ntable set ( set (set 1) )")).unwrap();
 
-        let input = tokens("schema my_schema ()");
+        assert_eq!(
+            tree.search_paths,
+            vec![SearchPath {
+                schemas: vec!["app".to_owned(), "Public".to_owned()],
+                position: Some(crate::Position { line: 2, column: 1 }),
+            }],
+        );
+        assert_eq!(tree.nodes.len(), 2);
+
+        match &tree.nodes[1] {
+            StructuralNode::Table(table) => {
+                assert_eq!(table.identity.name, "set");
+                assert_eq!(table.nodes[0].name.as_deref(), Some("set"));
+                assert_eq!(table.nodes[0].nodes[0].name, "set");
+            }
+            node => panic!("expected table, found {:?}", node),
+        }
+
+        for (input, message) in [
+            ("table t1 ()\nset search_path app", "unexpected identifier `set` on line 2"),
+            ("set search_path app\nset search_path public", "unexpected identifier `set` on line 2"),
+            ("set role admin", "expected `search_path` for setting, found identifier `role` on line 1"),
+            ("set search_path app,", "unexpected end of file"),
+            ("set search_path app public", "unexpected identifier `public` on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_expected_rows() {
+        let tree = parse(tokens(
+            "table t1 (\n  expect 2 rows\n  expect (a 1)\n  (a 2)\n)\ntable t2 (expect 1 row)\ntable t3 ()",
+        ))
+        .unwrap();
+        let tables: Vec<(Option<u64>, Vec<Option<&str>>)> = tree
+            .nodes
+            .iter()
+            .map(|node| match node {
+                StructuralNode::Table(table) => {
+                    (table.expected_rows, table.nodes.iter().map(|r| r.name.as_deref()).collect())
+                }
+                node => panic!("expected table, found {:?}", node),
+            })
+            .collect();
 
         assert_eq!(
-            parse(input),
-            Ok(ParseTree {
-                nodes: vec![StructuralNode::Schema(Box::new(Schema {
-                    identity: StructuralIdentity {
-                        alias: None,
-                        name: "my_schema".to_owned(),
-                    },
-                    nodes: Vec::new(),
-                })),],
-            }),
+            tables,
+            vec![(Some(2), vec![Some("expect"), None]), (Some(1), vec![]), (None, vec![])],
         );
+
+        for (input, message) in [
+            ("table t1 (expect -1 rows)", "expected non-negative integer for expected row count, found number `-1` on line 1"),
+            ("table t1 (expect 1.5 rows)", "expected non-negative integer for expected row count, found number `1.5` on line 1"),
+            ("table t1 (expect 2)", "expected `rows` after expected row count, found symbol `)` on line 1"),
+            ("table t1 (expect 'a')", "expected opening parenthesis, found string ''a'' on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
     }
 
     #[test]
-    fn test_empty_schema_with_alias() {
-        /*
-        let position = Position::default();
-        let input = vec![
-            Tkn { position, kind: TKnd::Keyword(Kwd::Schema) },
-            Tkn { position, kind: TKnd::Identifier("my_other_schema".to_owned()) },
-            Tkn { position, kind: TKnd::Keyword(Kwd::As) },
-            Tkn { position, kind: TKnd::Identifier("some_alias".to_owned()) },
-            Tkn { position, kind: TKnd::Symbol(Sym::ParenLeft) },
-            Tkn { position, kind: TKnd::Symbol(Sym::ParenRight) },
-        ];
-        */
+    fn test_variables() {
+        let tree = parse(tokens(
+            "let password = 'hunter2'\nlet max = -1\n\ntable t1 (\n  (pw $password, n $max + 1, email '{$password}@x.dev')\n)",
+        ))
+        .unwrap();
+        let position = |line| Some(crate::Position { line, column: 1 });
 
-        let input = tokens("schema my_other_schema as some_alias ()");
+        assert_eq!(
+            tree.variables,
+            vec![
+                Variable { name: "password".to_owned(), value: Value::Text("'hunter2'".to_owned()), position: position(1) },
+                Variable { name: "max".to_owned(), value: Value::Number("-1".to_owned()), position: position(2) },
+            ],
+        );
+
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
 
         assert_eq!(
-            parse(input),
-            Ok(ParseTree {
-                nodes: vec![StructuralNode::Schema(Box::new(Schema {
-                    identity: StructuralIdentity {
-                        alias: Some("some_alias".to_owned()),
-                        name: "my_other_schema".to_owned(),
-                    },
-                    nodes: Vec::new(),
-                })),],
-            }),
+            table.nodes[0].nodes.iter().map(|a| a.value.clone()).collect::<Vec<_>>(),
+            vec![
+                Value::Variable("password".to_owned()),
+                Value::Variable("max".to_owned()).combine(Operator::Add, Value::Number("1".to_owned())),
+                Value::InterpolatedText(vec![
+                    TextSegment::Value(Value::Variable("password".to_owned())),
+                    TextSegment::Literal("@x.dev".to_owned()),
+                ]),
+            ],
         );
+
+        for (input, message) in [
+            ("let = 1", "expected identifier, found symbol `=` on line 1"),
+            ("let pw 'hunter2'", "expected `=` after variable name, found string ''hunter2'' on line 1"),
+            ("let pw = @t1.a.b", "expected string, number, boolean, or null for variable value, found symbol `@` on line 1"),
+            ("let pw = 'a' table t1 ()", "unexpected keyword `table` on line 1"),
+            ("table t1 (\n  let pw = 'a'\n)", "expected opening parenthesis, found identifier `pw` on line 2"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
     }
 
     #[test]
-    fn test_empty_top_level_table() {
-        /*
-        let position = Position::default();
-        let input = vec![
-            Tkn {
-                kind: TKnd::Keyword(Kwd::Table),
-                position,
-            },
-            Tkn {
-                kind: TKnd::Identifier("my_table".to_owned()),
-                position,
-            },
-            Tkn {
-                kind: TKnd::Symbol(Sym::ParenLeft),
-                position,
-            },
-            Tkn {
-                kind: TKnd::Symbol(Sym::ParenRight),
-                position,
-            },
-        ];
-        */
+    fn test_reference_shorthand() {
+        let tree = parse(tokens(
+            "table pet (\n  (person_id@ kevin, favorite_toy_id@ s1.ball, person_id@ sales::kevin, person_id @kevin.)\n)",
+        ))
+        .unwrap();
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
 
-        let input = tokens("table my_table ()");
+        assert_eq!(
+            table.nodes[0].nodes.iter().map(|a| a.value.clone()).collect::<Vec<_>>(),
+            vec![
+                Value::Reference(Reference::TableLevel(TableLevelReference {
+                    namespace: None,
+                    table: "person".to_owned(),
+                    record: "kevin".to_owned(),
+                    column: ReferencedColumn::Explicit("id".to_owned()),
+                })),
+                Value::Reference(Reference::SchemaLevel(SchemaLevelReference {
+                    namespace: None,
+                    schema: "s1".to_owned(),
+                    table: "favorite_toy".to_owned(),
+                    record: "ball".to_owned(),
+                    column: ReferencedColumn::Explicit("id".to_owned()),
+                })),
+                Value::Reference(Reference::TableLevel(TableLevelReference {
+                    namespace: Some("sales".to_owned()),
+                    table: "person".to_owned(),
+                    record: "kevin".to_owned(),
+                    column: ReferencedColumn::Explicit("id".to_owned()),
+                })),
+                Value::Reference(Reference::RecordLevel(RecordLevelReference {
+                    namespace: None,
+                    record: "kevin".to_owned(),
+                    column: ReferencedColumn::Implicit,
+                })),
+            ],
+        );
+
+        for (input, message) in [
+            ("table t1 ( (name@ kevin) )", "expected column named after a table and its column for shorthand reference, eg. `person_id`, found `name` on line 1"),
+            ("table t1 ( (person_@ kevin) )", "expected column named after a table and its column for shorthand reference, eg. `person_id`, found `person_` on line 1"),
+            ("table t1 ( (person_id@ s1.person.kevin) )", "unexpected symbol `.` on line 1"),
+            ("table t1 ( (person_id@ kevin.) )", "expected identifier, found symbol `)` on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_multi_column_references() {
+        let tree = parse(tokens(
+            "table grant (\n  ((tenant_id, user_id) @m1.(tenant_id, id), role 'admin')\n  (name 'x', (a, b) @s1.t1.r1.\n  )\n)",
+        ))
+        .unwrap();
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+        let attributes = |record: &Record| {
+            record.nodes.iter().map(|a| (a.name.clone(), a.value.clone())).collect::<Vec<_>>()
+        };
+        let schema_level = |column: ReferencedColumn| {
+            Value::Reference(Reference::SchemaLevel(SchemaLevelReference {
+                namespace: None,
+                schema: "s1".to_owned(),
+                table: "t1".to_owned(),
+                record: "r1".to_owned(),
+                column,
+            }))
+        };
 
         assert_eq!(
-            parse(input),
-            Ok(ParseTree {
-                nodes: vec![StructuralNode::Table(Box::new(Table {
-                    identity: StructuralIdentity {
-                        alias: None,
-                        name: "my_table".to_owned(),
-                    },
-                    nodes: Vec::new(),
-                })),],
-            }),
+            attributes(&table.nodes[0]),
+            vec![
+                (
+                    "tenant_id".to_owned(),
+                    Value::Reference(Reference::RecordLevel(RecordLevelReference {
+                        namespace: None,
+                        record: "m1".to_owned(),
+                        column: ReferencedColumn::Explicit("tenant_id".to_owned()),
+                    })),
+                ),
+                (
+                    "user_id".to_owned(),
+                    Value::Reference(Reference::RecordLevel(RecordLevelReference {
+                        namespace: None,
+                        record: "m1".to_owned(),
+                        column: ReferencedColumn::Explicit("id".to_owned()),
+                    })),
+                ),
+                ("role".to_owned(), Value::Text("'admin'".to_owned())),
+            ],
+        );
+        assert_eq!(
+            attributes(&table.nodes[1]),
+            vec![
+                ("name".to_owned(), Value::Text("'x'".to_owned())),
+                ("a".to_owned(), schema_level(ReferencedColumn::Implicit)),
+                ("b".to_owned(), schema_level(ReferencedColumn::Implicit)),
+            ],
         );
+
+        for (input, message) in [
+            ("table t1 ( ((a, b) 1) )", "expected reference for columns, found number `1` on line 1"),
+            ("table t1 ( ((a, b) @m1.a) )", "expected columns in parentheses to reference for each column, found symbol `)` on line 1"),
+            ("table t1 ( ((a, b) @m1.(a)) )", "expected 2 referenced columns, found 1 on line 1"),
+            ("table t1 ( ((a, b) @m1.(a, b) + 1) )", "expected comma, newline, or closing parenthesis, found symbol `+` on line 1"),
+            ("table t1 ( ((a b) @m1.(a, b)) )", "expected comma or closing parenthesis, found identifier `b` on line 1"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
     }
 
     #[test]
-    fn test_empty_top_level_table_with_alias() {
-        /*
-        let position = Position::default();
-        let input = vec![
-            Tkn {
-                kind: TKnd::Keyword(Kwd::Table),
-                position,
-            },
-            Tkn {
-                kind: TKnd::Identifier("my_other_table".to_owned()),
-                position,
-            },
-            Tkn {
-                kind: TKnd::Keyword(Kwd::As),
-                position,
-            },
-            Tkn {
-                kind: TKnd::Identifier("some_alias".to_owned()),
-                position,
-            },
-            Tkn {
-                kind: TKnd::Symbol(Sym::ParenLeft),
-                position,
-            },
-            Tkn {
-                kind: TKnd::Symbol(Sym::ParenRight),
-                position,
-            },
-        ];
-        */
+    fn test_imports() {
+        let tree = parse(tokens("table t1 (\n  $import 'a.csv'\n  record1 (col1 1)\n  $import 'data/it''s.csv'\n)")).unwrap();
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
 
-        let input = tokens("table my_other_table as another_alias ()");
+        assert_eq!(table.nodes.len(), 1);
+        assert_eq!(
+            table.imports,
+            vec![
+                Import {
+                    path: "a.csv".to_owned(),
+                    index: 0,
+                    position: crate::Position { line: 2, column: 3 },
+                },
+                Import {
+                    path: "data/it's.csv".to_owned(),
+                    index: 1,
+                    position: crate::Position { line: 4, column: 3 },
+                },
+            ],
+        );
 
+        let err = parse(tokens("table t1 ( $import a.csv )")).unwrap_err();
+        assert_eq!(err.to_string(), "expected string for imported file path, found identifier `a` on line 1");
+    }
+
+    #[test]
+    fn test_includes() {
+        let tree = parse(tokens(
+            "include 'roles.hldr'\ntable t1 ()\n  include 'shared/it''s.hldr'\ntable include ( include (include 1) )",
+        ))
+        .unwrap();
+
+        assert_eq!(tree.nodes.len(), 2);
         assert_eq!(
-            parse(input.into_iter()),
-            Ok(ParseTree {
-                nodes: vec![StructuralNode::Table(Box::new(Table {
-                    identity: StructuralIdentity {
-                        alias: Some("another_alias".to_owned()),
-                        name: "my_other_table".to_owned(),
-                    },
-                    nodes: Vec::new(),
-                })),],
-            }),
+            tree.includes,
+            vec![
+                Include {
+                    path: "roles.hldr".to_owned(),
+                    index: 0,
+                    position: crate::Position { line: 1, column: 1 },
+                },
+                Include {
+                    path: "shared/it's.hldr".to_owned(),
+                    index: 1,
+                    position: crate::Position { line: 3, column: 3 },
+                },
+            ],
+        );
+
+        for (input, message) in [
+            ("include roles", "expected string for included file path, found identifier `roles` on line 1"),
+            ("include 'roles.hldr' table t1 ()", "unexpected keyword `table` on line 1"),
+            ("table t1 (\n  include 'roles.hldr'\n)", "expected opening parenthesis, found string ''roles.hldr'' on line 2"),
+        ] {
+            let err = parse(tokens(input)).unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_trivia() {
+        let tree = parse(tokens(
+            "-- people\ntable person ( -- everyone\n  kevin ( name 'Kevin' ) -- the boss\n\n  -- the others\n  stacey (\n    name 'Stacey' -- first name\n    boss_id @kevin.id -- reports to\n  )\n  -- more to come\n) -- end\n\n-- the end",
+        ))
+        .unwrap();
+        let comment = |text: &str| TriviaItem::Comment(text.to_owned());
+        let table = match &tree.nodes[0] {
+            StructuralNode::Table(table) => table,
+            node => panic!("expected table, found {:?}", node),
+        };
+
+        assert_eq!(
+            table.trivia,
+            Trivia {
+                leading: vec![comment(" people")],
+                trailing: vec![" everyone".to_owned(), " end".to_owned()],
+                inner: vec![comment(" more to come")],
+            },
+        );
+        assert_eq!(
+            table.nodes.iter().map(|record| &record.trivia).collect::<Vec<_>>(),
+            vec![
+                &Trivia {
+                    trailing: vec![" the boss".to_owned()],
+                    ..Trivia::default()
+                },
+                &Trivia {
+                    leading: vec![TriviaItem::BlankLine, comment(" the others")],
+                    ..Trivia::default()
+                },
+            ],
+        );
+        assert_eq!(
+            table.nodes[1].nodes.iter().map(|attribute| attribute.trivia.trailing.clone()).collect::<Vec<_>>(),
+            vec![vec![" first name".to_owned()], vec![" reports to".to_owned()]],
+        );
+        assert_eq!(tree.trivia, vec![TriviaItem::BlankLine, comment(" the end")]);
+        assert_eq!(
+            table.trivia.comments().collect::<Vec<_>>(),
+            vec![" people", " everyone", " end", " more to come"],
         );
     }
 
@@ -209,14 +2155,37 @@ mod tests {
                         alias: None,
                         name: "myschema".to_owned(),
                     },
+                    role: None,
+                    settings: Vec::new(),
                     nodes: vec![Table {
                         identity: StructuralIdentity {
                             alias: None,
                             name: "mytable".to_owned(),
                         },
+                        conflict_target: None,
+                        truncate: false,
+                        expected_rows: None,
+                        role: None,
+                        settings: Vec::new(),
+                        tags: Vec::new(),
+                        profile: None,
+                        namespace: None,
+                        defaults: Vec::new(),
+                        imports: Vec::new(),
                         nodes: Vec::new(),
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
                     },],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
                 })),],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
             }),
         );
     }
@@ -240,14 +2209,37 @@ mod tests {
                         alias: Some("s1".to_owned()),
                         name: "myschema".to_owned(),
                     },
+                    role: None,
+                    settings: Vec::new(),
                     nodes: vec![Table {
                         identity: StructuralIdentity {
                             alias: Some("t1".to_owned()),
                             name: "mytable".to_owned(),
                         },
+                        conflict_target: None,
+                        truncate: false,
+                        expected_rows: None,
+                        role: None,
+                        settings: Vec::new(),
+                        tags: Vec::new(),
+                        profile: None,
+                        namespace: None,
+                        defaults: Vec::new(),
+                        imports: Vec::new(),
                         nodes: Vec::new(),
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
                     },],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
                 })),],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
             }),
         );
     }
@@ -280,36 +2272,90 @@ mod tests {
                             alias: None,
                             name: "s1".to_owned(),
                         },
+                        role: None,
+                        settings: Vec::new(),
                         nodes: vec![Table {
                             identity: StructuralIdentity {
                                 alias: None,
                                 name: "t1".to_owned(),
                             },
+                            conflict_target: None,
+                            truncate: false,
+                            expected_rows: None,
+                            role: None,
+                            settings: Vec::new(),
+                            tags: Vec::new(),
+                            profile: None,
+                            namespace: None,
+                            defaults: Vec::new(),
+                            imports: Vec::new(),
                             nodes: vec![
                                 Record {
                                     name: Some("record1".to_owned()),
+                                    quantity: None,
+                                    extends: None,
+                                    is_abstract: false,
+                                    tags: Vec::new(),
+                                    update_where: None,
+                                    delete: false,
                                     nodes: Vec::new(),
+                                    position: None,
+                                    span: None,
+                                    trivia: Trivia::default(),
                                 },
                                 Record::default(),
                                 Record::default(),
                             ],
+                            position: None,
+                            span: None,
+                            trivia: Trivia::default(),
                         },],
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
                     })),
                     StructuralNode::Table(Box::new(Table {
                         identity: StructuralIdentity {
                             alias: None,
                             name: "t2".to_owned(),
                         },
+                        conflict_target: None,
+                        truncate: false,
+                        expected_rows: None,
+                        role: None,
+                        settings: Vec::new(),
+                        tags: Vec::new(),
+                        profile: None,
+                        namespace: None,
+                        defaults: Vec::new(),
+                        imports: Vec::new(),
                         nodes: vec![
                             Record::default(),
                             Record::default(),
                             Record {
                                 name: Some("record2".to_owned()),
+                                quantity: None,
+                                extends: None,
+                                is_abstract: false,
+                                tags: Vec::new(),
+                                update_where: None,
+                                delete: false,
                                 nodes: Vec::new(),
+                                position: None,
+                                span: None,
+                                trivia: Trivia::default(),
                             },
                         ],
+                        position: None,
+                        span: None,
+                        trivia: Trivia::default(),
                     })),
                 ],
+                includes: Vec::new(),
+                search_paths: Vec::new(),
+                variables: Vec::new(),
+                hooks: Vec::new(),
+                trivia: Vec::new(),
             })
         );
     }
@@ -325,6 +2371,7 @@ mod tests {
                         col1 123
                         col2 true
                         col3 'hello!'
+                        col5 null
 
                         -- column reference
                         col4 @col3
@@ -359,105 +2406,240 @@ mod tests {
                 alias: None,
                 name: "t1".to_owned(),
             },
+            conflict_target: None,
+            truncate: false,
+            expected_rows: None,
+            role: None,
+            settings: Vec::new(),
+            tags: Vec::new(),
+            profile: None,
+            namespace: None,
+            defaults: Vec::new(),
+            imports: Vec::new(),
             nodes: vec![
                 Record {
                     name: Some("record1".to_owned()),
+                    quantity: None,
+                    extends: None,
+                    is_abstract: false,
+                    tags: Vec::new(),
+                    update_where: None,
+                    delete: false,
                     nodes: vec![
                         Attribute {
                             name: "col1".to_owned(),
-                            value: Value::Number(Box::new("123".to_owned())),
+                            value: Value::Number("123".to_owned()),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
                         },
                         Attribute {
                             name: "col2".to_owned(),
                             value: Value::Bool(true),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
                         },
                         Attribute {
                             name: "col3".to_owned(),
-                            value: Value::Text(Box::new("'hello!'".to_owned())),
+                            value: Value::Text("'hello!'".to_owned()),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
+                        },
+                        Attribute {
+                            name: "col5".to_owned(),
+                            value: Value::Null,
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
                         },
                         Attribute {
                             name: "col4".to_owned(),
-                            value: Value::Reference(Box::new(Reference {
-                                schema: None,
-                                table: None,
-                                record: None,
+                            value: Value::Reference(Reference::ColumnLevel(ColumnLevelReference {
                                 column: "col3".to_owned(),
                             })),
+                            position: None,
+                            span: None,
+                            value_span: None,
+                            trivia: Trivia::default(),
                         },
                     ],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
                 },
                 Record {
                     name: None,
+                    quantity: None,
+                    extends: None,
+                    is_abstract: false,
+                    tags: Vec::new(),
+                    update_where: None,
+                    delete: false,
                     nodes: vec![Attribute {
                         name: "col".to_owned(),
-                        value: Value::Reference(Box::new(Reference {
-                            schema: None,
-                            table: None,
-                            record: Some("record1".to_owned()),
-                            column: "col1".to_owned(),
+                        value: Value::Reference(Reference::RecordLevel(RecordLevelReference {
+                            namespace: None,
+                            record: "record1".to_owned(),
+                            column: ReferencedColumn::Explicit("col1".to_owned()),
                         })),
+                        position: None,
+                        span: None,
+                        value_span: None,
+                        trivia: Trivia::default(),
                     }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
                 },
             ],
+            position: None,
+            span: None,
+            trivia: Trivia::default(),
         };
         let t2 = Table {
             identity: StructuralIdentity {
                 alias: None,
                 name: "t2".to_owned(),
             },
+            conflict_target: None,
+            truncate: false,
+            expected_rows: None,
+            role: None,
+            settings: Vec::new(),
+            tags: Vec::new(),
+            profile: None,
+            namespace: None,
+            defaults: Vec::new(),
+            imports: Vec::new(),
             nodes: vec![
                 Record {
                     name: None,
+                    quantity: None,
+                    extends: None,
+                    is_abstract: false,
+                    tags: Vec::new(),
+                    update_where: None,
+                    delete: false,
                     nodes: vec![Attribute {
                         name: "colx".to_owned(),
-                        value: Value::Reference(Box::new(Reference {
-                            schema: Some("s1".to_owned()),
-                            table: Some("t1".to_owned()),
-                            record: Some("record1".to_owned()),
-                            column: "col2".to_owned(),
+                        value: Value::Reference(Reference::SchemaLevel(SchemaLevelReference {
+                            namespace: None,
+                            schema: "s1".to_owned(),
+                            table: "t1".to_owned(),
+                            record: "record1".to_owned(),
+                            column: ReferencedColumn::Explicit("col2".to_owned()),
                         })),
+                        position: None,
+                        span: None,
+                        value_span: None,
+                        trivia: Trivia::default(),
                     }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
                 },
                 Record {
                     name: None,
+                    quantity: None,
+                    extends: None,
+                    is_abstract: false,
+                    tags: Vec::new(),
+                    update_where: None,
+                    delete: false,
                     nodes: vec![Attribute {
                         name: "coly".to_owned(),
-                        value: Value::Reference(Box::new(Reference {
+                        value: Value::Reference(Reference::SchemaLevel(SchemaLevelReference {
+                            namespace: None,
                             // TODO: Should these actually be explicitly quoted?
-                            schema: Some("\"s1\"".to_owned()),
-                            table: Some("\"t1\"".to_owned()),
-                            record: Some("record1".to_owned()),
-                            column: "\"col2\"".to_owned(),
+                            schema: "s1".to_owned(),
+                            table: "t1".to_owned(),
+                            record: "record1".to_owned(),
+                            column: ReferencedColumn::Explicit("col2".to_owned()),
                         })),
+                        position: None,
+                        span: None,
+                        value_span: None,
+                        trivia: Trivia::default(),
                     }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
                 },
                 Record {
                     name: Some("record2".to_owned()),
+                    quantity: None,
+                    extends: None,
+                    is_abstract: false,
+                    tags: Vec::new(),
+                    update_where: None,
+                    delete: false,
                     nodes: vec![Attribute {
                         name: "col".to_owned(),
-                        value: Value::Number(Box::new("1234".to_owned())),
+                        value: Value::Number("1234".to_owned()),
+                        position: None,
+                        span: None,
+                        value_span: None,
+                        trivia: Trivia::default(),
                     }],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
                 },
                 Record::default(),
             ],
+            position: None,
+            span: None,
+            trivia: Trivia::default(),
         };
         let t3 = Table {
             identity: StructuralIdentity {
                 alias: None,
                 name: "t3".to_owned(),
             },
+            conflict_target: None,
+            truncate: false,
+            expected_rows: None,
+            role: None,
+            settings: Vec::new(),
+            tags: Vec::new(),
+            profile: None,
+            namespace: None,
+            defaults: Vec::new(),
+            imports: Vec::new(),
             nodes: vec![Record {
                 name: None,
+                quantity: None,
+                extends: None,
+                is_abstract: false,
+                tags: Vec::new(),
+                update_where: None,
+                delete: false,
                 nodes: vec![Attribute {
                     name: "col".to_owned(),
-                    value: Value::Reference(Box::new(Reference {
-                        schema: None,
-                        table: Some("t2".to_owned()),
-                        record: Some("record2".to_owned()),
-                        column: "col".to_owned(),
+                    value: Value::Reference(Reference::TableLevel(TableLevelReference {
+                        namespace: None,
+                        table: "t2".to_owned(),
+                        record: "record2".to_owned(),
+                        column: ReferencedColumn::Explicit("col".to_owned()),
                     })),
+                    position: None,
+                    span: None,
+                    value_span: None,
+                    trivia: Trivia::default(),
                 }],
+                position: None,
+                span: None,
+                trivia: Trivia::default(),
             }],
+            position: None,
+            span: None,
+            trivia: Trivia::default(),
         };
 
         let expected = Ok(ParseTree {
@@ -467,14 +2649,131 @@ mod tests {
                         alias: None,
                         name: "s1".to_owned(),
                     },
+                    role: None,
+                    settings: Vec::new(),
                     nodes: vec![t1],
+                    position: None,
+                    span: None,
+                    trivia: Trivia::default(),
                 })),
                 StructuralNode::Table(Box::new(t2)),
                 StructuralNode::Table(Box::new(t3)),
             ],
+            includes: Vec::new(),
+            search_paths: Vec::new(),
+            variables: Vec::new(),
+            hooks: Vec::new(),
+            trivia: Vec::new(),
         });
         let result = parse(input);
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_stream() {
+        let input = "schema s1 (
+  table t1 (
+    rec1 (col 1)
+    (col 2)
+    (col 3)
+  )
+  table t2 ()
+)
+table t1 (
+  $defaults (col 0)
+  (col 4)
+  (col 5)
+)
+";
+        let streamed: Vec<(Option<String>, String, Vec<usize>, bool)> = super::stream(tokens(input).map(Ok::<_, ParseError>), 2)
+            .map(|streamed| {
+                let streamed = streamed.unwrap();
+                let lines = streamed.table.nodes.iter().map(|r| r.position.unwrap().line).collect();
+
+                (streamed.schema.map(|s| s.name), streamed.table.identity.name, lines, streamed.continued)
+            })
+            .collect();
+
+        assert_eq!(
+            streamed,
+            vec![
+                (Some("s1".to_owned()), "t1".to_owned(), vec![3, 4], false),
+                (Some("s1".to_owned()), "t1".to_owned(), vec![5], true),
+                (Some("s1".to_owned()), "t2".to_owned(), vec![], false),
+                // No records are left once the table closes, so it is not streamed again
+                (None, "t1".to_owned(), vec![11, 12], false),
+            ],
+        );
+
+        let errors = [
+            ("include 'a.hldr'\n", "cannot stream includes on line 1"),
+            ("set search_path app\n", "cannot stream search paths declared with `set search_path` on line 1"),
+            ("let pw = 'hunter2'\n", "cannot stream variables declared with `let` on line 1"),
+            ("after `ANALYZE`\n", "cannot stream hooks declared with `before` or `after` on line 1"),
+            ("table t1 (\n  $import 'a.csv'\n)\n", "cannot stream imports on line 2"),
+            ("table t1 truncate (\n  (col 1)\n)\n", "cannot stream tables declared with `truncate` on line 1"),
+            (
+                "table t1 (\n  (col 1)\n  (col 2)\n  $defaults (col 0)\n)\n",
+                "cannot stream `$defaults` declared after records on line 1",
+            ),
+            ("table t1 (\n  (col 1)\n  (col 2)\n", "unexpected end of file"),
+        ];
+
+        for (input, expected) in errors {
+            let error = super::stream(tokens(input).map(Ok::<_, ParseError>), 2).find_map(Result::err);
+            assert_eq!(error.map(|e| e.to_string()).as_deref(), Some(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let tree = parse(tokens("schema s1 (\n  table t1 (\n    rec1 (col1 1, col2 @rec2.id)\n  )\n)\n")).unwrap();
+        let span = |start: (usize, usize), end: (usize, usize)| {
+            Some(Span {
+                start: crate::Position { line: start.0, column: start.1 },
+                end: crate::Position { line: end.0, column: end.1 },
+            })
+        };
+
+        let StructuralNode::Schema(schema) = &tree.nodes[0] else {
+            panic!("expected a schema");
+        };
+        let table = &schema.nodes[0];
+        let record = &table.nodes[0];
+
+        assert_eq!(schema.span, span((1, 1), (5, 2)));
+        assert_eq!(table.span, span((2, 3), (4, 4)));
+        assert_eq!(record.span, span((3, 5), (3, 33)));
+        assert_eq!(record.nodes[0].span, span((3, 11), (3, 17)));
+        assert_eq!(record.nodes[0].value_span, span((3, 16), (3, 17)));
+        assert_eq!(record.nodes[1].span, span((3, 19), (3, 32)));
+        assert_eq!(record.nodes[1].value_span, span((3, 24), (3, 32)));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let tree = parse(tokens(
+            "let pw = 'secret'\nschema s1 (\n  table person (\n    bob (name 'Bob', password $pw) -- the boss\n    _ * 2 (boss_id @bob, uuid uuid5('6ba7b810-9dad-11d1-80b4-00c04fd430c8', 'bob'), created_at now() - interval '1 day')\n  )\n)",
+        ))
+        .unwrap();
+
+        for serialized in [tree.to_json(), tree.to_yaml()] {
+            let deserialized: ParseTree = serde_yaml::from_str(&serialized).unwrap();
+            assert_eq!(deserialized, tree);
+
+            let StructuralNode::Schema(schema) = &deserialized.nodes[0] else {
+                panic!("expected a schema");
+            };
+            assert_eq!(schema.nodes[0].nodes[0].position, Some(crate::Position { line: 4, column: 5 }));
+            assert_eq!(schema.nodes[0].nodes[0].trivia.trailing, vec![" the boss".to_owned()]);
+        }
+
+        // Anything that can be omitted from a built tree can be omitted when deserializing
+        let deserialized: ParseTree = serde_json::from_str(
+            r#"{"nodes": [{"type": "table", "value": {"identity": {"name": "person"}, "nodes": [{"nodes": [{"name": "name", "value": {"type": "text", "value": "'Bob'"}}]}]}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(deserialized, parse(tokens("table person ( (name 'Bob') )")).unwrap());
+    }
 }
@@ -1,15 +1,184 @@
-#[derive(Debug, Default, PartialEq)]
+use crate::identifier::render;
+use crate::Position;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::{fmt, mem};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct ParseTree {
     pub nodes: Vec<StructuralNode>,
+    /// Data files included with `include '...'`, which are expanded in place
+    /// when parsing data files rather than analyzed as part of the tree
+    pub includes: Vec<Include>,
+    /// The search paths declared with `set search_path ...` at the top of the data
+    /// files, which the analyzer checks all agree
+    pub search_paths: Vec<SearchPath>,
+    /// The variables declared with `let name = ...` in the data files, which the
+    /// analyzer substitutes for the values that use them
+    pub variables: Vec<Variable>,
+    /// The SQL declared with `before` and `after` at the top level of the data files
+    /// or in their schemas, which is executed before and after loading
+    pub hooks: Vec<Hook>,
+    /// Blank lines and comments after the last node in the file
+    pub trivia: Vec<TriviaItem>,
+}
+
+// Trivia is ignored when comparing trees and the nodes in them, like positions and spans are,
+// so that parsed nodes are equal to the same nodes built elsewhere
+impl PartialEq for ParseTree {
+    fn eq(&self, other: &Self) -> bool {
+        self.nodes == other.nodes
+            && self.includes == other.includes
+            && self.search_paths == other.search_paths
+            && self.variables == other.variables
+            && self.hooks == other.hooks
+    }
+}
+
+impl ParseTree {
+    /// Serializes the tree as pretty-printed JSON, which deserializes back into the same
+    /// tree, eg. for tools that generate trees to load.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("parse tree should serialize")
+    }
+
+    /// Serializes the tree as YAML, just as `to_json` does as JSON.
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("parse tree should serialize")
+    }
+}
+
+/// Where a node starts and ends in a data file, where the end is just past its last
+/// token, eg. the column after the closing parenthesis of a record. Attributes end at
+/// the comma or parenthesis after them instead, so may include whitespace.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// The blank lines and comments around a node, which are not loaded but are kept in
+/// the tree so that tools can reproduce or point at them.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Trivia {
+    /// Blank lines and comments on the lines before the node
+    pub leading: Vec<TriviaItem>,
+    /// Comments at the end of the lines the node starts or ends on, eg.
+    /// `kevin ( name 'Kevin' ) -- the boss`
+    pub trailing: Vec<String>,
+    /// Blank lines and comments after the last node declared within the node,
+    /// before its closing parenthesis
+    pub inner: Vec<TriviaItem>,
+}
+
+impl Trivia {
+    /// The text of every comment attached to the node
+    pub fn comments(&self) -> impl Iterator<Item = &str> {
+        self.leading
+            .iter()
+            .filter_map(TriviaItem::comment)
+            .chain(self.trailing.iter().map(String::as_str))
+            .chain(self.inner.iter().filter_map(TriviaItem::comment))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum TriviaItem {
+    BlankLine,
+    /// The text of a `--` comment, without the dashes
+    Comment(String),
+}
+
+impl TriviaItem {
+    fn comment(&self) -> Option<&str> {
+        match self {
+            TriviaItem::BlankLine => None,
+            TriviaItem::Comment(text) => Some(text),
+        }
+    }
+}
+
+/// A data file included at the top level of another with `include 'path'`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Include {
+    /// The path as written, which is relative to the including file
+    pub path: String,
+    /// The number of nodes declared before the include, which is where the nodes
+    /// of the included file go
+    pub index: usize,
+    pub position: Position,
+}
+
+/// The schemas that unqualified tables are resolved against, in order, declared with
+/// `set search_path ...` at the top of a data file.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SearchPath {
+    pub schemas: Vec<String>,
+    /// Where the search path was declared, if it was parsed rather than built
+    pub position: Option<Position>,
+}
+
+/// A value declared at the top level of a data file with `let name = ...`, which can
+/// then be used in place of the value with `$name`, eg. a shared password.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Variable {
+    pub name: String,
+    pub value: Value,
+    /// Where the variable was declared, if it was parsed rather than built
+    pub position: Option<Position>,
+}
+
+/// SQL declared with ``before `...` `` or ``after `...` ``, which is executed as-is at
+/// the start or the end of the transaction, eg. to refresh materialized views.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Hook {
+    pub timing: HookTiming,
+    pub sql: String,
+    /// Where the hook was declared, if it was parsed rather than built
+    pub position: Option<Position>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookTiming {
+    /// Executed before anything is truncated or loaded
+    Before,
+    /// Executed once everything is loaded
+    After,
+}
+
+/// A setting declared with `with (name 'value')` on a schema or table, which is set with
+/// `SET LOCAL` while the table's records are inserted, eg. the tenant that row-level
+/// security policies check.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Setting {
+    pub name: String,
+    pub value: String,
+}
+
+/// A CSV file imported into a table with `$import 'path'`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Import {
+    /// The path as written, which is relative to the importing file
+    pub path: String,
+    /// The number of records declared in the table before the import, which is
+    /// where the records read from the file go
+    pub index: usize,
+    pub position: Position,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum StructuralNode {
     Schema(Box<Schema>),
     Table(Box<Table>),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct StructuralIdentity {
     pub alias: Option<String>,
     pub name: String,
@@ -21,10 +190,26 @@ impl StructuralIdentity {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Schema {
     pub identity: StructuralIdentity,
+    /// The role that the schema's tables are loaded as unless they declare their own,
+    /// if declared with `as role ...`
+    #[serde(default)]
+    pub role: Option<String>,
+    /// The settings that the schema's tables are loaded with along with their own, if
+    /// declared with `with (...)`
+    #[serde(default)]
+    pub settings: Vec<Setting>,
+    #[serde(default)]
     pub nodes: Vec<Table>,
+    /// Where the schema was declared, if it was parsed rather than built
+    pub position: Option<Position>,
+    /// Where the schema's declaration starts and ends, if it was parsed rather than built
+    #[serde(default)]
+    pub span: Option<Span>,
+    #[serde(default)]
+    pub trivia: Trivia,
 }
 
 impl Schema {
@@ -32,66 +217,439 @@ impl Schema {
         let identity = StructuralIdentity::new(name, alias);
         Self {
             identity,
+            role: None,
+            settings: Vec::new(),
             nodes: Vec::new(),
+            position: None,
+            span: None,
+            trivia: Trivia::default(),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl PartialEq for Schema {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity == other.identity
+            && self.role == other.role
+            && self.settings == other.settings
+            && self.nodes == other.nodes
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Table {
     pub identity: StructuralIdentity,
+    /// The columns to use as the `ON CONFLICT` target when upserting records,
+    /// if declared with `conflict (...)`
+    #[serde(default)]
+    pub conflict_target: Option<Vec<String>>,
+    /// Whether the table is truncated before anything is loaded, if declared with `truncate`
+    #[serde(default)]
+    pub truncate: bool,
+    /// The number of rows that the table is expected to have once everything is loaded,
+    /// if declared with `expect N rows` in the table, which the loader counts to check
+    #[serde(default)]
+    pub expected_rows: Option<u64>,
+    /// The role that the table's records are inserted as, declared with `as role ...`
+    /// on the table or else on its schema
+    #[serde(default)]
+    pub role: Option<String>,
+    /// The settings that the table's records are inserted with, declared with `with (...)`
+    /// on the table and its schema, where the table's take precedence
+    #[serde(default)]
+    pub settings: Vec<Setting>,
+    /// The tags declared with `@tag(...)` above the table, which apply to each of its records
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The profile declared with `profile ... (...)` around the table, if any, in which
+    /// case the table is only loaded when the profile is given with the `profiles` option
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// The namespace declared with `namespace ...` at the top of the file the table was
+    /// declared in, which its records are referenced by from other namespaces
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Attributes declared with `$defaults (...)` that apply to every record in the
+    /// table unless overridden, which the analyzer merges into each record
+    #[serde(default)]
+    pub defaults: Vec<Attribute>,
+    /// CSV files imported with `$import '...'`, whose rows are expanded into records
+    /// when parsing data files rather than analyzed as part of the tree
+    #[serde(default)]
+    pub imports: Vec<Import>,
+    #[serde(default)]
     pub nodes: Vec<Record>,
+    /// Where the table was declared, if it was parsed rather than built
+    pub position: Option<Position>,
+    /// Where the table's declaration starts and ends, if it was parsed rather than built
+    #[serde(default)]
+    pub span: Option<Span>,
+    #[serde(default)]
+    pub trivia: Trivia,
 }
 
 impl Table {
-    pub fn new(name: String, alias: Option<String>) -> Self {
+    pub fn new(name: String, alias: Option<String>, conflict_target: Option<Vec<String>>) -> Self {
         let identity = StructuralIdentity::new(name, alias);
         Self {
             identity,
+            conflict_target,
+            truncate: false,
+            expected_rows: None,
+            role: None,
+            settings: Vec::new(),
+            tags: Vec::new(),
+            profile: None,
+            namespace: None,
+            defaults: Vec::new(),
+            imports: Vec::new(),
             nodes: Vec::new(),
+            position: None,
+            span: None,
+            trivia: Trivia::default(),
         }
     }
 }
 
-#[derive(Debug, Default, PartialEq)]
+impl PartialEq for Table {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity == other.identity
+            && self.conflict_target == other.conflict_target
+            && self.truncate == other.truncate
+            && self.expected_rows == other.expected_rows
+            && self.role == other.role
+            && self.settings == other.settings
+            && self.tags == other.tags
+            && self.profile == other.profile
+            && self.namespace == other.namespace
+            && self.defaults == other.defaults
+            && self.imports == other.imports
+            && self.nodes == other.nodes
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Record {
     pub name: Option<String>,
+    /// The number of copies of an anonymous record declared with `_ * N (...)`,
+    /// which the analyzer expands into separate records
+    pub quantity: Option<usize>,
+    /// The name of the record in the same table that the record extends with
+    /// `extends ...`, whose attributes the analyzer merges into the record
+    pub extends: Option<String>,
+    /// Whether the record was declared with `abstract`, so that it is only extended
+    /// by other records rather than loaded itself
+    pub is_abstract: bool,
+    /// The tags declared with `@tag(...)` above the record, by which records are
+    /// selected with the `only_tags` and `skip_tags` options
+    pub tags: Vec<String>,
+    /// The columns declared with `update ... where (...)`, whose values among the record's
+    /// attributes identify the existing rows that the record updates with the rest of
+    /// them, rather than inserting a row
+    pub update_where: Option<Vec<String>>,
+    /// Whether the record was declared with `delete from ... where (...)`, in which case
+    /// its attributes identify the existing rows that it deletes rather than inserting
+    /// a row, and it is the only record in its table declaration
+    pub delete: bool,
     pub nodes: Vec<Attribute>,
+    /// Where the record was declared, if it was parsed rather than built
+    pub position: Option<Position>,
+    /// Where the record's declaration starts and ends, if it was parsed rather than built
+    pub span: Option<Span>,
+    pub trivia: Trivia,
 }
 
 impl Record {
     pub fn new(name: Option<String>) -> Self {
         Self {
             name,
+            quantity: None,
+            extends: None,
+            is_abstract: false,
+            tags: Vec::new(),
+            update_where: None,
+            delete: false,
             nodes: Vec::new(),
+            position: None,
+            span: None,
+            trivia: Trivia::default(),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+// Positions, spans, and trivia are ignored when comparing records and attributes, so that
+// parsed nodes are equal to the same nodes built elsewhere
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.quantity == other.quantity
+            && self.extends == other.extends
+            && self.is_abstract == other.is_abstract
+            && self.update_where == other.update_where
+            && self.delete == other.delete
+            && self.tags == other.tags
+            && self.nodes == other.nodes
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Attribute {
     pub name: String,
     pub value: Value,
+    /// Where the attribute was declared, if it was parsed rather than built
+    pub position: Option<Position>,
+    /// Where the attribute starts and ends, from its column's name to the comma or
+    /// parenthesis after its value, if it was parsed rather than built
+    #[serde(default)]
+    pub span: Option<Span>,
+    /// Where the attribute's value starts and ends, if it was parsed rather than built
+    #[serde(default)]
+    pub value_span: Option<Span>,
+    #[serde(default)]
+    pub trivia: Trivia,
 }
 
 impl Attribute {
     pub fn new(name: String, value: Value) -> Self {
-        Self { name, value }
+        Self {
+            name,
+            value,
+            position: None,
+            span: None,
+            value_span: None,
+            trivia: Trivia::default(),
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl PartialEq for Attribute {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.value == other.value
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum Value {
     Bool(bool),
+    /// The column's default, declared with `default`, which is inserted as `DEFAULT` so
+    /// that records can leave columns in a column set to the database
+    Default,
+    /// The name of an environment variable declared with `env('NAME')`,
+    /// whose value is looked up when loading
+    EnvVar(String),
+    /// A file whose contents are loaded as binary data, declared with `bytes('path')`,
+    /// which is relative to the data file it is declared in
+    File(PathBuf),
+    /// The hex digits of binary data declared with `x'deadbeef'`
+    Hex(String),
+    /// An arithmetic or concatenation expression, eg. `@item1.position + 1`,
+    /// which is evaluated when loading after any references are resolved
+    Expression(Box<Expression>),
+    /// The number of each copy of a repeated record, declared with `index()`,
+    /// which is replaced with a number when the record is expanded
+    Index,
+    /// A JSON object or array declared with `json{ ... }` or `json[ ... ]`, which
+    /// is validated when lexing and is otherwise loaded exactly as written
+    Json(String),
+    /// A SQL fragment with references embedded in it, eg. `` `max(position) + 1 from task
+    /// where project_id = {@project1.id}` ``, whose referenced values are bound as
+    /// parameters when the fragment is selected
+    InterpolatedSqlFragment(Vec<TextSegment>),
+    /// Text with values embedded in it, eg. `'user-{@person1.id}@test.dev'`, which
+    /// is evaluated when loading after any references are resolved
+    InterpolatedText(Vec<TextSegment>),
+    /// The time of the transaction, declared with `now()`, which may be offset by an interval,
+    /// eg. `now() - interval '7 days'` or `ago('7 days')`, and is evaluated by the database
+    Now(Option<TimeOffset>),
+    Null,
     Number(String),
+    /// A random UUID declared with `uuid()`, which is generated when loading
+    RandomUuid,
     Reference(Reference),
+    /// A SQL fragment, whose value is cached by its SQL once selected with the
+    /// `cache_fragments` option, so that it is only selected once in each transaction
+    /// however many times it is declared
     SqlFragment(String),
     Text(String),
+    /// A UUID declared with `uuid5('namespace', 'name')`, which is always
+    /// the same for the same namespace and name
+    Uuid5 { namespace: Uuid, name: String },
+    /// The name of a variable used with `$name`, which the analyzer replaces with the
+    /// variable's value
+    Variable(String),
+    /// A SQL fragment declared with `volatile`, eg. ``volatile `random()` ``, which is
+    /// selected for every record that declares it even when caching fragments
+    VolatileSqlFragment(String),
+}
+
+impl Value {
+    /// Combines the value with the operand following an operator, eg. `1 + 2` and `* 3`
+    /// into `1 + (2 * 3)`, so that operators of higher precedence are applied first and
+    /// operators of the same precedence are applied from left to right.
+    pub fn combine(self, operator: Operator, operand: Value) -> Value {
+        match self {
+            Value::Expression(mut expression) if operator.precedence() > expression.operator.precedence() => {
+                let right = mem::replace(&mut expression.right, Value::Null);
+                expression.right = right.combine(operator, operand);
+                Value::Expression(expression)
+            }
+            left => Value::Expression(Box::new(Expression {
+                left,
+                operator,
+                right: operand,
+            })),
+        }
+    }
+
+    /// Returns the operand that an operator following the value applies to, as with `combine`,
+    /// if it is the time of the transaction without an offset, eg. `now()` in `'at ' || now()`
+    /// followed by `- interval '7 days'`.
+    pub(crate) fn now_operand(&mut self, operator: Operator) -> Option<&mut Value> {
+        if *self == Value::Now(None) {
+            return Some(self);
+        }
+
+        match self {
+            Value::Expression(expression) if operator.precedence() > expression.operator.precedence() => {
+                expression.right.now_operand(operator)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the value itself, or every value within it if it is an expression,
+    /// interpolated text, or a SQL fragment with references, eg. the operands of the
+    /// expression.
+    pub fn values(&self) -> Vec<&Value> {
+        match self {
+            Value::Expression(expression) => {
+                let mut values = expression.left.values();
+                values.extend(expression.right.values());
+                values
+            }
+            Value::InterpolatedText(segments) | Value::InterpolatedSqlFragment(segments) => segments
+                .iter()
+                .flat_map(|segment| match segment {
+                    TextSegment::Literal(_) => Vec::new(),
+                    TextSegment::Value(value) => value.values(),
+                })
+                .collect(),
+            value => vec![value],
+        }
+    }
+
+    /// Returns the references in the value, including those within other values
+    pub fn references(&self) -> impl Iterator<Item = &Reference> {
+        self.values().into_iter().filter_map(|value| match value {
+            Value::Reference(refval) => Some(refval),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn references_mut(&mut self) -> impl Iterator<Item = &mut Reference> {
+        self.values_mut().into_iter().filter_map(|value| match value {
+            Value::Reference(refval) => Some(refval),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Value::Expression(expression) => {
+                let Expression { left, right, .. } = &mut **expression;
+                let mut values = left.values_mut();
+                values.extend(right.values_mut());
+                values
+            }
+            Value::InterpolatedText(segments) | Value::InterpolatedSqlFragment(segments) => segments
+                .iter_mut()
+                .flat_map(|segment| match segment {
+                    TextSegment::Literal(_) => Vec::new(),
+                    TextSegment::Value(value) => value.values_mut(),
+                })
+                .collect(),
+            value => vec![value],
+        }
+    }
+}
+
+/// A part of interpolated text or a SQL fragment with references, either literal text
+/// or a value embedded in it.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum TextSegment {
+    /// Literal text, without surrounding or escaped quotes
+    Literal(String),
+    Value(Value),
+}
+
+/// An interval, in the syntax Postgres accepts for the `interval` type, added to or
+/// subtracted from the current time.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum TimeOffset {
+    Add(String),
+    Subtract(String),
+}
+
+impl fmt::Display for TimeOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (operator, interval) = match self {
+            TimeOffset::Add(interval) => ('+', interval),
+            TimeOffset::Subtract(interval) => ('-', interval),
+        };
+
+        write!(f, "{} interval '{}'", operator, interval.replace('\'', "''"))
+    }
+}
+
+/// A binary operation on two values, either of which may be another expression.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Expression {
+    pub left: Value,
+    pub operator: Operator,
+    pub right: Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    Add,
+    Concat,
+    Divide,
+    Multiply,
+    Subtract,
+}
+
+impl Operator {
+    /// Operators are applied in the same order as in Postgres, with concatenation last
+    fn precedence(&self) -> u8 {
+        match self {
+            Operator::Concat => 0,
+            Operator::Add | Operator::Subtract => 1,
+            Operator::Divide | Operator::Multiply => 2,
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Concat => write!(f, "||"),
+            Operator::Divide => write!(f, "/"),
+            Operator::Multiply => write!(f, "*"),
+            Operator::Subtract => write!(f, "-"),
+        }
+    }
 }
 
 /// The set of possible reference types, with varying levels
 /// of qualification.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum Reference {
     ColumnLevel(ColumnLevelReference),
     RecordLevel(RecordLevelReference),
@@ -99,19 +657,75 @@ pub enum Reference {
     SchemaLevel(SchemaLevelReference),
 }
 
+impl Reference {
+    /// The namespace the reference is qualified with, if any
+    pub fn namespace(&self) -> Option<&str> {
+        match self {
+            Reference::ColumnLevel(_) => None,
+            Reference::RecordLevel(r) => r.namespace.as_deref(),
+            Reference::TableLevel(t) => t.namespace.as_deref(),
+            Reference::SchemaLevel(s) => s.namespace.as_deref(),
+        }
+    }
+
+    pub(crate) fn namespace_mut(&mut self) -> Option<&mut Option<String>> {
+        match self {
+            Reference::ColumnLevel(_) => None,
+            Reference::RecordLevel(r) => Some(&mut r.namespace),
+            Reference::TableLevel(t) => Some(&mut t.namespace),
+            Reference::SchemaLevel(s) => Some(&mut s.namespace),
+        }
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let namespace = match self.namespace() {
+            Some(namespace) => format!("{}::", namespace),
+            None => String::new(),
+        };
+
+        let (record, column) = match self {
+            Reference::ColumnLevel(c) => return write!(f, "@{}", render(&c.column)),
+            Reference::RecordLevel(r) => (r.record.clone(), &r.column),
+            Reference::TableLevel(t) => (format!("{}.{}", render(&t.table), t.record), &t.column),
+            Reference::SchemaLevel(s) => (format!("{}.{}.{}", render(&s.schema), render(&s.table), s.record), &s.column),
+        };
+
+        match column {
+            ReferencedColumn::PrimaryKey => write!(f, "@{}{}", namespace, record),
+            column => write!(f, "@{}{}.{}", namespace, record, column),
+        }
+    }
+}
+
 /// The set of possible column reference values, either explicit
 /// with a name or implicit without one, in which case the column
-/// being referenced is inferred from the attribute.
-#[derive(Debug, PartialEq)]
+/// being referenced is inferred from the attribute, or the primary
+/// key of a record referenced without any column at all.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
 pub enum ReferencedColumn {
     Explicit(String),
     Implicit,
+    PrimaryKey,
+}
+
+impl fmt::Display for ReferencedColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReferencedColumn::Explicit(c) => write!(f, "{}", render(c)),
+            ReferencedColumn::Implicit | ReferencedColumn::PrimaryKey => Ok(()),
+        }
+    }
 }
 
 /// References to a column in the same record, eg:
 ///
-///     @column
-#[derive(Debug, PartialEq)]
+/// ```text
+/// @column
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct ColumnLevelReference {
     pub column: String,
 }
@@ -119,10 +733,14 @@ pub struct ColumnLevelReference {
 /// References that are record-qualified with either explicit or implicit
 /// column reference, eg:
 ///
-///     @record.column  -- explicit column
-///     @record.        -- implicit column
-#[derive(Debug, PartialEq)]
+/// ```text
+/// @record.column  -- explicit column
+/// @record.        -- implicit column
+/// @record         -- primary key, once resolved by the analyzer
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct RecordLevelReference {
+    pub namespace: Option<String>,
     pub record: String,
     pub column: ReferencedColumn,
 }
@@ -130,10 +748,14 @@ pub struct RecordLevelReference {
 /// References that are table-qualified with either explicit or implicit
 /// column reference, eg:
 ///
-///     @table.record.column  -- explicit column
-///     @table.record.        -- implicit column
-#[derive(Debug, PartialEq)]
+/// ```text
+/// @table.record.column  -- explicit column
+/// @table.record.        -- implicit column
+/// @table.record         -- primary key, once resolved by the analyzer
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct TableLevelReference {
+    pub namespace: Option<String>,
     pub table: String,
     pub record: String,
     pub column: ReferencedColumn,
@@ -142,10 +764,14 @@ pub struct TableLevelReference {
 /// References that are schema-qualified with either explicit or implicit
 /// column reference, eg:
 ///
-///     @schema.table.record.column -- explicit column
-///     @schema.table.record.       -- implicit column
-#[derive(Debug, PartialEq)]
+/// ```text
+/// @schema.table.record.column -- explicit column
+/// @schema.table.record.       -- implicit column
+/// @schema.table.record        -- primary key, once resolved by the analyzer
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct SchemaLevelReference {
+    pub namespace: Option<String>,
     pub schema: String,
     pub table: String,
     pub record: String,
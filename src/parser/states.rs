@@ -1,12 +1,14 @@
 use super::error::ParseError;
 use super::nodes;
+use crate::identifier::Identifier;
 use crate::Position;
-use crate::lexer::tokens::{Keyword, Symbol, Token, TokenKind};
+use crate::lexer::tokens::{Keyword, Symbol, TextPart, Token, TokenKind, FUNCTIONS};
 use std::mem;
+use uuid::Uuid;
 
 type ParseResult = Result<Box<dyn State>, ParseError>;
 
-pub trait State: std::fmt::Debug {
+pub trait State: std::fmt::Debug + Send {
     // TODO: Use `Box<Self>` like lexer `State` to make consumption easier
     fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult;
 }
@@ -25,6 +27,7 @@ pub enum StackItem {
     Schema(Box<nodes::Schema>),
     Table(Box<nodes::Table>),
     Record(Box<nodes::Record>),
+    Defaults(Vec<nodes::Attribute>),
     Attribute(Box<nodes::Attribute>),
 }
 
@@ -33,32 +36,244 @@ enum PushedTableTo {
     TreeRoot,
 }
 
+/// The kinds of node that a comment at the end of the line they were closed on is attached to
+#[derive(Clone, Copy, Debug)]
+enum Closed {
+    Attribute,
+    Record,
+    Schema,
+    Table,
+}
+
 #[derive(Default)]
 pub struct Context {
     pub stack: Vec<StackItem>,
+    /// Where the schema, table, record, and attribute currently being declared started
+    schema_position: Option<Position>,
+    table_position: Option<Position>,
+    record_position: Option<Position>,
+    attribute_position: Option<Position>,
+    /// Where the value of the attribute currently being declared started
+    value_position: Option<Position>,
+    /// Where the token currently being received is, which is where attributes end
+    /// when it closes them
+    token_position: Position,
+    /// Whether the table currently being declared was declared with `truncate`
+    truncate_table: bool,
+    /// The roles declared with `as role ...` for the schema and table currently being declared
+    schema_role: Option<String>,
+    table_role: Option<String>,
+    /// The settings declared with `with (...)` for the schema or table currently being declared
+    settings: Vec<nodes::Setting>,
+    /// The tags declared with `@tag(...)` above the table or record about to be declared
+    tags: Vec<String>,
+    /// The profile whose section is currently being declared, which every table declared
+    /// in it is only loaded with
+    profile: Option<String>,
+    /// Whether the record currently being declared was declared with `abstract`, and the
+    /// record it extends, if any
+    abstract_record: bool,
+    record_base: Option<String>,
+    /// Whether the columns of the record currently being declared are those declared with
+    /// `update ... where (...)`, which identify the rows it updates, rather than its values
+    update_conditions: bool,
+    /// The column set declared in the table currently being declared, which the
+    /// values of its records are assigned to in order
+    columns: Option<Vec<String>>,
+    /// The namespace declared at the top of the file, which every table belongs to
+    namespace: Option<String>,
+    /// The namespace that the reference currently being received is qualified with
+    reference_namespace: Option<String>,
+    /// The table and column that the reference currently being received is to, if
+    /// it is shorthand named after the attribute's column, eg. `person_id@ record1`
+    reference_shorthand: Option<(String, String)>,
+    /// The columns that the reference currently being received is declared for, if it
+    /// references several columns at once, eg. `(tenant_id, user_id) @membership1.(tenant_id, user_id)`
+    reference_columns: Option<Vec<String>>,
+    /// The operator received after the value of the attribute currently being
+    /// declared, which is applied to the next value received
+    operator: Option<nodes::Operator>,
+    /// Blank lines and comments received since the last node was declared, which are
+    /// attached to the next node declared or to the node they are inside of
+    trivia: Vec<nodes::TriviaItem>,
+    /// Comments received after other tokens on the current line, which are attached at
+    /// the end of the line to the node closed on it or otherwise the node being declared
+    trailing_comments: Vec<String>,
+    /// The last node closed on the current line
+    closed: Option<Closed>,
+    /// Whether anything other than a comment has been received on the current line
+    line_has_tokens: bool,
+    /// Whether a comment has been received on the current line
+    line_has_comment: bool,
 }
 
 impl Context {
+    /// Receives a comment, which is kept as trivia rather than passed to the states.
+    pub fn receive_comment(&mut self, text: String) {
+        if self.line_has_tokens {
+            self.trailing_comments.push(text);
+        } else {
+            self.trivia.push(nodes::TriviaItem::Comment(text));
+        }
+        self.line_has_comment = true;
+    }
+
+    /// Notes any other token before it is passed to the states, so that blank lines
+    /// can be kept as trivia.
+    pub fn receive_token(&mut self, t: &Token) {
+        self.token_position = t.position;
+
+        match t.kind {
+            TokenKind::LineSep if !self.line_has_tokens && !self.line_has_comment => {
+                self.trivia.push(nodes::TriviaItem::BlankLine);
+            }
+            TokenKind::LineSep => {}
+            _ => self.line_has_tokens = true,
+        }
+    }
+
+    /// Attaches the comments at the end of the line once the states have received
+    /// its line separator, or the end of the file.
+    pub fn end_line(&mut self) {
+        for comment in mem::take(&mut self.trailing_comments) {
+            match self.trailing_trivia_or_none() {
+                Some(trivia) => trivia.trailing.push(comment),
+                None => self.trivia.push(nodes::TriviaItem::Comment(comment)),
+            }
+        }
+
+        self.closed = None;
+        self.line_has_tokens = false;
+        self.line_has_comment = false;
+    }
+
+    /// Attaches the trivia after the last node in the file to the tree.
+    pub fn end_file(&mut self) {
+        self.end_line();
+
+        if let Some(StackItem::TreeRoot(tree)) = self.stack.last_mut() {
+            tree.trivia = mem::take(&mut self.trivia);
+        }
+    }
+
+    /// The trivia of the node closed on the current line, or otherwise the node being
+    /// declared, if either can have comments attached to it.
+    fn trailing_trivia_or_none(&mut self) -> Option<&mut nodes::Trivia> {
+        let item = self.stack.last_mut()?;
+
+        match (self.closed, item) {
+            (Some(Closed::Attribute), StackItem::Record(record)) => record.nodes.last_mut().map(|a| &mut a.trivia),
+            (Some(Closed::Attribute), StackItem::Defaults(defaults)) => defaults.last_mut().map(|a| &mut a.trivia),
+            (Some(Closed::Record), StackItem::Table(table)) => table.nodes.last_mut().map(|r| &mut r.trivia),
+            (Some(Closed::Table), StackItem::Schema(schema)) => schema.nodes.last_mut().map(|t| &mut t.trivia),
+            (Some(Closed::Table | Closed::Schema), StackItem::TreeRoot(tree)) => match tree.nodes.last_mut()? {
+                nodes::StructuralNode::Schema(schema) => Some(&mut schema.trivia),
+                nodes::StructuralNode::Table(table) => Some(&mut table.trivia),
+            },
+            (_, StackItem::Schema(schema)) => Some(&mut schema.trivia),
+            (_, StackItem::Table(table)) => Some(&mut table.trivia),
+            (_, StackItem::Record(record)) => Some(&mut record.trivia),
+            (_, StackItem::Attribute(attribute)) => Some(&mut attribute.trivia),
+            _ => None,
+        }
+    }
+
     fn push_schema(&mut self, schema_name: String, alias: Option<String>) {
-        let schema = nodes::Schema::new(schema_name, alias);
+        let mut schema = nodes::Schema::new(schema_name, alias);
+        schema.role = self.schema_role.take();
+        schema.settings = mem::take(&mut self.settings);
+        schema.position = self.schema_position.take();
+        schema.trivia.leading = mem::take(&mut self.trivia);
         self.stack.push(StackItem::Schema(Box::new(schema)));
     }
 
-    fn push_table(&mut self, table_name: String, alias: Option<String>) {
-        let table = nodes::Table::new(table_name, alias);
+    fn push_table(&mut self, table_name: String, alias: Option<String>, conflict_target: Option<Vec<String>>) {
+        let mut table = nodes::Table::new(table_name, alias, conflict_target);
+        table.truncate = mem::take(&mut self.truncate_table);
+        // Tables are loaded as the role of the schema they are declared in, unless they
+        // declare their own
+        table.role = match (self.table_role.take(), self.stack.last()) {
+            (None, Some(StackItem::Schema(schema))) => schema.role.clone(),
+            (role, _) => role,
+        };
+        // As are they with the schema's settings, along with their own
+        if let Some(StackItem::Schema(schema)) = self.stack.last() {
+            table.settings = schema.settings.clone();
+        }
+        for setting in mem::take(&mut self.settings) {
+            merge_setting(&mut table.settings, setting);
+        }
+        table.tags = mem::take(&mut self.tags);
+        table.profile = self.profile.clone();
+        table.namespace = self.namespace.clone();
+        table.position = self.table_position;
+        table.trivia.leading = mem::take(&mut self.trivia);
+        self.columns = None;
         self.stack.push(StackItem::Table(Box::new(table)));
     }
 
     fn push_record(&mut self, record_name: Option<String>) {
-        let record = nodes::Record::new(record_name);
+        let mut record = nodes::Record::new(record_name);
+        record.is_abstract = mem::take(&mut self.abstract_record);
+        record.extends = self.record_base.take();
+        record.tags = mem::take(&mut self.tags);
+        record.position = self.record_position;
+        record.trivia.leading = mem::take(&mut self.trivia);
+        self.stack.push(StackItem::Record(Box::new(record)));
+    }
+
+    fn push_repeated_record(&mut self, quantity: usize) {
+        let mut record = nodes::Record::new(None);
+        record.quantity = Some(quantity);
+        record.tags = mem::take(&mut self.tags);
+        record.position = self.record_position;
+        record.trivia.leading = mem::take(&mut self.trivia);
         self.stack.push(StackItem::Record(Box::new(record)));
     }
 
+    fn push_defaults(&mut self) {
+        self.stack.push(StackItem::Defaults(Vec::new()));
+    }
+
     fn push_attribute(&mut self, name: String, value: nodes::Value) {
-        let attribute = nodes::Attribute::new(name, value);
+        let attribute = self.new_attribute(name, value);
         self.stack.push(StackItem::Attribute(Box::new(attribute)));
     }
 
+    /// Pushes the attribute with the value, or combines the value with the value of the
+    /// attribute already being declared if it follows an operator, in which case the
+    /// attribute name is ignored.
+    fn push_value(&mut self, name: String, value: nodes::Value) {
+        match self.operator.take() {
+            Some(operator) => match self.stack.last_mut() {
+                Some(StackItem::Attribute(attribute)) => {
+                    let left = mem::replace(&mut attribute.value, nodes::Value::Null);
+                    attribute.value = left.combine(operator, value);
+                }
+                elt => panic!("expected attribute on stack; received {:?}", elt),
+            },
+            None => self.push_attribute(name, value),
+        }
+    }
+
+    fn new_attribute(&mut self, name: String, value: nodes::Value) -> nodes::Attribute {
+        let mut attribute = nodes::Attribute::new(name, value);
+        attribute.position = self.attribute_position;
+        attribute.trivia.leading = mem::take(&mut self.trivia);
+        attribute
+    }
+
+    /// The span of a node that starts at the position, if known, and is closed by the
+    /// parenthesis being received, which it ends just after.
+    fn closing_span(&self, start: Option<Position>) -> Option<nodes::Span> {
+        let end = Position {
+            column: self.token_position.column + 1,
+            ..self.token_position
+        };
+
+        start.map(|start| nodes::Span { start, end })
+    }
+
     // These utility methods all panic if certain expectations are not met,
     // primarily because that indicates faulty logic in the parser rather than
     // unexpected tokens in the token stream. In other words, unless I am woefully
@@ -78,13 +293,75 @@ impl Context {
         }
     }
 
-    fn pop_record_or_panic(&mut self) -> nodes::Record {
-        match self.stack.pop() {
-            Some(StackItem::Record(record)) => *record,
+    /// Marks the columns declared so far for the record being declared as those that
+    /// identify the rows it updates, returning false if there are none.
+    fn close_update_conditions_or_panic(&mut self) -> bool {
+        self.update_conditions = false;
+
+        match self.stack.last_mut() {
+            Some(StackItem::Record(record)) => {
+                record.update_where = Some(record.nodes.iter().map(|a| a.name.clone()).collect());
+                !record.nodes.is_empty()
+            }
+            elt => panic!("expected record on stack; received {:?}", elt),
+        }
+    }
+
+    /// Whether the record being declared updates rows but has no columns to update them with.
+    fn is_empty_update(&self) -> bool {
+        match self.stack.last() {
+            Some(StackItem::Record(record)) => record
+                .update_where
+                .as_ref()
+                .is_some_and(|columns| columns.len() == record.nodes.len()),
+            _ => false,
+        }
+    }
+
+    /// Starts the declaration of the table that rows are deleted from, along with the
+    /// record whose attributes identify the rows.
+    fn push_delete(&mut self, table_name: String) {
+        self.push_table(table_name, None, None);
+        self.push_record(None);
+
+        match self.stack.last_mut() {
+            Some(StackItem::Record(record)) => record.delete = true,
             elt => panic!("expected record on stack; received {:?}", elt),
         }
     }
 
+    /// Whether the record being declared deletes rows.
+    fn is_delete(&self) -> bool {
+        matches!(self.stack.last(), Some(StackItem::Record(record)) if record.delete)
+    }
+
+    /// Pops the record that deletes rows along with its table, adding the table to its
+    /// parent, unless the record does not identify the rows by any columns.
+    fn close_delete_or_panic(&mut self) -> Option<PushedTableTo> {
+        match self.stack.last() {
+            Some(StackItem::Record(record)) if record.nodes.is_empty() => return None,
+            Some(StackItem::Record(_)) => {}
+            elt => panic!("expected record on stack; received {:?}", elt),
+        }
+
+        self.close_record_or_panic();
+        let table = self.pop_table_or_panic();
+
+        Some(self.push_table_to_parent_or_panic(table))
+    }
+
+    /// Pops the record or table defaults being declared and adds it to the table.
+    fn close_record_or_panic(&mut self) {
+        match self.stack.pop() {
+            Some(StackItem::Record(record)) => self.push_record_to_table_or_panic(*record),
+            Some(StackItem::Defaults(defaults)) => match self.stack.last_mut() {
+                Some(StackItem::Table(table)) => table.defaults.extend(defaults),
+                elt => panic!("expected table on stack; received {:?}", elt),
+            },
+            elt => panic!("expected record or defaults on stack; received {:?}", elt),
+        }
+    }
+
     fn pop_attribute_or_panic(&mut self) -> nodes::Attribute {
         match self.stack.pop() {
             Some(StackItem::Attribute(attribute)) => *attribute,
@@ -92,7 +369,11 @@ impl Context {
         }
     }
 
-    fn push_schema_to_root_or_panic(&mut self, schema: nodes::Schema) {
+    fn push_schema_to_root_or_panic(&mut self, mut schema: nodes::Schema) {
+        schema.span = self.closing_span(schema.position);
+        schema.trivia.inner = mem::take(&mut self.trivia);
+        self.closed = Some(Closed::Schema);
+
         match self.stack.last_mut() {
             Some(StackItem::TreeRoot(tree)) => {
                 tree.nodes
@@ -102,7 +383,66 @@ impl Context {
         }
     }
 
-    fn push_table_to_parent_or_panic(&mut self, table: nodes::Table) -> PushedTableTo {
+    /// Whether nothing has been declared in the tree yet.
+    fn is_empty_root(&self) -> bool {
+        match self.stack.last() {
+            Some(StackItem::TreeRoot(tree)) => tree.nodes.is_empty() && tree.includes.is_empty(),
+            elt => panic!("expected tree root on stack; received {:?}", elt),
+        }
+    }
+
+    /// Whether a search path has been declared in the tree yet.
+    fn has_search_path(&self) -> bool {
+        match self.stack.last() {
+            Some(StackItem::TreeRoot(tree)) => !tree.search_paths.is_empty(),
+            elt => panic!("expected tree root on stack; received {:?}", elt),
+        }
+    }
+
+    fn push_search_path_to_root_or_panic(&mut self, schemas: Vec<String>, position: Position) {
+        match self.stack.last_mut() {
+            Some(StackItem::TreeRoot(tree)) => {
+                tree.search_paths.push(nodes::SearchPath { schemas, position: Some(position) });
+            }
+            elt => panic!("expected tree root on stack; received {:?}", elt),
+        }
+    }
+
+    fn push_variable_to_root_or_panic(&mut self, name: String, value: nodes::Value, position: Position) {
+        match self.stack.last_mut() {
+            Some(StackItem::TreeRoot(tree)) => {
+                tree.variables.push(nodes::Variable { name, value, position: Some(position) });
+            }
+            elt => panic!("expected tree root on stack; received {:?}", elt),
+        }
+    }
+
+    /// Pushes the hook to the tree root, which is at the bottom of the stack, since hooks
+    /// can also be declared in schemas.
+    fn push_hook_to_root_or_panic(&mut self, timing: nodes::HookTiming, sql: String, position: Position) {
+        match self.stack.first_mut() {
+            Some(StackItem::TreeRoot(tree)) => {
+                tree.hooks.push(nodes::Hook { timing, sql, position: Some(position) });
+            }
+            elt => panic!("expected tree root on stack; received {:?}", elt),
+        }
+    }
+
+    fn push_include_to_root_or_panic(&mut self, path: String, position: Position) {
+        match self.stack.last_mut() {
+            Some(StackItem::TreeRoot(tree)) => {
+                let index = tree.nodes.len();
+                tree.includes.push(nodes::Include { path, index, position });
+            }
+            elt => panic!("expected tree root on stack; received {:?}", elt),
+        }
+    }
+
+    fn push_table_to_parent_or_panic(&mut self, mut table: nodes::Table) -> PushedTableTo {
+        table.span = self.closing_span(table.position);
+        table.trivia.inner = mem::take(&mut self.trivia);
+        self.closed = Some(Closed::Table);
+
         match self.stack.last_mut() {
             Some(StackItem::TreeRoot(tree)) => {
                 let node = nodes::StructuralNode::Table(Box::new(table));
@@ -117,7 +457,28 @@ impl Context {
         }
     }
 
-    fn push_record_to_table_or_panic(&mut self, record: nodes::Record) {
+    fn push_import_to_table_or_panic(&mut self, path: String, position: Position) {
+        match self.stack.last_mut() {
+            Some(StackItem::Table(table)) => {
+                let index = table.nodes.len();
+                table.imports.push(nodes::Import { path, index, position });
+            }
+            elt => panic!("expected table on stack; received {:?}", elt),
+        }
+    }
+
+    fn set_expected_rows_or_panic(&mut self, count: u64) {
+        match self.stack.last_mut() {
+            Some(StackItem::Table(table)) => table.expected_rows = Some(count),
+            elt => panic!("expected table on stack; received {:?}", elt),
+        }
+    }
+
+    fn push_record_to_table_or_panic(&mut self, mut record: nodes::Record) {
+        record.span = self.closing_span(record.position);
+        record.trivia.inner = mem::take(&mut self.trivia);
+        self.closed = Some(Closed::Record);
+
         match self.stack.last_mut() {
             Some(StackItem::Table(table)) => {
                 table.nodes.push(record);
@@ -126,22 +487,116 @@ impl Context {
         }
     }
 
-    fn push_attribute_to_record_or_panic(&mut self, attribute: nodes::Attribute) {
+    /// Whether the value of the attribute being declared can be the left-hand operand
+    /// of an operator, as booleans and SQL fragments cannot be evaluated by the loader.
+    /// Whether an interval can follow the operator, which is only the case when
+    /// adding it to or subtracting it from the time of the transaction
+    fn accepts_interval(&mut self) -> bool {
+        let operator = match self.operator {
+            Some(operator @ (nodes::Operator::Add | nodes::Operator::Subtract)) => operator,
+            _ => return false,
+        };
+
+        match self.stack.last_mut() {
+            Some(StackItem::Attribute(attribute)) => attribute.value.now_operand(operator).is_some(),
+            elt => panic!("expected attribute on stack; received {:?}", elt),
+        }
+    }
+
+    fn offset_now_or_panic(&mut self, interval: String) {
+        let (operator, offset) = match self.operator.take() {
+            Some(operator @ nodes::Operator::Add) => (operator, nodes::TimeOffset::Add(interval)),
+            Some(operator @ nodes::Operator::Subtract) => (operator, nodes::TimeOffset::Subtract(interval)),
+            operator => panic!("expected addition or subtraction; received {:?}", operator),
+        };
+
+        match self.stack.last_mut() {
+            Some(StackItem::Attribute(attribute)) => {
+                *attribute.value.now_operand(operator).expect("expected now() operand") = nodes::Value::Now(Some(offset));
+            }
+            elt => panic!("expected attribute on stack; received {:?}", elt),
+        }
+    }
+
+    /// Whether the token directly follows the name of the attribute being declared,
+    /// without any whitespace between them
+    fn follows_attribute_name(&self, attribute_name: &str, position: Position) -> bool {
+        match self.attribute_position {
+            Some(p) => p.line == position.line && p.column + attribute_name.chars().count() == position.column,
+            None => false,
+        }
+    }
+
+    fn accepts_operator(&self) -> bool {
+        match self.stack.last() {
+            Some(StackItem::Attribute(attribute)) => {
+                !matches!(
+                    attribute.value,
+                    nodes::Value::Bool(_)
+                        | nodes::Value::Default
+                        | nodes::Value::File(_)
+                        | nodes::Value::Hex(_)
+                        | nodes::Value::Json(_)
+                        | nodes::Value::InterpolatedSqlFragment(_)
+                        | nodes::Value::SqlFragment(_)
+                        | nodes::Value::VolatileSqlFragment(_)
+                )
+            }
+            elt => panic!("expected attribute on stack; received {:?}", elt),
+        }
+    }
+
+    /// The column set and the number of values already received, if the record
+    /// being declared takes its values in order of the table's column set.
+    fn positional_values(&self) -> Option<(&[String], usize)> {
+        match (&self.columns, self.stack.last()) {
+            // Records that update rows name the columns they identify and update them by
+            (Some(columns), Some(StackItem::Record(record))) if record.update_where.is_none() && !self.update_conditions => {
+                Some((columns, record.nodes.len()))
+            }
+            _ => None,
+        }
+    }
+
+    fn push_attribute_to_record_or_panic(&mut self, mut attribute: nodes::Attribute) {
+        let span = |start: Option<Position>| start.map(|start| nodes::Span { start, end: self.token_position });
+        attribute.span = span(attribute.position);
+        attribute.value_span = span(self.value_position);
+        self.closed = Some(Closed::Attribute);
+
         match self.stack.last_mut() {
             Some(StackItem::Record(record)) => {
                 record.nodes.push(attribute);
             }
-            elt => panic!("expected record on stack; received {:?}", elt),
+            Some(StackItem::Defaults(defaults)) => {
+                defaults.push(attribute);
+            }
+            elt => panic!("expected record or defaults on stack; received {:?}", elt),
         }
     }
 }
 
+/// The name of a schema, table, column, or role given by an identifier token.
+fn name(kind: TokenKind) -> String {
+    match kind {
+        TokenKind::Identifier(value) => Identifier { quoted: false, value }.name(),
+        TokenKind::QuotedIdentifier(value) => Identifier { quoted: true, value }.name(),
+        kind => unreachable!("expected identifier, found {}", kind),
+    }
+}
+
+/// Adds the setting to the settings, replacing any with the same name.
+fn merge_setting(settings: &mut Vec<nodes::Setting>, setting: nodes::Setting) {
+    settings.retain(|s| s.name != setting.name);
+    settings.push(setting);
+}
+
 /// Root state that can expect top-level entities.
 #[derive(Debug)]
 pub struct Root;
 
 impl State for Root {
-    fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+    fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
         // TODO: There are a few general patterns that have emerged in the states:
         //   - How to handle `None`
         //   - The error type to return as default case for unexpected token
@@ -149,256 +604,1408 @@ impl State for Root {
         // These are good indicators that the State trait & usage could be refined
         let t = match t {
             Some(t) => t,
+            None if ctx.profile.is_some() => return Err(ParseError::eof()),
             None => return to(Root),
         };
         match t.kind {
             TokenKind::LineSep => to(Root),
-            TokenKind::Keyword(Keyword::Schema) => to(schema_states::DeclaringSchema),
-            TokenKind::Keyword(Keyword::Table) => to(table_states::DeclaringTable),
+            // Included files are parsed on their own, so their tables could not be in the profile,
+            // and `include` is not a keyword since it is only special here
+            TokenKind::Identifier(ident) if ident == "include" && ctx.profile.is_none() => {
+                to(directive_states::DeclaringInclude(t.position))
+            }
+            // Namespaces apply to the whole file, so must be declared before anything else,
+            // and are not a keyword either
+            TokenKind::Identifier(ident) if ident == "namespace" && ctx.namespace.is_none() && ctx.is_empty_root() => {
+                to(directive_states::DeclaringNamespace)
+            }
+            // As do settings, which apply to the whole transaction, and are not a keyword
+            // since they are only special here
+            TokenKind::Identifier(ident) if ident == "set" && !ctx.has_search_path() && ctx.is_empty_root() => {
+                to(directive_states::DeclaringSetting(t.position))
+            }
+            TokenKind::Keyword(Keyword::Schema) => {
+                ctx.schema_position = Some(t.position);
+                to(schema_states::DeclaringSchema)
+            }
+            TokenKind::Keyword(Keyword::Table) => {
+                ctx.table_position = Some(t.position);
+                to(table_states::DeclaringTable)
+            }
+            TokenKind::Symbol(Symbol::AtSign) => to(tag_states::DeclaringTags(tag_states::Tagged::Table)),
+            // Not a keyword, since it is only special outside of tables
+            TokenKind::Identifier(ident) if ident == "delete" => {
+                ctx.table_position = Some(t.position);
+                ctx.record_position = Some(t.position);
+                to(delete_states::DeclaringDelete)
+            }
+            // Neither is this, and variables apply to the whole file, like includes
+            TokenKind::Identifier(ident) if ident == "let" && ctx.profile.is_none() => {
+                to(directive_states::DeclaringVariable(t.position))
+            }
+            // Nor are hooks, which cannot be in profiles since they are not loaded like tables
+            TokenKind::Identifier(ident) if ident == "before" && ctx.profile.is_none() => {
+                to(directive_states::DeclaringHook(nodes::HookTiming::Before, t.position, false))
+            }
+            TokenKind::Identifier(ident) if ident == "after" && ctx.profile.is_none() => {
+                to(directive_states::DeclaringHook(nodes::HookTiming::After, t.position, false))
+            }
+            // Not a keyword, since it is only special outside of schemas and tables
+            TokenKind::Identifier(ident) if ident == "profile" && ctx.profile.is_none() => {
+                to(profile_states::DeclaringProfile)
+            }
+            TokenKind::Symbol(Symbol::ParenRight) if ctx.profile.is_some() => {
+                ctx.profile = None;
+                to(Root)
+            }
             _ => Err(ParseError::token(t)),
         }
     }
 }
 
-mod schema_states {
+mod directive_states {
     use super::*;
 
-    /// State after receiving the `schema` keyword for declaration.
+    /// State after receiving `before` or `after`, along with its position and whether
+    /// it was declared in a schema, which must be followed by the hook's SQL.
     #[derive(Debug)]
-    pub struct DeclaringSchema;
+    pub struct DeclaringHook(pub nodes::HookTiming, pub Position, pub bool);
 
-    impl State for DeclaringSchema {
-        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+    impl State for DeclaringHook {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Identifier(ident) | TokenKind::QuotedIdentifier(ident) => {
-                    to(ReceivedSchemaName(ident))
+                TokenKind::SqlFragment(sql) => {
+                    ctx.push_hook_to_root_or_panic(self.0, sql, self.1);
+
+                    if self.2 {
+                        to(schema_states::InSchemaScope)
+                    } else {
+                        to(ReceivedDirective)
+                    }
                 }
-                _ => Err(ParseError::exp_schema(t)),
+                _ => Err(ParseError::exp_hook_sql(t)),
             }
         }
     }
 
-    /// State after receiving the schema name during declaration.
+    /// State after receiving the `include` keyword, along with its position.
     #[derive(Debug)]
-    struct ReceivedSchemaName(String);
+    pub struct DeclaringInclude(pub Position);
 
-    impl State for ReceivedSchemaName {
+    impl State for DeclaringInclude {
         fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
-            let schema_name = mem::take(&mut self.0);
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Keyword(Keyword::As) => to(DeclaringSchemaAlias(schema_name)),
-                TokenKind::Symbol(Symbol::ParenLeft) => {
-                    ctx.push_schema(schema_name, None);
-                    to(InSchemaScope)
+                TokenKind::Text(_) => {
+                    ctx.push_include_to_root_or_panic(attribute_states::unquote(t), self.0);
+                    to(ReceivedDirective)
                 }
-                _ => Err(ParseError::alias_or_scope(t)),
+                _ => Err(ParseError::exp_include(t)),
             }
         }
     }
 
-    /// State after receiving the `as` keyword during schema declaration.
+    /// State after receiving the `namespace` keyword.
     #[derive(Debug)]
-    struct DeclaringSchemaAlias(String);
+    pub struct DeclaringNamespace;
 
-    impl State for DeclaringSchemaAlias {
-        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
-            let schema_name = mem::take(&mut self.0);
+    impl State for DeclaringNamespace {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                // Unlike the true database name, aliases do not support quoted identifiers
-                TokenKind::Identifier(ident) => to(ReceivedSchemaAlias(schema_name, ident)),
-                _ => Err(ParseError::exp_alias(t)),
+                TokenKind::Identifier(ident) => {
+                    ctx.namespace = Some(ident);
+                    to(ReceivedDirective)
+                }
+                _ => Err(ParseError::exp_ident(t)),
             }
         }
     }
 
+    /// State after receiving the `set` keyword, along with its position.
     #[derive(Debug)]
-    struct ReceivedSchemaAlias(String, String);
+    pub struct DeclaringSetting(pub Position);
 
-    impl State for ReceivedSchemaAlias {
-        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+    impl State for DeclaringSetting {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Symbol(Symbol::ParenLeft) => {
-                    let schema_name = mem::take(&mut self.0);
-                    let alias = mem::take(&mut self.1);
-                    ctx.push_schema(schema_name, Some(alias));
-                    to(InSchemaScope)
-                }
-                _ => Err(ParseError::exp_scope(t)),
+                TokenKind::Identifier(ident) if ident == "search_path" => to(DeclaringSearchPath(self.0, Vec::new())),
+                _ => Err(ParseError::exp_setting(t)),
             }
         }
     }
 
+    /// State after receiving `set search_path` or a comma after one of its schemas,
+    /// when expecting a schema name.
     #[derive(Debug)]
-    pub struct InSchemaScope;
+    struct DeclaringSearchPath(Position, Vec<String>);
 
-    impl State for InSchemaScope {
-        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+    impl State for DeclaringSearchPath {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
+            let mut schemas = mem::take(&mut self.1);
+
             match t.kind {
-                TokenKind::Symbol(Symbol::ParenRight) => {
-                    let schema = ctx.pop_schema_or_panic();
-                    ctx.push_schema_to_root_or_panic(schema);
-                    to(Root)
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    schemas.push(name(kind));
+                    to(ReceivedSearchPathSchema(self.0, schemas))
                 }
-                TokenKind::Keyword(Keyword::Table) => to(table_states::DeclaringTable),
-                TokenKind::LineSep => to(InSchemaScope),
-                _ => Err(ParseError::in_schema(t)),
+                _ => Err(ParseError::exp_schema(t)),
             }
         }
     }
-}
-
-mod table_states {
-    use super::*;
 
-    /// State after receiving the `table` keyword for declaration.
+    /// State after receiving a schema in the search path, which is followed by either
+    /// a comma and another schema or the end of the line.
     #[derive(Debug)]
-    pub struct DeclaringTable;
+    struct ReceivedSearchPathSchema(Position, Vec<String>);
+
+    impl State for ReceivedSearchPathSchema {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let schemas = mem::take(&mut self.1);
 
-    impl State for DeclaringTable {
-        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
             let t = match t {
                 Some(t) => t,
-                None => return Err(ParseError::eof()),
+                None => {
+                    ctx.push_search_path_to_root_or_panic(schemas, self.0);
+                    return to(Root);
+                }
             };
             match t.kind {
-                TokenKind::Identifier(ident) | TokenKind::QuotedIdentifier(ident) => {
-                    to(ReceivedTableName(ident))
+                TokenKind::Symbol(Symbol::Comma) => to(DeclaringSearchPath(self.0, schemas)),
+                TokenKind::LineSep => {
+                    ctx.push_search_path_to_root_or_panic(schemas, self.0);
+                    to(Root)
                 }
-                _ => Err(ParseError::exp_table(t)),
+                _ => Err(ParseError::token(t)),
             }
         }
     }
 
-    /// State after receiving the table name during declaration.
+    /// State after receiving `let`, along with its position, which must be followed
+    /// by the variable's name.
     #[derive(Debug)]
-    struct ReceivedTableName(String);
+    pub struct DeclaringVariable(pub Position);
 
-    impl State for ReceivedTableName {
-        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
-            let table_name = mem::take(&mut self.0);
+    impl State for DeclaringVariable {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Keyword(Keyword::As) => to(DeclaringTableAlias(table_name)),
-                TokenKind::Symbol(Symbol::ParenLeft) => {
-                    ctx.push_table(table_name, None);
-                    to(InTableScope)
-                }
-                _ => Err(ParseError::alias_or_scope(t)),
+                TokenKind::Identifier(ident) => to(ReceivedVariableName(self.0, ident)),
+                _ => Err(ParseError::exp_ident(t)),
             }
         }
     }
 
+    /// State after receiving the variable's name, which must be followed by `=`.
     #[derive(Debug)]
-    struct DeclaringTableAlias(String);
+    struct ReceivedVariableName(Position, String);
 
-    impl State for DeclaringTableAlias {
+    impl State for ReceivedVariableName {
         fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
-            let table_name = mem::take(&mut self.0);
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Identifier(ident) => to(ReceivedTableAlias(table_name, ident)),
-                _ => Err(ParseError::exp_alias(t)),
+                TokenKind::Symbol(Symbol::Equals) => to(ReceivedEquals(self.0, mem::take(&mut self.1))),
+                _ => Err(ParseError::exp_equals(t)),
             }
         }
     }
 
+    /// State after receiving `=`, which must be followed by the variable's value,
+    /// which can only be a literal.
     #[derive(Debug)]
-    struct ReceivedTableAlias(String, String);
+    struct ReceivedEquals(Position, String);
 
-    impl State for ReceivedTableAlias {
+    impl State for ReceivedEquals {
         fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
-            match t.kind {
-                TokenKind::Symbol(Symbol::ParenLeft) => {
-                    let table_name = mem::take(&mut self.0);
-                    let alias = mem::take(&mut self.1);
-                    ctx.push_table(table_name, Some(alias));
-                    to(InTableScope)
-                }
-                _ => Err(ParseError::exp_scope(t)),
-            }
+            let value = match t.kind {
+                TokenKind::Bool(b) => nodes::Value::Bool(b),
+                TokenKind::Null => nodes::Value::Null,
+                TokenKind::Number(n) => nodes::Value::Number(n),
+                TokenKind::Text(text) => nodes::Value::Text(text),
+                _ => return Err(ParseError::exp_variable_value(t)),
+            };
+            ctx.push_variable_to_root_or_panic(mem::take(&mut self.1), value, self.0);
+            to(ReceivedDirective)
         }
     }
 
+    /// State after receiving the path of an included file, the name of the
+    /// namespace, the value of a variable, or the SQL of a hook, any of which
+    /// must be the last thing on its line.
     #[derive(Debug)]
-    pub struct InTableScope;
+    struct ReceivedDirective;
 
-    impl State for InTableScope {
+    impl State for ReceivedDirective {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return to(Root),
+            };
+            match t.kind {
+                TokenKind::LineSep => to(Root),
+                _ => Err(ParseError::token(t)),
+            }
+        }
+    }
+}
+
+mod profile_states {
+    use super::*;
+
+    /// State after receiving `profile`, which must be followed by the profile's name.
+    #[derive(Debug)]
+    pub struct DeclaringProfile;
+
+    impl State for DeclaringProfile {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) => to(ReceivedProfileName(ident)),
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving the profile's name, which must be followed by its section
+    /// of schemas and tables in parentheses, which are otherwise declared as in the root.
+    #[derive(Debug)]
+    struct ReceivedProfileName(String);
+
+    impl State for ReceivedProfileName {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.profile = Some(mem::take(&mut self.0));
+                    to(Root)
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+}
+
+mod schema_states {
+    use super::*;
+
+    /// State after receiving the `schema` keyword for declaration.
+    #[derive(Debug)]
+    pub struct DeclaringSchema;
+
+    impl State for DeclaringSchema {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    to(ReceivedSchemaName(name(kind)))
+                }
+                _ => Err(ParseError::exp_schema(t)),
+            }
+        }
+    }
+
+    /// State after receiving the schema name during declaration.
+    #[derive(Debug)]
+    struct ReceivedSchemaName(String);
+
+    impl State for ReceivedSchemaName {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let schema_name = mem::take(&mut self.0);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Keyword(Keyword::As) => to(DeclaringSchemaAlias(schema_name)),
+                TokenKind::Identifier(ident) if ident == "with" => {
+                    to(settings_states::DeclaringSettings(settings_states::SettingsOf::schema(schema_name, None)))
+                }
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_schema(schema_name, None);
+                    to(InSchemaScope)
+                }
+                _ => Err(ParseError::alias_or_scope(t)),
+            }
+        }
+    }
+
+    /// State after receiving the `as` keyword during schema declaration.
+    #[derive(Debug)]
+    struct DeclaringSchemaAlias(String);
+
+    impl State for DeclaringSchemaAlias {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let schema_name = mem::take(&mut self.0);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "role" => to(DeclaringSchemaRoleName(schema_name, None)),
+                // Unlike the true database name, aliases do not support quoted identifiers
+                kind @ TokenKind::Identifier(_) => to(ReceivedSchemaAlias(schema_name, Some(name(kind)))),
+                _ => Err(ParseError::exp_alias(t)),
+            }
+        }
+    }
+
+    /// State after receiving `as role` during schema declaration, when expecting the name
+    /// of the role. Without an alias, `role` can instead be the alias, eg. `schema s1 as role (`.
+    #[derive(Debug)]
+    struct DeclaringSchemaRoleName(String, Option<String>);
+
+    impl State for DeclaringSchemaRoleName {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let schema_name = mem::take(&mut self.0);
+            let alias = self.1.take();
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    ctx.schema_role = Some(name(kind));
+                    to(ReceivedSchemaAlias(schema_name, alias))
+                }
+                _ if alias.is_none() => ReceivedSchemaAlias(schema_name, Some("role".to_owned())).receive(ctx, Some(t)),
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving the schema's alias, role, or settings, which can be followed
+    /// by the role or settings if they were not received yet.
+    #[derive(Debug)]
+    pub struct ReceivedSchemaAlias(pub String, pub Option<String>);
+
+    impl State for ReceivedSchemaAlias {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Keyword(Keyword::As) if ctx.schema_role.is_none() => {
+                    let schema_name = mem::take(&mut self.0);
+                    let alias = self.1.take();
+                    to(DeclaringSchemaRole(schema_name, alias))
+                }
+                TokenKind::Identifier(ident) if ident == "with" && ctx.settings.is_empty() => {
+                    let schema_name = mem::take(&mut self.0);
+                    let alias = self.1.take();
+                    to(settings_states::DeclaringSettings(settings_states::SettingsOf::schema(schema_name, alias)))
+                }
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    let schema_name = mem::take(&mut self.0);
+                    let alias = self.1.take();
+                    ctx.push_schema(schema_name, alias);
+                    to(InSchemaScope)
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State after receiving the `as` keyword following the schema's alias.
+    #[derive(Debug)]
+    struct DeclaringSchemaRole(String, Option<String>);
+
+    impl State for DeclaringSchemaRole {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let schema_name = mem::take(&mut self.0);
+            let alias = self.1.take();
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "role" => to(DeclaringSchemaRoleName(schema_name, alias)),
+                _ => Err(ParseError::exp_role(t)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct InSchemaScope;
+
+    impl State for InSchemaScope {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenRight) => {
+                    let schema = ctx.pop_schema_or_panic();
+                    ctx.push_schema_to_root_or_panic(schema);
+                    to(Root)
+                }
+                TokenKind::Keyword(Keyword::Table) => {
+                    ctx.table_position = Some(t.position);
+                    to(table_states::DeclaringTable)
+                }
+                TokenKind::Symbol(Symbol::AtSign) => to(tag_states::DeclaringTags(tag_states::Tagged::Table)),
+                // Not keywords, since they are only special outside of tables
+                TokenKind::Identifier(ident) if ident == "delete" => {
+                    ctx.table_position = Some(t.position);
+                    ctx.record_position = Some(t.position);
+                    to(delete_states::DeclaringDelete)
+                }
+                TokenKind::Identifier(ident) if ident == "before" && ctx.profile.is_none() => {
+                    to(directive_states::DeclaringHook(nodes::HookTiming::Before, t.position, true))
+                }
+                TokenKind::Identifier(ident) if ident == "after" && ctx.profile.is_none() => {
+                    to(directive_states::DeclaringHook(nodes::HookTiming::After, t.position, true))
+                }
+                TokenKind::LineSep => to(InSchemaScope),
+                _ => Err(ParseError::in_schema(t)),
+            }
+        }
+    }
+}
+
+mod settings_states {
+    use super::*;
+
+    /// The schema or table that settings are being declared for, which is returned to
+    /// once the settings are closed.
+    #[derive(Debug, Default)]
+    pub struct SettingsOf {
+        name: String,
+        alias: Option<String>,
+        schema: bool,
+    }
+
+    impl SettingsOf {
+        pub fn schema(name: String, alias: Option<String>) -> Self {
+            Self { name, alias, schema: true }
+        }
+
+        pub fn table(name: String, alias: Option<String>) -> Self {
+            Self { name, alias, schema: false }
+        }
+
+        fn close(self) -> ParseResult {
+            if self.schema {
+                to(schema_states::ReceivedSchemaAlias(self.name, self.alias))
+            } else {
+                to(table_states::ReceivedTableAlias(self.name, self.alias))
+            }
+        }
+    }
+
+    /// State after receiving `with` during schema or table declaration.
+    #[derive(Debug)]
+    pub struct DeclaringSettings(pub SettingsOf);
+
+    impl State for DeclaringSettings {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => to(InSettings(mem::take(&mut self.0))),
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State inside the settings' parentheses when expecting a setting's name.
+    #[derive(Debug)]
+    struct InSettings(SettingsOf);
+
+    impl State for InSettings {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let of = mem::take(&mut self.0);
+
+            match t.kind {
+                TokenKind::Identifier(ident) => to(ReceivedSettingName(of, ident)),
+                TokenKind::LineSep => to(InSettings(of)),
+                TokenKind::Symbol(Symbol::ParenRight) => of.close(),
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving a setting's name, which can be qualified, eg. `app.tenant_id`.
+    #[derive(Debug)]
+    struct ReceivedSettingName(SettingsOf, String);
+
+    impl State for ReceivedSettingName {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let of = mem::take(&mut self.0);
+            let name = mem::take(&mut self.1);
+
+            let value = match t.kind {
+                TokenKind::Symbol(Symbol::Period) => return to(DeclaringSettingName(of, name)),
+                TokenKind::Text(_) => attribute_states::unquote(t),
+                TokenKind::Number(n) => n,
+                TokenKind::Bool(b) => b.to_string(),
+                _ => return Err(ParseError::exp_setting_value(t)),
+            };
+
+            merge_setting(&mut ctx.settings, nodes::Setting { name, value });
+            to(ReceivedSettingValue(of))
+        }
+    }
+
+    /// State after receiving a period in a setting's name, when expecting the rest of it.
+    #[derive(Debug)]
+    struct DeclaringSettingName(SettingsOf, String);
+
+    impl State for DeclaringSettingName {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let of = mem::take(&mut self.0);
+            let name = mem::take(&mut self.1);
+
+            match t.kind {
+                TokenKind::Identifier(ident) => to(ReceivedSettingName(of, format!("{}.{}", name, ident))),
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving a setting's value, which is followed by another setting or
+    /// the closing parenthesis.
+    #[derive(Debug)]
+    struct ReceivedSettingValue(SettingsOf);
+
+    impl State for ReceivedSettingValue {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let of = mem::take(&mut self.0);
+
+            match t.kind {
+                TokenKind::Symbol(Symbol::Comma) | TokenKind::LineSep => to(InSettings(of)),
+                TokenKind::Symbol(Symbol::ParenRight) => of.close(),
+                _ => Err(ParseError::exp_close_attr(t)),
+            }
+        }
+    }
+}
+
+mod tag_states {
+    use super::*;
+
+    /// What the tags being declared are above, which must be declared once they are closed.
+    #[derive(Clone, Copy, Debug)]
+    pub enum Tagged {
+        Table,
+        Record,
+    }
+
+    /// State after receiving `@` above a table or record, which must be followed by `tag`.
+    #[derive(Debug)]
+    pub struct DeclaringTags(pub Tagged);
+
+    impl State for DeclaringTags {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                // Not a keyword, since it is only special after `@` outside of records
+                TokenKind::Identifier(ident) if ident == "tag" => to(ReceivedTagKeyword(self.0)),
+                _ => Err(ParseError::exp_tag(t)),
+            }
+        }
+    }
+
+    /// State after receiving `@tag`, which must be followed by the tags in parentheses.
+    #[derive(Debug)]
+    struct ReceivedTagKeyword(Tagged);
+
+    impl State for ReceivedTagKeyword {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => to(InTags(self.0)),
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State inside the tags' parentheses when expecting a tag.
+    #[derive(Debug)]
+    struct InTags(Tagged);
+
+    impl State for InTags {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) => {
+                    if !ctx.tags.contains(&ident) {
+                        ctx.tags.push(ident);
+                    }
+                    to(ReceivedTag(self.0))
+                }
+                TokenKind::LineSep => to(InTags(self.0)),
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving a tag, which is followed by another tag or the closing parenthesis.
+    #[derive(Debug)]
+    struct ReceivedTag(Tagged);
+
+    impl State for ReceivedTag {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::Comma) => to(InTags(self.0)),
+                TokenKind::LineSep => to(ReceivedTag(self.0)),
+                TokenKind::Symbol(Symbol::ParenRight) => to(ReceivedTags(self.0)),
+                _ => Err(ParseError::exp_close_attr(t)),
+            }
+        }
+    }
+
+    /// State after closing the tags, which must be followed by more tags or the table
+    /// or record they are above.
+    #[derive(Debug)]
+    struct ReceivedTags(Tagged);
+
+    impl State for ReceivedTags {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match (self.0, &t.kind) {
+                (_, TokenKind::LineSep) => to(ReceivedTags(self.0)),
+                (_, TokenKind::Symbol(Symbol::AtSign)) => to(DeclaringTags(self.0)),
+                (Tagged::Table, TokenKind::Keyword(Keyword::Table)) => {
+                    ctx.table_position = Some(t.position);
+                    to(table_states::DeclaringTable)
+                }
+                // Records are declared just as they would be without tags
+                (
+                    Tagged::Record,
+                    TokenKind::Identifier(_)
+                    | TokenKind::Symbol(Symbol::Underscore)
+                    | TokenKind::Symbol(Symbol::ParenLeft),
+                ) => table_states::InTableScope.receive(ctx, Some(t)),
+                _ => Err(ParseError::exp_tagged(t)),
+            }
+        }
+    }
+}
+
+mod delete_states {
+    use super::*;
+
+    /// State after receiving `delete` outside of a table, which must be followed by
+    /// `from` and the table to delete rows from.
+    #[derive(Debug)]
+    pub struct DeclaringDelete;
+
+    impl State for DeclaringDelete {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "from" => to(DeclaringDeleteTable),
+                _ => Err(ParseError::exp_from(t)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct DeclaringDeleteTable;
+
+    impl State for DeclaringDeleteTable {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    to(ReceivedDeleteTable(name(kind)))
+                }
+                _ => Err(ParseError::exp_table(t)),
+            }
+        }
+    }
+
+    /// State after receiving the table to delete rows from, which must be followed by
+    /// `where` and the columns that identify the rows, declared just like attributes.
+    #[derive(Debug)]
+    struct ReceivedDeleteTable(String);
+
+    impl State for ReceivedDeleteTable {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "where" => to(DeclaringDeleteConditions(mem::take(&mut self.0))),
+                _ => Err(ParseError::exp_where(t)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct DeclaringDeleteConditions(String);
+
+    impl State for DeclaringDeleteConditions {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_delete(mem::take(&mut self.0));
+                    to(record_states::InRecordScope)
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+}
+
+mod table_states {
+    use super::*;
+
+    /// State after receiving the `table` keyword for declaration.
+    #[derive(Debug)]
+    pub struct DeclaringTable;
+
+    impl State for DeclaringTable {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    to(ReceivedTableName(name(kind)))
+                }
+                _ => Err(ParseError::exp_table(t)),
+            }
+        }
+    }
+
+    /// State after receiving the table name during declaration.
+    #[derive(Debug)]
+    struct ReceivedTableName(String);
+
+    impl State for ReceivedTableName {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let table_name = mem::take(&mut self.0);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Keyword(Keyword::As) => to(DeclaringTableAlias(table_name)),
+                TokenKind::Identifier(ident) if ident == "with" => {
+                    to(settings_states::DeclaringSettings(settings_states::SettingsOf::table(table_name, None)))
+                }
+                TokenKind::Identifier(ident) if ident == "truncate" => {
+                    ctx.truncate_table = true;
+                    to(ReceivedTableTruncate(table_name, None))
+                }
+                TokenKind::Identifier(ident) if ident == "conflict" => to(DeclaringConflictTarget(table_name, None)),
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_table(table_name, None, None);
+                    to(InTableScope)
+                }
+                _ => Err(ParseError::alias_or_scope(t)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct DeclaringTableAlias(String);
+
+    impl State for DeclaringTableAlias {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let table_name = mem::take(&mut self.0);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "role" => to(DeclaringTableRoleName(table_name, None)),
+                kind @ TokenKind::Identifier(_) => to(ReceivedTableAlias(table_name, Some(name(kind)))),
+                _ => Err(ParseError::exp_alias(t)),
+            }
+        }
+    }
+
+    /// State after receiving `as role` during table declaration, when expecting the name
+    /// of the role. Without an alias, `role` can instead be the alias, eg. `table t1 as role (`.
+    #[derive(Debug)]
+    struct DeclaringTableRoleName(String, Option<String>);
+
+    impl State for DeclaringTableRoleName {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let table_name = mem::take(&mut self.0);
+            let alias = self.1.take();
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    ctx.table_role = Some(name(kind));
+                    to(ReceivedTableAlias(table_name, alias))
+                }
+                _ if alias.is_none() => ReceivedTableAlias(table_name, Some("role".to_owned())).receive(ctx, Some(t)),
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving the table's alias, role, or settings, which can be followed
+    /// by the role or settings if they were not received yet.
+    #[derive(Debug)]
+    pub struct ReceivedTableAlias(pub String, pub Option<String>);
+
+    impl State for ReceivedTableAlias {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let table_name = mem::take(&mut self.0);
+            let alias = self.1.take();
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Keyword(Keyword::As) if ctx.table_role.is_none() => to(DeclaringTableRole(table_name, alias)),
+                TokenKind::Identifier(ident) if ident == "with" && ctx.settings.is_empty() => {
+                    to(settings_states::DeclaringSettings(settings_states::SettingsOf::table(table_name, alias)))
+                }
+                TokenKind::Identifier(ident) if ident == "truncate" => {
+                    ctx.truncate_table = true;
+                    to(ReceivedTableTruncate(table_name, alias))
+                }
+                TokenKind::Identifier(ident) if ident == "conflict" => to(DeclaringConflictTarget(table_name, alias)),
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_table(table_name, alias, None);
+                    to(InTableScope)
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State after receiving the `as` keyword following the table's alias.
+    #[derive(Debug)]
+    struct DeclaringTableRole(String, Option<String>);
+
+    impl State for DeclaringTableRole {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let table_name = mem::take(&mut self.0);
+            let alias = self.1.take();
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "role" => to(DeclaringTableRoleName(table_name, alias)),
+                _ => Err(ParseError::exp_role(t)),
+            }
+        }
+    }
+
+    /// State after receiving `truncate` during table declaration, which can be followed
+    /// by a conflict target or the table scope.
+    #[derive(Debug)]
+    struct ReceivedTableTruncate(String, Option<String>);
+
+    impl State for ReceivedTableTruncate {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let table_name = mem::take(&mut self.0);
+            let alias = self.1.take();
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "conflict" => to(DeclaringConflictTarget(table_name, alias)),
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_table(table_name, alias, None);
+                    to(InTableScope)
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State after receiving `conflict` during table declaration, which is not a keyword,
+    /// since it is only special here.
+    #[derive(Debug)]
+    struct DeclaringConflictTarget(String, Option<String>);
+
+    impl State for DeclaringConflictTarget {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    let table_name = mem::take(&mut self.0);
+                    let alias = self.1.take();
+                    to(InConflictTarget(table_name, alias, Vec::new()))
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State inside the conflict target's parentheses when expecting a column name.
+    #[derive(Debug)]
+    struct InConflictTarget(String, Option<String>, Vec<String>);
+
+    impl State for InConflictTarget {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let table_name = mem::take(&mut self.0);
+            let alias = self.1.take();
+            let mut columns = mem::take(&mut self.2);
+
+            match t.kind {
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    columns.push(name(kind));
+                    to(ReceivedConflictColumn(table_name, alias, columns))
+                }
+                TokenKind::LineSep => to(InConflictTarget(table_name, alias, columns)),
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving a column name in the conflict target.
+    #[derive(Debug)]
+    struct ReceivedConflictColumn(String, Option<String>, Vec<String>);
+
+    impl State for ReceivedConflictColumn {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let table_name = mem::take(&mut self.0);
+            let alias = self.1.take();
+            let columns = mem::take(&mut self.2);
+
+            match t.kind {
+                TokenKind::Symbol(Symbol::Comma) => to(InConflictTarget(table_name, alias, columns)),
+                TokenKind::LineSep => to(ReceivedConflictColumn(table_name, alias, columns)),
+                TokenKind::Symbol(Symbol::ParenRight) => {
+                    to(ReceivedConflictTarget(table_name, alias, columns))
+                }
+                _ => Err(ParseError::exp_close_attr(t)),
+            }
+        }
+    }
+
+    /// State after closing the conflict target, which must be followed by the table scope.
+    #[derive(Debug)]
+    struct ReceivedConflictTarget(String, Option<String>, Vec<String>);
+
+    impl State for ReceivedConflictTarget {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    let table_name = mem::take(&mut self.0);
+                    let alias = self.1.take();
+                    let columns = mem::take(&mut self.2);
+                    ctx.push_table(table_name, alias, Some(columns));
+                    to(InTableScope)
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct InTableScope;
+
+    impl State for InTableScope {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            // Any record declared from here starts with this token
+            ctx.record_position = Some(t.position);
+
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenRight) => {
+                    let table = ctx.pop_table_or_panic();
+
+                    match ctx.push_table_to_parent_or_panic(table) {
+                        PushedTableTo::TreeRoot => to(Root),
+                        PushedTableTo::Schema => to(schema_states::InSchemaScope),
+                    }
+                }
+                // Not a keyword, since it is only special before a number, so records may
+                // still be named `expect`
+                TokenKind::Identifier(ident) if ident == "expect" && ctx.tags.is_empty() => to(ReceivedExpect),
+                // Not a keyword either, since it is only special before a record name
+                TokenKind::Identifier(ident) if ident == "update" => to(record_states::ReceivedUpdate),
+                TokenKind::Identifier(ident) if ident == "abstract" => to(record_states::ReceivedAbstract),
+                TokenKind::Identifier(ident) => to(record_states::ReceivedRecordName(ident)),
+                TokenKind::Symbol(Symbol::Underscore) => {
+                    to(record_states::ReceivedExplicitAnonymousRecord)
+                }
+                TokenKind::Keyword(Keyword::Defaults) => to(record_states::ReceivedDefaults),
+                TokenKind::Keyword(Keyword::Columns) => to(DeclaringColumnSet),
+                TokenKind::Keyword(Keyword::Import) => to(DeclaringImport(t.position)),
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_record(None);
+                    to(record_states::InRecordScope)
+                }
+                TokenKind::Symbol(Symbol::AtSign) => to(tag_states::DeclaringTags(tag_states::Tagged::Record)),
+                TokenKind::LineSep => to(InTableScope),
+                _ => Err(ParseError::in_table(t)),
+            }
+        }
+    }
+
+    /// State after receiving `expect` in the table scope, which is followed by the number
+    /// of rows the table is expected to have, or else names a record.
+    #[derive(Debug)]
+    struct ReceivedExpect;
+
+    impl State for ReceivedExpect {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Number(ref n) => match n.parse() {
+                    Ok(count) => to(ReceivedExpectedRowCount(count)),
+                    Err(_) => Err(ParseError::exp_row_count(t)),
+                },
+                _ => defer_to(&mut record_states::ReceivedRecordName("expect".to_owned()), ctx, Some(t)),
+            }
+        }
+    }
+
+    /// State after receiving the expected row count, which must be followed by `rows`.
+    #[derive(Debug)]
+    struct ReceivedExpectedRowCount(u64);
+
+    impl State for ReceivedExpectedRowCount {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "rows" || ident == "row" => {
+                    ctx.set_expected_rows_or_panic(self.0);
+                    to(InTableScope)
+                }
+                _ => Err(ParseError::exp_rows(t)),
+            }
+        }
+    }
+
+    /// State after receiving `$import` in the table scope, along with its position.
+    #[derive(Debug)]
+    struct DeclaringImport(Position);
+
+    impl State for DeclaringImport {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Text(_) => {
+                    ctx.push_import_to_table_or_panic(attribute_states::unquote(t), self.0);
+                    to(InTableScope)
+                }
+                _ => Err(ParseError::exp_import(t)),
+            }
+        }
+    }
+
+    /// State after receiving `$columns` in the table scope.
+    #[derive(Debug)]
+    struct DeclaringColumnSet;
+
+    impl State for DeclaringColumnSet {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => to(InColumnSet(Vec::new())),
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State inside the column set's parentheses when expecting a column name.
+    #[derive(Debug)]
+    struct InColumnSet(Vec<String>);
+
+    impl State for InColumnSet {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let mut columns = mem::take(&mut self.0);
+
+            match t.kind {
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    columns.push(name(kind));
+                    to(ReceivedSetColumn(columns))
+                }
+                TokenKind::LineSep => to(InColumnSet(columns)),
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving a column name in the column set.
+    #[derive(Debug)]
+    struct ReceivedSetColumn(Vec<String>);
+
+    impl State for ReceivedSetColumn {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let columns = mem::take(&mut self.0);
+
+            match t.kind {
+                TokenKind::Symbol(Symbol::Comma) => to(InColumnSet(columns)),
+                TokenKind::LineSep => to(ReceivedSetColumn(columns)),
+                TokenKind::Symbol(Symbol::ParenRight) => {
+                    ctx.columns = Some(columns);
+                    to(InTableScope)
+                }
+                _ => Err(ParseError::exp_close_attr(t)),
+            }
+        }
+    }
+}
+
+mod record_states {
+    use super::*;
+
+    /// State after receiving a record name in the table scope.
+    #[derive(Debug)]
+    pub struct ReceivedRecordName(pub String);
+
+    impl State for ReceivedRecordName {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let record_name = mem::take(&mut self.0);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_record(Some(record_name));
+                    to(InRecordScope)
+                }
+                TokenKind::Identifier(ident) if ident == "extends" => to(DeclaringBase(Some(record_name))),
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State after receiving `abstract` in the table scope, which is followed by the
+    /// name of the record, or else is itself the name of a record.
+    #[derive(Debug)]
+    pub struct ReceivedAbstract;
+
+    impl State for ReceivedAbstract {
         fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Symbol(Symbol::ParenRight) => {
-                    let table = ctx.pop_table_or_panic();
-
-                    match ctx.push_table_to_parent_or_panic(table) {
-                        PushedTableTo::TreeRoot => to(Root),
-                        PushedTableTo::Schema => to(schema_states::InSchemaScope),
-                    }
+                TokenKind::Identifier(ident) => {
+                    ctx.abstract_record = true;
+                    to(ReceivedRecordName(ident))
                 }
-                TokenKind::Identifier(ident) => to(record_states::ReceivedRecordName(ident)),
-                TokenKind::Symbol(Symbol::Underscore) => {
-                    to(record_states::ReceivedExplicitAnonymousRecord)
+                _ => defer_to(&mut ReceivedRecordName("abstract".to_owned()), ctx, Some(t)),
+            }
+        }
+    }
+
+    /// State after receiving `extends` for a named or anonymous record, which must
+    /// be followed by the name of the record it extends.
+    #[derive(Debug)]
+    struct DeclaringBase(Option<String>);
+
+    impl State for DeclaringBase {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) => {
+                    ctx.record_base = Some(ident);
+                    to(ReceivedBase(self.0.take()))
                 }
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
+
+    /// State after receiving the name of the record that a record extends.
+    #[derive(Debug)]
+    struct ReceivedBase(Option<String>);
+
+    impl State for ReceivedBase {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
                 TokenKind::Symbol(Symbol::ParenLeft) => {
-                    ctx.push_record(None);
-                    to(record_states::InRecordScope)
+                    ctx.push_record(self.0.take());
+                    to(InRecordScope)
                 }
-                TokenKind::LineSep => to(InTableScope),
-                _ => Err(ParseError::in_table(t)),
+                _ => Err(ParseError::exp_scope(t)),
             }
         }
     }
-}
 
-mod record_states {
-    use super::*;
+    /// State after receiving `update` in the table scope, which is followed by the name of
+    /// the record, or `_` for an anonymous one, or else names a record itself.
+    #[derive(Debug)]
+    pub struct ReceivedUpdate;
 
-    /// State after receiving a record name in the table scope.
+    impl State for ReceivedUpdate {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) => to(ReceivedUpdateName(Some(ident))),
+                TokenKind::Symbol(Symbol::Underscore) => to(ReceivedUpdateName(None)),
+                _ => defer_to(&mut ReceivedRecordName("update".to_owned()), ctx, Some(t)),
+            }
+        }
+    }
+
+    /// State after receiving the name of a record that updates rows, which must be
+    /// followed by `where`.
     #[derive(Debug)]
-    pub struct ReceivedRecordName(pub String);
+    struct ReceivedUpdateName(Option<String>);
 
-    impl State for ReceivedRecordName {
+    impl State for ReceivedUpdateName {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Identifier(ident) if ident == "where" => to(DeclaringUpdateConditions(self.0.take())),
+                _ => Err(ParseError::exp_where(t)),
+            }
+        }
+    }
+
+    /// State after receiving `where` for a record that updates rows, which must be
+    /// followed by the columns that identify them, declared just like attributes.
+    #[derive(Debug)]
+    struct DeclaringUpdateConditions(Option<String>);
+
+    impl State for DeclaringUpdateConditions {
         fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
-            let record_name = mem::take(&mut self.0);
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
                 TokenKind::Symbol(Symbol::ParenLeft) => {
-                    ctx.push_record(Some(record_name));
+                    ctx.push_record(self.0.take());
+                    ctx.update_conditions = true;
                     to(InRecordScope)
                 }
                 _ => Err(ParseError::exp_scope(t)),
@@ -406,95 +2013,349 @@ mod record_states {
         }
     }
 
+    /// State after closing the columns that identify the rows a record updates, which
+    /// must be followed by the columns to update them with.
+    #[derive(Debug)]
+    struct ReceivedUpdateConditions;
+
+    impl State for ReceivedUpdateConditions {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => to(InRecordScope),
+                TokenKind::LineSep => to(ReceivedUpdateConditions),
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
     /// State after receiving an `_` in the table scope.
     #[derive(Debug)]
     pub struct ReceivedExplicitAnonymousRecord;
 
-    impl State for ReceivedExplicitAnonymousRecord {
-        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+    impl State for ReceivedExplicitAnonymousRecord {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_record(None);
+                    to(InRecordScope)
+                }
+                TokenKind::Symbol(Symbol::Asterisk) => to(DeclaringRecordQuantity),
+                TokenKind::Identifier(ident) if ident == "extends" => to(DeclaringBase(None)),
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State after receiving `_ *` when declaring multiple copies of an anonymous record.
+    #[derive(Debug)]
+    pub struct DeclaringRecordQuantity;
+
+    impl State for DeclaringRecordQuantity {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let quantity = match &t.kind {
+                TokenKind::Number(n) => n.replace('_', "").parse::<usize>().ok(),
+                _ => None,
+            };
+            match quantity {
+                Some(quantity) if quantity > 0 => to(ReceivedRecordQuantity(quantity)),
+                _ => Err(ParseError::exp_quantity(t)),
+            }
+        }
+    }
+
+    /// State after receiving the quantity of copies of an anonymous record.
+    #[derive(Debug)]
+    pub struct ReceivedRecordQuantity(usize);
+
+    impl State for ReceivedRecordQuantity {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_repeated_record(self.0);
+                    to(InRecordScope)
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    /// State after receiving `$defaults` in the table scope, whose attributes
+    /// are then declared just like a record's.
+    #[derive(Debug)]
+    pub struct ReceivedDefaults;
+
+    impl State for ReceivedDefaults {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    ctx.push_defaults();
+                    to(InRecordScope)
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct InRecordScope;
+
+    impl State for InRecordScope {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            // Any attribute declared from here starts with this token
+            ctx.attribute_position = Some(t.position);
+
+            // Records in tables with a column set only declare values, which are
+            // assigned to the columns in order
+            if let Some((columns, received)) = ctx.positional_values() {
+                let column = columns.get(received).cloned();
+
+                return match t.kind {
+                    TokenKind::Symbol(Symbol::ParenRight) if received < columns.len() => {
+                        Err(ParseError::value_count(columns.len(), received, t))
+                    }
+                    TokenKind::Symbol(Symbol::ParenRight) => {
+                        ctx.close_record_or_panic();
+                        to(table_states::InTableScope)
+                    }
+                    TokenKind::LineSep => to(InRecordScope),
+                    _ => match column {
+                        Some(column) => defer_to(&mut attribute_states::ReceivedAttributeName(column), ctx, Some(t)),
+                        None => Err(ParseError::extra_value(t)),
+                    },
+                };
+            }
+
+            match t.kind {
+                // Records must identify the rows they update by at least one column and
+                // update at least one other
+                TokenKind::Symbol(Symbol::ParenRight) if ctx.update_conditions => match ctx.close_update_conditions_or_panic() {
+                    true => to(ReceivedUpdateConditions),
+                    false => Err(ParseError::exp_ident(t)),
+                },
+                TokenKind::Symbol(Symbol::ParenRight) if ctx.is_empty_update() => Err(ParseError::exp_ident(t)),
+                // As must records that delete them, which are declared outside of tables
+                TokenKind::Symbol(Symbol::ParenRight) if ctx.is_delete() => match ctx.close_delete_or_panic() {
+                    Some(PushedTableTo::TreeRoot) => to(Root),
+                    Some(PushedTableTo::Schema) => to(schema_states::InSchemaScope),
+                    None => Err(ParseError::exp_ident(t)),
+                },
+                TokenKind::Symbol(Symbol::ParenRight) => {
+                    ctx.close_record_or_panic();
+                    to(table_states::InTableScope)
+                }
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    to(attribute_states::ReceivedAttributeName(name(kind)))
+                }
+                TokenKind::Symbol(Symbol::ParenLeft) => to(attribute_states::DeclaringColumns(Vec::new())),
+                TokenKind::LineSep => to(InRecordScope),
+                _ => Err(ParseError::in_record(t)),
+            }
+        }
+    }
+}
+
+mod attribute_states {
+    use self::record_states::InRecordScope;
+
+    use super::*;
+
+    #[derive(Debug)]
+    pub struct ReceivedAttributeName(pub String);
+
+    impl State for ReceivedAttributeName {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let attribute_name = mem::take(&mut self.0);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            ctx.value_position = Some(t.position);
+            match t.kind {
+                TokenKind::Bool(b) => {
+                    let value = nodes::Value::Bool(b);
+                    ctx.push_attribute(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::Hex(h) => {
+                    ctx.push_attribute(attribute_name, nodes::Value::Hex(h));
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::Json(json) => {
+                    ctx.push_attribute(attribute_name, nodes::Value::Json(json));
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::Identifier(ident) if FUNCTIONS.contains(&ident.as_str()) => {
+                    to(ReceivedFunctionName(attribute_name, ident))
+                }
+                TokenKind::Null => {
+                    ctx.push_attribute(attribute_name, nodes::Value::Null);
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::Number(n) => {
+                    let value = nodes::Value::Number(n);
+                    ctx.push_attribute(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::SqlFragment(s) => {
+                    let value = nodes::Value::SqlFragment(s);
+                    ctx.push_attribute(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::InterpolatedSqlFragment(parts) => {
+                    let value = nodes::Value::InterpolatedSqlFragment(segments(parts)?);
+                    ctx.push_attribute(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                // Not a keyword, since it is only special as a value, so columns may still be
+                // named `default`
+                TokenKind::Identifier(ident) if ident == "default" => {
+                    ctx.push_attribute(attribute_name, nodes::Value::Default);
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::Variable(name) => {
+                    ctx.push_attribute(attribute_name, nodes::Value::Variable(name));
+                    to(ReceivedAttributeValue)
+                }
+                // Not a keyword, since it is only special before a SQL fragment
+                TokenKind::Identifier(ident) if ident == "volatile" => to(ReceivedVolatile(attribute_name)),
+                // An `@` directly after the column's name is shorthand for a reference
+                // to the column of the table that the column is named after
+                TokenKind::Symbol(Symbol::AtSign) if ctx.follows_attribute_name(&attribute_name, t.position) => {
+                    let (table, column) = match attribute_name.rsplit_once('_') {
+                        Some((table, column)) if !table.is_empty() && !column.is_empty() => {
+                            (table.to_owned(), column.to_owned())
+                        }
+                        _ => return Err(ParseError::shorthand(attribute_name, t.position)),
+                    };
+                    ctx.reference_shorthand = Some((table, column));
+                    to(ReceivedReferenceStart(attribute_name))
+                }
+                TokenKind::Symbol(Symbol::AtSign) => to(ReceivedReferenceStart(attribute_name)),
+                TokenKind::Text(t) => {
+                    let value = nodes::Value::Text(t);
+                    ctx.push_attribute(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::InterpolatedText(parts) => {
+                    let value = interpolated_text(parts)?;
+                    ctx.push_attribute(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                _ => Err(ParseError::exp_value(t)),
+            }
+        }
+    }
+
+    /// State after receiving `volatile` as the value of an attribute, which must be
+    /// followed by the SQL fragment that is not to be cached.
+    #[derive(Debug)]
+    pub struct ReceivedVolatile(pub String);
+
+    impl State for ReceivedVolatile {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let attribute_name = mem::take(&mut self.0);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::SqlFragment(s) => {
+                    ctx.push_attribute(attribute_name, nodes::Value::VolatileSqlFragment(s));
+                    to(ReceivedAttributeValue)
+                }
+                // Fragments with references are never cached anyway
+                TokenKind::InterpolatedSqlFragment(parts) => {
+                    let value = nodes::Value::InterpolatedSqlFragment(segments(parts)?);
+                    ctx.push_attribute(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                _ => Err(ParseError::exp_fragment(t)),
+            }
+        }
+    }
+
+    /// State after receiving the opening parenthesis of several columns that are declared
+    /// at once with a reference to the same number of columns of a record, eg.
+    /// `(tenant_id, user_id) @membership1.(tenant_id, user_id)`, each of which is then
+    /// declared as its own attribute.
+    #[derive(Debug)]
+    pub struct DeclaringColumns(pub Vec<String>);
+
+    impl State for DeclaringColumns {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let mut columns = mem::take(&mut self.0);
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Symbol(Symbol::ParenLeft) => {
-                    ctx.push_record(None);
-                    to(InRecordScope)
+                kind @ (TokenKind::Identifier(_) | TokenKind::QuotedIdentifier(_)) => {
+                    columns.push(name(kind));
+                    to(ReceivedColumn(columns))
                 }
-                _ => Err(ParseError::exp_scope(t)),
+                _ => Err(ParseError::exp_ident(t)),
             }
         }
     }
 
     #[derive(Debug)]
-    pub struct InRecordScope;
+    pub struct ReceivedColumn(Vec<String>);
 
-    impl State for InRecordScope {
-        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+    impl State for ReceivedColumn {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let columns = mem::take(&mut self.0);
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Symbol(Symbol::ParenRight) => {
-                    let record = ctx.pop_record_or_panic();
-                    ctx.push_record_to_table_or_panic(record);
-                    to(table_states::InTableScope)
-                }
-                TokenKind::Identifier(ident) | TokenKind::QuotedIdentifier(ident) => {
-                    to(attribute_states::ReceivedAttributeName(ident))
-                }
-                TokenKind::LineSep => to(InRecordScope),
-                _ => Err(ParseError::in_record(t)),
+                TokenKind::Symbol(Symbol::Comma) => to(DeclaringColumns(columns)),
+                TokenKind::Symbol(Symbol::ParenRight) => to(ReceivedColumns(columns)),
+                _ => Err(ParseError::exp_close_args(t)),
             }
         }
     }
-}
-
-mod attribute_states {
-    use self::record_states::InRecordScope;
-
-    use super::*;
-
-    #[derive(Debug)]
-    struct Identifier {
-        quoted: bool,
-        value: String,
-    }
 
     #[derive(Debug)]
-    pub struct ReceivedAttributeName(pub String);
+    pub struct ReceivedColumns(Vec<String>);
 
-    impl State for ReceivedAttributeName {
+    impl State for ReceivedColumns {
         fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
-            let attribute_name = mem::take(&mut self.0);
             let t = match t {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
             match t.kind {
-                TokenKind::Bool(b) => {
-                    let value = nodes::Value::Bool(b);
-                    ctx.push_attribute(attribute_name, value);
-                    to(ReceivedAttributeValue)
-                }
-                TokenKind::Number(n) => {
-                    let value = nodes::Value::Number(n);
-                    ctx.push_attribute(attribute_name, value);
-                    to(ReceivedAttributeValue)
-                }
-                TokenKind::SqlFragment(s) => {
-                    let value = nodes::Value::SqlFragment(s);
-                    ctx.push_attribute(attribute_name, value);
-                    to(ReceivedAttributeValue)
-                }
-                TokenKind::Symbol(Symbol::AtSign) => to(ReceivedReferenceStart(attribute_name)),
-                TokenKind::Text(t) => {
-                    let value = nodes::Value::Text(t);
-                    ctx.push_attribute(attribute_name, value);
-                    to(ReceivedAttributeValue)
+                TokenKind::Symbol(Symbol::AtSign) => {
+                    ctx.value_position = Some(t.position);
+                    ctx.reference_columns = Some(mem::take(&mut self.0));
+                    to(ReceivedReferenceStart(String::new()))
                 }
-                _ => Err(ParseError::exp_value(t)),
+                _ => Err(ParseError::exp_reference(t)),
             }
         }
     }
@@ -534,30 +2395,31 @@ mod attribute_states {
                 Some(t) => t,
                 None => return Err(ParseError::eof()),
             };
+            // Shorthand references can only have the schema and the record
+            let max_identifiers = if ctx.reference_shorthand.is_some() { 2 } else { 4 };
+
             match t.kind {
-                TokenKind::Symbol(Symbol::Period) if identifiers.len() < 4 => {
+                TokenKind::Symbol(Symbol::Period) if identifiers.len() < max_identifiers => {
                     to(ReceivedReferenceSeparator(attribute_name, identifiers))
                 }
-                TokenKind::LineSep
-                | TokenKind::Symbol(Symbol::Comma)
-                | TokenKind::Symbol(Symbol::ParenRight)
-                    if identifiers.len() < 5 =>
+                // Only the first identifier can be a namespace, which cannot be quoted
+                TokenKind::Symbol(Symbol::DoubleColon)
+                    if ctx.reference_namespace.is_none() && identifiers.len() == 1 && !identifiers[0].quoted =>
                 {
-                    let reference = identifiers_to_explicit_reference(t.position, identifiers)?;
-                    let attribute = nodes::Attribute {
-                        name: attribute_name,
-                        value: nodes::Value::Reference(reference),
+                    ctx.reference_namespace = identifiers.into_iter().next().map(|i| i.value);
+                    to(ReceivedReferenceStart(attribute_name))
+                }
+                // References for several columns must end with the columns they reference
+                _ if ctx.reference_columns.is_some() => Err(ParseError::exp_columns(t)),
+                _ if ends_value(&t.kind) && identifiers.len() <= max_identifiers => {
+                    let namespace = ctx.reference_namespace.take();
+                    let identifiers = match ctx.reference_shorthand.take() {
+                        Some(shorthand) => expand_shorthand(shorthand, identifiers),
+                        None => identifiers,
                     };
-                    ctx.push_attribute_to_record_or_panic(attribute);
-
-                    // TODO: This pattern is getting a bit gross. There needs to be a cleaner way of ending,
-                    // since all values need to handle this line sep/comma/paren pattern.
-                    match t.kind {
-                        TokenKind::Symbol(Symbol::ParenRight) => {
-                            defer_to(&mut InRecordScope, ctx, Some(t))
-                        }
-                        _ => to(InRecordScope),
-                    }
+                    let reference = identifiers_to_explicit_reference(t.position, namespace, identifiers)?;
+                    ctx.push_value(attribute_name, nodes::Value::Reference(reference));
+                    defer_to(&mut ReceivedAttributeValue, ctx, Some(t))
                 }
                 _ => Err(ParseError::token(t)),
             }
@@ -595,6 +2457,9 @@ mod attribute_states {
             // on the final form of the reference, so this state defers all checks to
             // the next state.
             match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) if ctx.reference_columns.is_some() => {
+                    to(DeclaringReferencedColumns(identifiers, Vec::new()))
+                }
                 TokenKind::Identifier(ident) | TokenKind::QuotedIdentifier(ident) => {
                     identifiers.push(Identifier {
                         quoted,
@@ -602,33 +2467,251 @@ mod attribute_states {
                     });
                     to(ReceivedReferenceIdentifier(attribute_name, identifiers))
                 }
+                // Implicit references for several columns reference the same columns
+                TokenKind::LineSep | TokenKind::Symbol(Symbol::Comma | Symbol::ParenRight)
+                    if ctx.reference_columns.is_some() =>
+                {
+                    let columns = ctx.reference_columns.take().unwrap();
+                    let namespace = ctx.reference_namespace.take();
+                    let reference = identifiers_to_implicit_reference(t.position, namespace, identifiers)?;
+                    let attributes = columns
+                        .into_iter()
+                        .map(|column| (column, nodes::Value::Reference(reference.clone())))
+                        .collect();
+
+                    push_attributes(ctx, attributes);
+                    defer_to(&mut ReceivedAttributeValue, ctx, Some(t))
+                }
                 // This state can, however, determine if it can successfully terminate without
                 // receiving an identifier, since that is allowed for references above the
                 // column level when using implicit column references.
-                TokenKind::LineSep
-                | TokenKind::Symbol(Symbol::Comma)
-                | TokenKind::Symbol(Symbol::ParenRight)
-                    if identifiers.len() < 4 =>
-                {
-                    let reference = identifiers_to_implicit_reference(t.position, identifiers)?;
-                    let attribute = nodes::Attribute {
-                        name: attribute_name,
-                        value: nodes::Value::Reference(reference),
-                    };
-                    ctx.push_attribute_to_record_or_panic(attribute);
+                _ if ends_value(&t.kind) && identifiers.len() < 4 && ctx.reference_shorthand.is_none() => {
+                    let namespace = ctx.reference_namespace.take();
+                    let reference = identifiers_to_implicit_reference(t.position, namespace, identifiers)?;
+                    ctx.push_value(attribute_name, nodes::Value::Reference(reference));
+                    defer_to(&mut ReceivedAttributeValue, ctx, Some(t))
+                }
+                _ => Err(ParseError::exp_ident(t)),
+            }
+        }
+    }
 
-                    match t.kind {
-                        TokenKind::Symbol(Symbol::ParenRight) => {
-                            defer_to(&mut InRecordScope, ctx, Some(t))
-                        }
-                        _ => to(InRecordScope),
-                    }
+    /// State after receiving the opening parenthesis of the columns referenced for
+    /// several columns at once, after the identifiers of the record being referenced.
+    #[derive(Debug)]
+    pub struct DeclaringReferencedColumns(Vec<Identifier>, Vec<Identifier>);
+
+    impl State for DeclaringReferencedColumns {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let identifiers = mem::take(&mut self.0);
+            let mut columns = mem::take(&mut self.1);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            let quoted = matches!(&t.kind, TokenKind::QuotedIdentifier(_));
+            match t.kind {
+                TokenKind::Identifier(ident) | TokenKind::QuotedIdentifier(ident) => {
+                    columns.push(Identifier { quoted, value: ident });
+                    to(ReceivedReferencedColumn(identifiers, columns))
                 }
                 _ => Err(ParseError::exp_ident(t)),
             }
         }
     }
 
+    #[derive(Debug)]
+    pub struct ReceivedReferencedColumn(Vec<Identifier>, Vec<Identifier>);
+
+    impl State for ReceivedReferencedColumn {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let identifiers = mem::take(&mut self.0);
+            let referenced = mem::take(&mut self.1);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::Comma) => to(DeclaringReferencedColumns(identifiers, referenced)),
+                TokenKind::Symbol(Symbol::ParenRight) => {
+                    let columns = ctx.reference_columns.take().unwrap();
+
+                    if columns.len() != referenced.len() {
+                        return Err(ParseError::column_count(columns.len(), referenced.len(), t));
+                    }
+
+                    let namespace = ctx.reference_namespace.take();
+                    let mut attributes = Vec::new();
+
+                    for (column, referenced) in columns.into_iter().zip(referenced) {
+                        let mut identifiers = identifiers.clone();
+                        identifiers.push(referenced);
+
+                        let reference = identifiers_to_explicit_reference(t.position, namespace.clone(), identifiers)?;
+                        attributes.push((column, nodes::Value::Reference(reference)));
+                    }
+
+                    push_attributes(ctx, attributes);
+                    to(ReceivedReferencedColumns)
+                }
+                _ => Err(ParseError::exp_close_args(t)),
+            }
+        }
+    }
+
+    /// State after receiving the columns referenced for several columns at once, which
+    /// can't be combined with other values by operators, so must end the attribute.
+    #[derive(Debug)]
+    pub struct ReceivedReferencedColumns;
+
+    impl State for ReceivedReferencedColumns {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::LineSep | TokenKind::Symbol(Symbol::Comma | Symbol::ParenRight) => {
+                    defer_to(&mut ReceivedAttributeValue, ctx, Some(t))
+                }
+                _ => Err(ParseError::exp_close_attr(t)),
+            }
+        }
+    }
+
+    /// Declares an attribute for each column and value, leaving the last on the stack to
+    /// be closed like any other attribute.
+    fn push_attributes(ctx: &mut Context, mut attributes: Vec<(String, nodes::Value)>) {
+        let (last_column, last_value) = attributes.pop().expect("expected at least one column");
+
+        for (column, value) in attributes {
+            let attribute = ctx.new_attribute(column, value);
+            ctx.push_attribute_to_record_or_panic(attribute);
+        }
+
+        ctx.push_attribute(last_column, last_value);
+    }
+
+    /// State after receiving the name of a value function, which must be followed
+    /// by its arguments in parentheses.
+    #[derive(Debug)]
+    pub struct ReceivedFunctionName(String, String);
+
+    impl State for ReceivedFunctionName {
+        fn receive(&mut self, _ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::ParenLeft) => {
+                    to(InFunctionArgs(mem::take(&mut self.0), mem::take(&mut self.1), Vec::new()))
+                }
+                _ => Err(ParseError::exp_scope(t)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct InFunctionArgs(String, String, Vec<Token>);
+
+    impl State for InFunctionArgs {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let attribute_name = mem::take(&mut self.0);
+            let function_name = mem::take(&mut self.1);
+            let mut args = mem::take(&mut self.2);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Text(_) => {
+                    args.push(t);
+                    to(ReceivedFunctionArg(attribute_name, function_name, args))
+                }
+                // Only allowed before any arguments, so that `f('a', )` is invalid
+                TokenKind::Symbol(Symbol::ParenRight) if args.is_empty() => {
+                    let value = function_value(&function_name, args, t)?;
+                    ctx.push_value(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                _ => Err(ParseError::exp_arg(t)),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct ReceivedFunctionArg(String, String, Vec<Token>);
+
+    impl State for ReceivedFunctionArg {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let attribute_name = mem::take(&mut self.0);
+            let function_name = mem::take(&mut self.1);
+            let args = mem::take(&mut self.2);
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Symbol(Symbol::Comma) => to(InFunctionArgs(attribute_name, function_name, args)),
+                TokenKind::Symbol(Symbol::ParenRight) => {
+                    let value = function_value(&function_name, args, t)?;
+                    ctx.push_value(attribute_name, value);
+                    to(ReceivedAttributeValue)
+                }
+                _ => Err(ParseError::exp_close_args(t)),
+            }
+        }
+    }
+
+    /// Converts a call to one of the value functions into its value, given the
+    /// closing parenthesis for reporting the wrong number of arguments.
+    fn function_value(function_name: &str, args: Vec<Token>, close: Token) -> Result<nodes::Value, ParseError> {
+        let expected = match function_name {
+            "ago" | "bytes" | "env" => 1,
+            "index" | "now" | "uuid" => 0,
+            "uuid5" => 2,
+            _ => unreachable!("unknown function `{}`", function_name),
+        };
+
+        if args.len() != expected {
+            return Err(ParseError::fn_args(function_name.to_owned(), expected, args.len(), close));
+        }
+
+        let mut args = args.into_iter();
+
+        Ok(match function_name {
+            "ago" => nodes::Value::Now(Some(nodes::TimeOffset::Subtract(unquote(args.next().unwrap())))),
+            "bytes" => nodes::Value::File(unquote(args.next().unwrap()).into()),
+            "env" => nodes::Value::EnvVar(unquote(args.next().unwrap())),
+            "index" => nodes::Value::Index,
+            "now" => nodes::Value::Now(None),
+            "uuid" => nodes::Value::RandomUuid,
+            _ => {
+                let namespace = args.next().unwrap();
+                let namespace = match unquote(namespace.clone()).to_lowercase().as_str() {
+                    "dns" => Uuid::NAMESPACE_DNS,
+                    "oid" => Uuid::NAMESPACE_OID,
+                    "url" => Uuid::NAMESPACE_URL,
+                    "x500" => Uuid::NAMESPACE_X500,
+                    s => Uuid::parse_str(s).map_err(|_| ParseError::uuid_namespace(namespace))?,
+                };
+                let name = unquote(args.next().unwrap());
+
+                nodes::Value::Uuid5 { namespace, name }
+            }
+        })
+    }
+
+    /// Returns the contents of a text token, which retains its surrounding
+    /// and any escaped quotes from the lexer.
+    pub(super) fn unquote(t: Token) -> String {
+        match t.kind {
+            TokenKind::Text(text) => text[1..text.len() - 1].replace("''", "'"),
+            _ => unreachable!("expected text token"),
+        }
+    }
+
     #[derive(Debug)]
     pub struct ReceivedAttributeValue;
 
@@ -652,12 +2735,184 @@ mod attribute_states {
                         _ => to(record_states::InRecordScope),
                     }
                 }
+                TokenKind::Symbol(symbol) if operator(&symbol).is_some() => {
+                    if !ctx.accepts_operator() {
+                        return Err(ParseError::exp_close_attr(Token { kind: TokenKind::Symbol(symbol), ..t }));
+                    }
+
+                    ctx.operator = operator(&symbol);
+                    to(ReceivedOperator)
+                }
+                // Without whitespace after the dash, eg. `@item.position -1`, the
+                // lexer cannot tell subtraction from a negative number
+                TokenKind::Number(n) if n.starts_with('-') && ctx.accepts_operator() => {
+                    ctx.operator = Some(nodes::Operator::Subtract);
+                    ctx.push_value(String::new(), nodes::Value::Number(n[1..].to_owned()));
+                    to(ReceivedAttributeValue)
+                }
                 _ => Err(ParseError::exp_close_attr(t)),
             }
         }
     }
 
-    fn identifiers_to_explicit_reference(position: Position, identifiers: Vec<Identifier>) -> Result<nodes::Reference, ParseError> {
+    /// State after receiving an operator, which must be followed by the operand on its
+    /// right-hand side, which is combined with the value of the attribute being declared.
+    #[derive(Debug)]
+    pub struct ReceivedOperator;
+
+    impl State for ReceivedOperator {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            // The attribute has already been pushed, so its name is not needed
+            match t.kind {
+                TokenKind::Identifier(ident) if FUNCTIONS.contains(&ident.as_str()) => {
+                    to(ReceivedFunctionName(String::new(), ident))
+                }
+                TokenKind::Null => {
+                    ctx.push_value(String::new(), nodes::Value::Null);
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::Number(n) => {
+                    ctx.push_value(String::new(), nodes::Value::Number(n));
+                    to(ReceivedAttributeValue)
+                }
+                // Not a keyword, since it is only special after `now() +` or `now() -`
+                TokenKind::Identifier(ident) if ident == "interval" && ctx.accepts_interval() => {
+                    to(ReceivedInterval)
+                }
+                TokenKind::Symbol(Symbol::AtSign) => to(ReceivedReferenceStart(String::new())),
+                TokenKind::Text(t) => {
+                    ctx.push_value(String::new(), nodes::Value::Text(t));
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::Variable(name) => {
+                    ctx.push_value(String::new(), nodes::Value::Variable(name));
+                    to(ReceivedAttributeValue)
+                }
+                TokenKind::InterpolatedText(parts) => {
+                    ctx.push_value(String::new(), interpolated_text(parts)?);
+                    to(ReceivedAttributeValue)
+                }
+                _ => Err(ParseError::exp_operand(t)),
+            }
+        }
+    }
+
+    /// State after receiving `interval` as the operand when adding to or subtracting
+    /// from the time of the transaction, which must be followed by the interval.
+    #[derive(Debug)]
+    pub struct ReceivedInterval;
+
+    impl State for ReceivedInterval {
+        fn receive(&mut self, ctx: &mut Context, t: Option<Token>) -> ParseResult {
+            let t = match t {
+                Some(t) => t,
+                None => return Err(ParseError::eof()),
+            };
+            match t.kind {
+                TokenKind::Text(_) => {
+                    ctx.offset_now_or_panic(unquote(t));
+                    to(ReceivedAttributeValue)
+                }
+                _ => Err(ParseError::exp_interval(t)),
+            }
+        }
+    }
+
+    /// Converts the parts of a string literal into interpolated text, where each
+    /// embedded value is parsed just like the value of an attribute.
+    fn interpolated_text(parts: Vec<TextPart>) -> Result<nodes::Value, ParseError> {
+        Ok(nodes::Value::InterpolatedText(segments(parts)?))
+    }
+
+    /// Converts the parts of a string literal or SQL fragment into segments, where each
+    /// embedded value is parsed just like the value of an attribute.
+    fn segments(parts: Vec<TextPart>) -> Result<Vec<nodes::TextSegment>, ParseError> {
+        parts
+            .into_iter()
+            .map(|part| match part {
+                TextPart::Literal(text) => Ok(nodes::TextSegment::Literal(text)),
+                TextPart::Embedded(tokens) => embedded_value(tokens).map(nodes::TextSegment::Value),
+            })
+            .collect()
+    }
+
+    fn embedded_value(tokens: Vec<Token>) -> Result<nodes::Value, ParseError> {
+        // There is nothing to attach comments to within a string
+        let tokens: Vec<_> = tokens.into_iter().filter(|t| !matches!(t.kind, TokenKind::Comment(_))).collect();
+        let end = tokens.last().expect("embedded value should have tokens").position;
+        let mut ctx = Context::default();
+        let mut state: Box<dyn State> = Box::new(ReceivedAttributeName(String::new()));
+
+        // The record is in a table so that a closing parenthesis can end it as usual,
+        // though it is an error for any token to end the value before the braces do
+        ctx.push_table(String::new(), None, None);
+        ctx.push_record(None);
+
+        for t in tokens {
+            state = state.receive(&mut ctx, Some(t.clone()))?;
+
+            let ended = match ctx.stack.last() {
+                Some(StackItem::Attribute(_)) => false,
+                Some(StackItem::Record(record)) => !record.nodes.is_empty(),
+                _ => true,
+            };
+
+            if ended {
+                return Err(ParseError::token(t));
+            }
+        }
+
+        state.receive(&mut ctx, Some(Token { kind: TokenKind::LineSep, position: end }))?;
+
+        match ctx.stack.pop() {
+            Some(StackItem::Record(mut record)) => Ok(record.nodes.remove(0).value),
+            elt => panic!("expected record on stack; received {:?}", elt),
+        }
+    }
+
+    /// Returns the operator that the symbol represents, if any
+    fn operator(symbol: &Symbol) -> Option<nodes::Operator> {
+        match symbol {
+            Symbol::Asterisk => Some(nodes::Operator::Multiply),
+            Symbol::Dash => Some(nodes::Operator::Subtract),
+            Symbol::DoublePipe => Some(nodes::Operator::Concat),
+            Symbol::Plus => Some(nodes::Operator::Add),
+            Symbol::Slash => Some(nodes::Operator::Divide),
+            _ => None,
+        }
+    }
+
+    /// Whether the token ends a value, either ending its attribute or as an operator
+    fn ends_value(kind: &TokenKind) -> bool {
+        match kind {
+            TokenKind::LineSep | TokenKind::Symbol(Symbol::Comma | Symbol::ParenRight) => true,
+            TokenKind::Symbol(symbol) => operator(symbol).is_some(),
+            TokenKind::Number(n) => n.starts_with('-'),
+            _ => false,
+        }
+    }
+
+    /// Expands the identifiers of a shorthand reference, being the record and
+    /// optionally its schema, with the table and column it is to.
+    fn expand_shorthand((table, column): (String, String), mut identifiers: Vec<Identifier>) -> Vec<Identifier> {
+        let record = identifiers.pop().expect("expected record in shorthand reference");
+
+        // Both are from the name of the attribute, which has already been folded
+        identifiers.push(Identifier { quoted: true, value: table });
+        identifiers.push(record);
+        identifiers.push(Identifier { quoted: true, value: column });
+        identifiers
+    }
+
+    fn identifiers_to_explicit_reference(
+        position: Position,
+        namespace: Option<String>,
+        identifiers: Vec<Identifier>,
+    ) -> Result<nodes::Reference, ParseError> {
         use nodes::*;
         use ReferencedColumn::Explicit;
 
@@ -678,30 +2933,42 @@ mod attribute_states {
             return Err(ParseError::rec_quot(value.to_owned(), position));
         }
 
+        // Columns of the same record cannot be in another namespace
+        if let (Some(namespace), None) = (&namespace, &record) {
+            return Err(ParseError::ns_column(namespace.to_owned(), column.value, position));
+        }
+
         Ok(match (schema, table, record) {
             (Some(s), Some(t), Some(r)) => Reference::SchemaLevel(SchemaLevelReference {
-                schema: s.value,
-                table: t.value,
+                namespace,
+                schema: s.name(),
+                table: t.name(),
                 record: r.value,
-                column: Explicit(column.value),
+                column: Explicit(column.name()),
             }),
             (None, Some(t), Some(r)) => Reference::TableLevel(TableLevelReference {
-                table: t.value,
+                namespace,
+                table: t.name(),
                 record: r.value,
-                column: Explicit(column.value),
+                column: Explicit(column.name()),
             }),
             (None, None, Some(r)) => Reference::RecordLevel(RecordLevelReference {
+                namespace,
                 record: r.value,
-                column: Explicit(column.value),
+                column: Explicit(column.name()),
             }),
             (None, None, None) => Reference::ColumnLevel(ColumnLevelReference {
-                column: column.value,
+                column: column.name(),
             }),
             _ => unreachable!(),
         })
     }
 
-    fn identifiers_to_implicit_reference(position: Position, identifiers: Vec<Identifier>) -> Result<nodes::Reference, ParseError> {
+    fn identifiers_to_implicit_reference(
+        position: Position,
+        namespace: Option<String>,
+        identifiers: Vec<Identifier>,
+    ) -> Result<nodes::Reference, ParseError> {
         use nodes::*;
         use ReferencedColumn::Implicit;
 
@@ -723,17 +2990,20 @@ mod attribute_states {
 
         Ok(match (schema, table) {
             (Some(s), Some(t)) => Reference::SchemaLevel(SchemaLevelReference {
-                schema: s.value,
-                table: t.value,
+                namespace,
+                schema: s.name(),
+                table: t.name(),
                 record: record.value,
                 column: Implicit,
             }),
             (None, Some(t)) => Reference::TableLevel(TableLevelReference {
-                table: t.value,
+                namespace,
+                table: t.name(),
                 record: record.value,
                 column: Implicit,
             }),
             (None, None) => Reference::RecordLevel(RecordLevelReference {
+                namespace,
                 record: record.value,
                 column: Implicit,
             }),
@@ -0,0 +1,150 @@
+//! Loads the same data repeatedly over a connection that is kept open, eg. to seed the
+//! database before each test of an integration suite, without connecting to the
+//! database or parsing and analyzing the data files each time.
+
+use crate::analyzer::ValidatedParseTree;
+use crate::error::HldrError;
+use crate::loader;
+use crate::{load_validated_tree, validate, Options, PlaceReport, Source};
+use postgres::Client;
+
+/// Where a [`Placer`] gets the client that each load is made with
+enum Connection {
+    Client(Box<Client>),
+    /// A pool that a client is taken from for each load, with the `r2d2` feature
+    #[cfg(feature = "r2d2")]
+    Pool(r2d2::Pool<r2d2_postgres::PostgresConnectionManager<postgres::NoTls>>),
+}
+
+/// Loads trees that have been parsed and analyzed once, with [`Placer::prepare`], as
+/// many times as needed, each in a transaction of its own that is committed only if
+/// the `commit` option is set, just as [`load_tree`](crate::load_tree) does.
+///
+/// ```no_run
+/// # fn main() -> Result<(), hldr::HldrError> {
+/// let options = hldr::Options {
+///     database_conn: "postgres://localhost/test".to_owned(),
+///     ..hldr::Options::default()
+/// };
+/// let mut placer = hldr::Placer::connect(options)?;
+/// let tree = placer.prepare("table person ( kevin (name 'Kevin') )")?;
+///
+/// for _ in 0..3 {
+///     placer.apply(&tree)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Placer {
+    connection: Connection,
+    options: Options,
+}
+
+impl Placer {
+    /// Loads with the client, which stays connected for as long as the placer is kept.
+    pub fn new(client: Client, options: Options) -> Self {
+        Self {
+            connection: Connection::Client(Box::new(client)),
+            options,
+        }
+    }
+
+    /// Connects to the database given by the `database_conn` option.
+    pub fn connect(options: Options) -> Result<Self, HldrError> {
+        let client = loader::new_client(&options.database_conn)?;
+
+        Ok(Self::new(client, options))
+    }
+
+    /// Loads with clients taken from the pool, so that placers on different threads,
+    /// eg. those of tests run in parallel, can share connections.
+    #[cfg(feature = "r2d2")]
+    pub fn with_pool(pool: r2d2::Pool<r2d2_postgres::PostgresConnectionManager<postgres::NoTls>>, options: Options) -> Self {
+        Self {
+            connection: Connection::Pool(pool),
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Parses, if needed, and analyzes the data with the placer's profiles and tags,
+    /// returning the tree to load with [`Placer::apply`].
+    pub fn prepare<'a>(&self, source: impl Into<Source<'a>>) -> Result<ValidatedParseTree, HldrError> {
+        prepare(source, &self.options)
+    }
+
+    /// Loads the tree in a new transaction, returning what was loaded.
+    pub fn apply(&mut self, tree: &ValidatedParseTree) -> Result<PlaceReport, HldrError> {
+        match &mut self.connection {
+            Connection::Client(client) => load_validated_tree(client, tree.clone(), &self.options),
+            #[cfg(feature = "r2d2")]
+            Connection::Pool(pool) => {
+                let mut client = pool.get().map_err(crate::error::ClientError::connection_error)?;
+
+                load_validated_tree(&mut client, tree.clone(), &self.options)
+            }
+        }
+    }
+}
+
+/// Parses, if needed, and analyzes the data with the profiles and tags in the options,
+/// which unlike loading it needs no connection.
+fn prepare<'a>(source: impl Into<Source<'a>>, options: &Options) -> Result<ValidatedParseTree, HldrError> {
+    validate(source.into().into_tree()?, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prepare;
+    use crate::ast::StructuralNode;
+    use crate::Options;
+
+    const INPUT: &str = "table person (\n  kevin ()\n  @tag(slow)\n  alice ()\n)\nprofile demo (\n  table pet (\n    rex (person_id @person.kevin.id)\n  )\n)\n";
+
+    fn options(profiles: &[&str], skip_tags: &[&str]) -> Options {
+        Options {
+            profiles: profiles.iter().map(|profile| profile.to_string()).collect(),
+            skip_tags: skip_tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Options::default()
+        }
+    }
+
+    fn records(options: &Options) -> Vec<String> {
+        let tree = prepare(INPUT, options).unwrap();
+
+        tree.inner()
+            .nodes
+            .iter()
+            .flat_map(|node| match node {
+                StructuralNode::Table(table) => table
+                    .nodes
+                    .iter()
+                    .map(|r| format!("{}.{}", table.identity.name, r.name.as_ref().unwrap()))
+                    .collect::<Vec<_>>(),
+                node => panic!("expected table, found {:?}", node),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_prepare() {
+        assert_eq!(records(&options(&[], &[])), ["person.kevin", "person.alice"]);
+        assert_eq!(records(&options(&["demo"], &[])), ["person.kevin", "person.alice", "pet.rex"]);
+        assert_eq!(records(&options(&["demo"], &["slow"])), ["person.kevin", "pet.rex"]);
+    }
+
+    #[test]
+    fn test_prepare_errors() {
+        let input = "table person (\n  @tag(slow)\n  kevin ()\n)\nprofile demo (\n  table pet (\n    (person_id @person.kevin.id)\n  )\n)\n";
+
+        assert!(prepare(input, &options(&[], &["slow"])).is_ok());
+        assert!(prepare(input, &options(&["demo"], &[])).is_ok());
+        assert_eq!(
+            prepare(input, &options(&["demo"], &["slow"])).unwrap_err().to_string(),
+            "referenced record `person.kevin` is excluded by tags on line 7",
+        );
+    }
+}